@@ -0,0 +1,7 @@
+-- Create stack_webhook table (one deploy token per stack, stored hashed)
+CREATE TABLE stack_webhook (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL UNIQUE,
+    token_hash VARCHAR(64) NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,14 @@
+-- Create stack_schedule table (cron-triggered stack actions)
+CREATE TABLE stack_schedule (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    cron VARCHAR(255) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    last_run_at DATETIME,
+    last_result TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on stack_name for per-stack listings
+CREATE INDEX idx_stack_schedule_stack_name ON stack_schedule(stack_name);
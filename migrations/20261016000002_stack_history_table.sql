@@ -0,0 +1,13 @@
+-- Create stack_history table (snapshots of compose + .env content)
+CREATE TABLE stack_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL,
+    operation VARCHAR(50) NOT NULL,
+    compose_file_name VARCHAR(255) NOT NULL,
+    compose_yaml TEXT NOT NULL,
+    compose_env TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on stack_name for per-stack history listings
+CREATE INDEX idx_stack_history_stack_name ON stack_history(stack_name);
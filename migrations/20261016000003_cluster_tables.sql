@@ -0,0 +1,15 @@
+-- Create cluster_node table (heartbeats of controller instances sharing this database)
+CREATE TABLE cluster_node (
+    node_id VARCHAR(255) PRIMARY KEY NOT NULL,
+    instance_id VARCHAR(64) NOT NULL,
+    started_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create cluster_event table (change notifications between nodes)
+CREATE TABLE cluster_event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    node_id VARCHAR(255) NOT NULL,
+    event VARCHAR(50) NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
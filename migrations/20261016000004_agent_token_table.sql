@@ -0,0 +1,12 @@
+-- Create agent_token table (tokens other instances log in with as a controller, stored hashed)
+CREATE TABLE agent_token (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at DATETIME
+);
+
+-- Agents paired with a token instead of a username/password (encrypted, like password)
+ALTER TABLE agent ADD COLUMN token TEXT;
@@ -0,0 +1,9 @@
+-- Create stack_dependency table (stacks that must be up before another one)
+CREATE TABLE stack_dependency (
+    stack_name VARCHAR(255) NOT NULL,
+    depends_on VARCHAR(255) NOT NULL,
+    PRIMARY KEY (stack_name, depends_on)
+);
+
+-- Create index on depends_on so a deleted stack's dependents can be found
+CREATE INDEX idx_stack_dependency_depends_on ON stack_dependency(depends_on);
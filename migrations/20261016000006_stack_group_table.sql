@@ -0,0 +1,8 @@
+-- Create stack_group table (the group/folder a stack is filed under)
+CREATE TABLE stack_group (
+    stack_name VARCHAR(255) PRIMARY KEY NOT NULL,
+    group_name VARCHAR(255) NOT NULL
+);
+
+-- Create index on group_name for listing groups
+CREATE INDEX idx_stack_group_group_name ON stack_group(group_name);
@@ -0,0 +1,6 @@
+-- Create secret table (values for ${secret:NAME} references, encrypted like agent passwords)
+CREATE TABLE secret (
+    name VARCHAR(255) PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
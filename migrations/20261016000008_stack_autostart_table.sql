@@ -0,0 +1,4 @@
+-- Create stack_autostart table (stacks started when dockru boots)
+CREATE TABLE stack_autostart (
+    stack_name VARCHAR(255) PRIMARY KEY NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Create stack_update_window table (time of day image updates may be applied)
+CREATE TABLE stack_update_window (
+    stack_name VARCHAR(255) PRIMARY KEY NOT NULL,
+    update_window VARCHAR(20) NOT NULL
+);
@@ -0,0 +1,13 @@
+-- Create stack_event table (timeline of lifecycle actions run on a stack)
+CREATE TABLE stack_event (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    actor VARCHAR(255) NOT NULL,
+    exit_code INTEGER,
+    error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create index on stack_name for per-stack timelines
+CREATE INDEX idx_stack_event_stack_name ON stack_event(stack_name);
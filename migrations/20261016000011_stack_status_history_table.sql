@@ -0,0 +1,12 @@
+-- Create stack_status_history table (periods a stack was seen in one status)
+CREATE TABLE stack_status_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL,
+    status INTEGER NOT NULL,
+    -- Unix timestamps of the first and latest sample in the status
+    since INTEGER NOT NULL,
+    until INTEGER NOT NULL
+);
+
+-- Create index on stack_name and until for per-stack windows
+CREATE INDEX idx_stack_status_history_stack_until ON stack_status_history(stack_name, until);
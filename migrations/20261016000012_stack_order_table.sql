@@ -0,0 +1,8 @@
+-- Create stack_order table (stacks a user pinned or moved in their stack list)
+CREATE TABLE stack_order (
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    stack_name VARCHAR(255) NOT NULL,
+    pinned BOOLEAN NOT NULL DEFAULT 0,
+    position INTEGER,
+    PRIMARY KEY (user_id, stack_name)
+);
@@ -0,0 +1,11 @@
+-- Create share_link table (time-limited read-only access to a stack's logs, token stored hashed)
+CREATE TABLE share_link (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    stack_name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_by VARCHAR(255) NOT NULL,
+    expires_at INTEGER NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_share_link_stack_name ON share_link(stack_name);
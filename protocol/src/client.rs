@@ -0,0 +1,289 @@
+// Typed Socket.io client for a dockru instance (`client` feature)
+//
+// Wraps a rust_socketio client with methods for the events automation needs
+// most. Any other event can be sent with `request`, and events of one of the
+// instance's agents with `call_endpoint`. The server's agent manager talks to
+// remote instances through this client as well.
+
+use crate::events::client as event;
+use crate::stack::{StackJson, StackSimpleJson};
+use anyhow::{anyhow, bail, Result};
+use futures_util::future::FutureExt;
+use rust_socketio::asynchronous::{Client, ClientBuilder};
+use rust_socketio::Payload;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long to wait for an ack unless configured otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stacks fetched per queryStackList page, the most the server allows
+const STACK_LIST_PAGE_SIZE: usize = 500;
+
+#[derive(Deserialize)]
+struct StackListPage {
+    #[serde(rename = "stackList")]
+    stack_list: Vec<StackSimpleJson>,
+    total: usize,
+}
+
+#[derive(Deserialize)]
+struct StackResponse {
+    stack: StackJson,
+}
+
+/// A connection to a dockru instance
+#[derive(Clone)]
+pub struct DockruClient {
+    socket: Client,
+    timeout: Duration,
+}
+
+impl DockruClient {
+    /// Connect to the instance at `url`, e.g. "http://nas:5001"
+    ///
+    /// The connection is not re-established when it drops. Use `ClientBuilder`
+    /// and `from_socket` to register event handlers or enable reconnects.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let socket = ClientBuilder::new(url).reconnect(false).connect().await?;
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Wrap a connected rust_socketio client
+    ///
+    /// rust_socketio runs event handlers on the loop that receives acks, so a
+    /// handler must spawn a task to make requests instead of awaiting them.
+    pub fn from_socket(socket: Client) -> Self {
+        Self {
+            socket,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long to wait for the server to answer a request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The underlying rust_socketio client
+    pub fn socket(&self) -> &Client {
+        &self.socket
+    }
+
+    /// Emit an event and return the server's ack as is
+    pub async fn call(&self, event: &str, args: Value) -> Result<Value> {
+        self.emit_with_ack(event, args, event).await
+    }
+
+    /// Emit `event` and wait for its ack; errors name the event `name`
+    async fn emit_with_ack(&self, event: &str, args: Value, name: &str) -> Result<Value> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let tx = Arc::new(Mutex::new(Some(tx)));
+        self.socket
+            .emit_with_ack(
+                event,
+                args,
+                self.timeout,
+                move |payload: Payload, _socket: Client| {
+                    let tx = tx.lock().unwrap().take();
+                    async move {
+                        let response = match payload {
+                            Payload::Text(values) => values.into_iter().next(),
+                            _ => None,
+                        };
+                        if let Some(tx) = tx {
+                            tx.send(response.unwrap_or(Value::Null)).ok();
+                        }
+                    }
+                    .boxed()
+                },
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to emit {}: {}", name, e))?;
+
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(response)) => Ok(response),
+            _ => Err(anyhow!("No response for {}", name)),
+        }
+    }
+
+    /// Emit an event and return the server's ack, or its message as an error
+    /// if the ack is not ok
+    pub async fn request(&self, event: &str, args: Value) -> Result<Value> {
+        let response = self.call(event, args).await?;
+        check_ok(event, response)
+    }
+
+    /// Emit an event without waiting for an answer
+    pub async fn emit(&self, event: &str, args: Value) -> Result<()> {
+        self.socket
+            .emit(event, args)
+            .await
+            .map_err(|e| anyhow!("Failed to emit {}: {}", event, e))
+    }
+
+    /// Log in with a username and password, returning the session's JWT
+    pub async fn login(&self, username: &str, password: &str) -> Result<String> {
+        let response = self
+            .call(
+                event::LOGIN,
+                json!({ "username": username, "password": password }),
+            )
+            .await?;
+        if response["tokenRequired"].as_bool() == Some(true) {
+            bail!("The account requires a two-factor token");
+        }
+        let response = check_ok(event::LOGIN, response)?;
+        response["token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Login response has no token"))
+    }
+
+    /// Log in with a token from createAgentToken
+    pub async fn login_by_agent_token(&self, token: &str) -> Result<()> {
+        self.request(event::LOGIN_BY_AGENT_TOKEN, json!(token))
+            .await?;
+        Ok(())
+    }
+
+    /// Every stack of the instance, in the logged in user's order
+    pub async fn get_stack_list(&self) -> Result<Vec<StackSimpleJson>> {
+        let mut stacks = Vec::new();
+        for page in 0.. {
+            let response = self
+                .request(
+                    event::QUERY_STACK_LIST,
+                    json!({ "page": page, "pageSize": STACK_LIST_PAGE_SIZE }),
+                )
+                .await?;
+            let result: StackListPage = serde_json::from_value(response)?;
+            let last = result.stack_list.len() < STACK_LIST_PAGE_SIZE;
+            stacks.extend(result.stack_list);
+            if last || stacks.len() >= result.total {
+                break;
+            }
+        }
+        Ok(stacks)
+    }
+
+    /// A stack with its compose files
+    pub async fn get_stack(&self, name: &str) -> Result<StackJson> {
+        let response = self.request(event::GET_STACK, json!(name)).await?;
+        let response: StackResponse = serde_json::from_value(response)?;
+        Ok(response.stack)
+    }
+
+    /// Save a stack's files without deploying it
+    ///
+    /// `is_add` creates the stack and fails if it already exists.
+    pub async fn save_stack(
+        &self,
+        name: &str,
+        compose_yaml: &str,
+        compose_env: &str,
+        is_add: bool,
+    ) -> Result<()> {
+        self.request(
+            event::SAVE_STACK,
+            json!([name, compose_yaml, compose_env, is_add]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Save a stack's files and deploy it
+    ///
+    /// `is_add` creates the stack and fails if it already exists.
+    pub async fn deploy_stack(
+        &self,
+        name: &str,
+        compose_yaml: &str,
+        compose_env: &str,
+        is_add: bool,
+    ) -> Result<()> {
+        self.request(
+            event::DEPLOY_STACK,
+            json!([name, compose_yaml, compose_env, is_add]),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Emit an event to one of the instance's agents, or to the instance
+    /// itself for the empty endpoint, and return its ack as is
+    pub async fn call_endpoint(
+        &self,
+        endpoint: &str,
+        event_name: &str,
+        args: Value,
+    ) -> Result<Value> {
+        let payload = agent_payload(endpoint, event_name, args);
+        self.emit_with_ack(event::AGENT, payload, event_name).await
+    }
+
+    /// Emit an event to one of the instance's agents without waiting for an answer
+    pub async fn emit_endpoint(&self, endpoint: &str, event_name: &str, args: Value) -> Result<()> {
+        self.emit(event::AGENT, agent_payload(endpoint, event_name, args))
+            .await
+    }
+
+    pub async fn disconnect(&self) -> Result<()> {
+        self.socket.disconnect().await?;
+        Ok(())
+    }
+}
+
+/// The ack itself if it is ok, its message as an error otherwise
+fn check_ok(event: &str, response: Value) -> Result<Value> {
+    if response["ok"].as_bool() == Some(true) {
+        return Ok(response);
+    }
+    match response["msg"].as_str() {
+        Some(msg) => bail!("{}", msg),
+        None => bail!("{} failed", event),
+    }
+}
+
+/// Arguments of an "agent" event: [endpoint, eventName, ...args]
+pub fn agent_payload(endpoint: &str, event_name: &str, args: Value) -> Value {
+    let mut payload = vec![json!(endpoint), json!(event_name)];
+    match args {
+        Value::Array(args) => payload.extend(args),
+        arg => payload.push(arg),
+    }
+    Value::Array(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_payload() {
+        assert_eq!(
+            agent_payload(
+                "remote:5001",
+                "interactiveTerminal",
+                json!(["web", "app", "sh"])
+            ),
+            json!(["remote:5001", "interactiveTerminal", "web", "app", "sh"])
+        );
+        assert_eq!(
+            agent_payload("remote:5001", "getStack", json!("web")),
+            json!(["remote:5001", "getStack", "web"])
+        );
+    }
+
+    #[test]
+    fn test_check_ok() {
+        assert!(check_ok("getStack", json!({ "ok": true, "stack": {} })).is_ok());
+        let err = check_ok("getStack", json!({ "ok": false, "msg": "Stack not found" }));
+        assert_eq!(err.unwrap_err().to_string(), "Stack not found");
+        let err = check_ok("getStack", Value::Null);
+        assert_eq!(err.unwrap_err().to_string(), "getStack failed");
+    }
+}
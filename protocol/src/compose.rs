@@ -0,0 +1,105 @@
+// What the server reads from a stack's compose files
+//
+// Parsing lives in the server; these are the results it sends to clients.
+
+use serde::{Deserialize, Serialize};
+
+/// How the UI should present a stack
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UiHints {
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    /// CSS hex color, "#rgb" or "#rrggbb"
+    #[serde(rename = "accentColor")]
+    pub accent_color: Option<String>,
+    pub group: Option<String>,
+}
+
+impl UiHints {
+    pub fn is_empty(&self) -> bool {
+        *self == UiHints::default()
+    }
+}
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    PreDeploy,
+    PostDeploy,
+}
+
+impl HookStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookStage::PreDeploy => "pre-deploy",
+            HookStage::PostDeploy => "post-deploy",
+        }
+    }
+}
+
+/// Commands to run before and after a deploy
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployHooks {
+    #[serde(rename = "preDeploy")]
+    pub pre_deploy: Vec<String>,
+    #[serde(rename = "postDeploy")]
+    pub post_deploy: Vec<String>,
+}
+
+impl DeployHooks {
+    pub fn commands(&self, stage: HookStage) -> &[String] {
+        match stage {
+            HookStage::PreDeploy => &self.pre_deploy,
+            HookStage::PostDeploy => &self.post_deploy,
+        }
+    }
+}
+
+/// A compose file pulled in through `include:`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncludedFile {
+    /// Path relative to the stack directory, or the reference for remote includes
+    pub name: String,
+    /// Git or OCI reference, which compose fetches itself
+    pub remote: bool,
+    /// None if the file is remote, missing or unreadable
+    pub content: Option<String>,
+}
+
+/// Kind of value a variable holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvVarType {
+    #[default]
+    String,
+    Number,
+    Boolean,
+    /// A password or key, shown masked
+    Secret,
+}
+
+/// Description of a single environment variable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvVarSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub var_type: EnvVarType,
+    pub description: Option<String>,
+    pub required: bool,
+    /// Allowed values, empty when any value is allowed
+    #[serde(rename = "enum", default)]
+    pub allowed: Vec<String>,
+    pub default: Option<String>,
+}
+
+/// A service of a compose file as compose would run it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposeService {
+    pub name: String,
+    pub image: Option<String>,
+    /// The service has a `build:` section
+    pub build: bool,
+    pub profiles: Vec<String>,
+    #[serde(rename = "dependsOn")]
+    pub depends_on: Vec<String>,
+}
@@ -0,0 +1,176 @@
+// Socket.io event names
+//
+// `client` events are emitted by clients and answered through the ack. `server`
+// events are pushed by the server, most of them wrapped in the `agent` event
+// as `("agent", eventName, data)` so the frontend can route them by endpoint.
+
+macro_rules! events {
+    ($($name:ident = $value:literal,)*) => {
+        $(pub const $name: &str = $value;)*
+
+        /// Every event name of this direction
+        pub const ALL: &[&str] = &[$($name),*];
+    };
+}
+
+/// Events a client emits to the server
+pub mod client {
+    events! {
+        ADD_AGENT = "addAgent",
+        AGENT = "agent",
+        BATCH_STACK_ACTION = "batchStackAction",
+        BUILD_STACK = "buildStack",
+        BULK_ENV_EDIT = "bulkEnvEdit",
+        CHANGE_PASSWORD = "changePassword",
+        CHECK_MAIN_TERMINAL = "checkMainTerminal",
+        CHECK_UPDATES_NOW = "checkUpdatesNow",
+        CLONE_STACK = "cloneStack",
+        COMBINED_LOGS_TERMINAL = "combinedLogsTerminal",
+        COMPOSERIZE = "composerize",
+        CONTAINER_LOGS_TERMINAL = "containerLogsTerminal",
+        CONVERT_DOCKER_RUN = "convertDockerRun",
+        CREATE_AGENT_TOKEN = "createAgentToken",
+        CREATE_DOCKER_NETWORK = "createDockerNetwork",
+        CREATE_SCHEDULE = "createSchedule",
+        CREATE_SHARE_LINK = "createShareLink",
+        DEBUG_INJECT = "debugInject",
+        DELETE_AGENT_TOKEN = "deleteAgentToken",
+        DELETE_DOCKER_NETWORK = "deleteDockerNetwork",
+        DELETE_SCHEDULE = "deleteSchedule",
+        DELETE_SHARE_LINK = "deleteShareLink",
+        DELETE_STACK = "deleteStack",
+        DELETE_STACK_WEBHOOK = "deleteStackWebhook",
+        DEPLOY_STACK = "deployStack",
+        DIFF_STACK = "diffStack",
+        DISCONNECT_OTHER_SOCKET_CLIENTS = "disconnectOtherSocketClients",
+        DISCOVER_AGENTS = "discoverAgents",
+        DOWN_STACK = "downStack",
+        EXPORT_STACK = "exportStack",
+        GET_AGENT_TOKEN_LIST = "getAgentTokenList",
+        GET_AUDIT_LOG = "getAuditLog",
+        GET_BACKUP_CONFIG = "getBackupConfig",
+        GET_BACKUP_STATUS = "getBackupStatus",
+        GET_COMPOSE_ENV_POLICY = "getComposeEnvPolicy",
+        GET_COMPOSE_POLICY = "getComposePolicy",
+        GET_DOCKER_CONTEXTS = "getDockerContexts",
+        GET_DOCKER_DISK_USAGE = "getDockerDiskUsage",
+        GET_DOCKER_INFO = "getDockerInfo",
+        GET_DOCKER_NETWORK_LIST = "getDockerNetworkList",
+        GET_EXPOSURE_REPORT = "getExposureReport",
+        GET_INJECTED_ENV = "getInjectedEnv",
+        GET_REWRITE_RULES = "getRewriteRules",
+        GET_SCHEDULE_LIST = "getScheduleList",
+        GET_SERVER_STATS = "getServerStats",
+        GET_SETTINGS = "getSettings",
+        GET_SHARE_LINKS = "getShareLinks",
+        GET_STACK = "getStack",
+        GET_STACK_EVENTS = "getStackEvents",
+        GET_STACK_HISTORY = "getStackHistory",
+        GET_STACK_NORMALIZED = "getStackNormalized",
+        GET_STACK_ORDER = "getStackOrder",
+        GET_TERMINAL_CLIENTS = "getTerminalClients",
+        IMPORT_STACK = "importStack",
+        INSPECT_CONTAINER = "inspectContainer",
+        INSPECT_DOCKER_NETWORK = "inspectDockerNetwork",
+        INTERACTIVE_TERMINAL = "interactiveTerminal",
+        KICK_TERMINAL_CLIENT = "kickTerminalClient",
+        LEAVE_COMBINED_TERMINAL = "leaveCombinedTerminal",
+        LEAVE_CONTAINER_LOGS = "leaveContainerLogs",
+        LIST_GROUPS = "listGroups",
+        LIST_SECRETS = "listSecrets",
+        LOGIN = "login",
+        LOGIN_BY_AGENT_TOKEN = "loginByAgentToken",
+        LOGIN_BY_TOKEN = "loginByToken",
+        MAIN_TERMINAL = "mainTerminal",
+        MIGRATE_STACKS = "migrateStacks",
+        NEED_SETUP = "needSetup",
+        PREVIEW_SCHEDULES = "previewSchedules",
+        PREVIEW_STACK_CONFIG = "previewStackConfig",
+        PROBE_SERVICE = "probeService",
+        PRUNE_DOCKER_DISK_USAGE = "pruneDockerDiskUsage",
+        PULL_SERVICE = "pullService",
+        QUERY_STACK_LIST = "queryStackList",
+        REGENERATE_STACK_WEBHOOK = "regenerateStackWebhook",
+        RELOCATE_STACKS_DIR = "relocateStacksDir",
+        REMOVE_AGENT = "removeAgent",
+        REQUEST_STACK_LIST = "requestStackList",
+        RESTART_CONTAINER = "restartContainer",
+        RESTART_SERVICE = "restartService",
+        RESTART_STACK = "restartStack",
+        RESTORE_STACK_SNAPSHOT = "restoreStackSnapshot",
+        ROLLBACK_STACK = "rollbackStack",
+        RUN_BACKUP = "runBackup",
+        SAVE_STACK = "saveStack",
+        SERVICE_PROCESS_LIST = "serviceProcessList",
+        SERVICE_STATUS_LIST = "serviceStatusList",
+        SET_BACKUP_CONFIG = "setBackupConfig",
+        SET_COMPOSE_ENV_POLICY = "setComposeEnvPolicy",
+        SET_COMPOSE_POLICY = "setComposePolicy",
+        SET_INJECTED_ENV = "setInjectedEnv",
+        SET_REWRITE_RULES = "setRewriteRules",
+        SET_SECRET = "setSecret",
+        SET_SETTINGS = "setSettings",
+        SET_STACK_AUTOSTART = "setStackAutostart",
+        SET_STACK_COMPOSE_FILES = "setStackComposeFiles",
+        SET_STACK_DEPENDENCIES = "setStackDependencies",
+        SET_STACK_DOCKER_CONTEXT = "setStackDockerContext",
+        SET_STACK_GROUP = "setStackGroup",
+        SET_STACK_ORDER = "setStackOrder",
+        SET_STACK_PINNED = "setStackPinned",
+        SET_STACK_RESTART_POLICY = "setStackRestartPolicy",
+        SET_STACK_UPDATE_WINDOW = "setStackUpdateWindow",
+        SETUP = "setup",
+        START_SERVICE = "startService",
+        START_STACK = "startStack",
+        STOP_CONTAINER_STATS = "stopContainerStats",
+        STOP_SERVICE = "stopService",
+        STOP_STACK = "stopStack",
+        STREAM_CONTAINER_STATS = "streamContainerStats",
+        TERMINAL_INPUT = "terminalInput",
+        TERMINAL_JOIN = "terminalJoin",
+        TERMINAL_RESIZE = "terminalResize",
+        UNWATCH_STACK = "unwatchStack",
+        UPDATE_STACK = "updateStack",
+        WATCH_STACK = "watchStack",
+    }
+}
+
+/// Events the server pushes to clients
+pub mod server {
+    events! {
+        AGENT = "agent",
+        AGENT_LIST = "agentList",
+        AGENT_REMOVED = "agentRemoved",
+        AGENT_STATUS = "agentStatus",
+        AUTO_LOGIN = "autoLogin",
+        AUTOSTART = "autostart",
+        CONTAINER_STATS = "containerStats",
+        DOCKER_AVAILABLE = "dockerAvailable",
+        DOCKER_UNAVAILABLE = "dockerUnavailable",
+        INFO = "info",
+        PULL_PROGRESS = "pullProgress",
+        REFRESH = "refresh",
+        SCHEDULE_RUN = "scheduleRun",
+        SERVICE_STATUS_LIST = "serviceStatusList",
+        SETUP = "setup",
+        STACK_LIST = "stackList",
+        STACK_STATUS = "stackStatus",
+        TERMINAL_EXIT = "terminalExit",
+        TERMINAL_KICKED = "terminalKicked",
+        TERMINAL_WRITE = "terminalWrite",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_event_names_are_unique() {
+        for all in [client::ALL, server::ALL] {
+            let unique: HashSet<_> = all.iter().collect();
+            assert_eq!(unique.len(), all.len());
+        }
+    }
+}
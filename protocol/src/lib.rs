@@ -0,0 +1,20 @@
+// Wire types of the dockru Socket.io protocol
+//
+// The server serializes these types into its acks and pushed events. Clients
+// written in Rust (including a frontend compiled to WebAssembly) can depend on
+// this crate instead of mirroring the JSON by hand, and stay compatible with
+// the server they are built against. The `client` feature adds a typed client
+// for scripting an instance.
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod compose;
+pub mod events;
+pub mod response;
+pub mod stack;
+pub mod status;
+
+#[cfg(feature = "client")]
+pub use client::DockruClient;
+pub use response::{BaseRes, CustomResponse};
+pub use stack::{ServiceStatus, StackJson, StackSimpleJson};
@@ -0,0 +1,239 @@
+// Stacks as listed and shown to clients
+
+use crate::compose::{ComposeService, DeployHooks, EnvVarSchema, IncludedFile, UiHints};
+use crate::status::{CREATED_STACK, EXITED, PARTIAL, RESTARTING, RUNNING, UNHEALTHY, UNKNOWN};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Created/modified times of a file or directory, in unix seconds
+///
+/// `created` is None on filesystems that don't record a birth time.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct FileTimes {
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+}
+
+impl FileTimes {
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        fn unix_secs(time: std::io::Result<std::time::SystemTime>) -> Option<i64> {
+            let duration = time.ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+            i64::try_from(duration.as_secs()).ok()
+        }
+
+        Self {
+            created: unix_secs(metadata.created()),
+            modified: unix_secs(metadata.modified()),
+        }
+    }
+}
+
+/// Simple JSON representation for stack lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackSimpleJson {
+    pub name: String,
+    pub status: i32,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub autostart: bool,
+    /// "HH:MM-HH:MM" in which image updates are applied automatically
+    #[serde(rename = "updateWindow", default)]
+    pub update_window: Option<String>,
+    /// Container states of the stack, None if it has no containers
+    #[serde(default)]
+    pub services: Option<ServiceCounts>,
+    /// Healthcheck results, None if no container has a healthcheck
+    #[serde(default)]
+    pub health: Option<StackHealth>,
+    /// Presentation hints from the compose file, None if it has none
+    #[serde(rename = "uiHints", default)]
+    pub ui_hints: Option<UiHints>,
+    #[serde(rename = "isManagedByDockru")]
+    pub is_managed_by_dockru: bool,
+    #[serde(rename = "composeFileName")]
+    pub compose_file_name: String,
+    /// Docker context the stack is deployed to, None for the instance's daemon
+    #[serde(rename = "dockerContext", default)]
+    pub docker_context: Option<String>,
+    pub endpoint: String,
+    #[serde(rename = "directoryTimes")]
+    pub directory_times: Option<FileTimes>,
+    #[serde(rename = "composeFileTimes")]
+    pub compose_file_times: Option<FileTimes>,
+    /// Service name -> newer image available, for services that have been checked
+    #[serde(rename = "imageUpdates", default)]
+    pub image_updates: HashMap<String, bool>,
+    #[serde(rename = "updateAvailable", default)]
+    pub update_available: bool,
+    /// An update is available and waiting for the stack's update window
+    #[serde(rename = "pendingUpdate", default)]
+    pub pending_update: bool,
+}
+
+/// Full JSON representation with compose files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackJson {
+    pub name: String,
+    pub status: i32,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub autostart: bool,
+    /// "HH:MM-HH:MM" in which image updates are applied automatically
+    #[serde(rename = "updateWindow", default)]
+    pub update_window: Option<String>,
+    /// Presentation hints from the compose file, None if it has none
+    #[serde(rename = "uiHints", default)]
+    pub ui_hints: Option<UiHints>,
+    #[serde(rename = "isManagedByDockru")]
+    pub is_managed_by_dockru: bool,
+    #[serde(rename = "composeFileName")]
+    pub compose_file_name: String,
+    /// Docker context the stack is deployed to, None for the instance's daemon
+    #[serde(rename = "dockerContext", default)]
+    pub docker_context: Option<String>,
+    pub endpoint: String,
+    #[serde(rename = "composeYAML")]
+    pub compose_yaml: String,
+    #[serde(rename = "composeENV")]
+    pub compose_env: String,
+    #[serde(rename = "primaryHostname")]
+    pub primary_hostname: String,
+    /// README.md from the stack directory, truncated to 64 KiB
+    pub readme: Option<String>,
+    /// Every compose file passed to docker compose, in merge order
+    #[serde(rename = "composeFiles", default)]
+    pub compose_files: Vec<ComposeFile>,
+    /// Files pulled in through the compose file's `include:`
+    #[serde(rename = "includedFiles", default)]
+    pub included_files: Vec<IncludedFile>,
+    /// Variables described by the compose file's `x-dockru.env-schema`
+    #[serde(rename = "envSchema", default)]
+    pub env_schema: Vec<EnvVarSchema>,
+    /// Commands from the compose file's `x-dockru.hooks`
+    #[serde(rename = "deployHooks", default)]
+    pub deploy_hooks: DeployHooks,
+    /// Services compose would run, with `extends:` and profiles applied
+    #[serde(default)]
+    pub services: Vec<ComposeService>,
+}
+
+/// One of the compose files a stack is deployed with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComposeFile {
+    /// Path relative to the stack directory
+    pub name: String,
+    /// None if the file is missing or unreadable
+    pub content: Option<String>,
+}
+
+/// How many of a stack's containers are in each state
+///
+/// Replicas count separately. An unhealthy container is also counted as running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceCounts {
+    pub total: u32,
+    pub running: u32,
+    pub exited: u32,
+    pub created: u32,
+    pub restarting: u32,
+    pub unhealthy: u32,
+    #[serde(default)]
+    pub healthy: u32,
+    /// Healthcheck hasn't passed yet
+    #[serde(default)]
+    pub starting: u32,
+}
+
+/// Healthcheck results of a stack's containers, e.g. for "2/3 healthy"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackHealth {
+    pub healthy: u32,
+    pub unhealthy: u32,
+    pub starting: u32,
+    /// Containers that have a healthcheck
+    pub checked: u32,
+    /// All containers, with or without a healthcheck
+    pub total: u32,
+}
+
+impl ServiceCounts {
+    /// Count a container by its state and health
+    pub fn add(&mut self, state: &str, health: Option<&str>) {
+        self.total += 1;
+        match state {
+            "running" => self.running += 1,
+            "exited" | "dead" => self.exited += 1,
+            "created" => self.created += 1,
+            "restarting" => self.restarting += 1,
+            _ => {}
+        }
+        match health {
+            Some("healthy") => self.healthy += 1,
+            Some("unhealthy") => self.unhealthy += 1,
+            Some("starting") => self.starting += 1,
+            _ => {}
+        }
+    }
+
+    /// Healthcheck results, None if no container has a healthcheck
+    pub fn health(&self) -> Option<StackHealth> {
+        let checked = self.healthy + self.unhealthy + self.starting;
+        (checked > 0).then_some(StackHealth {
+            healthy: self.healthy,
+            unhealthy: self.unhealthy,
+            starting: self.starting,
+            checked,
+            total: self.total,
+        })
+    }
+
+    /// Stack status from the container states, UNKNOWN if there are none
+    ///
+    /// A crash-looping or unhealthy container outweighs the rest; otherwise the
+    /// stack is running only if every container runs.
+    pub fn status(&self) -> i32 {
+        if self.total == 0 {
+            UNKNOWN
+        } else if self.restarting > 0 {
+            RESTARTING
+        } else if self.unhealthy > 0 {
+            UNHEALTHY
+        } else if self.running == self.total {
+            RUNNING
+        } else if self.running > 0 {
+            PARTIAL
+        } else if self.created == self.total {
+            CREATED_STACK
+        } else {
+            EXITED
+        }
+    }
+}
+
+/// Service status information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub state: String,
+    pub ports: Vec<String>,
+    pub health: Option<String>,
+    pub image: Option<String>,
+    #[serde(rename = "containerName", default)]
+    pub container_name: Option<String>,
+    #[serde(rename = "containerId", default)]
+    pub container_id: Option<String>,
+    /// Unix timestamp (seconds) the container was created
+    #[serde(default)]
+    pub created: Option<i64>,
+    /// RFC3339, None if the container never started
+    #[serde(rename = "startedAt", default)]
+    pub started_at: Option<String>,
+    /// RFC3339, None if the container never stopped
+    #[serde(rename = "finishedAt", default)]
+    pub finished_at: Option<String>,
+    #[serde(rename = "exitCode", default)]
+    pub exit_code: Option<i64>,
+}
@@ -0,0 +1,11 @@
+// Stack status codes, as sent in the `status` of a stack
+
+pub const UNKNOWN: i32 = 0;
+pub const CREATED_FILE: i32 = 1;
+pub const CREATED_STACK: i32 = 2;
+pub const RUNNING: i32 = 3;
+pub const EXITED: i32 = 4;
+// Some containers running, others stopped
+pub const PARTIAL: i32 = 5;
+pub const RESTARTING: i32 = 6;
+pub const UNHEALTHY: i32 = 7;
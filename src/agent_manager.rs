@@ -1,6 +1,9 @@
-use crate::db::models::agent::Agent;
+use crate::db::models::agent::{Agent, AgentCredentials};
+use crate::utils::constants::AGENT_PROXY_ACK_TIMEOUT_SECS;
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
+use dockru_protocol::events::server as server_event;
+use dockru_protocol::DockruClient;
 use futures_util::future::FutureExt;
 use redact::Secret;
 use rust_socketio::asynchronous::{Client, ClientBuilder};
@@ -9,6 +12,7 @@ use serde_json::{json, Value};
 use socketioxide::extract::SocketRef;
 use sqlx::SqlitePool;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
@@ -18,9 +22,7 @@ use tracing::{debug, error, info, warn};
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AgentStatus {
     Connecting,
-    #[allow(dead_code)]
     Online,
-    #[allow(dead_code)]
     Offline,
 }
 
@@ -36,7 +38,7 @@ impl AgentStatus {
 
 /// Agent client wrapper tracking connection state
 struct AgentClient {
-    client: Client,
+    client: DockruClient,
     logged_in: bool,
     #[allow(dead_code)]
     endpoint: String,
@@ -52,6 +54,8 @@ pub struct AgentManager {
     encryption_secret: Secret<String>,
     agent_clients: Arc<RwLock<HashMap<String, AgentClient>>>,
     first_connect_time: Arc<RwLock<DateTime<Utc>>>,
+    /// Set once connect_all() ran for a frontend socket (agent sockets never proxy)
+    connects_agents: AtomicBool,
 }
 
 impl AgentManager {
@@ -67,12 +71,13 @@ impl AgentManager {
             encryption_secret: Secret::new(encryption_secret),
             agent_clients: Arc::new(RwLock::new(HashMap::new())),
             first_connect_time: Arc::new(RwLock::new(Utc::now())),
+            connects_agents: AtomicBool::new(false),
         }
     }
 
     /// Test connection to a remote Dockru instance
     /// Returns Ok(()) if connection and login succeed
-    pub async fn test(&self, url: &str, username: &str, password: &str) -> Result<()> {
+    pub async fn test(&self, url: &str, credentials: &AgentCredentials) -> Result<()> {
         let parsed_url = url::Url::parse(url)
             .map_err(|e| anyhow!("Invalid Dockru URL: {}", e))?;
 
@@ -95,7 +100,8 @@ impl AgentManager {
         }
 
         // Try to connect with a timeout
-        let test_future = Self::test_connection_internal(url, &endpoint_with_port, username, password);
+        let test_future =
+            Self::test_connection_internal(url, &endpoint_with_port, credentials.login_event());
         
         tokio::time::timeout(Duration::from_secs(30), test_future)
             .await
@@ -106,14 +112,11 @@ impl AgentManager {
     async fn test_connection_internal(
         url: &str,
         endpoint: &str,
-        username: &str,
-        password: &str,
+        (login_event, login_data): (&'static str, Value),
     ) -> Result<()> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         let tx = Arc::new(tokio::sync::Mutex::new(Some(tx)));
 
-        let username = username.to_string();
-        let password = password.to_string();
         let endpoint_clone = endpoint.to_string();
 
         // Clone for the second callback
@@ -124,78 +127,24 @@ impl AgentManager {
             .opening_header("endpoint", endpoint_clone.as_str())
             .reconnect(false)
             .on("connect", move |_payload: Payload, socket: Client| {
-                let username = username.clone();
-                let password = password.clone();
+                let login_data = login_data.clone();
                 let endpoint = endpoint_clone.clone();
                 let tx = tx.clone();
 
                 async move {
                     debug!("Test connection established to {}", endpoint);
-                    
-                    // Emit login
-                    let login_data = json!({
-                        "username": username,
-                        "password": password,
-                    });
 
-                    let (login_tx, login_rx) = tokio::sync::oneshot::channel();
-                    let login_tx = Arc::new(tokio::sync::Mutex::new(Some(login_tx)));
-
-                    if let Err(e) = socket.emit_with_ack(
-                        "login",
-                        login_data,
-                        Duration::from_secs(10),
-                        move |payload: Payload, _socket: Client| {
-                            let login_tx = login_tx.clone();
-                            async move {
-                                if let Payload::Text(values) = payload {
-                                    if let Some(obj) = values.first() {
-                                        if let Some(lock) = login_tx.lock().await.take() {
-                                            lock.send(obj.clone()).ok();
-                                        }
-                                    }
-                                }
-                            }
-                            .boxed()
-                        },
-                    ).await {
-                        error!("Failed to emit login: {}", e);
+                    // Log in from a task; waiting for the ack in this callback
+                    // would block the client's event loop that delivers it
+                    tokio::spawn(async move {
+                        let result = DockruClient::from_socket(socket)
+                            .request(login_event, login_data)
+                            .await
+                            .map(|_| ());
                         if let Some(lock) = tx.lock().await.take() {
-                            lock.send(Err(anyhow!("Failed to emit login"))).ok();
+                            lock.send(result).ok();
                         }
-                        return;
-                    }
-
-                    // Wait for login response
-                    match tokio::time::timeout(Duration::from_secs(10), login_rx).await {
-                        Ok(Ok(response)) => {
-                            if let Some(ok) = response.get("ok").and_then(|v| v.as_bool()) {
-                                if ok {
-                                    if let Some(lock) = tx.lock().await.take() {
-                                        lock.send(Ok(())).ok();
-                                    }
-                                } else {
-                                    let msg = response
-                                        .get("msg")
-                                        .and_then(|v| v.as_str())
-                                        .unwrap_or("Login failed");
-                                    if let Some(lock) = tx.lock().await.take() {
-                                        lock.send(Err(anyhow!("{}", msg))).ok();
-                                    }
-                                }
-                            }
-                        }
-                        Ok(Err(_)) => {
-                            if let Some(lock) = tx.lock().await.take() {
-                                lock.send(Err(anyhow!("Login response channel closed"))).ok();
-                            }
-                        }
-                        Err(_) => {
-                            if let Some(lock) = tx.lock().await.take() {
-                                lock.send(Err(anyhow!("Login timeout"))).ok();
-                            }
-                        }
-                    }
+                    });
                 }
                 .boxed()
             })
@@ -223,13 +172,23 @@ impl AgentManager {
     }
 
     /// Add a remote Dockru agent to the database
-    pub async fn add(&self, url: &str, username: &str, password: &str) -> Result<Agent> {
+    pub async fn add(&self, url: &str, credentials: &AgentCredentials) -> Result<Agent> {
         use crate::db::models::agent::NewAgent;
-        let new_agent = NewAgent {
-            url: url.to_string(),
-            username: username.to_string(),
-            password: Secret::new(password.to_string()),
-            active: true,
+        let new_agent = match credentials {
+            AgentCredentials::Password { username, password } => NewAgent {
+                url: url.to_string(),
+                username: username.clone(),
+                password: password.clone(),
+                active: true,
+                token: None,
+            },
+            AgentCredentials::Token(token) => NewAgent {
+                url: url.to_string(),
+                username: String::new(),
+                password: Secret::new(String::new()),
+                active: true,
+                token: Some(token.clone()),
+            },
         };
         let agent = Agent::create(&self.db, new_agent, &self.encryption_secret).await?;
         let endpoint = agent.endpoint.clone();
@@ -238,7 +197,10 @@ impl AgentManager {
     }
 
     /// Remove a remote Dockru agent
-    pub async fn remove(&self, url: &str) -> Result<()> {
+    ///
+    /// Returns the endpoint of the removed agent. Other sockets still holding a
+    /// connection to it should be cleaned up with [`deregister_endpoint`].
+    pub async fn remove(&self, url: &str) -> Result<String> {
         let agent = Agent::find_by_url(&self.db, url, &self.encryption_secret)
             .await?
             .ok_or_else(|| anyhow!("Agent not found"))?;
@@ -253,14 +215,11 @@ impl AgentManager {
 
         info!("Removed agent: {} (endpoint: {})", url, endpoint);
 
-        // Send updated agent list
-        self.send_agent_list().await;
-
-        Ok(())
+        Ok(endpoint)
     }
 
     /// Connect to a remote Dockru instance
-    pub async fn connect(&self, url: &str, username: &str, password: &str) {
+    pub async fn connect(&self, url: &str, credentials: &AgentCredentials) {
         let parsed_url = match url::Url::parse(url) {
             Ok(u) => u,
             Err(e) => {
@@ -301,21 +260,12 @@ impl AgentManager {
         let socket_ref = self.socket.clone();
         let agent_clients = self.agent_clients.clone();
         let endpoint_clone = endpoint.clone();
-        let username = username.to_string();
-        let password = password.to_string();
+        let login = credentials.login_event();
         let url = url.to_string();
 
         // Spawn connection task
         tokio::spawn(async move {
-            Self::connect_internal(
-                socket_ref,
-                agent_clients,
-                url,
-                endpoint_clone,
-                username,
-                password,
-            )
-            .await;
+            Self::connect_internal(socket_ref, agent_clients, url, endpoint_clone, login).await;
         });
     }
 
@@ -325,8 +275,7 @@ impl AgentManager {
         agent_clients: Arc<RwLock<HashMap<String, AgentClient>>>,
         url: String,
         endpoint: String,
-        username: String,
-        password: String,
+        (login_event, login_data): (&'static str, Value),
     ) {
         // Create clones for each callback (can't move the same value into multiple closures)
         let socket_ref_for_connect = socket_ref.clone();
@@ -341,8 +290,6 @@ impl AgentManager {
         let endpoint_for_info = endpoint.clone();
         
         let agent_clients_for_connect = agent_clients.clone();
-        let username_for_connect = username.clone();
-        let password_for_connect = password.clone();
 
         match ClientBuilder::new(&url)
             .opening_header("endpoint", endpoint.as_str())
@@ -350,66 +297,36 @@ impl AgentManager {
                 let socket_ref = socket_ref_for_connect.clone();
                 let endpoint = endpoint_for_connect.clone();
                 let agent_clients = agent_clients_for_connect.clone();
-                let username = username_for_connect.clone();
-                let password = password_for_connect.clone();
+                let login_data = login_data.clone();
 
                 async move {
                     info!("Connected to socket server: {}", endpoint);
 
-                    // Clone endpoint for error message (in case emit_with_ack fails)
-                    let endpoint_for_error = endpoint.clone();
-
-                    // Emit login
-                    let login_data = json!({
-                        "username": username,
-                        "password": password,
-                    });
-
-                    if let Err(e) = socket.emit_with_ack(
-                        "login",
-                        login_data,
-                        Duration::from_secs(10),
-                        move |payload: Payload, _socket: Client| {
-                            let socket_ref = socket_ref.clone();
-                            let endpoint = endpoint.clone();
-                            let agent_clients = agent_clients.clone();
-
-                            async move {
-                                if let Payload::Text(values) = payload {
-                                    if let Some(obj) = values.first() {
-                                        if let Some(ok) = obj.get("ok").and_then(|v| v.as_bool()) {
-                                            if ok {
-                                                    info!("Logged in to socket server: {}", endpoint);
-                                                    
-                                                    // Update logged_in status
-                                                    {
-                                                        let mut clients = agent_clients.write().await;
-                                                        if let Some(client) = clients.get_mut(&endpoint) {
-                                                            client.logged_in = true;
-                                                        }
-                                                    }
-
-                                                    // Emit online status
-                                                    socket_ref.emit("agentStatus", &json!({
-                                                        "endpoint": endpoint,
-                                                        "status": "online",
-                                                    })).ok();
-                                                } else {
-                                                    error!("Failed to login to socket server: {}", endpoint);
-                                                    socket_ref.emit("agentStatus", &json!({
-                                                        "endpoint": endpoint,
-                                                        "status": "offline",
-                                                    })).ok();
-                                                }
-                                            }
-                                        }
-                                    }
+                    // Log in from a task; waiting for the ack in this callback
+                    // would block the client's event loop that delivers it
+                    tokio::spawn(async move {
+                        let login = DockruClient::from_socket(socket)
+                            .request(login_event, login_data)
+                            .await;
+                        let status = match login {
+                            Ok(_) => {
+                                info!("Logged in to socket server: {}", endpoint);
+                                let mut clients = agent_clients.write().await;
+                                if let Some(client) = clients.get_mut(&endpoint) {
+                                    client.logged_in = true;
                                 }
-                            .boxed()
-                        }
-                    ).await {
-                        error!("Failed to emit login to {}: {}", endpoint_for_error, e);
-                    }
+                                AgentStatus::Online
+                            }
+                            Err(e) => {
+                                error!("Failed to login to socket server {}: {}", endpoint, e);
+                                AgentStatus::Offline
+                            }
+                        };
+                        socket_ref.emit(server_event::AGENT_STATUS, &json!({
+                            "endpoint": endpoint,
+                            "status": status.as_str(),
+                        })).ok();
+                    });
                 }
                 .boxed()
             })
@@ -418,7 +335,7 @@ impl AgentManager {
                 let endpoint = endpoint_for_error.clone();
                 async move {
                     error!("Connection error from socket server: {}", endpoint);
-                    socket_ref.emit("agentStatus", &json!({
+                    socket_ref.emit(server_event::AGENT_STATUS, &json!({
                         "endpoint": endpoint,
                         "status": "offline",
                     })).ok();
@@ -430,7 +347,7 @@ impl AgentManager {
                 let endpoint = endpoint_for_disconnect.clone();
                 async move {
                     info!("Disconnected from socket server: {}", endpoint);
-                    socket_ref.emit("agentStatus", &json!({
+                    socket_ref.emit(server_event::AGENT_STATUS, &json!({
                         "endpoint": endpoint,
                         "status": "offline",
                     })).ok();
@@ -442,7 +359,7 @@ impl AgentManager {
                 async move {
                     // Forward agent events to the main socket
                     if let Payload::Text(values) = payload {
-                        socket_ref.emit("agent", &values).ok();
+                        socket_ref.emit(server_event::AGENT, &values).ok();
                     }
                 }
                 .boxed()
@@ -462,7 +379,7 @@ impl AgentManager {
                                         let min_version = semver::Version::new(1, 4, 0);
                                         if version < min_version {
                                             warn!("Agent {} has unsupported version: {}", endpoint, version_str);
-                                            socket_ref.emit("agentStatus", &json!({
+                                            socket_ref.emit(server_event::AGENT_STATUS, &json!({
                                                 "endpoint": endpoint,
                                                 "status": "offline",
                                                 "msg": format!("{}: Unsupported version: {}", endpoint, version_str),
@@ -489,7 +406,7 @@ impl AgentManager {
                 clients.insert(
                     endpoint.clone(),
                     AgentClient {
-                        client,
+                        client: DockruClient::from_socket(client),
                         logged_in: false,
                         endpoint: endpoint.clone(),
                     },
@@ -498,7 +415,7 @@ impl AgentManager {
             }
             Err(e) => {
                 error!("Failed to connect to {}: {}", endpoint, e);
-                socket_ref.emit("agentStatus", &json!({
+                socket_ref.emit(server_event::AGENT_STATUS, &json!({
                     "endpoint": endpoint,
                     "status": "offline",
                 })).ok();
@@ -531,6 +448,8 @@ impl AgentManager {
             return;
         }
 
+        self.connects_agents.store(true, Ordering::Relaxed);
+
         let agents = match Agent::find_all(&self.db, &self.encryption_secret).await {
             Ok(agents) => agents,
             Err(e) => {
@@ -544,8 +463,39 @@ impl AgentManager {
         }
 
         for agent in agents {
-            self.connect(&agent.url, &agent.username, agent.password.expose_secret()).await;
+            self.connect(&agent.url, &agent.credentials()).await;
+        }
+    }
+
+    /// Match connections to the agents table: drop removed agents, connect new ones
+    pub async fn sync_with_db(&self) {
+        let agents = match Agent::find_all(&self.db, &self.encryption_secret).await {
+            Ok(agents) => agents,
+            Err(e) => {
+                error!("Failed to list agents: {}", e);
+                return;
+            }
+        };
+
+        let stale: Vec<String> = {
+            let clients = self.agent_clients.read().await;
+            clients
+                .keys()
+                .filter(|endpoint| !agents.iter().any(|a| &a.endpoint == *endpoint))
+                .cloned()
+                .collect()
+        };
+        for endpoint in stale {
+            self.disconnect(&endpoint).await;
         }
+
+        if self.connects_agents.load(Ordering::Relaxed) {
+            for agent in agents {
+                self.connect(&agent.url, &agent.credentials()).await;
+            }
+        }
+
+        self.send_agent_list().await;
     }
 
     /// Disconnect from all agents
@@ -567,13 +517,42 @@ impl AgentManager {
         args: Value,
     ) -> Result<()> {
         debug!("Emitting event {} to endpoint: {}", event_name, endpoint);
+        let client = self.ready_client(endpoint).await?;
+
+        // Emit the event via the agent proxy
+        client
+            .emit_endpoint(endpoint, event_name, args)
+            .await
+            .map_err(|e| anyhow!("{}: {}", endpoint, e))
+    }
 
+    /// Emit an event to a specific endpoint and wait for the endpoint's
+    /// response, so it can be passed on to the client's ack
+    pub async fn emit_to_endpoint_with_ack(
+        &self,
+        endpoint: &str,
+        event_name: &str,
+        args: Value,
+    ) -> Result<Value> {
+        debug!("Emitting event {} to endpoint {} with ack", event_name, endpoint);
+        let client = self.ready_client(endpoint).await?;
+
+        client
+            .with_timeout(Duration::from_secs(AGENT_PROXY_ACK_TIMEOUT_SECS))
+            .call_endpoint(endpoint, event_name, args)
+            .await
+            .map_err(|e| anyhow!("{}: {}", endpoint, e))
+    }
+
+    /// The client of an endpoint once it's logged in, waiting for it for up to
+    /// 10 seconds after the first connect
+    async fn ready_client(&self, endpoint: &str) -> Result<DockruClient> {
         let client = {
             let clients = self.agent_clients.read().await;
             clients.get(endpoint).map(|c| c.client.clone())
         };
 
-            let client = client.ok_or_else(|| {
+        let client = client.ok_or_else(|| {
             error!("Socket client not found for endpoint: {}", endpoint);
             anyhow!("Socket client not found for endpoint: {}", endpoint)
         })?;
@@ -633,14 +612,7 @@ impl AgentManager {
             }
         }
 
-        // Emit the event via the agent proxy
-        let wrapped_args = json!([endpoint, event_name, args]);
-        client
-            .emit("agent", wrapped_args)
-            .await
-            .map_err(|e| anyhow!("Failed to emit to {}: {}", endpoint, e))?;
-
-        Ok(())
+        Ok(client)
     }
 
     /// Emit an event to all endpoints
@@ -659,6 +631,13 @@ impl AgentManager {
         }
     }
 
+    /// Get the number of agent connections and how many of them are logged in
+    pub async fn connection_counts(&self) -> (usize, usize) {
+        let clients = self.agent_clients.read().await;
+        let logged_in = clients.values().filter(|c| c.logged_in).count();
+        (clients.len(), logged_in)
+    }
+
     /// Send the agent list to the client
     pub async fn send_agent_list(&self) {
         let agents = match Agent::find_all(&self.db, &self.encryption_secret).await {
@@ -689,7 +668,7 @@ impl AgentManager {
             }
         }
 
-        self.socket.emit("agentList", &json!({
+        self.socket.emit(server_event::AGENT_LIST, &json!({
             "ok": true,
             "agentList": agent_list,
         })).ok();
@@ -708,7 +687,7 @@ impl AgentManager {
             data["msg"] = json!(msg);
         }
 
-        self.socket.emit("agentStatus", &data).ok();
+        self.socket.emit(server_event::AGENT_STATUS, &data).ok();
     }
 }
 
@@ -742,3 +721,52 @@ pub async fn remove_agent_manager(socket_id: &str) {
     let mut managers = AGENT_MANAGERS.write().await;
     managers.remove(socket_id);
 }
+
+/// Sum agent connection counts across all AgentManagers
+///
+/// Returns (total connections, logged in connections)
+pub async fn agent_connection_counts() -> (usize, usize) {
+    let managers: Vec<Arc<AgentManager>> = {
+        let managers = AGENT_MANAGERS.read().await;
+        managers.values().cloned().collect()
+    };
+
+    let mut total = 0;
+    let mut logged_in = 0;
+    for manager in managers {
+        let (t, l) = manager.connection_counts().await;
+        total += t;
+        logged_in += l;
+    }
+    (total, logged_in)
+}
+
+/// Tear down an endpoint across every AgentManager after its agent was removed
+///
+/// Disconnecting the proxied client closes any remote terminal sessions that were
+/// joined through it, then each socket gets a fresh agent list.
+pub async fn deregister_endpoint(endpoint: &str) {
+    let managers: Vec<Arc<AgentManager>> = {
+        let managers = AGENT_MANAGERS.read().await;
+        managers.values().cloned().collect()
+    };
+
+    for manager in managers {
+        manager.disconnect(endpoint).await;
+        manager.send_agent_list().await;
+    }
+}
+
+/// Bring every AgentManager in line with the agents table
+///
+/// Used when agents were added or removed on another cluster node.
+pub async fn sync_all_with_db() {
+    let managers: Vec<Arc<AgentManager>> = {
+        let managers = AGENT_MANAGERS.read().await;
+        managers.values().cloned().collect()
+    };
+
+    for manager in managers {
+        manager.sync_with_db().await;
+    }
+}
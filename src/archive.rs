@@ -0,0 +1,459 @@
+// Stack archives
+//
+// A stack is exported as a gzipped tarball of its directory, with every entry
+// under a top-level `<stackName>/` folder. Symlinks are stored as links rather
+// than followed, so an export never includes files from outside the stack.
+// REST downloads stream the same tarball uncompressed and leave compression to
+// the transfer encoding.
+//
+// Imports accept .tar.gz, .tar and .zip. Archives are read fully into memory
+// and checked before anything is written:
+// - only regular files and directories are kept (links/devices are skipped)
+// - absolute paths and `..` components are rejected
+// - a single top-level folder (as produced by export) is stripped
+//
+// Uploads arrive over HTTP and are held in memory for a few minutes until the
+// importStack socket event picks them up by id.
+//
+// Exports can be encrypted with a passphrase (`.tar.gz.enc`) so backups can be
+// kept on untrusted storage. Imports recognise encrypted archives and need the
+// passphrase, and decryption fails if the archive was altered.
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::utils::constants::{
+    ACCEPTED_COMPOSE_FILE_NAMES, MAX_STACK_ARCHIVE_BYTES, MAX_STACK_ARCHIVE_UNPACKED_BYTES,
+};
+use crate::utils::crypto::{
+    decrypt_with_passphrase, encrypt_with_passphrase, is_passphrase_encrypted,
+};
+use redact::Secret;
+
+/// How long an uploaded archive waits for importStack
+const UPLOAD_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of uploads held at once
+const MAX_PENDING_UPLOADS: usize = 8;
+
+/// An uploaded archive waiting for importStack
+struct PendingUpload {
+    received_at: Instant,
+    /// Only the uploader can claim it
+    user_id: i64,
+    data: Vec<u8>,
+}
+
+/// Upload id -> upload
+type PendingUploads = HashMap<String, PendingUpload>;
+
+static PENDING_UPLOADS: Lazy<Mutex<PendingUploads>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A file or directory read from a stack archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Path relative to the stack directory
+    pub path: PathBuf,
+    /// File contents, None for directories
+    pub data: Option<Vec<u8>>,
+}
+
+/// Pack a stack directory into a `.tar.gz` archive
+pub fn pack_stack_dir(stack_path: &Path, stack_name: &str) -> Result<Vec<u8>> {
+    let encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let archive = write_stack_tar(stack_path, stack_name, encoder)?
+        .finish()
+        .context("Failed to finish stack archive")?;
+
+    if archive.len() > MAX_STACK_ARCHIVE_BYTES {
+        return Err(anyhow!(
+            "Stack archive is too large ({} bytes, limit {} bytes)",
+            archive.len(),
+            MAX_STACK_ARCHIVE_BYTES
+        ));
+    }
+
+    Ok(archive)
+}
+
+/// Encrypt a packed archive with a passphrase
+pub fn encrypt_archive(archive: &[u8], passphrase: &Secret<String>) -> Result<Vec<u8>> {
+    encrypt_with_passphrase(archive, passphrase)
+}
+
+/// The archive in an upload, decrypted if it was encrypted
+pub fn decrypt_archive(data: Vec<u8>, passphrase: Option<&Secret<String>>) -> Result<Vec<u8>> {
+    if !is_passphrase_encrypted(&data) {
+        return Ok(data);
+    }
+    let passphrase =
+        passphrase.ok_or_else(|| anyhow!("The archive is encrypted, enter its passphrase"))?;
+    decrypt_with_passphrase(&data, passphrase)
+}
+
+/// File name of an exported stack
+pub fn archive_filename(stack_name: &str, encrypted: bool) -> String {
+    if encrypted {
+        format!("{}.tar.gz.enc", stack_name)
+    } else {
+        format!("{}.tar.gz", stack_name)
+    }
+}
+
+/// Write a stack directory as an uncompressed tar to `writer`, returning the writer
+///
+/// Used directly for streamed downloads, which have no size limit.
+pub fn write_stack_tar<W: Write>(stack_path: &Path, stack_name: &str, writer: W) -> Result<W> {
+    if !stack_path.is_dir() {
+        return Err(anyhow!("Stack directory not found"));
+    }
+
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    builder
+        .append_dir_all(stack_name, stack_path)
+        .with_context(|| format!("Failed to archive {}", stack_path.display()))?;
+
+    builder
+        .into_inner()
+        .context("Failed to finish stack archive")
+}
+
+/// Read a .tar.gz, .tar or .zip stack archive into memory
+pub fn read_stack_archive(data: &[u8]) -> Result<Vec<ArchiveEntry>> {
+    let entries = if data.starts_with(&[0x1f, 0x8b]) {
+        read_tar(GzDecoder::new(data))?
+    } else if data.starts_with(b"PK\x03\x04") {
+        read_zip(data)?
+    } else if data.len() > 262 && &data[257..262] == b"ustar" {
+        read_tar(data)?
+    } else {
+        return Err(anyhow!(
+            "Unsupported archive format, expected .tar.gz, .tar or .zip"
+        ));
+    };
+
+    Ok(strip_top_level_dir(entries))
+}
+
+fn read_tar<R: Read>(reader: R) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+    let mut total = 0usize;
+
+    for entry in archive.entries().context("Failed to read tar archive")? {
+        let mut entry = entry.context("Failed to read tar entry")?;
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            continue;
+        }
+
+        let Some(path) = sanitize_path(&entry.path().context("Invalid tar entry path")?)? else {
+            continue;
+        };
+
+        let data = if entry_type.is_file() {
+            Some(read_limited(&mut entry, &mut total)?)
+        } else {
+            None
+        };
+        entries.push(ArchiveEntry { path, data });
+    }
+
+    Ok(entries)
+}
+
+fn read_zip(data: &[u8]) -> Result<Vec<ArchiveEntry>> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).context("Failed to read zip archive")?;
+    let mut entries = Vec::new();
+    let mut total = 0usize;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).context("Failed to read zip entry")?;
+        if file.is_symlink() {
+            continue;
+        }
+
+        let path = file
+            .enclosed_name()
+            .ok_or_else(|| anyhow!("Archive entry \"{}\" has an unsafe path", file.name()))?;
+        let Some(path) = sanitize_path(&path)? else {
+            continue;
+        };
+
+        let data = if file.is_dir() {
+            None
+        } else {
+            Some(read_limited(&mut file, &mut total)?)
+        };
+        entries.push(ArchiveEntry { path, data });
+    }
+
+    Ok(entries)
+}
+
+/// Read an entry, keeping a running total against the unpacked size limit
+fn read_limited<R: Read>(reader: &mut R, total: &mut usize) -> Result<Vec<u8>> {
+    let remaining = MAX_STACK_ARCHIVE_UNPACKED_BYTES.saturating_sub(*total);
+    let mut buf = Vec::new();
+    reader
+        .take(remaining as u64 + 1)
+        .read_to_end(&mut buf)
+        .context("Failed to read archive entry")?;
+
+    *total += buf.len();
+    if *total > MAX_STACK_ARCHIVE_UNPACKED_BYTES {
+        return Err(anyhow!(
+            "Archive contents exceed {} bytes",
+            MAX_STACK_ARCHIVE_UNPACKED_BYTES
+        ));
+    }
+
+    Ok(buf)
+}
+
+/// Normalize an entry path, rejecting anything that could escape the stack
+/// directory. Returns None for the archive root itself.
+fn sanitize_path(path: &Path) -> Result<Option<PathBuf>> {
+    let mut clean = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => clean.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(anyhow!(
+                    "Archive entry \"{}\" has an unsafe path",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    Ok((!clean.as_os_str().is_empty()).then_some(clean))
+}
+
+/// Strip a single shared top-level folder, unless a compose file already sits at the root
+fn strip_top_level_dir(entries: Vec<ArchiveEntry>) -> Vec<ArchiveEntry> {
+    let has_root_compose = entries.iter().any(|e| {
+        ACCEPTED_COMPOSE_FILE_NAMES
+            .iter()
+            .any(|name| e.path == Path::new(name))
+    });
+    if has_root_compose {
+        return entries;
+    }
+
+    let first = |e: &ArchiveEntry| e.path.components().next().map(|c| c.as_os_str().to_owned());
+    let Some(top) = entries.first().and_then(first) else {
+        return entries;
+    };
+    let shared = entries.iter().all(|e| {
+        first(e).as_ref() == Some(&top) && (e.data.is_none() || e.path.components().count() > 1)
+    });
+    if !shared {
+        return entries;
+    }
+
+    entries
+        .into_iter()
+        .filter_map(|e| {
+            let path = e.path.strip_prefix(&top).ok()?.to_path_buf();
+            (!path.as_os_str().is_empty()).then_some(ArchiveEntry { path, data: e.data })
+        })
+        .collect()
+}
+
+/// Write archive entries below a directory
+pub fn write_entries(dest: &Path, entries: &[ArchiveEntry]) -> Result<()> {
+    std::fs::create_dir_all(dest).context("Failed to create stack directory")?;
+
+    for entry in entries {
+        let target = dest.join(&entry.path);
+        match &entry.data {
+            None => std::fs::create_dir_all(&target),
+            Some(data) => {
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&target, data)
+            }
+        }
+        .with_context(|| format!("Failed to write {}", target.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Hold an archive uploaded by a user until importStack claims it, returning
+/// its id
+pub fn store_upload(user_id: i64, data: Vec<u8>) -> Result<String> {
+    let mut uploads = PENDING_UPLOADS.lock().unwrap();
+    uploads.retain(|_, upload| upload.received_at.elapsed() < UPLOAD_TTL);
+    if uploads.len() >= MAX_PENDING_UPLOADS {
+        return Err(anyhow!("Too many pending uploads, try again later"));
+    }
+
+    let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+    uploads.insert(
+        id.clone(),
+        PendingUpload {
+            received_at: Instant::now(),
+            user_id,
+            data,
+        },
+    );
+    Ok(id)
+}
+
+/// Claim an archive the user uploaded. Each upload can be claimed once.
+pub fn take_upload(id: &str, user_id: i64) -> Option<Vec<u8>> {
+    let mut uploads = PENDING_UPLOADS.lock().unwrap();
+    if uploads.get(id)?.user_id != user_id {
+        return None;
+    }
+    let upload = uploads.remove(id)?;
+    (upload.received_at.elapsed() < UPLOAD_TTL).then_some(upload.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_pack_stack_dir() {
+        let dir = TempDir::new().unwrap();
+        let stack_path = dir.path().join("web");
+        std::fs::create_dir_all(stack_path.join("config")).unwrap();
+        std::fs::write(stack_path.join("compose.yaml"), "services: {}\n").unwrap();
+        std::fs::write(stack_path.join(".env"), "A=1\n").unwrap();
+        std::fs::write(stack_path.join("config/app.conf"), "x").unwrap();
+
+        let archive = pack_stack_dir(&stack_path, "web").unwrap();
+
+        let mut entries: Vec<String> = tar::Archive::new(GzDecoder::new(archive.as_slice()))
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect();
+        entries.sort();
+
+        assert!(entries.contains(&"web/compose.yaml".to_string()));
+        assert!(entries.contains(&"web/.env".to_string()));
+        assert!(entries.contains(&"web/config/app.conf".to_string()));
+        assert!(entries.iter().all(|e| e.starts_with("web")));
+    }
+
+    #[test]
+    fn test_encrypted_archive_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let stack_path = dir.path().join("web");
+        std::fs::create_dir_all(&stack_path).unwrap();
+        std::fs::write(stack_path.join("compose.yaml"), "services: {}\n").unwrap();
+
+        let archive = pack_stack_dir(&stack_path, "web").unwrap();
+        let passphrase = Secret::new("correct horse battery".to_string());
+        // Few rounds, full ones are slow in debug builds
+        let encrypted =
+            crate::utils::crypto::encrypt_with_passphrase_rounds(&archive, &passphrase, 1000)
+                .unwrap();
+        assert!(read_stack_archive(&encrypted).is_err());
+
+        let err = decrypt_archive(encrypted.clone(), None).unwrap_err();
+        assert!(err.to_string().contains("passphrase"));
+        let decrypted = decrypt_archive(encrypted, Some(&passphrase)).unwrap();
+        assert_eq!(decrypted, archive);
+
+        // Plain archives pass through, passphrase or not
+        assert_eq!(
+            decrypt_archive(archive.clone(), Some(&passphrase)).unwrap(),
+            archive
+        );
+        assert_eq!(archive_filename("web", true), "web.tar.gz.enc");
+    }
+
+    #[test]
+    fn test_read_exported_archive_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let stack_path = dir.path().join("web");
+        std::fs::create_dir_all(&stack_path).unwrap();
+        std::fs::write(stack_path.join("compose.yaml"), "services: {}\n").unwrap();
+
+        let archive = pack_stack_dir(&stack_path, "web").unwrap();
+        let entries = read_stack_archive(&archive).unwrap();
+
+        // The top-level "web/" folder is stripped
+        let file = entries
+            .iter()
+            .find(|e| e.path == Path::new("compose.yaml"))
+            .unwrap();
+        assert_eq!(file.data.as_deref(), Some(b"services: {}\n".as_slice()));
+
+        let dest = dir.path().join("imported");
+        write_entries(&dest, &entries).unwrap();
+        assert!(dest.join("compose.yaml").is_file());
+    }
+
+    #[test]
+    fn test_read_zip_archive() {
+        use std::io::Write;
+
+        let mut buf = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::SimpleFileOptions::default();
+            zip.start_file("compose.yaml", options).unwrap();
+            zip.write_all(b"services: {}\n").unwrap();
+            zip.start_file("data/app.conf", options).unwrap();
+            zip.write_all(b"x").unwrap();
+            zip.finish().unwrap();
+        }
+
+        let entries = read_stack_archive(buf.get_ref()).unwrap();
+        let paths: Vec<_> = entries.iter().map(|e| e.path.clone()).collect();
+        assert!(paths.contains(&PathBuf::from("compose.yaml")));
+        assert!(paths.contains(&PathBuf::from("data/app.conf")));
+    }
+
+    #[test]
+    fn test_sanitize_path() {
+        assert_eq!(
+            sanitize_path(Path::new("./a/b")).unwrap(),
+            Some(PathBuf::from("a/b"))
+        );
+        assert_eq!(sanitize_path(Path::new(".")).unwrap(), None);
+        assert!(sanitize_path(Path::new("../etc/passwd")).is_err());
+        assert!(sanitize_path(Path::new("/etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert!(read_stack_archive(b"not an archive").is_err());
+    }
+
+    #[test]
+    fn test_uploads_are_claimed_once() {
+        let id = store_upload(1, vec![1, 2, 3]).unwrap();
+        // Other users can neither claim nor discard it
+        assert_eq!(take_upload(&id, 2), None);
+        assert_eq!(take_upload(&id, 1), Some(vec![1, 2, 3]));
+        assert_eq!(take_upload(&id, 1), None);
+    }
+
+    #[test]
+    fn test_pack_missing_dir() {
+        let dir = TempDir::new().unwrap();
+        assert!(pack_stack_dir(&dir.path().join("missing"), "missing").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+// Auto-start of stacks at boot
+//
+// Stacks flagged in the stack_autostart table are started when dockru boots,
+// which matters when dockru is the only orchestrator on the host and nothing
+// else brings the stacks back after a reboot. Once the Docker daemon answers,
+// every flagged managed stack that isn't running is started one at a time, in
+// dependency order, with progress in the batch terminal and each stack's
+// output in its own compose terminal. Unlike deployAll a failing stack doesn't
+// stop the rest; the outcome is logged and broadcast as an `autostart` event.
+// In a cluster, where the nodes share the stacks and Docker host, only the
+// leader does this.
+
+use crate::db::models::{StackAutostart, StackDependency};
+use crate::docker::DeployOptions;
+use crate::server::ServerContext;
+use crate::socket_handlers::broadcast_to_authenticated;
+use crate::stack::Stack;
+use crate::terminal::{Terminal, TerminalType};
+use crate::utils::constants::RUNNING;
+use crate::utils::stack_order::dependency_order;
+use crate::utils::terminal::get_batch_terminal_name;
+use anyhow::{anyhow, Result};
+use dockru_protocol::events::server as server_event;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often to check whether the Docker daemon is up
+const DOCKER_WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Give up on auto-start if the daemon isn't up by then
+const DOCKER_WAIT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Outcome of an auto-start run
+#[derive(Debug, Default, Serialize)]
+pub struct AutostartSummary {
+    /// Stacks that were started, in order
+    pub started: Vec<String>,
+    pub failed: Vec<AutostartFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AutostartFailure {
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub msg: String,
+}
+
+/// Start the flagged stacks in the background once Docker is reachable
+pub fn start(ctx: Arc<ServerContext>) {
+    tokio::spawn(async move {
+        if !wait_for_docker(&ctx).await {
+            warn!(
+                "Docker daemon unreachable for {:?}, skipping stack auto-start",
+                DOCKER_WAIT_TIMEOUT
+            );
+            return;
+        }
+        // In a cluster only the leader starts stacks, once it is known
+        if !crate::cluster::wait_for_leader(&ctx).await {
+            info!("Not the cluster leader, skipping stack auto-start");
+            return;
+        }
+
+        match run(&ctx).await {
+            Ok(summary) if summary.started.is_empty() && summary.failed.is_empty() => {}
+            Ok(summary) => {
+                info!(
+                    "Auto-start finished: {} started, {} failed",
+                    summary.started.len(),
+                    summary.failed.len()
+                );
+                let data = serde_json::json!({
+                    "ok": summary.failed.is_empty(),
+                    "started": summary.started,
+                    "failed": summary.failed,
+                });
+                if let Err(e) =
+                    broadcast_to_authenticated(&ctx.io, server_event::AUTOSTART, data).await
+                {
+                    warn!("Failed to broadcast auto-start result: {}", e);
+                }
+                ctx.broadcast_notify.notify_one();
+            }
+            Err(e) => error!("Stack auto-start failed: {:#}", e),
+        }
+    });
+}
+
+/// Wait until the daemon answers a ping; false on timeout
+async fn wait_for_docker(ctx: &ServerContext) -> bool {
+    let deadline = tokio::time::Instant::now() + DOCKER_WAIT_TIMEOUT;
+    loop {
+        if ctx.docker.check_health().await {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(DOCKER_WAIT_INTERVAL).await;
+    }
+}
+
+/// Start every flagged managed stack that isn't running, continuing past failures
+pub async fn run(ctx: &Arc<ServerContext>) -> Result<AutostartSummary> {
+    let flagged = StackAutostart::find_all(&ctx.db).await?;
+    if flagged.is_empty() {
+        return Ok(AutostartSummary::default());
+    }
+
+    let mut stack_list = Stack::get_stack_list(ctx.clone(), String::new(), false).await?;
+    let mut candidates = Vec::new();
+    for (name, stack) in &stack_list {
+        if stack.is_managed_by_dockru().await {
+            candidates.push((name.clone(), stack.status()));
+        }
+    }
+    let to_start = stacks_to_start(&flagged, &candidates);
+    if to_start.is_empty() {
+        info!("All auto-start stacks are already running");
+        return Ok(AutostartSummary::default());
+    }
+
+    let dependencies = StackDependency::find_all(&ctx.db).await?;
+    let order = dependency_order(&to_start, &dependencies)?;
+
+    let terminal_name = get_batch_terminal_name("");
+    if Terminal::get_terminal(&terminal_name).await.is_some() {
+        return Err(anyhow!("Another batch operation is already running"));
+    }
+    let terminal = Terminal::new(
+        ctx.io.clone(),
+        terminal_name,
+        TerminalType::Base,
+        String::new(),
+        Vec::new(),
+        String::new(),
+    );
+
+    let mut summary = AutostartSummary::default();
+    for (i, name) in order.iter().enumerate() {
+        info!("Auto-starting stack {}", name);
+        terminal
+            .write_output(&format!(
+                "\x1b[1m[{}/{}] Auto-starting {}\x1b[0m\r\n",
+                i + 1,
+                order.len(),
+                name
+            ))
+            .await;
+
+        let Some(stack) = stack_list.remove(name) else {
+            continue;
+        };
+        let result = stack.start(&DeployOptions::default(), None).await;
+        stack.record_event("start", "autostart", &result).await;
+        let result = match result {
+            Ok(0) => Ok(()),
+            Ok(code) => Err(format!("exited with code {}", code)),
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(()) => summary.started.push(name.clone()),
+            Err(msg) => {
+                warn!("Auto-start of {} failed: {}", name, msg);
+                terminal
+                    .write_output(&format!("\x1b[31m{} failed: {}\x1b[0m\r\n", name, msg))
+                    .await;
+                summary.failed.push(AutostartFailure {
+                    stack_name: name.clone(),
+                    msg,
+                });
+            }
+        }
+    }
+
+    terminal
+        .write_output(&format!(
+            "Done: {} started, {} failed\r\n",
+            summary.started.len(),
+            summary.failed.len()
+        ))
+        .await;
+    terminal
+        .finish(if summary.failed.is_empty() { 0 } else { 1 })
+        .await;
+    Ok(summary)
+}
+
+/// Flagged stacks among the (name, status) candidates that aren't running, sorted
+fn stacks_to_start(flagged: &HashSet<String>, candidates: &[(String, i32)]) -> Vec<String> {
+    let mut names: Vec<String> = candidates
+        .iter()
+        .filter(|(name, status)| flagged.contains(name) && *status != RUNNING)
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::constants::{CREATED_FILE, EXITED, PARTIAL};
+
+    #[test]
+    fn test_stacks_to_start() {
+        let flagged: HashSet<String> = ["web", "db", "cache", "gone"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let candidates = vec![
+            ("web".to_string(), EXITED),
+            ("db".to_string(), RUNNING),
+            ("cache".to_string(), PARTIAL),
+            ("other".to_string(), CREATED_FILE),
+        ];
+        assert_eq!(stacks_to_start(&flagged, &candidates), ["cache", "web"]);
+        assert!(stacks_to_start(&HashSet::new(), &candidates).is_empty());
+    }
+}
@@ -0,0 +1,667 @@
+// Instance backups to remote storage
+//
+// A backup is a .tar.gz of a snapshot of the database (`dockru.db`, taken with
+// VACUUM INTO) and of the stacks directory (`stacks/`), encrypted like stack
+// exports when a passphrase is set. Backups are uploaded to one target:
+//
+// - s3: any S3-compatible object storage (AWS, MinIO, B2, R2, ...)
+// - webdav: a collection on a WebDAV server (Nextcloud, ...)
+//
+// The configuration is stored in the backupConfig setting. Credentials and the
+// passphrase aren't stored in it: it names stored secrets (see secrets) that
+// hold them, so reading the configuration never reveals them.
+//
+// With the configuration enabled, a backup runs whenever its cron schedule
+// comes due (on the cluster leader only). After each upload the oldest
+// backups on the target beyond `retention` are deleted; only files named like
+// backups (`dockru-backup-<UTC time>.tar.gz[.enc]`) are ever touched.
+
+mod s3;
+mod webdav;
+
+use crate::db::models::{Setting, StoredSecret};
+use crate::scheduler::{is_due, parse_cron};
+use crate::server::ServerContext;
+use crate::utils::constants::MAX_INSTANCE_BACKUP_BYTES;
+use crate::utils::crypto::{decrypt_password, encrypt_with_passphrase};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use once_cell::sync::Lazy;
+use redact::Secret;
+use s3::S3Client;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use webdav::WebDavClient;
+
+/// Setting holding the configuration
+pub const BACKUP_CONFIG_SETTING: &str = "backupConfig";
+
+/// Setting type of the configuration, keeping it out of the general settings
+pub const BACKUP_CONFIG_SETTING_TYPE: &str = "backup";
+
+/// How often the schedule is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest an upload or other request to the target may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Names of backup files start with this
+const BACKUP_NAME_PREFIX: &str = "dockru-backup-";
+
+/// Database snapshot in the data directory while a backup is built
+const SNAPSHOT_FILE: &str = "backup-snapshot.db";
+
+const DEFAULT_SCHEDULE: &str = "0 3 * * *";
+const DEFAULT_RETENTION: u32 = 7;
+const MAX_RETENTION: u32 = 1000;
+
+/// Whether a backup is running
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The outcome of the last backup
+static LAST_RUN: Lazy<Mutex<Option<BackupRun>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Run backups on the schedule
+    pub enabled: bool,
+    /// Cron expression, in local time
+    pub schedule: String,
+    /// Number of backups kept on the target
+    pub retention: u32,
+    /// Stored secret holding the passphrase backups are encrypted with
+    #[serde(rename = "passphraseSecret")]
+    pub passphrase_secret: Option<String>,
+    pub target: Option<BackupTarget>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            schedule: DEFAULT_SCHEDULE.to_string(),
+            retention: DEFAULT_RETENTION,
+            passphrase_secret: None,
+            target: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum BackupTarget {
+    S3(S3Target),
+    Webdav(WebDavTarget),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct S3Target {
+    /// e.g. https://s3.eu-west-1.amazonaws.com or http://minio:9000
+    pub endpoint: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Prepended to the names of backups, e.g. `dockru/`
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(rename = "accessKeyId")]
+    pub access_key_id: String,
+    /// Stored secret holding the secret access key
+    #[serde(rename = "secretAccessKeySecret")]
+    pub secret_access_key_secret: String,
+    /// Address the bucket in the path instead of the host name
+    #[serde(default, rename = "pathStyle")]
+    pub path_style: bool,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebDavTarget {
+    /// URL of the collection backups are put in
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    /// Stored secret holding the password
+    #[serde(default, rename = "passwordSecret")]
+    pub password_secret: Option<String>,
+}
+
+impl BackupConfig {
+    /// Check the configuration, with errors suitable for clients
+    pub fn validate(&self) -> Result<()> {
+        parse_cron(&self.schedule)?;
+        if !(1..=MAX_RETENTION).contains(&self.retention) {
+            bail!("Retention must be between 1 and {} backups", MAX_RETENTION);
+        }
+        if let Some(name) = &self.passphrase_secret {
+            crate::utils::secrets::validate_name(name)?;
+        }
+
+        match &self.target {
+            None if self.enabled => bail!("Choose where to upload backups to"),
+            None => {}
+            Some(BackupTarget::S3(s3)) => {
+                validate_url(&s3.endpoint)?;
+                let bucket_valid = !s3.bucket.is_empty()
+                    && s3.bucket.chars().all(|c| {
+                        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.'
+                    });
+                if !bucket_valid {
+                    bail!("Invalid bucket name \"{}\"", s3.bucket);
+                }
+                if s3.region.is_empty() || s3.access_key_id.is_empty() {
+                    bail!("The region and access key ID are required");
+                }
+                crate::utils::secrets::validate_name(&s3.secret_access_key_secret)?;
+            }
+            Some(BackupTarget::Webdav(dav)) => {
+                validate_url(&dav.url)?;
+                if let Some(name) = &dav.password_secret {
+                    crate::utils::secrets::validate_name(name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).map_err(|_| anyhow!("Invalid URL \"{}\"", url))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        bail!("URL \"{}\" must be http or https", url);
+    }
+    Ok(())
+}
+
+/// The outcome of a backup
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupRun {
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: String,
+    /// Name of the uploaded backup
+    pub name: Option<String>,
+    /// Size of the uploaded backup in bytes
+    pub size: Option<usize>,
+    /// Older backups deleted from the target
+    pub deleted: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Whether a backup is running, and the outcome of the last one
+pub fn status() -> (bool, Option<BackupRun>) {
+    (
+        RUNNING.load(Ordering::SeqCst),
+        LAST_RUN.lock().unwrap().clone(),
+    )
+}
+
+/// Load the configuration, the default when there is none
+pub async fn load_config(ctx: &ServerContext) -> Result<BackupConfig> {
+    match Setting::get(&ctx.db, &ctx.cache, BACKUP_CONFIG_SETTING).await? {
+        Some(value) => {
+            serde_json::from_value(value).context("Stored backup configuration is invalid")
+        }
+        None => Ok(BackupConfig::default()),
+    }
+}
+
+/// Back up the instance and upload it now
+///
+/// Fails without doing anything if a backup is already running. The outcome,
+/// failed or not, is kept for [`status`].
+pub async fn run_backup(ctx: &ServerContext) -> Result<BackupRun> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        bail!("A backup is already running");
+    }
+    let started = Local::now();
+    let result = backup_and_upload(ctx).await;
+    RUNNING.store(false, Ordering::SeqCst);
+
+    let finished_at = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+    let started_at = started.to_rfc3339_opts(SecondsFormat::Secs, false);
+    let run = match &result {
+        Ok((name, size, deleted)) => BackupRun {
+            started_at,
+            finished_at,
+            name: Some(name.clone()),
+            size: Some(*size),
+            deleted: deleted.clone(),
+            error: None,
+        },
+        Err(e) => BackupRun {
+            started_at,
+            finished_at,
+            name: None,
+            size: None,
+            deleted: Vec::new(),
+            error: Some(format!("{:#}", e)),
+        },
+    };
+    *LAST_RUN.lock().unwrap() = Some(run.clone());
+    result.map(|_| run)
+}
+
+/// Build, upload and rotate: (name, size, deleted backups)
+async fn backup_and_upload(ctx: &ServerContext) -> Result<(String, usize, Vec<String>)> {
+    let config = load_config(ctx).await?;
+    let target = config
+        .target
+        .clone()
+        .ok_or_else(|| anyhow!("No backup target is configured"))?;
+    let remote = Remote::connect(ctx, target).await?;
+    let passphrase = match &config.passphrase_secret {
+        Some(name) => Some(secret_value(ctx, name).await?),
+        None => None,
+    };
+
+    let snapshot = ctx.config.data_dir.join(SNAPSHOT_FILE);
+    let _ = tokio::fs::remove_file(&snapshot).await;
+    let archive = async {
+        sqlx::query("VACUUM INTO ?")
+            .bind(snapshot.to_string_lossy().to_string())
+            .execute(&ctx.db)
+            .await
+            .context("Failed to snapshot the database")?;
+        let snapshot = snapshot.clone();
+        let stacks_dir = ctx.config.stacks_dir.clone();
+        tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            let archive = pack_instance(&snapshot, &stacks_dir)?;
+            match passphrase {
+                Some(passphrase) => encrypt_with_passphrase(&archive, &passphrase),
+                None => Ok(archive),
+            }
+        })
+        .await
+        .context("Backup task failed")?
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&snapshot).await;
+    let archive = archive?;
+
+    let name = backup_name(Utc::now(), config.passphrase_secret.is_some());
+    let size = archive.len();
+    remote
+        .put(&name, archive)
+        .await
+        .with_context(|| format!("Failed to upload {}", name))?;
+    info!("Uploaded backup {} ({} bytes)", name, size);
+
+    // A failed rotation doesn't fail the backup, which is already uploaded
+    let mut deleted = Vec::new();
+    match remote.list().await {
+        Ok(names) => {
+            for old in expired_backups(names, config.retention as usize) {
+                match remote.delete(&old).await {
+                    Ok(()) => deleted.push(old),
+                    Err(e) => warn!("Failed to delete old backup {}: {:#}", old, e),
+                }
+            }
+        }
+        Err(e) => warn!("Failed to list backups for rotation: {:#}", e),
+    }
+
+    Ok((name, size, deleted))
+}
+
+/// Pack the database snapshot and the stacks directory into a .tar.gz
+fn pack_instance(db_snapshot: &Path, stacks_dir: &Path) -> Result<Vec<u8>> {
+    let encoder = GzEncoder::new(LimitedWriter::default(), Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    builder
+        .append_path_with_name(db_snapshot, "dockru.db")
+        .context("Failed to archive the database")?;
+    if stacks_dir.is_dir() {
+        builder
+            .append_dir_all("stacks", stacks_dir)
+            .context("Failed to archive the stacks directory")?;
+    }
+    let encoder = builder
+        .into_inner()
+        .context("Failed to finish the backup")?;
+    Ok(encoder.finish().context("Failed to finish the backup")?.0)
+}
+
+/// An in-memory buffer that fails past MAX_INSTANCE_BACKUP_BYTES
+#[derive(Default)]
+struct LimitedWriter(Vec<u8>);
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0.len() + buf.len() > MAX_INSTANCE_BACKUP_BYTES {
+            return Err(io::Error::other(format!(
+                "Backup is larger than {} bytes",
+                MAX_INSTANCE_BACKUP_BYTES
+            )));
+        }
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn backup_name(now: DateTime<Utc>, encrypted: bool) -> String {
+    format!(
+        "{}{}.tar.gz{}",
+        BACKUP_NAME_PREFIX,
+        now.format("%Y%m%d-%H%M%S"),
+        if encrypted { ".enc" } else { "" }
+    )
+}
+
+/// The backups to delete to keep the newest `keep`, ignoring other files
+fn expired_backups(names: Vec<String>, keep: usize) -> Vec<String> {
+    let mut backups: Vec<String> = names
+        .into_iter()
+        .filter(|name| {
+            name.starts_with(BACKUP_NAME_PREFIX)
+                && (name.ends_with(".tar.gz") || name.ends_with(".tar.gz.enc"))
+        })
+        .collect();
+    // The UTC time in the name sorts chronologically
+    backups.sort_by(|a, b| b.cmp(a));
+    backups.dedup();
+    backups.into_iter().skip(keep).collect()
+}
+
+/// The decrypted value of a stored secret
+async fn secret_value(ctx: &ServerContext, name: &str) -> Result<Secret<String>> {
+    let key = ctx.require_encryption_secret()?;
+    let encrypted = StoredSecret::find_values(&ctx.db, &[name.to_string()])
+        .await?
+        .remove(name)
+        .ok_or_else(|| anyhow!("Secret {} doesn't exist", name))?;
+    decrypt_password(&encrypted, &key).with_context(|| format!("Failed to decrypt secret {}", name))
+}
+
+/// A connected backup target
+enum Remote {
+    S3(S3Client),
+    WebDav(WebDavClient),
+}
+
+impl Remote {
+    async fn connect(ctx: &ServerContext, target: BackupTarget) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(match target {
+            BackupTarget::S3(s3) => {
+                let secret_key = secret_value(ctx, &s3.secret_access_key_secret).await?;
+                Remote::S3(S3Client::new(http, s3, secret_key))
+            }
+            BackupTarget::Webdav(dav) => {
+                let password = match &dav.password_secret {
+                    Some(name) => Some(secret_value(ctx, name).await?),
+                    None => None,
+                };
+                Remote::WebDav(WebDavClient::new(http, dav, password)?)
+            }
+        })
+    }
+
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        match self {
+            Remote::S3(s3) => s3.put(name, data).await,
+            Remote::WebDav(dav) => dav.put(name, data).await,
+        }
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        match self {
+            Remote::S3(s3) => s3.list().await,
+            Remote::WebDav(dav) => dav.list().await,
+        }
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        match self {
+            Remote::S3(s3) => s3.delete(name).await,
+            Remote::WebDav(dav) => dav.delete(name).await,
+        }
+    }
+}
+
+/// The text of every `<tag>` element, with or without a namespace prefix
+///
+/// Enough for the flat responses of S3 and WebDAV servers; entities are
+/// decoded, CDATA and nested elements aren't handled.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let name = rest[..end].split_whitespace().next().unwrap_or_default();
+        let local = name.rsplit(':').next().unwrap_or(name);
+        let opening = !name.starts_with('/') && !rest[..end].ends_with('/');
+        rest = &rest[end + 1..];
+        if !opening || local != tag {
+            continue;
+        }
+        let Some(close) = rest.find("</") else {
+            break;
+        };
+        values.push(
+            rest[..close]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[close..];
+    }
+    values
+}
+
+/// Start running scheduled backups
+pub fn start(ctx: Arc<ServerContext>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        let mut last_check = Local::now();
+
+        loop {
+            interval.tick().await;
+            let now = Local::now();
+            let since = std::mem::replace(&mut last_check, now);
+
+            // In a cluster only the leader backs up
+            if !crate::cluster::is_leader(&ctx) {
+                continue;
+            }
+
+            let config = match load_config(&ctx).await {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Failed to load the backup configuration: {:#}", e);
+                    continue;
+                }
+            };
+            if !config.enabled {
+                continue;
+            }
+            let due = match parse_cron(&config.schedule) {
+                Ok(cron) => is_due(&cron, &since, &now),
+                Err(e) => {
+                    warn!("Backup schedule is invalid: {}", e);
+                    false
+                }
+            };
+            if !due {
+                continue;
+            }
+
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = run_backup(&ctx).await {
+                    error!("Scheduled backup failed: {:#}", e);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    fn s3_config() -> BackupConfig {
+        BackupConfig {
+            enabled: true,
+            target: Some(BackupTarget::S3(S3Target {
+                endpoint: "http://minio:9000".to_string(),
+                region: default_region(),
+                bucket: "backups".to_string(),
+                prefix: "dockru/".to_string(),
+                access_key_id: "dockru".to_string(),
+                secret_access_key_secret: "MINIO_SECRET".to_string(),
+                path_style: true,
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(BackupConfig::default().validate().is_ok());
+        assert!(s3_config().validate().is_ok());
+
+        let enabled_without_target = BackupConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(enabled_without_target.validate().is_err());
+
+        let mut config = s3_config();
+        config.schedule = "every night".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = s3_config();
+        config.retention = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = s3_config();
+        config.passphrase_secret = Some("not a name".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = s3_config();
+        if let Some(BackupTarget::S3(s3)) = &mut config.target {
+            s3.bucket = "../other".to_string();
+        }
+        assert!(config.validate().is_err());
+
+        let config = BackupConfig {
+            target: Some(BackupTarget::Webdav(WebDavTarget {
+                url: "ftp://example.com/backups".to_string(),
+                username: String::new(),
+                password_secret: None,
+            })),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_config_deserialize() {
+        let config: BackupConfig = serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "target": {
+                "type": "webdav",
+                "url": "https://cloud.example.com/remote.php/dav/files/me/backups",
+                "username": "me",
+                "passwordSecret": "NEXTCLOUD_PASSWORD"
+            }
+        }))
+        .unwrap();
+        assert_eq!(config.schedule, DEFAULT_SCHEDULE);
+        assert_eq!(config.retention, DEFAULT_RETENTION);
+        assert!(matches!(config.target, Some(BackupTarget::Webdav(_))));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_backup_names_and_rotation() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 17, 3, 0, 0).unwrap();
+        assert_eq!(
+            backup_name(now, false),
+            "dockru-backup-20261017-030000.tar.gz"
+        );
+        assert_eq!(
+            backup_name(now, true),
+            "dockru-backup-20261017-030000.tar.gz.enc"
+        );
+
+        let names = vec![
+            "dockru-backup-20261015-030000.tar.gz".to_string(),
+            "notes.txt".to_string(),
+            "dockru-backup-20261017-030000.tar.gz.enc".to_string(),
+            "dockru-backup-20261016-030000.tar.gz".to_string(),
+            "dockru-backup-20261014-030000.tar.gz".to_string(),
+        ];
+        assert_eq!(
+            expired_backups(names.clone(), 2),
+            vec![
+                "dockru-backup-20261015-030000.tar.gz",
+                "dockru-backup-20261014-030000.tar.gz",
+            ]
+        );
+        assert!(expired_backups(names, 10).is_empty());
+    }
+
+    #[test]
+    fn test_pack_instance() {
+        let dir = TempDir::new().unwrap();
+        let snapshot = dir.path().join("snapshot.db");
+        std::fs::write(&snapshot, "sqlite").unwrap();
+        let stacks_dir = dir.path().join("stacks");
+        std::fs::create_dir_all(stacks_dir.join("web")).unwrap();
+        std::fs::write(stacks_dir.join("web/compose.yaml"), "services: {}\n").unwrap();
+
+        let archive = pack_instance(&snapshot, &stacks_dir).unwrap();
+        let mut entries: Vec<String> = tar::Archive::new(GzDecoder::new(archive.as_slice()))
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect();
+        entries.sort();
+        assert!(entries.contains(&"dockru.db".to_string()));
+        assert!(entries.contains(&"stacks/web/compose.yaml".to_string()));
+    }
+
+    #[test]
+    fn test_xml_values() {
+        let xml = "<ListBucketResult><IsTruncated>false</IsTruncated>\
+                   <Contents><Key>a&amp;b.tar.gz</Key></Contents>\
+                   <Contents><Key>c.tar.gz</Key></Contents></ListBucketResult>";
+        assert_eq!(xml_values(xml, "Key"), vec!["a&b.tar.gz", "c.tar.gz"]);
+        assert_eq!(xml_values(xml, "IsTruncated"), vec!["false"]);
+        assert_eq!(
+            xml_values("<d:href>/x</d:href><D:href>/y</D:href>", "href"),
+            vec!["/x", "/y"]
+        );
+        assert!(xml_values("<Key>unterminated", "Key").is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+// WebDAV backup target
+//
+// Backups are files in one collection (directory) of a WebDAV server, e.g.
+// Nextcloud's `remote.php/dav/files/<user>/backups/`. Files are uploaded with
+// PUT, listed with a depth-1 PROPFIND and deleted with DELETE, authenticated
+// with HTTP basic auth.
+
+use super::{xml_values, WebDavTarget};
+use anyhow::{bail, Context, Result};
+use redact::Secret;
+use reqwest::{Client, Method, Response, Url};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/></d:prop></d:propfind>"#;
+
+pub struct WebDavClient {
+    http: Client,
+    /// The collection, ending in `/`
+    base: Url,
+    username: String,
+    password: Option<Secret<String>>,
+}
+
+impl WebDavClient {
+    pub fn new(
+        http: Client,
+        target: WebDavTarget,
+        password: Option<Secret<String>>,
+    ) -> Result<Self> {
+        let mut url = target.url;
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        Ok(Self {
+            http,
+            base: Url::parse(&url).context("Invalid WebDAV URL")?,
+            username: target.username,
+            password,
+        })
+    }
+
+    /// Upload a file into the collection
+    pub async fn put(&self, name: &str, data: Vec<u8>) -> Result<()> {
+        let url = self.file_url(name)?;
+        self.send(self.request(Method::PUT, url).body(data)).await?;
+        Ok(())
+    }
+
+    /// Names of the files in the collection
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let propfind = Method::from_bytes(b"PROPFIND").expect("valid method");
+        let response = self
+            .send(
+                self.request(propfind, self.base.clone())
+                    .header("depth", "1")
+                    .header("content-type", "application/xml")
+                    .body(PROPFIND_BODY),
+            )
+            .await?;
+        let body = response
+            .text()
+            .await
+            .context("Failed to read WebDAV response")?;
+        Ok(file_names(&body))
+    }
+
+    /// Delete a file from the collection
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let url = self.file_url(name)?;
+        self.send(self.request(Method::DELETE, url)).await?;
+        Ok(())
+    }
+
+    fn file_url(&self, name: &str) -> Result<Url> {
+        self.base
+            .join(name)
+            .with_context(|| format!("Invalid WebDAV file name {}", name))
+    }
+
+    fn request(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, url);
+        if self.username.is_empty() {
+            return request;
+        }
+        request.basic_auth(
+            &self.username,
+            self.password.as_ref().map(|p| p.expose_secret()),
+        )
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Response> {
+        let response = request.send().await.context("WebDAV request failed")?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("WebDAV request to {} failed: {}", response.url(), status);
+        }
+        Ok(response)
+    }
+}
+
+/// The last path segment of each href in a PROPFIND response, except the
+/// collection itself (whose href ends in `/`)
+fn file_names(multistatus: &str) -> Vec<String> {
+    xml_values(multistatus, "href")
+        .into_iter()
+        .filter(|href| !href.ends_with('/'))
+        .filter_map(|href| href.rsplit('/').next().map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names() {
+        let body = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/remote.php/dav/files/admin/backups/</d:href>
+    <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/admin/backups/dockru-backup-20261016-030000.tar.gz</d:href>
+  </d:response>
+  <D:response xmlns:D="DAV:">
+    <D:href>https://dav.example.com/backups/dockru-backup-20261017-030000.tar.gz.enc</D:href>
+  </D:response>
+</d:multistatus>"#;
+        assert_eq!(
+            file_names(body),
+            vec![
+                "dockru-backup-20261016-030000.tar.gz",
+                "dockru-backup-20261017-030000.tar.gz.enc",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_base_url_is_a_collection() {
+        let client = WebDavClient::new(
+            Client::new(),
+            WebDavTarget {
+                url: "https://dav.example.com/backups".to_string(),
+                username: String::new(),
+                password_secret: None,
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            client.file_url("a.tar.gz").unwrap().as_str(),
+            "https://dav.example.com/backups/a.tar.gz"
+        );
+    }
+}
@@ -2,14 +2,45 @@
 
 use crate::db::models::Setting;
 use crate::server::ServerContext;
+use crate::socket_handlers::AUTHENTICATED_ROOM;
+use crate::utils::constants::{
+    DEFAULT_STACK_REFRESH_SECS, MAX_STACK_REFRESH_SECS, MIN_STACK_REFRESH_SECS,
+};
 use anyhow::Result;
+use dockru_protocol::events::server as server_event;
 use socketioxide::extract::SocketRef;
+use std::time::Duration;
 use tracing::debug;
 
 /// Send server info to a specific socket
 ///
 /// Emits: { version, latestVersion, primaryHostname }
 pub async fn send_info(socket: &SocketRef, ctx: &ServerContext, hide_version: bool) -> Result<()> {
+    let info = build_info(ctx, hide_version).await?;
+
+    socket.emit(server_event::INFO, &info).ok();
+
+    debug!("Sent info to socket {}", socket.id);
+
+    Ok(())
+}
+
+/// Send server info to every logged-in socket, e.g. after a version check
+pub async fn broadcast_info(ctx: &ServerContext) -> Result<()> {
+    let info = build_info(ctx, false).await?;
+
+    ctx.io
+        .to(AUTHENTICATED_ROOM)
+        .emit(server_event::INFO, &info)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to broadcast info: {}", e))?;
+
+    debug!("Broadcasted info to authenticated sockets");
+
+    Ok(())
+}
+
+async fn build_info(ctx: &ServerContext, hide_version: bool) -> Result<serde_json::Value> {
     let version = if hide_version {
         None
     } else {
@@ -38,17 +69,60 @@ pub async fn send_info(socket: &SocketRef, ctx: &ServerContext, hide_version: bo
         .await?
         .and_then(|v| v.as_str().map(|s| s.to_string()));
 
-    let info = serde_json::json!({
+    let checked_at = if hide_version {
+        None
+    } else {
+        ctx.version_checker.checked_at().await
+    };
+
+    Ok(serde_json::json!({
         "version": version,
         "latestVersion": latest_version,
         "currentSha": current_sha,
         "latestImageSha": latest_image_sha,
+        "versionCheckedAt": checked_at,
         "primaryHostname": primary_hostname,
-    });
+    }))
+}
 
-    socket.emit("info", &info).ok();
+/// Interval of the stack list broadcast, from the stackRefreshInterval setting
+pub async fn stack_refresh_interval(ctx: &ServerContext) -> Duration {
+    let value = match Setting::get(&ctx.db, &ctx.cache, "stackRefreshInterval").await {
+        Ok(value) => value,
+        Err(e) => {
+            debug!("Failed to read stackRefreshInterval: {}", e);
+            None
+        }
+    };
+    Duration::from_secs(parse_refresh_secs(value.as_ref()))
+}
 
-    debug!("Sent info to socket {}", socket.id);
+/// Seconds from a setting value (a number or numeric string), within bounds
+pub fn parse_refresh_secs(value: Option<&serde_json::Value>) -> u64 {
+    let secs = value.and_then(|v| {
+        v.as_u64()
+            .or_else(|| v.as_f64().map(|f| f.max(0.0) as u64))
+            .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+    });
+    secs.unwrap_or(DEFAULT_STACK_REFRESH_SECS)
+        .clamp(MIN_STACK_REFRESH_SECS, MAX_STACK_REFRESH_SECS)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_refresh_secs() {
+        assert_eq!(parse_refresh_secs(None), DEFAULT_STACK_REFRESH_SECS);
+        assert_eq!(parse_refresh_secs(Some(&json!(30))), 30);
+        assert_eq!(parse_refresh_secs(Some(&json!("45"))), 45);
+        assert_eq!(parse_refresh_secs(Some(&json!(1))), MIN_STACK_REFRESH_SECS);
+        assert_eq!(parse_refresh_secs(Some(&json!(-3))), MIN_STACK_REFRESH_SECS);
+        assert_eq!(
+            parse_refresh_secs(Some(&json!("soon"))),
+            DEFAULT_STACK_REFRESH_SECS
+        );
+    }
 }
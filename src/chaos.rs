@@ -0,0 +1,158 @@
+// Failure injection for development (`chaos` feature)
+//
+// Error paths such as a failing compose command, a database timeout or an
+// agent dropping off are hard to reproduce on a working setup. With the
+// feature enabled they can be armed with the debugInject event (see
+// socket_handlers/chaos), to test how the frontend and notifications handle
+// them:
+//
+// - composeExit: the next `count` compose commands exit with `exitCode`
+//   (default 1) instead of running
+// - dbTimeout: the next `count` socket events, or only events named `event`,
+//   fail with sqlx's pool timeout error before their handler runs
+// - agentDisconnect: drop the connection to the agent at `endpoint`
+// - clear: disarm everything
+//
+// The feature only builds in debug builds.
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Injection {
+    #[serde(rename_all = "camelCase")]
+    ComposeExit {
+        #[serde(default = "default_exit_code")]
+        exit_code: i32,
+        #[serde(default = "default_count")]
+        count: usize,
+    },
+    DbTimeout {
+        event: Option<String>,
+        #[serde(default = "default_count")]
+        count: usize,
+    },
+    AgentDisconnect {
+        endpoint: String,
+    },
+    Clear,
+}
+
+fn default_exit_code() -> i32 {
+    1
+}
+
+fn default_count() -> usize {
+    1
+}
+
+#[derive(Debug, Default)]
+struct Armed {
+    /// Exit codes of the next compose commands
+    compose_exits: VecDeque<i32>,
+    /// Failures of the next socket events, None matching any event
+    db_timeouts: Vec<Option<String>>,
+}
+
+static ARMED: Lazy<Mutex<Armed>> = Lazy::new(Mutex::default);
+
+/// Arm an injected compose exit or database timeout
+///
+/// Agent disconnects happen right away and are handled by the caller.
+pub fn arm(injection: &Injection) {
+    let mut armed = ARMED.lock().unwrap();
+    match injection {
+        Injection::ComposeExit { exit_code, count } => {
+            armed
+                .compose_exits
+                .extend(std::iter::repeat(*exit_code).take(*count));
+        }
+        Injection::DbTimeout { event, count } => {
+            armed
+                .db_timeouts
+                .extend(std::iter::repeat(event.clone()).take(*count));
+        }
+        Injection::AgentDisconnect { .. } => {}
+        Injection::Clear => *armed = Armed::default(),
+    }
+}
+
+/// The exit code the next compose command fakes, if one is armed
+pub fn take_compose_exit() -> Option<i32> {
+    ARMED.lock().unwrap().compose_exits.pop_front()
+}
+
+/// Whether the socket event `event` should fail with a database timeout
+pub fn take_db_timeout(event: &str) -> bool {
+    let mut armed = ARMED.lock().unwrap();
+    let position = armed
+        .db_timeouts
+        .iter()
+        .position(|e| e.as_deref().map_or(true, |e| e == event));
+    match position {
+        Some(i) => {
+            armed.db_timeouts.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_injection_deserialize() {
+        let injection: Injection =
+            serde_json::from_value(json!({ "kind": "composeExit" })).unwrap();
+        assert_eq!(
+            injection,
+            Injection::ComposeExit {
+                exit_code: 1,
+                count: 1
+            }
+        );
+        let injection: Injection =
+            serde_json::from_value(json!({ "kind": "dbTimeout", "event": "getStack", "count": 2 }))
+                .unwrap();
+        assert_eq!(
+            injection,
+            Injection::DbTimeout {
+                event: Some("getStack".to_string()),
+                count: 2
+            }
+        );
+        assert!(serde_json::from_value::<Injection>(json!({ "kind": "agentDisconnect" })).is_err());
+    }
+
+    #[test]
+    fn test_arm_and_take() {
+        arm(&Injection::Clear);
+        arm(&Injection::ComposeExit {
+            exit_code: 137,
+            count: 2,
+        });
+        arm(&Injection::DbTimeout {
+            event: Some("getStack".to_string()),
+            count: 1,
+        });
+        assert_eq!(take_compose_exit(), Some(137));
+        assert_eq!(take_compose_exit(), Some(137));
+        assert_eq!(take_compose_exit(), None);
+        assert!(!take_db_timeout("queryStackList"));
+        assert!(take_db_timeout("getStack"));
+        assert!(!take_db_timeout("getStack"));
+
+        arm(&Injection::DbTimeout {
+            event: None,
+            count: 1,
+        });
+        arm(&Injection::Clear);
+        assert!(!take_db_timeout("getStack"));
+    }
+}
@@ -4,9 +4,13 @@
 //   1. GitHub Releases API — detect semver bumps
 //   2. GHCR image manifest — detect image updates at the same version
 //
-// Runs every 48 hours.
+// Runs every 48 hours. Results are stored in the `versionCheck` setting and
+// served from there to every client, so restarts don't trigger a fresh check
+// and an admin can only force one once per cooldown.
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -15,6 +19,29 @@ use tracing::{debug, info};
 use crate::db::models::setting::SettingsCache;
 use crate::db::models::Setting;
 
+/// Setting key the last check's results are stored under
+const VERSION_CHECK_SETTING: &str = "versionCheck";
+
+/// How often to check for updates
+const CHECK_INTERVAL: chrono::Duration = chrono::Duration::hours(48);
+
+/// How often the scheduler looks whether a check is due
+const DUE_POLL_SECS: u64 = 60 * 60;
+
+/// Minimum time between checks forced with checkUpdatesNow
+const MANUAL_CHECK_COOLDOWN: chrono::Duration = chrono::Duration::minutes(10);
+
+/// Results of the last version check, as stored in settings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VersionCheckResult {
+    #[serde(rename = "latestVersion")]
+    pub latest_version: Option<String>,
+    #[serde(rename = "latestImageSha")]
+    pub latest_image_sha: Option<String>,
+    #[serde(rename = "checkedAt")]
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
 /// Version checker that periodically checks for updates via GitHub
 #[derive(Clone)]
 pub struct VersionChecker {
@@ -26,6 +53,8 @@ pub struct VersionChecker {
     latest_version: Arc<RwLock<Option<String>>>,
     /// SHA of latest GHCR image (None until first check)
     latest_image_sha: Arc<RwLock<Option<String>>>,
+    /// When the last check finished (None until first check)
+    checked_at: Arc<RwLock<Option<DateTime<Utc>>>>,
 }
 
 impl VersionChecker {
@@ -36,6 +65,7 @@ impl VersionChecker {
             current_sha: env!("GIT_COMMIT_SHA").to_string(),
             latest_version: Arc::new(RwLock::new(None)),
             latest_image_sha: Arc::new(RwLock::new(None)),
+            checked_at: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -59,6 +89,78 @@ impl VersionChecker {
         self.latest_image_sha.read().await.clone()
     }
 
+    /// Get when the last check finished
+    pub async fn checked_at(&self) -> Option<DateTime<Utc>> {
+        *self.checked_at.read().await
+    }
+
+    /// Get the results of the last check
+    pub async fn result(&self) -> VersionCheckResult {
+        VersionCheckResult {
+            latest_version: self.latest_version().await,
+            latest_image_sha: self.latest_image_sha().await,
+            checked_at: self.checked_at().await,
+        }
+    }
+
+    /// Load the results of the last check from settings
+    pub async fn load_cached(&self, pool: &SqlitePool, cache: &SettingsCache) -> Result<()> {
+        let Some(value) = Setting::get(pool, cache, VERSION_CHECK_SETTING).await? else {
+            return Ok(());
+        };
+        let result: VersionCheckResult = match serde_json::from_value(value) {
+            Ok(result) => result,
+            Err(e) => {
+                debug!("Ignoring invalid {} setting: {}", VERSION_CHECK_SETTING, e);
+                return Ok(());
+            }
+        };
+
+        *self.latest_version.write().await = result.latest_version;
+        *self.latest_image_sha.write().await = result.latest_image_sha;
+        *self.checked_at.write().await = result.checked_at;
+
+        Ok(())
+    }
+
+    /// Store the results of the last check in settings
+    async fn save_cached(&self, pool: &SqlitePool, cache: &SettingsCache) -> Result<()> {
+        let value = serde_json::to_value(self.result().await)?;
+        Setting::set(pool, cache, VERSION_CHECK_SETTING, &value, None).await
+    }
+
+    /// Check for updates if the last check is older than the check interval
+    ///
+    /// Returns Ok(true) if a check was performed
+    pub async fn check_if_due(&self, pool: &SqlitePool, cache: &SettingsCache) -> Result<bool> {
+        if let Some(checked_at) = self.checked_at().await {
+            if Utc::now() - checked_at < CHECK_INTERVAL {
+                return Ok(false);
+            }
+        }
+        self.check_now(pool, cache).await
+    }
+
+    /// Check for updates on an admin's request
+    ///
+    /// Fails if the last check was less than the cooldown ago; clients get the
+    /// stored results in the meantime.
+    pub async fn check_now_rate_limited(
+        &self,
+        pool: &SqlitePool,
+        cache: &SettingsCache,
+    ) -> Result<bool> {
+        if let Some(remaining) =
+            cooldown_remaining(self.checked_at().await, Utc::now(), MANUAL_CHECK_COOLDOWN)
+        {
+            return Err(anyhow!(
+                "Checked for updates recently, try again in {} seconds",
+                remaining.num_seconds().max(1)
+            ));
+        }
+        self.check_now(pool, cache).await
+    }
+
     /// Check for updates now
     ///
     /// Returns Ok(true) if a check was performed, Ok(false) if disabled
@@ -90,6 +192,11 @@ impl VersionChecker {
             info!("GHCR image check failed: {}", e);
         }
 
+        // Failed checks count too, so an unreachable endpoint isn't retried
+        // by every restart and every admin click
+        *self.checked_at.write().await = Some(Utc::now());
+        self.save_cached(pool, cache).await?;
+
         Ok(true)
     }
 
@@ -205,7 +312,9 @@ impl VersionChecker {
 
     /// Start periodic version checking (every 48 hours)
     ///
-    /// Returns a task handle that can be aborted to stop checking
+    /// The stored results are loaded first, so a restart only checks if the
+    /// last check is due. Returns a task handle that can be aborted to stop
+    /// checking
     pub fn start_interval(
         &self,
         pool: SqlitePool,
@@ -214,20 +323,18 @@ impl VersionChecker {
         let checker = self.clone();
 
         tokio::spawn(async move {
-            // Check immediately on startup
-            if let Err(e) = checker.check_now(&pool, &cache).await {
-                info!("Failed to check for updates: {}", e);
+            if let Err(e) = checker.load_cached(&pool, &cache).await {
+                info!("Failed to load cached version check: {}", e);
             }
 
-            // Then check every 48 hours
+            // The first tick completes immediately
             let mut interval =
-                tokio::time::interval(tokio::time::Duration::from_secs(48 * 60 * 60));
-            interval.tick().await; // First tick completes immediately
+                tokio::time::interval(tokio::time::Duration::from_secs(DUE_POLL_SECS));
 
             loop {
                 interval.tick().await;
 
-                if let Err(e) = checker.check_now(&pool, &cache).await {
+                if let Err(e) = checker.check_if_due(&pool, &cache).await {
                     info!("Failed to check for updates: {}", e);
                 }
             }
@@ -235,6 +342,16 @@ impl VersionChecker {
     }
 }
 
+/// Time left until another check is allowed, None if one is allowed now
+fn cooldown_remaining(
+    checked_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    cooldown: chrono::Duration,
+) -> Option<chrono::Duration> {
+    let remaining = checked_at? + cooldown - now;
+    (remaining > chrono::Duration::zero()).then_some(remaining)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,4 +380,46 @@ mod tests {
         let checker = VersionChecker::new("1.5.0".to_string());
         assert_eq!(checker.latest_image_sha().await, None);
     }
+
+    #[test]
+    fn test_cooldown_remaining() {
+        let now = Utc::now();
+        let cooldown = chrono::Duration::minutes(10);
+
+        assert_eq!(cooldown_remaining(None, now, cooldown), None);
+        assert_eq!(
+            cooldown_remaining(Some(now - chrono::Duration::minutes(4)), now, cooldown),
+            Some(chrono::Duration::minutes(6))
+        );
+        assert_eq!(
+            cooldown_remaining(Some(now - chrono::Duration::minutes(10)), now, cooldown),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_result_round_trip() {
+        use crate::db::Database;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).await.unwrap();
+        db.migrate().await.unwrap();
+        let cache = SettingsCache::default();
+
+        let checker = VersionChecker::new("1.5.0".to_string());
+        *checker.latest_version.write().await = Some("1.6.0".to_string());
+        *checker.checked_at.write().await = Some(Utc::now());
+        checker.save_cached(db.pool(), &cache).await.unwrap();
+
+        // A fresh instance picks the results up and doesn't check again
+        let restarted = VersionChecker::new("1.5.0".to_string());
+        restarted.load_cached(db.pool(), &cache).await.unwrap();
+        assert_eq!(restarted.result().await, checker.result().await);
+        assert!(!restarted.check_if_due(db.pool(), &cache).await.unwrap());
+        assert!(restarted
+            .check_now_rate_limited(db.pool(), &cache)
+            .await
+            .is_err());
+    }
 }
@@ -0,0 +1,276 @@
+// Clustering (optional, for running more than one controller instance)
+//
+// Enabled by giving each instance a unique --cluster-node-id and pointing them all
+// at the same database directory (--cluster-db-dir). Agents, users and sessions
+// (the JWT secret), settings and stack metadata (history, schedules, webhooks) are
+// then read from the shared database. All nodes are expected to manage the same
+// Docker host and stacks directory.
+//
+// Nodes coordinate through two tables in that database:
+// - cluster_node: a heartbeat per node. The live node with the lowest id is the
+//   leader and is the only one that runs scheduled stack actions. A node id that
+//   is still held by a live instance can't be reused, so a misconfigured second
+//   copy refuses to start instead of running schedules twice.
+// - cluster_event: a small append-only log polled every second. Nodes publish
+//   stack/agent/settings changes so the others refresh their own clients; an agent
+//   removed on one node is disconnected on every node.
+
+use crate::db::models::{ClusterEventRecord, ClusterNode};
+use crate::server::ServerContext;
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// How often each node records a heartbeat
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Nodes without a heartbeat for this long are considered gone
+const NODE_TIMEOUT_SECS: i64 = 20;
+
+/// How often nodes poll for events from the others
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events older than this are pruned by the leader
+const EVENT_RETENTION_SECS: i64 = 10 * 60;
+
+/// A change other nodes should react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterEvent {
+    /// Stacks were created, changed or removed
+    StackList,
+    /// Agents were added or removed
+    AgentList,
+    /// Settings were saved
+    Settings,
+}
+
+impl ClusterEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusterEvent::StackList => "stackList",
+            ClusterEvent::AgentList => "agentList",
+            ClusterEvent::Settings => "settings",
+        }
+    }
+}
+
+impl FromStr for ClusterEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stackList" => Ok(ClusterEvent::StackList),
+            "agentList" => Ok(ClusterEvent::AgentList),
+            "settings" => Ok(ClusterEvent::Settings),
+            _ => Err(anyhow!("Unknown cluster event \"{}\"", s)),
+        }
+    }
+}
+
+/// This node's membership in the cluster
+pub struct Cluster {
+    node_id: String,
+    /// Random per process, tells a restarted node apart from a duplicate
+    instance_id: String,
+    leader: AtomicBool,
+    /// Set once the first heartbeat decided whether this node leads
+    elected: tokio::sync::watch::Sender<bool>,
+}
+
+impl Cluster {
+    pub fn new(node_id: String) -> Self {
+        Self {
+            node_id,
+            instance_id: hex::encode(rand::thread_rng().gen::<[u8; 8]>()),
+            leader: AtomicBool::new(false),
+            elected: tokio::sync::watch::channel(false).0,
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+
+    /// Record a heartbeat and refresh the leader flag
+    async fn heartbeat(&self, ctx: &ServerContext) -> Result<()> {
+        if !ClusterNode::heartbeat(&ctx.db, &self.node_id, &self.instance_id, NODE_TIMEOUT_SECS)
+            .await?
+        {
+            self.leader.store(false, Ordering::Relaxed);
+            return Err(anyhow!(
+                "Cluster node id \"{}\" is in use by another running instance",
+                self.node_id
+            ));
+        }
+
+        let alive = ClusterNode::find_alive(&ctx.db, NODE_TIMEOUT_SECS).await?;
+        let leader = alive
+            .first()
+            .map(|n| n.node_id == self.node_id)
+            .unwrap_or(false);
+        if leader != self.is_leader() {
+            info!(
+                "Cluster node {} is {} the leader ({} node(s) alive)",
+                self.node_id,
+                if leader { "now" } else { "no longer" },
+                alive.len()
+            );
+        }
+        self.leader.store(leader, Ordering::Relaxed);
+        self.elected.send_replace(true);
+
+        Ok(())
+    }
+}
+
+/// Whether this node should run cluster-wide background work
+///
+/// Always true when clustering is disabled.
+pub fn is_leader(ctx: &ServerContext) -> bool {
+    ctx.cluster.as_ref().map_or(true, |c| c.is_leader())
+}
+
+/// Wait for the first heartbeat, then tell whether this node is the leader
+///
+/// For work at startup, which `is_leader` would refuse on every node before
+/// the leader is known. Returns right away when clustering is disabled.
+pub async fn wait_for_leader(ctx: &ServerContext) -> bool {
+    let Some(cluster) = &ctx.cluster else {
+        return true;
+    };
+    let mut elected = cluster.elected.subscribe();
+    // The sender lives as long as the cluster, so this only ends once elected
+    elected.wait_for(|elected| *elected).await.ok();
+    cluster.is_leader()
+}
+
+/// Tell the other nodes about a change. No-op when clustering is disabled.
+pub async fn publish(ctx: &ServerContext, event: ClusterEvent) {
+    let Some(cluster) = &ctx.cluster else {
+        return;
+    };
+
+    if let Err(e) = ClusterEventRecord::publish(&ctx.db, &cluster.node_id, event.as_str()).await {
+        warn!("Failed to publish cluster event {}: {}", event.as_str(), e);
+    }
+}
+
+/// Join the cluster, failing if this node id is already held by a live instance
+pub async fn join(ctx: &ServerContext) -> Result<()> {
+    let Some(cluster) = &ctx.cluster else {
+        return Ok(());
+    };
+
+    cluster.heartbeat(ctx).await?;
+    info!("Joined cluster as node {}", cluster.node_id);
+    Ok(())
+}
+
+/// Leave the cluster on shutdown so another node can take over leadership right away
+pub async fn leave(ctx: &ServerContext) {
+    let Some(cluster) = &ctx.cluster else {
+        return;
+    };
+
+    if let Err(e) = ClusterNode::delete(&ctx.db, &cluster.node_id, &cluster.instance_id).await {
+        warn!("Failed to leave cluster: {}", e);
+    }
+}
+
+/// Start the heartbeat and event polling loops. No-op when clustering is disabled.
+pub fn start(ctx: Arc<ServerContext>) {
+    let Some(cluster) = ctx.cluster.clone() else {
+        return;
+    };
+
+    // Heartbeat + leader election + event pruning
+    let ctx_clone = ctx.clone();
+    let cluster_clone = cluster.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = cluster_clone.heartbeat(&ctx_clone).await {
+                error!("Cluster heartbeat failed: {}", e);
+                continue;
+            }
+
+            if cluster_clone.is_leader() {
+                match ClusterEventRecord::prune(&ctx_clone.db, EVENT_RETENTION_SECS).await {
+                    Ok(0) => {}
+                    Ok(n) => debug!("Pruned {} cluster event(s)", n),
+                    Err(e) => warn!("Failed to prune cluster events: {}", e),
+                }
+            }
+        }
+    });
+
+    // Event polling
+    tokio::spawn(async move {
+        let mut last_id = ClusterEventRecord::latest_id(&ctx.db).await.unwrap_or(0);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let events =
+                match ClusterEventRecord::find_after(&ctx.db, last_id, &cluster.node_id).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        warn!("Failed to poll cluster events: {}", e);
+                        continue;
+                    }
+                };
+
+            // Several events of the same kind collapse into one refresh
+            let mut pending = Vec::new();
+            for record in events {
+                last_id = last_id.max(record.id);
+                match ClusterEvent::from_str(&record.event) {
+                    Ok(event) if !pending.contains(&event) => pending.push(event),
+                    Ok(_) => {}
+                    Err(e) => debug!("Ignoring event from node {}: {}", record.node_id, e),
+                }
+            }
+
+            for event in pending {
+                apply(&ctx, event).await;
+            }
+        }
+    });
+
+    info!("Cluster sync started");
+}
+
+/// React to a change made on another node
+async fn apply(ctx: &ServerContext, event: ClusterEvent) {
+    debug!("Applying cluster event {}", event.as_str());
+
+    match event {
+        ClusterEvent::StackList => ctx.broadcast_notify.notify_one(),
+        ClusterEvent::AgentList => crate::agent_manager::sync_all_with_db().await,
+        ClusterEvent::Settings => ctx.cache.clear().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_event_round_trip() {
+        for event in [
+            ClusterEvent::StackList,
+            ClusterEvent::AgentList,
+            ClusterEvent::Settings,
+        ] {
+            assert_eq!(ClusterEvent::from_str(event.as_str()).unwrap(), event);
+        }
+        assert!(ClusterEvent::from_str("reboot").is_err());
+    }
+}
@@ -1,5 +1,7 @@
+use crate::utils::constants::{DEFAULT_TERMINAL_LANG, DEFAULT_TERMINAL_TERM};
 use anyhow::Result;
 use clap::Parser;
+use ipnet::IpNet;
 use std::path::PathBuf;
 
 /// Dockru - A fancy, easy-to-use and reactive self-hosted docker compose.yaml stack manager
@@ -28,6 +30,54 @@ pub struct Config {
     /// Enable interactive console
     #[arg(long, env = "DOCKRU_ENABLE_CONSOLE", default_value = "false")]
     pub enable_console: bool,
+
+    /// Unique id of this instance, enables cluster mode (shared database between instances)
+    #[arg(long, env = "DOCKRU_CLUSTER_NODE_ID")]
+    pub cluster_node_id: Option<String>,
+
+    /// Directory of the database shared by all cluster nodes (defaults to the data directory)
+    #[arg(long, env = "DOCKRU_CLUSTER_DB_DIR")]
+    pub cluster_db_dir: Option<PathBuf>,
+
+    /// Output kept per terminal for clients that join later, in KB
+    #[arg(long, env = "DOCKRU_TERMINAL_BUFFER_KB", default_value = "256")]
+    pub terminal_buffer_kb: usize,
+
+    /// TERM of terminals and container shells; COLORTERM=truecolor is set unless it is "dumb"
+    #[arg(long, env = "DOCKRU_TERMINAL_TERM", default_value = DEFAULT_TERMINAL_TERM)]
+    pub terminal_term: String,
+
+    /// LANG of terminals and container shells, empty to leave the locale alone
+    #[arg(long, env = "DOCKRU_TERMINAL_LANG", default_value = DEFAULT_TERMINAL_LANG)]
+    pub terminal_lang: String,
+
+    /// Header a trusted reverse proxy puts the logged in username in (e.g. Remote-User),
+    /// enables header authentication
+    #[arg(long, env = "DOCKRU_AUTH_HEADER")]
+    pub auth_header: Option<String>,
+
+    /// Comma separated addresses or CIDR ranges of the reverse proxies trusted to set the
+    /// auth header
+    #[arg(long, env = "DOCKRU_TRUSTED_PROXIES", value_delimiter = ',')]
+    pub trusted_proxies: Vec<IpNet>,
+
+    /// Announce this instance on the LAN over mDNS and discover other instances to add as agents
+    #[arg(long, env = "DOCKRU_MDNS", default_value = "false")]
+    pub mdns: bool,
+
+    /// Docker daemon to manage instead of the local one: unix://, tcp://, https:// or
+    /// ssh://user@host[:port]
+    #[arg(long, env = "DOCKER_HOST")]
+    pub docker_host: Option<String>,
+
+    /// Use TLS for a tcp:// docker host
+    #[arg(long, env = "DOCKER_TLS_VERIFY", default_value = "false")]
+    #[arg(value_parser = clap::builder::BoolishValueParser::new())]
+    pub docker_tls_verify: bool,
+
+    /// Directory holding ca.pem, cert.pem and key.pem for TLS (defaults to ~/.docker)
+    #[arg(long, env = "DOCKER_CERT_PATH")]
+    pub docker_cert_path: Option<PathBuf>,
 }
 
 impl Config {
@@ -36,6 +86,14 @@ impl Config {
         Ok(config)
     }
 
+    /// Directory holding dockru.db
+    pub fn db_dir(&self) -> &PathBuf {
+        match (&self.cluster_node_id, &self.cluster_db_dir) {
+            (Some(_), Some(dir)) => dir,
+            _ => &self.data_dir,
+        }
+    }
+
     /// Get the bind address as a string
     pub fn bind_address(&self) -> String {
         if let Some(ref hostname) = self.hostname {
@@ -56,4 +114,38 @@ mod tests {
         let config = Config::parse();
         assert!(config.is_ok());
     }
+
+    #[test]
+    fn test_docker_host_args() {
+        let config = <Config as Parser>::try_parse_from([
+            "dockru",
+            "--docker-host",
+            "tcp://10.0.0.2:2376",
+            "--docker-tls-verify",
+            "--docker-cert-path",
+            "/certs",
+        ])
+        .unwrap();
+        assert_eq!(config.docker_host.as_deref(), Some("tcp://10.0.0.2:2376"));
+        assert!(config.docker_tls_verify);
+        assert_eq!(config.docker_cert_path, Some(PathBuf::from("/certs")));
+    }
+
+    #[test]
+    fn test_terminal_env_args() {
+        let config = <Config as Parser>::try_parse_from(["dockru"]).unwrap();
+        assert_eq!(config.terminal_term, "xterm-256color");
+        assert_eq!(config.terminal_lang, "C.UTF-8");
+
+        let config = <Config as Parser>::try_parse_from([
+            "dockru",
+            "--terminal-term",
+            "xterm",
+            "--terminal-lang",
+            "",
+        ])
+        .unwrap();
+        assert_eq!(config.terminal_term, "xterm");
+        assert!(config.terminal_lang.is_empty());
+    }
 }
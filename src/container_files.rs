@@ -0,0 +1,238 @@
+// Container file copy
+//
+// The `docker cp` equivalent for single files, e.g. to grab a log file out of
+// a container or drop a config file into one. Docker's archive endpoints
+// transfer tars; downloads unpack the one file from the tar, uploads pack the
+// file into one.
+//
+// Only regular files are copied. An uploaded file replaces an existing file
+// at the path and keeps its mode and owner; a new file is created with mode
+// 0644, owned by root. The parent directory has to exist.
+//
+// Downloads are streamed. Uploads are at most MAX_CONTAINER_FILE_BYTES and
+// held in memory.
+
+use crate::docker::DockerHandle;
+use anyhow::{anyhow, bail, Result};
+use axum::body::Bytes;
+use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
+use chrono::Utc;
+use futures_util::StreamExt;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+use tokio::io::DuplexStream;
+use tokio::sync::oneshot;
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+/// Buffer between the blocking tar reader and the download
+const STREAM_BUFFER_BYTES: usize = 64 * 1024;
+
+/// A file being downloaded from a container
+pub struct ContainerFile {
+    pub name: String,
+    pub size: u64,
+    pub content: DuplexStream,
+}
+
+/// Check a path in a container, returning its file name
+pub fn file_name(path: &str) -> Result<&str> {
+    let parsed = Path::new(path);
+    if !parsed.is_absolute() || path.contains('\0') {
+        bail!("The path must be absolute");
+    }
+    match parsed.components().next_back() {
+        Some(Component::Normal(name)) if !path.ends_with('/') && !path.ends_with("/.") => name
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid path {}", path)),
+        _ => bail!("The path must name a file"),
+    }
+}
+
+/// Start downloading a regular file from a container
+pub async fn download_file(
+    docker: &DockerHandle,
+    container: &str,
+    path: &str,
+) -> Result<ContainerFile> {
+    file_name(path)?;
+    let tar = docker
+        .client()
+        .download_from_container(
+            container,
+            Some(DownloadFromContainerOptions {
+                path: path.to_string(),
+            }),
+        )
+        .map(|chunk| chunk.map_err(io::Error::other));
+    let reader = SyncIoBridge::new(StreamReader::new(tar));
+    let (writer, content) = tokio::io::duplex(STREAM_BUFFER_BYTES);
+    let writer = SyncIoBridge::new(writer);
+
+    let (found, file) = oneshot::channel();
+    tokio::task::spawn_blocking(move || {
+        let mut archive = tar::Archive::new(reader);
+        let entry = archive
+            .entries()
+            .and_then(|mut entries| entries.next().transpose())
+            .map_err(|e| anyhow!("{}", e))
+            .and_then(|entry| entry.ok_or_else(|| anyhow!("The file is empty")))
+            .and_then(|entry| {
+                let header = entry.header();
+                if !header.entry_type().is_file() {
+                    bail!("Only regular files can be downloaded");
+                }
+                let path = entry.path()?;
+                let name = path
+                    .file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned();
+                Ok((name, header.size()?, entry))
+            });
+        match entry {
+            Ok((name, size, entry)) => {
+                if found.send(Ok((name, size))).is_ok() {
+                    // An error here ends the body early; the client sees a truncated file
+                    copy(entry, writer).ok();
+                }
+            }
+            Err(e) => {
+                found.send(Err(e)).ok();
+            }
+        }
+    });
+
+    let (name, size) = file
+        .await
+        .map_err(|_| anyhow!("Failed to read {} from the container", path))?
+        .map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    Ok(ContainerFile {
+        name,
+        size,
+        content,
+    })
+}
+
+/// Write a regular file into a container
+pub async fn upload_file(
+    docker: &DockerHandle,
+    container: &str,
+    path: &str,
+    content: Bytes,
+) -> Result<()> {
+    let name = file_name(path)?;
+    let parent = Path::new(path)
+        .parent()
+        .and_then(Path::to_str)
+        .unwrap_or("/")
+        .to_string();
+    let existing = existing_header(docker, container, path).await;
+    let tar = file_tar(name, &content, existing.as_ref())?;
+
+    let options = UploadToContainerOptions {
+        path: parent,
+        no_overwrite_dir_non_dir: "true".to_string(),
+    };
+    docker
+        .client()
+        .upload_to_container(container, Some(options), tar.into())
+        .await
+        .map_err(|e| anyhow!("Failed to write {}: {}", path, e))
+}
+
+/// The tar header of the regular file at `path`, if there is one
+async fn existing_header(
+    docker: &DockerHandle,
+    container: &str,
+    path: &str,
+) -> Option<tar::Header> {
+    let tar = docker
+        .client()
+        .download_from_container(
+            container,
+            Some(DownloadFromContainerOptions {
+                path: path.to_string(),
+            }),
+        )
+        .map(|chunk| chunk.map_err(io::Error::other));
+    let reader = SyncIoBridge::new(StreamReader::new(tar));
+    tokio::task::spawn_blocking(move || {
+        let mut archive = tar::Archive::new(reader);
+        let entry = archive.entries().ok()?.next()?.ok()?;
+        entry
+            .header()
+            .entry_type()
+            .is_file()
+            .then(|| entry.header().clone())
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// A tar holding one file, with the mode and owner of `existing` if given
+fn file_tar(name: &str, content: &[u8], existing: Option<&tar::Header>) -> Result<Vec<u8>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(content.len() as u64);
+    header.set_mtime(Utc::now().timestamp().max(0) as u64);
+    match existing {
+        Some(existing) => {
+            header.set_mode(existing.mode()?);
+            header.set_uid(existing.uid()?);
+            header.set_gid(existing.gid()?);
+        }
+        None => {
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+        }
+    }
+
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.append_data(&mut header, name, content)?;
+    Ok(builder.into_inner()?)
+}
+
+fn copy(mut from: impl Read, mut to: impl Write) -> io::Result<()> {
+    io::copy(&mut from, &mut to)?;
+    to.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("/var/log/app.log").unwrap(), "app.log");
+        assert_eq!(file_name("/etc/../etc/app.conf").unwrap(), "app.conf");
+        assert!(file_name("app.log").is_err());
+        assert!(file_name("/").is_err());
+        assert!(file_name("/var/log/").is_err());
+        assert!(file_name("/var/log/..").is_err());
+        assert!(file_name("/var/log/.").is_err());
+    }
+
+    #[test]
+    fn test_file_tar() {
+        let tar = file_tar("app.conf", b"port = 80\n", None).unwrap();
+        let mut archive = tar::Archive::new(tar.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("app.conf"));
+        assert_eq!(entry.header().mode().unwrap(), 0o644);
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "port = 80\n");
+
+        let mut existing = tar::Header::new_gnu();
+        existing.set_mode(0o600);
+        existing.set_uid(1000);
+        existing.set_gid(1000);
+        let tar = file_tar("app.conf", b"", Some(&existing)).unwrap();
+        let mut archive = tar::Archive::new(tar.as_slice());
+        let entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(entry.header().mode().unwrap(), 0o600);
+        assert_eq!(entry.header().uid().unwrap(), 1000);
+    }
+}
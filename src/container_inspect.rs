@@ -0,0 +1,498 @@
+// Container inspection
+//
+// The parts of `docker inspect` that come up when debugging a service without
+// the CLI: state, restart policy, mounts, environment, networks, published
+// ports and labels, for every container of the service.
+//
+// The environment ends up in the browser, so by default the values of
+// variables whose names look like credentials (PASSWORD, TOKEN, API_KEY, ...)
+// are replaced with REDACTED_VALUE. Callers can ask for them as they are.
+//
+// The processes running in a service's containers come from `docker top`,
+// as the table `ps -ef` prints.
+
+use crate::docker::{BollardResultExt, DockerHandle};
+use anyhow::{bail, Result};
+use bollard::container::{InspectContainerOptions, ListContainersOptions, TopOptions};
+use bollard::models::{ContainerInspectResponse, ContainerSummary, ContainerTopResponse};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Shown instead of the value of a redacted variable
+pub const REDACTED_VALUE: &str = "[redacted]";
+
+/// Parts of variable names (split on `_`, `-` and `.`) that mark their value as a credential
+const SENSITIVE_NAME_PARTS: &[&str] = &[
+    "PASSWORD",
+    "PASSWD",
+    "PASS",
+    "PWD",
+    "SECRET",
+    "TOKEN",
+    "KEY",
+    "APIKEY",
+    "CREDENTIAL",
+    "CREDENTIALS",
+    "AUTH",
+    "PRIVATE",
+    "SALT",
+    "DSN",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerState {
+    pub status: Option<String>,
+    pub running: bool,
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i64>,
+    pub error: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: Option<String>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<String>,
+    pub health: Option<String>,
+    #[serde(rename = "oomKilled")]
+    pub oom_killed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RestartPolicy {
+    pub name: Option<String>,
+    #[serde(rename = "maximumRetryCount")]
+    pub maximum_retry_count: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerMount {
+    #[serde(rename = "type")]
+    pub mount_type: Option<String>,
+    /// Volume name, for volumes
+    pub name: Option<String>,
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    pub mode: Option<String>,
+    pub rw: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    /// The value was replaced with REDACTED_VALUE
+    pub redacted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerNetwork {
+    pub name: String,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    pub gateway: Option<String>,
+    #[serde(rename = "macAddress")]
+    pub mac_address: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerPort {
+    /// e.g. 80/tcp
+    #[serde(rename = "containerPort")]
+    pub container_port: String,
+    #[serde(rename = "hostIp")]
+    pub host_ip: Option<String>,
+    #[serde(rename = "hostPort")]
+    pub host_port: Option<String>,
+}
+
+/// What inspectContainer reports about a container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerDetails {
+    pub id: String,
+    pub name: String,
+    pub image: Option<String>,
+    pub created: Option<String>,
+    #[serde(rename = "restartCount")]
+    pub restart_count: i64,
+    pub state: Option<ContainerState>,
+    #[serde(rename = "restartPolicy")]
+    pub restart_policy: Option<RestartPolicy>,
+    pub mounts: Vec<ContainerMount>,
+    pub env: Vec<EnvVar>,
+    pub networks: Vec<ContainerNetwork>,
+    pub ports: Vec<ContainerPort>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Whether a variable's value is likely a credential
+pub fn is_sensitive_name(name: &str) -> bool {
+    name.to_ascii_uppercase()
+        .split(['_', '-', '.'])
+        .any(|part| SENSITIVE_NAME_PARTS.contains(&part))
+}
+
+/// Split `NAME=value` entries, redacting credentials if asked to
+fn env_vars(env: Vec<String>, redact: bool) -> Vec<EnvVar> {
+    env.into_iter()
+        .map(|entry| {
+            let (name, value) = entry.split_once('=').unwrap_or((&entry, ""));
+            let redacted = redact && is_sensitive_name(name) && !value.is_empty();
+            EnvVar {
+                name: name.to_string(),
+                value: if redacted {
+                    REDACTED_VALUE.to_string()
+                } else {
+                    value.to_string()
+                },
+                redacted,
+            }
+        })
+        .collect()
+}
+
+impl ContainerDetails {
+    pub fn from_inspect(inspect: ContainerInspectResponse, redact_env: bool) -> Self {
+        let config = inspect.config.unwrap_or_default();
+        let network_settings = inspect.network_settings.unwrap_or_default();
+
+        let mut networks: Vec<ContainerNetwork> = network_settings
+            .networks
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, endpoint)| ContainerNetwork {
+                name,
+                ip_address: endpoint.ip_address.filter(|ip| !ip.is_empty()),
+                gateway: endpoint.gateway.filter(|gw| !gw.is_empty()),
+                mac_address: endpoint.mac_address.filter(|mac| !mac.is_empty()),
+                aliases: endpoint.aliases.unwrap_or_default(),
+            })
+            .collect();
+        networks.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut ports = Vec::new();
+        for (container_port, bindings) in network_settings.ports.unwrap_or_default() {
+            match bindings {
+                Some(bindings) if !bindings.is_empty() => {
+                    ports.extend(bindings.into_iter().map(|binding| ContainerPort {
+                        container_port: container_port.clone(),
+                        host_ip: binding.host_ip,
+                        host_port: binding.host_port,
+                    }))
+                }
+                // Exposed, not published
+                _ => ports.push(ContainerPort {
+                    container_port,
+                    host_ip: None,
+                    host_port: None,
+                }),
+            }
+        }
+        ports.sort_by(|a, b| (&a.container_port, &a.host_ip).cmp(&(&b.container_port, &b.host_ip)));
+
+        let state = inspect.state.map(|state| ContainerState {
+            status: state.status.map(|s| s.to_string()),
+            running: state.running.unwrap_or(false),
+            exit_code: state.exit_code,
+            error: state.error.filter(|e| !e.is_empty()),
+            started_at: state.started_at,
+            finished_at: state.finished_at,
+            health: state
+                .health
+                .and_then(|h| h.status)
+                .map(|s| s.to_string())
+                .filter(|s| !s.is_empty()),
+            oom_killed: state.oom_killed.unwrap_or(false),
+        });
+
+        let restart_policy = inspect
+            .host_config
+            .and_then(|host_config| host_config.restart_policy)
+            .map(|policy| RestartPolicy {
+                name: policy.name.map(|n| n.to_string()).filter(|n| !n.is_empty()),
+                maximum_retry_count: policy.maximum_retry_count,
+            });
+
+        let mounts = inspect
+            .mounts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mount| ContainerMount {
+                mount_type: mount.typ.map(|t| t.to_string()),
+                name: mount.name,
+                source: mount.source,
+                destination: mount.destination,
+                mode: mount.mode.filter(|m| !m.is_empty()),
+                rw: mount.rw.unwrap_or(false),
+            })
+            .collect();
+
+        ContainerDetails {
+            id: inspect.id.unwrap_or_default(),
+            name: inspect
+                .name
+                .unwrap_or_default()
+                .trim_start_matches('/')
+                .to_string(),
+            image: config.image,
+            created: inspect.created,
+            restart_count: inspect.restart_count.unwrap_or(0),
+            state,
+            restart_policy,
+            mounts,
+            env: env_vars(config.env.unwrap_or_default(), redact_env),
+            networks,
+            ports,
+            labels: config.labels.unwrap_or_default().into_iter().collect(),
+        }
+    }
+}
+
+/// The processes of a running container, as serviceProcessList reports them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContainerProcesses {
+    pub id: String,
+    pub name: String,
+    /// Column names, e.g. UID, PID, PPID, C, STIME, TTY, TIME, CMD
+    pub titles: Vec<String>,
+    /// One row per process, in the order of the titles
+    pub processes: Vec<Vec<String>>,
+}
+
+impl ContainerProcesses {
+    fn from_top(id: String, name: String, top: ContainerTopResponse) -> Self {
+        Self {
+            id,
+            name,
+            titles: top.titles.unwrap_or_default(),
+            processes: top.processes.unwrap_or_default(),
+        }
+    }
+}
+
+/// The containers of a compose service, including stopped ones if `all`
+pub(crate) async fn service_containers(
+    docker: &DockerHandle,
+    project_name: &str,
+    service_name: &str,
+    all: bool,
+) -> Result<Vec<ContainerSummary>> {
+    let filters = HashMap::from([(
+        "label".to_string(),
+        vec![
+            format!("com.docker.compose.project={}", project_name),
+            format!("com.docker.compose.service={}", service_name),
+        ],
+    )]);
+    let options = ListContainersOptions {
+        all,
+        filters,
+        ..Default::default()
+    };
+    docker
+        .run(|d| {
+            let options = options.clone();
+            async move { d.list_containers(Some(options)).await }
+        })
+        .await
+        .docker_context(&format!("Failed to list containers of {}", service_name))
+}
+
+/// Inspect the containers of a compose service, sorted by name
+pub async fn inspect_service(
+    docker: &DockerHandle,
+    project_name: &str,
+    service_name: &str,
+    redact_env: bool,
+) -> Result<Vec<ContainerDetails>> {
+    let containers = service_containers(docker, project_name, service_name, true).await?;
+    if containers.is_empty() {
+        bail!("Service {} has no containers", service_name);
+    }
+
+    let mut details = Vec::new();
+    for id in containers.into_iter().filter_map(|c| c.id) {
+        let inspect = docker
+            .run(|d| {
+                let id = id.clone();
+                async move {
+                    d.inspect_container(&id, None::<InspectContainerOptions>)
+                        .await
+                }
+            })
+            .await
+            .docker_context(&format!("Failed to inspect container {}", id))?;
+        details.push(ContainerDetails::from_inspect(inspect, redact_env));
+    }
+    details.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(details)
+}
+
+/// The processes of the running containers of a compose service, sorted by
+/// container name
+pub async fn service_processes(
+    docker: &DockerHandle,
+    project_name: &str,
+    service_name: &str,
+) -> Result<Vec<ContainerProcesses>> {
+    let containers = service_containers(docker, project_name, service_name, false).await?;
+    if containers.is_empty() {
+        bail!("Service {} has no running containers", service_name);
+    }
+
+    let mut result = Vec::new();
+    for container in containers {
+        let Some(id) = container.id else {
+            continue;
+        };
+        let name = container
+            .names
+            .and_then(|names| names.into_iter().next())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| id.clone());
+        let top = docker
+            .run(|d| {
+                let id = id.clone();
+                async move { d.top_processes(&id, None::<TopOptions<String>>).await }
+            })
+            .await
+            .docker_context(&format!("Failed to list the processes of {}", name))?;
+        result.push(ContainerProcesses::from_top(id, name, top));
+    }
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{
+        ContainerConfig, EndpointSettings, HostConfig, MountPoint, MountPointTypeEnum,
+        NetworkSettings, PortBinding, RestartPolicy as DockerRestartPolicy, RestartPolicyNameEnum,
+    };
+
+    #[test]
+    fn test_is_sensitive_name() {
+        assert!(is_sensitive_name("POSTGRES_PASSWORD"));
+        assert!(is_sensitive_name("api_key"));
+        assert!(is_sensitive_name("GITHUB_TOKEN"));
+        assert!(is_sensitive_name("SENTRY_DSN"));
+        assert!(!is_sensitive_name("KEYCLOAK_URL"));
+        assert!(!is_sensitive_name("PATH"));
+        assert!(!is_sensitive_name("TZ"));
+    }
+
+    #[test]
+    fn test_from_inspect() {
+        let inspect = ContainerInspectResponse {
+            id: Some("abc123".to_string()),
+            name: Some("/web-app-1".to_string()),
+            restart_count: Some(2),
+            config: Some(ContainerConfig {
+                image: Some("nginx:1.27".to_string()),
+                env: Some(vec![
+                    "DB_PASSWORD=hunter2".to_string(),
+                    "EMPTY_TOKEN=".to_string(),
+                    "TZ=Europe/Berlin".to_string(),
+                    "OPTS=a=b".to_string(),
+                ]),
+                labels: Some(HashMap::from([(
+                    "com.docker.compose.service".to_string(),
+                    "app".to_string(),
+                )])),
+                ..Default::default()
+            }),
+            host_config: Some(HostConfig {
+                restart_policy: Some(DockerRestartPolicy {
+                    name: Some(RestartPolicyNameEnum::UNLESS_STOPPED),
+                    maximum_retry_count: Some(0),
+                }),
+                ..Default::default()
+            }),
+            mounts: Some(vec![MountPoint {
+                typ: Some(MountPointTypeEnum::VOLUME),
+                name: Some("web_data".to_string()),
+                destination: Some("/data".to_string()),
+                rw: Some(true),
+                ..Default::default()
+            }]),
+            network_settings: Some(NetworkSettings {
+                networks: Some(HashMap::from([(
+                    "web_default".to_string(),
+                    EndpointSettings {
+                        ip_address: Some("172.20.0.2".to_string()),
+                        aliases: Some(vec!["app".to_string()]),
+                        ..Default::default()
+                    },
+                )])),
+                ports: Some(HashMap::from([
+                    (
+                        "80/tcp".to_string(),
+                        Some(vec![PortBinding {
+                            host_ip: Some("0.0.0.0".to_string()),
+                            host_port: Some("8080".to_string()),
+                        }]),
+                    ),
+                    ("9000/tcp".to_string(), None),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let details = ContainerDetails::from_inspect(inspect.clone(), true);
+        assert_eq!(details.name, "web-app-1");
+        assert_eq!(details.image.as_deref(), Some("nginx:1.27"));
+        assert_eq!(details.restart_count, 2);
+        assert_eq!(
+            details.restart_policy.unwrap().name.as_deref(),
+            Some("unless-stopped")
+        );
+        assert_eq!(details.mounts[0].mount_type.as_deref(), Some("volume"));
+        assert_eq!(
+            details.networks[0].ip_address.as_deref(),
+            Some("172.20.0.2")
+        );
+        assert_eq!(details.ports.len(), 2);
+        assert_eq!(details.ports[0].host_port.as_deref(), Some("8080"));
+        assert_eq!(details.ports[1].host_port, None);
+        assert_eq!(details.labels["com.docker.compose.service"], "app");
+
+        let env: Vec<(&str, &str, bool)> = details
+            .env
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.as_str(), v.redacted))
+            .collect();
+        assert_eq!(
+            env,
+            vec![
+                ("DB_PASSWORD", REDACTED_VALUE, true),
+                ("EMPTY_TOKEN", "", false),
+                ("TZ", "Europe/Berlin", false),
+                ("OPTS", "a=b", false),
+            ]
+        );
+
+        let unredacted = ContainerDetails::from_inspect(inspect, false);
+        assert_eq!(unredacted.env[0].value, "hunter2");
+        assert!(!unredacted.env[0].redacted);
+    }
+
+    #[test]
+    fn test_from_top() {
+        let top = ContainerTopResponse {
+            titles: Some(vec!["PID".to_string(), "CMD".to_string()]),
+            processes: Some(vec![vec!["4242".to_string(), "nginx: master".to_string()]]),
+        };
+        let processes =
+            ContainerProcesses::from_top("abc123".to_string(), "web-app-1".to_string(), top);
+        assert_eq!(processes.titles, vec!["PID", "CMD"]);
+        assert_eq!(processes.processes[0][1], "nginx: master");
+
+        let empty = ContainerProcesses::from_top(
+            "abc123".to_string(),
+            "web-app-1".to_string(),
+            ContainerTopResponse::default(),
+        );
+        assert!(empty.titles.is_empty());
+        assert!(empty.processes.is_empty());
+    }
+}
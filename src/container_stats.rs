@@ -0,0 +1,367 @@
+// Container stats streaming
+//
+// Clients subscribe to the stats of a stack, or of one of its services, with
+// streamContainerStats. Each subscribed stack/service has one task reading
+// Docker's stats stream of its running containers; Docker samples every
+// second, the task keeps the latest sample per container and every
+// CONTAINER_STATS_EMIT_SECS sends them to the subscribed sockets:
+//
+//   containerStats { ok, stackName, serviceName, containers: [
+//       { name, service, cpuPercent, memoryUsage, memoryLimit, memoryPercent,
+//         networkRx, networkTx } ] }
+//
+// The running containers are listed again every CONTAINER_STATS_RETRY_SECS so
+// containers that start or stop later join or leave the stream. When the last
+// subscriber unsubscribes or disconnects the task stops.
+
+use crate::docker::{list_containers_by_project, DockerHandle};
+use crate::socket_handlers::emit_agent;
+use crate::utils::constants::{CONTAINER_STATS_EMIT_SECS, CONTAINER_STATS_RETRY_SECS};
+use anyhow::Result;
+use bollard::container::{CPUStats, MemoryStats, MemoryStatsStats, Stats, StatsOptions};
+use dockru_protocol::events::server as server_event;
+use futures_util::stream::{select_all, StreamExt};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::json;
+use socketioxide::extract::SocketRef;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::debug;
+
+/// Label compose puts on every container of a service
+const SERVICE_LABEL: &str = "com.docker.compose.service";
+
+static STREAMS: Lazy<Mutex<HashMap<StreamKey, StatsStream>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// What a stream covers: a whole stack or one of its services
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamKey {
+    pub stack: String,
+    pub service: Option<String>,
+}
+
+struct StatsStream {
+    /// Subscribed sockets by id
+    sockets: HashMap<String, SocketRef>,
+    task: JoinHandle<()>,
+}
+
+/// One container's latest sample
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerStats {
+    pub name: String,
+    pub service: String,
+    pub cpu_percent: f64,
+    /// Bytes, without the page cache
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub memory_percent: f64,
+    /// Bytes received and sent since the container started
+    pub network_rx: u64,
+    pub network_tx: u64,
+}
+
+/// A running container of a stream
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Target {
+    name: String,
+    service: String,
+}
+
+/// Subscribe a socket to a stack's or service's stats, starting the stream if needed
+pub fn subscribe(docker: &DockerHandle, socket: &SocketRef, key: StreamKey) {
+    let mut streams = STREAMS.lock().unwrap();
+    let stream = streams.entry(key.clone()).or_insert_with(|| {
+        debug!("Starting stats stream of {:?}", key);
+        let docker = docker.clone();
+        let key = key.clone();
+        StatsStream {
+            sockets: HashMap::new(),
+            task: tokio::spawn(run(docker, key)),
+        }
+    });
+    stream.sockets.insert(socket.id.to_string(), socket.clone());
+}
+
+/// Unsubscribe a socket from one stream, stopping it if nobody is left
+pub fn unsubscribe(socket_id: &str, key: &StreamKey) {
+    let mut streams = STREAMS.lock().unwrap();
+    if let Some(stream) = streams.get_mut(key) {
+        stream.sockets.remove(socket_id);
+        if stream.sockets.is_empty() {
+            debug!("Stopping stats stream of {:?}", key);
+            stream.task.abort();
+            streams.remove(key);
+        }
+    }
+}
+
+/// Unsubscribe a socket from all its streams
+pub fn unsubscribe_all(socket_id: &str) {
+    let mut streams = STREAMS.lock().unwrap();
+    streams.retain(|key, stream| {
+        stream.sockets.remove(socket_id);
+        if stream.sockets.is_empty() {
+            debug!("Stopping stats stream of {:?}", key);
+            stream.task.abort();
+            return false;
+        }
+        true
+    });
+}
+
+async fn run(docker: DockerHandle, key: StreamKey) {
+    loop {
+        match follow(&docker, &key).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                debug!("Stats stream of {:?} failed: {:#}", key, e);
+                if !publish(&key, Vec::new()) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_secs(CONTAINER_STATS_RETRY_SECS)).await;
+            }
+        }
+    }
+}
+
+/// Stream the stats of the running containers until they change
+///
+/// Returns false once no subscribers are left.
+async fn follow(docker: &DockerHandle, key: &StreamKey) -> Result<bool> {
+    let targets = running_targets(docker, key).await?;
+    if targets.is_empty() {
+        if !publish(key, Vec::new()) {
+            return Ok(false);
+        }
+        tokio::time::sleep(Duration::from_secs(CONTAINER_STATS_RETRY_SECS)).await;
+        return Ok(true);
+    }
+
+    let client = docker.client();
+    let options = StatsOptions {
+        stream: true,
+        one_shot: false,
+    };
+    let mut stats = select_all(targets.iter().map(|target| {
+        let target = target.clone();
+        client
+            .stats(&target.name, Some(options))
+            .map(move |stats| (target.clone(), stats))
+            .boxed()
+    }));
+
+    // Both start a period from now, so the first emit has samples to send
+    let emit_every = Duration::from_secs(CONTAINER_STATS_EMIT_SECS);
+    let mut emit = tokio::time::interval_at(Instant::now() + emit_every, emit_every);
+    emit.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let rescan_every = Duration::from_secs(CONTAINER_STATS_RETRY_SECS);
+    let mut rescan = tokio::time::interval_at(Instant::now() + rescan_every, rescan_every);
+    rescan.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let mut latest: BTreeMap<Target, ContainerStats> = BTreeMap::new();
+    let mut ended = false;
+    loop {
+        tokio::select! {
+            item = stats.next(), if !ended => match item {
+                Some((target, Ok(stats))) => {
+                    let sample = sample(&target, &stats);
+                    latest.insert(target, sample);
+                }
+                Some((target, Err(e))) => {
+                    debug!("Stats of {} failed: {}", target.name, e);
+                    latest.remove(&target);
+                }
+                None => {
+                    ended = true;
+                    latest.clear();
+                }
+            },
+            _ = emit.tick() => {
+                if !publish(key, latest.values().cloned().collect()) {
+                    return Ok(false);
+                }
+            }
+            _ = rescan.tick() => {
+                if running_targets(docker, key).await? != targets {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+/// The running containers a stream covers, sorted by name
+async fn running_targets(docker: &DockerHandle, key: &StreamKey) -> Result<Vec<Target>> {
+    let containers = list_containers_by_project(docker, &key.stack).await?;
+    let mut targets: Vec<Target> = containers
+        .into_iter()
+        .filter(|c| c.state.as_deref() == Some("running"))
+        .filter_map(|c| {
+            let service = c.labels.as_ref()?.get(SERVICE_LABEL)?.clone();
+            if key.service.as_ref().is_some_and(|s| *s != service) {
+                return None;
+            }
+            let name = c.names?.first()?.trim_start_matches('/').to_string();
+            Some(Target { name, service })
+        })
+        .collect();
+    targets.sort();
+    Ok(targets)
+}
+
+/// Send samples to a stream's subscribers
+///
+/// Forgets sockets that disconnected; returns false and drops the stream when
+/// none are left.
+fn publish(key: &StreamKey, containers: Vec<ContainerStats>) -> bool {
+    let sockets: Vec<SocketRef> = {
+        let mut streams = STREAMS.lock().unwrap();
+        let Some(stream) = streams.get_mut(key) else {
+            return false;
+        };
+        stream.sockets.retain(|_, socket| socket.connected());
+        if stream.sockets.is_empty() {
+            debug!("Stats stream of {:?} has no subscribers left", key);
+            streams.remove(key);
+            return false;
+        }
+        stream.sockets.values().cloned().collect()
+    };
+
+    let data = json!({
+        "ok": true,
+        "stackName": key.stack,
+        "serviceName": key.service,
+        "containers": containers,
+    });
+    for socket in sockets {
+        emit_agent(&socket, server_event::CONTAINER_STATS, data.clone()).ok();
+    }
+    true
+}
+
+fn sample(target: &Target, stats: &Stats) -> ContainerStats {
+    let memory_usage = memory_usage(&stats.memory_stats);
+    let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+    let (network_rx, network_tx) = stats
+        .networks
+        .iter()
+        .flat_map(|networks| networks.values())
+        .fold((0, 0), |(rx, tx), n| (rx + n.rx_bytes, tx + n.tx_bytes));
+    ContainerStats {
+        name: target.name.clone(),
+        service: target.service.clone(),
+        cpu_percent: cpu_percent(&stats.cpu_stats, &stats.precpu_stats),
+        memory_usage,
+        memory_limit,
+        memory_percent: percent(memory_usage as f64, memory_limit as f64),
+        network_rx,
+        network_tx,
+    }
+}
+
+/// CPU use since the previous sample, 100% per core like `docker stats`
+fn cpu_percent(cpu: &CPUStats, precpu: &CPUStats) -> f64 {
+    let cpu_delta = cpu
+        .cpu_usage
+        .total_usage
+        .saturating_sub(precpu.cpu_usage.total_usage);
+    let system_delta = cpu
+        .system_cpu_usage
+        .unwrap_or(0)
+        .saturating_sub(precpu.system_cpu_usage.unwrap_or(0));
+    let cpus = cpu
+        .online_cpus
+        .filter(|&n| n > 0)
+        .or_else(|| cpu.cpu_usage.percpu_usage.as_ref().map(|p| p.len() as u64))
+        .unwrap_or(1);
+    percent(cpu_delta as f64, system_delta as f64) * cpus as f64
+}
+
+/// Memory in use without the inactive page cache, as `docker stats` shows it
+fn memory_usage(memory: &MemoryStats) -> u64 {
+    let usage = memory.usage.unwrap_or(0);
+    let cache = match memory.stats {
+        Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+        Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+        None => 0,
+    };
+    usage.saturating_sub(cache)
+}
+
+fn percent(part: f64, whole: f64) -> f64 {
+    if whole > 0.0 {
+        part / whole * 100.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::container::{CPUUsage, ThrottlingData};
+
+    fn cpu(total: u64, system: u64, online: Option<u64>) -> CPUStats {
+        CPUStats {
+            cpu_usage: CPUUsage {
+                percpu_usage: Some(vec![0; 4]),
+                usage_in_usermode: 0,
+                total_usage: total,
+                usage_in_kernelmode: 0,
+            },
+            system_cpu_usage: Some(system),
+            online_cpus: online,
+            throttling_data: ThrottlingData {
+                periods: 0,
+                throttled_periods: 0,
+                throttled_time: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        let pre = cpu(1_000, 10_000, Some(2));
+        assert_eq!(cpu_percent(&cpu(1_500, 11_000, Some(2)), &pre), 100.0);
+        assert_eq!(cpu_percent(&cpu(1_250, 11_000, Some(2)), &pre), 50.0);
+        // Falls back to the per-CPU list without online_cpus
+        assert_eq!(cpu_percent(&cpu(1_250, 11_000, None), &pre), 100.0);
+        // First sample of a stream has no previous reading
+        assert_eq!(cpu_percent(&cpu(1_250, 10_000, Some(2)), &pre), 0.0);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let memory = MemoryStats {
+            stats: None,
+            max_usage: None,
+            usage: Some(300),
+            failcnt: None,
+            limit: Some(1_000),
+            commit: None,
+            commit_peak: None,
+            commitbytes: None,
+            commitpeakbytes: None,
+            privateworkingset: None,
+        };
+        assert_eq!(memory_usage(&memory), 300);
+        assert_eq!(
+            memory_usage(&MemoryStats {
+                usage: None,
+                ..memory
+            }),
+            0
+        );
+        assert_eq!(percent(300.0, 1_000.0), 30.0);
+        assert_eq!(percent(300.0, 0.0), 0.0);
+    }
+}
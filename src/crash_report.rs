@@ -0,0 +1,129 @@
+// Crash reports
+//
+// A panic hook appends every panic, with where it happened and a backtrace, to
+// `crash-report.log` in the data directory before the default hook prints it.
+// The file is rotated to `crash-report.log.1` once it grows past
+// CRASH_REPORT_MAX_BYTES, so a panic loop can't fill the disk.
+//
+// Socket handlers run their work through `spawn_handler`, which answers the
+// client's ack with an error when the work panics; this module only records.
+
+use chrono::{Local, SecondsFormat};
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::io::Write;
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::utils::constants::{CRASH_REPORT_FILE_NAME, CRASH_REPORT_MAX_BYTES};
+
+static REPORT_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Install the panic hook, writing reports to the data directory
+pub fn install(data_dir: &Path) {
+    if REPORT_PATH
+        .set(data_dir.join(CRASH_REPORT_FILE_NAME))
+        .is_err()
+    {
+        return;
+    }
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some(path) = REPORT_PATH.get() {
+            write_report(path, info.payload(), info.location());
+        }
+        default_hook(info);
+    }));
+}
+
+/// The message a panic was raised with
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn write_report(path: &Path, payload: &(dyn Any + Send), location: Option<&Location<'_>>) {
+    let thread = std::thread::current();
+    let report = format_report(
+        &Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        thread.name().unwrap_or("unnamed"),
+        &panic_message(payload),
+        &location
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| "unknown location".to_string()),
+        &Backtrace::force_capture().to_string(),
+    );
+
+    // Nothing sensible to do if this fails while panicking; the default hook
+    // still prints the panic
+    let _ = rotate_if_full(path).and_then(|_| {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(report.as_bytes())
+    });
+}
+
+fn rotate_if_full(path: &Path) -> std::io::Result<()> {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.len() >= CRASH_REPORT_MAX_BYTES => {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(".1");
+            std::fs::rename(path, rotated)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn format_report(
+    timestamp: &str,
+    thread: &str,
+    message: &str,
+    location: &str,
+    backtrace: &str,
+) -> String {
+    format!(
+        "=== {} panic in thread '{}' at {}\n{}\n\nBacktrace:\n{}\n\n",
+        timestamp, thread, location, message, backtrace
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "boom");
+
+        let payload = std::panic::catch_unwind(|| panic!("code {}", 7)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "code 7");
+
+        let payload = std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown panic");
+    }
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report(
+            "2026-10-16T03:00:00Z",
+            "tokio-runtime-worker",
+            "boom",
+            "src/stack.rs:10:5",
+            "0: dockru::main",
+        );
+        assert!(report.starts_with(
+            "=== 2026-10-16T03:00:00Z panic in thread 'tokio-runtime-worker' at src/stack.rs:10:5\nboom\n"
+        ));
+        assert!(report.contains("Backtrace:\n0: dockru::main"));
+    }
+}
@@ -1,7 +1,8 @@
 pub mod models;
+pub mod status_writer;
 
 use anyhow::{Context, Result};
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
 use sqlx::ConnectOptions;
 use std::path::Path;
 use std::str::FromStr;
@@ -21,13 +22,25 @@ impl Database {
     /// - Incremental auto-vacuum
     /// - Normal synchronous mode (balance safety and performance)
     pub async fn new(data_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::open(data_dir, SqliteJournalMode::Wal).await
+    }
+
+    /// Initialize a connection to a database shared by several cluster nodes
+    ///
+    /// Uses the rollback journal instead of WAL, since WAL relies on shared memory
+    /// that isn't available when the file is on network storage.
+    pub async fn new_shared(db_dir: impl AsRef<Path>) -> Result<Self> {
+        Self::open(db_dir, SqliteJournalMode::Delete).await
+    }
+
+    async fn open(data_dir: impl AsRef<Path>, journal_mode: SqliteJournalMode) -> Result<Self> {
         let db_path = data_dir.as_ref().join("dockru.db");
         info!("Connecting to database at: {}", db_path.display());
 
         // Build connection options
         let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", db_path.display()))?
             .create_if_missing(true)
-            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+            .journal_mode(journal_mode)
             .synchronous(sqlx::sqlite::SqliteSynchronous::Normal)
             .busy_timeout(std::time::Duration::from_secs(120))
             .disable_statement_logging();
@@ -52,6 +65,27 @@ impl Database {
         Ok(db)
     }
 
+    /// Open a private in-memory database, for tests
+    #[cfg(test)]
+    pub async fn new_in_memory() -> Result<Self> {
+        let options =
+            SqliteConnectOptions::from_str("sqlite::memory:")?.disable_statement_logging();
+
+        // The database only lives as long as its connection, so keep exactly one open
+        let pool = SqlitePoolOptions::new()
+            .min_connections(1)
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .context("Failed to open in-memory database")?;
+
+        let db = Database { pool };
+        db.init_sqlite().await?;
+        Ok(db)
+    }
+
     /// Get a reference to the connection pool
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
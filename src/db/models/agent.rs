@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use redact::Secret;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use url::Url;
 
 use crate::utils::crypto::{decrypt_password, encrypt_password, is_password_encrypted};
@@ -15,6 +15,7 @@ struct AgentRow {
     pub username: String,
     pub password: String, // Encrypted in DB
     pub active: bool,
+    pub token: Option<String>, // Encrypted in DB
 }
 
 /// Agent model representing a remote Dockru instance (application type with decrypted password)
@@ -27,6 +28,9 @@ pub struct Agent {
     pub password: Secret<String>, // Plaintext in memory
     pub active: bool,
     pub endpoint: String,
+    /// Agent token issued by the remote instance, used instead of username/password
+    #[serde(skip_serializing)]
+    pub token: Option<Secret<String>>,
 }
 
 /// Data for creating a new agent
@@ -36,6 +40,36 @@ pub struct NewAgent {
     pub username: String,
     pub password: Secret<String>,
     pub active: bool,
+    pub token: Option<Secret<String>>,
+}
+
+/// How to log in to a remote instance
+#[derive(Debug, Clone)]
+pub enum AgentCredentials {
+    Password {
+        username: String,
+        password: Secret<String>,
+    },
+    /// An agent token created on the remote instance
+    Token(Secret<String>),
+}
+
+impl AgentCredentials {
+    /// The login event to emit to the remote instance and its payload
+    pub fn login_event(&self) -> (&'static str, serde_json::Value) {
+        match self {
+            AgentCredentials::Password { username, password } => (
+                "login",
+                serde_json::json!({
+                    "username": username,
+                    "password": password.expose_secret(),
+                }),
+            ),
+            AgentCredentials::Token(token) => {
+                ("loginByAgentToken", serde_json::json!(token.expose_secret()))
+            }
+        }
+    }
 }
 
 impl AgentRow {
@@ -49,6 +83,12 @@ impl AgentRow {
             Secret::new(self.password)
         };
 
+        let token = self
+            .token
+            .map(|token| decrypt_password(&token, encryption_secret))
+            .transpose()
+            .context("Failed to decrypt agent token")?;
+
         let endpoint = parse_endpoint(&self.url)?;
 
         Ok(Agent {
@@ -58,6 +98,7 @@ impl AgentRow {
             password: password_str,
             active: self.active,
             endpoint,
+            token,
         })
     }
 }
@@ -123,6 +164,19 @@ impl Agent {
             .collect()
     }
 
+    /// Get the endpoint of every agent, without decrypting credentials
+    pub async fn endpoints(pool: &SqlitePool) -> Result<HashSet<String>> {
+        let urls: Vec<String> = sqlx::query_scalar("SELECT url FROM agent")
+            .fetch_all(pool)
+            .await
+            .context("Failed to query agent URLs")?;
+
+        Ok(urls
+            .iter()
+            .filter_map(|url| parse_endpoint(url).ok())
+            .collect())
+    }
+
     /// Get all agents as a map keyed by endpoint
     #[allow(dead_code)]
     pub async fn get_agent_list(
@@ -152,16 +206,24 @@ impl Agent {
         // Encrypt the password before storing
         let encrypted_password = encrypt_password(&new_agent.password, encryption_secret)
             .context("Failed to encrypt agent password")?;
-
-        let result =
-            sqlx::query("INSERT INTO agent (url, username, password, active) VALUES (?, ?, ?, ?)")
-                .bind(&new_agent.url)
-                .bind(&new_agent.username)
-                .bind(&encrypted_password)
-                .bind(new_agent.active)
-                .execute(pool)
-                .await
-                .context("Failed to insert agent")?;
+        let encrypted_token = new_agent
+            .token
+            .as_ref()
+            .map(|token| encrypt_password(token, encryption_secret))
+            .transpose()
+            .context("Failed to encrypt agent token")?;
+
+        let result = sqlx::query(
+            "INSERT INTO agent (url, username, password, active, token) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&new_agent.url)
+        .bind(&new_agent.username)
+        .bind(&encrypted_password)
+        .bind(new_agent.active)
+        .bind(&encrypted_token)
+        .execute(pool)
+        .await
+        .context("Failed to insert agent")?;
 
         let agent_id = result.last_insert_rowid();
 
@@ -278,12 +340,24 @@ impl Agent {
         Ok(migrated)
     }
 
+    /// The credentials to log in to this agent with, preferring the agent token
+    pub fn credentials(&self) -> AgentCredentials {
+        match &self.token {
+            Some(token) => AgentCredentials::Token(token.clone()),
+            None => AgentCredentials::Password {
+                username: self.username.clone(),
+                password: self.password.clone(),
+            },
+        }
+    }
+
     /// Convert agent to JSON representation for client
     pub fn to_json(&self) -> Result<serde_json::Value> {
         Ok(serde_json::json!({
             "url": self.url,
             "username": self.username,
             "endpoint": self.endpoint,
+            "authType": if self.token.is_some() { "token" } else { "password" },
         }))
     }
 }
@@ -291,23 +365,11 @@ impl Agent {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::Database;
-    use tempfile::TempDir;
-
-    fn test_secret() -> Secret<String> {
-        Secret::new("test_encryption_secret_for_agents".to_string())
-    }
-
-    async fn setup_test_db() -> (Database, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let db = Database::new(temp_dir.path()).await.unwrap();
-        db.migrate().await.unwrap();
-        (db, temp_dir)
-    }
+    use crate::test_support::{self, test_db, test_encryption_secret};
 
     #[tokio::test]
     async fn test_create_and_find_agent() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let new_agent = NewAgent {
@@ -315,9 +377,10 @@ mod tests {
             username: "admin".to_string(),
             password: Secret::new("secret".to_string()),
             active: true,
+            token: None,
         };
 
-        let agent = Agent::create(pool, new_agent, &test_secret())
+        let agent = Agent::create(pool, new_agent, &test_encryption_secret())
             .await
             .unwrap();
 
@@ -328,7 +391,7 @@ mod tests {
         assert!(agent.active);
 
         // Find by ID
-        let found = Agent::find_by_id(pool, agent.id, &test_secret())
+        let found = Agent::find_by_id(pool, agent.id, &test_encryption_secret())
             .await
             .unwrap()
             .unwrap();
@@ -336,7 +399,7 @@ mod tests {
         assert_eq!(found.password.expose_secret(), "secret");
 
         // Find by URL
-        let found = Agent::find_by_url(pool, "https://example.com:5001", &test_secret())
+        let found = Agent::find_by_url(pool, "https://example.com:5001", &test_encryption_secret())
             .await
             .unwrap()
             .unwrap();
@@ -346,7 +409,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_password_stored_encrypted() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let new_agent = NewAgent {
@@ -354,9 +417,10 @@ mod tests {
             username: "admin".to_string(),
             password: Secret::new("my_secret_pass".to_string()),
             active: true,
+            token: None,
         };
 
-        let agent = Agent::create(pool, new_agent, &test_secret())
+        let agent = Agent::create(pool, new_agent, &test_encryption_secret())
             .await
             .unwrap();
 
@@ -378,91 +442,82 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_endpoint_parsing() {
-        let (db, _temp) = setup_test_db().await;
+    async fn test_token_agent() {
+        let db = test_db().await;
         let pool = db.pool();
 
-        // With port
-        let agent1 = Agent::create(
+        let agent = Agent::create(
             pool,
             NewAgent {
                 url: "https://example.com:5001".to_string(),
-                username: "admin".to_string(),
-                password: Secret::new("pass".to_string()),
+                username: String::new(),
+                password: Secret::new(String::new()),
                 active: true,
+                token: Some(Secret::new("agent_token".to_string())),
             },
-            &test_secret(),
+            &test_encryption_secret(),
         )
         .await
         .unwrap();
 
+        let row: (String,) = sqlx::query_as("SELECT token FROM agent WHERE id = ?")
+            .bind(agent.id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert!(is_password_encrypted(&row.0));
+
+        let (event, payload) = agent.credentials().login_event();
+        assert_eq!(event, "loginByAgentToken");
+        assert_eq!(payload, serde_json::json!("agent_token"));
+        assert_eq!(agent.to_json().unwrap()["authType"], "token");
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_parsing() {
+        let db = test_db().await;
+        // With port
+        let agent1 = test_support::agent("https://example.com:5001")
+            .create(&db)
+            .await;
+
         assert_eq!(agent1.endpoint, "example.com:5001");
 
         // Without explicit port (HTTPS default)
-        let agent2 = Agent::create(
-            pool,
-            NewAgent {
-                url: "https://example.com".to_string(),
-                username: "admin".to_string(),
-                password: Secret::new("pass".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        let agent2 = test_support::agent("https://example.com").create(&db).await;
 
         assert_eq!(agent2.endpoint, "example.com");
 
         // HTTP with port
-        let agent3 = Agent::create(
-            pool,
-            NewAgent {
-                url: "http://192.168.1.100:8080".to_string(),
-                username: "admin".to_string(),
-                password: Secret::new("pass".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        let agent3 = test_support::agent("http://192.168.1.100:8080")
+            .create(&db)
+            .await;
 
         assert_eq!(agent3.endpoint, "192.168.1.100:8080");
+
+        let endpoints = Agent::endpoints(db.pool()).await.unwrap();
+        assert_eq!(endpoints.len(), 3);
+        assert!(endpoints.contains("192.168.1.100:8080"));
     }
 
     #[tokio::test]
     async fn test_get_agent_list() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
-        Agent::create(
-            pool,
-            NewAgent {
-                url: "https://agent1.com:5001".to_string(),
-                username: "user1".to_string(),
-                password: Secret::new("pass1".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        test_support::agent("https://agent1.com:5001")
+            .credentials("user1", "pass1")
+            .create(&db)
+            .await;
 
-        Agent::create(
-            pool,
-            NewAgent {
-                url: "https://agent2.com:5002".to_string(),
-                username: "user2".to_string(),
-                password: Secret::new("pass2".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        test_support::agent("https://agent2.com:5002")
+            .credentials("user2", "pass2")
+            .create(&db)
+            .await;
 
-        let agent_list = Agent::get_agent_list(pool, &test_secret()).await.unwrap();
+        let agent_list = Agent::get_agent_list(pool, &test_encryption_secret())
+            .await
+            .unwrap();
 
         assert_eq!(agent_list.len(), 2);
         assert!(agent_list.contains_key("agent1.com:5001"));
@@ -471,21 +526,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_update_agent() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
-        let mut agent = Agent::create(
-            pool,
-            NewAgent {
-                url: "https://old.com:5001".to_string(),
-                username: "olduser".to_string(),
-                password: Secret::new("oldpass".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        let mut agent = test_support::agent("https://old.com:5001")
+            .credentials("olduser", "oldpass")
+            .create(&db)
+            .await;
 
         // Update URL
         agent
@@ -496,7 +543,7 @@ mod tests {
 
         // Update credentials
         agent
-            .update_credentials(pool, "newuser", "newpass", &test_secret())
+            .update_credentials(pool, "newuser", "newpass", &test_encryption_secret())
             .await
             .unwrap();
         assert_eq!(agent.username, "newuser");
@@ -517,21 +564,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_to_json() {
-        let (db, _temp) = setup_test_db().await;
-        let pool = db.pool();
-
-        let agent = Agent::create(
-            pool,
-            NewAgent {
-                url: "https://example.com:5001".to_string(),
-                username: "admin".to_string(),
-                password: Secret::new("secret".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        let db = test_db().await;
+        let agent = test_support::agent("https://example.com:5001")
+            .credentials("admin", "secret")
+            .create(&db)
+            .await;
 
         let json = agent.to_json().unwrap();
 
@@ -544,7 +581,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_invalid_url() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let result = Agent::create(
@@ -554,8 +591,9 @@ mod tests {
                 username: "admin".to_string(),
                 password: Secret::new("pass".to_string()),
                 active: true,
+                token: None,
             },
-            &test_secret(),
+            &test_encryption_secret(),
         )
         .await;
 
@@ -564,27 +602,18 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_agent() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
-        let agent = Agent::create(
-            pool,
-            NewAgent {
-                url: "https://example.com:5001".to_string(),
-                username: "admin".to_string(),
-                password: Secret::new("pass".to_string()),
-                active: true,
-            },
-            &test_secret(),
-        )
-        .await
-        .unwrap();
+        let agent = test_support::agent("https://example.com:5001")
+            .create(&db)
+            .await;
 
         let agent_id = agent.id;
 
         Agent::delete(pool, agent_id).await.unwrap();
 
-        let found = Agent::find_by_id(pool, agent_id, &test_secret())
+        let found = Agent::find_by_id(pool, agent_id, &test_encryption_secret())
             .await
             .unwrap();
         assert!(found.is_none());
@@ -592,7 +621,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_migrate_plaintext_passwords() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         // Insert agents with plaintext passwords directly (simulating old behavior)
@@ -615,19 +644,21 @@ mod tests {
             .unwrap();
 
         // Run migration
-        let migrated = Agent::migrate_plaintext_passwords(pool, &test_secret())
+        let migrated = Agent::migrate_plaintext_passwords(pool, &test_encryption_secret())
             .await
             .unwrap();
         assert_eq!(migrated, 2);
 
         // Running again should migrate 0 (already encrypted)
-        let migrated = Agent::migrate_plaintext_passwords(pool, &test_secret())
+        let migrated = Agent::migrate_plaintext_passwords(pool, &test_encryption_secret())
             .await
             .unwrap();
         assert_eq!(migrated, 0);
 
         // Verify passwords are decrypted correctly
-        let agents = Agent::find_all(pool, &test_secret()).await.unwrap();
+        let agents = Agent::find_all(pool, &test_encryption_secret())
+            .await
+            .unwrap();
         assert_eq!(agents.len(), 2);
         assert_eq!(agents[0].password.expose_secret(), "plaintext_pass_1");
         assert_eq!(agents[1].password.expose_secret(), "plaintext_pass_2");
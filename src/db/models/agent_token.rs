@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::webhook::hash_token;
+use crate::utils::crypto::gen_secret;
+
+/// Length of generated agent tokens
+const TOKEN_LENGTH: usize = 48;
+
+/// Everything but the token hash, which never leaves the database
+const COLUMNS: &str = "id, user_id, name, created_at, last_used_at";
+
+/// A token another instance uses to connect to this one as an agent
+///
+/// Tokens log in as the user that created them, but can't be used to manage
+/// users or tokens. Only a hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AgentToken {
+    pub id: i64,
+    #[serde(rename = "userId")]
+    pub user_id: i64,
+    /// Label to tell tokens apart, usually the controller's name
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<String>,
+}
+
+impl AgentToken {
+    /// Get a user's tokens, oldest first
+    pub async fn find_by_user(pool: &SqlitePool, user_id: i64) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, AgentToken>(&format!(
+            "SELECT {} FROM agent_token WHERE user_id = ? ORDER BY id",
+            COLUMNS
+        ))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query agent tokens")
+    }
+
+    /// Issue a new token for a user
+    ///
+    /// Returns the token record and the plaintext token, which cannot be
+    /// recovered afterwards.
+    pub async fn create(pool: &SqlitePool, user_id: i64, name: &str) -> Result<(Self, String)> {
+        let token = gen_secret(TOKEN_LENGTH);
+
+        let result =
+            sqlx::query("INSERT INTO agent_token (user_id, name, token_hash) VALUES (?, ?, ?)")
+                .bind(user_id)
+                .bind(name)
+                .bind(hash_token(&token))
+                .execute(pool)
+                .await
+                .context("Failed to insert agent token")?;
+
+        let record = sqlx::query_as::<_, AgentToken>(&format!(
+            "SELECT {} FROM agent_token WHERE id = ?",
+            COLUMNS
+        ))
+        .bind(result.last_insert_rowid())
+        .fetch_one(pool)
+        .await
+        .context("Failed to find newly created agent token")?;
+
+        Ok((record, token))
+    }
+
+    /// Revoke one of a user's tokens. Returns false if there was no such token.
+    pub async fn delete(pool: &SqlitePool, id: i64, user_id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM agent_token WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .context("Failed to delete agent token")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Find the token a presented value belongs to, recording its use
+    pub async fn verify(pool: &SqlitePool, token: &str) -> Result<Option<Self>> {
+        let record = sqlx::query_as::<_, AgentToken>(&format!(
+            "SELECT {} FROM agent_token WHERE token_hash = ?",
+            COLUMNS
+        ))
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await
+        .context("Failed to query agent token")?;
+
+        if let Some(record) = &record {
+            sqlx::query("UPDATE agent_token SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(record.id)
+                .execute(pool)
+                .await
+                .context("Failed to update agent token")?;
+        }
+
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_create_verify_and_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let (record, token) = AgentToken::create(pool, 1, "controller").await.unwrap();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(record.name, "controller");
+        assert!(record.last_used_at.is_none());
+
+        let verified = AgentToken::verify(pool, &token).await.unwrap().unwrap();
+        assert_eq!(verified.id, record.id);
+        assert_eq!(verified.user_id, 1);
+        assert!(AgentToken::verify(pool, "wrong").await.unwrap().is_none());
+
+        let listed = AgentToken::find_by_user(pool, 1).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].last_used_at.is_some());
+        assert!(AgentToken::find_by_user(pool, 2).await.unwrap().is_empty());
+
+        // Only the owner can revoke a token
+        assert!(!AgentToken::delete(pool, record.id, 2).await.unwrap());
+        assert!(AgentToken::delete(pool, record.id, 1).await.unwrap());
+        assert!(AgentToken::verify(pool, &token).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// Number of audit entries kept, older ones are pruned
+pub const AUDIT_LOG_LIMIT: i64 = 10000;
+
+/// A security-relevant action, kept for compliance review
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// Username that did it
+    pub actor: String,
+    /// What was done (e.g. "terminalInput")
+    pub action: String,
+    /// What it was done to, e.g. the terminal name
+    pub target: String,
+    /// The recorded data, e.g. the line typed
+    pub detail: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// Data for recording a new audit entry
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub detail: String,
+}
+
+impl AuditEntry {
+    /// Record an entry and prune the log down to AUDIT_LOG_LIMIT
+    pub async fn create(pool: &SqlitePool, new_entry: NewAuditEntry) -> Result<()> {
+        sqlx::query("INSERT INTO audit_log (actor, action, target, detail) VALUES (?, ?, ?, ?)")
+            .bind(&new_entry.actor)
+            .bind(&new_entry.action)
+            .bind(&new_entry.target)
+            .bind(&new_entry.detail)
+            .execute(pool)
+            .await
+            .context("Failed to insert audit entry")?;
+
+        sqlx::query(
+            "DELETE FROM audit_log WHERE id <=
+             (SELECT id FROM audit_log ORDER BY id DESC LIMIT 1 OFFSET ?)",
+        )
+        .bind(AUDIT_LOG_LIMIT)
+        .execute(pool)
+        .await
+        .context("Failed to prune audit log")?;
+
+        Ok(())
+    }
+
+    /// Get a page of the log, newest first
+    pub async fn find_page(pool: &SqlitePool, limit: i64, offset: i64) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, AuditEntry>("SELECT * FROM audit_log ORDER BY id DESC LIMIT ? OFFSET ?")
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(pool)
+            .await
+            .context("Failed to query audit log")
+    }
+
+    /// Count the entries of the log
+    pub async fn count(pool: &SqlitePool) -> Result<i64> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM audit_log")
+            .fetch_one(pool)
+            .await
+            .context("Failed to count audit entries")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn entry(detail: &str) -> NewAuditEntry {
+        NewAuditEntry {
+            actor: "admin".to_string(),
+            action: "terminalInput".to_string(),
+            target: "container-exec-web-nginx-0".to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_page_entries() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        for detail in ["ls", "cat /etc/hosts", "exit"] {
+            AuditEntry::create(pool, entry(detail)).await.unwrap();
+        }
+
+        assert_eq!(AuditEntry::count(pool).await.unwrap(), 3);
+
+        // Newest first
+        let first = AuditEntry::find_page(pool, 2, 0).await.unwrap();
+        let details: Vec<&str> = first.iter().map(|e| e.detail.as_str()).collect();
+        assert_eq!(details, ["exit", "cat /etc/hosts"]);
+
+        let second = AuditEntry::find_page(pool, 2, 2).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].detail, "ls");
+        assert_eq!(second[0].actor, "admin");
+    }
+
+    #[tokio::test]
+    async fn test_entries_are_pruned() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        for _ in 0..AUDIT_LOG_LIMIT + 5 {
+            AuditEntry::create(pool, entry("ls")).await.unwrap();
+        }
+
+        assert_eq!(AuditEntry::count(pool).await.unwrap(), AUDIT_LOG_LIMIT);
+    }
+}
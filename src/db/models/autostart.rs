@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+
+/// Stacks flagged to be started when dockru boots
+pub struct StackAutostart;
+
+impl StackAutostart {
+    /// Whether a stack is flagged
+    pub async fn is_enabled(pool: &SqlitePool, stack_name: &str) -> Result<bool> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT stack_name FROM stack_autostart WHERE stack_name = ?")
+                .bind(stack_name)
+                .fetch_optional(pool)
+                .await
+                .context("Failed to query stack autostart")?;
+
+        Ok(row.is_some())
+    }
+
+    /// Names of every flagged stack
+    pub async fn find_all(pool: &SqlitePool) -> Result<HashSet<String>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT stack_name FROM stack_autostart")
+            .fetch_all(pool)
+            .await
+            .context("Failed to query stack autostart")?;
+
+        Ok(rows.into_iter().map(|(name,)| name).collect())
+    }
+
+    /// Flag or unflag a stack
+    pub async fn set(pool: &SqlitePool, stack_name: &str, enabled: bool) -> Result<()> {
+        if enabled {
+            sqlx::query("INSERT OR IGNORE INTO stack_autostart (stack_name) VALUES (?)")
+                .bind(stack_name)
+                .execute(pool)
+                .await
+                .context("Failed to save stack autostart")?;
+        } else {
+            Self::delete_by_stack(pool, stack_name).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Unflag a stack
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_autostart WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack autostart")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_set_and_find() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        StackAutostart::set(pool, "web", true).await.unwrap();
+        StackAutostart::set(pool, "db", true).await.unwrap();
+        // Flagging twice is fine
+        StackAutostart::set(pool, "web", true).await.unwrap();
+        assert!(StackAutostart::is_enabled(pool, "web").await.unwrap());
+        assert_eq!(StackAutostart::find_all(pool).await.unwrap().len(), 2);
+
+        StackAutostart::set(pool, "web", false).await.unwrap();
+        assert!(!StackAutostart::is_enabled(pool, "web").await.unwrap());
+        StackAutostart::delete_by_stack(pool, "db").await.unwrap();
+        assert!(StackAutostart::find_all(pool).await.unwrap().is_empty());
+    }
+}
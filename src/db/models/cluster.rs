@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// A controller instance sharing the database
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ClusterNode {
+    #[serde(rename = "nodeId")]
+    pub node_id: String,
+    #[serde(rename = "instanceId")]
+    pub instance_id: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "lastSeen")]
+    pub last_seen: String,
+}
+
+/// A change published by one node for the others
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ClusterEventRecord {
+    pub id: i64,
+    #[serde(rename = "nodeId")]
+    pub node_id: String,
+    pub event: String,
+}
+
+impl ClusterNode {
+    /// Record a heartbeat for this node
+    ///
+    /// Returns false if the node id is held by another instance that is still alive
+    /// (seen within `timeout_secs`), in which case nothing is written.
+    pub async fn heartbeat(
+        pool: &SqlitePool,
+        node_id: &str,
+        instance_id: &str,
+        timeout_secs: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO cluster_node (node_id, instance_id) VALUES (?, ?)
+             ON CONFLICT(node_id) DO UPDATE SET
+                 instance_id = excluded.instance_id,
+                 started_at = CASE WHEN cluster_node.instance_id = excluded.instance_id
+                                   THEN cluster_node.started_at ELSE CURRENT_TIMESTAMP END,
+                 last_seen = CURRENT_TIMESTAMP
+             WHERE cluster_node.instance_id = excluded.instance_id
+                OR cluster_node.last_seen < datetime('now', ?)",
+        )
+        .bind(node_id)
+        .bind(instance_id)
+        .bind(format!("-{} seconds", timeout_secs))
+        .execute(pool)
+        .await
+        .context("Failed to record cluster heartbeat")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Nodes seen within `timeout_secs`, ordered by node id
+    pub async fn find_alive(pool: &SqlitePool, timeout_secs: i64) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, ClusterNode>(
+            "SELECT * FROM cluster_node WHERE last_seen >= datetime('now', ?) ORDER BY node_id",
+        )
+        .bind(format!("-{} seconds", timeout_secs))
+        .fetch_all(pool)
+        .await
+        .context("Failed to query cluster nodes")
+    }
+
+    /// Remove a node's heartbeat (on shutdown)
+    pub async fn delete(pool: &SqlitePool, node_id: &str, instance_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM cluster_node WHERE node_id = ? AND instance_id = ?")
+            .bind(node_id)
+            .bind(instance_id)
+            .execute(pool)
+            .await
+            .context("Failed to delete cluster node")?;
+        Ok(())
+    }
+}
+
+impl ClusterEventRecord {
+    /// Append an event
+    pub async fn publish(pool: &SqlitePool, node_id: &str, event: &str) -> Result<()> {
+        sqlx::query("INSERT INTO cluster_event (node_id, event) VALUES (?, ?)")
+            .bind(node_id)
+            .bind(event)
+            .execute(pool)
+            .await
+            .context("Failed to publish cluster event")?;
+        Ok(())
+    }
+
+    /// Highest event id, 0 if there are none
+    pub async fn latest_id(pool: &SqlitePool) -> Result<i64> {
+        sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM cluster_event")
+            .fetch_one(pool)
+            .await
+            .context("Failed to query latest cluster event")
+    }
+
+    /// Events after `after_id` published by other nodes, oldest first
+    pub async fn find_after(pool: &SqlitePool, after_id: i64, node_id: &str) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, ClusterEventRecord>(
+            "SELECT id, node_id, event FROM cluster_event WHERE id > ? AND node_id != ? ORDER BY id",
+        )
+        .bind(after_id)
+        .bind(node_id)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query cluster events")
+    }
+
+    /// Delete events older than `max_age_secs`
+    pub async fn prune(pool: &SqlitePool, max_age_secs: i64) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM cluster_event WHERE created_at < datetime('now', ?)")
+            .bind(format!("-{} seconds", max_age_secs))
+            .execute(pool)
+            .await
+            .context("Failed to prune cluster events")?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_heartbeat_rejects_live_duplicate() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        assert!(ClusterNode::heartbeat(pool, "a", "inst-1", 20)
+            .await
+            .unwrap());
+        assert!(ClusterNode::heartbeat(pool, "a", "inst-1", 20)
+            .await
+            .unwrap());
+
+        // Same node id from another process while the first is alive
+        assert!(!ClusterNode::heartbeat(pool, "a", "inst-2", 20)
+            .await
+            .unwrap());
+
+        // After the first instance goes quiet, the id can be taken over
+        sqlx::query("UPDATE cluster_node SET last_seen = datetime('now', '-60 seconds')")
+            .execute(pool)
+            .await
+            .unwrap();
+        assert!(ClusterNode::heartbeat(pool, "a", "inst-2", 20)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_find_alive() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        ClusterNode::heartbeat(pool, "b", "inst-b", 20)
+            .await
+            .unwrap();
+        ClusterNode::heartbeat(pool, "a", "inst-a", 20)
+            .await
+            .unwrap();
+        ClusterNode::heartbeat(pool, "c", "inst-c", 20)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE cluster_node SET last_seen = datetime('now', '-60 seconds') WHERE node_id = 'a'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let alive: Vec<String> = ClusterNode::find_alive(pool, 20)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| n.node_id)
+            .collect();
+        assert_eq!(alive, vec!["b", "c"]);
+
+        ClusterNode::delete(pool, "b", "inst-b").await.unwrap();
+        assert_eq!(ClusterNode::find_alive(pool, 20).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        assert_eq!(ClusterEventRecord::latest_id(pool).await.unwrap(), 0);
+
+        ClusterEventRecord::publish(pool, "a", "stackList")
+            .await
+            .unwrap();
+        ClusterEventRecord::publish(pool, "b", "agentList")
+            .await
+            .unwrap();
+
+        // Own events are skipped
+        let events = ClusterEventRecord::find_after(pool, 0, "a").await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "agentList");
+
+        let latest = ClusterEventRecord::latest_id(pool).await.unwrap();
+        assert!(ClusterEventRecord::find_after(pool, latest, "c")
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(ClusterEventRecord::prune(pool, 60).await.unwrap(), 0);
+    }
+}
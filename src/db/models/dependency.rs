@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+/// Stacks that have to be up before another stack is deployed or started
+pub struct StackDependency;
+
+impl StackDependency {
+    /// Get the stacks a stack depends on, sorted by name
+    pub async fn find_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<Vec<String>> {
+        sqlx::query_scalar(
+            "SELECT depends_on FROM stack_dependency WHERE stack_name = ? ORDER BY depends_on",
+        )
+        .bind(stack_name)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack dependencies")
+    }
+
+    /// Get every stack's dependencies, keyed by stack name
+    pub async fn find_all(pool: &SqlitePool) -> Result<HashMap<String, Vec<String>>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT stack_name, depends_on FROM stack_dependency ORDER BY stack_name, depends_on",
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack dependencies")?;
+
+        let mut dependencies: HashMap<String, Vec<String>> = HashMap::new();
+        for (stack_name, depends_on) in rows {
+            dependencies.entry(stack_name).or_default().push(depends_on);
+        }
+        Ok(dependencies)
+    }
+
+    /// Replace a stack's dependencies
+    pub async fn set(pool: &SqlitePool, stack_name: &str, depends_on: &[String]) -> Result<()> {
+        let mut tx = pool.begin().await.context("Failed to start transaction")?;
+
+        sqlx::query("DELETE FROM stack_dependency WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to clear stack dependencies")?;
+
+        for dependency in depends_on {
+            sqlx::query(
+                "INSERT OR IGNORE INTO stack_dependency (stack_name, depends_on) VALUES (?, ?)",
+            )
+            .bind(stack_name)
+            .bind(dependency)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to insert stack dependency")?;
+        }
+
+        tx.commit()
+            .await
+            .context("Failed to save stack dependencies")
+    }
+
+    /// Remove a stack's dependencies and every dependency on it
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_dependency WHERE stack_name = ? OR depends_on = ?")
+            .bind(stack_name)
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack dependencies")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_set_find_and_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let deps = vec!["proxy".to_string(), "db".to_string()];
+        StackDependency::set(pool, "app", &deps).await.unwrap();
+        StackDependency::set(pool, "worker", &["db".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            StackDependency::find_by_stack(pool, "app").await.unwrap(),
+            vec!["db", "proxy"]
+        );
+
+        // Setting again replaces the list
+        StackDependency::set(pool, "app", &["proxy".to_string()])
+            .await
+            .unwrap();
+        let all = StackDependency::find_all(pool).await.unwrap();
+        assert_eq!(all["app"], vec!["proxy"]);
+        assert_eq!(all["worker"], vec!["db"]);
+
+        // Deleting db drops worker's dependency on it too
+        StackDependency::delete_by_stack(pool, "db").await.unwrap();
+        assert!(StackDependency::find_by_stack(pool, "worker")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            StackDependency::find_by_stack(pool, "app").await.unwrap(),
+            vec!["proxy"]
+        );
+    }
+}
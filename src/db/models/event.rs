@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// Number of events kept per stack, older ones are pruned
+pub const EVENT_LIMIT: i64 = 1000;
+
+/// A lifecycle action run on a stack
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StackEvent {
+    pub id: i64,
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    /// The action run (e.g. "deploy", "start", "stop", "update", "delete")
+    pub action: String,
+    /// Username that triggered the action, or what did for background runs
+    /// (e.g. "scheduler", "webhook", "autostart")
+    pub actor: String,
+    /// Exit code of the compose command, None if it didn't get to run
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i64>,
+    /// Why the action failed, if it did
+    pub error: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// Data for recording a new event
+#[derive(Debug, Clone)]
+pub struct NewStackEvent {
+    pub stack_name: String,
+    pub action: String,
+    pub actor: String,
+    pub exit_code: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl StackEvent {
+    /// Record an event and prune the stack's timeline down to EVENT_LIMIT
+    pub async fn create(pool: &SqlitePool, new_event: NewStackEvent) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO stack_event (stack_name, action, actor, exit_code, error)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&new_event.stack_name)
+        .bind(&new_event.action)
+        .bind(&new_event.actor)
+        .bind(new_event.exit_code)
+        .bind(&new_event.error)
+        .execute(pool)
+        .await
+        .context("Failed to insert stack event")?;
+
+        sqlx::query(
+            "DELETE FROM stack_event WHERE stack_name = ? AND id NOT IN
+             (SELECT id FROM stack_event WHERE stack_name = ? ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(&new_event.stack_name)
+        .bind(&new_event.stack_name)
+        .bind(EVENT_LIMIT)
+        .execute(pool)
+        .await
+        .context("Failed to prune stack events")?;
+
+        Ok(())
+    }
+
+    /// Get a page of a stack's events, newest first
+    pub async fn find_by_stack(
+        pool: &SqlitePool,
+        stack_name: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, StackEvent>(
+            "SELECT * FROM stack_event WHERE stack_name = ? ORDER BY id DESC LIMIT ? OFFSET ?",
+        )
+        .bind(stack_name)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack events")
+    }
+
+    /// Count a stack's events
+    pub async fn count_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<i64> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM stack_event WHERE stack_name = ?")
+            .bind(stack_name)
+            .fetch_one(pool)
+            .await
+            .context("Failed to count stack events")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn event(stack_name: &str, action: &str, exit_code: Option<i64>) -> NewStackEvent {
+        NewStackEvent {
+            stack_name: stack_name.to_string(),
+            action: action.to_string(),
+            actor: "admin".to_string(),
+            exit_code,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_page_events() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        for action in ["deploy", "stop", "start"] {
+            StackEvent::create(pool, event("web", action, Some(0)))
+                .await
+                .unwrap();
+        }
+        StackEvent::create(pool, event("db", "update", Some(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(StackEvent::count_by_stack(pool, "web").await.unwrap(), 3);
+
+        // Newest first
+        let first = StackEvent::find_by_stack(pool, "web", 2, 0).await.unwrap();
+        let actions: Vec<&str> = first.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["start", "stop"]);
+
+        let second = StackEvent::find_by_stack(pool, "web", 2, 2).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].action, "deploy");
+        assert_eq!(second[0].actor, "admin");
+        assert_eq!(second[0].exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_events_are_pruned() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        for _ in 0..EVENT_LIMIT + 5 {
+            StackEvent::create(pool, event("web", "restart", Some(0)))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            StackEvent::count_by_stack(pool, "web").await.unwrap(),
+            EVENT_LIMIT
+        );
+    }
+}
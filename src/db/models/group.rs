@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+/// The group (folder) each stack is filed under, if any
+pub struct StackGroup;
+
+/// A group and how many stacks are in it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct GroupSummary {
+    pub name: String,
+    #[serde(rename = "stackCount")]
+    pub stack_count: i64,
+}
+
+impl StackGroup {
+    /// Get the group of a stack
+    pub async fn find_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<Option<String>> {
+        sqlx::query_scalar("SELECT group_name FROM stack_group WHERE stack_name = ?")
+            .bind(stack_name)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to query stack group")
+    }
+
+    /// Get every grouped stack's group, keyed by stack name
+    pub async fn find_all(pool: &SqlitePool) -> Result<HashMap<String, String>> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT stack_name, group_name FROM stack_group")
+                .fetch_all(pool)
+                .await
+                .context("Failed to query stack groups")?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Every group in use, sorted by name
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<GroupSummary>> {
+        sqlx::query_as(
+            "SELECT group_name AS name, COUNT(*) AS stack_count FROM stack_group \
+             GROUP BY group_name ORDER BY group_name",
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to list stack groups")
+    }
+
+    /// Put a stack in a group, or take it out of its group with None
+    pub async fn set(pool: &SqlitePool, stack_name: &str, group_name: Option<&str>) -> Result<()> {
+        match group_name {
+            Some(group_name) => {
+                sqlx::query(
+                    "INSERT INTO stack_group (stack_name, group_name) VALUES (?, ?) \
+                     ON CONFLICT(stack_name) DO UPDATE SET group_name = excluded.group_name",
+                )
+                .bind(stack_name)
+                .bind(group_name)
+                .execute(pool)
+                .await
+                .context("Failed to save stack group")?;
+            }
+            None => Self::delete_by_stack(pool, stack_name).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Take a stack out of its group
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_group WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack group")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_set_list_and_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        StackGroup::set(pool, "web", Some("frontend"))
+            .await
+            .unwrap();
+        StackGroup::set(pool, "api", Some("backend")).await.unwrap();
+        StackGroup::set(pool, "db", Some("backend")).await.unwrap();
+        assert_eq!(
+            StackGroup::find_by_stack(pool, "web").await.unwrap(),
+            Some("frontend".to_string())
+        );
+
+        // Setting again moves the stack
+        StackGroup::set(pool, "web", Some("backend")).await.unwrap();
+        StackGroup::set(pool, "db", None).await.unwrap();
+        let all = StackGroup::find_all(pool).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["web"], "backend");
+
+        assert_eq!(
+            StackGroup::list(pool).await.unwrap(),
+            vec![GroupSummary {
+                name: "backend".to_string(),
+                stack_count: 2
+            }]
+        );
+
+        StackGroup::delete_by_stack(pool, "web").await.unwrap();
+        assert_eq!(StackGroup::find_by_stack(pool, "web").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// Number of snapshots kept per stack, older ones are pruned
+pub const HISTORY_LIMIT: i64 = 50;
+
+/// A snapshot of a stack's compose file and .env
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StackHistory {
+    pub id: i64,
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    /// What triggered the snapshot (e.g. "save", "rollback", "delete", "down", "update")
+    pub operation: String,
+    #[serde(rename = "composeFileName")]
+    pub compose_file_name: String,
+    #[serde(rename = "composeYAML")]
+    pub compose_yaml: String,
+    #[serde(rename = "composeENV")]
+    pub compose_env: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// Data for creating a new snapshot
+#[derive(Debug, Clone)]
+pub struct NewStackHistory {
+    pub stack_name: String,
+    pub operation: String,
+    pub compose_file_name: String,
+    pub compose_yaml: String,
+    pub compose_env: String,
+}
+
+impl StackHistory {
+    /// Find a snapshot by ID
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Self>> {
+        sqlx::query_as::<_, StackHistory>("SELECT * FROM stack_history WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to query stack history by id")
+    }
+
+    /// Get a stack's snapshots, newest first
+    pub async fn find_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, StackHistory>(
+            "SELECT * FROM stack_history WHERE stack_name = ? ORDER BY id DESC",
+        )
+        .bind(stack_name)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack history")
+    }
+
+    /// Store a snapshot and prune the stack's history down to HISTORY_LIMIT
+    pub async fn create(pool: &SqlitePool, new_history: NewStackHistory) -> Result<Self> {
+        let result = sqlx::query(
+            "INSERT INTO stack_history (stack_name, operation, compose_file_name, compose_yaml, compose_env)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&new_history.stack_name)
+        .bind(&new_history.operation)
+        .bind(&new_history.compose_file_name)
+        .bind(&new_history.compose_yaml)
+        .bind(&new_history.compose_env)
+        .execute(pool)
+        .await
+        .context("Failed to insert stack history")?;
+
+        sqlx::query(
+            "DELETE FROM stack_history WHERE stack_name = ? AND id NOT IN
+             (SELECT id FROM stack_history WHERE stack_name = ? ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(&new_history.stack_name)
+        .bind(&new_history.stack_name)
+        .bind(HISTORY_LIMIT)
+        .execute(pool)
+        .await
+        .context("Failed to prune stack history")?;
+
+        Self::find_by_id(pool, result.last_insert_rowid())
+            .await?
+            .context("Failed to find newly created stack history")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn snapshot(stack_name: &str, operation: &str) -> NewStackHistory {
+        NewStackHistory {
+            stack_name: stack_name.to_string(),
+            operation: operation.to_string(),
+            compose_file_name: "compose.yaml".to_string(),
+            compose_yaml: "services: {}\n".to_string(),
+            compose_env: "FOO=bar\n".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_find_history() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let first = StackHistory::create(pool, snapshot("web", "update"))
+            .await
+            .unwrap();
+        assert_eq!(first.operation, "update");
+        assert_eq!(first.compose_env, "FOO=bar\n");
+
+        StackHistory::create(pool, snapshot("web", "delete"))
+            .await
+            .unwrap();
+        StackHistory::create(pool, snapshot("db", "down"))
+            .await
+            .unwrap();
+
+        let history = StackHistory::find_by_stack(pool, "web").await.unwrap();
+        assert_eq!(history.len(), 2);
+        // Newest first
+        assert_eq!(history[0].operation, "delete");
+    }
+
+    #[tokio::test]
+    async fn test_history_is_pruned() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        for _ in 0..HISTORY_LIMIT + 5 {
+            StackHistory::create(pool, snapshot("web", "update"))
+                .await
+                .unwrap();
+        }
+
+        let history = StackHistory::find_by_stack(pool, "web").await.unwrap();
+        assert_eq!(history.len() as i64, HISTORY_LIMIT);
+    }
+}
@@ -1,6 +1,36 @@
 pub mod agent;
+pub mod agent_token;
+pub mod audit;
+pub mod autostart;
+pub mod cluster;
+pub mod dependency;
+pub mod event;
+pub mod group;
+pub mod history;
+pub mod schedule;
+pub mod secret;
 pub mod setting;
+pub mod share_link;
+pub mod stack_order;
+pub mod status_history;
+pub mod update_window;
 pub mod user;
+pub mod webhook;
 
+pub use agent_token::AgentToken;
+pub use audit::{AuditEntry, NewAuditEntry};
+pub use autostart::StackAutostart;
+pub use cluster::{ClusterEventRecord, ClusterNode};
+pub use dependency::StackDependency;
+pub use event::{NewStackEvent, StackEvent};
+pub use group::{GroupSummary, StackGroup};
+pub use history::{NewStackHistory, StackHistory};
+pub use schedule::{NewStackSchedule, StackSchedule};
+pub use secret::{SecretInfo, StoredSecret};
 pub use setting::{Setting, SettingsCache};
+pub use share_link::ShareLink;
+pub use stack_order::StackOrder;
+pub use status_history::{StatusPeriod, StatusSample};
+pub use update_window::StackUpdateWindow;
 pub use user::{NewUser, User};
+pub use webhook::StackWebhook;
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// A cron-triggered action on a stack
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StackSchedule {
+    pub id: i64,
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub action: String,
+    pub cron: String,
+    pub enabled: bool,
+    #[serde(rename = "lastRunAt")]
+    pub last_run_at: Option<String>,
+    #[serde(rename = "lastResult")]
+    pub last_result: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+/// Data for creating a new schedule
+#[derive(Debug, Clone)]
+pub struct NewStackSchedule {
+    pub stack_name: String,
+    pub action: String,
+    pub cron: String,
+}
+
+impl StackSchedule {
+    /// Find a schedule by ID
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Self>> {
+        sqlx::query_as::<_, StackSchedule>("SELECT * FROM stack_schedule WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to query schedule by id")
+    }
+
+    /// Get all schedules, optionally only those for one stack
+    pub async fn find_all(pool: &SqlitePool, stack_name: Option<&str>) -> Result<Vec<Self>> {
+        let query = match stack_name {
+            Some(name) => sqlx::query_as::<_, StackSchedule>(
+                "SELECT * FROM stack_schedule WHERE stack_name = ? ORDER BY id",
+            )
+            .bind(name.to_string()),
+            None => sqlx::query_as::<_, StackSchedule>("SELECT * FROM stack_schedule ORDER BY id"),
+        };
+
+        query
+            .fetch_all(pool)
+            .await
+            .context("Failed to query schedules")
+    }
+
+    /// Get all enabled schedules
+    pub async fn find_enabled(pool: &SqlitePool) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, StackSchedule>("SELECT * FROM stack_schedule WHERE enabled = 1")
+            .fetch_all(pool)
+            .await
+            .context("Failed to query enabled schedules")
+    }
+
+    /// Create a new schedule
+    pub async fn create(pool: &SqlitePool, new_schedule: NewStackSchedule) -> Result<Self> {
+        let result =
+            sqlx::query("INSERT INTO stack_schedule (stack_name, action, cron) VALUES (?, ?, ?)")
+                .bind(&new_schedule.stack_name)
+                .bind(&new_schedule.action)
+                .bind(&new_schedule.cron)
+                .execute(pool)
+                .await
+                .context("Failed to insert schedule")?;
+
+        Self::find_by_id(pool, result.last_insert_rowid())
+            .await?
+            .context("Failed to find newly created schedule")
+    }
+
+    /// Record the outcome of a run
+    pub async fn record_run(pool: &SqlitePool, id: i64, result: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE stack_schedule SET last_run_at = CURRENT_TIMESTAMP, last_result = ? WHERE id = ?",
+        )
+        .bind(result)
+        .bind(id)
+        .execute(pool)
+        .await
+        .context("Failed to record schedule run")?;
+
+        Ok(())
+    }
+
+    /// Delete a schedule. Returns false if it did not exist.
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM stack_schedule WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .context("Failed to delete schedule")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete every schedule for a stack
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_schedule WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack schedules")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    fn new_schedule(stack_name: &str, action: &str) -> NewStackSchedule {
+        NewStackSchedule {
+            stack_name: stack_name.to_string(),
+            action: action.to_string(),
+            cron: "0 4 * * *".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_list_schedules() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let schedule = StackSchedule::create(pool, new_schedule("web", "restart"))
+            .await
+            .unwrap();
+        assert_eq!(schedule.stack_name, "web");
+        assert_eq!(schedule.action, "restart");
+        assert!(schedule.enabled);
+        assert!(schedule.last_run_at.is_none());
+
+        StackSchedule::create(pool, new_schedule("db", "update"))
+            .await
+            .unwrap();
+
+        assert_eq!(StackSchedule::find_all(pool, None).await.unwrap().len(), 2);
+        assert_eq!(
+            StackSchedule::find_all(pool, Some("web"))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(StackSchedule::find_enabled(pool).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_record_run_and_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let schedule = StackSchedule::create(pool, new_schedule("web", "stop"))
+            .await
+            .unwrap();
+        StackSchedule::record_run(pool, schedule.id, "ok")
+            .await
+            .unwrap();
+
+        let found = StackSchedule::find_by_id(pool, schedule.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.last_result.as_deref(), Some("ok"));
+        assert!(found.last_run_at.is_some());
+
+        assert!(StackSchedule::delete(pool, schedule.id).await.unwrap());
+        assert!(!StackSchedule::delete(pool, schedule.id).await.unwrap());
+
+        StackSchedule::create(pool, new_schedule("web", "stop"))
+            .await
+            .unwrap();
+        StackSchedule::delete_by_stack(pool, "web").await.unwrap();
+        assert!(StackSchedule::find_all(pool, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+/// A secret's name and when it was last set; the value never leaves the server
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SecretInfo {
+    pub name: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// Values for `${secret:NAME}` references, stored encrypted
+pub struct StoredSecret;
+
+impl StoredSecret {
+    /// Get every secret's name, sorted
+    pub async fn list(pool: &SqlitePool) -> Result<Vec<SecretInfo>> {
+        sqlx::query_as("SELECT name, updated_at FROM secret ORDER BY name")
+            .fetch_all(pool)
+            .await
+            .context("Failed to query secrets")
+    }
+
+    /// Get the encrypted values of the given secrets that exist, keyed by name
+    pub async fn find_values(
+        pool: &SqlitePool,
+        names: &[String],
+    ) -> Result<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        for name in names {
+            let value: Option<String> =
+                sqlx::query_scalar("SELECT value FROM secret WHERE name = ?")
+                    .bind(name)
+                    .fetch_optional(pool)
+                    .await
+                    .context("Failed to query secret")?;
+            if let Some(value) = value {
+                values.insert(name.clone(), value);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Create or replace a secret with an already encrypted value
+    pub async fn set(pool: &SqlitePool, name: &str, encrypted_value: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO secret (name, value) VALUES (?, ?) \
+             ON CONFLICT(name) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(name)
+        .bind(encrypted_value)
+        .execute(pool)
+        .await
+        .context("Failed to save secret")?;
+
+        Ok(())
+    }
+
+    /// Delete a secret. Returns false if there was no such secret.
+    pub async fn delete(pool: &SqlitePool, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM secret WHERE name = ?")
+            .bind(name)
+            .execute(pool)
+            .await
+            .context("Failed to delete secret")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_set_find_and_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        StoredSecret::set(pool, "DB_PASSWORD", "enc:one")
+            .await
+            .unwrap();
+        StoredSecret::set(pool, "API_KEY", "enc:two").await.unwrap();
+        StoredSecret::set(pool, "DB_PASSWORD", "enc:three")
+            .await
+            .unwrap();
+
+        let names: Vec<String> = StoredSecret::list(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, ["API_KEY", "DB_PASSWORD"]);
+
+        let values =
+            StoredSecret::find_values(pool, &["DB_PASSWORD".to_string(), "MISSING".to_string()])
+                .await
+                .unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["DB_PASSWORD"], "enc:three");
+
+        assert!(StoredSecret::delete(pool, "API_KEY").await.unwrap());
+        assert!(!StoredSecret::delete(pool, "API_KEY").await.unwrap());
+    }
+}
@@ -130,8 +130,7 @@ impl SettingsCache {
     }
 
     /// Clear all cached values
-    #[allow(dead_code)]
-    async fn clear(&self) {
+    pub async fn clear(&self) {
         let mut cache = self.cache.write().await;
         cache.clear();
     }
@@ -291,7 +290,6 @@ impl Setting {
     }
 
     /// Delete a setting by key
-    #[allow(dead_code)]
     pub async fn delete(pool: &SqlitePool, cache: &SettingsCache, key: &str) -> Result<()> {
         sqlx::query("DELETE FROM setting WHERE key = ?")
             .bind(key)
@@ -309,19 +307,15 @@ impl Setting {
 mod tests {
     use super::*;
     use crate::db::Database;
-    use tempfile::TempDir;
-
-    async fn setup_test_db() -> (Database, TempDir, SettingsCache) {
-        let temp_dir = TempDir::new().unwrap();
-        let db = Database::new(temp_dir.path()).await.unwrap();
-        db.migrate().await.unwrap();
-        let cache = SettingsCache::new();
-        (db, temp_dir, cache)
+    use crate::test_support::test_db;
+
+    async fn setup_test_db() -> (Database, SettingsCache) {
+        (test_db().await, SettingsCache::new())
     }
 
     #[tokio::test]
     async fn test_set_and_get_setting() {
-        let (db, _temp, cache) = setup_test_db().await;
+        let (db, cache) = setup_test_db().await;
         let pool = db.pool();
 
         // Set a string value
@@ -357,7 +351,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_settings_by_type() {
-        let (db, _temp, cache) = setup_test_db().await;
+        let (db, cache) = setup_test_db().await;
         let pool = db.pool();
 
         // Set multiple settings of "general" type
@@ -381,7 +375,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_set_settings_bulk() {
-        let (db, _temp, cache) = setup_test_db().await;
+        let (db, cache) = setup_test_db().await;
         let pool = db.pool();
 
         let mut data = HashMap::new();
@@ -400,7 +394,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_cache() {
-        let (db, _temp, cache) = setup_test_db().await;
+        let (db, cache) = setup_test_db().await;
         let pool = db.pool();
 
         // Set a value
@@ -428,7 +422,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_delete_setting() {
-        let (db, _temp, cache) = setup_test_db().await;
+        let (db, cache) = setup_test_db().await;
         let pool = db.pool();
 
         Setting::set(pool, &cache, "to_delete", &JsonValue::String("delete_me".to_string()), Some("general"))
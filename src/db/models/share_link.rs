@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::webhook::hash_token;
+use crate::utils::crypto::gen_secret;
+
+/// Length of generated share link tokens
+const TOKEN_LENGTH: usize = 48;
+
+/// Everything but the token hash, which never leaves the database
+const COLUMNS: &str = "id, stack_name, created_by, expires_at, created_at";
+
+/// A link giving anyone who has it read-only access to a stack's logs until
+/// it expires. Only a hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: i64,
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    /// Username of the user that created the link
+    #[serde(rename = "createdBy")]
+    pub created_by: String,
+    /// Unix seconds
+    #[serde(rename = "expiresAt")]
+    pub expires_at: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl ShareLink {
+    /// Get a stack's links that haven't expired, newest first
+    pub async fn find_by_stack(pool: &SqlitePool, stack_name: &str, now: i64) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, ShareLink>(&format!(
+            "SELECT {} FROM share_link WHERE stack_name = ? AND expires_at > ? ORDER BY id DESC",
+            COLUMNS
+        ))
+        .bind(stack_name)
+        .bind(now)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query share links")
+    }
+
+    /// Create a link to a stack's logs, pruning expired links
+    ///
+    /// Returns the link and the plaintext token, which cannot be recovered
+    /// afterwards.
+    pub async fn create(
+        pool: &SqlitePool,
+        stack_name: &str,
+        created_by: &str,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<(Self, String)> {
+        sqlx::query("DELETE FROM share_link WHERE expires_at <= ?")
+            .bind(now)
+            .execute(pool)
+            .await
+            .context("Failed to prune share links")?;
+
+        let token = gen_secret(TOKEN_LENGTH);
+        let result = sqlx::query(
+            "INSERT INTO share_link (stack_name, token_hash, created_by, expires_at) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(stack_name)
+        .bind(hash_token(&token))
+        .bind(created_by)
+        .bind(expires_at)
+        .execute(pool)
+        .await
+        .context("Failed to insert share link")?;
+
+        let record = sqlx::query_as::<_, ShareLink>(&format!(
+            "SELECT {} FROM share_link WHERE id = ?",
+            COLUMNS
+        ))
+        .bind(result.last_insert_rowid())
+        .fetch_one(pool)
+        .await
+        .context("Failed to find newly created share link")?;
+
+        Ok((record, token))
+    }
+
+    /// Find the unexpired link a presented token belongs to
+    pub async fn verify(pool: &SqlitePool, token: &str, now: i64) -> Result<Option<Self>> {
+        sqlx::query_as::<_, ShareLink>(&format!(
+            "SELECT {} FROM share_link WHERE token_hash = ? AND expires_at > ?",
+            COLUMNS
+        ))
+        .bind(hash_token(token))
+        .bind(now)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to query share link")
+    }
+
+    /// Revoke a link. Returns false if there was no such link.
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM share_link WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+            .context("Failed to delete share link")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revoke every link to a stack
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM share_link WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete share links")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_create_verify_and_expire() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let (link, token) = ShareLink::create(pool, "web", "admin", 200, 100)
+            .await
+            .unwrap();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(link.stack_name, "web");
+        assert_eq!(link.created_by, "admin");
+
+        assert_eq!(
+            ShareLink::verify(pool, &token, 150)
+                .await
+                .unwrap()
+                .unwrap()
+                .id,
+            link.id
+        );
+        assert!(ShareLink::verify(pool, &token, 200)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(ShareLink::verify(pool, "wrong", 150)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            ShareLink::find_by_stack(pool, "web", 150)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Creating a link prunes expired ones
+        let (other, _) = ShareLink::create(pool, "db", "admin", 400, 300)
+            .await
+            .unwrap();
+        assert!(ShareLink::find_by_stack(pool, "web", 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(ShareLink::delete(pool, other.id).await.unwrap());
+        assert!(!ShareLink::delete(pool, other.id).await.unwrap());
+    }
+}
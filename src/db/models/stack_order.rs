@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Where a user wants a stack in their stack list
+///
+/// Pinned stacks come first, then stacks with a position in position order,
+/// then the rest by name. Rows are only kept for stacks that are pinned or
+/// have a position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct StackOrder {
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub pinned: bool,
+    pub position: Option<i64>,
+}
+
+impl StackOrder {
+    /// Get a user's ordering hints, in list order
+    pub async fn find_by_user(pool: &SqlitePool, user_id: i64) -> Result<Vec<Self>> {
+        let mut hints: Vec<Self> = sqlx::query_as(
+            "SELECT stack_name, pinned, position FROM stack_order WHERE user_id = ?",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack order")?;
+
+        hints.sort_by(|a, b| {
+            compare(Some(a), Some(b)).then_with(|| a.stack_name.cmp(&b.stack_name))
+        });
+        Ok(hints)
+    }
+
+    /// Pin a stack to the top of a user's list, or unpin it
+    pub async fn set_pinned(
+        pool: &SqlitePool,
+        user_id: i64,
+        stack_name: &str,
+        pinned: bool,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO stack_order (user_id, stack_name, pinned) VALUES (?, ?, ?) \
+             ON CONFLICT(user_id, stack_name) DO UPDATE SET pinned = excluded.pinned",
+        )
+        .bind(user_id)
+        .bind(stack_name)
+        .bind(pinned)
+        .execute(pool)
+        .await
+        .context("Failed to save stack pin")?;
+
+        sqlx::query(
+            "DELETE FROM stack_order WHERE user_id = ? AND stack_name = ? \
+             AND pinned = 0 AND position IS NULL",
+        )
+        .bind(user_id)
+        .bind(stack_name)
+        .execute(pool)
+        .await
+        .context("Failed to clean up stack order")?;
+
+        Ok(())
+    }
+
+    /// Replace the order of a user's stacks; stacks not listed go after them
+    ///
+    /// Pins are kept.
+    pub async fn set_order(pool: &SqlitePool, user_id: i64, stack_names: &[String]) -> Result<()> {
+        let mut tx = pool.begin().await.context("Failed to start transaction")?;
+
+        sqlx::query("UPDATE stack_order SET position = NULL WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to clear stack order")?;
+
+        for (position, stack_name) in stack_names.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO stack_order (user_id, stack_name, position) VALUES (?, ?, ?) \
+                 ON CONFLICT(user_id, stack_name) DO UPDATE SET position = excluded.position",
+            )
+            .bind(user_id)
+            .bind(stack_name)
+            .bind(position as i64)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to save stack order")?;
+        }
+
+        sqlx::query(
+            "DELETE FROM stack_order WHERE user_id = ? AND pinned = 0 AND position IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to clean up stack order")?;
+
+        tx.commit().await.context("Failed to save stack order")
+    }
+
+    /// Forget a stack in every user's list
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_order WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack order")?;
+
+        Ok(())
+    }
+}
+
+/// Compare two stacks by their hints: pinned first, then by position, and
+/// stacks without hints last. Ties are left to the caller.
+fn compare(a: Option<&StackOrder>, b: Option<&StackOrder>) -> Ordering {
+    let key = |hint: Option<&StackOrder>| {
+        let pinned = hint.is_some_and(|h| h.pinned);
+        let position = hint.and_then(|h| h.position);
+        (!pinned, position.is_none(), position)
+    };
+    key(a).cmp(&key(b))
+}
+
+/// Sort items the way a user ordered their stacks, by name otherwise
+pub fn sort_by_hints<T>(items: &mut [T], hints: &[StackOrder], name: impl Fn(&T) -> &str) {
+    let hints: HashMap<&str, &StackOrder> =
+        hints.iter().map(|h| (h.stack_name.as_str(), h)).collect();
+    items.sort_by(|a, b| {
+        let (a, b) = (name(a), name(b));
+        compare(hints.get(a).copied(), hints.get(b).copied()).then_with(|| a.cmp(b))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::User;
+    use crate::test_support::{test_db, user};
+
+    #[tokio::test]
+    async fn test_pin_and_order() {
+        let db = test_db().await;
+        let pool = db.pool();
+        let alice = user("alice").create(&db).await;
+        let bob = user("bob").create(&db).await;
+
+        StackOrder::set_pinned(pool, alice.id, "db", true)
+            .await
+            .unwrap();
+        StackOrder::set_order(pool, alice.id, &["web".to_string(), "db".to_string()])
+            .await
+            .unwrap();
+        StackOrder::set_pinned(pool, bob.id, "web", true)
+            .await
+            .unwrap();
+
+        let hints = StackOrder::find_by_user(pool, alice.id).await.unwrap();
+        let mut stacks = vec!["api", "web", "cache", "db"];
+        sort_by_hints(&mut stacks, &hints, |s| s);
+        assert_eq!(stacks, ["db", "web", "api", "cache"]);
+
+        // Unpinning keeps the position; a new order drops stacks left out
+        StackOrder::set_pinned(pool, alice.id, "db", false)
+            .await
+            .unwrap();
+        let hints = StackOrder::find_by_user(pool, alice.id).await.unwrap();
+        assert_eq!(hints[1].stack_name, "db");
+        assert_eq!(hints[1].position, Some(1));
+        StackOrder::set_order(pool, alice.id, &["api".to_string()])
+            .await
+            .unwrap();
+        let hints = StackOrder::find_by_user(pool, alice.id).await.unwrap();
+        assert_eq!(hints.len(), 1);
+
+        StackOrder::delete_by_stack(pool, "web").await.unwrap();
+        assert!(StackOrder::find_by_user(pool, bob.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Hints go with the user
+        User::delete(pool, alice.id).await.unwrap();
+        assert!(StackOrder::find_by_user(pool, alice.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
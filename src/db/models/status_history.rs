@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+
+/// A period in which every sample of a stack had the same status
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StatusPeriod {
+    pub id: i64,
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub status: i32,
+    /// Unix timestamp of the first sample
+    pub since: i64,
+    /// Unix timestamp of the latest sample
+    pub until: i64,
+}
+
+/// A stack's status at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusSample {
+    pub stack_name: String,
+    pub status: i32,
+    /// Unix timestamp the status was sampled at
+    pub at: i64,
+}
+
+impl StatusPeriod {
+    /// Record status samples, oldest first, in one transaction
+    ///
+    /// A sample extends the stack's latest period if the status is unchanged
+    /// and the previous sample is at most `max_gap` seconds old; otherwise it
+    /// starts a new period, so time nobody was sampling (dockru was down)
+    /// counts as neither up nor down.
+    pub async fn record_batch(
+        pool: &SqlitePool,
+        samples: &[StatusSample],
+        max_gap: i64,
+    ) -> Result<()> {
+        let mut tx = pool.begin().await?;
+        for sample in samples {
+            Self::record(&mut tx, sample, max_gap).await?;
+        }
+        tx.commit()
+            .await
+            .context("Failed to commit stack status history")?;
+        Ok(())
+    }
+
+    async fn record(
+        conn: &mut SqliteConnection,
+        sample: &StatusSample,
+        max_gap: i64,
+    ) -> Result<()> {
+        let (status, now) = (sample.status, sample.at);
+        let latest = sqlx::query_as::<_, StatusPeriod>(
+            "SELECT * FROM stack_status_history WHERE stack_name = ? ORDER BY until DESC LIMIT 1",
+        )
+        .bind(&sample.stack_name)
+        .fetch_optional(&mut *conn)
+        .await
+        .context("Failed to query stack status history")?;
+
+        match latest {
+            Some(latest) if latest.status == status && now - latest.until <= max_gap => {
+                sqlx::query("UPDATE stack_status_history SET until = ? WHERE id = ?")
+                    .bind(now)
+                    .bind(latest.id)
+                    .execute(&mut *conn)
+                    .await
+                    .context("Failed to update stack status history")?;
+            }
+            latest => {
+                // Close the gap to the new status if sampling was continuous
+                let since = match latest {
+                    Some(latest) if now - latest.until <= max_gap => latest.until,
+                    _ => now,
+                };
+                sqlx::query(
+                    "INSERT INTO stack_status_history (stack_name, status, since, until)
+                     VALUES (?, ?, ?, ?)",
+                )
+                .bind(&sample.stack_name)
+                .bind(status)
+                .bind(since)
+                .bind(now)
+                .execute(&mut *conn)
+                .await
+                .context("Failed to insert stack status history")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A stack's periods that end at or after `from`, oldest first
+    pub async fn find_since(pool: &SqlitePool, stack_name: &str, from: i64) -> Result<Vec<Self>> {
+        sqlx::query_as::<_, StatusPeriod>(
+            "SELECT * FROM stack_status_history WHERE stack_name = ? AND until >= ? ORDER BY since",
+        )
+        .bind(stack_name)
+        .bind(from)
+        .fetch_all(pool)
+        .await
+        .context("Failed to query stack status history")
+    }
+
+    /// Remove periods that ended before `before`, returning how many were removed
+    pub async fn prune(pool: &SqlitePool, before: i64) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM stack_status_history WHERE until < ?")
+            .bind(before)
+            .execute(pool)
+            .await
+            .context("Failed to prune stack status history")?;
+        Ok(result.rows_affected())
+    }
+
+    /// Remove a stack's status history
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_status_history WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack status history")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+    use crate::utils::constants::{EXITED, RUNNING};
+
+    async fn record(pool: &SqlitePool, stack_name: &str, status: i32, at: i64) {
+        let sample = StatusSample {
+            stack_name: stack_name.to_string(),
+            status,
+            at,
+        };
+        StatusPeriod::record_batch(pool, &[sample], 180)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_status_periods() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        record(pool, "web", RUNNING, 1000).await;
+        record(pool, "web", RUNNING, 1060).await;
+        record(pool, "web", EXITED, 1120).await;
+        // dockru was down in between
+        record(pool, "web", EXITED, 5000).await;
+        record(pool, "db", RUNNING, 1000).await;
+
+        let periods: Vec<(i32, i64, i64)> = StatusPeriod::find_since(pool, "web", 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| (p.status, p.since, p.until))
+            .collect();
+        assert_eq!(
+            periods,
+            [
+                (RUNNING, 1000, 1060),
+                (EXITED, 1060, 1120),
+                (EXITED, 5000, 5000)
+            ]
+        );
+        assert_eq!(
+            StatusPeriod::find_since(pool, "web", 2000)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert_eq!(StatusPeriod::prune(pool, 1100).await.unwrap(), 2);
+        StatusPeriod::delete_by_stack(pool, "web").await.unwrap();
+        assert!(StatusPeriod::find_since(pool, "web", 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_status_batch() {
+        let db = test_db().await;
+        let pool = db.pool();
+        let sample = |stack_name: &str, status, at| StatusSample {
+            stack_name: stack_name.to_string(),
+            status,
+            at,
+        };
+
+        StatusPeriod::record_batch(
+            pool,
+            &[
+                sample("web", RUNNING, 1000),
+                sample("db", RUNNING, 1000),
+                sample("web", RUNNING, 1060),
+                sample("web", EXITED, 1120),
+            ],
+            180,
+        )
+        .await
+        .unwrap();
+
+        let periods: Vec<(i32, i64, i64)> = StatusPeriod::find_since(pool, "web", 0)
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| (p.status, p.since, p.until))
+            .collect();
+        assert_eq!(periods, [(RUNNING, 1000, 1060), (EXITED, 1060, 1120)]);
+        assert_eq!(
+            StatusPeriod::find_since(pool, "db", 0).await.unwrap().len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::utils::update_window::UpdateWindow;
+
+/// The update window of each stack that has image updates applied automatically
+pub struct StackUpdateWindow;
+
+fn parse_stored(stack_name: &str, window: &str) -> Option<UpdateWindow> {
+    match window.parse() {
+        Ok(window) => Some(window),
+        Err(e) => {
+            tracing::warn!("Ignoring update window of stack {}: {}", stack_name, e);
+            None
+        }
+    }
+}
+
+impl StackUpdateWindow {
+    /// Get the update window of a stack
+    pub async fn find_by_stack(
+        pool: &SqlitePool,
+        stack_name: &str,
+    ) -> Result<Option<UpdateWindow>> {
+        let window: Option<String> = sqlx::query_scalar(
+            "SELECT update_window FROM stack_update_window WHERE stack_name = ?",
+        )
+        .bind(stack_name)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to query stack update window")?;
+
+        Ok(window.and_then(|window| parse_stored(stack_name, &window)))
+    }
+
+    /// Get every stack's update window, keyed by stack name
+    pub async fn find_all(pool: &SqlitePool) -> Result<HashMap<String, UpdateWindow>> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT stack_name, update_window FROM stack_update_window")
+                .fetch_all(pool)
+                .await
+                .context("Failed to query stack update windows")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(name, window)| {
+                let window = parse_stored(&name, &window)?;
+                Some((name, window))
+            })
+            .collect())
+    }
+
+    /// Set a stack's update window, or remove it with None
+    pub async fn set(
+        pool: &SqlitePool,
+        stack_name: &str,
+        window: Option<&UpdateWindow>,
+    ) -> Result<()> {
+        match window {
+            Some(window) => {
+                sqlx::query(
+                    "INSERT INTO stack_update_window (stack_name, update_window) VALUES (?, ?) \
+                     ON CONFLICT(stack_name) DO UPDATE SET update_window = excluded.update_window",
+                )
+                .bind(stack_name)
+                .bind(window.to_string())
+                .execute(pool)
+                .await
+                .context("Failed to save stack update window")?;
+            }
+            None => Self::delete_by_stack(pool, stack_name).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Remove a stack's update window
+    pub async fn delete_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM stack_update_window WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack update window")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_set_and_find() {
+        let db = test_db().await;
+        let pool = db.pool();
+        let night: UpdateWindow = "03:00-05:00".parse().unwrap();
+        let late: UpdateWindow = "23:00-01:00".parse().unwrap();
+
+        StackUpdateWindow::set(pool, "web", Some(&night))
+            .await
+            .unwrap();
+        StackUpdateWindow::set(pool, "db", Some(&night))
+            .await
+            .unwrap();
+        StackUpdateWindow::set(pool, "web", Some(&late))
+            .await
+            .unwrap();
+        assert_eq!(
+            StackUpdateWindow::find_by_stack(pool, "web").await.unwrap(),
+            Some(late)
+        );
+
+        StackUpdateWindow::set(pool, "db", None).await.unwrap();
+        let all = StackUpdateWindow::find_all(pool).await.unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all["web"], late);
+
+        StackUpdateWindow::delete_by_stack(pool, "web")
+            .await
+            .unwrap();
+        assert_eq!(
+            StackUpdateWindow::find_by_stack(pool, "web").await.unwrap(),
+            None
+        );
+    }
+}
@@ -251,19 +251,11 @@ impl User {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::db::Database;
-    use tempfile::TempDir;
-
-    async fn setup_test_db() -> (Database, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let db = Database::new(temp_dir.path()).await.unwrap();
-        db.migrate().await.unwrap();
-        (db, temp_dir)
-    }
+    use crate::test_support::{self, test_db};
 
     #[tokio::test]
     async fn test_create_and_find_user() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let new_user = NewUser {
@@ -293,7 +285,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_user_count() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let count = User::count(pool).await.unwrap();
@@ -314,17 +306,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_update_password() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
-        let new_user = NewUser {
-            username: "testuser".to_string(),
-            password: Some("oldpass".to_string()),
-            active: true,
-            timezone: None,
-        };
-
-        let mut user = User::create(pool, new_user).await.unwrap();
+        let mut user = test_support::user("testuser")
+            .password("oldpass")
+            .create(&db)
+            .await;
 
         // Password should be hashed, not plaintext
         assert_ne!(user.password.as_ref().unwrap(), "oldpass");
@@ -341,17 +329,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_twofa() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
-        let new_user = NewUser {
-            username: "testuser".to_string(),
-            password: Some("pass".to_string()),
-            active: true,
-            timezone: None,
-        };
-
-        let mut user = User::create(pool, new_user).await.unwrap();
+        let mut user = test_support::user("testuser")
+            .password("pass")
+            .create(&db)
+            .await;
         assert!(!user.twofa_status);
 
         user.enable_twofa(pool, "SECRET123").await.unwrap();
@@ -365,17 +349,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_verify_password() {
-        let (db, _temp) = setup_test_db().await;
-        let pool = db.pool();
-
-        let new_user = NewUser {
-            username: "testuser".to_string(),
-            password: Some("correct_password".to_string()),
-            active: true,
-            timezone: None,
-        };
-
-        let user = User::create(pool, new_user).await.unwrap();
+        let db = test_db().await;
+        let user = test_support::user("testuser")
+            .password("correct_password")
+            .create(&db)
+            .await;
 
         // Correct password should verify
         assert!(user.verify_password("correct_password").unwrap());
@@ -386,18 +364,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_jwt() {
-        let (db, _temp) = setup_test_db().await;
-        let pool = db.pool();
-
+        let db = test_db().await;
         let password = "test_password";
-        let new_user = NewUser {
-            username: "testuser".to_string(),
-            password: Some(password.to_string()),
-            active: true,
-            timezone: None,
-        };
-
-        let user = User::create(pool, new_user).await.unwrap();
+        let user = test_support::user("testuser")
+            .password(password)
+            .create(&db)
+            .await;
         let jwt_secret = "test_jwt_secret";
 
         // Create JWT - pass the original password, not the hash!
@@ -416,21 +388,17 @@ mod tests {
 
     #[tokio::test]
     async fn test_jwt_detects_password_change() {
-        let (db, _temp) = setup_test_db().await;
+        let db = test_db().await;
         let pool = db.pool();
 
         let old_password = "old_password";
         let new_password = "new_password";
         let jwt_secret = "test_jwt_secret";
 
-        let new_user = NewUser {
-            username: "testuser".to_string(),
-            password: Some(old_password.to_string()),
-            active: true,
-            timezone: None,
-        };
-
-        let mut user = User::create(pool, new_user).await.unwrap();
+        let mut user = test_support::user("testuser")
+            .password(old_password)
+            .create(&db)
+            .await;
 
         // Create JWT with old password
         let token = user.create_jwt(old_password, jwt_secret).unwrap();
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use sqlx::SqlitePool;
+
+use crate::utils::crypto::gen_secret;
+
+/// Length of generated webhook tokens
+const TOKEN_LENGTH: usize = 40;
+
+/// Per-stack deploy webhook. Only a hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct StackWebhook {
+    pub id: i64,
+    pub stack_name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: String,
+}
+
+impl StackWebhook {
+    /// Find the webhook for a stack
+    pub async fn find_by_stack(pool: &SqlitePool, stack_name: &str) -> Result<Option<Self>> {
+        sqlx::query_as::<_, StackWebhook>("SELECT * FROM stack_webhook WHERE stack_name = ?")
+            .bind(stack_name)
+            .fetch_optional(pool)
+            .await
+            .context("Failed to query stack webhook")
+    }
+
+    /// Generate a new token for a stack, replacing any existing one
+    ///
+    /// Returns the plaintext token. It cannot be recovered afterwards.
+    pub async fn regenerate(pool: &SqlitePool, stack_name: &str) -> Result<String> {
+        let token = gen_secret(TOKEN_LENGTH);
+
+        sqlx::query(
+            "INSERT INTO stack_webhook (stack_name, token_hash) VALUES (?, ?)
+             ON CONFLICT(stack_name) DO UPDATE SET token_hash = excluded.token_hash,
+             created_at = CURRENT_TIMESTAMP",
+        )
+        .bind(stack_name)
+        .bind(hash_token(&token))
+        .execute(pool)
+        .await
+        .context("Failed to store stack webhook")?;
+
+        Ok(token)
+    }
+
+    /// Remove the webhook for a stack. Returns false if there was none.
+    pub async fn delete(pool: &SqlitePool, stack_name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM stack_webhook WHERE stack_name = ?")
+            .bind(stack_name)
+            .execute(pool)
+            .await
+            .context("Failed to delete stack webhook")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Check a presented token against the stored hash for a stack
+    pub async fn verify(pool: &SqlitePool, stack_name: &str, token: &str) -> Result<bool> {
+        let Some(webhook) = Self::find_by_stack(pool, stack_name).await? else {
+            return Ok(false);
+        };
+
+        Ok(constant_time_eq(
+            webhook.token_hash.as_bytes(),
+            hash_token(token).as_bytes(),
+        ))
+    }
+}
+
+/// Hash a webhook (or agent) token for storage
+pub(super) fn hash_token(token: &str) -> String {
+    hex::encode(Sha3_256::digest(token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_regenerate_and_verify() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        assert!(!StackWebhook::verify(pool, "web", "anything").await.unwrap());
+
+        let token = StackWebhook::regenerate(pool, "web").await.unwrap();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert!(StackWebhook::verify(pool, "web", &token).await.unwrap());
+        assert!(!StackWebhook::verify(pool, "web", "wrong").await.unwrap());
+        assert!(!StackWebhook::verify(pool, "other", &token).await.unwrap());
+
+        // Regenerating invalidates the old token
+        let new_token = StackWebhook::regenerate(pool, "web").await.unwrap();
+        assert!(!StackWebhook::verify(pool, "web", &token).await.unwrap());
+        assert!(StackWebhook::verify(pool, "web", &new_token).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let db = test_db().await;
+        let pool = db.pool();
+
+        let token = StackWebhook::regenerate(pool, "web").await.unwrap();
+        assert!(StackWebhook::delete(pool, "web").await.unwrap());
+        assert!(!StackWebhook::delete(pool, "web").await.unwrap());
+        assert!(!StackWebhook::verify(pool, "web", &token).await.unwrap());
+    }
+}
@@ -0,0 +1,164 @@
+// Batched status history writes
+//
+// All queries share a single SQLite connection, so writing every status sample
+// on its own makes it queue behind, and hold up, everything else. Samples are
+// buffered here instead and written by one task every STATUS_WRITE_BATCH_SECS,
+// in a single transaction.
+//
+// The buffer holds at most STATUS_WRITE_BUFFER samples. Once it is half full
+// the writer flushes early. If writes still can't keep up, the oldest samples
+// are dropped: that leaves a gap in the history, which uptime already treats
+// as unsampled time, rather than blocking the samplers or growing without
+// bound. A batch that fails to write goes back in front of newer samples to
+// be retried.
+//
+// Samples still buffered when dockru stops are lost, like the ones it never
+// took while down.
+
+use crate::db::models::{StatusPeriod, StatusSample};
+use crate::utils::constants::{STATUS_WRITE_BATCH_SECS, STATUS_WRITE_BUFFER};
+use crate::utils::limit_queue::LimitQueue;
+use anyhow::Result;
+use sqlx::SqlitePool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+pub struct StatusWriter {
+    pool: SqlitePool,
+    /// Passed to [`StatusPeriod::record_batch`]
+    max_gap: i64,
+    buffer: Mutex<LimitQueue<StatusSample>>,
+    /// Samples dropped since the last warning about it
+    dropped: AtomicU64,
+    flush_now: Notify,
+}
+
+impl StatusWriter {
+    pub fn new(pool: SqlitePool, max_gap: i64) -> Self {
+        Self::with_capacity(pool, max_gap, STATUS_WRITE_BUFFER)
+    }
+
+    fn with_capacity(pool: SqlitePool, max_gap: i64, capacity: usize) -> Self {
+        Self {
+            pool,
+            max_gap,
+            buffer: Mutex::new(LimitQueue::new(capacity)),
+            dropped: AtomicU64::new(0),
+            flush_now: Notify::new(),
+        }
+    }
+
+    /// Write buffered samples in the background for as long as the server runs
+    pub fn start(self: &Arc<Self>) {
+        let writer = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(STATUS_WRITE_BATCH_SECS)) => {}
+                    _ = writer.flush_now.notified() => {}
+                }
+                if let Err(e) = writer.flush().await {
+                    warn!("Failed to write stack status history: {:#}", e);
+                }
+            }
+        });
+    }
+
+    /// Buffer a sample for the next batch
+    pub fn push(&self, sample: StatusSample) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() == buffer.limit() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        buffer.push(sample);
+        if buffer.len() >= buffer.limit() / 2 {
+            self.flush_now.notify_one();
+        }
+    }
+
+    /// Write the buffered samples, returning how many were written
+    pub(crate) async fn flush(&self) -> Result<usize> {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!(
+                "Dropped {} stack status samples, writes can't keep up",
+                dropped
+            );
+        }
+
+        let batch = self.buffer.lock().unwrap().drain();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = StatusPeriod::record_batch(&self.pool, &batch, self.max_gap).await {
+            self.requeue(batch);
+            return Err(e);
+        }
+        debug!("Wrote {} stack status samples", batch.len());
+        Ok(batch.len())
+    }
+
+    /// Put a failed batch back before the samples buffered since
+    fn requeue(&self, batch: Vec<StatusSample>) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let newer = buffer.drain();
+        let total = batch.len() + newer.len();
+        self.dropped.fetch_add(
+            total.saturating_sub(buffer.limit()) as u64,
+            Ordering::Relaxed,
+        );
+        for sample in batch.into_iter().chain(newer) {
+            buffer.push(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+    use crate::utils::constants::RUNNING;
+
+    fn sample(at: i64) -> StatusSample {
+        StatusSample {
+            stack_name: "web".to_string(),
+            status: RUNNING,
+            at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_writer_batches() {
+        let db = test_db().await;
+        let writer = StatusWriter::with_capacity(db.pool().clone(), 180, 4);
+
+        for at in [1000, 1060, 1120, 1180, 1240] {
+            writer.push(sample(at));
+        }
+        // The oldest sample made room for the newest
+        assert_eq!(writer.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(writer.flush().await.unwrap(), 4);
+        assert_eq!(writer.flush().await.unwrap(), 0);
+
+        let periods = StatusPeriod::find_since(db.pool(), "web", 0).await.unwrap();
+        assert_eq!(periods.len(), 1);
+        assert_eq!((periods[0].since, periods[0].until), (1060, 1240));
+    }
+
+    #[tokio::test]
+    async fn test_status_writer_requeues_failed_batch() {
+        let db = test_db().await;
+        let writer = StatusWriter::with_capacity(db.pool().clone(), 180, 3);
+
+        writer.push(sample(1060));
+        writer.requeue(vec![sample(940), sample(1000)]);
+        writer.push(sample(1120));
+
+        let buffered: Vec<i64> = writer.buffer.lock().unwrap().iter().map(|s| s.at).collect();
+        assert_eq!(buffered, [1000, 1060, 1120]);
+        assert_eq!(writer.dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,200 @@
+// Agent discovery over mDNS
+//
+// With DOCKRU_MDNS enabled, every instance announces itself on the LAN as a
+// `_dockru._tcp` service and browses for the others. Instances found this way
+// are offered in `discoverAgents` so they can be added without typing their
+// URL; adding one still goes through addAgent with credentials.
+
+use crate::server::ServerContext;
+use chrono::Utc;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+
+/// mDNS service type dockru instances announce themselves as
+const SERVICE_TYPE: &str = "_dockru._tcp.local.";
+
+/// Instance name used when the hostname can't be read
+const DEFAULT_INSTANCE_NAME: &str = "dockru";
+
+/// Service full name -> instance found on the LAN
+static DISCOVERED: Lazy<Mutex<HashMap<String, DiscoveredAgent>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A dockru instance found on the LAN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredAgent {
+    /// Instance name, the host name of the machine it runs on
+    pub name: String,
+    /// URL to add the instance with
+    pub url: String,
+    /// host:port, as agents are keyed
+    pub endpoint: String,
+    pub version: Option<String>,
+    /// Unix seconds the instance last announced itself
+    #[serde(rename = "lastSeen")]
+    pub last_seen: i64,
+}
+
+/// Announce this instance and browse for others, if enabled
+pub fn start(ctx: Arc<ServerContext>) {
+    if !ctx.config.mdns {
+        return;
+    }
+
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            warn!("Failed to start mDNS: {}", e);
+            return;
+        }
+    };
+
+    let name = instance_name();
+    let own_fullname = format!("{}.{}", name, SERVICE_TYPE);
+    let properties = [("version", env!("CARGO_PKG_VERSION"))];
+    let registered = ServiceInfo::new(
+        SERVICE_TYPE,
+        &name,
+        &format!("{}.local.", name),
+        (),
+        ctx.config.port,
+        &properties[..],
+    )
+    .map_err(|e| e.to_string())
+    .and_then(|service| {
+        daemon
+            .register(service.enable_addr_auto())
+            .map_err(|e| e.to_string())
+    });
+    match registered {
+        Ok(()) => info!("Announcing this instance on the LAN as {}", name),
+        Err(e) => warn!("Failed to announce this instance over mDNS: {}", e),
+    }
+
+    let receiver = match daemon.browse(SERVICE_TYPE) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            warn!("Failed to browse for agents over mDNS: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        // The daemon runs until it is shut down; keep its handle with the task
+        let _daemon = daemon;
+        while let Ok(event) = receiver.recv_async().await {
+            match event {
+                ServiceEvent::ServiceResolved(service) => {
+                    if service.get_fullname() == own_fullname {
+                        continue;
+                    }
+                    if let Some(agent) = discovered_agent(&service, Utc::now().timestamp()) {
+                        debug!("Discovered agent {} at {}", agent.name, agent.url);
+                        DISCOVERED
+                            .lock()
+                            .unwrap()
+                            .insert(service.get_fullname().to_string(), agent);
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    DISCOVERED.lock().unwrap().remove(&fullname);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+/// Instances currently announced on the LAN, sorted by name
+pub fn discovered_agents() -> Vec<DiscoveredAgent> {
+    let mut agents: Vec<DiscoveredAgent> = DISCOVERED.lock().unwrap().values().cloned().collect();
+    agents.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.url.cmp(&b.url)));
+    agents
+}
+
+/// The machine's host name as an mDNS label
+fn instance_name() -> String {
+    let hostname = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .unwrap_or_default();
+    let label: String = hostname
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    if label.is_empty() {
+        DEFAULT_INSTANCE_NAME.to_string()
+    } else {
+        label
+    }
+}
+
+/// The instance a resolved service describes, reached over IPv4 when it can be
+fn discovered_agent(service: &ServiceInfo, now: i64) -> Option<DiscoveredAgent> {
+    let address = service
+        .get_addresses()
+        .iter()
+        .filter(|ip| !ip.is_loopback())
+        .min_by_key(|ip| (ip.is_ipv6(), **ip))?;
+    let endpoint = match address {
+        IpAddr::V4(ip) => format!("{}:{}", ip, service.get_port()),
+        IpAddr::V6(ip) => format!("[{}]:{}", ip, service.get_port()),
+    };
+    let name = service
+        .get_fullname()
+        .strip_suffix(SERVICE_TYPE)
+        .map(|name| name.trim_end_matches('.'))
+        .unwrap_or(service.get_fullname())
+        .to_string();
+
+    Some(DiscoveredAgent {
+        name,
+        url: format!("http://{}", endpoint),
+        endpoint,
+        version: service.get_property_val_str("version").map(str::to_string),
+        last_seen: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovered_agent() {
+        let properties = [("version", "1.5.1")];
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            "nas",
+            "nas.local.",
+            "fe80::1,192.168.1.20,127.0.0.1",
+            5001,
+            &properties[..],
+        )
+        .unwrap();
+        let agent = discovered_agent(&service, 100).unwrap();
+        assert_eq!(agent.name, "nas");
+        assert_eq!(agent.url, "http://192.168.1.20:5001");
+        assert_eq!(agent.endpoint, "192.168.1.20:5001");
+        assert_eq!(agent.version.as_deref(), Some("1.5.1"));
+
+        let service =
+            ServiceInfo::new(SERVICE_TYPE, "pi", "pi.local.", "fe80::1", 5001, None).unwrap();
+        let agent = discovered_agent(&service, 100).unwrap();
+        assert_eq!(agent.url, "http://[fe80::1]:5001");
+        assert_eq!(agent.version, None);
+
+        let service =
+            ServiceInfo::new(SERVICE_TYPE, "lo", "lo.local.", "127.0.0.1", 5001, None).unwrap();
+        assert_eq!(discovered_agent(&service, 100), None);
+    }
+}
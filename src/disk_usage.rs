@@ -0,0 +1,301 @@
+// Docker disk usage
+//
+// What `docker system df` reports, per category: how many objects there are,
+// how many are in use, the space they take and how much of it a prune would
+// reclaim. Each category can be pruned; the prune runs the docker CLI in the
+// prune terminal, so its output streams to the client like a compose command.
+//
+// What a prune removes, and so what counts as reclaimable:
+//
+// - images: every image no container uses (they can be pulled again)
+// - containers: every stopped container
+// - volumes: unused anonymous volumes only; named volumes hold data and are
+//   never pruned
+// - buildCache: every build cache record not in use
+
+use crate::docker::{BollardResultExt, DockerHandle};
+use crate::terminal::Terminal;
+use crate::utils::terminal::get_prune_terminal_name;
+use anyhow::{bail, Context, Result};
+use bollard::models::SystemDataUsageResponse;
+use serde::{Deserialize, Serialize};
+use socketioxide::extract::SocketRef;
+use tracing::info;
+
+/// Label Docker puts on volumes created without a name
+const ANONYMOUS_VOLUME_LABEL: &str = "com.docker.volume.anonymous";
+
+/// Usage of one category
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CategoryUsage {
+    pub count: u64,
+    /// Objects in use, which a prune keeps
+    pub active: u64,
+    /// Bytes
+    pub size: u64,
+    /// Bytes a prune would free
+    pub reclaimable: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DiskUsage {
+    pub images: CategoryUsage,
+    pub containers: CategoryUsage,
+    pub volumes: CategoryUsage,
+    #[serde(rename = "buildCache")]
+    pub build_cache: CategoryUsage,
+}
+
+/// What a prune removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PruneCategory {
+    #[serde(rename = "images")]
+    Images,
+    #[serde(rename = "containers")]
+    Containers,
+    #[serde(rename = "volumes")]
+    Volumes,
+    #[serde(rename = "buildCache")]
+    BuildCache,
+}
+
+impl PruneCategory {
+    /// Arguments of the docker CLI pruning this category
+    fn cli_args(self) -> Vec<String> {
+        let args: &[&str] = match self {
+            PruneCategory::Images => &["image", "prune", "--all", "--force"],
+            PruneCategory::Containers => &["container", "prune", "--force"],
+            PruneCategory::Volumes => &["volume", "prune", "--force"],
+            PruneCategory::BuildCache => &["builder", "prune", "--all", "--force"],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+/// Sizes Docker doesn't know are -1
+fn known_size(size: Option<i64>) -> u64 {
+    size.unwrap_or(0).max(0) as u64
+}
+
+impl From<SystemDataUsageResponse> for DiskUsage {
+    fn from(df: SystemDataUsageResponse) -> Self {
+        let mut usage = DiskUsage::default();
+
+        for image in df.images.unwrap_or_default() {
+            let size = known_size(Some(image.size));
+            usage.images.count += 1;
+            usage.images.size += size;
+            if image.containers > 0 {
+                usage.images.active += 1;
+            } else {
+                // Layers shared with other images stay
+                usage.images.reclaimable +=
+                    size.saturating_sub(known_size(Some(image.shared_size)));
+            }
+        }
+        // Shared layers are counted once in the total
+        if let Some(layers_size) = df.layers_size {
+            usage.images.size = known_size(Some(layers_size));
+        }
+
+        for container in df.containers.unwrap_or_default() {
+            let size = known_size(container.size_rw);
+            usage.containers.count += 1;
+            usage.containers.size += size;
+            if container.state.as_deref() == Some("running") {
+                usage.containers.active += 1;
+            } else {
+                usage.containers.reclaimable += size;
+            }
+        }
+
+        for volume in df.volumes.unwrap_or_default() {
+            let (size, ref_count) = volume
+                .usage_data
+                .map(|u| (known_size(Some(u.size)), u.ref_count))
+                .unwrap_or_default();
+            usage.volumes.count += 1;
+            usage.volumes.size += size;
+            if ref_count > 0 {
+                usage.volumes.active += 1;
+            } else if volume.labels.contains_key(ANONYMOUS_VOLUME_LABEL) {
+                usage.volumes.reclaimable += size;
+            }
+        }
+
+        for record in df.build_cache.unwrap_or_default() {
+            let size = known_size(record.size);
+            usage.build_cache.count += 1;
+            usage.build_cache.size += size;
+            if record.in_use.unwrap_or(false) {
+                usage.build_cache.active += 1;
+            } else if !record.shared.unwrap_or(false) {
+                usage.build_cache.reclaimable += size;
+            }
+        }
+
+        usage
+    }
+}
+
+/// Current disk usage of the local Docker daemon
+pub async fn disk_usage(docker: &DockerHandle) -> Result<DiskUsage> {
+    let df = docker
+        .run(|d| async move { d.df().await })
+        .await
+        .docker_context("Failed to get Docker disk usage")?;
+    Ok(df.into())
+}
+
+/// Prune a category, streaming the output to the prune terminal `socket` joins
+pub async fn prune(
+    io: socketioxide::SocketIo,
+    socket: Option<SocketRef>,
+    endpoint: &str,
+    category: PruneCategory,
+    cwd: String,
+) -> Result<()> {
+    info!("Pruning Docker {:?}", category);
+    let exit_code = Terminal::exec(
+        io,
+        socket,
+        get_prune_terminal_name(endpoint),
+        "docker".to_string(),
+        category.cli_args(),
+        cwd,
+    )
+    .await
+    .context("Failed to run docker prune")?;
+
+    if exit_code != 0 {
+        bail!(
+            "Prune failed with exit code {}, please check the terminal output for more information.",
+            exit_code
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{BuildCache, ContainerSummary, ImageSummary, Volume, VolumeUsageData};
+    use std::collections::HashMap;
+
+    fn image(size: i64, shared_size: i64, containers: i64) -> ImageSummary {
+        ImageSummary {
+            size,
+            shared_size,
+            containers,
+            ..Default::default()
+        }
+    }
+
+    fn volume(size: i64, ref_count: i64, anonymous: bool) -> Volume {
+        let mut labels = HashMap::new();
+        if anonymous {
+            labels.insert(ANONYMOUS_VOLUME_LABEL.to_string(), String::new());
+        }
+        Volume {
+            labels,
+            usage_data: Some(VolumeUsageData { size, ref_count }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_disk_usage_summary() {
+        let df = SystemDataUsageResponse {
+            layers_size: Some(900),
+            images: Some(vec![
+                image(500, 100, 1),
+                image(300, 100, 0),
+                image(200, -1, 0),
+            ]),
+            containers: Some(vec![
+                ContainerSummary {
+                    size_rw: Some(10),
+                    state: Some("running".to_string()),
+                    ..Default::default()
+                },
+                ContainerSummary {
+                    size_rw: Some(20),
+                    state: Some("exited".to_string()),
+                    ..Default::default()
+                },
+            ]),
+            volumes: Some(vec![
+                volume(1000, 1, false),
+                volume(2000, 0, false),
+                volume(300, 0, true),
+                volume(-1, 0, true),
+            ]),
+            build_cache: Some(vec![
+                BuildCache {
+                    size: Some(50),
+                    in_use: Some(true),
+                    ..Default::default()
+                },
+                BuildCache {
+                    size: Some(70),
+                    shared: Some(true),
+                    ..Default::default()
+                },
+                BuildCache {
+                    size: Some(30),
+                    ..Default::default()
+                },
+            ]),
+        };
+
+        let usage = DiskUsage::from(df);
+        assert_eq!(
+            usage.images,
+            CategoryUsage {
+                count: 3,
+                active: 1,
+                size: 900,
+                reclaimable: 400,
+            }
+        );
+        assert_eq!(
+            usage.containers,
+            CategoryUsage {
+                count: 2,
+                active: 1,
+                size: 30,
+                reclaimable: 20,
+            }
+        );
+        // Named volumes aren't pruned, and unknown sizes count as 0
+        assert_eq!(
+            usage.volumes,
+            CategoryUsage {
+                count: 4,
+                active: 1,
+                size: 3300,
+                reclaimable: 300,
+            }
+        );
+        assert_eq!(
+            usage.build_cache,
+            CategoryUsage {
+                count: 3,
+                active: 1,
+                size: 150,
+                reclaimable: 30,
+            }
+        );
+    }
+
+    #[test]
+    fn test_prune_category() {
+        let category: PruneCategory = serde_json::from_str("\"buildCache\"").unwrap();
+        assert_eq!(category, PruneCategory::BuildCache);
+        assert!(serde_json::from_str::<PruneCategory>("\"system\"").is_err());
+        assert_eq!(
+            PruneCategory::Volumes.cli_args(),
+            vec!["volume", "prune", "--force"]
+        );
+    }
+}
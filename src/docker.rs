@@ -24,7 +24,7 @@
 //! ## Usage
 //!
 //! Stack operations call functions in this module passing:
-//! - `docker: &Docker` - Bollard client from ServerContext
+//! - `docker: &DockerHandle` - Reconnect-aware Bollard client from ServerContext
 //! - `stack_name: &str` - Compose project name
 //! - `stack_path: &Path` - Path to compose directory
 //! - `endpoint: &str` - Agent endpoint (or empty for local)
@@ -34,22 +34,40 @@
 //! on compose file management and high-level orchestration logic.
 
 use anyhow::{Context, Result};
-use bollard::container::ListContainersOptions;
+use bollard::container::{
+    InspectContainerOptions, ListContainersOptions, RestartContainerOptions, UpdateContainerOptions,
+};
 use bollard::errors::Error as BollardError;
-use bollard::models::ContainerSummary;
+use bollard::image::RemoveImageOptions;
+use bollard::models::{ContainerSummary, HealthStatusEnum, SystemInfo};
 use bollard::network::ListNetworksOptions;
 use bollard::Docker;
-use serde::Deserialize;
+use dockru_protocol::events::server as server_event;
+use serde::{Deserialize, Serialize};
 use socketioxide::extract::SocketRef;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tracing::{debug, info, warn};
 
-use crate::terminal::Terminal;
+use crate::docker_host::{self, DockerConnector};
+use crate::terminal::{CommandEnv, OutputFilter, Terminal, TerminalEnv};
 use crate::utils::constants::{
-    COMBINED_TERMINAL_COLS, COMBINED_TERMINAL_ROWS, CREATED_STACK, EXITED, RUNNING, TERMINAL_ROWS,
-    UNKNOWN,
+    ACCEPTED_COMPOSE_FILE_NAMES, ACCEPTED_COMPOSE_OVERRIDE_FILE_NAMES, COMBINED_TERMINAL_COLS,
+    COMBINED_TERMINAL_ROWS, COMPOSE_FILE_LIST_NAME, DOCKER_CONTEXT_FILE_NAME,
+    PULL_PROGRESS_EMIT_MS, TERMINAL_ROWS,
 };
+use crate::utils::deploy_hooks::HookStage;
+use crate::utils::docker::CommandTrace;
+use crate::utils::log_timestamps::{LogOptions, TimestampRewriter};
+use crate::utils::pull_progress::PullProgressParser;
+use crate::utils::restart_policy::RestartPolicy;
+use crate::utils::secrets::{env_file_has_references, EMPTY_ENV_FILE};
 use crate::utils::terminal::{
     get_combined_terminal_name, get_compose_terminal_name, get_container_exec_terminal_name,
     get_container_logs_terminal_name,
@@ -80,10 +98,151 @@ impl<T> BollardResultExt<T> for Result<T, BollardError> {
     }
 }
 
+/// Reconnect-aware handle to the Docker daemon
+///
+/// Bollard keeps a pooled connection to the daemon socket, which goes stale when
+/// dockerd restarts. The handle swaps in a fresh client when a call fails with a
+/// connection-level error and tracks daemon health for the watchdog.
+#[derive(Clone)]
+pub struct DockerHandle {
+    client: Arc<std::sync::RwLock<Docker>>,
+    connector: Arc<DockerConnector>,
+    healthy: Arc<AtomicBool>,
+    reconnect_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Handles of the docker contexts stacks are deployed to, by name
+    contexts: Arc<tokio::sync::Mutex<HashMap<String, DockerHandle>>>,
+}
+
+impl DockerHandle {
+    /// Connect to the daemon of the configured docker host
+    pub async fn connect(connector: DockerConnector) -> Result<Self> {
+        let docker = connector.connect().await.with_context(|| {
+            format!(
+                "Failed to connect to Docker daemon ({})",
+                connector.host().describe()
+            )
+        })?;
+        Ok(Self {
+            client: Arc::new(std::sync::RwLock::new(docker)),
+            connector: Arc::new(connector),
+            healthy: Arc::new(AtomicBool::new(true)),
+            reconnect_lock: Arc::new(tokio::sync::Mutex::new(())),
+            contexts: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// The handle of a docker context, this handle for None
+    ///
+    /// Context handles are connected on first use and kept.
+    pub async fn for_context(&self, context: Option<&str>) -> Result<DockerHandle> {
+        let Some(name) = context else {
+            return Ok(self.clone());
+        };
+        let mut contexts = self.contexts.lock().await;
+        if let Some(handle) = contexts.get(name) {
+            return Ok(handle.clone());
+        }
+        let connector = self.connector.for_context(name).await?;
+        let handle = Box::pin(DockerHandle::connect(connector)).await?;
+        info!("Connected to docker context {}", name);
+        contexts.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Get the current Bollard client (cheap clone)
+    pub fn client(&self) -> Docker {
+        self.client.read().unwrap().clone()
+    }
+
+    /// Whether the last interaction with the daemon succeeded
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Replace the client with a freshly connected one
+    pub async fn reconnect(&self) -> Result<()> {
+        let _guard = self.reconnect_lock.lock().await;
+        let docker = self
+            .connector
+            .connect()
+            .await
+            .context("Failed to reconnect to Docker daemon")?;
+        docker
+            .ping()
+            .await
+            .docker_context("Docker daemon did not respond after reconnect")?;
+        *self.client.write().unwrap() = docker;
+        self.set_healthy(true);
+        Ok(())
+    }
+
+    /// Ping the daemon, reconnecting once if it is unreachable
+    ///
+    /// Returns the resulting health, which is also stored for [`Self::is_healthy`].
+    pub async fn check_health(&self) -> bool {
+        if self.client().ping().await.is_ok() {
+            self.set_healthy(true);
+            return true;
+        }
+
+        match self.reconnect().await {
+            Ok(()) => true,
+            Err(e) => {
+                self.set_healthy(false);
+                debug!("Docker reconnect failed: {:#}", e);
+                false
+            }
+        }
+    }
+
+    /// Run a Bollard call, reconnecting and retrying once on connection errors
+    pub async fn run<T, F, Fut>(&self, f: F) -> Result<T, BollardError>
+    where
+        F: Fn(Docker) -> Fut,
+        Fut: Future<Output = Result<T, BollardError>>,
+    {
+        match f(self.client()).await {
+            Err(e) if is_connection_error(&e) => {
+                warn!("Docker connection error ({}), reconnecting", e);
+                if let Err(re) = self.reconnect().await {
+                    self.set_healthy(false);
+                    debug!("Docker reconnect failed: {:#}", re);
+                    return Err(e);
+                }
+                f(self.client()).await
+            }
+            result => result,
+        }
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        let was = self.healthy.swap(healthy, Ordering::Relaxed);
+        if was != healthy {
+            if healthy {
+                info!("Docker daemon connection restored");
+            } else {
+                warn!("Docker daemon is unreachable");
+            }
+        }
+    }
+}
+
+/// Whether a Bollard error means the daemon connection itself is broken
+fn is_connection_error(e: &BollardError) -> bool {
+    matches!(
+        e,
+        BollardError::IOError { .. }
+            | BollardError::HyperResponseError { .. }
+            | BollardError::HyperLegacyError { .. }
+            | BollardError::RequestTimeoutError
+            | BollardError::SocketNotFoundError(_)
+    )
+}
+
 /// List Docker networks
-pub async fn list_networks(docker: &Docker) -> Result<Vec<String>> {
+pub async fn list_networks(docker: &DockerHandle) -> Result<Vec<String>> {
     let networks = docker
-        .list_networks(None::<ListNetworksOptions<String>>)
+        .run(|d| async move { d.list_networks(None::<ListNetworksOptions<String>>).await })
         .await
         .docker_context("Failed to list Docker networks")?;
 
@@ -94,7 +253,7 @@ pub async fn list_networks(docker: &Docker) -> Result<Vec<String>> {
 
 /// List containers for a Docker Compose project
 pub async fn list_containers_by_project(
-    docker: &Docker,
+    docker: &DockerHandle,
     project_name: &str,
 ) -> Result<Vec<ContainerSummary>> {
     let mut filters = HashMap::new();
@@ -110,7 +269,10 @@ pub async fn list_containers_by_project(
     };
 
     docker
-        .list_containers(Some(options))
+        .run(|d| {
+            let options = options.clone();
+            async move { d.list_containers(Some(options)).await }
+        })
         .await
         .docker_context(&format!(
             "Failed to list containers for project {}",
@@ -118,6 +280,333 @@ pub async fn list_containers_by_project(
         ))
 }
 
+/// Names of the host's container runtimes that provide GPUs (see utils::gpu)
+pub async fn gpu_runtimes(docker: &DockerHandle) -> Result<Vec<String>> {
+    let info = docker
+        .run(|d| async move { d.info().await })
+        .await
+        .docker_context("Failed to get Docker info")?;
+    let mut runtimes: Vec<String> = info
+        .runtimes
+        .unwrap_or_default()
+        .into_keys()
+        .filter(|name| crate::utils::gpu::is_gpu_runtime(name))
+        .collect();
+    runtimes.sort();
+    Ok(runtimes)
+}
+
+/// What getDockerInfo reports about the daemon
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DockerInfo {
+    #[serde(rename = "serverVersion")]
+    pub server_version: Option<String>,
+    #[serde(rename = "storageDriver")]
+    pub storage_driver: Option<String>,
+    /// "1" or "2"
+    #[serde(rename = "cgroupVersion")]
+    pub cgroup_version: Option<String>,
+    #[serde(rename = "cgroupDriver")]
+    pub cgroup_driver: Option<String>,
+    pub containers: i64,
+    #[serde(rename = "containersRunning")]
+    pub containers_running: i64,
+    #[serde(rename = "containersPaused")]
+    pub containers_paused: i64,
+    #[serde(rename = "containersStopped")]
+    pub containers_stopped: i64,
+    pub images: i64,
+    #[serde(rename = "operatingSystem")]
+    pub operating_system: Option<String>,
+    #[serde(rename = "kernelVersion")]
+    pub kernel_version: Option<String>,
+    pub architecture: Option<String>,
+    pub cpus: i64,
+    #[serde(rename = "memoryBytes")]
+    pub memory_bytes: i64,
+}
+
+impl DockerInfo {
+    fn from_system_info(info: SystemInfo) -> Self {
+        // The daemon reports "" for unknown enum values
+        let non_empty = |s: String| Some(s).filter(|s| !s.is_empty());
+        Self {
+            server_version: info.server_version,
+            storage_driver: info.driver,
+            cgroup_version: info.cgroup_version.and_then(|v| non_empty(v.to_string())),
+            cgroup_driver: info.cgroup_driver.and_then(|d| non_empty(d.to_string())),
+            containers: info.containers.unwrap_or_default(),
+            containers_running: info.containers_running.unwrap_or_default(),
+            containers_paused: info.containers_paused.unwrap_or_default(),
+            containers_stopped: info.containers_stopped.unwrap_or_default(),
+            images: info.images.unwrap_or_default(),
+            operating_system: info.operating_system,
+            kernel_version: info.kernel_version,
+            architecture: info.architecture,
+            cpus: info.ncpu.unwrap_or_default(),
+            memory_bytes: info.mem_total.unwrap_or_default(),
+        }
+    }
+}
+
+/// Engine details of the Docker daemon, like `docker info`
+pub async fn docker_info(docker: &DockerHandle) -> Result<DockerInfo> {
+    let info = docker
+        .run(|d| async move { d.info().await })
+        .await
+        .docker_context("Failed to get Docker info")?;
+    Ok(DockerInfo::from_system_info(info))
+}
+
+/// Set the restart policy of every container of a compose project, like
+/// `docker update --restart`, returning how many were updated
+pub async fn update_restart_policy(
+    docker: &DockerHandle,
+    project_name: &str,
+    policy: RestartPolicy,
+) -> Result<usize> {
+    let containers = list_containers_by_project(docker, project_name).await?;
+    let mut updated = 0;
+    for id in containers.into_iter().filter_map(|c| c.id) {
+        let options = UpdateContainerOptions::<String> {
+            restart_policy: Some(bollard::models::RestartPolicy {
+                name: Some(policy.docker_name()),
+                maximum_retry_count: None,
+            }),
+            ..Default::default()
+        };
+        docker
+            .run(|d| {
+                let id = id.clone();
+                let options = options.clone();
+                async move { d.update_container(&id, options).await }
+            })
+            .await
+            .docker_context(&format!("Failed to update container {}", id))?;
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+/// Restart one container of a compose project, e.g. a bad replica of a
+/// scaled service, returning its name
+///
+/// `container` is a container ID, a prefix of at least 12 characters, or a
+/// container name. Containers of other projects are not found.
+pub async fn restart_container(
+    docker: &DockerHandle,
+    project_name: &str,
+    container: &str,
+) -> Result<String> {
+    let (id, name) = project_container(docker, project_name, container).await?;
+
+    docker
+        .run(|d| {
+            let id = id.clone();
+            async move {
+                d.restart_container(&id, None::<RestartContainerOptions>)
+                    .await
+            }
+        })
+        .await
+        .docker_context(&format!("Failed to restart container {}", name))?;
+    Ok(name)
+}
+
+/// ID and name of a compose project's container
+///
+/// `container` is matched as by `restart_container`.
+pub async fn project_container(
+    docker: &DockerHandle,
+    project_name: &str,
+    container: &str,
+) -> Result<(String, String)> {
+    let containers = list_containers_by_project(docker, project_name).await?;
+    find_container(&containers, container).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Container {} not found in stack {}",
+            container,
+            project_name
+        )
+    })
+}
+
+/// ID and name of the container `container` refers to (see `restart_container`)
+fn find_container(containers: &[ContainerSummary], container: &str) -> Option<(String, String)> {
+    containers.iter().find_map(|c| {
+        let id = c.id.as_deref()?;
+        let name = c
+            .names
+            .as_ref()
+            .and_then(|names| names.first())
+            .map(|name| name.trim_start_matches('/'))
+            .unwrap_or(id);
+        let matches = id == container
+            || (container.len() >= 12 && id.starts_with(container))
+            || name == container;
+        matches.then(|| (id.to_string(), name.to_string()))
+    })
+}
+
+/// IDs of the images used by a compose project's containers
+pub async fn project_image_ids(
+    docker: &DockerHandle,
+    project_name: &str,
+) -> Result<HashSet<String>> {
+    let containers = list_containers_by_project(docker, project_name).await?;
+    Ok(containers.into_iter().filter_map(|c| c.image_id).collect())
+}
+
+/// Remove the images a project used before an update that are now dangling
+///
+/// Only images that none of the project's containers use anymore and that
+/// have no tags left are removed, so images shared with other stacks or still
+/// tagged are kept. Returns the IDs of the removed images.
+pub async fn prune_superseded_images(
+    docker: &DockerHandle,
+    project_name: &str,
+    old_image_ids: &HashSet<String>,
+) -> Result<Vec<String>> {
+    let current = project_image_ids(docker, project_name).await?;
+    let mut removed = Vec::new();
+
+    for id in superseded_images(old_image_ids, &current) {
+        let inspect = match docker
+            .run(|d| {
+                let id = id.clone();
+                async move { d.inspect_image(&id).await }
+            })
+            .await
+        {
+            Ok(inspect) => inspect,
+            Err(e) => {
+                debug!("Skipping prune of image {}: {}", id, e);
+                continue;
+            }
+        };
+        if !is_dangling(&inspect.repo_tags.unwrap_or_default()) {
+            continue;
+        }
+
+        let options = RemoveImageOptions {
+            force: false,
+            noprune: false,
+        };
+        match docker
+            .run(|d| {
+                let id = id.clone();
+                async move { d.remove_image(&id, Some(options), None).await }
+            })
+            .await
+        {
+            Ok(_) => removed.push(id),
+            // Most likely still used by a container of another project
+            Err(e) => debug!("Failed to remove image {}: {}", id, e),
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Images that were in use before but aren't anymore, sorted
+fn superseded_images(old: &HashSet<String>, current: &HashSet<String>) -> Vec<String> {
+    let mut ids: Vec<String> = old.difference(current).cloned().collect();
+    ids.sort();
+    ids
+}
+
+/// An image is dangling when no tag points to it anymore
+fn is_dangling(repo_tags: &[String]) -> bool {
+    repo_tags.iter().all(|tag| tag == "<none>:<none>")
+}
+
+/// Count the containers of every compose project by state
+///
+/// Keyed by project name; projects without containers are missing.
+pub async fn project_service_counts(
+    docker: &DockerHandle,
+) -> Result<HashMap<String, crate::stack::ServiceCounts>> {
+    compose_service_counts(docker, "com.docker.compose.project".to_string()).await
+}
+
+/// Count the containers of one compose project by state
+///
+/// None when the project has no containers.
+pub async fn service_counts_of(
+    docker: &DockerHandle,
+    project: &str,
+) -> Result<Option<crate::stack::ServiceCounts>> {
+    let label = format!("com.docker.compose.project={}", project);
+    Ok(compose_service_counts(docker, label).await?.remove(project))
+}
+
+async fn compose_service_counts(
+    docker: &DockerHandle,
+    label: String,
+) -> Result<HashMap<String, crate::stack::ServiceCounts>> {
+    let containers = list_containers_with_label(docker, label).await?;
+    Ok(count_project_services(&containers))
+}
+
+/// List the containers of every compose project
+pub async fn list_compose_containers(docker: &DockerHandle) -> Result<Vec<ContainerSummary>> {
+    list_containers_with_label(docker, "com.docker.compose.project".to_string()).await
+}
+
+async fn list_containers_with_label(
+    docker: &DockerHandle,
+    label: String,
+) -> Result<Vec<ContainerSummary>> {
+    let mut filters = HashMap::new();
+    filters.insert("label".to_string(), vec![label]);
+
+    let options = ListContainersOptions {
+        all: true,
+        filters,
+        ..Default::default()
+    };
+
+    docker
+        .run(|d| {
+            let options = options.clone();
+            async move { d.list_containers(Some(options)).await }
+        })
+        .await
+        .docker_context("Failed to list compose containers")
+}
+
+fn count_project_services(
+    containers: &[ContainerSummary],
+) -> HashMap<String, crate::stack::ServiceCounts> {
+    let mut counts: HashMap<String, crate::stack::ServiceCounts> = HashMap::new();
+    for container in containers {
+        let Some(project) = container
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get("com.docker.compose.project"))
+        else {
+            continue;
+        };
+        let state = container.state.as_deref().unwrap_or("unknown");
+        let health = container.status.as_deref().and_then(parse_health);
+        counts
+            .entry(project.clone())
+            .or_default()
+            .add(state, health);
+    }
+    counts
+}
+
+/// Health from a container's verbose status string, e.g. "Up 2 hours (healthy)"
+/// or "Up 5 seconds (health: starting)"
+fn parse_health(status: &str) -> Option<&str> {
+    let start = status.find('(')?;
+    let end = status.find(')')?;
+    let inner = status.get(start + 1..end)?;
+    let inner = inner.strip_prefix("health: ").unwrap_or(inner);
+    matches!(inner, "healthy" | "unhealthy" | "starting").then_some(inner)
+}
+
 /// Map container summary to ServiceStatus
 pub fn map_to_service_status(
     containers: Vec<ContainerSummary>,
@@ -136,13 +625,11 @@ pub fn map_to_service_status(
             // Use clean state ("running", "exited", etc.)
             let state = container.state.clone().unwrap_or_else(|| "unknown".to_string());
 
-            // Extract health from the verbose status string, e.g. "Up 2 hours (healthy)"
-            let health = container.status.as_deref().and_then(|s| {
-                let start = s.find('(')?;
-                let end = s.find(')')?;
-                let inner = &s[start + 1..end];
-                matches!(inner, "healthy" | "unhealthy" | "starting").then(|| inner.to_string())
-            });
+            let health = container
+                .status
+                .as_deref()
+                .and_then(parse_health)
+                .map(str::to_string);
 
             // Extract port mappings, deduplicating across IPv4/IPv6 bindings, sorted by public port
             let ports: Vec<String> = {
@@ -161,10 +648,26 @@ pub fn map_to_service_status(
             };
 
             let image = container.image.clone();
+            let container_name = container
+                .names
+                .as_ref()
+                .and_then(|names| names.first())
+                .map(|name| name.trim_start_matches('/').to_string());
 
             status_map.insert(
                 service,
-                crate::stack::ServiceStatus { state, ports, health, image },
+                crate::stack::ServiceStatus {
+                    state,
+                    ports,
+                    health,
+                    image,
+                    container_name,
+                    container_id: container.id.clone(),
+                    created: container.created,
+                    started_at: None,
+                    finished_at: None,
+                    exit_code: None,
+                },
             );
         }
     }
@@ -172,16 +675,183 @@ pub fn map_to_service_status(
     status_map
 }
 
+/// Fill in details only available from container inspect
+///
+/// Adds start/finish times and exit code, and replaces the health parsed from the
+/// status string with the engine's health status. Containers that fail to inspect
+/// (e.g. removed in the meantime) keep their summary data.
+pub async fn enrich_service_status(
+    docker: &DockerHandle,
+    status_map: &mut HashMap<String, crate::stack::ServiceStatus>,
+) {
+    let inspects = futures_util::future::join_all(status_map.values().map(|status| {
+        let id = status.container_id.clone();
+        async move {
+            let id = id?;
+            docker
+                .run(|d| {
+                    let id = id.clone();
+                    async move { d.inspect_container(&id, None::<InspectContainerOptions>).await }
+                })
+                .await
+                .ok()
+        }
+    }))
+    .await;
+
+    for (status, inspect) in status_map.values_mut().zip(inspects) {
+        let Some(container_state) = inspect.and_then(|i| i.state) else {
+            continue;
+        };
+
+        // Docker reports the zero time for containers that never started/stopped
+        let valid_time = |t: Option<String>| t.filter(|t| !t.starts_with("0001-"));
+        status.started_at = valid_time(container_state.started_at);
+        status.finished_at = valid_time(container_state.finished_at);
+        status.exit_code = container_state.exit_code;
+
+        if let Some(health) = container_state.health.and_then(|h| h.status) {
+            status.health = match health {
+                HealthStatusEnum::EMPTY | HealthStatusEnum::NONE => None,
+                other => Some(other.to_string()),
+            };
+        }
+    }
+}
+
 //------------------------------------------------------------------------------
 // Compose Command Building
 //------------------------------------------------------------------------------
 
+/// Resolve the compose files of a stack directory, in the order they are merged
+///
+/// Uses the list in COMPOSE_FILE_LIST_NAME when the stack has one, otherwise the
+/// main compose file followed by its override file if there is one. Returns an
+/// empty list when the directory has no compose file (e.g. unmanaged stacks).
+pub fn resolve_compose_files(stack_path: &Path) -> Vec<String> {
+    if let Ok(list) = std::fs::read_to_string(stack_path.join(COMPOSE_FILE_LIST_NAME)) {
+        let files = parse_compose_file_list(&list);
+        if !files.is_empty() {
+            return files;
+        }
+    }
+
+    let Some(main) = ACCEPTED_COMPOSE_FILE_NAMES
+        .iter()
+        .find(|name| stack_path.join(name).is_file())
+    else {
+        return Vec::new();
+    };
+
+    let mut files = vec![main.to_string()];
+    files.extend(
+        ACCEPTED_COMPOSE_OVERRIDE_FILE_NAMES
+            .iter()
+            .find(|name| stack_path.join(name).is_file())
+            .map(|name| name.to_string()),
+    );
+    files
+}
+
+/// Parse a compose file list, skipping blank lines and `#` comments
+pub fn parse_compose_file_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Check that a compose file name stays inside the stack directory
+pub fn validate_compose_file_name(name: &str) -> Result<()> {
+    let path = Path::new(name);
+    let is_plain = !name.is_empty()
+        && !name.starts_with('-')
+        && path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)));
+    if !is_plain {
+        anyhow::bail!("Invalid compose file name \"{}\"", name);
+    }
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => Ok(()),
+        _ => anyhow::bail!("Compose file \"{}\" must be a .yaml or .yml file", name),
+    }
+}
+
+/// The docker context a stack directory is deployed to, from DOCKER_CONTEXT_FILE_NAME
+pub fn stack_docker_context(stack_path: &Path) -> Option<String> {
+    std::fs::read_to_string(stack_path.join(DOCKER_CONTEXT_FILE_NAME))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Check that a docker context name is one the docker CLI accepts
+pub fn validate_docker_context_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '+' | '-'));
+    if !valid {
+        anyhow::bail!("Invalid docker context name \"{}\"", name);
+    }
+    Ok(())
+}
+
+/// A docker context, as `docker context ls` lists it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DockerContext {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Description", default)]
+    pub description: String,
+    #[serde(rename = "DockerEndpoint", default)]
+    pub docker_endpoint: String,
+    #[serde(rename = "Current", default)]
+    pub current: bool,
+}
+
+/// List the docker contexts of the docker CLI
+pub async fn list_docker_contexts() -> Result<Vec<DockerContext>> {
+    let output = Command::new("docker")
+        .args(["context", "ls", "--format", "json"])
+        .envs(docker_host::cli_env().iter().cloned())
+        .output()
+        .await
+        .context("Failed to run docker context ls")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "docker context ls failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_docker_contexts(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse `docker context ls --format json`, a JSON array or one object per line
+/// depending on the CLI version
+fn parse_docker_contexts(output: &str) -> Result<Vec<DockerContext>> {
+    let output = output.trim();
+    if output.starts_with('[') {
+        return serde_json::from_str(output).context("Invalid docker context ls output");
+    }
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("Invalid docker context ls output"))
+        .collect()
+}
+
 /// Build docker compose command options including env files
 ///
 /// Constructs the complete argument list for docker compose commands:
-/// - Starts with ["compose"]
+/// - Starts with ["--context", name] when the stack has a docker context
+/// - Then ["compose"]
 /// - Adds global.env if it exists in stacks_dir parent
 /// - Adds .env if it exists in stack directory (only if global.env exists)
+/// - Replaces a .env with secret references by an empty env file
+/// - Adds a --file for each of the stack's compose files (see resolve_compose_files)
 /// - Appends the command (up, stop, logs, etc.)
 /// - Extends with extra options
 ///
@@ -196,20 +866,50 @@ pub fn compose_options(
     command: &str,
     extra_options: &[&str],
 ) -> Vec<String> {
-    let mut options = vec!["compose".to_string()];
+    compose_options_with_env_file(stacks_dir, stack_name, command, extra_options, None)
+}
+
+/// Like [`compose_options`], reading `env_file` instead of the stack's .env
+pub fn compose_options_with_env_file(
+    stacks_dir: &Path,
+    stack_name: &str,
+    command: &str,
+    extra_options: &[&str],
+    env_file: Option<&Path>,
+) -> Vec<String> {
+    let mut options = Vec::new();
+    if let Some(context) = stack_docker_context(&stacks_dir.join(stack_name)) {
+        options.push("--context".to_string());
+        options.push(context);
+    }
+    options.push("compose".to_string());
 
     // Check for global.env in stacks_dir
     let global_env_path = stacks_dir.join("global.env");
-    if global_env_path.exists() {
+    let has_global_env = global_env_path.exists();
+    if has_global_env {
         options.push("--env-file".to_string());
         options.push("../global.env".to_string());
+    }
 
+    // An explicit --env-file replaces the default .env lookup
+    let stack_env_path = stacks_dir.join(stack_name).join(".env");
+    let stack_env_file = match env_file {
+        Some(env_file) => Some(env_file.display().to_string()),
+        None if env_file_has_references(&stack_env_path) => Some(EMPTY_ENV_FILE.to_string()),
         // Add per-stack .env if it exists (only if global.env exists)
-        let stack_env_path = stacks_dir.join(stack_name).join(".env");
-        if stack_env_path.exists() {
-            options.push("--env-file".to_string());
-            options.push("./.env".to_string());
-        }
+        None if has_global_env && stack_env_path.exists() => Some("./.env".to_string()),
+        None => None,
+    };
+    if let Some(stack_env_file) = stack_env_file {
+        options.push("--env-file".to_string());
+        options.push(stack_env_file);
+    }
+
+    // Add the compose files, relative to the stack directory (the working directory)
+    for file in resolve_compose_files(&stacks_dir.join(stack_name)) {
+        options.push("--file".to_string());
+        options.push(file);
     }
 
     // Add the command
@@ -221,11 +921,459 @@ pub fn compose_options(
     options
 }
 
+/// When `docker compose up` pulls images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PullPolicy {
+    Always,
+    Missing,
+    Never,
+}
+
+impl PullPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PullPolicy::Always => "always",
+            PullPolicy::Missing => "missing",
+            PullPolicy::Never => "never",
+        }
+    }
+}
+
+/// Options for deploying or starting a stack
+///
+/// The defaults give a plain `up -d --remove-orphans`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeployOptions {
+    /// Recreate containers even if their configuration is unchanged
+    #[serde(rename = "forceRecreate")]
+    pub force_recreate: bool,
+    /// Pull policy, compose's own default when unset
+    pub pull: Option<PullPolicy>,
+    /// Don't build images, even if they are missing
+    #[serde(rename = "noBuild")]
+    pub no_build: bool,
+}
+
+impl DeployOptions {
+    /// Flags for `docker compose up`
+    pub fn up_flags(&self) -> Vec<&'static str> {
+        let mut flags = vec!["-d", "--remove-orphans"];
+        if self.force_recreate {
+            flags.push("--force-recreate");
+        }
+        if let Some(pull) = self.pull {
+            flags.push("--pull");
+            flags.push(pull.as_str());
+        }
+        if self.no_build {
+            flags.push("--no-build");
+        }
+        flags
+    }
+}
+
+/// Environment of a stack's compose commands
+#[derive(Debug, Default)]
+pub struct ComposeEnv {
+    /// Variables passed through the process environment
+    pub vars: Vec<(String, String)>,
+    /// Variables of the dockru process compose doesn't inherit
+    pub removed: Vec<String>,
+    /// Env file read instead of the stack's .env, only given to commands
+    /// that create containers
+    pub env_file: Option<PathBuf>,
+}
+
+impl ComposeEnv {
+    /// The process environment changes, for a terminal running compose
+    pub fn command_env(&self) -> CommandEnv {
+        CommandEnv {
+            vars: self.vars.clone(),
+            removed: self.removed.clone(),
+        }
+    }
+}
+
+/// Who and where `docker compose exec` runs as, the image's defaults when unset
+///
+/// The shell gets the configured TERM and locale (see `TerminalEnv`), with
+/// `term` in place of the TERM.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecOptions {
+    /// User name or UID, optionally with `:group`
+    pub user: Option<String>,
+    /// Absolute working directory inside the container
+    pub workdir: Option<String>,
+    /// TERM of the shell, e.g. "xterm" for images without 256-color terminfo
+    pub term: Option<String>,
+}
+
+impl ExecOptions {
+    /// Check the values before they're passed to docker
+    pub fn validate(&self) -> Result<()> {
+        if let Some(user) = &self.user {
+            let valid = !user.is_empty()
+                && !user.starts_with('-')
+                && user
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | ':'));
+            if !valid {
+                anyhow::bail!("Invalid exec user: {:?}", user);
+            }
+        }
+        if let Some(workdir) = &self.workdir {
+            if !workdir.starts_with('/') || workdir.chars().any(char::is_control) {
+                anyhow::bail!("Exec working directory must be an absolute path");
+            }
+        }
+        if let Some(term) = &self.term {
+            crate::terminal::validate_term(term)?;
+        }
+        Ok(())
+    }
+
+    /// Flags for `docker compose exec`
+    pub fn exec_flags(&self) -> Vec<&str> {
+        let mut flags = Vec::new();
+        if let Some(user) = &self.user {
+            flags.push("--user");
+            flags.push(user.as_str());
+        }
+        if let Some(workdir) = &self.workdir {
+            flags.push("--workdir");
+            flags.push(workdir.as_str());
+        }
+        flags
+    }
+
+    /// `-e` flags with the shell's TERM and locale, which docker would
+    /// otherwise set to TERM=xterm and nothing
+    pub fn env_flags(&self, terminal_env: &TerminalEnv) -> Vec<String> {
+        terminal_env
+            .vars(self.term.as_deref())
+            .into_iter()
+            .flat_map(|(key, value)| ["-e".to_string(), format!("{}={}", key, value)])
+            .collect()
+    }
+
+    /// Suffix appended to the terminal name, so each variant gets its own terminal
+    pub fn terminal_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if let Some(user) = &self.user {
+            suffix.push_str(&format!("-u-{}", user));
+        }
+        if let Some(workdir) = &self.workdir {
+            suffix.push_str(&format!("-w-{}", workdir));
+        }
+        if let Some(term) = &self.term {
+            suffix.push_str(&format!("-t-{}", term));
+        }
+        suffix
+    }
+}
+
 //------------------------------------------------------------------------------
 // Compose Orchestration
 //------------------------------------------------------------------------------
 
-/// Deploy a compose stack (up -d --remove-orphans)
+/// A docker compose subcommand run on a stack, with its flags
+///
+/// Every compose operation on a stack is built here, so a flag only has to be
+/// added in one place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ComposeCommand<'a> {
+    subcommand: &'static str,
+    args: Vec<&'a str>,
+    /// Completes "Failed to ..." when compose exits non-zero
+    failure: &'static str,
+}
+
+impl<'a> ComposeCommand<'a> {
+    fn new(subcommand: &'static str, args: Vec<&'a str>, failure: &'static str) -> Self {
+        Self {
+            subcommand,
+            args,
+            failure,
+        }
+    }
+
+    /// `up -d --remove-orphans`, plus the deploy options
+    fn up(options: &DeployOptions) -> Self {
+        Self::new("up", options.up_flags(), "deploy")
+    }
+
+    fn build(no_cache: bool) -> Self {
+        let args = if no_cache {
+            vec!["--no-cache"]
+        } else {
+            Vec::new()
+        };
+        Self::new("build", args, "build")
+    }
+
+    fn pull() -> Self {
+        Self::new("pull", Vec::new(), "pull")
+    }
+
+    fn stop() -> Self {
+        Self::new("stop", Vec::new(), "stop")
+    }
+
+    fn restart() -> Self {
+        Self::new("restart", Vec::new(), "restart")
+    }
+
+    /// `down --remove-orphans`, also used to delete a stack
+    fn down() -> Self {
+        Self::new("down", vec!["--remove-orphans"], "shut down")
+    }
+
+    /// `down`, reported as a failed delete
+    fn delete() -> Self {
+        Self {
+            failure: "delete",
+            ..Self::down()
+        }
+    }
+
+    fn start_service(service_name: &'a str) -> Self {
+        Self::new("start", vec![service_name], "start service")
+    }
+
+    fn stop_service(service_name: &'a str) -> Self {
+        Self::new("stop", vec![service_name], "stop service")
+    }
+
+    fn restart_service(service_name: &'a str) -> Self {
+        Self::new("restart", vec![service_name], "restart service")
+    }
+
+    fn pull_service(service_name: &'a str) -> Self {
+        Self::new("pull", vec![service_name], "pull service image")
+    }
+
+    /// `logs --no-color`, plus the logs options
+    fn logs(options: &'a ComposeLogs) -> Self {
+        Self::new("logs", options.flags(), "read logs")
+    }
+
+    /// Full `docker` arguments for the stack
+    fn options(&self, stacks_dir: &Path, stack_name: &str, env_file: Option<&Path>) -> Vec<String> {
+        compose_options_with_env_file(
+            stacks_dir,
+            stack_name,
+            self.subcommand,
+            &self.args,
+            env_file,
+        )
+    }
+}
+
+/// Options of `docker compose logs`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComposeLogs {
+    /// Only this service's logs
+    pub service: Option<String>,
+    /// Number of lines from the end, all lines when absent
+    pub tail: Option<String>,
+    pub timestamps: bool,
+    /// Keep streaming new output
+    pub follow: bool,
+}
+
+impl ComposeLogs {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(service) = &self.service {
+            if service.is_empty() || service.starts_with('-') {
+                anyhow::bail!("Invalid service name");
+            }
+        }
+        Ok(())
+    }
+
+    /// Flags for `docker compose logs`
+    fn flags(&self) -> Vec<&str> {
+        let mut flags = vec!["--no-color"];
+        if self.follow {
+            flags.push("--follow");
+        }
+        if self.timestamps {
+            flags.push("--timestamps");
+        }
+        if let Some(tail) = &self.tail {
+            flags.push("--tail");
+            flags.push(tail);
+        }
+        if let Some(service) = &self.service {
+            flags.push(service);
+        }
+        flags
+    }
+}
+
+/// Start `docker compose logs` on a stack, with its output piped
+///
+/// Unlike the other compose commands this doesn't run in a terminal: the
+/// caller streams stdout. The process is killed when the child is dropped.
+/// Logs are looked up by project name, so unmanaged stacks, which have no
+/// compose files here, work as well.
+pub fn compose_logs(
+    stack_name: &str,
+    stack_path: &Path,
+    stacks_dir: &Path,
+    options: &ComposeLogs,
+    env: &ComposeEnv,
+) -> Result<tokio::process::Child> {
+    options.validate()?;
+    let args = compose_logs_args(stack_name, stack_path, stacks_dir, options);
+    let cwd = if stack_path.is_dir() {
+        stack_path
+    } else {
+        stacks_dir
+    };
+
+    let mut process = Command::new("docker");
+    for key in &env.removed {
+        process.env_remove(key);
+    }
+    process
+        .args(&args)
+        .envs(docker_host::cli_env().iter().cloned())
+        .envs(env.vars.iter().cloned())
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run docker compose logs")
+}
+
+/// Arguments of `docker compose logs`, with the project name set
+fn compose_logs_args(
+    stack_name: &str,
+    stack_path: &Path,
+    stacks_dir: &Path,
+    options: &ComposeLogs,
+) -> Vec<String> {
+    let command = ComposeCommand::logs(options);
+    if !stack_path.is_dir() {
+        let mut args = vec![
+            "compose".to_string(),
+            "--project-name".to_string(),
+            stack_name.to_string(),
+            command.subcommand.to_string(),
+        ];
+        args.extend(command.args.iter().map(|arg| arg.to_string()));
+        return args;
+    }
+    let mut args = command.options(stacks_dir, stack_name, None);
+    let compose = args.iter().position(|arg| arg == "compose").unwrap_or(0);
+    args.splice(
+        compose + 1..compose + 1,
+        ["--project-name".to_string(), stack_name.to_string()],
+    );
+    args
+}
+
+/// Run a compose command on a stack in its compose terminal
+///
+/// Fails if compose exits non-zero, pointing at the terminal output.
+#[allow(clippy::too_many_arguments)]
+async fn run_compose(
+    io: socketioxide::SocketIo,
+    stack_name: &str,
+    stack_path: &Path,
+    stacks_dir: &Path,
+    endpoint: &str,
+    command: ComposeCommand<'_>,
+    env: &ComposeEnv,
+    socket: Option<SocketRef>,
+) -> Result<i32> {
+    crate::relocation::check_not_relocating()?;
+    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
+    let env_file = env.env_file.as_deref();
+    #[allow(unused_mut)]
+    let (mut file, mut options) = (
+        "docker".to_string(),
+        command.options(stacks_dir, stack_name, env_file),
+    );
+
+    // Fake the exit code in the terminal instead of running compose
+    #[cfg(feature = "chaos")]
+    if let Some(code) = crate::chaos::take_compose_exit() {
+        file = "sh".to_string();
+        options = vec![
+            "-c".to_string(),
+            format!("echo 'chaos: injected exit code {code}'; exit {code}"),
+        ];
+    }
+
+    let output_filter = (command.subcommand == "pull")
+        .then(|| pull_progress_filter(io.clone(), terminal_name.clone(), stack_name));
+    let exit_code = Terminal::exec_with_env(
+        io,
+        socket,
+        terminal_name,
+        file,
+        options,
+        stack_path.display().to_string(),
+        env.command_env(),
+        output_filter,
+    )
+    .await
+    .with_context(|| format!("Failed to execute docker compose {}", command.subcommand))?;
+
+    if exit_code != 0 {
+        anyhow::bail!(
+            "Failed to {}, please check the terminal output for more information.",
+            command.failure
+        );
+    }
+
+    Ok(exit_code)
+}
+
+/// Output filter of `compose pull` that sends its progress as pullProgress
+/// events to the sockets in the terminal, at most every PULL_PROGRESS_EMIT_MS
+fn pull_progress_filter(
+    io: socketioxide::SocketIo,
+    terminal_name: String,
+    stack_name: &str,
+) -> OutputFilter {
+    let stack_name = stack_name.to_string();
+    let mut parser = PullProgressParser::default();
+    let mut last_emit: Option<Instant> = None;
+    let interval = Duration::from_millis(PULL_PROGRESS_EMIT_MS);
+
+    Box::new(move |data| {
+        let due = last_emit.map_or(true, |at| at.elapsed() >= interval);
+        if parser.process(data) && (due || parser.is_done()) {
+            last_emit = Some(Instant::now());
+            let payload = serde_json::json!({
+                "stackName": stack_name,
+                "services": parser.progress().services,
+            });
+            let io = io.clone();
+            let room = terminal_name.clone();
+            tokio::spawn(async move {
+                let _ = io
+                    .to(room)
+                    .emit(
+                        server_event::AGENT,
+                        &(server_event::PULL_PROGRESS, &payload),
+                    )
+                    .await;
+            });
+        }
+        data.to_string()
+    })
+}
+
+/// Deploy a compose stack (up -d --remove-orphans, plus any deploy options)
 ///
 /// # Arguments
 /// * `io` - SocketIo instance for terminal communication
@@ -233,37 +1381,103 @@ pub fn compose_options(
 /// * `stack_path` - Path to the directory containing compose file
 /// * `stacks_dir` - Path to the stacks directory (for env file resolution)
 /// * `endpoint` - Agent endpoint (empty string for local)
+/// * `deploy_options` - Extra `up` flags such as --force-recreate
 /// * `socket` - Optional socket for streaming output
 ///
 /// # Returns
 /// Exit code from docker compose command (0 = success)
+#[allow(clippy::too_many_arguments)]
 pub async fn deploy(
     io: socketioxide::SocketIo,
     stack_name: &str,
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    deploy_options: &DeployOptions,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "up", &["-d", "--remove-orphans"]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::up(deploy_options),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose up")?;
+}
 
-    if exit_code != 0 {
-        anyhow::bail!("Failed to deploy, please check the terminal output for more information.");
+/// Run a stack's deploy hooks for one stage, in the stack's terminal
+///
+/// Commands run with `sh -c` in the stack directory. Stops at the first
+/// command that fails.
+pub async fn run_hooks(
+    io: socketioxide::SocketIo,
+    stack_name: &str,
+    stack_path: &Path,
+    endpoint: &str,
+    stage: HookStage,
+    commands: &[String],
+    socket: Option<SocketRef>,
+) -> Result<()> {
+    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
+
+    for command in commands {
+        info!("Running {} hook for {}: {}", stage.as_str(), stack_name, command);
+
+        let exit_code = Terminal::exec(
+            io.clone(),
+            socket.clone(),
+            terminal_name.clone(),
+            "sh".to_string(),
+            vec!["-c".to_string(), command.clone()],
+            stack_path.display().to_string(),
+        )
+        .await
+        .with_context(|| format!("Failed to run {} hook", stage.as_str()))?;
+
+        if exit_code != 0 {
+            anyhow::bail!(
+                "The {} hook \"{}\" failed with exit code {}, please check the terminal output for more information.",
+                stage.as_str(),
+                command,
+                exit_code
+            );
+        }
     }
 
-    Ok(exit_code)
+    Ok(())
+}
+
+/// Build the images of a compose stack's services that have a build context
+///
+/// # Arguments
+/// * `no_cache` - Build without using the layer cache
+#[allow(clippy::too_many_arguments)]
+pub async fn build(
+    io: socketioxide::SocketIo,
+    stack_name: &str,
+    stack_path: &Path,
+    stacks_dir: &Path,
+    endpoint: &str,
+    no_cache: bool,
+    env: &ComposeEnv,
+    socket: Option<SocketRef>,
+) -> Result<i32> {
+    run_compose(
+        io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::build(no_cache),
+        env,
+        socket,
+    )
+    .await
 }
 
 /// Stop a compose stack
@@ -273,27 +1487,20 @@ pub async fn stop(
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "stop", &[]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::stop(),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose stop")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to stop, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
 /// Restart a compose stack
@@ -303,89 +1510,80 @@ pub async fn restart(
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "restart", &[]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::restart(),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose restart")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to restart, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
-/// Shut down a compose stack (down)
+/// Shut down a compose stack (down --remove-orphans)
 pub async fn down(
     io: socketioxide::SocketIo,
     stack_name: &str,
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "down", &[]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::down(),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose down")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to shut down, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
-/// Update a compose stack (pull + redeploy if running)
+/// Update a compose stack (pull or build + redeploy if running)
+///
+/// With `rebuild`, images are rebuilt from their build contexts instead of
+/// pulled, for stacks whose services aren't published images.
 ///
-/// Returns exit code from final operation (pull or deploy)
+/// Returns exit code from final operation (pull, build or deploy)
+#[allow(clippy::too_many_arguments)]
 pub async fn update(
     io: socketioxide::SocketIo,
-    docker: &Docker,
+    docker: &DockerHandle,
     stack_name: &str,
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    rebuild: bool,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "pull", &[]);
-
-    // Pull latest images
-    let exit_code = Terminal::exec(
+    let command = if rebuild {
+        ComposeCommand::build(false)
+    } else {
+        // Pull latest images
+        ComposeCommand::pull()
+    };
+    let exit_code = run_compose(
         io.clone(),
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        command,
+        env,
         socket.clone(),
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
-    .await
-    .context("Failed to execute docker compose pull")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to pull, please check the terminal output for more information.");
-    }
+    .await?;
 
     // Check if stack is running
     let containers = list_containers_by_project(docker, stack_name)
@@ -398,7 +1596,17 @@ pub async fn update(
 
     // Only restart if it was running
     if is_running {
-        deploy(io, stack_name, stack_path, stacks_dir, endpoint, socket).await
+        deploy(
+            io,
+            stack_name,
+            stack_path,
+            stacks_dir,
+            endpoint,
+            &DeployOptions::default(),
+            env,
+            socket,
+        )
+        .await
     } else {
         Ok(exit_code)
     }
@@ -417,25 +1625,20 @@ pub async fn delete(
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "down", &["--remove-orphans"]);
-
-    let exit_code = Terminal::exec(
+    let exit_code = run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::delete(),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
-    .await
-    .context("Failed to execute docker compose down")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to delete, please check the terminal output for more information.");
-    }
+    .await?;
 
     // Remove the stack directory
     tokio::fs::remove_dir_all(stack_path)
@@ -450,6 +1653,7 @@ pub async fn delete(
 //------------------------------------------------------------------------------
 
 /// Restart a single service in a compose stack
+#[allow(clippy::too_many_arguments)]
 pub async fn restart_service(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -457,30 +1661,24 @@ pub async fn restart_service(
     stacks_dir: &Path,
     endpoint: &str,
     service_name: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "restart", &[service_name]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::restart_service(service_name),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose restart")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to restart service, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
 /// Start a single service in a compose stack
+#[allow(clippy::too_many_arguments)]
 pub async fn start_service(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -488,30 +1686,24 @@ pub async fn start_service(
     stacks_dir: &Path,
     endpoint: &str,
     service_name: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "start", &[service_name]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::start_service(service_name),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose start")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to start service, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
 /// Stop a single service in a compose stack
+#[allow(clippy::too_many_arguments)]
 pub async fn stop_service(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -519,30 +1711,24 @@ pub async fn stop_service(
     stacks_dir: &Path,
     endpoint: &str,
     service_name: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "stop", &[service_name]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::stop_service(service_name),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose stop")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to stop service, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
 /// Pull a new image for a single service in a compose stack
+#[allow(clippy::too_many_arguments)]
 pub async fn pull_service(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -550,27 +1736,20 @@ pub async fn pull_service(
     stacks_dir: &Path,
     endpoint: &str,
     service_name: &str,
+    env: &ComposeEnv,
     socket: Option<SocketRef>,
 ) -> Result<i32> {
-    let terminal_name = get_compose_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "pull", &[service_name]);
-
-    let exit_code = Terminal::exec(
+    run_compose(
         io,
+        stack_name,
+        stack_path,
+        stacks_dir,
+        endpoint,
+        ComposeCommand::pull_service(service_name),
+        env,
         socket,
-        terminal_name,
-        "docker".to_string(),
-        options,
-        stack_path.display().to_string(),
     )
     .await
-    .context("Failed to execute docker compose pull")?;
-
-    if exit_code != 0 {
-        anyhow::bail!("Failed to pull service image, please check the terminal output for more information.");
-    }
-
-    Ok(exit_code)
 }
 
 //------------------------------------------------------------------------------
@@ -588,28 +1767,40 @@ pub async fn pull_service(
 /// * `stack_path` - Path to the directory containing compose file
 /// * `stacks_dir` - Path to the stacks directory (for env file resolution)
 /// * `endpoint` - Agent endpoint (empty string for local)
+/// * `log_options` - Timestamp options (each variant gets its own terminal)
+/// * `env` - Process environment of the compose command
 /// * `socket` - Socket to join to terminal room
+///
+/// Returns the terminal name.
+#[allow(clippy::too_many_arguments)]
 pub async fn join_logs_terminal(
     io: socketioxide::SocketIo,
     stack_name: &str,
     stack_path: &Path,
     stacks_dir: &Path,
     endpoint: &str,
+    log_options: &LogOptions,
+    env: &ComposeEnv,
     socket: SocketRef,
-) -> Result<()> {
-    let terminal_name = get_combined_terminal_name(endpoint, stack_name);
-    let options = compose_options(stacks_dir, stack_name, "logs", &["-f", "--tail", "100"]);
+) -> Result<String> {
+    let terminal_name = format!(
+        "{}{}",
+        get_combined_terminal_name(endpoint, stack_name),
+        log_options.terminal_suffix()
+    );
+    let options = compose_options(stacks_dir, stack_name, "logs", &logs_args(log_options, None));
 
-    let terminal = Terminal::get_or_create_terminal(
+    let terminal = get_or_create_logs_terminal(
         io,
-        terminal_name,
-        "docker".to_string(),
+        &terminal_name,
         options.clone(),
-        stack_path.display().to_string(),
+        stack_path,
+        log_options,
     )
     .await;
 
     // Enable keep-alive and set dimensions
+    terminal.set_env(env.command_env()).await;
     terminal.enable_keep_alive(true).await;
     terminal.set_rows(COMBINED_TERMINAL_ROWS).await?;
     terminal.set_cols(COMBINED_TERMINAL_COLS).await?;
@@ -622,7 +1813,46 @@ pub async fn join_logs_terminal(
         )
         .await?;
 
-    Ok(())
+    Ok(terminal_name)
+}
+
+/// `docker compose logs` arguments for the given options and optional service
+fn logs_args<'a>(log_options: &LogOptions, service_name: Option<&'a str>) -> Vec<&'a str> {
+    let mut args = vec!["-f", "--tail", "100"];
+    if log_options.timestamps {
+        args.push("--timestamps");
+    }
+    args.extend(service_name);
+    args
+}
+
+/// Get or create a logs terminal, installing the timestamp rewriter on new terminals
+async fn get_or_create_logs_terminal(
+    io: socketioxide::SocketIo,
+    terminal_name: &str,
+    options: Vec<String>,
+    stack_path: &Path,
+    log_options: &LogOptions,
+) -> Arc<Terminal> {
+    let is_new = Terminal::get_terminal(terminal_name).await.is_none();
+
+    let terminal = Terminal::get_or_create_terminal(
+        io,
+        terminal_name.to_string(),
+        "docker".to_string(),
+        options,
+        stack_path.display().to_string(),
+    )
+    .await;
+
+    if let (true, Some(tz)) = (is_new, log_options.timezone) {
+        let mut rewriter = TimestampRewriter::new(tz);
+        terminal
+            .set_output_filter(Box::new(move |data| rewriter.process(data)))
+            .await;
+    }
+
+    terminal
 }
 
 /// Leave the combined logs terminal for a stack
@@ -635,14 +1865,36 @@ pub async fn leave_logs_terminal(
     socket: SocketRef,
 ) -> Result<()> {
     let terminal_name = get_combined_terminal_name(endpoint, stack_name);
+    leave_logs_terminal_variants(&terminal_name, socket).await
+}
 
-    if let Some(terminal) = Terminal::get_terminal(&terminal_name).await {
-        terminal.leave(socket).await?;
+/// Leave a logs terminal and any timestamped variants of it the socket joined
+async fn leave_logs_terminal_variants(terminal_name: &str, socket: SocketRef) -> Result<()> {
+    let joined: Vec<String> = socket
+        .rooms()
+        .iter()
+        .map(|room| room.to_string())
+        .filter(|room| is_logs_terminal_variant(terminal_name, room))
+        .collect();
+
+    for name in joined {
+        if let Some(terminal) = Terminal::get_terminal(&name).await {
+            terminal.leave(socket.clone()).await?;
+        }
     }
 
     Ok(())
 }
 
+/// Whether `room` is the logs terminal `terminal_name` or one of its
+/// timestamped variants (see `LogOptions::terminal_suffix`)
+fn is_logs_terminal_variant(terminal_name: &str, room: &str) -> bool {
+    match room.strip_prefix(terminal_name) {
+        Some(suffix) => suffix.is_empty() || suffix == "-ts" || suffix.starts_with("-ts-"),
+        None => false,
+    }
+}
+
 /// Join an interactive exec terminal for a service container
 ///
 /// Creates or reuses an interactive terminal for shell access to a service.
@@ -656,8 +1908,13 @@ pub async fn leave_logs_terminal(
 /// * `endpoint` - Agent endpoint (empty string for local)
 /// * `service_name` - Service name from compose file
 /// * `shell` - Shell to execute (e.g., "bash", "sh", "/bin/sh")
+/// * `exec_options` - User, working directory and TERM to exec with
+/// * `env` - Process environment of the compose command
 /// * `index` - Terminal index (allows multiple terminals per service)
 /// * `socket` - Socket to join to terminal room
+///
+/// Returns the terminal name
+#[allow(clippy::too_many_arguments)]
 pub async fn join_exec_terminal(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -666,11 +1923,23 @@ pub async fn join_exec_terminal(
     endpoint: &str,
     service_name: &str,
     shell: &str,
+    exec_options: &ExecOptions,
+    env: &ComposeEnv,
     index: usize,
     socket: SocketRef,
-) -> Result<()> {
-    let terminal_name = get_container_exec_terminal_name(endpoint, stack_name, service_name, index);
-    let options = compose_options(stacks_dir, stack_name, "exec", &[service_name, shell]);
+) -> Result<String> {
+    exec_options.validate()?;
+    let terminal_name = format!(
+        "{}{}",
+        get_container_exec_terminal_name(endpoint, stack_name, service_name, index),
+        exec_options.terminal_suffix()
+    );
+    let env_flags = exec_options.env_flags(crate::terminal::terminal_env());
+    let mut extra = exec_options.exec_flags();
+    extra.extend(env_flags.iter().map(String::as_str));
+    extra.push(service_name);
+    extra.push(shell);
+    let options = compose_options(stacks_dir, stack_name, "exec", &extra);
 
     // Check if terminal already exists
     let terminal = if let Some(term) = Terminal::get_terminal(&terminal_name).await {
@@ -679,12 +1948,13 @@ pub async fn join_exec_terminal(
         // Create new interactive terminal
         let term = Terminal::new_interactive(
             io,
-            terminal_name,
+            terminal_name.clone(),
             "docker".to_string(),
             options.clone(),
             stack_path.display().to_string(),
         );
         term.set_rows(TERMINAL_ROWS).await?;
+        term.set_env(env.command_env()).await;
         term
     };
 
@@ -697,10 +1967,14 @@ pub async fn join_exec_terminal(
         )
         .await?;
 
-    Ok(())
+    Ok(terminal_name)
 }
 
 /// Join or create a container logs terminal (docker compose logs -f --tail 100 <service>)
+///
+/// Like the combined logs terminal, it closes once it has had no clients for
+/// a while.
+#[allow(clippy::too_many_arguments)]
 pub async fn join_container_logs_terminal(
     io: socketioxide::SocketIo,
     stack_name: &str,
@@ -708,21 +1982,34 @@ pub async fn join_container_logs_terminal(
     stacks_dir: &Path,
     endpoint: &str,
     service_name: &str,
+    log_options: &LogOptions,
+    env: &ComposeEnv,
     socket: SocketRef,
-) -> Result<()> {
-    let terminal_name = get_container_logs_terminal_name(endpoint, stack_name, service_name);
-    let options = compose_options(stacks_dir, stack_name, "logs", &["-f", "--tail", "100", service_name]);
+) -> Result<String> {
+    let terminal_name = format!(
+        "{}{}",
+        get_container_logs_terminal_name(endpoint, stack_name, service_name),
+        log_options.terminal_suffix()
+    );
+    let options = compose_options(
+        stacks_dir,
+        stack_name,
+        "logs",
+        &logs_args(log_options, Some(service_name)),
+    );
 
     // Get or create terminal
-    let terminal = Terminal::get_or_create_terminal(
+    let terminal = get_or_create_logs_terminal(
         io,
-        terminal_name,
-        "docker".to_string(),
+        &terminal_name,
         options.clone(),
-        stack_path.display().to_string(),
+        stack_path,
+        log_options,
     )
     .await;
+    terminal.enable_keep_alive(true).await;
     terminal.set_rows(TERMINAL_ROWS).await?;
+    terminal.set_env(env.command_env()).await;
 
     terminal.join(socket).await?;
     terminal
@@ -733,7 +2020,18 @@ pub async fn join_container_logs_terminal(
         )
         .await?;
 
-    Ok(())
+    Ok(terminal_name)
+}
+
+/// Leave a service's logs terminal, and any timestamped variants of it
+pub async fn leave_container_logs_terminal(
+    stack_name: &str,
+    endpoint: &str,
+    service_name: &str,
+    socket: SocketRef,
+) -> Result<()> {
+    let terminal_name = get_container_logs_terminal_name(endpoint, stack_name, service_name);
+    leave_logs_terminal_variants(&terminal_name, socket).await
 }
 
 //------------------------------------------------------------------------------
@@ -751,20 +2049,21 @@ struct ComposeListItem {
 
 /// Convert docker compose status string to app status constant
 ///
-/// Maps Docker status strings like "running(2)", "exited(1)", "created(1)"
-/// to application status constants.
+/// Parses Docker status strings like "running(2), exited(1)" into container
+/// counts, so they map to the same status as counted container states.
 pub fn status_convert(status: &str) -> i32 {
-    let status_lower = status.to_lowercase();
-
-    if status_lower.starts_with("created") {
-        CREATED_STACK
-    } else if status_lower.contains("exited") {
-        EXITED
-    } else if status_lower.starts_with("running") {
-        RUNNING
-    } else {
-        UNKNOWN
+    let mut counts = crate::stack::ServiceCounts::default();
+    for part in status.split(',').filter(|part| !part.trim().is_empty()) {
+        let part = part.trim().to_lowercase();
+        let (state, count) = match part.split_once('(') {
+            Some((state, count)) => (state, count.trim_end_matches(')').parse().unwrap_or(1)),
+            None => (part.as_str(), 1),
+        };
+        for _ in 0..count {
+            counts.add(state, None);
+        }
     }
+    counts.status()
 }
 
 /// List all compose projects known to Docker
@@ -778,6 +2077,7 @@ pub async fn list_compose_projects() -> Result<HashMap<String, (i32, String)>> {
 
     let output = Command::new("docker")
         .args(["compose", "ls", "--all", "--format", "json"])
+        .envs(docker_host::cli_env().iter().cloned())
         .output()
         .await
         .context("Failed to run docker compose ls")?;
@@ -802,3 +2102,617 @@ pub async fn list_compose_projects() -> Result<HashMap<String, (i32, String)>> {
 
     Ok(project_map)
 }
+
+/// Output of `docker compose config`
+#[derive(Debug, Clone, Serialize)]
+pub struct ComposeConfig {
+    /// The compose file after variable interpolation and merging, as YAML or
+    /// JSON depending on the requested format
+    pub config: String,
+    /// Warnings printed by compose, e.g. variables that are not set
+    pub warnings: Vec<String>,
+}
+
+/// Output format of `docker compose config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+}
+
+/// Render a compose file with `docker compose config`
+///
+/// The YAML and .env are written to a temporary directory so unsaved edits can be
+/// rendered. `project_dir` is used to resolve relative paths (build contexts,
+/// env_file, bind mounts); it defaults to the temporary directory for stacks
+/// that don't exist yet.
+///
+/// # Arguments
+/// * `stacks_dir` - Path to the stacks directory (for global.env)
+/// * `stack_name` - Compose project name
+/// * `project_dir` - The stack directory, if it exists
+/// * `compose_file_name` - File name to write the YAML as
+/// * `compose_yaml` - Compose file content
+/// * `compose_env` - .env content
+/// * `env` - Process environment of the command
+/// * `format` - Whether to render the model as YAML or JSON
+#[allow(clippy::too_many_arguments)]
+pub async fn compose_config(
+    stacks_dir: &Path,
+    stack_name: &str,
+    project_dir: Option<&Path>,
+    compose_file_name: &str,
+    compose_yaml: &str,
+    compose_env: &str,
+    env: &ComposeEnv,
+    format: ConfigFormat,
+) -> Result<ComposeConfig> {
+    use rand::Rng;
+
+    let tmp_dir = std::env::temp_dir().join(format!(
+        "dockru-config-{}",
+        hex::encode(rand::thread_rng().gen::<[u8; 8]>())
+    ));
+    tokio::fs::create_dir_all(&tmp_dir)
+        .await
+        .context("Failed to create temporary directory")?;
+
+    let result = async {
+        let compose_path = tmp_dir.join(compose_file_name);
+        let env_path = tmp_dir.join(".env");
+        tokio::fs::write(&compose_path, compose_yaml)
+            .await
+            .context("Failed to write compose file")?;
+        tokio::fs::write(&env_path, compose_env)
+            .await
+            .context("Failed to write .env file")?;
+
+        let project_dir = project_dir.unwrap_or(&tmp_dir);
+        let mut args = vec![
+            "compose".to_string(),
+            "--project-name".to_string(),
+            stack_name.to_string(),
+            "--project-directory".to_string(),
+            project_dir.display().to_string(),
+            "--file".to_string(),
+            compose_path.display().to_string(),
+        ];
+
+        // Same env file order as compose_options(); an explicit --env-file
+        // replaces the default .env lookup, so the stack's .env is always passed
+        let global_env_path = stacks_dir.join("global.env");
+        if global_env_path.exists() {
+            args.push("--env-file".to_string());
+            args.push(global_env_path.display().to_string());
+        }
+        args.push("--env-file".to_string());
+        args.push(env_path.display().to_string());
+        args.push("config".to_string());
+        if format == ConfigFormat::Json {
+            args.push("--format".to_string());
+            args.push("json".to_string());
+        }
+
+        let trace = CommandTrace::start("docker", &args, &project_dir.display().to_string());
+        let mut command = Command::new("docker");
+        for key in &env.removed {
+            command.env_remove(key);
+        }
+        let output = match command
+            .args(&args)
+            .envs(docker_host::cli_env().iter().cloned())
+            .envs(env.vars.iter().cloned())
+            .current_dir(project_dir)
+            .output()
+            .await
+            .context("Failed to run docker compose config")
+        {
+            Ok(output) => output,
+            Err(e) => {
+                trace.fail(&e);
+                return Err(e);
+            }
+        };
+        trace.finish(output.status.code().unwrap_or(-1));
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            anyhow::bail!("docker compose config failed: {}", stderr.trim());
+        }
+
+        Ok(ComposeConfig {
+            config: String::from_utf8_lossy(&output.stdout).into_owned(),
+            warnings: stderr
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+    .await;
+
+    if let Err(e) = tokio::fs::remove_dir_all(&tmp_dir).await {
+        warn!("Failed to remove {}: {}", tmp_dir.display(), e);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::StacksDir;
+    use crate::utils::constants::{
+        CREATED_STACK, EXITED, PARTIAL, RESTARTING, RUNNING, UNHEALTHY, UNKNOWN,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_superseded_images() {
+        let old: HashSet<String> = ["sha256:b", "sha256:a", "sha256:c"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let current: HashSet<String> = ["sha256:c", "sha256:d"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(superseded_images(&old, &current), ["sha256:a", "sha256:b"]);
+        assert!(superseded_images(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_count_project_services() {
+        let container = |project: &str, state: &str, status: &str| ContainerSummary {
+            labels: Some(HashMap::from([(
+                "com.docker.compose.project".to_string(),
+                project.to_string(),
+            )])),
+            state: Some(state.to_string()),
+            status: Some(status.to_string()),
+            ..Default::default()
+        };
+        let counts = count_project_services(&[
+            container("web", "running", "Up 2 hours"),
+            container("web", "running", "Up 2 hours"),
+            container("web", "exited", "Exited (1) 5 minutes ago"),
+            container("db", "running", "Up 1 hour (unhealthy)"),
+            container("db", "running", "Up 1 hour (healthy)"),
+            container("worker", "restarting", "Restarting (1) 3 seconds ago"),
+            container("worker", "running", "Up 1 minute"),
+            container("api", "running", "Up 1 minute (healthy)"),
+            container("api", "running", "Up 5 seconds (health: starting)"),
+            container("new", "created", "Created"),
+            container("old", "exited", "Exited (0) 2 days ago"),
+            ContainerSummary::default(),
+        ]);
+
+        assert_eq!(counts.len(), 6);
+        assert_eq!(counts["web"].total, 3);
+        assert_eq!(counts["web"].running, 2);
+        assert_eq!(counts["web"].status(), PARTIAL);
+        assert_eq!(counts["db"].unhealthy, 1);
+        assert_eq!(counts["db"].status(), UNHEALTHY);
+        assert_eq!(counts["worker"].status(), RESTARTING);
+        assert_eq!(counts["api"].status(), RUNNING);
+        let health = counts["api"].health().unwrap();
+        assert_eq!((health.healthy, health.starting, health.checked), (1, 1, 2));
+        assert_eq!(counts["db"].health().unwrap().healthy, 1);
+        assert_eq!(counts["web"].health(), None);
+        assert_eq!(counts["new"].status(), CREATED_STACK);
+        assert_eq!(counts["old"].status(), EXITED);
+        assert_eq!(crate::stack::ServiceCounts::default().status(), UNKNOWN);
+
+        assert!(is_dangling(&[]));
+        assert!(is_dangling(&["<none>:<none>".to_string()]));
+        assert!(!is_dangling(&["nginx:latest".to_string()]));
+    }
+
+    #[test]
+    fn test_is_logs_terminal_variant() {
+        let name = "container-logs--web-app";
+        assert!(is_logs_terminal_variant(name, name));
+        assert!(is_logs_terminal_variant(name, "container-logs--web-app-ts"));
+        assert!(is_logs_terminal_variant(
+            name,
+            "container-logs--web-app-ts-Europe/Berlin"
+        ));
+        assert!(!is_logs_terminal_variant(name, "container-logs--web-app-2"));
+        assert!(!is_logs_terminal_variant(name, "container-logs--web-ap"));
+    }
+
+    #[test]
+    fn test_find_container() {
+        let container = |id: &str, name: &str| ContainerSummary {
+            id: Some(id.to_string()),
+            names: Some(vec![format!("/{}", name)]),
+            ..Default::default()
+        };
+        let id = "3f1c9a7be2d40c5e8f6a1b2c3d4e5f60718293a4b5c6d7e8f9012345678abcde";
+        let containers = [
+            container("aaaa1111bbbb2222cccc", "web-app-1"),
+            container(id, "web-app-2"),
+        ];
+        let found = Some((id.to_string(), "web-app-2".to_string()));
+
+        assert_eq!(find_container(&containers, id), found);
+        assert_eq!(find_container(&containers, &id[..12]), found);
+        assert_eq!(find_container(&containers, "web-app-2"), found);
+        // Short prefixes are ambiguous
+        assert_eq!(find_container(&containers, "3f1c"), None);
+        assert_eq!(find_container(&containers, "db-1"), None);
+    }
+
+    #[test]
+    fn test_docker_info_from_system_info() {
+        use bollard::models::{SystemInfoCgroupDriverEnum, SystemInfoCgroupVersionEnum};
+
+        let info = DockerInfo::from_system_info(SystemInfo {
+            server_version: Some("27.3.1".to_string()),
+            driver: Some("overlay2".to_string()),
+            cgroup_version: Some(SystemInfoCgroupVersionEnum::_2),
+            cgroup_driver: Some(SystemInfoCgroupDriverEnum::SYSTEMD),
+            containers: Some(5),
+            containers_running: Some(3),
+            containers_stopped: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(info.server_version.as_deref(), Some("27.3.1"));
+        assert_eq!(info.storage_driver.as_deref(), Some("overlay2"));
+        assert_eq!(info.cgroup_version.as_deref(), Some("2"));
+        assert_eq!(info.cgroup_driver.as_deref(), Some("systemd"));
+        assert_eq!(info.containers, 5);
+        assert_eq!(info.containers_paused, 0);
+
+        let info = DockerInfo::from_system_info(SystemInfo {
+            cgroup_version: Some(SystemInfoCgroupVersionEnum::EMPTY),
+            ..Default::default()
+        });
+        assert_eq!(info.cgroup_version, None);
+    }
+
+    #[test]
+    fn test_map_to_service_status() {
+        use bollard::models::Port;
+
+        let port = |private_port: u16, public_port: u16| Port {
+            private_port,
+            public_port: Some(public_port),
+            ..Default::default()
+        };
+        let status = map_to_service_status(vec![
+            ContainerSummary {
+                id: Some("abc123".to_string()),
+                names: Some(vec!["/web-app-1".to_string()]),
+                image: Some("nginx:latest".to_string()),
+                labels: Some(HashMap::from([(
+                    "com.docker.compose.service".to_string(),
+                    "app".to_string(),
+                )])),
+                state: Some("running".to_string()),
+                status: Some("Up 2 hours (healthy)".to_string()),
+                // IPv4 and IPv6 bindings of the same ports
+                ports: Some(vec![port(80, 8080), port(443, 443), port(80, 8080)]),
+                ..Default::default()
+            },
+            ContainerSummary {
+                labels: Some(HashMap::from([(
+                    "com.docker.compose.service".to_string(),
+                    "db".to_string(),
+                )])),
+                state: Some("exited".to_string()),
+                status: Some("Exited (1) 5 minutes ago".to_string()),
+                ..Default::default()
+            },
+            // Not a compose container
+            ContainerSummary::default(),
+        ]);
+
+        assert_eq!(status.len(), 2);
+        let app = &status["app"];
+        assert_eq!(app.state, "running");
+        assert_eq!(app.health.as_deref(), Some("healthy"));
+        assert_eq!(app.ports, ["443:443", "8080:80"]);
+        assert_eq!(app.container_name.as_deref(), Some("web-app-1"));
+        assert_eq!(app.container_id.as_deref(), Some("abc123"));
+        assert_eq!(status["db"].state, "exited");
+        assert_eq!(status["db"].health, None);
+        assert!(status["db"].ports.is_empty());
+    }
+
+    #[test]
+    fn test_deploy_options_up_flags() {
+        assert_eq!(DeployOptions::default().up_flags(), ["-d", "--remove-orphans"]);
+
+        let options = DeployOptions {
+            force_recreate: true,
+            pull: Some(PullPolicy::Never),
+            no_build: true,
+        };
+        assert_eq!(
+            options.up_flags(),
+            [
+                "-d",
+                "--remove-orphans",
+                "--force-recreate",
+                "--pull",
+                "never",
+                "--no-build"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compose_commands() {
+        // No compose file on disk, so no --file arguments
+        let stacks = StacksDir::new();
+        let options = |command: ComposeCommand| command.options(stacks.path(), "web", None);
+
+        assert_eq!(
+            options(ComposeCommand::up(&DeployOptions::default())),
+            ["compose", "up", "-d", "--remove-orphans"]
+        );
+        // down and delete must not drift apart
+        assert_eq!(
+            options(ComposeCommand::down()),
+            ["compose", "down", "--remove-orphans"]
+        );
+        assert_eq!(
+            options(ComposeCommand::delete()),
+            options(ComposeCommand::down())
+        );
+        assert_eq!(
+            options(ComposeCommand::build(true)),
+            ["compose", "build", "--no-cache"]
+        );
+        assert_eq!(options(ComposeCommand::stop()), ["compose", "stop"]);
+        assert_eq!(
+            options(ComposeCommand::restart_service("db")),
+            ["compose", "restart", "db"]
+        );
+        assert_eq!(
+            options(ComposeCommand::pull_service("db")),
+            ["compose", "pull", "db"]
+        );
+
+        let env_file = Path::new("/data/env/web.env");
+        assert_eq!(
+            ComposeCommand::pull().options(stacks.path(), "web", Some(env_file)),
+            ["compose", "--env-file", "/data/env/web.env", "pull"]
+        );
+    }
+
+    #[test]
+    fn test_status_convert() {
+        assert_eq!(status_convert("running(2)"), RUNNING);
+        assert_eq!(status_convert("exited(1)"), EXITED);
+        assert_eq!(status_convert("created(3)"), CREATED_STACK);
+        assert_eq!(status_convert("running(2), exited(1)"), PARTIAL);
+        assert_eq!(status_convert("restarting(1), running(1)"), RESTARTING);
+        assert_eq!(status_convert(""), UNKNOWN);
+        assert_eq!(status_convert("Running(1)"), RUNNING);
+    }
+
+    #[test]
+    fn test_exec_options() {
+        let options = ExecOptions::default();
+        assert!(options.exec_flags().is_empty());
+        assert_eq!(options.terminal_suffix(), "");
+
+        let options = ExecOptions {
+            user: Some("1000:1000".to_string()),
+            workdir: Some("/app".to_string()),
+            term: None,
+        };
+        options.validate().unwrap();
+        assert_eq!(
+            options.exec_flags(),
+            ["--user", "1000:1000", "--workdir", "/app"]
+        );
+        assert_eq!(options.terminal_suffix(), "-u-1000:1000-w-/app");
+
+        let terminal_env = TerminalEnv::default();
+        assert_eq!(
+            options.env_flags(&terminal_env),
+            [
+                "-e",
+                "TERM=xterm-256color",
+                "-e",
+                "COLORTERM=truecolor",
+                "-e",
+                "LANG=C.UTF-8"
+            ]
+        );
+        let options = ExecOptions {
+            term: Some("xterm".to_string()),
+            ..Default::default()
+        };
+        options.validate().unwrap();
+        assert_eq!(options.env_flags(&terminal_env)[1], "TERM=xterm");
+        assert_eq!(options.terminal_suffix(), "-t-xterm");
+
+        for options in [
+            ExecOptions {
+                user: Some("--privileged".to_string()),
+                ..Default::default()
+            },
+            ExecOptions {
+                user: Some("root user".to_string()),
+                ..Default::default()
+            },
+            ExecOptions {
+                workdir: Some("app".to_string()),
+                ..Default::default()
+            },
+            ExecOptions {
+                term: Some("xterm -e x".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(options.validate().is_err(), "{:?}", options);
+        }
+    }
+
+    #[test]
+    fn test_resolve_compose_files() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path();
+        assert!(resolve_compose_files(path).is_empty());
+
+        std::fs::write(path.join("docker-compose.yml"), "services: {}\n").unwrap();
+        assert_eq!(resolve_compose_files(path), vec!["docker-compose.yml"]);
+
+        std::fs::write(path.join("compose.override.yaml"), "services: {}\n").unwrap();
+        assert_eq!(
+            resolve_compose_files(path),
+            vec!["docker-compose.yml", "compose.override.yaml"]
+        );
+
+        std::fs::write(
+            path.join(COMPOSE_FILE_LIST_NAME),
+            "# production\ndocker-compose.yml\n\n  prod.yaml\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_compose_files(path),
+            vec!["docker-compose.yml", "prod.yaml"]
+        );
+    }
+
+    #[test]
+    fn test_compose_options_env_files() {
+        let stacks = StacksDir::new();
+        let stacks_dir = stacks.path();
+        stacks.stack("web", "services: {}\n");
+        let env_args = |options: Vec<String>| -> Vec<String> {
+            options
+                .windows(2)
+                .filter(|pair| pair[0] == "--env-file")
+                .map(|pair| pair[1].clone())
+                .collect()
+        };
+
+        // Without global.env compose finds .env by itself
+        std::fs::write(stacks_dir.join("web").join(".env"), "A=1\n").unwrap();
+        assert!(env_args(compose_options(stacks_dir, "web", "ps", &[])).is_empty());
+
+        std::fs::write(stacks_dir.join("global.env"), "B=2\n").unwrap();
+        assert_eq!(
+            env_args(compose_options(stacks_dir, "web", "ps", &[])),
+            ["../global.env", "./.env"]
+        );
+
+        // A .env with secret references is never read as is
+        std::fs::write(
+            stacks_dir.join("web").join(".env"),
+            "A=${secret:A}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            env_args(compose_options(stacks_dir, "web", "ps", &[])),
+            ["../global.env", EMPTY_ENV_FILE]
+        );
+        let generated = Path::new("/data/generated-env/web-1234.env");
+        assert_eq!(
+            env_args(compose_options_with_env_file(
+                stacks_dir,
+                "web",
+                "up",
+                &[],
+                Some(generated)
+            )),
+            ["../global.env", "/data/generated-env/web-1234.env"]
+        );
+    }
+
+    #[test]
+    fn test_compose_options_docker_context() {
+        let stacks = StacksDir::new();
+        let stacks_dir = stacks.path();
+        stacks.stack("web", "services: {}\n");
+        assert_eq!(compose_options(stacks_dir, "web", "ps", &[])[0], "compose");
+
+        std::fs::write(stacks_dir.join("web").join(DOCKER_CONTEXT_FILE_NAME), "nas\n").unwrap();
+        assert_eq!(
+            &compose_options(stacks_dir, "web", "ps", &[])[..3],
+            ["--context", "nas", "compose"]
+        );
+    }
+
+    #[test]
+    fn test_compose_logs_args() {
+        let stacks = StacksDir::new();
+        let stacks_dir = stacks.path();
+        stacks.stack("web", "services: {}\n");
+        std::fs::write(
+            stacks_dir.join("web").join(DOCKER_CONTEXT_FILE_NAME),
+            "nas\n",
+        )
+        .unwrap();
+        let options = ComposeLogs {
+            service: Some("app".to_string()),
+            tail: Some("100".to_string()),
+            timestamps: false,
+            follow: true,
+        };
+
+        let args = compose_logs_args("web", &stacks_dir.join("web"), stacks_dir, &options);
+        assert_eq!(
+            &args[..5],
+            ["--context", "nas", "compose", "--project-name", "web"]
+        );
+        assert!(args.contains(&"--file".to_string()));
+        assert_eq!(
+            &args[args.len() - 6..],
+            ["logs", "--no-color", "--follow", "--tail", "100", "app"]
+        );
+
+        // Unmanaged stacks are found by project name alone
+        let args = compose_logs_args("other", &stacks_dir.join("other"), stacks_dir, &options);
+        assert_eq!(
+            &args[..5],
+            ["compose", "--project-name", "other", "logs", "--no-color"]
+        );
+
+        let invalid = ComposeLogs {
+            service: Some("--volumes".to_string()),
+            ..ComposeLogs::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_docker_context_name() {
+        assert!(validate_docker_context_name("nas").is_ok());
+        assert!(validate_docker_context_name("nas-2.lan+tls").is_ok());
+        assert!(validate_docker_context_name("").is_err());
+        assert!(validate_docker_context_name("-nas").is_err());
+        assert!(validate_docker_context_name("nas/../x").is_err());
+    }
+
+    #[test]
+    fn test_parse_docker_contexts() {
+        let lines = "{\"Current\":true,\"Description\":\"\",\"DockerEndpoint\":\"unix:///var/run/docker.sock\",\"Error\":\"\",\"Name\":\"default\"}\n{\"Current\":false,\"Description\":\"NAS\",\"DockerEndpoint\":\"ssh://nas\",\"Error\":\"\",\"Name\":\"nas\"}\n";
+        let contexts = parse_docker_contexts(lines).unwrap();
+        assert_eq!(contexts.len(), 2);
+        assert!(contexts[0].current);
+        assert_eq!(contexts[1].name, "nas");
+        assert_eq!(contexts[1].docker_endpoint, "ssh://nas");
+
+        let array = "[{\"Name\":\"default\",\"Current\":true}]";
+        assert_eq!(parse_docker_contexts(array).unwrap()[0].name, "default");
+        assert!(parse_docker_contexts("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_compose_file_name() {
+        assert!(validate_compose_file_name("compose.yaml").is_ok());
+        assert!(validate_compose_file_name("overrides/dev.yml").is_ok());
+        assert!(validate_compose_file_name("").is_err());
+        assert!(validate_compose_file_name("../other/compose.yaml").is_err());
+        assert!(validate_compose_file_name("/etc/compose.yaml").is_err());
+        assert!(validate_compose_file_name("--help.yaml").is_err());
+        assert!(validate_compose_file_name(".env").is_err());
+    }
+}
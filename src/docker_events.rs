@@ -0,0 +1,277 @@
+// Stack status from Docker events
+//
+// Rather than asking compose for every stack's status on a timer, the daemon's
+// event stream is followed for the containers of compose projects. An event
+// marks its project as changed; after a short debounce the changed projects'
+// containers are counted again and the stacks whose status moved are sent to
+// clients as a `stackStatus` delta:
+//
+//   { ok: true, stacks: { web: { status, services, health } } }
+//
+// A project gaining its first container or losing its last one changes the
+// stack list itself, so that triggers a full stack list broadcast instead.
+//
+// While the stream is up the counts of every project are kept here, and the
+// full stack list reads them instead of listing containers. When the stream
+// ends, e.g. because the daemon restarted, the counts are dropped, the stack
+// list falls back to polling at stackRefreshInterval, and the subscription is
+// retried.
+
+use crate::docker::{project_service_counts, service_counts_of};
+use crate::server::ServerContext;
+use crate::socket_handlers::broadcast_to_authenticated;
+use crate::stack::ServiceCounts;
+use crate::utils::constants::{DOCKER_EVENTS_RETRY_SECS, DOCKER_EVENT_DEBOUNCE_MS};
+use anyhow::{Context, Result};
+use bollard::models::EventMessage;
+use bollard::system::EventsOptions;
+use dockru_protocol::events::server as server_event;
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// Label compose puts on every container of a project
+const PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// Container actions that can change a stack's status
+///
+/// Health events carry the result after a colon, e.g. "health_status: healthy".
+const STATUS_ACTIONS: &[&str] = &[
+    "create",
+    "start",
+    "restart",
+    "stop",
+    "die",
+    "kill",
+    "oom",
+    "pause",
+    "unpause",
+    "destroy",
+    "health_status",
+];
+
+/// Project name -> container counts, None while events aren't followed
+static COUNTS: Lazy<RwLock<Option<HashMap<String, ServiceCounts>>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// How recounting a project changed what clients were sent
+#[derive(Debug, PartialEq, Eq)]
+enum Change {
+    None,
+    Status(ServiceCounts),
+    /// The project appeared or disappeared
+    List,
+}
+
+/// Whether stack status is currently kept up to date from Docker events
+pub fn is_following() -> bool {
+    COUNTS.read().unwrap().is_some()
+}
+
+/// The container counts of every compose project, if events are followed
+pub fn cached_service_counts() -> Option<HashMap<String, ServiceCounts>> {
+    COUNTS.read().unwrap().clone()
+}
+
+/// Follow Docker events for as long as the server runs
+pub fn start(ctx: Arc<ServerContext>) {
+    tokio::spawn(async move {
+        loop {
+            let result = follow(&ctx).await;
+            let was_following = COUNTS.write().unwrap().take().is_some();
+            match result {
+                Ok(()) => debug!("Docker event stream closed"),
+                // Retried quietly while the daemon stays unreachable
+                Err(e) if !was_following => debug!("Can't follow Docker events: {:#}", e),
+                Err(e) => warn!("Docker event stream failed: {:#}", e),
+            }
+            if was_following {
+                info!("Lost Docker events, polling stack status until they are back");
+                // The cached counts may be stale by now
+                ctx.broadcast_notify.notify_one();
+            }
+            tokio::time::sleep(Duration::from_secs(DOCKER_EVENTS_RETRY_SECS)).await;
+        }
+    });
+}
+
+async fn follow(ctx: &ServerContext) -> Result<()> {
+    let mut filters = HashMap::new();
+    filters.insert("type", vec!["container"]);
+    filters.insert("label", vec![PROJECT_LABEL]);
+    let mut events = ctx.docker.client().events(Some(EventsOptions {
+        since: None,
+        until: None,
+        filters,
+    }));
+
+    // Counted after subscribing, so no change falls between the two
+    let counts = project_service_counts(&ctx.docker).await?;
+    *COUNTS.write().unwrap() = Some(counts);
+    info!("Following Docker events for stack status");
+    ctx.broadcast_notify.notify_one();
+
+    let debounce = Duration::from_millis(DOCKER_EVENT_DEBOUNCE_MS);
+    let mut changed: HashSet<String> = HashSet::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let event = tokio::select! {
+            event = events.next() => event,
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                deadline = None;
+                apply_changes(ctx, std::mem::take(&mut changed)).await?;
+                continue;
+            }
+        };
+
+        match event {
+            Some(Ok(event)) => {
+                if let Some(project) = status_event_project(&event) {
+                    changed.insert(project.to_string());
+                    deadline.get_or_insert_with(|| Instant::now() + debounce);
+                }
+            }
+            Some(Err(e)) => return Err(e).context("Failed to read Docker events"),
+            None => return Ok(()),
+        }
+    }
+}
+
+/// The compose project of an event that may change its stack's status
+fn status_event_project(event: &EventMessage) -> Option<&str> {
+    let action = event.action.as_deref()?;
+    let action = action.split(':').next().unwrap_or(action).trim();
+    if !STATUS_ACTIONS.contains(&action) {
+        return None;
+    }
+    event
+        .actor
+        .as_ref()?
+        .attributes
+        .as_ref()?
+        .get(PROJECT_LABEL)
+        .map(String::as_str)
+}
+
+/// Count the changed projects again and tell clients what moved
+async fn apply_changes(ctx: &ServerContext, projects: HashSet<String>) -> Result<()> {
+    let mut stacks = serde_json::Map::new();
+    let mut list_changed = false;
+
+    for project in projects {
+        let counts = service_counts_of(&ctx.docker, &project).await?;
+        let change = {
+            let mut cache = COUNTS.write().unwrap();
+            let Some(cache) = cache.as_mut() else {
+                return Ok(());
+            };
+            record(cache, &project, counts)
+        };
+        match change {
+            Change::None => {}
+            Change::Status(counts) => {
+                stacks.insert(
+                    project,
+                    json!({
+                        "status": counts.status(),
+                        "services": counts,
+                        "health": counts.health(),
+                    }),
+                );
+            }
+            Change::List => list_changed = true,
+        }
+    }
+
+    if list_changed {
+        ctx.broadcast_notify.notify_one();
+    } else if !stacks.is_empty() {
+        debug!("Stack status changed: {:?}", stacks.keys());
+        let response = json!({ "ok": true, "stacks": stacks });
+        if let Err(e) =
+            broadcast_to_authenticated(&ctx.io, server_event::STACK_STATUS, response).await
+        {
+            error!("Failed to broadcast stack status: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Store a project's new counts, None if it has no containers left
+fn record(
+    cache: &mut HashMap<String, ServiceCounts>,
+    project: &str,
+    counts: Option<ServiceCounts>,
+) -> Change {
+    let previous = match counts {
+        Some(counts) => cache.insert(project.to_string(), counts),
+        None => cache.remove(project),
+    };
+    match (previous, counts) {
+        (None, None) => Change::None,
+        (Some(previous), Some(counts)) if previous == counts => Change::None,
+        (Some(_), Some(counts)) => Change::Status(counts),
+        _ => Change::List,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::EventActor;
+
+    fn event(action: &str, project: Option<&str>) -> EventMessage {
+        EventMessage {
+            action: Some(action.to_string()),
+            actor: Some(EventActor {
+                id: Some("abc".to_string()),
+                attributes: project
+                    .map(|p| HashMap::from([(PROJECT_LABEL.to_string(), p.to_string())])),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_status_event_project() {
+        assert_eq!(
+            status_event_project(&event("die", Some("web"))),
+            Some("web")
+        );
+        assert_eq!(
+            status_event_project(&event("health_status: unhealthy", Some("web"))),
+            Some("web")
+        );
+        assert_eq!(
+            status_event_project(&event("exec_start: sh", Some("web"))),
+            None
+        );
+        assert_eq!(status_event_project(&event("attach", Some("web"))), None);
+        assert_eq!(status_event_project(&event("start", None)), None);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut cache = HashMap::new();
+        let mut running = ServiceCounts::default();
+        running.add("running", None);
+        let mut exited = ServiceCounts::default();
+        exited.add("exited", None);
+
+        assert_eq!(record(&mut cache, "web", Some(running)), Change::List);
+        assert_eq!(record(&mut cache, "web", Some(running)), Change::None);
+        assert_eq!(
+            record(&mut cache, "web", Some(exited)),
+            Change::Status(exited)
+        );
+        assert_eq!(cache.get("web"), Some(&exited));
+        assert_eq!(record(&mut cache, "web", None), Change::List);
+        assert!(cache.is_empty());
+        assert_eq!(record(&mut cache, "db", None), Change::None);
+    }
+}
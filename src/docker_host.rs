@@ -0,0 +1,522 @@
+// Docker host selection
+//
+// By default Dockru talks to the daemon on the local socket. --docker-host
+// (DOCKER_HOST) points both the Bollard client and the docker / compose CLI at
+// another daemon, so one instance can manage a machine without an agent on it:
+//
+//   unix:///path/docker.sock   another local socket
+//   tcp://host:2375            plain TCP, only for trusted networks
+//   tcp://host:2376            TLS with --docker-tls-verify (DOCKER_TLS_VERIFY)
+//   https://host:2376          TLS
+//   ssh://user@host[:port]     SSH, the remote user must be able to use docker
+//
+// TLS reads ca.pem, cert.pem and key.pem from --docker-cert-path
+// (DOCKER_CERT_PATH), ~/.docker by default, as the docker CLI does.
+//
+// The CLI understands every form itself and just gets the variables. Bollard
+// cannot speak SSH, so for ssh:// the remote socket is forwarded to a local one
+// with `ssh -L` and the client connects to that; the tunnel is restarted when a
+// reconnect finds it gone.
+//
+// Bind mounts and build contexts are resolved on the daemon's machine, so stacks
+// that mount host paths need those paths to exist there.
+//
+// Stacks can also be deployed to a named docker context (see
+// Stack::set_docker_context); the CLI gets `--context` and Bollard connects to
+// the host the context describes, as read from `docker context inspect`.
+
+use crate::config::Config;
+use anyhow::{bail, Context, Result};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tracing::{info, warn};
+
+/// Read/write timeout of Bollard connections, in seconds (Bollard's default)
+const CLIENT_TIMEOUT_SECS: u64 = 120;
+
+/// Socket the Docker daemon listens on at the other end of an SSH tunnel
+const REMOTE_SOCKET: &str = "/var/run/docker.sock";
+
+/// How long to wait for an SSH tunnel to come up
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// File name of the forwarded socket in the data directory, contexts get their own
+const TUNNEL_SOCKET_NAME: &str = "docker-ssh.sock";
+
+/// Variables pointing docker CLI invocations at the configured daemon
+static CLI_ENV: OnceCell<Vec<(String, String)>> = OnceCell::new();
+
+/// The daemon Dockru manages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DockerHost {
+    /// Bollard's local default (the standard socket or named pipe)
+    Local,
+    /// A unix socket, as a unix:// URL
+    Unix(String),
+    /// A TCP daemon, with the certificate directory when TLS is used
+    Tcp { url: String, tls: Option<PathBuf> },
+    /// A daemon reached over SSH
+    Ssh {
+        url: String,
+        destination: String,
+        port: Option<u16>,
+    },
+}
+
+impl DockerHost {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let host = Self::parse(
+            config.docker_host.as_deref(),
+            config.docker_tls_verify,
+            config.docker_cert_path.clone(),
+        )?;
+        if let DockerHost::Tcp { tls: Some(dir), .. } = &host {
+            for file in ["ca.pem", "cert.pem", "key.pem"] {
+                if !dir.join(file).is_file() {
+                    bail!("TLS is enabled but {} is missing", dir.join(file).display());
+                }
+            }
+        }
+        Ok(host)
+    }
+
+    /// Parse a DOCKER_HOST style URL
+    ///
+    /// `cert_path` defaults to ~/.docker and is only used for TLS.
+    pub fn parse(host: Option<&str>, tls_verify: bool, cert_path: Option<PathBuf>) -> Result<Self> {
+        let Some(host) = host.map(str::trim).filter(|h| !h.is_empty()) else {
+            return Ok(DockerHost::Local);
+        };
+        let cert_dir = || {
+            cert_path.clone().unwrap_or_else(|| {
+                std::env::var_os("HOME")
+                    .map(PathBuf::from)
+                    .unwrap_or_default()
+                    .join(".docker")
+            })
+        };
+
+        let (scheme, rest) = host
+            .split_once("://")
+            .with_context(|| format!("Docker host {} has no scheme (e.g. tcp://)", host))?;
+        if rest.is_empty() {
+            bail!("Docker host {} has no address", host);
+        }
+        match scheme {
+            "unix" => Ok(DockerHost::Unix(host.to_string())),
+            "tcp" | "http" => Ok(DockerHost::Tcp {
+                url: host.to_string(),
+                tls: tls_verify.then(cert_dir),
+            }),
+            "https" => Ok(DockerHost::Tcp {
+                url: host.to_string(),
+                tls: Some(cert_dir()),
+            }),
+            "ssh" => {
+                let authority = rest.trim_end_matches('/');
+                if authority.contains('/') {
+                    bail!("Docker host {} must not have a path", host);
+                }
+                let (destination, port) = match authority.rsplit_once(':') {
+                    Some((destination, port)) => (
+                        destination,
+                        Some(
+                            port.parse::<u16>()
+                                .with_context(|| format!("Invalid SSH port in {}", host))?,
+                        ),
+                    ),
+                    None => (authority, None),
+                };
+                if destination.is_empty() || destination.ends_with('@') {
+                    bail!("Docker host {} has no host name", host);
+                }
+                Ok(DockerHost::Ssh {
+                    url: host.to_string(),
+                    destination: destination.to_string(),
+                    port,
+                })
+            }
+            _ => bail!(
+                "Unsupported docker host scheme {}, expected unix, tcp, https or ssh",
+                scheme
+            ),
+        }
+    }
+
+    /// Whether the daemon is on another machine
+    pub fn is_remote(&self) -> bool {
+        matches!(self, DockerHost::Tcp { .. } | DockerHost::Ssh { .. })
+    }
+
+    /// Environment for docker CLI invocations
+    pub fn cli_env(&self) -> Vec<(String, String)> {
+        match self {
+            DockerHost::Local => Vec::new(),
+            DockerHost::Unix(url) | DockerHost::Ssh { url, .. } => {
+                vec![("DOCKER_HOST".to_string(), url.clone())]
+            }
+            DockerHost::Tcp { url, tls: None } => {
+                vec![("DOCKER_HOST".to_string(), url.clone())]
+            }
+            DockerHost::Tcp {
+                url,
+                tls: Some(dir),
+            } => vec![
+                // The CLI only uses TLS for tcp:// when asked to
+                (
+                    "DOCKER_HOST".to_string(),
+                    url.replacen("https://", "tcp://", 1),
+                ),
+                ("DOCKER_TLS_VERIFY".to_string(), "1".to_string()),
+                ("DOCKER_CERT_PATH".to_string(), dir.display().to_string()),
+            ],
+        }
+    }
+
+    /// How the host is shown in logs
+    pub fn describe(&self) -> String {
+        match self {
+            DockerHost::Local => "local socket".to_string(),
+            DockerHost::Unix(url) | DockerHost::Ssh { url, .. } => url.clone(),
+            DockerHost::Tcp { url, tls } => {
+                format!("{}{}", url, if tls.is_some() { " (TLS)" } else { "" })
+            }
+        }
+    }
+}
+
+/// Environment variables to give docker CLI invocations, empty for the local daemon
+pub fn cli_env() -> &'static [(String, String)] {
+    CLI_ENV.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Make docker CLI invocations target the host, see [`cli_env`]
+pub fn set_cli_env(host: &DockerHost) {
+    if CLI_ENV.set(host.cli_env()).is_err() {
+        warn!("Docker CLI environment was already set");
+    }
+}
+
+/// Creates Bollard clients for a docker host
+pub struct DockerConnector {
+    host: DockerHost,
+    data_dir: PathBuf,
+    tunnel: Option<SshTunnel>,
+}
+
+impl DockerConnector {
+    /// `data_dir` holds the forwarded sockets of ssh:// hosts.
+    pub fn new(host: DockerHost, data_dir: &Path) -> Self {
+        Self::with_tunnel_socket(host, data_dir, TUNNEL_SOCKET_NAME.to_string())
+    }
+
+    /// A connector for the host of a docker context
+    pub async fn for_context(&self, name: &str) -> Result<Self> {
+        let output = Command::new("docker")
+            .args(["context", "inspect", "--", name])
+            .envs(cli_env().iter().cloned())
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Failed to run docker context inspect")?;
+        if !output.status.success() {
+            bail!(
+                "Docker context {} not found: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let (host, tls) = context_host(&String::from_utf8_lossy(&output.stdout))?;
+        let host = DockerHost::parse(Some(&host), tls.is_some(), tls)?;
+        Ok(Self::with_tunnel_socket(
+            host,
+            &self.data_dir,
+            format!("docker-ssh-{}.sock", name),
+        ))
+    }
+
+    fn with_tunnel_socket(host: DockerHost, data_dir: &Path, socket_name: String) -> Self {
+        let tunnel = match &host {
+            DockerHost::Ssh {
+                destination, port, ..
+            } => Some(SshTunnel {
+                destination: destination.clone(),
+                port: *port,
+                socket: data_dir.join(socket_name),
+                child: tokio::sync::Mutex::new(None),
+            }),
+            _ => None,
+        };
+        Self {
+            host,
+            data_dir: data_dir.to_path_buf(),
+            tunnel,
+        }
+    }
+
+    pub fn host(&self) -> &DockerHost {
+        &self.host
+    }
+
+    /// Connect a new client, (re)starting the SSH tunnel if needed
+    pub async fn connect(&self) -> Result<Docker> {
+        let docker = match &self.host {
+            DockerHost::Local => Docker::connect_with_local_defaults()?,
+            DockerHost::Unix(url) => {
+                Docker::connect_with_unix(url, CLIENT_TIMEOUT_SECS, API_DEFAULT_VERSION)?
+            }
+            DockerHost::Tcp { url, tls: None } => {
+                Docker::connect_with_http(url, CLIENT_TIMEOUT_SECS, API_DEFAULT_VERSION)?
+            }
+            DockerHost::Tcp {
+                url,
+                tls: Some(dir),
+            } => Docker::connect_with_ssl(
+                url,
+                &dir.join("key.pem"),
+                &dir.join("cert.pem"),
+                &dir.join("ca.pem"),
+                CLIENT_TIMEOUT_SECS,
+                API_DEFAULT_VERSION,
+            )?,
+            DockerHost::Ssh { .. } => {
+                let tunnel = self.tunnel.as_ref().expect("ssh hosts have a tunnel");
+                tunnel.ensure().await?;
+                Docker::connect_with_unix(
+                    &tunnel.socket.display().to_string(),
+                    CLIENT_TIMEOUT_SECS,
+                    API_DEFAULT_VERSION,
+                )?
+            }
+        };
+        Ok(docker)
+    }
+}
+
+/// `ssh -L` forwarding the remote daemon socket to a local one
+struct SshTunnel {
+    destination: String,
+    port: Option<u16>,
+    socket: PathBuf,
+    child: tokio::sync::Mutex<Option<Child>>,
+}
+
+impl SshTunnel {
+    /// Start the tunnel unless it is running
+    async fn ensure(&self) -> Result<()> {
+        let mut child = self.child.lock().await;
+        if let Some(running) = child.as_mut() {
+            if running.try_wait()?.is_none() {
+                return Ok(());
+            }
+            warn!("SSH tunnel to {} exited, restarting it", self.destination);
+        }
+
+        // A socket left behind by an earlier tunnel would make ssh fail to bind
+        match tokio::fs::remove_file(&self.socket).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context("Failed to remove stale SSH tunnel socket"),
+        }
+
+        let mut command = Command::new("ssh");
+        command
+            .args(ssh_args(&self.destination, self.port, &self.socket))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut spawned = command
+            .spawn()
+            .context("Failed to run ssh, is an SSH client installed?")?;
+
+        let deadline = tokio::time::Instant::now() + TUNNEL_TIMEOUT;
+        while !self.socket.exists() {
+            if let Some(status) = spawned.try_wait()? {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = spawned.stderr.take() {
+                    use tokio::io::AsyncReadExt;
+                    pipe.read_to_string(&mut stderr).await.ok();
+                }
+                bail!(
+                    "SSH tunnel to {} exited ({}): {}",
+                    self.destination,
+                    status,
+                    stderr.trim()
+                );
+            }
+            if tokio::time::Instant::now() >= deadline {
+                bail!("Timed out opening an SSH tunnel to {}", self.destination);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        info!("SSH tunnel to {} is up", self.destination);
+        *child = Some(spawned);
+        Ok(())
+    }
+}
+
+/// The docker host and, when the context has TLS material, its certificate
+/// directory, from `docker context inspect` output
+fn context_host(inspect: &str) -> Result<(String, Option<PathBuf>)> {
+    let contexts: serde_json::Value =
+        serde_json::from_str(inspect).context("Invalid docker context inspect output")?;
+    let context = &contexts[0];
+    let host = context["Endpoints"]["docker"]["Host"]
+        .as_str()
+        .filter(|h| !h.is_empty())
+        .context("Docker context has no docker endpoint")?;
+    let tls = context["TLSMaterial"]["docker"]
+        .as_array()
+        .filter(|files| !files.is_empty())
+        .and(context["Storage"]["TLSPath"].as_str())
+        .map(|path| Path::new(path).join("docker"));
+    Ok((host.to_string(), tls))
+}
+
+/// Arguments of the tunnel's ssh command
+///
+/// Batch mode because nobody can answer a password prompt; keys must be set up.
+fn ssh_args(destination: &str, port: Option<u16>, socket: &Path) -> Vec<String> {
+    let mut args = vec![
+        "-nNT".to_string(),
+        "-o".to_string(),
+        "BatchMode=yes".to_string(),
+        "-o".to_string(),
+        "ExitOnForwardFailure=yes".to_string(),
+        "-o".to_string(),
+        "ServerAliveInterval=30".to_string(),
+        "-L".to_string(),
+        format!("{}:{}", socket.display(), REMOTE_SOCKET),
+    ];
+    if let Some(port) = port {
+        args.push("-p".to_string());
+        args.push(port.to_string());
+    }
+    args.push("--".to_string());
+    args.push(destination.to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_docker_host() {
+        assert_eq!(
+            DockerHost::parse(None, false, None).unwrap(),
+            DockerHost::Local
+        );
+        assert_eq!(
+            DockerHost::parse(Some(" "), false, None).unwrap(),
+            DockerHost::Local
+        );
+        assert_eq!(
+            DockerHost::parse(Some("unix:///run/docker.sock"), false, None).unwrap(),
+            DockerHost::Unix("unix:///run/docker.sock".to_string())
+        );
+        assert_eq!(
+            DockerHost::parse(Some("tcp://10.0.0.2:2375"), false, None).unwrap(),
+            DockerHost::Tcp {
+                url: "tcp://10.0.0.2:2375".to_string(),
+                tls: None
+            }
+        );
+        assert_eq!(
+            DockerHost::parse(Some("tcp://10.0.0.2:2376"), true, Some("/certs".into())).unwrap(),
+            DockerHost::Tcp {
+                url: "tcp://10.0.0.2:2376".to_string(),
+                tls: Some("/certs".into())
+            }
+        );
+        assert!(matches!(
+            DockerHost::parse(Some("https://10.0.0.2:2376"), false, None).unwrap(),
+            DockerHost::Tcp { tls: Some(_), .. }
+        ));
+        assert_eq!(
+            DockerHost::parse(Some("ssh://deploy@nas:2222"), false, None).unwrap(),
+            DockerHost::Ssh {
+                url: "ssh://deploy@nas:2222".to_string(),
+                destination: "deploy@nas".to_string(),
+                port: Some(2222)
+            }
+        );
+        assert!(matches!(
+            DockerHost::parse(Some("ssh://nas"), false, None).unwrap(),
+            DockerHost::Ssh { port: None, .. }
+        ));
+
+        assert!(DockerHost::parse(Some("10.0.0.2:2375"), false, None).is_err());
+        assert!(DockerHost::parse(Some("ftp://nas"), false, None).is_err());
+        assert!(DockerHost::parse(Some("ssh://"), false, None).is_err());
+        assert!(DockerHost::parse(Some("ssh://deploy@"), false, None).is_err());
+        assert!(DockerHost::parse(Some("ssh://nas:port"), false, None).is_err());
+        assert!(DockerHost::parse(Some("ssh://nas/var/run"), false, None).is_err());
+    }
+
+    #[test]
+    fn test_cli_env() {
+        assert!(DockerHost::Local.cli_env().is_empty());
+        assert_eq!(
+            DockerHost::parse(Some("ssh://nas"), false, None)
+                .unwrap()
+                .cli_env(),
+            vec![("DOCKER_HOST".to_string(), "ssh://nas".to_string())]
+        );
+        assert_eq!(
+            DockerHost::parse(Some("https://nas:2376"), false, Some("/certs".into()))
+                .unwrap()
+                .cli_env(),
+            vec![
+                ("DOCKER_HOST".to_string(), "tcp://nas:2376".to_string()),
+                ("DOCKER_TLS_VERIFY".to_string(), "1".to_string()),
+                ("DOCKER_CERT_PATH".to_string(), "/certs".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_context_host() {
+        let inspect = r#"[{
+            "Name": "nas",
+            "Endpoints": { "docker": { "Host": "tcp://nas:2376", "SkipTLSVerify": false } },
+            "TLSMaterial": { "docker": ["ca.pem", "cert.pem", "key.pem"] },
+            "Storage": { "MetadataPath": "/m", "TLSPath": "/root/.docker/contexts/tls/abc" }
+        }]"#;
+        assert_eq!(
+            context_host(inspect).unwrap(),
+            (
+                "tcp://nas:2376".to_string(),
+                Some(PathBuf::from("/root/.docker/contexts/tls/abc/docker"))
+            )
+        );
+
+        let inspect = r#"[{
+            "Endpoints": { "docker": { "Host": "ssh://deploy@nas" } },
+            "TLSMaterial": {},
+            "Storage": { "TLSPath": "/root/.docker/contexts/tls/def" }
+        }]"#;
+        assert_eq!(
+            context_host(inspect).unwrap(),
+            ("ssh://deploy@nas".to_string(), None)
+        );
+
+        assert!(context_host("[]").is_err());
+    }
+
+    #[test]
+    fn test_ssh_args() {
+        let args = ssh_args("deploy@nas", Some(2222), Path::new("/data/docker-ssh.sock"));
+        assert!(args.contains(&"/data/docker-ssh.sock:/var/run/docker.sock".to_string()));
+        assert_eq!(
+            &args[args.len() - 4..],
+            &["-p", "2222", "--", "deploy@nas"].map(String::from)
+        );
+    }
+}
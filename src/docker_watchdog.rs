@@ -0,0 +1,69 @@
+// Docker daemon watchdog
+//
+// Every DOCKER_WATCHDOG_SECS the daemon is pinged, and the client reconnected
+// if dockerd restarted. When the daemon can't be reached:
+//
+//   - every stack's status is unknown (see Stack::get_stack_list), and a fresh
+//     stack list is broadcast right away
+//   - logged-in clients get a `dockerUnavailable` event on every check, so
+//     clients that connect meanwhile learn about it too:
+//
+//       { since }   (unix seconds the daemon has been unreachable since)
+//
+//   - the daemon is checked every DOCKER_WATCHDOG_RETRY_SECS instead
+//
+// Once it is back clients get `dockerAvailable` and the stack list again.
+
+use crate::server::ServerContext;
+use crate::socket_handlers::broadcast_to_authenticated;
+use crate::utils::constants::{DOCKER_WATCHDOG_RETRY_SECS, DOCKER_WATCHDOG_SECS};
+use chrono::Utc;
+use dockru_protocol::events::server as server_event;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Watch the Docker daemon connection for as long as the server runs
+pub fn start(ctx: Arc<ServerContext>) {
+    tokio::spawn(async move {
+        let mut unavailable_since: Option<i64> = None;
+        loop {
+            let healthy = ctx.docker.check_health().await;
+            match (healthy, unavailable_since) {
+                (false, None) => {
+                    unavailable_since = Some(Utc::now().timestamp());
+                    info!("Marking all stacks unknown until Docker is reachable again");
+                    ctx.broadcast_notify.notify_one();
+                }
+                (true, Some(_)) => {
+                    unavailable_since = None;
+                    broadcast(&ctx, server_event::DOCKER_AVAILABLE, json!({})).await;
+                    ctx.broadcast_notify.notify_one();
+                }
+                _ => {}
+            }
+            if let Some(since) = unavailable_since {
+                broadcast(
+                    &ctx,
+                    server_event::DOCKER_UNAVAILABLE,
+                    json!({ "since": since }),
+                )
+                .await;
+            }
+
+            let secs = if healthy {
+                DOCKER_WATCHDOG_SECS
+            } else {
+                DOCKER_WATCHDOG_RETRY_SECS
+            };
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+        }
+    });
+}
+
+async fn broadcast(ctx: &ServerContext, event: &str, data: serde_json::Value) {
+    if let Err(e) = broadcast_to_authenticated(&ctx.io, event, data).await {
+        debug!("Failed to broadcast {}: {:#}", event, e);
+    }
+}
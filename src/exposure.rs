@@ -0,0 +1,211 @@
+// Port exposure audit
+//
+// Lists the services of compose stacks that publish ports on the host, from
+// the ports Docker actually published for their running containers, and flags
+// what matters when hardening an internet-facing host:
+//
+// - ports published on all interfaces (0.0.0.0 or ::) rather than one address
+// - services without labels for a reverse proxy (Traefik, Caddy, SWAG,
+//   nginx-proxy), which are likely reached directly
+// - host ports below 1024
+
+use crate::docker::{list_compose_containers, DockerHandle};
+use anyhow::Result;
+use bollard::models::ContainerSummary;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Label compose puts on every container of a project
+const PROJECT_LABEL: &str = "com.docker.compose.project";
+
+/// Label compose puts on every container of a service
+const SERVICE_LABEL: &str = "com.docker.compose.service";
+
+/// Prefixes of the labels reverse proxies discover containers by
+const REVERSE_PROXY_LABEL_PREFIXES: &[&str] = &["traefik.", "caddy", "swag", "nginx-proxy."];
+
+/// Host ports below this need root to bind
+const FIRST_UNPRIVILEGED_PORT: u16 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct PublishedPort {
+    /// Host address, 0.0.0.0 or :: for all interfaces
+    pub ip: String,
+    #[serde(rename = "hostPort")]
+    pub host_port: u16,
+    #[serde(rename = "containerPort")]
+    pub container_port: u16,
+    pub protocol: String,
+}
+
+impl PublishedPort {
+    fn on_all_interfaces(&self) -> bool {
+        matches!(self.ip.as_str(), "" | "0.0.0.0" | "::")
+    }
+
+    fn is_privileged(&self) -> bool {
+        self.host_port < FIRST_UNPRIVILEGED_PORT
+    }
+}
+
+/// A service publishing ports, with what the audit flags about it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExposedService {
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub service: String,
+    pub ports: Vec<PublishedPort>,
+    /// Some port is published on all interfaces
+    #[serde(rename = "allInterfaces")]
+    pub all_interfaces: bool,
+    /// No container of the service has reverse proxy labels
+    #[serde(rename = "missingReverseProxy")]
+    pub missing_reverse_proxy: bool,
+    /// Host ports below 1024
+    #[serde(rename = "privilegedPorts")]
+    pub privileged_ports: Vec<u16>,
+}
+
+/// The exposure of every compose service on the host
+pub async fn exposure_report(docker: &DockerHandle) -> Result<Vec<ExposedService>> {
+    let containers = list_compose_containers(docker).await?;
+    Ok(build_report(&containers))
+}
+
+/// Group the published ports of containers by stack and service, sorted by both
+fn build_report(containers: &[ContainerSummary]) -> Vec<ExposedService> {
+    let mut services: BTreeMap<(String, String), (Vec<PublishedPort>, bool)> = BTreeMap::new();
+    for container in containers {
+        let Some(labels) = &container.labels else {
+            continue;
+        };
+        let (Some(project), Some(service)) = (labels.get(PROJECT_LABEL), labels.get(SERVICE_LABEL))
+        else {
+            continue;
+        };
+        let ports: Vec<PublishedPort> = container
+            .ports
+            .iter()
+            .flatten()
+            .filter_map(|port| {
+                Some(PublishedPort {
+                    ip: port.ip.clone().unwrap_or_default(),
+                    host_port: port.public_port?,
+                    container_port: port.private_port,
+                    protocol: port
+                        .typ
+                        .map(|t| t.to_string())
+                        .unwrap_or_else(|| "tcp".to_string()),
+                })
+            })
+            .collect();
+        if ports.is_empty() {
+            continue;
+        }
+
+        let proxied = labels.keys().any(|key| {
+            REVERSE_PROXY_LABEL_PREFIXES
+                .iter()
+                .any(|p| key.starts_with(p))
+        });
+        let entry = services
+            .entry((project.clone(), service.clone()))
+            .or_default();
+        entry.0.extend(ports);
+        entry.1 |= proxied;
+    }
+
+    services
+        .into_iter()
+        .map(|((stack_name, service), (mut ports, proxied))| {
+            ports.sort();
+            ports.dedup();
+            let mut privileged_ports: Vec<u16> = ports
+                .iter()
+                .filter(|p| p.is_privileged())
+                .map(|p| p.host_port)
+                .collect();
+            privileged_ports.dedup();
+            ExposedService {
+                stack_name,
+                service,
+                all_interfaces: ports.iter().any(PublishedPort::on_all_interfaces),
+                missing_reverse_proxy: !proxied,
+                privileged_ports,
+                ports,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{Port, PortTypeEnum};
+    use std::collections::HashMap;
+
+    fn container(
+        project: &str,
+        service: &str,
+        ports: &[(&str, Option<u16>, u16)],
+    ) -> ContainerSummary {
+        ContainerSummary {
+            labels: Some(HashMap::from([
+                (PROJECT_LABEL.to_string(), project.to_string()),
+                (SERVICE_LABEL.to_string(), service.to_string()),
+            ])),
+            ports: Some(
+                ports
+                    .iter()
+                    .map(|(ip, public, private)| Port {
+                        ip: Some(ip.to_string()),
+                        private_port: *private,
+                        public_port: *public,
+                        typ: Some(PortTypeEnum::TCP),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_build_report() {
+        let mut proxied = container("web", "app", &[("127.0.0.1", Some(8080), 80)]);
+        proxied
+            .labels
+            .as_mut()
+            .unwrap()
+            .insert("traefik.enable".to_string(), "true".to_string());
+        let containers = vec![
+            container(
+                "web",
+                "nginx",
+                &[("0.0.0.0", Some(443), 443), ("::", Some(443), 443)],
+            ),
+            container("web", "nginx", &[("0.0.0.0", Some(443), 443)]),
+            proxied,
+            // Exposed to other containers only
+            container("web", "db", &[("", None, 5432)]),
+            container("media", "jellyfin", &[("0.0.0.0", Some(8096), 8096)]),
+        ];
+
+        let report = build_report(&containers);
+        assert_eq!(report.len(), 3);
+
+        assert_eq!(report[0].stack_name, "media");
+        assert!(report[0].all_interfaces);
+        assert!(report[0].missing_reverse_proxy);
+        assert!(report[0].privileged_ports.is_empty());
+
+        assert_eq!(report[1].service, "app");
+        assert!(!report[1].all_interfaces);
+        assert!(!report[1].missing_reverse_proxy);
+
+        assert_eq!(report[2].service, "nginx");
+        assert_eq!(report[2].ports.len(), 2);
+        assert_eq!(report[2].ports[0].protocol, "tcp");
+        assert!(report[2].all_interfaces);
+        assert_eq!(report[2].privileged_ports, vec![443]);
+    }
+}
@@ -0,0 +1,101 @@
+// Reverse-proxy header authentication
+//
+// Behind an authenticating reverse proxy (Authelia, authentik, oauth2-proxy,
+// ...) dockru can take the user's identity from a header the proxy sets, e.g.
+//
+//   DOCKRU_AUTH_HEADER=Remote-User
+//   DOCKRU_TRUSTED_PROXIES=172.18.0.0/16
+//
+// The header is only believed on connections coming straight from one of the
+// trusted proxies, since anyone else could set it. Sockets connecting through
+// a proxy with the header are logged in on connect without the password login
+// flow, and a user that doesn't exist yet is created without a password.
+
+use crate::config::Config;
+use anyhow::{anyhow, Result};
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+
+/// Longest username accepted from the header
+const MAX_USERNAME_LENGTH: usize = 255;
+
+/// Refuse to start with header auth on but no proxy to trust
+pub fn validate(config: &Config) -> Result<()> {
+    if config.auth_header.is_some() && config.trusted_proxies.is_empty() {
+        return Err(anyhow!(
+            "DOCKRU_AUTH_HEADER needs DOCKRU_TRUSTED_PROXIES, or any client could log in as anyone"
+        ));
+    }
+    Ok(())
+}
+
+/// The username a trusted proxy vouches for
+///
+/// None if header auth is off, the connection doesn't come from a trusted
+/// proxy, or the header is missing or not a plausible username.
+pub fn trusted_identity(
+    config: &Config,
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let header = config.auth_header.as_deref()?;
+    let peer = peer?;
+    if !config.trusted_proxies.iter().any(|net| net.contains(&peer)) {
+        return None;
+    }
+
+    let username = headers.get(header)?.to_str().ok()?.trim();
+    let valid = !username.is_empty()
+        && username.len() <= MAX_USERNAME_LENGTH
+        && !username.chars().any(char::is_control);
+    valid.then(|| username.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_trusted_identity() {
+        let config = Config::parse_from([
+            "dockru",
+            "--auth-header",
+            "Remote-User",
+            "--trusted-proxies",
+            "10.0.0.0/8,192.168.1.5/32",
+        ]);
+        assert!(validate(&config).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("remote-user", " alice ".parse().unwrap());
+        let proxy = Some("10.1.2.3".parse().unwrap());
+
+        assert_eq!(
+            trusted_identity(&config, proxy, &headers).as_deref(),
+            Some("alice")
+        );
+        assert_eq!(
+            trusted_identity(&config, Some("192.168.1.5".parse().unwrap()), &headers).as_deref(),
+            Some("alice")
+        );
+        // Not from a trusted proxy
+        assert_eq!(
+            trusted_identity(&config, Some("192.168.1.6".parse().unwrap()), &headers),
+            None
+        );
+        assert_eq!(trusted_identity(&config, None, &headers), None);
+        assert_eq!(trusted_identity(&config, proxy, &HeaderMap::new()), None);
+
+        headers.insert("remote-user", "".parse().unwrap());
+        assert_eq!(trusted_identity(&config, proxy, &headers), None);
+
+        // Off unless configured
+        let config = Config::parse_from(["dockru"]);
+        headers.insert("remote-user", "alice".parse().unwrap());
+        assert_eq!(trusted_identity(&config, proxy, &headers), None);
+
+        let config = Config::parse_from(["dockru", "--auth-header", "Remote-User"]);
+        assert!(validate(&config).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+// Image archives
+//
+// For hosts that can't pull from a registry, a stack's images can be saved to
+// a tarball (`docker save`) on a connected host and loaded (`docker load`) on
+// the air-gapped one before deploying.
+//
+// A stack's images are the ones its resolved compose model names: a service's
+// `image`, or `<project>-<service>` for a service that is only built, as
+// compose names those. Every image must be present on the daemon the stack is
+// deployed to; pull or build the stack first. Tags are kept, so the loaded
+// images are found by compose without pulling.
+//
+// Archives are streamed both ways and never held in memory in full. An upload
+// larger than MAX_IMAGE_ARCHIVE_BYTES is cut off and fails to load.
+
+use crate::docker::DockerHandle;
+use anyhow::{anyhow, bail, Result};
+use axum::body::Bytes;
+use bollard::image::ImportImageOptions;
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The images a stack's resolved compose model uses, sorted
+pub fn stack_images(config: &Value) -> Vec<String> {
+    let project = config["name"].as_str().unwrap_or_default();
+    let Some(services) = config["services"].as_object() else {
+        return Vec::new();
+    };
+    let mut images: Vec<String> = services
+        .iter()
+        .filter_map(|(name, service)| match service["image"].as_str() {
+            Some(image) => Some(image.to_string()),
+            None if !service["build"].is_null() && !project.is_empty() => {
+                Some(format!("{}-{}", project, name))
+            }
+            None => None,
+        })
+        .collect();
+    images.sort();
+    images.dedup();
+    images
+}
+
+/// Stream a tarball of the images, after checking they are all present
+pub async fn save_images(
+    docker: &DockerHandle,
+    images: &[String],
+) -> Result<impl Stream<Item = Result<Bytes, bollard::errors::Error>>> {
+    if images.is_empty() {
+        bail!("The stack doesn't use any images");
+    }
+    let mut missing = Vec::new();
+    for image in images {
+        let inspect = docker
+            .run(|d| {
+                let image = image.clone();
+                async move { d.inspect_image(&image).await }
+            })
+            .await;
+        if inspect.is_err() {
+            missing.push(image.as_str());
+        }
+    }
+    if !missing.is_empty() {
+        bail!("Pull or build these images first: {}", missing.join(", "));
+    }
+
+    let names: Vec<&str> = images.iter().map(String::as_str).collect();
+    Ok(docker.client().export_images(&names))
+}
+
+/// Load the images of an uploaded tarball, returning what was loaded
+///
+/// Docker prints `Loaded image: <tag>` for tagged images and
+/// `Loaded image ID: <id>` for the others.
+pub async fn load_images<S>(docker: &DockerHandle, archive: S) -> Result<Vec<String>>
+where
+    S: Stream<Item = Bytes> + Send + 'static,
+{
+    let options = ImportImageOptions { quiet: true };
+    let mut progress = docker.client().import_image_stream(options, archive, None);
+    let mut loaded = Vec::new();
+    while let Some(info) = progress.next().await {
+        let info = info.map_err(|e| anyhow!("docker load failed: {}", e))?;
+        if let Some(output) = info.stream {
+            loaded.extend(loaded_images(&output));
+        }
+    }
+    if loaded.is_empty() {
+        bail!("The archive didn't contain any images");
+    }
+    Ok(loaded)
+}
+
+/// The images named in `docker load` output
+fn loaded_images(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("Loaded image: ")
+                .or_else(|| line.strip_prefix("Loaded image ID: "))
+        })
+        .map(|image| image.trim().to_string())
+        .collect()
+}
+
+/// End a stream once more than `max` bytes went through, setting `exceeded`
+pub fn limit_stream<S, E>(
+    stream: S,
+    max: u64,
+    exceeded: Arc<AtomicBool>,
+) -> impl Stream<Item = Bytes> + Send + 'static
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+{
+    stream
+        .scan(0u64, move |total, chunk| {
+            let chunk = chunk.ok().filter(|chunk| {
+                *total += chunk.len() as u64;
+                if *total > max {
+                    exceeded.store(true, Ordering::Relaxed);
+                }
+                *total <= max
+            });
+            futures_util::future::ready(chunk)
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stack_images() {
+        let config = json!({
+            "name": "web",
+            "services": {
+                "app": { "build": { "context": "." } },
+                "db": { "image": "postgres:16" },
+                "worker": { "image": "postgres:16" },
+                "proxy": { "image": "ghcr.io/acme/proxy:1.2", "build": { "context": "proxy" } },
+            }
+        });
+        assert_eq!(
+            stack_images(&config),
+            vec!["ghcr.io/acme/proxy:1.2", "postgres:16", "web-app"]
+        );
+        assert!(stack_images(&json!({ "name": "web" })).is_empty());
+    }
+
+    #[test]
+    fn test_loaded_images() {
+        let output = "Loaded image: nginx:1.25\nLoaded image ID: sha256:abc\n";
+        assert_eq!(loaded_images(output), vec!["nginx:1.25", "sha256:abc"]);
+        assert!(loaded_images("Loading layer 1/3\n").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_limit_stream() {
+        let chunks = || {
+            futures_util::stream::iter(
+                ["abc", "def", "gh"].map(|s| Ok::<_, std::io::Error>(Bytes::from(s))),
+            )
+        };
+
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let all: Vec<Bytes> = limit_stream(chunks(), 8, exceeded.clone()).collect().await;
+        assert_eq!(all.len(), 3);
+        assert!(!exceeded.load(Ordering::Relaxed));
+
+        let cut: Vec<Bytes> = limit_stream(chunks(), 5, exceeded.clone()).collect().await;
+        assert_eq!(cut, vec![Bytes::from("abc")]);
+        assert!(exceeded.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,560 @@
+// Image update checking
+//
+// Periodically compares the local digest of every service image in every managed
+// stack against the registry:
+//   1. Local digests come from `RepoDigests` in the Docker image inspect response
+//   2. The remote digest comes from a registry v2 manifest HEAD request, using an
+//      anonymous bearer token when the registry asks for one
+//
+// Results are kept in memory per stack/service and included in the stackList
+// broadcast. Images pinned by digest, built locally, or using variables are skipped.
+//
+// Runs every 6 hours, can be disabled with the `checkImageUpdates` setting.
+//
+// Stacks with an update window get the updates found applied automatically
+// (`docker compose pull && up`), but only inside their window; until then the
+// update is pending. A failed update isn't retried for an hour. In a cluster
+// only the leader applies updates.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::db::models::{Setting, StackUpdateWindow};
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use crate::terminal::Terminal;
+use crate::utils::terminal::get_compose_terminal_name;
+
+/// How often images are checked
+const CHECK_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
+/// How often stacks with an update window are checked for pending updates
+const APPLY_INTERVAL_SECS: u64 = 60;
+
+/// Wait before retrying an update that failed
+const APPLY_RETRY_SECS: u64 = 60 * 60;
+
+/// Manifest media types accepted when asking a registry for a digest
+const MANIFEST_ACCEPT: &str = "application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json, \
+    application/vnd.oci.image.manifest.v1+json";
+
+/// Per-stack map of service name -> update available
+type UpdateMap = HashMap<String, HashMap<String, bool>>;
+
+static IMAGE_UPDATES: once_cell::sync::Lazy<Arc<RwLock<UpdateMap>>> =
+    once_cell::sync::Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
+
+/// When an update was last attempted, per stack
+static LAST_APPLY_ATTEMPT: once_cell::sync::Lazy<std::sync::Mutex<HashMap<String, Instant>>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+/// A parsed image reference (registry host, repository path and tag)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+}
+
+/// Parse an image name like `nginx`, `ghcr.io/org/app:1.2` or `localhost:5000/app`
+///
+/// Returns None for images pinned by digest, since those can't go stale.
+pub fn parse_image_reference(image: &str) -> Option<ImageReference> {
+    let image = image.trim();
+    if image.is_empty() || image.contains('@') {
+        return None;
+    }
+
+    // A colon after the last slash separates the tag
+    let (name, tag) = match image.rfind(':') {
+        Some(pos) if pos > image.rfind('/').unwrap_or(0) => (&image[..pos], &image[pos + 1..]),
+        _ => (image, "latest"),
+    };
+
+    let (registry, repository) = match name.split_once('/') {
+        Some((first, rest))
+            if first.contains('.') || first.contains(':') || first == "localhost" =>
+        {
+            (first.to_string(), rest.to_string())
+        }
+        _ => ("docker.io".to_string(), name.to_string()),
+    };
+
+    let (registry, repository) = if registry == "docker.io" {
+        let repository = if repository.contains('/') {
+            repository
+        } else {
+            format!("library/{}", repository)
+        };
+        ("registry-1.docker.io".to_string(), repository)
+    } else {
+        (registry, repository)
+    };
+
+    Some(ImageReference {
+        registry,
+        repository,
+        tag: tag.to_string(),
+    })
+}
+
+/// Collect `service -> image` from a compose file
+pub fn service_images(compose_yaml: &str) -> HashMap<String, String> {
+    let mut images = HashMap::new();
+
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return images;
+    };
+    let Some(Yaml::Hash(services)) = docs.first().map(|doc| &doc["services"]) else {
+        return images;
+    };
+
+    for (name, service) in services {
+        if let (Some(name), Some(image)) = (name.as_str(), service["image"].as_str()) {
+            images.insert(name.to_string(), image.to_string());
+        }
+    }
+
+    images
+}
+
+/// Parse the parameters of a `WWW-Authenticate: Bearer ...` challenge
+fn parse_bearer_challenge(header: &str) -> Option<HashMap<String, String>> {
+    let params = header.strip_prefix("Bearer ")?;
+    let mut map = HashMap::new();
+
+    for part in params.split(',') {
+        if let Some((key, value)) = part.trim().split_once('=') {
+            map.insert(key.to_string(), value.trim_matches('"').to_string());
+        }
+    }
+
+    Some(map)
+}
+
+/// Fetch the current digest of an image tag from its registry
+async fn remote_digest(client: &reqwest::Client, image: &ImageReference) -> Result<String> {
+    let url = format!(
+        "https://{}/v2/{}/manifests/{}",
+        image.registry, image.repository, image.tag
+    );
+
+    let mut response = client
+        .head(&url)
+        .header("Accept", MANIFEST_ACCEPT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach registry {}", image.registry))?;
+
+    // Most registries require an (anonymous) token even for public images
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get("www-authenticate")
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_bearer_challenge)
+            .ok_or_else(|| anyhow!("Registry {} requires unsupported auth", image.registry))?;
+
+        let realm = challenge
+            .get("realm")
+            .ok_or_else(|| anyhow!("Missing realm in auth challenge"))?;
+        let mut query = vec![("scope", format!("repository:{}:pull", image.repository))];
+        if let Some(service) = challenge.get("service") {
+            query.push(("service", service.clone()));
+        }
+
+        let token_resp: serde_json::Value = client
+            .get(realm)
+            .query(&query)
+            .send()
+            .await
+            .context("Failed to fetch registry token")?
+            .json()
+            .await
+            .context("Failed to parse registry token response")?;
+
+        let token = token_resp["token"]
+            .as_str()
+            .or_else(|| token_resp["access_token"].as_str())
+            .ok_or_else(|| anyhow!("Missing token in registry token response"))?;
+
+        response = client
+            .head(&url)
+            .header("Accept", MANIFEST_ACCEPT)
+            .bearer_auth(token)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach registry {}", image.registry))?;
+    }
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Registry returned {} for {}",
+            response.status(),
+            url
+        ));
+    }
+
+    response
+        .headers()
+        .get("docker-content-digest")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("Registry did not return a digest for {}", url))
+}
+
+/// Digests the local copy of an image is known by. None if the image isn't present.
+async fn local_digests(ctx: &ServerContext, image: &str) -> Option<Vec<String>> {
+    let image = image.to_string();
+    let inspect = ctx
+        .docker
+        .run(|d| {
+            let image = image.clone();
+            async move { d.inspect_image(&image).await }
+        })
+        .await
+        .ok()?;
+
+    Some(
+        inspect
+            .repo_digests
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|d| d.split_once('@').map(|(_, digest)| digest.to_string()))
+            .collect(),
+    )
+}
+
+/// Check whether a newer image is available for a single image name
+///
+/// Returns None when the image can't be checked (pinned, local-only, not pulled).
+async fn check_image(ctx: &ServerContext, client: &reqwest::Client, image: &str) -> Option<bool> {
+    if image.contains('$') {
+        return None;
+    }
+    let reference = parse_image_reference(image)?;
+
+    let local = local_digests(ctx, image).await?;
+    if local.is_empty() {
+        // Built locally or loaded from a tarball, nothing to compare against
+        return None;
+    }
+
+    match remote_digest(client, &reference).await {
+        Ok(remote) => Some(!local.contains(&remote)),
+        Err(e) => {
+            debug!("Image update check failed for {}: {}", image, e);
+            None
+        }
+    }
+}
+
+/// Check every service image of every managed stack
+pub async fn check_all(ctx: Arc<ServerContext>) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .user_agent(format!("dockru/{}", ctx.version_checker.version()))
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to build HTTP client")?;
+
+    let stack_list = Stack::get_stack_list(ctx.clone(), String::new(), false).await?;
+    let mut results: UpdateMap = HashMap::new();
+
+    for (name, mut stack) in stack_list {
+        if !stack.is_managed_by_dockru().await {
+            continue;
+        }
+
+        let compose_yaml = match stack.compose_yaml().await {
+            Ok(yaml) => yaml,
+            Err(e) => {
+                warn!("Skipping image update check of {}: {:#}", name, e);
+                continue;
+            }
+        };
+        let mut images = service_images(&compose_yaml);
+        for file in stack.included_files().await.unwrap_or_default() {
+            if let Some(content) = &file.content {
+                images.extend(service_images(content));
+            }
+        }
+
+        let mut services = HashMap::new();
+        for (service, image) in images {
+            if let Some(available) = check_image(&ctx, &client, &image).await {
+                services.insert(service, available);
+            }
+        }
+
+        if !services.is_empty() {
+            results.insert(name, services);
+        }
+    }
+
+    let stale = results
+        .values()
+        .filter(|services| services.values().any(|v| *v))
+        .count();
+    info!(
+        "Image update check complete, {} stack(s) have updates",
+        stale
+    );
+
+    *IMAGE_UPDATES.write().await = results;
+
+    Ok(())
+}
+
+/// Get the update status of a stack's services, if it has been checked
+pub async fn get_stack_updates(stack_name: &str) -> HashMap<String, bool> {
+    IMAGE_UPDATES
+        .read()
+        .await
+        .get(stack_name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Mark a stack's images as up to date after it was updated
+async fn clear_stack_updates(stack_name: &str) {
+    if let Some(services) = IMAGE_UPDATES.write().await.get_mut(stack_name) {
+        services
+            .values_mut()
+            .for_each(|available| *available = false);
+    }
+}
+
+/// Whether an attempt is allowed now, recording it if so
+fn take_apply_attempt(stack_name: &str, now: Instant) -> bool {
+    let mut attempts = LAST_APPLY_ATTEMPT.lock().unwrap();
+    let retry = Duration::from_secs(APPLY_RETRY_SECS);
+    if attempts
+        .get(stack_name)
+        .is_some_and(|last| now.duration_since(*last) < retry)
+    {
+        return false;
+    }
+    attempts.insert(stack_name.to_string(), now);
+    true
+}
+
+/// Update the stacks whose window is open and that have an update pending
+pub async fn apply_pending(ctx: &Arc<ServerContext>) -> Result<()> {
+    let windows = StackUpdateWindow::find_all(&ctx.db).await?;
+    let now = chrono::Local::now();
+    let mut applied = false;
+
+    for (name, window) in windows {
+        if !window.contains(&now) || !get_stack_updates(&name).await.values().any(|v| *v) {
+            continue;
+        }
+        // Don't get in the way of a deploy/stop/etc. that's running; try next tick
+        if Terminal::get_terminal(&get_compose_terminal_name("", &name))
+            .await
+            .is_some()
+        {
+            continue;
+        }
+        if !take_apply_attempt(&name, Instant::now()) {
+            continue;
+        }
+
+        info!(
+            "Applying image updates to {} in its update window {}",
+            name, window
+        );
+        let result = match Stack::get_stack(ctx.clone(), &name, String::new()).await {
+            Ok(mut stack) => {
+                let result = stack.update(false, None).await;
+                stack.record_event("update", "update-window", &result).await;
+                result
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(0) => {
+                clear_stack_updates(&name).await;
+                applied = true;
+            }
+            Ok(code) => warn!("Update of {} exited with code {}", name, code),
+            Err(e) => warn!("Update of {} failed: {:#}", name, e),
+        }
+    }
+
+    if applied {
+        ctx.broadcast_notify.notify_one();
+    }
+    Ok(())
+}
+
+/// Apply pending updates, unless another cluster node does
+///
+/// The nodes of a cluster share the Docker host and stacks, so only the leader
+/// updates them.
+async fn apply_pending_if_leader(ctx: &Arc<ServerContext>) -> Result<()> {
+    if !crate::cluster::is_leader(ctx) {
+        return Ok(());
+    }
+    apply_pending(ctx).await
+}
+
+/// Start applying pending updates in update windows (every minute)
+pub fn start_update_windows(ctx: Arc<ServerContext>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(APPLY_INTERVAL_SECS));
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = apply_pending_if_leader(&ctx).await {
+                warn!("Failed to apply pending image updates: {:#}", e);
+            }
+        }
+    })
+}
+
+/// Start periodic image update checking (every 6 hours)
+pub fn start_interval(ctx: Arc<ServerContext>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(tokio::time::Duration::from_secs(CHECK_INTERVAL_SECS));
+
+        loop {
+            interval.tick().await;
+
+            let enabled = Setting::get(&ctx.db, &ctx.cache, "checkImageUpdates")
+                .await
+                .ok()
+                .flatten()
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true);
+            if !enabled {
+                debug!("Image update check disabled in settings");
+                continue;
+            }
+
+            if let Err(e) = check_all(ctx.clone()).await {
+                info!("Image update check failed: {}", e);
+            }
+
+            // Push the new status to clients
+            ctx.broadcast_notify.notify_one();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(registry: &str, repository: &str, tag: &str) -> ImageReference {
+        ImageReference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            tag: tag.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_image_reference() {
+        assert_eq!(
+            parse_image_reference("nginx"),
+            Some(reference("registry-1.docker.io", "library/nginx", "latest"))
+        );
+        assert_eq!(
+            parse_image_reference("louislam/uptime-kuma:1"),
+            Some(reference(
+                "registry-1.docker.io",
+                "louislam/uptime-kuma",
+                "1"
+            ))
+        );
+        assert_eq!(
+            parse_image_reference("ghcr.io/kyeotic/dockru:latest"),
+            Some(reference("ghcr.io", "kyeotic/dockru", "latest"))
+        );
+        assert_eq!(
+            parse_image_reference("localhost:5000/app"),
+            Some(reference("localhost:5000", "app", "latest"))
+        );
+        assert_eq!(
+            parse_image_reference("docker.io/library/redis:7"),
+            Some(reference("registry-1.docker.io", "library/redis", "7"))
+        );
+        assert_eq!(parse_image_reference("nginx@sha256:abc"), None);
+        assert_eq!(parse_image_reference(""), None);
+    }
+
+    #[test]
+    fn test_service_images() {
+        let yaml = "services:\n  web:\n    image: nginx:1.25\n  app:\n    build: .\n";
+        let images = service_images(yaml);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images.get("web").map(String::as_str), Some("nginx:1.25"));
+
+        assert!(service_images("not: [valid").is_empty());
+    }
+
+    #[test]
+    fn test_take_apply_attempt() {
+        let now = Instant::now();
+        assert!(take_apply_attempt("update-window-test", now));
+        assert!(!take_apply_attempt(
+            "update-window-test",
+            now + Duration::from_secs(60)
+        ));
+        assert!(take_apply_attempt(
+            "update-window-test",
+            now + Duration::from_secs(APPLY_RETRY_SECS)
+        ));
+    }
+
+    #[test]
+    fn test_parse_bearer_challenge() {
+        let header = r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull""#;
+        let challenge = parse_bearer_challenge(header).unwrap();
+        assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge["service"], "registry.docker.io");
+
+        assert!(parse_bearer_challenge("Basic realm=\"x\"").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_windows_only_applied_by_leader() {
+        let db = crate::test_support::test_db().await;
+        let now = chrono::Local::now();
+        let later = now + chrono::Duration::minutes(5);
+        let window: crate::utils::update_window::UpdateWindow =
+            format!("{}-{}", now.format("%H:%M"), later.format("%H:%M"))
+                .parse()
+                .unwrap();
+        StackUpdateWindow::set(db.pool(), "window-leader-test", Some(&window))
+            .await
+            .unwrap();
+        IMAGE_UPDATES.write().await.insert(
+            "window-leader-test".to_string(),
+            HashMap::from([("web".to_string(), true)]),
+        );
+        let attempted = || {
+            LAST_APPLY_ATTEMPT
+                .lock()
+                .unwrap()
+                .contains_key("window-leader-test")
+        };
+
+        // A cluster node isn't the leader until its first heartbeat
+        let follower = crate::test_support::test_context(&db, &["--cluster-node-id", "n2"]).await;
+        apply_pending_if_leader(&follower).await.unwrap();
+        assert!(!attempted());
+
+        let single = crate::test_support::test_context(&db, &[]).await;
+        apply_pending_if_leader(&single).await.unwrap();
+        assert!(attempted());
+    }
+}
@@ -1,23 +1,56 @@
 // Main entry point for Dockru Rust backend
 mod agent_manager;
+mod archive;
+mod autostart;
+mod backup;
 mod auth;
 mod broadcasts;
+#[cfg(feature = "chaos")]
+mod chaos;
 mod check_version;
+mod cluster;
 mod config;
+mod container_files;
+mod container_inspect;
+mod container_stats;
+mod crash_report;
 mod db;
+mod discovery;
+mod disk_usage;
 mod docker;
+mod docker_events;
+mod docker_host;
+mod docker_watchdog;
+mod exposure;
+mod header_auth;
+mod image_archive;
+mod image_updates;
+mod migration;
+mod networks;
+mod probe;
 mod rate_limiter;
+mod relocation;
+mod rest;
+mod scheduler;
 mod server;
 mod socket_auth;
 mod socket_handlers;
 mod stack;
 mod static_files;
 mod terminal;
+mod terminal_audit;
+#[cfg(test)]
+mod test_support;
+mod uptime;
 mod utils;
+mod webhook;
 
 use anyhow::Result;
 use tracing::info;
 
+#[cfg(all(feature = "chaos", not(debug_assertions)))]
+compile_error!("The chaos feature injects failures and is only for development builds");
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
@@ -33,6 +66,8 @@ async fn main() -> Result<()> {
         .init();
 
     info!("Welcome to dockru!");
+    #[cfg(feature = "chaos")]
+    tracing::warn!("Built with the chaos feature, failures can be injected with debugInject");
 
     // Parse configuration
     let config = config::Config::parse()?;
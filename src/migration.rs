@@ -0,0 +1,477 @@
+// Stack migration from other tools
+//
+// Creates stacks in bulk from what another tool manages:
+//
+// - dockge: a Dockge stacks directory on this host, with one directory per
+//   stack holding its compose file and .env (the same layout as ours)
+// - portainer: the stacks of a Portainer export, the JSON array returned by
+//   GET /api/stacks with each stack's StackFileContent (from
+//   GET /api/stacks/{id}/file) merged in. Env variables become the .env.
+//
+// Only the compose file and .env are copied. Other files next to a Dockge
+// compose file (usually bind-mounted data) stay where they are, and stacks
+// mounting relative paths get a warning to move them before deploying.
+//
+// A dry run reports what would happen to each stack without writing anything.
+// Otherwise each stack is created like an imported archive (validated, checked
+// against the compose policy); one failing doesn't stop the others, and stacks
+// whose name is taken are left alone.
+
+use crate::archive::ArchiveEntry;
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use crate::utils::constants::{
+    ACCEPTED_COMPOSE_FILE_NAMES, DEFAULT_COMPOSE_FILE_NAME, MAX_MIGRATION_FILE_BYTES,
+};
+use crate::utils::env_schema::quote_env_value;
+use crate::utils::stack_name::StackName;
+use crate::utils::yaml_utils::parse_yaml;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tracing::info;
+use yaml_rust2::Yaml;
+
+/// Portainer's stack type of compose stacks (1 is swarm, 3 kubernetes)
+const PORTAINER_COMPOSE_STACK_TYPE: u8 = 2;
+
+/// Where stacks are migrated from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MigrationSource {
+    Dockge {
+        /// Dockge's stacks directory on this host
+        path: String,
+    },
+    Portainer {
+        stacks: Vec<PortainerStack>,
+    },
+}
+
+/// A stack of a Portainer export, as Portainer names the fields
+#[derive(Debug, Clone, Deserialize)]
+pub struct PortainerStack {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Type", default)]
+    pub stack_type: Option<u8>,
+    #[serde(rename = "EntryPoint", default)]
+    pub entry_point: Option<String>,
+    #[serde(rename = "Env", default)]
+    pub env: Option<Vec<PortainerEnvVar>>,
+    #[serde(rename = "StackFileContent", default)]
+    pub stack_file_content: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PortainerEnvVar {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+/// A stack found in a source
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    /// Name in the source
+    source_name: String,
+    /// Name of the stack to create, or why there is none
+    name: Result<StackName, String>,
+    compose_file_name: String,
+    compose_yaml: String,
+    compose_env: String,
+    warnings: Vec<String>,
+    /// Why the stack can't be migrated
+    problem: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationStatus {
+    /// Would be created (dry run)
+    Ready,
+    Created,
+    /// A stack with the name exists
+    Exists,
+    /// Can't be migrated, see the error
+    Skipped,
+    Failed,
+}
+
+/// What happened (or would) to one stack
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MigrationResult {
+    /// Name in the source
+    pub source: String,
+    /// Name of the stack in dockru
+    pub name: Option<String>,
+    pub status: MigrationStatus,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Migrate the stacks of a source, or with `dry_run` only report what would happen
+///
+/// `only` limits the migration to the stacks with these (dockru) names.
+pub async fn migrate(
+    ctx: Arc<ServerContext>,
+    endpoint: String,
+    source: MigrationSource,
+    dry_run: bool,
+    only: Option<Vec<String>>,
+) -> Result<Vec<MigrationResult>> {
+    let candidates = match source {
+        MigrationSource::Dockge { path } => scan_dockge(Path::new(&path)).await?,
+        MigrationSource::Portainer { stacks } => {
+            stacks.into_iter().map(portainer_candidate).collect()
+        }
+    };
+
+    let mut claimed = HashSet::new();
+    let mut results = Vec::new();
+    for candidate in candidates {
+        if let (Some(only), Ok(name)) = (&only, &candidate.name) {
+            if !only.iter().any(|n| n == name.as_str()) {
+                continue;
+            }
+        }
+
+        let mut result = MigrationResult {
+            source: candidate.source_name.clone(),
+            name: candidate.name.as_ref().ok().map(|n| n.to_string()),
+            status: MigrationStatus::Skipped,
+            warnings: candidate.warnings.clone(),
+            error: None,
+        };
+        let name = match (&candidate.name, &candidate.problem) {
+            (Err(e), _) | (Ok(_), Some(e)) => {
+                result.error = Some(e.clone());
+                results.push(result);
+                continue;
+            }
+            (Ok(name), None) => name.clone(),
+        };
+
+        let taken = !claimed.insert(name.clone())
+            || fs::metadata(ctx.config.stacks_dir.join(&name))
+                .await
+                .is_ok();
+        if taken {
+            result.status = MigrationStatus::Exists;
+        } else if dry_run {
+            let mut stack = Stack::new_with_content(
+                ctx.clone(),
+                name,
+                endpoint.clone(),
+                candidate.compose_yaml,
+                candidate.compose_env,
+            );
+            match stack.validate().await {
+                Ok(()) => result.status = MigrationStatus::Ready,
+                Err(e) => {
+                    result.status = MigrationStatus::Failed;
+                    result.error = Some(format!("{:#}", e));
+                }
+            }
+        } else {
+            let entries = vec![
+                ArchiveEntry {
+                    path: PathBuf::from(&candidate.compose_file_name),
+                    data: Some(candidate.compose_yaml.into_bytes()),
+                },
+                ArchiveEntry {
+                    path: PathBuf::from(".env"),
+                    data: Some(candidate.compose_env.into_bytes()),
+                },
+            ];
+            match Stack::import(ctx.clone(), &name, endpoint.clone(), entries).await {
+                Ok(_) => {
+                    info!("Migrated stack {} from {}", name, candidate.source_name);
+                    result.status = MigrationStatus::Created;
+                }
+                Err(e) => {
+                    result.status = MigrationStatus::Failed;
+                    result.error = Some(format!("{:#}", e));
+                }
+            }
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// The stacks of a Dockge stacks directory, by directory name
+async fn scan_dockge(dir: &Path) -> Result<Vec<Candidate>> {
+    if !dir.is_absolute() {
+        bail!("The Dockge stacks directory must be an absolute path");
+    }
+    let mut read_dir = fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut stack_dirs = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            stack_dirs.push(entry.path());
+        }
+    }
+    stack_dirs.sort();
+
+    let mut candidates = Vec::new();
+    for stack_dir in stack_dirs {
+        let Some(compose_file_name) = ACCEPTED_COMPOSE_FILE_NAMES
+            .iter()
+            .find(|file_name| stack_dir.join(file_name).is_file())
+        else {
+            continue;
+        };
+        let source_name = stack_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut candidate = Candidate {
+            name: stack_name(&source_name),
+            source_name,
+            compose_file_name: compose_file_name.to_string(),
+            compose_yaml: String::new(),
+            compose_env: String::new(),
+            warnings: Vec::new(),
+            problem: None,
+        };
+        match read_small_file(&stack_dir.join(compose_file_name)).await {
+            Ok(Some(yaml)) => candidate.compose_yaml = yaml,
+            // Removed or renamed since it was found
+            Ok(None) => candidate.problem = Some("compose file disappeared".to_string()),
+            Err(e) => candidate.problem = Some(format!("{:#}", e)),
+        }
+        match read_small_file(&stack_dir.join(".env")).await {
+            Ok(env) => candidate.compose_env = env.unwrap_or_default(),
+            Err(e) => candidate.problem = Some(format!("{:#}", e)),
+        }
+
+        let mut others = Vec::new();
+        let mut entries = fs::read_dir(&stack_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if file_name != *compose_file_name && file_name != ".env" {
+                others.push(file_name);
+            }
+        }
+        if !others.is_empty() {
+            others.sort();
+            candidate
+                .warnings
+                .push(format!("Not copied: {}", others.join(", ")));
+        }
+        candidate
+            .warnings
+            .extend(relative_mount_warnings(&candidate.compose_yaml));
+        candidates.push(candidate);
+    }
+    Ok(candidates)
+}
+
+/// A file's contents, None if it doesn't exist
+async fn read_small_file(path: &Path) -> Result<Option<String>> {
+    let metadata = match fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if metadata.len() > MAX_MIGRATION_FILE_BYTES {
+        bail!(
+            "{} is larger than {} bytes",
+            path.display(),
+            MAX_MIGRATION_FILE_BYTES
+        );
+    }
+    let content = fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Some(content))
+}
+
+fn portainer_candidate(stack: PortainerStack) -> Candidate {
+    let name = stack_name(&stack.name);
+    let mut warnings = Vec::new();
+    if let Ok(name) = &name {
+        if name.as_str() != stack.name {
+            warnings.push(format!("Renamed from \"{}\"", stack.name));
+        }
+    }
+
+    let problem = match (stack.stack_type, &stack.stack_file_content) {
+        (Some(t), _) if t != PORTAINER_COMPOSE_STACK_TYPE => {
+            Some("Only compose stacks can be migrated, not swarm or kubernetes stacks".to_string())
+        }
+        (_, None) => Some("The export has no StackFileContent for this stack".to_string()),
+        _ => None,
+    };
+
+    let compose_file_name = stack
+        .entry_point
+        .filter(|e| ACCEPTED_COMPOSE_FILE_NAMES.contains(&e.as_str()))
+        .unwrap_or_else(|| DEFAULT_COMPOSE_FILE_NAME.to_string());
+    let compose_yaml = stack.stack_file_content.unwrap_or_default();
+    let compose_env: String = stack
+        .env
+        .unwrap_or_default()
+        .iter()
+        .map(|var| format!("{}={}\n", var.name, quote_env_value(&var.value)))
+        .collect();
+    warnings.extend(relative_mount_warnings(&compose_yaml));
+
+    Candidate {
+        source_name: stack.name,
+        name,
+        compose_file_name,
+        compose_yaml,
+        compose_env,
+        warnings,
+        problem,
+    }
+}
+
+/// The name a stack gets: lowercased, other characters than [a-z0-9_-] as `-`
+fn stack_name(source_name: &str) -> Result<StackName, String> {
+    let name: String = source_name
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    StackName::parse(name.trim_matches('-')).map_err(|e| e.to_string())
+}
+
+/// Warnings for the relative host paths services mount, which aren't copied
+fn relative_mount_warnings(compose_yaml: &str) -> Vec<String> {
+    let Ok(docs) = parse_yaml(compose_yaml) else {
+        return Vec::new();
+    };
+    let Some(services) = docs.first().and_then(|d| d["services"].as_hash()) else {
+        return Vec::new();
+    };
+
+    let mut warnings = Vec::new();
+    for (service, definition) in services {
+        let service = service.as_str().unwrap_or_default();
+        for volume in definition["volumes"].as_vec().into_iter().flatten() {
+            let source = match volume {
+                Yaml::String(short) => short.split(':').next(),
+                long => long["source"].as_str(),
+            };
+            if let Some(source) = source.filter(|s| s.starts_with('.')) {
+                warnings.push(format!(
+                    "Service {} mounts {}, which isn't copied; move it into the new stack directory before deploying",
+                    service, source
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Parse the migration source of a request, with errors suitable for clients
+pub fn parse_source(value: serde_json::Value) -> Result<MigrationSource> {
+    serde_json::from_value(value).map_err(|e| anyhow!("Invalid migration source: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stack_name() {
+        assert_eq!(stack_name("web").unwrap().as_str(), "web");
+        assert_eq!(stack_name("My App.v2").unwrap().as_str(), "my-app-v2");
+        assert!(stack_name("..").is_err());
+        assert!(stack_name("con").is_err());
+    }
+
+    #[test]
+    fn test_portainer_candidate() {
+        let source = parse_source(json!({
+            "type": "portainer",
+            "stacks": [
+                {
+                    "Id": 3,
+                    "Name": "Nextcloud",
+                    "Type": 2,
+                    "EntryPoint": "docker-compose.yml",
+                    "Env": [
+                        { "name": "DB_PASSWORD", "value": "p4ss word" },
+                        { "name": "PORT", "value": "8080" }
+                    ],
+                    "StackFileContent": "services:\n  app:\n    image: nextcloud\n    volumes:\n      - ./data:/var/www/html\n"
+                },
+                { "Id": 4, "Name": "swarm", "Type": 1, "Env": null, "StackFileContent": "services: {}\n" },
+                { "Id": 5, "Name": "nofile", "Type": 2 }
+            ]
+        }))
+        .unwrap();
+        let MigrationSource::Portainer { stacks } = source else {
+            panic!("expected a portainer source");
+        };
+        let candidates: Vec<Candidate> = stacks.into_iter().map(portainer_candidate).collect();
+
+        let nextcloud = &candidates[0];
+        assert_eq!(nextcloud.name.as_ref().unwrap().as_str(), "nextcloud");
+        assert_eq!(nextcloud.compose_file_name, "docker-compose.yml");
+        assert_eq!(
+            nextcloud.compose_env,
+            "DB_PASSWORD='p4ss word'\nPORT=8080\n"
+        );
+        assert!(nextcloud.problem.is_none());
+        assert_eq!(nextcloud.warnings.len(), 2);
+        assert!(nextcloud.warnings[0].contains("Renamed"));
+        assert!(nextcloud.warnings[1].contains("./data"));
+
+        assert!(candidates[1].problem.is_some());
+        assert!(candidates[2].problem.is_some());
+    }
+
+    #[test]
+    fn test_relative_mount_warnings() {
+        let yaml = "services:\n  db:\n    image: postgres\n    volumes:\n      - db:/var/lib/postgresql/data\n      - type: bind\n        source: ../shared\n        target: /shared\n      - /etc/localtime:/etc/localtime:ro\nvolumes:\n  db: {}\n";
+        let warnings = relative_mount_warnings(yaml);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("../shared"));
+        assert!(relative_mount_warnings("not: [valid").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_dockge() {
+        let dir = TempDir::new().unwrap();
+        let web = dir.path().join("web");
+        std::fs::create_dir_all(web.join("data")).unwrap();
+        std::fs::write(
+            web.join("compose.yaml"),
+            "services:\n  web:\n    image: nginx\n",
+        )
+        .unwrap();
+        std::fs::write(web.join(".env"), "PORT=80\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("empty")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+
+        let candidates = scan_dockge(dir.path()).await.unwrap();
+        assert_eq!(candidates.len(), 1);
+        let web = &candidates[0];
+        assert_eq!(web.source_name, "web");
+        assert_eq!(web.compose_file_name, "compose.yaml");
+        assert_eq!(web.compose_env, "PORT=80\n");
+        assert_eq!(web.warnings, vec!["Not copied: data".to_string()]);
+
+        assert!(scan_dockge(Path::new("relative/stacks")).await.is_err());
+    }
+}
@@ -0,0 +1,391 @@
+// Docker network management
+//
+// Compose files can join networks declared `external: true`, which compose
+// won't create or remove, so they are shared between stacks and have to be
+// managed by hand. This covers what that takes: inspecting a network (driver,
+// subnets, attached containers), creating one and deleting one.
+//
+// Names come from clients and end up in Docker API paths, so they are checked
+// against Docker's own rules for network names first.
+
+use crate::docker::{BollardResultExt, DockerHandle};
+use anyhow::{anyhow, bail, Result};
+use bollard::models::{Ipam, IpamConfig, Network};
+use bollard::network::{CreateNetworkOptions, InspectNetworkOptions};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Driver of networks created without one
+const DEFAULT_DRIVER: &str = "bridge";
+
+/// Longest network name accepted
+const MAX_NAME_LEN: usize = 255;
+
+/// A subnet of a network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NetworkSubnet {
+    pub subnet: Option<String>,
+    pub gateway: Option<String>,
+}
+
+/// A container attached to a network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttachedContainer {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "ipv4Address")]
+    pub ipv4_address: Option<String>,
+    #[serde(rename = "ipv6Address")]
+    pub ipv6_address: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkDetails {
+    pub id: String,
+    pub name: String,
+    pub driver: String,
+    pub scope: String,
+    pub created: Option<String>,
+    pub internal: bool,
+    pub attachable: bool,
+    #[serde(rename = "enableIpv6")]
+    pub enable_ipv6: bool,
+    pub subnets: Vec<NetworkSubnet>,
+    /// Sorted by name
+    pub containers: Vec<AttachedContainer>,
+    pub labels: HashMap<String, String>,
+}
+
+impl From<Network> for NetworkDetails {
+    fn from(network: Network) -> Self {
+        let subnets = network
+            .ipam
+            .and_then(|ipam| ipam.config)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|config| NetworkSubnet {
+                subnet: config.subnet,
+                gateway: config.gateway,
+            })
+            .collect();
+
+        let mut containers: Vec<AttachedContainer> = network
+            .containers
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, container)| AttachedContainer {
+                name: container.name.unwrap_or_else(|| id.clone()),
+                id,
+                ipv4_address: container.ipv4_address.filter(|a| !a.is_empty()),
+                ipv6_address: container.ipv6_address.filter(|a| !a.is_empty()),
+            })
+            .collect();
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        NetworkDetails {
+            id: network.id.unwrap_or_default(),
+            name: network.name.unwrap_or_default(),
+            driver: network.driver.unwrap_or_default(),
+            scope: network.scope.unwrap_or_default(),
+            created: network.created.map(|c| c.to_string()),
+            internal: network.internal.unwrap_or(false),
+            attachable: network.attachable.unwrap_or(false),
+            enable_ipv6: network.enable_ipv6.unwrap_or(false),
+            subnets,
+            containers,
+            labels: network.labels.unwrap_or_default(),
+        }
+    }
+}
+
+/// What to create a network with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct NewNetwork {
+    pub name: String,
+    /// Defaults to bridge
+    pub driver: Option<String>,
+    /// CIDR, e.g. 172.30.0.0/16; Docker picks one when not given
+    pub subnet: Option<String>,
+    /// Must be in the subnet, which is then required
+    pub gateway: Option<String>,
+    /// No access to the outside
+    pub internal: bool,
+    /// Standalone containers can join an overlay network
+    pub attachable: bool,
+}
+
+impl NewNetwork {
+    /// Check the request and turn it into Docker's create options
+    fn into_options(self) -> Result<CreateNetworkOptions<String>> {
+        validate_network_name(&self.name)?;
+        let driver = match self.driver.filter(|d| !d.is_empty()) {
+            Some(driver) => {
+                validate_driver(&driver)?;
+                driver
+            }
+            None => DEFAULT_DRIVER.to_string(),
+        };
+
+        let subnet = self
+            .subnet
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<IpNet>()
+                    .map_err(|_| anyhow!("Invalid subnet \"{}\", expected CIDR notation", s))
+            })
+            .transpose()?;
+        let gateway = self
+            .gateway
+            .filter(|g| !g.is_empty())
+            .map(|g| {
+                g.parse::<IpAddr>()
+                    .map_err(|_| anyhow!("Invalid gateway \"{}\"", g))
+            })
+            .transpose()?;
+        if let Some(gateway) = gateway {
+            match subnet {
+                Some(subnet) if subnet.contains(&gateway) => {}
+                Some(subnet) => bail!("Gateway {} is not in subnet {}", gateway, subnet),
+                None => bail!("A gateway needs a subnet"),
+            }
+        }
+
+        let ipam = Ipam {
+            config: subnet.map(|subnet| {
+                vec![IpamConfig {
+                    subnet: Some(subnet.trunc().to_string()),
+                    gateway: gateway.map(|g| g.to_string()),
+                    ..Default::default()
+                }]
+            }),
+            ..Default::default()
+        };
+
+        Ok(CreateNetworkOptions {
+            name: self.name,
+            check_duplicate: true,
+            driver,
+            internal: self.internal,
+            attachable: self.attachable,
+            ipam,
+            enable_ipv6: matches!(subnet, Some(IpNet::V6(_))),
+            ..Default::default()
+        })
+    }
+}
+
+/// Check a network name or ID: [a-zA-Z0-9][a-zA-Z0-9_.-]*
+pub fn validate_network_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("Network name must not be empty");
+    }
+    if name.len() > MAX_NAME_LEN {
+        bail!("Network name is longer than {} characters", MAX_NAME_LEN);
+    }
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if !valid {
+        bail!(
+            "Network name \"{}\" can only contain [a-zA-Z0-9] _ . - and must start with a letter or digit",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Check a driver name, which may be a plugin like `weaveworks/net-plugin:latest`
+fn validate_driver(driver: &str) -> Result<()> {
+    let valid = driver.len() <= MAX_NAME_LEN
+        && driver
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '/' | ':'));
+    if !valid {
+        bail!("Invalid network driver \"{}\"", driver);
+    }
+    Ok(())
+}
+
+/// Inspect a network by name or ID
+pub async fn inspect_network(docker: &DockerHandle, name: &str) -> Result<NetworkDetails> {
+    validate_network_name(name)?;
+    let network = docker
+        .run(|d| async move {
+            d.inspect_network(name, None::<InspectNetworkOptions<String>>)
+                .await
+        })
+        .await
+        .docker_context(&format!("Failed to inspect network {}", name))?;
+    Ok(network.into())
+}
+
+/// Create a network, returning its ID
+pub async fn create_network(docker: &DockerHandle, new_network: NewNetwork) -> Result<String> {
+    let options = new_network.into_options()?;
+    let response = docker
+        .run(|d| {
+            let options = options.clone();
+            async move { d.create_network(options).await }
+        })
+        .await
+        .docker_context(&format!("Failed to create network {}", options.name))?;
+    Ok(response.id.unwrap_or_default())
+}
+
+/// Delete a network by name or ID
+///
+/// Docker refuses while containers are attached and for its predefined
+/// networks (bridge, host, none).
+pub async fn delete_network(docker: &DockerHandle, name: &str) -> Result<()> {
+    validate_network_name(name)?;
+    docker
+        .run(|d| async move { d.remove_network(name).await })
+        .await
+        .docker_context(&format!("Failed to delete network {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::NetworkContainer;
+
+    #[test]
+    fn test_validate_network_name() {
+        assert!(validate_network_name("proxy").is_ok());
+        assert!(validate_network_name("traefik_public-v2.1").is_ok());
+        assert!(validate_network_name("0f3a9c").is_ok());
+
+        assert!(validate_network_name("").is_err());
+        assert!(validate_network_name("-proxy").is_err());
+        assert!(validate_network_name("../containers/json").is_err());
+        assert!(validate_network_name("my network").is_err());
+        assert!(validate_network_name(&"a".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn test_new_network_options() {
+        let options = NewNetwork {
+            name: "proxy".to_string(),
+            subnet: Some("172.30.1.7/16".to_string()),
+            gateway: Some("172.30.0.1".to_string()),
+            internal: true,
+            ..Default::default()
+        }
+        .into_options()
+        .unwrap();
+        assert_eq!(options.driver, "bridge");
+        assert!(options.internal);
+        assert!(!options.enable_ipv6);
+        let config = &options.ipam.config.unwrap()[0];
+        assert_eq!(config.subnet.as_deref(), Some("172.30.0.0/16"));
+        assert_eq!(config.gateway.as_deref(), Some("172.30.0.1"));
+
+        let options = NewNetwork {
+            name: "v6".to_string(),
+            driver: Some("macvlan".to_string()),
+            subnet: Some("fd00:1::/64".to_string()),
+            ..Default::default()
+        }
+        .into_options()
+        .unwrap();
+        assert_eq!(options.driver, "macvlan");
+        assert!(options.enable_ipv6);
+
+        // Docker picks the subnet
+        let options = NewNetwork {
+            name: "shared".to_string(),
+            subnet: Some(String::new()),
+            ..Default::default()
+        }
+        .into_options()
+        .unwrap();
+        assert!(options.ipam.config.is_none());
+    }
+
+    #[test]
+    fn test_new_network_rejects_invalid() {
+        let invalid = |network: NewNetwork| network.into_options().unwrap_err().to_string();
+        let base = NewNetwork {
+            name: "proxy".to_string(),
+            ..Default::default()
+        };
+
+        assert!(invalid(NewNetwork {
+            subnet: Some("172.30.0.0".to_string()),
+            ..base.clone()
+        })
+        .contains("Invalid subnet"));
+        assert!(invalid(NewNetwork {
+            gateway: Some("172.30.0.1".to_string()),
+            ..base.clone()
+        })
+        .contains("needs a subnet"));
+        assert!(invalid(NewNetwork {
+            subnet: Some("172.30.0.0/16".to_string()),
+            gateway: Some("10.0.0.1".to_string()),
+            ..base.clone()
+        })
+        .contains("not in subnet"));
+        assert!(invalid(NewNetwork {
+            driver: Some("bridge; rm".to_string()),
+            ..base.clone()
+        })
+        .contains("Invalid network driver"));
+    }
+
+    #[test]
+    fn test_network_details_from_inspect() {
+        let network = Network {
+            name: Some("proxy".to_string()),
+            id: Some("abc123".to_string()),
+            driver: Some("bridge".to_string()),
+            scope: Some("local".to_string()),
+            ipam: Some(Ipam {
+                config: Some(vec![IpamConfig {
+                    subnet: Some("172.30.0.0/16".to_string()),
+                    gateway: Some("172.30.0.1".to_string()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            containers: Some(HashMap::from([
+                (
+                    "c2".to_string(),
+                    NetworkContainer {
+                        name: Some("web-nginx-1".to_string()),
+                        ipv4_address: Some("172.30.0.3/16".to_string()),
+                        ipv6_address: Some(String::new()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "c1".to_string(),
+                    NetworkContainer {
+                        name: Some("traefik-traefik-1".to_string()),
+                        ipv4_address: Some("172.30.0.2/16".to_string()),
+                        ..Default::default()
+                    },
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        let details = NetworkDetails::from(network);
+        assert_eq!(details.name, "proxy");
+        assert!(!details.internal);
+        assert_eq!(
+            details.subnets,
+            vec![NetworkSubnet {
+                subnet: Some("172.30.0.0/16".to_string()),
+                gateway: Some("172.30.0.1".to_string()),
+            }]
+        );
+        let names: Vec<&str> = details.containers.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["traefik-traefik-1", "web-nginx-1"]);
+        assert_eq!(details.containers[1].id, "c2");
+        assert_eq!(details.containers[1].ipv6_address, None);
+    }
+}
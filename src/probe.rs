@@ -0,0 +1,303 @@
+// Service reachability probes
+//
+// "It deployed, but is it actually reachable?" probeService connects to the
+// TCP ports a service's running containers publish, from the dockru host, and
+// reports whether the connection succeeded and how long it took. With a
+// scheme it also sends an HTTP GET and reports the response status.
+//
+// Ports published on all interfaces are probed on the loopback address, ports
+// bound to one address on that address. A host can be given instead, e.g. the
+// host name users reach the service by. When dockru itself runs in a
+// container or the stack runs on another Docker host, the loopback address
+// isn't the Docker host, so the host has to be given.
+//
+// HTTPS probes accept any certificate, since they check reachability rather
+// than the certificate. Redirects are reported rather than followed.
+
+use crate::docker::DockerHandle;
+use crate::utils::constants::PROBE_TIMEOUT_SECS;
+use anyhow::{anyhow, bail, Context, Result};
+use bollard::models::{ContainerSummary, PortTypeEnum};
+use serde::Serialize;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// What to probe, from probeService's options
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeOptions {
+    /// Connect to this host instead of the published address
+    pub host: Option<String>,
+    /// Only probe this host port
+    pub port: Option<u16>,
+    /// "http" or "https" to send a GET after connecting
+    pub scheme: Option<String>,
+    /// Path of the GET, "/" by default
+    pub path: Option<String>,
+}
+
+impl ProbeOptions {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(scheme) = &self.scheme {
+            if scheme != "http" && scheme != "https" {
+                bail!("scheme must be http or https");
+            }
+        }
+        if let Some(path) = &self.path {
+            if !path.starts_with('/') {
+                bail!("path must start with /");
+            }
+        }
+        if let Some(host) = &self.host {
+            let valid = !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '_'));
+            if !valid {
+                bail!("Invalid host \"{}\"", host);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of probing one published port
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProbeResult {
+    pub host: String,
+    #[serde(rename = "hostPort")]
+    pub host_port: u16,
+    #[serde(rename = "containerPort")]
+    pub container_port: u16,
+    pub reachable: bool,
+    /// Time to connect, or to the HTTP response
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: Option<f64>,
+    #[serde(rename = "httpStatus")]
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// A TCP port a container publishes, as (address to probe, host port, container port)
+type Target = (String, u16, u16);
+
+/// Probe the published TCP ports of a service's running containers
+pub async fn probe_service(
+    docker: &DockerHandle,
+    project_name: &str,
+    service_name: &str,
+    options: &ProbeOptions,
+) -> Result<Vec<ProbeResult>> {
+    let containers =
+        crate::container_inspect::service_containers(docker, project_name, service_name, false)
+            .await?;
+    let targets = probe_targets(&containers, options);
+    if targets.is_empty() {
+        match options.port {
+            Some(port) => bail!("Service {} doesn't publish port {}", service_name, port),
+            None => bail!("Service {} has no published TCP ports", service_name),
+        }
+    }
+
+    let client = match &options.scheme {
+        Some(_) => Some(
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(PROBE_TIMEOUT_SECS))
+                .redirect(reqwest::redirect::Policy::none())
+                .danger_accept_invalid_certs(true)
+                .build()
+                .context("Failed to build HTTP client")?,
+        ),
+        None => None,
+    };
+
+    let mut results = Vec::new();
+    for (host, host_port, container_port) in targets {
+        let mut result = ProbeResult {
+            host: host.clone(),
+            host_port,
+            container_port,
+            reachable: false,
+            latency_ms: None,
+            http_status: None,
+            error: None,
+        };
+        let outcome = match (&client, &options.scheme) {
+            (Some(client), Some(scheme)) => {
+                let path = options.path.as_deref().unwrap_or("/");
+                probe_http(client, scheme, &host, host_port, path)
+                    .await
+                    .map(|(latency, status)| {
+                        result.http_status = Some(status);
+                        latency
+                    })
+            }
+            _ => probe_tcp(&host, host_port).await,
+        };
+        match outcome {
+            Ok(latency) => {
+                result.reachable = true;
+                result.latency_ms = Some(latency.as_secs_f64() * 1000.0);
+            }
+            Err(e) => result.error = Some(e.to_string()),
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// The ports to probe, one per host port, sorted by host port
+fn probe_targets(containers: &[ContainerSummary], options: &ProbeOptions) -> Vec<Target> {
+    let mut targets: Vec<Target> = containers
+        .iter()
+        .flat_map(|c| c.ports.iter().flatten())
+        .filter(|port| port.typ.is_none() || port.typ == Some(PortTypeEnum::TCP))
+        .filter_map(|port| {
+            let host_port = port.public_port?;
+            let host = match &options.host {
+                Some(host) => host.clone(),
+                None => probe_address(port.ip.as_deref().unwrap_or_default()),
+            };
+            Some((host, host_port, port.private_port))
+        })
+        .filter(|(_, host_port, _)| options.port.map_or(true, |port| port == *host_port))
+        .collect();
+    // IPv4 addresses sort before IPv6 ones
+    targets.sort_by(|a, b| (a.1, a.0.contains(':'), &a.0).cmp(&(b.1, b.0.contains(':'), &b.0)));
+    // Published on both 0.0.0.0 and ::, probed once
+    targets.dedup_by_key(|(_, host_port, _)| *host_port);
+    targets
+}
+
+/// The address to reach a port published on `ip`
+fn probe_address(ip: &str) -> String {
+    match ip {
+        "" | "0.0.0.0" => "127.0.0.1".to_string(),
+        "::" => "::1".to_string(),
+        ip => ip.to_string(),
+    }
+}
+
+/// `host:port`, with brackets around IPv6 addresses
+fn socket_address(host: &str, port: u16) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    }
+}
+
+async fn probe_tcp(host: &str, port: u16) -> Result<Duration> {
+    let started = Instant::now();
+    let connect = TcpStream::connect(socket_address(host, port));
+    match tokio::time::timeout(Duration::from_secs(PROBE_TIMEOUT_SECS), connect).await {
+        Ok(Ok(_)) => Ok(started.elapsed()),
+        Ok(Err(e)) => Err(anyhow!("Connection failed: {}", e)),
+        Err(_) => Err(anyhow!(
+            "No connection within {} seconds",
+            PROBE_TIMEOUT_SECS
+        )),
+    }
+}
+
+async fn probe_http(
+    client: &reqwest::Client,
+    scheme: &str,
+    host: &str,
+    port: u16,
+    path: &str,
+) -> Result<(Duration, u16)> {
+    let url = format!("{}://{}{}", scheme, socket_address(host, port), path);
+    let started = Instant::now();
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| anyhow!("GET {} failed: {}", url, e))?;
+    Ok((started.elapsed(), response.status().as_u16()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::Port;
+
+    fn container(ports: &[(&str, Option<u16>, u16, PortTypeEnum)]) -> ContainerSummary {
+        ContainerSummary {
+            ports: Some(
+                ports
+                    .iter()
+                    .map(|(ip, public, private, typ)| Port {
+                        ip: Some(ip.to_string()),
+                        private_port: *private,
+                        public_port: *public,
+                        typ: Some(*typ),
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_probe_targets() {
+        let containers = [
+            container(&[
+                ("0.0.0.0", Some(8080), 80, PortTypeEnum::TCP),
+                ("::", Some(8080), 80, PortTypeEnum::TCP),
+                ("0.0.0.0", Some(53), 53, PortTypeEnum::UDP),
+                ("", None, 9000, PortTypeEnum::TCP),
+            ]),
+            container(&[("192.168.1.10", Some(8443), 443, PortTypeEnum::TCP)]),
+        ];
+
+        assert_eq!(
+            probe_targets(&containers, &ProbeOptions::default()),
+            vec![
+                ("127.0.0.1".to_string(), 8080, 80),
+                ("192.168.1.10".to_string(), 8443, 443),
+            ]
+        );
+
+        let options = ProbeOptions {
+            host: Some("nas.lan".to_string()),
+            port: Some(8443),
+            ..Default::default()
+        };
+        assert_eq!(
+            probe_targets(&containers, &options),
+            vec![("nas.lan".to_string(), 8443, 443)]
+        );
+    }
+
+    #[test]
+    fn test_socket_address() {
+        assert_eq!(socket_address("127.0.0.1", 80), "127.0.0.1:80");
+        assert_eq!(socket_address("::1", 80), "[::1]:80");
+        assert_eq!(socket_address("nas.lan", 80), "nas.lan:80");
+    }
+
+    #[test]
+    fn test_validate_options() {
+        assert!(ProbeOptions::default().validate().is_ok());
+        let options = |scheme: &str, path: &str, host: &str| ProbeOptions {
+            host: Some(host.to_string()),
+            port: None,
+            scheme: Some(scheme.to_string()),
+            path: Some(path.to_string()),
+        };
+        assert!(options("https", "/health", "nas.lan").validate().is_ok());
+        assert!(options("ftp", "/", "nas.lan").validate().is_err());
+        assert!(options("http", "health", "nas.lan").validate().is_err());
+        assert!(options("http", "/", "nas.lan/x").validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_tcp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(probe_tcp("127.0.0.1", port).await.is_ok());
+
+        drop(listener);
+        assert!(probe_tcp("127.0.0.1", port).await.is_err());
+    }
+}
@@ -88,6 +88,25 @@ impl ApiRateLimiter {
     }
 }
 
+/// Rate limiter for terminal resize events (20 per second per socket)
+pub struct TerminalResizeRateLimiter {
+    limiter: Arc<GovernorRateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>>,
+}
+
+impl TerminalResizeRateLimiter {
+    pub fn new() -> Self {
+        let quota = Quota::per_second(NonZeroU32::new(20).unwrap());
+        Self {
+            limiter: Arc::new(GovernorRateLimiter::dashmap(quota)),
+        }
+    }
+
+    /// Check if a resize from this socket should be allowed
+    pub fn check(&self, socket_id: &str) -> bool {
+        self.limiter.check_key(&socket_id.to_string()).is_ok()
+    }
+}
+
 /// Global rate limiters singleton
 #[allow(dead_code)]
 pub struct RateLimiters {
@@ -160,6 +179,20 @@ mod tests {
         assert!(limiter.check(ip).is_err());
     }
 
+    #[test]
+    fn test_terminal_resize_rate_limiter() {
+        let limiter = TerminalResizeRateLimiter::new();
+
+        // First 20 resizes should succeed
+        for _ in 0..20 {
+            assert!(limiter.check("socket-a"));
+        }
+
+        // 21st resize should be dropped, other sockets are unaffected
+        assert!(!limiter.check("socket-a"));
+        assert!(limiter.check("socket-b"));
+    }
+
     #[test]
     fn test_different_ips_independent() {
         let limiter = LoginRateLimiter::new();
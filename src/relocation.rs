@@ -0,0 +1,587 @@
+// Stacks directory relocation
+//
+// For installs that started out with the stacks directory on the wrong disk,
+// relocateStacksDir moves it to a new path while dockru keeps running:
+//
+// 1. Check: not in cluster mode; the new path is absolute, outside the stacks
+//    and data directories, and missing or empty; no compose command is running
+//    and no managed stack has running containers, whose bind-mounted data
+//    would keep changing underneath the copy.
+// 2. Pause: until the move is done, socket events other than reads are
+//    refused, compose commands (also from schedules and webhooks) don't start,
+//    and the open terminals are closed.
+// 3. Copy the directory, keeping permissions, ownership and symlinks, and
+//    compare every entry of the copy with the original.
+// 4. Swap: the old directory is renamed to `<name>.relocated-<time>`, kept as
+//    a backup to delete by hand, and replaced by a symlink to the new path.
+//    Paths dockru, compose and stopped containers already hold keep working.
+// 5. Record the move in the data directory. Starting with the same configured
+//    stacks directory then uses the new path directly; configuring another
+//    one ignores the record.
+//
+// Anything failing before the swap removes the copy and leaves everything as
+// it was. A stacks directory that is a mount point can't be swapped; it is
+// moved by mounting it elsewhere and changing DOCKRU_STACKS_DIR instead.
+
+use crate::config::Config;
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use crate::terminal::Terminal;
+use crate::utils::constants::{
+    PARTIAL, RELOCATION_RECORD_FILE_NAME, RESTARTING, RUNNING, UNHEALTHY, UNKNOWN,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Prefixes of socket events that only read, still handled during a move.
+/// `agent` wraps other events, which are checked when they are dispatched.
+const READ_EVENT_PREFIXES: [&str; 10] = [
+    "get", "list", "query", "inspect", "preview", "request", "login", "logout", "check", "agent",
+];
+
+/// Prefixes of the terminals of compose commands (see utils::terminal)
+const COMMAND_TERMINAL_PREFIXES: [&str; 2] = ["compose-", "batch-"];
+
+static RELOCATING: AtomicBool = AtomicBool::new(false);
+
+/// What relocateStacksDir did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Relocation {
+    pub from: String,
+    pub to: String,
+    /// Where the old directory was kept
+    pub backup: String,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// The move recorded in the data directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RelocationRecord {
+    /// The stacks directory dockru is configured with
+    configured: PathBuf,
+    to: PathBuf,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct CopyStats {
+    files: u64,
+    bytes: u64,
+}
+
+/// Clears the relocation flag when the move ends, however it ends
+struct RelocatingGuard;
+
+impl Drop for RelocatingGuard {
+    fn drop(&mut self) {
+        RELOCATING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Refuse an event that may write while the stacks directory is moved
+pub fn check_event(event: &str) -> Result<()> {
+    if is_read_event(event) {
+        return Ok(());
+    }
+    check_not_relocating()
+}
+
+fn is_read_event(event: &str) -> bool {
+    READ_EVENT_PREFIXES.iter().any(|p| event.starts_with(p))
+}
+
+/// Refuse to run a compose command while the stacks directory is moved
+pub fn check_not_relocating() -> Result<()> {
+    if RELOCATING.load(Ordering::SeqCst) {
+        bail!("The stacks directory is being relocated, try again when it's done");
+    }
+    Ok(())
+}
+
+/// Use the path the stacks directory was relocated to, if it was relocated
+/// from the configured one
+pub fn apply_record(mut config: Config) -> Config {
+    let Some(record) = read_record(&config.data_dir) else {
+        return config;
+    };
+    if record.configured == config.stacks_dir {
+        info!(
+            "Stacks directory {} was relocated to {}",
+            config.stacks_dir.display(),
+            record.to.display()
+        );
+        config.stacks_dir = record.to;
+    } else {
+        warn!(
+            "Ignoring the relocation of stacks directory {}, {} is configured",
+            record.configured.display(),
+            config.stacks_dir.display()
+        );
+    }
+    config
+}
+
+/// Move the stacks directory to `to`
+pub async fn relocate_stacks_dir(ctx: &Arc<ServerContext>, to: &Path) -> Result<Relocation> {
+    if ctx.cluster.is_some() {
+        bail!("The stacks directory can't be relocated in cluster mode");
+    }
+    let from = fs::canonicalize(&ctx.config.stacks_dir).with_context(|| {
+        format!(
+            "Failed to resolve stacks directory {}",
+            ctx.config.stacks_dir.display()
+        )
+    })?;
+    let data_dir =
+        fs::canonicalize(&ctx.config.data_dir).context("Failed to resolve data directory")?;
+    let to = resolve_target(to)?;
+    check_target(&from, &data_dir, &to)?;
+
+    if RELOCATING.swap(true, Ordering::SeqCst) {
+        bail!("The stacks directory is already being relocated");
+    }
+    let _guard = RelocatingGuard;
+
+    check_idle(ctx).await?;
+    crate::terminal::close_all_terminals().await;
+    info!(
+        "Relocating stacks directory {} to {}",
+        from.display(),
+        to.display()
+    );
+
+    let existed = to.exists();
+    let copy_from = from.clone();
+    let copy_to = to.clone();
+    let copied = tokio::task::spawn_blocking(move || -> Result<CopyStats> {
+        let mut stats = CopyStats::default();
+        copy_tree(&copy_from, &copy_to, &mut stats)
+            .context("Failed to copy the stacks directory")?;
+        verify_tree(&copy_from, &copy_to)?;
+        Ok(stats)
+    })
+    .await?;
+    let stats = match copied.and_then(|stats| swap(&from, &to).map(|backup| (stats, backup))) {
+        Ok(result) => result,
+        Err(e) => {
+            remove_copy(&to, existed);
+            return Err(e);
+        }
+    };
+    let (stats, backup) = stats;
+
+    let record = RelocationRecord {
+        configured: match read_record(&ctx.config.data_dir) {
+            Some(record) if record.to == ctx.config.stacks_dir => record.configured,
+            _ => ctx.config.stacks_dir.clone(),
+        },
+        to: to.clone(),
+    };
+    if let Err(e) = write_record(&ctx.config.data_dir, &record) {
+        warn!(
+            "Failed to record the relocation, {} is used through the symlink: {:#}",
+            to.display(),
+            e
+        );
+    }
+    info!(
+        "Relocated stacks directory to {} ({} files), the old one is kept as {}",
+        to.display(),
+        stats.files,
+        backup.display()
+    );
+    ctx.broadcast_notify.notify_one();
+
+    Ok(Relocation {
+        from: from.display().to_string(),
+        to: to.display().to_string(),
+        backup: backup.display().to_string(),
+        files: stats.files,
+        bytes: stats.bytes,
+    })
+}
+
+/// The absolute path of `to`, with its parent's symlinks resolved
+fn resolve_target(to: &Path) -> Result<PathBuf> {
+    if !to.is_absolute() {
+        bail!("The new stacks directory must be an absolute path");
+    }
+    let (Some(parent), Some(name)) = (to.parent(), to.file_name()) else {
+        bail!("Invalid stacks directory {}", to.display());
+    };
+    let parent =
+        fs::canonicalize(parent).with_context(|| format!("{} doesn't exist", parent.display()))?;
+    Ok(parent.join(name))
+}
+
+fn check_target(from: &Path, data_dir: &Path, to: &Path) -> Result<()> {
+    if to.starts_with(from) || from.starts_with(to) {
+        bail!("The new stacks directory can't contain or be inside the current one");
+    }
+    if to.starts_with(data_dir) || data_dir.starts_with(to) {
+        bail!("The new stacks directory can't contain or be inside the data directory");
+    }
+    match fs::symlink_metadata(to) {
+        Ok(metadata) if !metadata.is_dir() => bail!("{} isn't a directory", to.display()),
+        Ok(_) => {
+            let mut entries = fs::read_dir(to)?;
+            if entries.next().is_some() {
+                bail!("{} isn't empty", to.display());
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to check {}", to.display())),
+    }
+    Ok(())
+}
+
+/// Make sure nothing changes the stacks directory during the copy
+async fn check_idle(ctx: &Arc<ServerContext>) -> Result<()> {
+    let busy: Vec<String> = Terminal::get_all_terminals()
+        .await
+        .iter()
+        .map(|t| t.name().to_string())
+        .filter(|name| {
+            COMMAND_TERMINAL_PREFIXES
+                .iter()
+                .any(|p| name.starts_with(p))
+        })
+        .collect();
+    if !busy.is_empty() {
+        bail!(
+            "Wait for the running commands to finish: {}",
+            busy.join(", ")
+        );
+    }
+
+    let stack_list = Stack::get_stack_list(ctx.clone(), String::new(), false).await?;
+    let mut running = Vec::new();
+    for (name, stack) in &stack_list {
+        if !stack.is_managed_by_dockru().await {
+            continue;
+        }
+        match stack.status() {
+            UNKNOWN => bail!(
+                "Can't tell whether stack {} is running, is Docker reachable?",
+                name
+            ),
+            RUNNING | PARTIAL | RESTARTING | UNHEALTHY => running.push(name.clone()),
+            _ => {}
+        }
+    }
+    if !running.is_empty() {
+        running.sort();
+        bail!("Stop these stacks first: {}", running.join(", "));
+    }
+    Ok(())
+}
+
+/// Copy a directory tree, keeping permissions, ownership and symlinks
+///
+/// Special files (sockets, FIFOs, devices) are skipped.
+fn copy_tree(from: &Path, to: &Path, stats: &mut CopyStats) -> io::Result<()> {
+    let metadata = fs::metadata(from)?;
+    if !to.exists() {
+        fs::create_dir(to)?;
+    }
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        if file_type.is_symlink() {
+            symlink(&fs::read_link(entry.path())?, &target)?;
+            copy_owner(&entry.path(), &target)?;
+        } else if file_type.is_dir() {
+            copy_tree(&entry.path(), &target, stats)?;
+        } else if file_type.is_file() {
+            stats.bytes += fs::copy(entry.path(), &target)?;
+            copy_owner(&entry.path(), &target)?;
+            stats.files += 1;
+        }
+    }
+    fs::set_permissions(to, metadata.permissions())?;
+    copy_owner(from, to)
+}
+
+/// Compare the copy of a directory tree with the original
+fn verify_tree(from: &Path, to: &Path) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = to.join(entry.file_name());
+        let mismatch = || anyhow!("The copy of {} doesn't match", entry.path().display());
+        let copied = fs::symlink_metadata(&target).map_err(|_| mismatch())?;
+        if file_type.is_symlink() {
+            if !copied.file_type().is_symlink()
+                || fs::read_link(entry.path())? != fs::read_link(&target)?
+            {
+                return Err(mismatch());
+            }
+        } else if file_type.is_dir() {
+            if !copied.is_dir() {
+                return Err(mismatch());
+            }
+            verify_tree(&entry.path(), &target)?;
+        } else if file_type.is_file() && !same_content(&entry.path(), &target)? {
+            return Err(mismatch());
+        }
+    }
+    Ok(())
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let (mut buf_a, mut buf_b) = (vec![0u8; 64 * 1024], vec![0u8; 64 * 1024]);
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Replace `from` with a symlink to `to`, returning where `from` was kept
+fn swap(from: &Path, to: &Path) -> Result<PathBuf> {
+    let name = from
+        .file_name()
+        .ok_or_else(|| anyhow!("Can't relocate {}", from.display()))?;
+    let backup = from.with_file_name(format!(
+        "{}.relocated-{}",
+        name.to_string_lossy(),
+        Utc::now().format("%Y%m%d%H%M%S")
+    ));
+    fs::rename(from, &backup).with_context(|| {
+        format!(
+            "Failed to move {} aside; if it is a mount point, mount it at the new path and \
+             set DOCKRU_STACKS_DIR instead",
+            from.display()
+        )
+    })?;
+    if let Err(e) = symlink(to, from) {
+        fs::rename(&backup, from).ok();
+        return Err(e)
+            .with_context(|| format!("Failed to link {} to the new path", from.display()));
+    }
+    Ok(backup)
+}
+
+/// Undo a copy that didn't make it
+fn remove_copy(to: &Path, existed: bool) {
+    let result = if existed {
+        fs::read_dir(to).and_then(|entries| {
+            for entry in entries {
+                let path = entry?.path();
+                if fs::symlink_metadata(&path)?.is_dir() {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+            Ok(())
+        })
+    } else {
+        fs::remove_dir_all(to)
+    };
+    if let Err(e) = result {
+        warn!(
+            "Failed to remove the partial copy at {}: {}",
+            to.display(),
+            e
+        );
+    }
+}
+
+fn read_record(data_dir: &Path) -> Option<RelocationRecord> {
+    let path = data_dir.join(RELOCATION_RECORD_FILE_NAME);
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(record) => Some(record),
+        Err(e) => {
+            warn!("Ignoring invalid {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_record(data_dir: &Path, record: &RelocationRecord) -> Result<()> {
+    let path = data_dir.join(RELOCATION_RECORD_FILE_NAME);
+    fs::write(&path, serde_json::to_string_pretty(record)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Relocating the stacks directory needs symlinks",
+    ))
+}
+
+#[cfg(unix)]
+fn copy_owner(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::symlink_metadata(from)?;
+    let copied = fs::symlink_metadata(to)?;
+    if metadata.uid() == copied.uid() && metadata.gid() == copied.gid() {
+        return Ok(());
+    }
+    std::os::unix::fs::lchown(to, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_from: &Path, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_copy_and_verify_tree() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.path().join("stacks");
+        write(&from.join("web/compose.yaml"), "services: {}\n");
+        write(&from.join("web/data/db.sqlite"), "data");
+        write(&from.join("db/.env"), "TZ=UTC\n");
+        symlink(Path::new("../web/data"), &from.join("db/data")).unwrap();
+
+        let to = dir.path().join("moved");
+        let mut stats = CopyStats::default();
+        copy_tree(&from, &to, &mut stats).unwrap();
+        assert_eq!(
+            stats,
+            CopyStats {
+                files: 3,
+                bytes: 24
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(to.join("web/data/db.sqlite")).unwrap(),
+            "data"
+        );
+        assert_eq!(
+            fs::read_link(to.join("db/data")).unwrap(),
+            Path::new("../web/data")
+        );
+        verify_tree(&from, &to).unwrap();
+
+        write(&to.join("web/data/db.sqlite"), "dat4");
+        assert!(verify_tree(&from, &to).is_err());
+        fs::remove_file(to.join("db/.env")).unwrap();
+        assert!(verify_tree(&from, &to).is_err());
+    }
+
+    #[test]
+    fn test_check_target() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let (from, data) = (root.join("stacks"), root.join("data"));
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&data).unwrap();
+
+        assert!(check_target(&from, &data, &root.join("disk2/stacks")).is_ok());
+        assert!(check_target(&from, &data, &from.join("inner")).is_err());
+        assert!(check_target(&from, &data, root).is_err());
+        assert!(check_target(&from, &data, &data.join("stacks")).is_err());
+
+        let empty = root.join("empty");
+        fs::create_dir(&empty).unwrap();
+        assert!(check_target(&from, &data, &empty).is_ok());
+        write(&empty.join("x"), "");
+        assert!(check_target(&from, &data, &empty).is_err());
+        assert!(check_target(&from, &data, &empty.join("x")).is_err());
+
+        assert!(resolve_target(Path::new("stacks")).is_err());
+        assert!(resolve_target(&root.join("missing/stacks")).is_err());
+    }
+
+    #[test]
+    fn test_swap() {
+        let dir = TempDir::new().unwrap();
+        let (from, to) = (dir.path().join("stacks"), dir.path().join("moved"));
+        write(&from.join("web/compose.yaml"), "old");
+        write(&to.join("web/compose.yaml"), "new");
+
+        let backup = swap(&from, &to).unwrap();
+        assert!(backup.to_string_lossy().contains("stacks.relocated-"));
+        assert_eq!(
+            fs::read_to_string(backup.join("web/compose.yaml")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(from.join("web/compose.yaml")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn test_apply_record() {
+        let dir = TempDir::new().unwrap();
+        let data_dir = dir.path().to_str().unwrap();
+        let config = || {
+            <Config as Parser>::try_parse_from([
+                "dockru",
+                "--data-dir",
+                data_dir,
+                "--stacks-dir",
+                "/opt/stacks",
+            ])
+            .unwrap()
+        };
+        assert_eq!(apply_record(config()).stacks_dir, Path::new("/opt/stacks"));
+
+        let record = RelocationRecord {
+            configured: PathBuf::from("/opt/stacks"),
+            to: PathBuf::from("/mnt/disk2/stacks"),
+        };
+        write_record(dir.path(), &record).unwrap();
+        assert_eq!(
+            apply_record(config()).stacks_dir,
+            Path::new("/mnt/disk2/stacks")
+        );
+
+        let record = RelocationRecord {
+            configured: PathBuf::from("/srv/stacks"),
+            ..record
+        };
+        write_record(dir.path(), &record).unwrap();
+        assert_eq!(apply_record(config()).stacks_dir, Path::new("/opt/stacks"));
+    }
+
+    #[test]
+    fn test_is_read_event() {
+        assert!(is_read_event("getStack"));
+        assert!(is_read_event("requestStackList"));
+        assert!(is_read_event("agent"));
+        assert!(!is_read_event("deployStack"));
+        assert!(!is_read_event("terminalInput"));
+        assert!(!is_read_event("saveStack"));
+    }
+}
@@ -0,0 +1,637 @@
+//! REST endpoints for file transfers
+//!
+//! Socket.io is a poor fit for large payloads, so uploads and downloads go over
+//! plain HTTP, authenticated with the login token as `Authorization: Bearer <token>`
+//! (downloads also accept `?token=`, so a browser can save them directly).
+//!
+//! - `POST /api/stack/import` - upload an archive for the importStack event
+//! - `GET /api/stack/:stack/export` - the stack directory as a tar
+//! - `GET /api/stack/:stack/logs` - `docker compose logs` output as text
+//! - `GET /api/stack/:stack/container/:container/file?path=` - a file from one
+//!   of the stack's containers
+//! - `POST /api/stack/:stack/container/:container/file?path=` - write the body
+//!   to a file in one of the stack's containers
+//! - `GET /api/stack/:stack/images` - the stack's images as a `docker save` tar
+//! - `POST /api/images/load` - `docker load` an uploaded image tar
+//! - `GET /api/share/:token/logs` - a stack's logs, followed, for holders of a
+//!   share link; no login needed
+//!
+//! Downloads are streamed and never held in memory in full. Responses are
+//! compressed with gzip or zstd when the client's Accept-Encoding allows it,
+//! except followed logs, which are sent as they come, and image tars, which
+//! are too large to compress on the fly.
+
+use crate::db::models::{ShareLink, User};
+use crate::docker::{ComposeLogs, DockerHandle};
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use crate::utils::constants::{
+    MAX_CONTAINER_FILE_BYTES, MAX_IMAGE_ARCHIVE_BYTES, MAX_STACK_ARCHIVE_BYTES,
+    SHARE_LINK_LOG_TAIL, SHARE_LINK_RECHECK_SECS,
+};
+use crate::utils::stack_name::StackName;
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+use tower_http::compression::CompressionLayer;
+use tracing::{debug, info, warn};
+
+/// Buffer between the blocking tar writer and the response body
+const STREAM_BUFFER_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct DownloadQuery {
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogsQuery {
+    token: Option<String>,
+    /// Only this service's logs
+    service: Option<String>,
+    /// Number of lines from the end, all lines when absent
+    tail: Option<u64>,
+    #[serde(default)]
+    timestamps: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerFileQuery {
+    token: Option<String>,
+    /// Absolute path of the file in the container
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoadImagesQuery {
+    /// Load into the daemon this stack is deployed to, the instance's by default
+    stack: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SharedLogsQuery {
+    #[serde(default)]
+    timestamps: bool,
+}
+
+/// Build the REST routes
+pub fn routes(ctx: Arc<ServerContext>) -> Router {
+    Router::new()
+        .route(
+            "/api/stack/import",
+            post(upload_stack_archive).layer(DefaultBodyLimit::max(MAX_STACK_ARCHIVE_BYTES)),
+        )
+        .route("/api/stack/:stack/export", get(download_stack_archive))
+        .route("/api/stack/:stack/logs", get(download_stack_logs))
+        .route(
+            "/api/stack/:stack/container/:container/file",
+            get(download_container_file)
+                .post(upload_container_file)
+                .layer(DefaultBodyLimit::max(MAX_CONTAINER_FILE_BYTES)),
+        )
+        .layer(CompressionLayer::new().no_br().no_deflate())
+        .route("/api/stack/:stack/images", get(download_stack_images))
+        .route("/api/images/load", post(upload_image_archive))
+        .route("/api/share/:token/logs", get(shared_stack_logs))
+        .with_state(ctx)
+}
+
+/// Accept a stack archive upload (POST /api/stack/import)
+///
+/// The body is the raw archive; the response holds an `uploadId` for the
+/// importStack event.
+async fn upload_stack_archive(
+    State(ctx): State<Arc<ServerContext>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let user = match authenticate(&ctx, &headers, None).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if body.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "Empty upload".to_string());
+    }
+
+    match crate::archive::store_upload(user.id, body.to_vec()) {
+        Ok(upload_id) => {
+            info!(
+                "Stack archive uploaded by {} ({} bytes)",
+                user.username,
+                body.len()
+            );
+            Json(json!({ "ok": true, "uploadId": upload_id })).into_response()
+        }
+        Err(e) => error(StatusCode::TOO_MANY_REQUESTS, e.to_string()),
+    }
+}
+
+/// Stream a stack directory as a tar (GET /api/stack/:stack/export)
+async fn download_stack_archive(
+    State(ctx): State<Arc<ServerContext>>,
+    Path(stack_name): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&ctx, &headers, query.token).await {
+        return response;
+    }
+    let stack_name = match StackName::parse(&stack_name) {
+        Ok(name) => name,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    let stack = match Stack::get_stack(ctx.clone(), &stack_name, String::new()).await {
+        Ok(stack) => stack,
+        Err(e) => return error(StatusCode::NOT_FOUND, e.to_string()),
+    };
+    if !stack.is_managed_by_dockru().await {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Only stacks managed by Dockru can be exported".to_string(),
+        );
+    }
+
+    let (writer, reader) = tokio::io::duplex(STREAM_BUFFER_BYTES);
+    let writer = SyncIoBridge::new(writer);
+    let path = stack.path();
+    let name = stack_name.clone();
+    tokio::task::spawn_blocking(move || {
+        // An error here ends the body early; the client sees a truncated tar
+        if let Err(e) = crate::archive::write_stack_tar(&path, &name, writer) {
+            warn!("Stack export of {} failed: {}", name, e);
+        }
+    });
+
+    attachment(
+        "application/x-tar",
+        &format!("{}.tar", stack_name),
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+}
+
+/// Stream a stack's logs (GET /api/stack/:stack/logs)
+async fn download_stack_logs(
+    State(ctx): State<Arc<ServerContext>>,
+    Path(stack_name): Path<String>,
+    Query(query): Query<LogsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = authenticate(&ctx, &headers, query.token).await {
+        return response;
+    }
+    let stack_name = match StackName::parse(&stack_name) {
+        Ok(name) => name,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let logs = ComposeLogs {
+        service: query.service.filter(|s| !s.is_empty()),
+        tail: query.tail.map(|tail| tail.to_string()),
+        timestamps: query.timestamps,
+        follow: false,
+    };
+
+    match compose_logs(&ctx, &stack_name, &logs).await {
+        Ok(body) => attachment(
+            "text/plain; charset=utf-8",
+            &format!("{}.log", stack_name),
+            body,
+        ),
+        Err((status, msg)) => error(status, msg),
+    }
+}
+
+/// Stream a file from a container
+/// (GET /api/stack/:stack/container/:container/file?path=)
+async fn download_container_file(
+    State(ctx): State<Arc<ServerContext>>,
+    Path((stack_name, container)): Path<(String, String)>,
+    Query(query): Query<ContainerFileQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match authenticate(&ctx, &headers, query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let (docker, id, name) = match stack_container(&ctx, &stack_name, &container).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let file = match crate::container_files::download_file(&docker, &id, &query.path).await {
+        Ok(file) => file,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    info!(
+        "{} downloaded {} from container {} ({} bytes)",
+        user.username, query.path, name, file.size
+    );
+    attachment(
+        "application/octet-stream",
+        &file.name,
+        Body::from_stream(ReaderStream::new(file.content)),
+    )
+}
+
+/// Write the body to a file in a container
+/// (POST /api/stack/:stack/container/:container/file?path=)
+async fn upload_container_file(
+    State(ctx): State<Arc<ServerContext>>,
+    Path((stack_name, container)): Path<(String, String)>,
+    Query(query): Query<ContainerFileQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let user = match authenticate(&ctx, &headers, None).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let (docker, id, name) = match stack_container(&ctx, &stack_name, &container).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let size = body.len();
+    match crate::container_files::upload_file(&docker, &id, &query.path, body).await {
+        Ok(()) => {
+            info!(
+                "{} uploaded {} to container {} ({} bytes)",
+                user.username, query.path, name, size
+            );
+            Json(json!({ "ok": true })).into_response()
+        }
+        Err(e) => error(StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+/// The Docker handle, ID and name of one of a stack's containers
+async fn stack_container(
+    ctx: &Arc<ServerContext>,
+    stack_name: &str,
+    container: &str,
+) -> Result<(DockerHandle, String, String), Response> {
+    let stack_name =
+        StackName::parse(stack_name).map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let stack = Stack::get_stack(ctx.clone(), &stack_name, String::new())
+        .await
+        .map_err(|e| error(StatusCode::NOT_FOUND, e.to_string()))?;
+    let docker = stack
+        .docker()
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+    let (id, name) = crate::docker::project_container(&docker, stack_name.as_str(), container)
+        .await
+        .map_err(|e| error(StatusCode::NOT_FOUND, e.to_string()))?;
+    Ok((docker, id, name))
+}
+
+/// Stream a stack's images as a `docker save` tar (GET /api/stack/:stack/images)
+async fn download_stack_images(
+    State(ctx): State<Arc<ServerContext>>,
+    Path(stack_name): Path<String>,
+    Query(query): Query<DownloadQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let user = match authenticate(&ctx, &headers, query.token).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let stack_name = match StackName::parse(&stack_name) {
+        Ok(name) => name,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    let mut stack = match Stack::get_stack(ctx.clone(), &stack_name, String::new()).await {
+        Ok(stack) => stack,
+        Err(e) => return error(StatusCode::NOT_FOUND, e.to_string()),
+    };
+    let images = match stack.normalized_config().await {
+        Ok(config) => crate::image_archive::stack_images(&config.config),
+        Err(e) => return error(StatusCode::BAD_REQUEST, format!("{:#}", e)),
+    };
+    let docker = match stack.docker().await {
+        Ok(docker) => docker,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)),
+    };
+    let stream = match crate::image_archive::save_images(&docker, &images).await {
+        Ok(stream) => stream,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+
+    info!(
+        "Images of stack {} saved by {}: {}",
+        stack_name,
+        user.username,
+        images.join(", ")
+    );
+    // An error mid-stream ends the body early; the client sees a truncated tar
+    attachment(
+        "application/x-tar",
+        &format!("{}-images.tar", stack_name),
+        Body::from_stream(stream),
+    )
+}
+
+/// Load an uploaded `docker save` tar (POST /api/images/load)
+///
+/// The body is the raw tar; the response lists the loaded images.
+async fn upload_image_archive(
+    State(ctx): State<Arc<ServerContext>>,
+    Query(query): Query<LoadImagesQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let user = match authenticate(&ctx, &headers, None).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let docker = match query.stack.filter(|s| !s.is_empty()) {
+        Some(stack_name) => {
+            let stack_name = match StackName::parse(&stack_name) {
+                Ok(name) => name,
+                Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+            };
+            let stack = match Stack::get_stack(ctx.clone(), &stack_name, String::new()).await {
+                Ok(stack) => stack,
+                Err(e) => return error(StatusCode::NOT_FOUND, e.to_string()),
+            };
+            match stack.docker().await {
+                Ok(docker) => docker,
+                Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)),
+            }
+        }
+        None => ctx.docker.clone(),
+    };
+
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let archive = crate::image_archive::limit_stream(
+        body.into_data_stream(),
+        MAX_IMAGE_ARCHIVE_BYTES,
+        exceeded.clone(),
+    );
+    match crate::image_archive::load_images(&docker, archive).await {
+        Ok(images) => {
+            info!("Images loaded by {}: {}", user.username, images.join(", "));
+            Json(json!({ "ok": true, "images": images })).into_response()
+        }
+        Err(_) if exceeded.load(Ordering::Relaxed) => error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "Image archives can be at most {} GiB",
+                MAX_IMAGE_ARCHIVE_BYTES / (1024 * 1024 * 1024)
+            ),
+        ),
+        Err(e) => error(StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+/// Follow a stack's logs through a share link (GET /api/share/:token/logs)
+///
+/// The stream ends when the link expires or is revoked.
+async fn shared_stack_logs(
+    State(ctx): State<Arc<ServerContext>>,
+    Path(token): Path<String>,
+    Query(query): Query<SharedLogsQuery>,
+) -> Response {
+    let now = Utc::now().timestamp();
+    let link = match ShareLink::verify(&ctx.db, &token, now).await {
+        Ok(Some(link)) => link,
+        Ok(None) => {
+            return error(
+                StatusCode::NOT_FOUND,
+                "Share link is invalid or has expired".to_string(),
+            )
+        }
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let stack_name = match StackName::parse(&link.stack_name) {
+        Ok(name) => name,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let logs = ComposeLogs {
+        service: None,
+        tail: Some(SHARE_LINK_LOG_TAIL.to_string()),
+        timestamps: query.timestamps,
+        follow: true,
+    };
+
+    let body = match compose_logs(&ctx, &stack_name, &logs).await {
+        Ok(body) => body,
+        Err((status, msg)) => return error(status, msg),
+    };
+    let remaining = Duration::from_secs((link.expires_at - now).max(0) as u64);
+    let ended = share_link_ended(
+        ctx.db.clone(),
+        token,
+        remaining,
+        Duration::from_secs(SHARE_LINK_RECHECK_SECS),
+    );
+    let stream = body.into_data_stream().take_until(ended);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(CACHE_CONTROL, "no-store")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+/// Complete when a share link expires, or once it is found revoked
+///
+/// Links are revoked by deleting them, possibly on another cluster node, so
+/// the database is checked every `check_every`. A failed check keeps the link.
+async fn share_link_ended(
+    db: SqlitePool,
+    token: String,
+    expires_in: Duration,
+    check_every: Duration,
+) {
+    let revoked = async {
+        loop {
+            tokio::time::sleep(check_every).await;
+            match ShareLink::verify(&db, &token, Utc::now().timestamp()).await {
+                Ok(Some(_)) => {}
+                Ok(None) => return,
+                Err(e) => debug!("Failed to check share link: {:#}", e),
+            }
+        }
+    };
+    tokio::select! {
+        _ = tokio::time::sleep(expires_in) => {}
+        _ = revoked => {}
+    }
+}
+
+/// Run `docker compose logs` for a stack, streaming its output
+///
+/// Fails with the status and message to respond with.
+async fn compose_logs(
+    ctx: &Arc<ServerContext>,
+    stack_name: &StackName,
+    logs: &ComposeLogs,
+) -> Result<Body, (StatusCode, String)> {
+    logs.validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let stack = Stack::get_stack(ctx.clone(), stack_name, String::new())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    let mut child = stack
+        .logs(logs)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)))?;
+    let Some(stdout) = child.stdout.take() else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read docker compose logs".to_string(),
+        ));
+    };
+
+    // The stream owns the child, so a client that disconnects stops the process
+    let stream = ReaderStream::new(stdout).map(move |chunk| {
+        let _ = &child;
+        chunk
+    });
+    Ok(Body::from_stream(stream))
+}
+
+/// Check the login token from the Authorization header or the `token` parameter
+async fn authenticate(
+    ctx: &ServerContext,
+    headers: &HeaderMap,
+    query_token: Option<String>,
+) -> Result<User, Response> {
+    let Some(token) = crate::webhook::bearer_token(headers).or(query_token) else {
+        return Err(error(
+            StatusCode::UNAUTHORIZED,
+            "Missing login token".to_string(),
+        ));
+    };
+
+    crate::socket_handlers::user_from_token(ctx, &token)
+        .await
+        .map_err(|e| error(StatusCode::UNAUTHORIZED, e.to_string()))
+}
+
+fn attachment(content_type: &str, filename: &str, body: Body) -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, content_type)
+        .header(CONTENT_DISPOSITION, content_disposition(filename))
+        .body(body)
+        .unwrap_or_else(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// `attachment` Content-Disposition for a file name from anywhere, e.g. a file
+/// in a container
+///
+/// Per RFC 6266 the name is given twice: `filename` with anything but
+/// printable ASCII replaced, for old clients, and `filename*` percent-encoded
+/// as UTF-8.
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii_graphic() || c == ' ' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+fn error(status: StatusCode, msg: String) -> Response {
+    (status, Json(json!({ "ok": false, "msg": msg }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_db;
+
+    #[tokio::test]
+    async fn test_share_link_ended_on_revoke() {
+        let db = test_db().await;
+        let now = Utc::now().timestamp();
+        let (link, token) = ShareLink::create(db.pool(), "web", "admin", now + 3600, now)
+            .await
+            .unwrap();
+        let check_every = Duration::from_millis(10);
+        let mut ended = Box::pin(share_link_ended(
+            db.pool().clone(),
+            token,
+            Duration::from_secs(3600),
+            check_every,
+        ));
+
+        // Still streaming while the link is valid
+        assert!(tokio::time::timeout(check_every * 5, &mut ended)
+            .await
+            .is_err());
+
+        ShareLink::delete(db.pool(), link.id).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_secs(5), ended)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("web.tar"),
+            "attachment; filename=\"web.tar\"; filename*=UTF-8''web.tar"
+        );
+
+        let value = content_disposition("a\"b\nc\\d é.log");
+        assert_eq!(
+            value,
+            "attachment; filename=\"a_b_c_d _.log\"; filename*=UTF-8''a%22b%0Ac%5Cd%20%C3%A9.log"
+        );
+        assert!(axum::http::HeaderValue::from_str(&value).is_ok());
+
+        let response = attachment("text/plain", "x\"\r\ny", Body::empty());
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
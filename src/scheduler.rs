@@ -0,0 +1,511 @@
+// Scheduled stack actions
+//
+// Cron expressions are stored per stack in the stack_schedule table. A background
+// loop checks enabled schedules every few seconds and runs any that came due since
+// the previous check. Expressions use local time and accept the usual 5-field form
+// (minute hour day month weekday) or the 6/7-field form with seconds (and year).
+//
+// Each run is recorded on the schedule and broadcast to authenticated clients as
+// a `scheduleRun` event. Only one operation runs on a stack at a time: a schedule
+// that comes due while another schedule, or a deploy/stop/etc. from the UI, is
+// still running on the same stack is skipped, and the skip is broadcast too.
+//
+// Schedules on the same stack are checked against each other over the next week
+// of runs. Two kinds of conflict are reported (not prevented):
+// - overlap: runs of different schedules less than CONFLICT_WINDOW apart
+// - stoppedWindow: a restart/update between a scheduled stop and the next start,
+//   which would bring the stack back up early
+
+use crate::cluster::ClusterEvent;
+use crate::db::models::StackSchedule;
+use crate::docker::DeployOptions;
+use crate::server::ServerContext;
+use crate::socket_handlers::broadcast_to_authenticated;
+use crate::stack::Stack;
+use crate::terminal::Terminal;
+use crate::utils::terminal::get_compose_terminal_name;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use cron::Schedule;
+use dockru_protocol::events::server as server_event;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often the scheduler looks for due schedules
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Runs of different schedules closer than this are reported as overlapping
+const CONFLICT_WINDOW: chrono::Duration = chrono::Duration::minutes(10);
+
+/// How far ahead schedules are compared for conflicts
+const CONFLICT_HORIZON: chrono::Duration = chrono::Duration::days(7);
+
+/// Upper bound on runs expanded per schedule, for expressions like `* * * * * *`
+const MAX_RUNS_PER_SCHEDULE: usize = 2000;
+
+/// Action a schedule performs on its stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleAction {
+    Start,
+    Stop,
+    Restart,
+    Update,
+}
+
+impl ScheduleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleAction::Start => "start",
+            ScheduleAction::Stop => "stop",
+            ScheduleAction::Restart => "restart",
+            ScheduleAction::Update => "update",
+        }
+    }
+}
+
+impl FromStr for ScheduleAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "start" => Ok(ScheduleAction::Start),
+            "stop" => Ok(ScheduleAction::Stop),
+            "restart" => Ok(ScheduleAction::Restart),
+            "update" => Ok(ScheduleAction::Update),
+            _ => Err(anyhow!(
+                "Invalid schedule action \"{}\", expected start, stop, restart or update",
+                s
+            )),
+        }
+    }
+}
+
+/// A future run of a schedule
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledRun {
+    pub schedule_id: i64,
+    pub action: String,
+    #[serde(serialize_with = "serialize_local_time")]
+    pub at: DateTime<Local>,
+}
+
+/// Kind of conflict between two schedules of a stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictKind {
+    Overlap,
+    StoppedWindow,
+}
+
+/// Two schedules of the same stack that get in each other's way
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleConflict {
+    pub stack_name: String,
+    pub kind: ConflictKind,
+    /// The schedule that runs second
+    pub schedule_id: i64,
+    /// The schedule it conflicts with
+    pub other_schedule_id: i64,
+    /// First time the conflict happens
+    #[serde(serialize_with = "serialize_local_time")]
+    pub at: DateTime<Local>,
+    pub msg: String,
+}
+
+impl ScheduleConflict {
+    /// Whether a schedule is one of the two in this conflict
+    pub fn involves(&self, schedule_id: i64) -> bool {
+        self.schedule_id == schedule_id || self.other_schedule_id == schedule_id
+    }
+}
+
+fn serialize_local_time<S: serde::Serializer>(
+    time: &DateTime<Local>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::Secs, false))
+}
+
+/// Parse a cron expression, accepting 5-field expressions without seconds
+pub fn parse_cron(expr: &str) -> Result<Schedule> {
+    let expr = expr.trim();
+    let normalized = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+
+    Schedule::from_str(&normalized).map_err(|e| anyhow!("Invalid cron expression: {}", e))
+}
+
+/// Whether a schedule has a fire time in (since, now]
+pub(crate) fn is_due(schedule: &Schedule, since: &DateTime<Local>, now: &DateTime<Local>) -> bool {
+    schedule
+        .after(since)
+        .next()
+        .map(|next| next <= *now)
+        .unwrap_or(false)
+}
+
+/// Runs of enabled schedules in (from, until], grouped by stack and sorted by time
+///
+/// Schedules with an invalid cron are left out.
+pub fn upcoming_runs(
+    schedules: &[StackSchedule],
+    from: &DateTime<Local>,
+    until: &DateTime<Local>,
+) -> BTreeMap<String, Vec<ScheduledRun>> {
+    let mut runs: BTreeMap<String, Vec<ScheduledRun>> = BTreeMap::new();
+
+    for schedule in schedules.iter().filter(|s| s.enabled) {
+        let Ok(cron) = parse_cron(&schedule.cron) else {
+            continue;
+        };
+
+        let stack_runs = runs.entry(schedule.stack_name.clone()).or_default();
+        stack_runs.extend(
+            cron.after(from)
+                .take_while(|at| at <= until)
+                .take(MAX_RUNS_PER_SCHEDULE)
+                .map(|at| ScheduledRun {
+                    schedule_id: schedule.id,
+                    action: schedule.action.clone(),
+                    at,
+                }),
+        );
+    }
+
+    for stack_runs in runs.values_mut() {
+        stack_runs.sort_by_key(|run| (run.at, run.schedule_id));
+    }
+    runs.retain(|_, stack_runs| !stack_runs.is_empty());
+
+    runs
+}
+
+/// Find conflicts between the enabled schedules of each stack over the next week
+///
+/// Each pair of schedules is reported once per kind, at its first occurrence.
+pub fn find_conflicts(
+    schedules: &[StackSchedule],
+    from: &DateTime<Local>,
+) -> Vec<ScheduleConflict> {
+    let mut conflicts = Vec::new();
+
+    for (stack_name, runs) in upcoming_runs(schedules, from, &(*from + CONFLICT_HORIZON)) {
+        let mut seen = HashSet::new();
+        let mut report =
+            |kind: ConflictKind, run: &ScheduledRun, other: &ScheduledRun, msg: String| {
+                if seen.insert((kind, run.schedule_id, other.schedule_id)) {
+                    conflicts.push(ScheduleConflict {
+                        stack_name: stack_name.clone(),
+                        kind,
+                        schedule_id: run.schedule_id,
+                        other_schedule_id: other.schedule_id,
+                        at: run.at,
+                        msg,
+                    });
+                }
+            };
+
+        let mut stopped_by: Option<&ScheduledRun> = None;
+        for (i, run) in runs.iter().enumerate() {
+            if let Some(prev) = i.checked_sub(1).map(|p| &runs[p]) {
+                if prev.schedule_id != run.schedule_id && run.at - prev.at < CONFLICT_WINDOW {
+                    report(
+                        ConflictKind::Overlap,
+                        run,
+                        prev,
+                        format!(
+                            "{} runs {} minute(s) after {}",
+                            run.action,
+                            (run.at - prev.at).num_minutes(),
+                            prev.action
+                        ),
+                    );
+                }
+            }
+
+            match ScheduleAction::from_str(&run.action) {
+                Ok(ScheduleAction::Stop) => stopped_by = Some(run),
+                Ok(ScheduleAction::Start) => stopped_by = None,
+                Ok(ScheduleAction::Restart | ScheduleAction::Update) => {
+                    if let Some(stop) = stopped_by {
+                        report(
+                            ConflictKind::StoppedWindow,
+                            run,
+                            stop,
+                            format!(
+                                "{} runs while the stack is stopped by a schedule",
+                                run.action
+                            ),
+                        );
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Start the scheduler loop
+pub fn start(ctx: Arc<ServerContext>) {
+    // Stacks with a scheduled action in progress
+    let running: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        let mut last_check = Local::now();
+
+        loop {
+            interval.tick().await;
+            let now = Local::now();
+
+            // In a cluster only the leader runs schedules
+            if !crate::cluster::is_leader(&ctx) {
+                last_check = now;
+                continue;
+            }
+
+            let schedules = match StackSchedule::find_enabled(&ctx.db).await {
+                Ok(schedules) => schedules,
+                Err(e) => {
+                    error!("Failed to load schedules: {}", e);
+                    continue;
+                }
+            };
+
+            for schedule in schedules {
+                let cron = match parse_cron(&schedule.cron) {
+                    Ok(cron) => cron,
+                    Err(e) => {
+                        warn!("Schedule {} has an invalid cron: {}", schedule.id, e);
+                        continue;
+                    }
+                };
+
+                if !is_due(&cron, &last_check, &now) {
+                    continue;
+                }
+
+                // One operation per stack: skip if a schedule or a UI action is
+                // still running on it
+                if is_stack_busy(&schedule.stack_name).await
+                    || !running.lock().unwrap().insert(schedule.stack_name.clone())
+                {
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move { skip_schedule(&ctx, &schedule).await });
+                    continue;
+                }
+
+                let ctx = ctx.clone();
+                let running = running.clone();
+                tokio::spawn(async move {
+                    run_schedule(&ctx, &schedule).await;
+                    running.lock().unwrap().remove(&schedule.stack_name);
+                });
+            }
+
+            last_check = now;
+        }
+    });
+
+    info!("Scheduler started");
+}
+
+/// Run a schedule's action now, record the result and broadcast it
+pub async fn run_schedule(ctx: &Arc<ServerContext>, schedule: &StackSchedule) {
+    info!(
+        "Running schedule {}: {} {}",
+        schedule.id, schedule.action, schedule.stack_name
+    );
+
+    let result = run_action(ctx, &schedule.stack_name, &schedule.action).await;
+
+    let (ok, msg) = match &result {
+        Ok(0) => (true, "ok".to_string()),
+        Ok(code) => (false, format!("exited with code {}", code)),
+        Err(e) => (false, e.to_string()),
+    };
+
+    if !ok {
+        warn!("Schedule {} failed: {}", schedule.id, msg);
+    }
+
+    if let Err(e) = StackSchedule::record_run(&ctx.db, schedule.id, &msg).await {
+        error!("Failed to record schedule run: {}", e);
+    }
+
+    let data = json!({
+        "scheduleId": schedule.id,
+        "stackName": schedule.stack_name,
+        "action": schedule.action,
+        "ok": ok,
+        "skipped": false,
+        "msg": msg,
+    });
+    if let Err(e) = broadcast_to_authenticated(&ctx.io, server_event::SCHEDULE_RUN, data).await {
+        warn!("Failed to broadcast schedule run: {}", e);
+    }
+
+    // Status likely changed, refresh the stack list
+    ctx.broadcast_notify.notify_one();
+    crate::cluster::publish(ctx, ClusterEvent::StackList).await;
+}
+
+/// Whether a compose operation (deploy, stop, ...) is running on a local stack
+async fn is_stack_busy(stack_name: &str) -> bool {
+    Terminal::get_terminal(&get_compose_terminal_name("", stack_name))
+        .await
+        .is_some()
+}
+
+/// Record and broadcast that a due schedule was skipped
+async fn skip_schedule(ctx: &ServerContext, schedule: &StackSchedule) {
+    let msg = "skipped: another operation is running on the stack";
+    warn!(
+        "Schedule {} ({} {}) {}",
+        schedule.id, schedule.action, schedule.stack_name, msg
+    );
+
+    if let Err(e) = StackSchedule::record_run(&ctx.db, schedule.id, msg).await {
+        error!("Failed to record schedule run: {}", e);
+    }
+
+    let data = json!({
+        "scheduleId": schedule.id,
+        "stackName": schedule.stack_name,
+        "action": schedule.action,
+        "ok": false,
+        "skipped": true,
+        "msg": msg,
+    });
+    if let Err(e) = broadcast_to_authenticated(&ctx.io, server_event::SCHEDULE_RUN, data).await {
+        warn!("Failed to broadcast schedule run: {}", e);
+    }
+}
+
+async fn run_action(ctx: &Arc<ServerContext>, stack_name: &str, action: &str) -> Result<i32> {
+    let action = ScheduleAction::from_str(action)?;
+    let mut stack = Stack::get_stack(ctx.clone(), stack_name, String::new()).await?;
+
+    let result = match action {
+        ScheduleAction::Start => stack.start(&DeployOptions::default(), None).await,
+        ScheduleAction::Stop => stack.stop(None).await,
+        ScheduleAction::Restart => stack.restart(None).await,
+        ScheduleAction::Update => stack.update(false, None).await,
+    };
+    stack
+        .record_event(action.as_str(), "scheduler", &result)
+        .await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(parse_cron("0 4 * * *").is_ok());
+        assert!(parse_cron("30 0 4 * * *").is_ok());
+        assert!(parse_cron("not a cron").is_err());
+        assert!(parse_cron("").is_err());
+    }
+
+    #[test]
+    fn test_schedule_action_round_trip() {
+        for action in ["start", "stop", "restart", "update"] {
+            assert_eq!(ScheduleAction::from_str(action).unwrap().as_str(), action);
+        }
+        assert!(ScheduleAction::from_str("delete").is_err());
+    }
+
+    #[test]
+    fn test_is_due() {
+        // Every day at 04:00
+        let cron = parse_cron("0 4 * * *").unwrap();
+        let at = |h, m, s| Local.with_ymd_and_hms(2024, 6, 1, h, m, s).unwrap();
+
+        assert!(is_due(&cron, &at(3, 59, 50), &at(4, 0, 5)));
+        assert!(!is_due(&cron, &at(3, 59, 30), &at(3, 59, 45)));
+        // A fire time equal to the previous check has already been handled
+        assert!(!is_due(&cron, &at(4, 0, 0), &at(4, 0, 15)));
+    }
+
+    fn schedule(id: i64, stack_name: &str, action: &str, cron: &str) -> StackSchedule {
+        StackSchedule {
+            id,
+            stack_name: stack_name.to_string(),
+            action: action.to_string(),
+            cron: cron.to_string(),
+            enabled: true,
+            last_run_at: None,
+            last_result: None,
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_upcoming_runs() {
+        let from = Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let until = Local.with_ymd_and_hms(2024, 6, 2, 0, 0, 0).unwrap();
+        let mut disabled = schedule(3, "web", "stop", "0 1 * * *");
+        disabled.enabled = false;
+        let schedules = vec![
+            schedule(1, "web", "restart", "0 4 * * *"),
+            schedule(2, "web", "update", "0 */12 * * *"),
+            disabled,
+            schedule(4, "db", "stop", "bad cron"),
+        ];
+
+        let runs = upcoming_runs(&schedules, &from, &until);
+        assert_eq!(runs.len(), 1);
+        let order: Vec<(i64, u32)> = runs["web"]
+            .iter()
+            .map(|r| (r.schedule_id, chrono::Timelike::hour(&r.at)))
+            .collect();
+        assert_eq!(order, vec![(1, 4), (2, 12), (2, 0)]);
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let from = Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let schedules = vec![
+            schedule(1, "web", "stop", "0 2 * * *"),
+            schedule(2, "web", "start", "0 6 * * *"),
+            // Inside the stop window
+            schedule(3, "web", "update", "0 3 * * *"),
+            // Five minutes after the start
+            schedule(4, "web", "restart", "5 6 * * *"),
+            // Same times on another stack don't conflict with web
+            schedule(5, "db", "update", "0 3 * * *"),
+        ];
+
+        let conflicts = find_conflicts(&schedules, &from);
+        assert_eq!(conflicts.len(), 2);
+
+        let stopped = conflicts
+            .iter()
+            .find(|c| c.kind == ConflictKind::StoppedWindow)
+            .unwrap();
+        assert_eq!((stopped.schedule_id, stopped.other_schedule_id), (3, 1));
+
+        let overlap = conflicts
+            .iter()
+            .find(|c| c.kind == ConflictKind::Overlap)
+            .unwrap();
+        assert_eq!((overlap.schedule_id, overlap.other_schedule_id), (4, 2));
+        assert!(overlap.involves(2) && !overlap.involves(3));
+    }
+}
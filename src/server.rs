@@ -1,4 +1,5 @@
 use crate::check_version::VersionChecker;
+use crate::cluster::Cluster;
 use crate::config::Config;
 use crate::db::models::setting::SettingsCache;
 use crate::db::Database;
@@ -6,16 +7,18 @@ use crate::static_files::PreCompressedStaticFiles;
 use anyhow::{Context, Result};
 use axum::{
     body::Body,
-    extract::Request,
-    http::{header::CONTENT_TYPE, StatusCode, Uri},
-    response::{Html, IntoResponse, Response},
+    extract::{ConnectInfo, Request},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode, Uri},
+    response::{Html, Response},
     routing::get,
     Router,
 };
-use bollard::Docker;
+use crate::docker::DockerHandle;
+use crate::docker_host::{DockerConnector, DockerHost};
+use dockru_protocol::events::server as server_event;
 use socketioxide::{extract::SocketRef, SocketIo, TransportType};
 use sqlx::SqlitePool;
-use std::{fs, path::PathBuf, sync::Arc};
+use std::{fs, net::SocketAddr, path::{Path, PathBuf}, sync::Arc};
 use tokio::signal;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
@@ -34,8 +37,12 @@ pub struct ServerContext {
     /// Secret used to encrypt/decrypt agent passwords at rest.
     /// Derived from the jwtSecret setting; empty until setup is complete.
     pub encryption_secret: Arc<std::sync::RwLock<String>>,
-    /// Docker client for API operations
-    pub docker: Docker,
+    /// Docker client for API operations, reconnects if the daemon restarts
+    pub docker: DockerHandle,
+    /// Time the server context was created, used for uptime reporting
+    pub started_at: std::time::Instant,
+    /// Cluster membership, None unless a cluster node id is configured
+    pub cluster: Option<Arc<Cluster>>,
 }
 
 impl ServerContext {
@@ -45,8 +52,13 @@ impl ServerContext {
         db: SqlitePool,
         cache: SettingsCache,
         version_checker: VersionChecker,
-        docker: Docker,
+        docker: DockerHandle,
     ) -> Self {
+        let cluster = config
+            .cluster_node_id
+            .clone()
+            .map(|node_id| Arc::new(Cluster::new(node_id)));
+
         Self {
             config,
             io,
@@ -56,6 +68,8 @@ impl ServerContext {
             broadcast_notify: Arc::new(tokio::sync::Notify::new()),
             encryption_secret: Arc::new(std::sync::RwLock::new(String::new())),
             docker,
+            started_at: std::time::Instant::now(),
+            cluster,
         }
     }
 
@@ -65,6 +79,15 @@ impl ServerContext {
         self.encryption_secret.read().unwrap().clone()
     }
 
+    /// Get the encryption secret, failing before first user setup
+    pub fn require_encryption_secret(&self) -> Result<redact::Secret<String>> {
+        let secret = self.get_encryption_secret();
+        if secret.is_empty() {
+            anyhow::bail!("Encryption is not available until setup is complete");
+        }
+        Ok(redact::Secret::new(secret))
+    }
+
     /// Set the encryption secret (called at startup and after initial setup).
     pub fn set_encryption_secret(&self, secret: String) {
         let mut w = self.encryption_secret.write().unwrap();
@@ -107,7 +130,11 @@ impl DockruServer {
     }
 
     /// Build the router with all routes and middleware
-    fn build_router(&self, socket_layer: socketioxide::layer::SocketIoLayer) -> Router {
+    fn build_router(
+        &self,
+        socket_layer: socketioxide::layer::SocketIoLayer,
+        ctx: Arc<ServerContext>,
+    ) -> Router {
         let mut router = Router::new();
 
         // Health check endpoint for Docker
@@ -135,9 +162,19 @@ impl DockruServer {
             }),
         );
 
+        // Deploy webhooks for CI/CD
+        router = router.merge(crate::webhook::routes(ctx.clone()));
+
+        // File uploads and downloads (stack archives, logs)
+        router = router.merge(crate::rest::routes(ctx));
+
         // Serve static files from frontend-dist with pre-compressed support
         // Use fallback_service instead of routes to allow socket.io layer to intercept first
         if PathBuf::from("./frontend-dist").exists() {
+            tokio::task::spawn_blocking(|| {
+                crate::static_files::precompress_dir(Path::new("./frontend-dist"))
+            });
+
             let static_files = Arc::new(PreCompressedStaticFiles::new("./frontend-dist"));
             let index_html = self.index_html.clone();
 
@@ -146,14 +183,14 @@ impl DockruServer {
                 let static_files = static_files.clone();
                 let index_html = index_html.clone();
                 async move {
+                    let headers = req.headers().clone();
+
                     // Try to serve the file first
                     let response = static_files.handle(uri.clone(), req).await;
 
-                    // If 404, serve index.html for SPA routing
+                    // If 404, serve index.html for SPA routing (navigations only)
                     if response.status() == StatusCode::NOT_FOUND {
-                        if let Some(html) = index_html {
-                            return Html(html).into_response();
-                        }
+                        return crate::static_files::not_found(uri.path(), &headers, index_html);
                     }
 
                     response
@@ -166,9 +203,9 @@ impl DockruServer {
 
             // Fallback for all other routes in dev mode
             let html_clone = html.clone();
-            router = router.fallback(move || {
+            router = router.fallback(move |uri: Uri, headers: HeaderMap| {
                 let html = html_clone.clone();
-                async move { Html(html) }
+                async move { crate::static_files::not_found(uri.path(), &headers, Some(html)) }
             });
         }
 
@@ -207,11 +244,21 @@ impl DockruServer {
     /// Set up Socket.IO namespace handlers (must be called after ServerContext is created)
     fn setup_socketio_handlers(io: &SocketIo, ctx: Arc<ServerContext>) {
         io.ns("/", async move |socket: SocketRef| {
-            info!("Socket connected: {} (transport: websocket)", socket.id);
-
-            // Initialize socket state
+            // Initialize socket state from the handshake headers
             use crate::socket_handlers::{set_socket_state, SocketState};
-            set_socket_state(&socket.id.to_string(), SocketState::default());
+            let mut state = SocketState::from_handshake(&socket.req_parts().headers);
+            state.ip_address = socket
+                .req_parts()
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|info| info.0.ip());
+            info!(
+                "Socket connected: {} (transport: websocket, origin: {}, user agent: {})",
+                socket.id,
+                state.origin.as_deref().unwrap_or("-"),
+                state.user_agent.as_deref().unwrap_or("-")
+            );
+            set_socket_state(&socket.id.to_string(), state);
 
             // Create AgentManager for this socket
             let agent_manager = std::sync::Arc::new(crate::agent_manager::AgentManager::new(
@@ -238,13 +285,31 @@ impl DockruServer {
                     warn!("Failed to send info: {}", e);
                 }
 
+                // Behind a trusted authenticating proxy the socket is logged in
+                // right away, and the client gets a token as if it had logged in
+                match crate::socket_handlers::login_by_trusted_header(
+                    &socket_for_info,
+                    &ctx_for_info,
+                )
+                .await
+                {
+                    Ok(Some(token)) => {
+                        let data = serde_json::json!({ "token": token });
+                        if let Err(e) = socket_for_info.emit(server_event::AUTO_LOGIN, &data) {
+                            warn!("Failed to emit 'autoLogin' event: {:?}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Trusted header login failed: {:#}", e),
+                }
+
                 // Check if setup is needed and emit "setup" event
                 let user_count = crate::db::models::User::count(&ctx_for_info.db)
                     .await
                     .unwrap_or(1);
                 if user_count == 0 {
                     info!("No users found, emitting 'setup' to redirect client");
-                    match socket_for_info.emit("setup", &()) {
+                    match socket_for_info.emit(server_event::SETUP, &()) {
                         Ok(_) => info!(
                             "'setup' event emitted successfully to {}",
                             socket_for_info.id
@@ -278,6 +343,8 @@ impl DockruServer {
                     // Clean up socket state
                     use crate::socket_handlers::remove_socket_state;
                     remove_socket_state(&socket_id);
+                    crate::socket_handlers::stop_stack_watch(&socket_id).await;
+                    crate::container_stats::unsubscribe_all(&socket_id);
 
                     // Close terminals whose rooms became empty
                     for room in rooms {
@@ -303,7 +370,16 @@ impl DockruServer {
 
 /// Start the server
 pub async fn serve(config: Config) -> Result<()> {
+    let config = crate::relocation::apply_record(config);
     let server = DockruServer::new(config)?;
+    crate::header_auth::validate(&server.config)?;
+    crate::terminal::set_buffer_byte_limit(server.config.terminal_buffer_kb * 1024);
+    let terminal_env = crate::terminal::TerminalEnv {
+        term: server.config.terminal_term.clone(),
+        lang: server.config.terminal_lang.clone(),
+    };
+    terminal_env.validate()?;
+    crate::terminal::set_terminal_env(terminal_env);
 
     // Create data directory if it doesn't exist
     fs::create_dir_all(&server.config.data_dir).context("Failed to create data directory")?;
@@ -311,11 +387,18 @@ pub async fn serve(config: Config) -> Result<()> {
     // Create stacks directory if it doesn't exist
     fs::create_dir_all(&server.config.stacks_dir).context("Failed to create stacks directory")?;
 
+    crate::crash_report::install(&server.config.data_dir);
+
     info!("Data directory: {}", server.config.data_dir.display());
     info!("Stacks directory: {}", server.config.stacks_dir.display());
 
-    // Initialize database
-    let db = Database::new(&server.config.data_dir).await?;
+    // Initialize database (shared between nodes in cluster mode)
+    let db = if server.config.cluster_node_id.is_some() {
+        info!("Cluster mode: database directory {}", server.config.db_dir().display());
+        Database::new_shared(server.config.db_dir()).await?
+    } else {
+        Database::new(&server.config.data_dir).await?
+    };
 
     // Run migrations
     db.migrate().await?;
@@ -327,10 +410,16 @@ pub async fn serve(config: Config) -> Result<()> {
     let version_checker = VersionChecker::new(env!("CARGO_PKG_VERSION").to_string());
 
     // Connect to Docker daemon
-    let docker = Docker::connect_with_local_defaults()
-        .context("Failed to connect to Docker daemon")?;
+    let docker_host = DockerHost::from_config(&server.config)?;
+    let docker_host_name = docker_host.describe();
+    if docker_host.is_remote() {
+        info!("Bind mounts and build contexts are resolved on the remote docker host");
+    }
+    crate::docker_host::set_cli_env(&docker_host);
+    let docker =
+        DockerHandle::connect(DockerConnector::new(docker_host, &server.config.data_dir)).await?;
 
-    info!("Connected to Docker daemon");
+    info!("Connected to Docker daemon ({})", docker_host_name);
 
     // Create Socket.IO layer first (with transport config)
     let (io, socket_layer) = server.create_socketio_layer();
@@ -366,11 +455,14 @@ pub async fn serve(config: Config) -> Result<()> {
         }
     }
 
+    // Refuse to start if another live instance uses our cluster node id
+    crate::cluster::join(&ctx).await?;
+
     // Now set up namespace handlers with the real context
     DockruServer::setup_socketio_handlers(&io, ctx.clone());
 
     // Build router
-    let app = server.build_router(socket_layer);
+    let app = server.build_router(socket_layer, ctx.clone());
 
     // Get bind address
     let bind_addr = server.config.bind_address();
@@ -386,11 +478,16 @@ pub async fn serve(config: Config) -> Result<()> {
     // Phase 10: Start scheduled tasks
     start_scheduled_tasks(ctx.clone());
 
-    // Start server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .context("Server error")?;
+    // Start server with graceful shutdown. The peer address is kept for header auth.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .context("Server error")?;
+
+    crate::cluster::leave(&ctx).await;
 
     info!("Server shutdown complete");
 
@@ -438,21 +535,38 @@ fn start_scheduled_tasks(ctx: Arc<ServerContext>) {
             .start_interval(ctx_clone.db.clone(), ctx_clone.cache.clone());
     });
 
-    // Start stack list broadcast (every 10 seconds, only when clients are connected)
-    // Also fires immediately when a client connects via broadcast_notify.
+    // Follow Docker events so stack status changes are pushed as they happen
+    crate::docker_events::start(ctx.clone());
+
+    // Start stack list broadcast (every stackRefreshInterval seconds, default 10, only
+    // when clients are connected). Also fires immediately when a client connects via
+    // broadcast_notify. While Docker events are followed, status changes arrive as
+    // stackStatus deltas and the ticks only resync every STACK_RESYNC_SECS.
     let ctx_clone = ctx.clone();
     tokio::spawn(async move {
-        use tokio::time::{interval, Duration};
-        let mut interval = interval(Duration::from_secs(10));
+        use tokio::time::interval;
+        let mut period = crate::broadcasts::stack_refresh_interval(&ctx_clone).await;
+        let mut interval = interval(period);
+        let resync = std::time::Duration::from_secs(crate::utils::constants::STACK_RESYNC_SECS);
+        let mut last_broadcast: Option<tokio::time::Instant> = None;
 
         loop {
-            // Wait for either the 10s tick or a client-connect notification
-            tokio::select! {
-                _ = interval.tick() => {},
+            // Wait for either the interval tick or a client-connect notification
+            let notified = tokio::select! {
+                _ = interval.tick() => false,
                 _ = ctx_clone.broadcast_notify.notified() => {
                     // Reset the interval so we don't double-fire shortly after
                     interval.reset();
+                    true
                 },
+            };
+
+            // Pick up a changed setting for the next tick
+            let new_period = crate::broadcasts::stack_refresh_interval(&ctx_clone).await;
+            if new_period != period {
+                debug!("Stack list broadcast interval changed to {:?}", new_period);
+                period = new_period;
+                interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
             }
 
             // Skip expensive Docker polling when no clients are connected
@@ -462,12 +576,45 @@ fn start_scheduled_tasks(ctx: Arc<ServerContext>) {
                 continue;
             }
 
+            let recent = last_broadcast.is_some_and(|at| at.elapsed() < resync);
+            if !notified && recent && crate::docker_events::is_following() {
+                continue;
+            }
+
+            last_broadcast = Some(tokio::time::Instant::now());
             if let Err(e) = broadcast_stack_list_to_authenticated(&ctx_clone).await {
                 error!("Failed to broadcast stack list: {}", e);
             }
         }
     });
 
+    // Start image update checking (every 6 hours)
+    crate::image_updates::start_interval(ctx.clone());
+
+    // Apply found image updates in stacks' update windows (every minute)
+    crate::image_updates::start_update_windows(ctx.clone());
+
+    // Start cron-scheduled stack actions
+    crate::scheduler::start(ctx.clone());
+
+    // Upload instance backups on their schedule
+    crate::backup::start(ctx.clone());
+
+    // Start cluster heartbeat and event sync (cluster mode only)
+    crate::cluster::start(ctx.clone());
+
+    // Start the stacks flagged for auto-start once Docker is reachable
+    crate::autostart::start(ctx.clone());
+
+    // Sample stack statuses for uptime (every minute)
+    crate::uptime::start(ctx.clone());
+
+    // Announce this instance and discover others on the LAN (opt-in)
+    crate::discovery::start(ctx.clone());
+
+    // Watch the Docker daemon connection, reconnecting if dockerd restarted
+    crate::docker_watchdog::start(ctx.clone());
+
     info!("All scheduled tasks started");
 }
 
@@ -498,7 +645,7 @@ async fn broadcast_stack_list_to_authenticated(ctx: &ServerContext) -> Result<()
     // Broadcast to authenticated sockets only wrapped in "agent" protocol
     // The frontend listens for socket.on("agent", (eventName, ...args) => ...)
     use crate::socket_handlers::broadcast_to_authenticated;
-    broadcast_to_authenticated(&ctx.io, "stackList", response).await?;
+    broadcast_to_authenticated(&ctx.io, server_event::STACK_LIST, response).await?;
 
     Ok(())
 }
@@ -0,0 +1,528 @@
+use crate::check_version::VersionCheckResult;
+use crate::db::models::AuditEntry;
+use crate::relocation::Relocation;
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, check_login, check_user_login, get_authenticated_user_ids, get_endpoint,
+    spawn_handler,
+};
+use crate::stack::Stack;
+use crate::terminal::Terminal;
+use crate::utils::bulk_edit::FileEdit;
+use crate::utils::constants::{
+    DEFAULT_AUDIT_LOG_PAGE_SIZE, MAX_AUDIT_LOG_PAGE_SIZE, MAX_BATCH_STACKS,
+};
+use crate::utils::stack_name::StackName;
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+struct ServerStats {
+    #[serde(rename = "connectedSockets")]
+    connected_sockets: usize,
+    #[serde(rename = "authenticatedUsers")]
+    authenticated_users: usize,
+    terminals: HashMap<&'static str, usize>,
+    /// Output buffered by all terminals, in bytes
+    #[serde(rename = "terminalBufferBytes")]
+    terminal_buffer_bytes: usize,
+    #[serde(rename = "agentConnections")]
+    agent_connections: usize,
+    #[serde(rename = "agentsLoggedIn")]
+    agents_logged_in: usize,
+    #[serde(rename = "dbSize")]
+    db_size: i64,
+    #[serde(rename = "memoryRss")]
+    memory_rss: Option<u64>,
+    uptime: u64,
+    #[serde(rename = "dockerHealthy")]
+    docker_healthy: bool,
+}
+
+#[derive(Serialize)]
+struct ServerStatsResponse {
+    stats: ServerStats,
+}
+
+#[derive(Serialize)]
+struct CheckUpdatesResponse {
+    /// False when update checks are disabled
+    checked: bool,
+    #[serde(flatten)]
+    result: VersionCheckResult,
+}
+
+/// A page of the audit log
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct AuditLogQuery {
+    /// Zero-based page number
+    page: i64,
+    #[serde(rename = "pageSize")]
+    page_size: Option<i64>,
+}
+
+impl AuditLogQuery {
+    fn page_size(&self) -> i64 {
+        self.page_size
+            .unwrap_or(DEFAULT_AUDIT_LOG_PAGE_SIZE)
+            .clamp(1, MAX_AUDIT_LOG_PAGE_SIZE)
+    }
+}
+
+#[derive(Serialize)]
+struct AuditLogResponse {
+    entries: Vec<AuditEntry>,
+    total: i64,
+    page: i64,
+    #[serde(rename = "pageSize")]
+    page_size: i64,
+}
+
+/// bulkEnvEdit positional args: [find, replace, { stacks, apply }?]
+#[derive(Debug, PartialEq, Eq)]
+struct BulkEnvEditArgs {
+    find: String,
+    replace: String,
+    /// Stacks confirmed for the edit, required when applying
+    stacks: Vec<StackName>,
+    apply: bool,
+}
+
+/// What bulkEnvEdit changes, or would change, in one stack
+#[derive(Debug, Serialize)]
+struct BulkEditStack {
+    #[serde(rename = "stackName")]
+    stack_name: String,
+    ok: bool,
+    files: Vec<FileEdit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BulkEnvEditResponse {
+    stacks: Vec<BulkEditStack>,
+}
+
+#[derive(Serialize)]
+struct RelocateStacksDirResponse {
+    relocation: Relocation,
+}
+
+/// Setup admin event handlers
+pub fn setup_admin_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // getServerStats
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getServerStats",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getServerStats", ack, |ack| async move {
+                match handle_get_server_stats(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // checkUpdatesNow
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "checkUpdatesNow",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("checkUpdatesNow", ack, |ack| async move {
+                match handle_check_updates_now(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // getAuditLog
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getAuditLog",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getAuditLog", ack, |ack| async move {
+                match handle_get_audit_log(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // bulkEnvEdit
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "bulkEnvEdit",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("bulkEnvEdit", ack, |ack| async move {
+                match handle_bulk_env_edit(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // relocateStacksDir
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "relocateStacksDir",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("relocateStacksDir", ack, |ack| async move {
+                match handle_relocate_stacks_dir(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+}
+
+async fn handle_get_server_stats(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let (agent_connections, agents_logged_in) =
+        crate::agent_manager::agent_connection_counts().await;
+
+    // Page count * page size covers the main database file (WAL excluded)
+    let db_size: i64 = sqlx::query_scalar(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+    )
+    .fetch_one(&ctx.db)
+    .await?;
+
+    let stats = ServerStats {
+        connected_sockets: ctx.io.sockets().len(),
+        authenticated_users: get_authenticated_user_ids().len(),
+        terminals: Terminal::get_terminal_count_by_type().await,
+        terminal_buffer_bytes: Terminal::get_total_buffer_size().await,
+        agent_connections,
+        agents_logged_in,
+        db_size,
+        memory_rss: process_memory_rss(),
+        uptime: ctx.started_at.elapsed().as_secs(),
+        docker_healthy: ctx.docker.is_healthy(),
+    };
+
+    Ok(CustomResponse::ok_with_fields(ServerStatsResponse { stats }).into())
+}
+
+async fn handle_check_updates_now(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value> {
+    check_user_login(socket)?;
+
+    let checked = ctx
+        .version_checker
+        .check_now_rate_limited(&ctx.db, &ctx.cache)
+        .await?;
+
+    if checked {
+        // Every client shows the new results, not just the one that asked
+        if let Err(e) = crate::broadcasts::broadcast_info(ctx).await {
+            tracing::debug!("Failed to broadcast info: {}", e);
+        }
+    }
+
+    let result = ctx.version_checker.result().await;
+    Ok(CustomResponse::ok_with_fields(CheckUpdatesResponse { checked, result }).into())
+}
+
+async fn handle_get_audit_log(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<serde_json::Value> {
+    check_user_login(socket)?;
+
+    let query = parse_audit_log_query(data)?;
+    let page_size = query.page_size();
+    let entries =
+        AuditEntry::find_page(&ctx.db, page_size, query.page.saturating_mul(page_size)).await?;
+    let total = AuditEntry::count(&ctx.db).await?;
+
+    Ok(CustomResponse::ok_with_fields(AuditLogResponse {
+        entries,
+        total,
+        page: query.page,
+        page_size,
+    })
+    .into())
+}
+
+/// Preview or apply a find and replace across the stacks (see utils::bulk_edit)
+///
+/// A preview lists every managed stack with a match. Applying edits only the
+/// confirmed stacks; a stack that fails doesn't stop the others.
+async fn handle_bulk_env_edit(
+    socket: &SocketRef,
+    ctx: &Arc<ServerContext>,
+    data: &Value,
+) -> Result<serde_json::Value> {
+    let user_id = check_user_login(socket)?;
+    let args = parse_bulk_env_edit_args(data)?;
+    let endpoint = get_endpoint(socket);
+
+    let mut stacks = Vec::new();
+    if args.apply {
+        for name in &args.stacks {
+            let result = match Stack::get_stack(ctx.clone(), name, endpoint.clone()).await {
+                Ok(stack) => stack.bulk_replace(&args.find, &args.replace, true).await,
+                Err(e) => Err(e),
+            };
+            stacks.push(match result {
+                Ok(files) => BulkEditStack {
+                    stack_name: name.to_string(),
+                    ok: true,
+                    files,
+                    error: None,
+                },
+                Err(e) => BulkEditStack {
+                    stack_name: name.to_string(),
+                    ok: false,
+                    files: Vec::new(),
+                    error: Some(format!("{:#}", e)),
+                },
+            });
+        }
+        let edited = stacks.iter().filter(|s| s.ok && !s.files.is_empty());
+        tracing::info!(
+            "User {} replaced {:?} in {} stacks",
+            user_id,
+            args.find,
+            edited.count()
+        );
+    } else {
+        for (name, stack) in Stack::get_stack_list(ctx.clone(), endpoint, false).await? {
+            if !stack.is_managed_by_dockru().await {
+                continue;
+            }
+            let files = stack.bulk_replace(&args.find, &args.replace, false).await?;
+            if !files.is_empty() {
+                stacks.push(BulkEditStack {
+                    stack_name: name,
+                    ok: true,
+                    files,
+                    error: None,
+                });
+            }
+        }
+        stacks.sort_by(|a, b| a.stack_name.cmp(&b.stack_name));
+    }
+
+    Ok(CustomResponse::ok_with_fields(BulkEnvEditResponse { stacks }).into())
+}
+
+/// Move the stacks directory to a new path (see relocation)
+async fn handle_relocate_stacks_dir(
+    socket: &SocketRef,
+    ctx: &Arc<ServerContext>,
+    data: &Value,
+) -> Result<serde_json::Value> {
+    let user_id = check_user_login(socket)?;
+    let new_path = parse_relocate_stacks_dir_args(data)?;
+
+    tracing::info!(
+        "User {} relocates the stacks directory to {}",
+        user_id,
+        new_path.display()
+    );
+    let relocation = crate::relocation::relocate_stacks_dir(ctx, &new_path).await?;
+
+    Ok(CustomResponse::ok_with_fields(RelocateStacksDirResponse { relocation }).into())
+}
+
+fn parse_relocate_stacks_dir_args(data: &Value) -> Result<PathBuf> {
+    let path = data
+        .as_array()
+        .and_then(|args| args.first())
+        .and_then(Value::as_str)
+        .filter(|s| !s.trim().is_empty())
+        .ok_or_else(|| anyhow!("newPath must be a non-empty string"))?;
+    Ok(PathBuf::from(path.trim()))
+}
+
+fn parse_bulk_env_edit_args(data: &Value) -> Result<BulkEnvEditArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let find = args
+        .first()
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("find must be a non-empty string"))?;
+    let replace = args
+        .get(1)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("replace must be a string"))?;
+    if find == replace {
+        return Err(anyhow!("find and replace are the same"));
+    }
+
+    let options = args.get(2).unwrap_or(&Value::Null);
+    let apply = options
+        .get("apply")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let stacks = match options.get("stacks") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(names)) => names
+            .iter()
+            .map(|name| {
+                name.as_str()
+                    .ok_or_else(|| anyhow!("stacks must be an array of strings"))
+                    .and_then(StackName::parse)
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(anyhow!("stacks must be an array of strings")),
+    };
+    if apply && stacks.is_empty() {
+        return Err(anyhow!("Confirm the stacks to edit"));
+    }
+    if stacks.len() > MAX_BATCH_STACKS {
+        return Err(anyhow!(
+            "At most {} stacks can be edited at once",
+            MAX_BATCH_STACKS
+        ));
+    }
+
+    Ok(BulkEnvEditArgs {
+        find: find.to_string(),
+        replace: replace.to_string(),
+        stacks,
+        apply,
+    })
+}
+
+/// Parse the optional { page, pageSize } query of getAuditLog
+fn parse_audit_log_query(data: &Value) -> Result<AuditLogQuery> {
+    let query: AuditLogQuery = match data {
+        Value::Null => AuditLogQuery::default(),
+        Value::Array(args) if args.is_empty() => AuditLogQuery::default(),
+        Value::Array(args) => return parse_audit_log_query(&args[0]),
+        query => serde_json::from_value(query.clone())
+            .map_err(|e| anyhow!("Invalid audit log query: {}", e))?,
+    };
+    if query.page < 0 {
+        return Err(anyhow!("page must not be negative"));
+    }
+    Ok(query)
+}
+
+/// Resident set size of the dockru process in bytes (Linux only)
+fn process_memory_rss() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        parse_vm_rss(&status)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Parse the VmRSS line of /proc/self/status into bytes
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vm_rss() {
+        let status = "Name:\tdockru\nVmPeak:\t  20000 kB\nVmRSS:\t   12345 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(12345 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tdockru\n"), None);
+    }
+
+    #[test]
+    fn test_parse_audit_log_query() {
+        let query = parse_audit_log_query(&Value::Null).unwrap();
+        assert_eq!(query.page, 0);
+        assert_eq!(query.page_size(), DEFAULT_AUDIT_LOG_PAGE_SIZE);
+
+        let query =
+            parse_audit_log_query(&serde_json::json!([{ "page": 2, "pageSize": 10000 }])).unwrap();
+        assert_eq!(query.page, 2);
+        assert_eq!(query.page_size(), MAX_AUDIT_LOG_PAGE_SIZE);
+
+        assert!(parse_audit_log_query(&serde_json::json!({ "page": -1 })).is_err());
+        assert!(parse_audit_log_query(&serde_json::json!({ "page": "x" })).is_err());
+    }
+
+    #[test]
+    fn test_parse_bulk_env_edit_args() {
+        let args =
+            parse_bulk_env_edit_args(&serde_json::json!(["smtp.old.lan", "smtp.lan"])).unwrap();
+        assert_eq!(args.find, "smtp.old.lan");
+        assert_eq!(args.replace, "smtp.lan");
+        assert!(args.stacks.is_empty());
+        assert!(!args.apply);
+
+        let args = parse_bulk_env_edit_args(&serde_json::json!([
+            "smtp.old.lan",
+            "smtp.lan",
+            { "stacks": ["mail", "web"], "apply": true }
+        ]))
+        .unwrap();
+        assert_eq!(args.stacks, vec!["mail", "web"]);
+        assert!(args.apply);
+
+        // Applying needs the confirmed stacks
+        assert!(
+            parse_bulk_env_edit_args(&serde_json::json!(["a", "b", { "apply": true }])).is_err()
+        );
+        assert!(parse_bulk_env_edit_args(&serde_json::json!(["", "b"])).is_err());
+        assert!(parse_bulk_env_edit_args(&serde_json::json!(["a", "a"])).is_err());
+        assert!(
+            parse_bulk_env_edit_args(&serde_json::json!(["a", "b", { "stacks": ["../etc"] }]))
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_parse_relocate_stacks_dir_args() {
+        assert_eq!(
+            parse_relocate_stacks_dir_args(&serde_json::json!(["/mnt/disk2/stacks"])).unwrap(),
+            PathBuf::from("/mnt/disk2/stacks")
+        );
+        assert!(parse_relocate_stacks_dir_args(&serde_json::json!([" "])).is_err());
+        assert!(parse_relocate_stacks_dir_args(&serde_json::json!([])).is_err());
+    }
+}
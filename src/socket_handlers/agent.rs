@@ -1,22 +1,60 @@
 use crate::agent_manager;
+use crate::cluster::ClusterEvent;
+use crate::db::models::agent::{Agent, AgentCredentials};
+use crate::db::models::AgentToken;
+use crate::discovery::{discovered_agents, DiscoveredAgent};
 use crate::server::ServerContext;
-use crate::socket_handlers::{callback_error, check_login, get_endpoint, ok_response};
+use crate::socket_handlers::{
+    broadcast_to_authenticated, callback_error, callback_ok, check_login, check_user_login,
+    get_endpoint, ok_response, spawn_handler, AckSlot,
+};
+use crate::utils::types::CustomResponse;
 use crate::utils::ALL_ENDPOINTS;
 use anyhow::anyhow;
-use serde::Deserialize;
+use dockru_protocol::events::server as server_event;
+use redact::Secret;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use socketioxide::extract::{AckSender, Data, SocketRef};
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
+use super::containers::dispatch_container_event;
+use super::disk_usage::dispatch_disk_usage_event;
+use super::migration::dispatch_migration_event;
+use super::networks::dispatch_network_event;
+use super::schedule::dispatch_schedule_event;
+use super::secrets::dispatch_secret_event;
 use super::stack_management::dispatch_stack_event;
+use super::stats::dispatch_stats_event;
 use super::terminal::dispatch_terminal_event;
+use super::transfer::dispatch_transfer_event;
 
 #[derive(Debug, Deserialize)]
 struct AddAgentData {
     url: String,
+    #[serde(default)]
     username: String,
+    #[serde(default)]
     password: String,
+    /// Agent token created on the remote instance, instead of username/password
+    #[serde(default)]
+    token: Option<String>,
+}
+
+impl AddAgentData {
+    fn credentials(&self) -> Result<AgentCredentials, anyhow::Error> {
+        match self.token.as_deref().map(str::trim) {
+            Some(token) if !token.is_empty() => {
+                Ok(AgentCredentials::Token(Secret::new(token.to_string())))
+            }
+            _ if self.username.is_empty() => Err(anyhow!("Username or agent token is required")),
+            _ => Ok(AgentCredentials::Password {
+                username: self.username.clone(),
+                password: Secret::new(self.password.clone()),
+            }),
+        }
+    }
 }
 
 /// Setup agent management event handlers
@@ -27,12 +65,12 @@ pub fn setup_agent_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "addAgent",
         async move |socket: SocketRef, Data::<AddAgentData>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("addAgent", ack, |ack| async move {
                 match handle_add_agent(&socket, &ctx, data).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -44,12 +82,78 @@ pub fn setup_agent_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "removeAgent",
         async move |socket: SocketRef, Data::<String>(url), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("removeAgent", ack, |ack| async move {
                 match handle_remove_agent(&socket, &ctx, &url).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // createAgentToken - Issue a token another instance can add this one with
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "createAgentToken",
+        async move |socket: SocketRef, Data::<String>(name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("createAgentToken", ack, |ack| async move {
+                match handle_create_agent_token(&socket, &ctx, &name).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // getAgentTokenList
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getAgentTokenList",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getAgentTokenList", ack, |ack| async move {
+                match handle_get_agent_token_list(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // deleteAgentToken - Revoke a token; controllers using it can no longer log in
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "deleteAgentToken",
+        async move |socket: SocketRef, Data::<i64>(id), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("deleteAgentToken", ack, |ack| async move {
+                match handle_delete_agent_token(&socket, &ctx, id).await {
+                    Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // discoverAgents - Instances announced on the LAN over mDNS
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "discoverAgents",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("discoverAgents", ack, |ack| async move {
+                match handle_discover_agents(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -62,9 +166,10 @@ pub fn setup_agent_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "agent",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
-                if let Err(e) = handle_agent_proxy(&socket, &ctx, data, ack).await {
+            spawn_handler("agent", ack, |ack| async move {
+                if let Err(e) = handle_agent_proxy(&socket, &ctx, data, &ack).await {
                     warn!("Agent proxy error: {}", e);
+                    callback_error(ack.take(), e);
                 }
             });
         },
@@ -73,12 +178,13 @@ pub fn setup_agent_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
 
 async fn handle_add_agent(
     socket: &SocketRef,
-    _ctx: &ServerContext,
+    ctx: &ServerContext,
     data: AddAgentData,
 ) -> Result<serde_json::Value, anyhow::Error> {
-    check_login(socket)?;
+    check_user_login(socket)?;
 
     info!("Adding agent: {}", data.url);
+    let credentials = data.credentials()?;
 
     // Get agent manager
     let manager = agent_manager::get_agent_manager(&socket.id.to_string())
@@ -86,25 +192,20 @@ async fn handle_add_agent(
         .ok_or_else(|| anyhow!("Agent manager not found"))?;
 
     // Test connection first
-    manager
-        .test(&data.url, &data.username, &data.password)
-        .await?;
+    manager.test(&data.url, &credentials).await?;
 
     // Add to database
-    manager
-        .add(&data.url, &data.username, &data.password)
-        .await?;
+    manager.add(&data.url, &credentials).await?;
 
     // Connect to the agent
-    manager
-        .connect(&data.url, &data.username, &data.password)
-        .await;
+    manager.connect(&data.url, &credentials).await;
 
     // Broadcast to force refresh other clients
     // TODO: Implement disconnectAllSocketClients except current socket
 
     // Send updated agent list
     manager.send_agent_list().await;
+    crate::cluster::publish(ctx, ClusterEvent::AgentList).await;
 
     Ok(ok_response(json!({
         "msg": "agentAddedSuccessfully",
@@ -115,10 +216,10 @@ async fn handle_add_agent(
 
 async fn handle_remove_agent(
     socket: &SocketRef,
-    _ctx: &ServerContext,
+    ctx: &ServerContext,
     url: &str,
 ) -> Result<serde_json::Value, anyhow::Error> {
-    check_login(socket)?;
+    check_user_login(socket)?;
 
     info!("Removing agent: {}", url);
 
@@ -128,9 +229,27 @@ async fn handle_remove_agent(
         .ok_or_else(|| anyhow!("Agent manager not found"))?;
 
     // Remove agent
-    manager.remove(url).await?;
-
-    // TODO: Broadcast to force refresh other clients
+    let endpoint = manager.remove(url).await?;
+
+    // Close proxied sessions to the endpoint on every socket, not just this one
+    agent_manager::deregister_endpoint(&endpoint).await;
+
+    // Tell clients to drop the endpoint's tabs, stacks and terminals
+    if let Err(e) = broadcast_to_authenticated(
+        &ctx.io,
+        server_event::AGENT_REMOVED,
+        json!({
+            "endpoint": endpoint,
+            "url": url,
+            "removeStacks": true,
+            "closeTerminals": true,
+        }),
+    )
+    .await
+    {
+        warn!("Failed to broadcast agentRemoved: {}", e);
+    }
+    crate::cluster::publish(ctx, ClusterEvent::AgentList).await;
 
     Ok(ok_response(json!({
         "msg": "agentRemovedSuccessfully",
@@ -139,11 +258,106 @@ async fn handle_remove_agent(
     .into())
 }
 
+async fn handle_create_agent_token(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    name: &str,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let user_id = check_user_login(socket)?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Token name must not be empty"));
+    }
+
+    let (agent_token, token) = AgentToken::create(&ctx.db, user_id, name).await?;
+    info!("Created agent token \"{}\" for user {}", name, user_id);
+
+    #[derive(Serialize)]
+    struct CreateAgentTokenResponse {
+        /// Only returned once, it can't be recovered afterwards
+        token: String,
+        #[serde(rename = "agentToken")]
+        agent_token: AgentToken,
+    }
+
+    Ok(CustomResponse::ok_with_fields(CreateAgentTokenResponse { token, agent_token }).into())
+}
+
+async fn handle_get_agent_token_list(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value, anyhow::Error> {
+    let user_id = check_user_login(socket)?;
+
+    #[derive(Serialize)]
+    struct AgentTokenListResponse {
+        #[serde(rename = "agentTokens")]
+        agent_tokens: Vec<AgentToken>,
+    }
+
+    let agent_tokens = AgentToken::find_by_user(&ctx.db, user_id).await?;
+
+    Ok(CustomResponse::ok_with_fields(AgentTokenListResponse { agent_tokens }).into())
+}
+
+async fn handle_delete_agent_token(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    id: i64,
+) -> Result<(), anyhow::Error> {
+    let user_id = check_user_login(socket)?;
+
+    if !AgentToken::delete(&ctx.db, id, user_id).await? {
+        return Err(anyhow!("Agent token not found"));
+    }
+    info!("Deleted agent token {} of user {}", id, user_id);
+
+    Ok(())
+}
+
+/// Discovered instances, flagged when they are already added as an agent
+async fn handle_discover_agents(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value, anyhow::Error> {
+    check_user_login(socket)?;
+
+    #[derive(Serialize)]
+    struct Candidate {
+        #[serde(flatten)]
+        agent: DiscoveredAgent,
+        paired: bool,
+    }
+
+    #[derive(Serialize)]
+    struct DiscoverAgentsResponse {
+        /// Whether mDNS discovery is enabled on this instance
+        enabled: bool,
+        agents: Vec<Candidate>,
+    }
+
+    let endpoints = Agent::endpoints(&ctx.db).await?;
+    let agents = discovered_agents()
+        .into_iter()
+        .map(|agent| Candidate {
+            paired: endpoints.contains(&agent.endpoint),
+            agent,
+        })
+        .collect();
+
+    Ok(CustomResponse::ok_with_fields(DiscoverAgentsResponse {
+        enabled: ctx.config.mdns,
+        agents,
+    })
+    .into())
+}
+
 async fn handle_agent_proxy(
     socket: &SocketRef,
     ctx: &ServerContext,
     data: serde_json::Value,
-    ack: AckSender,
+    ack: &AckSlot,
 ) -> Result<(), anyhow::Error> {
     check_login(socket)?;
 
@@ -185,7 +399,7 @@ async fn handle_agent_proxy(
         debug!("Sending to all endpoints: {}", event_name);
 
         // Handle locally first
-        let mut local_ack = Some(ack);
+        let mut local_ack = ack.take();
         dispatch_local_event(socket, ctx, event_name, &event_args, &mut local_ack).await;
 
         // Forward to remote endpoints
@@ -195,15 +409,20 @@ async fn handle_agent_proxy(
     } else if endpoint.is_empty() || endpoint == socket_endpoint {
         // Direct connection or matching endpoint - handle locally
         debug!("Handling local event: {}", event_name);
-        let mut local_ack = Some(ack);
+        let mut local_ack = ack.take();
         dispatch_local_event(socket, ctx, event_name, &event_args, &mut local_ack).await;
     } else {
-        // Proxy to specific remote endpoint
+        // Proxy to specific remote endpoint, passing its response on to the ack
         debug!("Proxying request to {} for {}", endpoint, event_name);
-        // TODO: Forward ack to remote endpoint
-        manager
-            .emit_to_endpoint(endpoint, event_name, json!(event_args))
-            .await?;
+        match manager
+            .emit_to_endpoint_with_ack(endpoint, event_name, json!(event_args))
+            .await
+        {
+            Ok(response) => {
+                ack.send(&response).ok();
+            }
+            Err(e) => callback_error(ack.take(), e),
+        }
     }
 
     Ok(())
@@ -217,6 +436,11 @@ async fn dispatch_local_event(
     event_args: &[serde_json::Value],
     ack: &mut Option<AckSender>,
 ) {
+    if let Err(e) = crate::relocation::check_event(event_name) {
+        callback_error(ack.take(), e);
+        return;
+    }
+
     // Try stack handlers first
     match dispatch_stack_event(socket, ctx, event_name, event_args, ack).await {
         Ok(true) => return,
@@ -239,6 +463,94 @@ async fn dispatch_local_event(
         }
     }
 
+    // Try schedule handlers
+    match dispatch_schedule_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Schedule event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try secret handlers
+    match dispatch_secret_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Secret event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try chunked transfer handlers
+    match dispatch_transfer_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Transfer event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try container stats handlers
+    match dispatch_stats_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Stats event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try network handlers
+    match dispatch_network_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Network event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try disk usage handlers
+    match dispatch_disk_usage_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Disk usage event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try migration handlers
+    match dispatch_migration_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Migration event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
+    // Try container handlers
+    match dispatch_container_event(socket, ctx, event_name, event_args, ack).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => {
+            warn!("Container event dispatch error for {}: {}", event_name, e);
+            callback_error(ack.take(), e);
+            return;
+        }
+    }
+
     // No handler found
     warn!("Unknown local agent event: {}", event_name);
     callback_error(ack.take(), anyhow!("Unknown event: {}", event_name));
@@ -260,6 +572,29 @@ mod tests {
         assert_eq!(data.username, "admin");
     }
 
+    #[test]
+    fn test_add_agent_credentials() {
+        let data: AddAgentData =
+            serde_json::from_str(r#"{"url": "http://localhost:5002", "token": "abc"}"#).unwrap();
+        assert!(matches!(
+            data.credentials().unwrap(),
+            AgentCredentials::Token(_)
+        ));
+
+        let data: AddAgentData = serde_json::from_str(
+            r#"{"url": "http://localhost:5002", "username": "admin", "password": "secret", "token": ""}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            data.credentials().unwrap(),
+            AgentCredentials::Password { .. }
+        ));
+
+        let data: AddAgentData =
+            serde_json::from_str(r#"{"url": "http://localhost:5002"}"#).unwrap();
+        assert!(data.credentials().is_err());
+    }
+
     #[test]
     fn test_remove_agent_deserialize() {
         let json = r#""http://localhost:5002""#;
@@ -1,22 +1,29 @@
 use crate::auth::{create_jwt, hash_password, shake256, verify_jwt, SHAKE256_LENGTH};
-use crate::db::models::{NewUser, Setting, User};
+use crate::db::models::{AgentToken, NewUser, Setting, User};
 use crate::rate_limiter::{LoginRateLimiter, TwoFaRateLimiter};
 use crate::server::ServerContext;
 use crate::socket_handlers::add_authenticated_socket;
 use crate::socket_handlers::{
-    broadcast_to_authenticated, callback_error, callback_ok, check_login, error_response,
-    error_response_i18n, set_endpoint, set_user_id,
+    broadcast_to_authenticated, callback_error, callback_ok, check_login, check_user_login,
+    error_response, error_response_i18n, get_endpoint, get_ip_address, set_agent_token_id,
+    set_user_id, set_username, spawn_handler,
 };
 use crate::utils::crypto::gen_secret;
 use crate::utils::types::{BaseRes, CustomResponse};
 use anyhow::{anyhow, Result};
+use dockru_protocol::events::server as server_event;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use socketioxide::extract::{AckSender, Data, SocketRef};
 use sqlx::SqlitePool;
+use std::net::IpAddr;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
+/// Shared limiter so agent token guessing is throttled across connections
+static AGENT_TOKEN_RATE_LIMITER: once_cell::sync::Lazy<LoginRateLimiter> =
+    once_cell::sync::Lazy::new(LoginRateLimiter::new);
+
 #[derive(Debug, Deserialize)]
 struct SetupData {
     username: String,
@@ -47,7 +54,7 @@ pub fn setup_auth_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         async move |socket: SocketRef, ack: AckSender| {
             let ctx = ctx_clone.clone();
             info!("'needSetup' event from socket {}", socket.id);
-            tokio::spawn(async move {
+            spawn_handler("needSetup", ack, |ack| async move {
                 let user_count = User::count(&ctx.db).await.unwrap_or(0);
                 let need_setup = user_count == 0;
                 info!(
@@ -71,7 +78,7 @@ pub fn setup_auth_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
                 "'setup' event from socket {} for user '{}'",
                 socket.id, data.username
             );
-            tokio::spawn(async move {
+            spawn_handler("setup", ack, |ack| async move {
                 match handle_setup(&socket, &ctx, data).await {
                     Ok(response) => {
                         info!("Setup handler succeeded for socket {}", socket.id);
@@ -98,7 +105,7 @@ pub fn setup_auth_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
                 "'login' event from socket {} for user '{}'",
                 socket.id, data.username
             );
-            tokio::spawn(async move {
+            spawn_handler("login", ack, |ack| async move {
                 match handle_login(&socket, &ctx, data).await {
                     Ok(response) => {
                         info!("Login handler succeeded for socket {}", socket.id);
@@ -122,7 +129,7 @@ pub fn setup_auth_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         async move |socket: SocketRef, Data::<String>(token), ack: AckSender| {
             let ctx = ctx_clone.clone();
             info!("'loginByToken' event from socket {}", socket.id);
-            tokio::spawn(async move {
+            spawn_handler("loginByToken", ack, |ack| async move {
                 match handle_login_by_token(&socket, &ctx, &token).await {
                     Ok(response) => {
                         info!("loginByToken succeeded for socket {}", socket.id);
@@ -144,16 +151,38 @@ pub fn setup_auth_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         },
     );
 
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "loginByAgentToken",
+        async move |socket: SocketRef, Data::<String>(token), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            info!("'loginByAgentToken' event from socket {}", socket.id);
+            spawn_handler("loginByAgentToken", ack, |ack| async move {
+                match handle_login_by_agent_token(&socket, &ctx, &token).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => {
+                        warn!("loginByAgentToken failed for socket {}: {}", socket.id, e);
+                        let response: serde_json::Value =
+                            error_response_i18n("authInvalidToken").into();
+                        ack.send(&response).ok();
+                    }
+                };
+            });
+        },
+    );
+
     let ctx_clone = ctx.clone();
     socket.on(
         "changePassword",
         async move |socket: SocketRef, Data::<ChangePasswordData>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("changePassword", ack, |ack| async move {
                 if let Err(e) = handle_change_password(&socket, &ctx, data).await {
-                    callback_error(Some(ack), e);
+                    callback_error(ack.take(), e);
                 } else {
-                    callback_ok(Some(ack), "Password has been updated successfully.", false);
+                    callback_ok(ack.take(), "Password has been updated successfully.", false);
                 }
             });
         },
@@ -218,7 +247,7 @@ async fn handle_setup(
     }
 
     // Broadcast that setup is complete
-    broadcast_to_authenticated(&ctx.io, "setup", json!({})).await?;
+    broadcast_to_authenticated(&ctx.io, server_event::SETUP, json!({})).await?;
 
     Ok(BaseRes::ok_with_msg_i18n("successAdded").into())
 }
@@ -335,11 +364,9 @@ async fn handle_login_by_token(
         return Ok(error_response_i18n("authUserInactiveOrDeleted").into());
     }
 
-    // Verify password hash matches (detect password change)
-    let stored_password = user
-        .password
-        .as_ref()
-        .ok_or_else(|| anyhow!("User has no password"))?;
+    // Verify password hash matches (detect password change). Users created
+    // from the trusted auth header have no password.
+    let stored_password = user.password.as_deref().unwrap_or_default();
     let stored_hash = shake256(stored_password, SHAKE256_LENGTH);
     if password_hash != stored_hash {
         return Err(anyhow!(
@@ -354,12 +381,136 @@ async fn handle_login_by_token(
     Ok(BaseRes::ok().into())
 }
 
+/// Count an agent token login attempt from `ip`, false once over the limit
+fn agent_token_login_allowed(ip: IpAddr) -> bool {
+    AGENT_TOKEN_RATE_LIMITER.check(ip).is_ok()
+}
+
+/// Log in a controller connecting with an agent token
+async fn handle_login_by_agent_token(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    token: &str,
+) -> Result<serde_json::Value> {
+    // Limited by the connection's peer, which get_client_ip doesn't know yet
+    let ip = get_ip_address(socket).unwrap_or_else(|| get_client_ip(socket));
+    if !agent_token_login_allowed(ip) {
+        info!("Agent token login rate limit exceeded for IP: {:?}", ip);
+        return Ok(error_response_i18n("authRateLimitExceeded").into());
+    }
+
+    let agent_token = AgentToken::verify(&ctx.db, token)
+        .await?
+        .ok_or_else(|| anyhow!("Unknown agent token"))?;
+
+    let user = User::find_by_id(&ctx.db, agent_token.user_id)
+        .await?
+        .filter(|user| user.active)
+        .ok_or_else(|| anyhow!("authUserInactiveOrDeleted"))?;
+
+    set_agent_token_id(socket, agent_token.id);
+    after_login(socket, ctx, &user).await?;
+
+    info!(
+        "Agent token \"{}\" logged in as user {}. IP={}",
+        agent_token.name, user.username, ip
+    );
+
+    Ok(BaseRes::ok().into())
+}
+
+/// Log a socket in as the user a trusted reverse proxy vouches for
+///
+/// Creates the user, without a password, on first sight. Returns a login token
+/// for the client, or None when the socket didn't come through a trusted proxy
+/// with the auth header.
+pub(crate) async fn login_by_trusted_header(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<Option<String>> {
+    let peer = get_ip_address(socket);
+    let Some(username) =
+        crate::header_auth::trusted_identity(&ctx.config, peer, &socket.req_parts().headers)
+    else {
+        return Ok(None);
+    };
+
+    let user = match User::find_by_username(&ctx.db, &username).await? {
+        Some(user) => user,
+        None => {
+            let user = User::create(
+                &ctx.db,
+                NewUser {
+                    username: username.clone(),
+                    password: None,
+                    active: true,
+                    timezone: None,
+                },
+            )
+            .await?;
+            init_jwt_secret(&ctx.db).await?;
+            info!("Created user {} from the trusted auth header", username);
+            user
+        }
+    };
+    if !user.active {
+        return Err(anyhow!("authUserInactiveOrDeleted"));
+    }
+
+    after_login(socket, ctx, &user).await?;
+
+    let jwt_secret_value = Setting::get(&ctx.db, &ctx.cache, "jwtSecret")
+        .await?
+        .ok_or_else(|| anyhow!("JWT secret not found"))?;
+    let jwt_secret = jwt_secret_value
+        .as_str()
+        .ok_or_else(|| anyhow!("JWT secret is not a string"))?;
+    let token = create_jwt(
+        &user.username,
+        user.password.as_deref().unwrap_or_default(),
+        jwt_secret,
+    )?;
+
+    info!(
+        "Logged in user {} from the trusted auth header. IP={:?}",
+        user.username, peer
+    );
+    Ok(Some(token))
+}
+
+/// Resolve the active user a login token (JWT) belongs to
+///
+/// Used to authenticate HTTP requests with the token the frontend got from login.
+pub(crate) async fn user_from_token(ctx: &ServerContext, token: &str) -> Result<User> {
+    let jwt_secret_value = Setting::get(&ctx.db, &ctx.cache, "jwtSecret")
+        .await?
+        .ok_or_else(|| anyhow!("JWT secret not found"))?;
+    let jwt_secret = jwt_secret_value
+        .as_str()
+        .ok_or_else(|| anyhow!("JWT secret is not a string"))?;
+
+    let payload = verify_jwt(token, jwt_secret)?;
+    let user = User::find_by_username(&ctx.db, &payload.username)
+        .await?
+        .filter(|user| user.active)
+        .ok_or_else(|| anyhow!("authUserInactiveOrDeleted"))?;
+
+    let stored_hash = shake256(user.password.as_deref().unwrap_or_default(), SHAKE256_LENGTH);
+    if payload.h != stored_hash {
+        return Err(anyhow!(
+            "The token is invalid due to password change or old token"
+        ));
+    }
+
+    Ok(user)
+}
+
 async fn handle_change_password(
     socket: &SocketRef,
     ctx: &ServerContext,
     data: ChangePasswordData,
 ) -> Result<()> {
-    let user_id = check_login(socket)?;
+    let user_id = check_user_login(socket)?;
 
     // Validate new password
     if data.new_password.len() < 6 {
@@ -399,6 +550,7 @@ async fn handle_disconnect_others(socket: &SocketRef, ctx: &ServerContext) -> Re
 async fn after_login(socket: &SocketRef, ctx: &ServerContext, user: &User) -> Result<()> {
     // Set user ID in socket state
     set_user_id(socket, user.id);
+    set_username(socket, &user.username);
 
     // Mark socket as authenticated by joining the authenticated room
     add_authenticated_socket(socket);
@@ -406,9 +558,8 @@ async fn after_login(socket: &SocketRef, ctx: &ServerContext, user: &User) -> Re
     // Join user room for broadcasting
     socket.join(user.id.to_string());
 
-    // Set endpoint from request headers or default to empty
-    let endpoint = extract_endpoint(socket).unwrap_or_default();
-    set_endpoint(socket, endpoint.clone());
+    // Captured from the handshake headers, empty for the local endpoint
+    let endpoint = get_endpoint(socket);
 
     // Send server info (Phase 10)
     crate::broadcasts::send_info(socket, ctx, false).await?;
@@ -438,14 +589,6 @@ fn get_client_ip(_socket: &SocketRef) -> std::net::IpAddr {
     std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1))
 }
 
-/// Extract endpoint from request headers
-fn extract_endpoint(_socket: &SocketRef) -> Option<String> {
-    // socketioxide doesn't currently expose request headers
-    // We would need to extract this at connection time
-    // For now, default to empty (local endpoint)
-    Some("".to_string())
-}
-
 /// Disconnect all sockets for a user except the current one
 async fn disconnect_all_other_sockets(
     ctx: &ServerContext,
@@ -456,7 +599,7 @@ async fn disconnect_all_other_sockets(
     // For now, emit refresh to the user room
     ctx.io
         .to(user_id.to_string())
-        .emit("refresh", &json!({}))
+        .emit(server_event::REFRESH, &json!({}))
         .await
         .map_err(|e| anyhow::anyhow!("Failed to disconnect other sockets: {}", e))?;
     debug!(
@@ -512,6 +655,17 @@ mod tests {
         assert!(data.token.is_none());
     }
 
+    #[test]
+    fn test_agent_token_login_rate_limited() {
+        let ip: IpAddr = "192.0.2.66".parse().unwrap();
+        for _ in 0..20 {
+            assert!(agent_token_login_allowed(ip));
+        }
+        assert!(!agent_token_login_allowed(ip));
+        // Other clients aren't affected
+        assert!(agent_token_login_allowed("192.0.2.67".parse().unwrap()));
+    }
+
     #[test]
     fn test_change_password_data_deserialize() {
         let json = r#"{"currentPassword": "old123", "newPassword": "new123"}"#;
@@ -0,0 +1,153 @@
+// Remote instance backups
+//
+//   getBackupConfig                -> { config }
+//   setBackupConfig  [config]
+//   runBackup                      -> { backup }
+//   getBackupStatus                -> { running, lastRun }
+//
+// See backup for the configuration and what a backup contains.
+
+use crate::backup::{
+    self, BackupConfig, BackupRun, BACKUP_CONFIG_SETTING, BACKUP_CONFIG_SETTING_TYPE,
+};
+use crate::cluster::ClusterEvent;
+use crate::db::models::Setting;
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, callback_ok, callback_ok_with_fields, check_login, check_user_login,
+    spawn_handler,
+};
+use crate::utils::types::CustomResponse;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Serialize)]
+struct BackupConfigResponse {
+    config: BackupConfig,
+}
+
+#[derive(Serialize)]
+struct RunBackupResponse {
+    backup: BackupRun,
+}
+
+#[derive(Serialize)]
+struct BackupStatusResponse {
+    running: bool,
+    #[serde(rename = "lastRun")]
+    last_run: Option<BackupRun>,
+}
+
+pub fn setup_backup_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // getBackupConfig
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getBackupConfig",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getBackupConfig", ack, |ack| async move {
+                match handle_get_backup_config(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // setBackupConfig
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setBackupConfig",
+        async move |socket: SocketRef, Data::<BackupConfig>(config), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setBackupConfig", ack, |ack| async move {
+                match handle_set_backup_config(&socket, &ctx, config).await {
+                    Ok(_) => callback_ok(ack.take(), "Saved", true),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // runBackup
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "runBackup",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("runBackup", ack, |ack| async move {
+                match handle_run_backup(&socket, &ctx).await {
+                    Ok(backup) => callback_ok_with_fields(
+                        ack.take(),
+                        "Backup uploaded",
+                        false,
+                        RunBackupResponse { backup },
+                    ),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // getBackupStatus
+    socket.on(
+        "getBackupStatus",
+        async move |socket: SocketRef, ack: AckSender| {
+            spawn_handler("getBackupStatus", ack, |ack| async move {
+                match handle_get_backup_status(&socket) {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+async fn handle_get_backup_config(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+    let config = backup::load_config(ctx).await?;
+    Ok(CustomResponse::ok_with_fields(BackupConfigResponse { config }).into())
+}
+
+async fn handle_set_backup_config(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    config: BackupConfig,
+) -> Result<()> {
+    let user_id = check_user_login(socket)?;
+    config.validate()?;
+
+    Setting::set(
+        &ctx.db,
+        &ctx.cache,
+        BACKUP_CONFIG_SETTING,
+        &serde_json::to_value(&config)?,
+        Some(BACKUP_CONFIG_SETTING_TYPE),
+    )
+    .await?;
+    info!("User {} updated the backup configuration", user_id);
+
+    crate::cluster::publish(ctx, ClusterEvent::Settings).await;
+
+    Ok(())
+}
+
+async fn handle_run_backup(socket: &SocketRef, ctx: &ServerContext) -> Result<BackupRun> {
+    let user_id = check_user_login(socket)?;
+    info!("User {} started a backup", user_id);
+    backup::run_backup(ctx).await
+}
+
+fn handle_get_backup_status(socket: &SocketRef) -> Result<Value> {
+    check_login(socket)?;
+    let (running, last_run) = backup::status();
+    Ok(CustomResponse::ok_with_fields(BackupStatusResponse { running, last_run }).into())
+}
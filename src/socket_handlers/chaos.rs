@@ -0,0 +1,47 @@
+// Failure injection (`chaos` feature, development builds only)
+//
+//   debugInject  { kind, ... }
+//
+// Arms an injected failure or disconnects an agent right away. See chaos for
+// the kinds and their fields.
+
+use crate::agent_manager;
+use crate::chaos::{self, Injection};
+use crate::server::ServerContext;
+use crate::socket_handlers::{callback_error, callback_ok, check_user_login, spawn_handler};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+use tracing::warn;
+
+pub fn setup_chaos_handlers(socket: SocketRef, _ctx: Arc<ServerContext>) {
+    // debugInject
+    socket.on(
+        "debugInject",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            spawn_handler("debugInject", ack, |ack| async move {
+                match handle_debug_inject(&socket, data).await {
+                    Ok(()) => callback_ok(ack.take(), "Injected", false),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+async fn handle_debug_inject(socket: &SocketRef, data: Value) -> Result<()> {
+    let user_id = check_user_login(socket)?;
+    let injection: Injection =
+        serde_json::from_value(data).map_err(|e| anyhow!("Invalid injection: {}", e))?;
+    warn!("User {} injected a failure: {:?}", user_id, injection);
+
+    if let Injection::AgentDisconnect { endpoint } = &injection {
+        let manager = agent_manager::get_agent_manager(&socket.id.to_string())
+            .await
+            .ok_or_else(|| anyhow!("Agent manager not found"))?;
+        manager.disconnect(endpoint).await;
+    }
+    chaos::arm(&injection);
+    Ok(())
+}
@@ -0,0 +1,449 @@
+// Container details
+//
+//   inspectContainer  [stackName, serviceName, { redactEnv }?]  -> { containers }
+//   probeService  [stackName, serviceName, { host, port, scheme, path }?]  -> { results }
+//   restartContainer  [stackName, container]
+//   serviceProcessList  [stackName, serviceName]  -> { containers }
+//
+// redactEnv defaults to true. See container_inspect for what is reported.
+// serviceProcessList returns the `docker top` table of each running container
+// of the service.
+// restartContainer restarts one container of the stack, by ID or name, where
+// restartService would restart every replica of the service.
+// probeService connects to the service's published ports from the dockru
+// host, see probe for the options.
+
+use crate::container_inspect::{self, ContainerDetails, ContainerProcesses};
+use crate::probe::{self, ProbeOptions, ProbeResult};
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, callback_ok, check_login, check_user_login, spawn_handler,
+};
+use crate::utils::stack_name::StackName;
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Debug, PartialEq, Eq)]
+struct InspectContainerArgs {
+    stack_name: StackName,
+    service_name: String,
+    redact_env: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ProbeServiceArgs {
+    stack_name: StackName,
+    service_name: String,
+    options: ProbeOptions,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct RestartContainerArgs {
+    stack_name: StackName,
+    container: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ServiceProcessListArgs {
+    stack_name: StackName,
+    service_name: String,
+}
+
+#[derive(Serialize)]
+struct InspectContainerResponse {
+    containers: Vec<ContainerDetails>,
+}
+
+#[derive(Serialize)]
+struct ProbeServiceResponse {
+    results: Vec<ProbeResult>,
+}
+
+#[derive(Serialize)]
+struct ServiceProcessListResponse {
+    containers: Vec<ContainerProcesses>,
+}
+
+pub fn setup_container_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // inspectContainer
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "inspectContainer",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("inspectContainer", ack, |ack| async move {
+                match handle_inspect_container(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // probeService
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "probeService",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("probeService", ack, |ack| async move {
+                match handle_probe_service(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // restartContainer
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "restartContainer",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("restartContainer", ack, |ack| async move {
+                match handle_restart_container(&socket, &ctx, &data).await {
+                    Ok(()) => callback_ok(ack.take(), "Restarted", false),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // serviceProcessList
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "serviceProcessList",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("serviceProcessList", ack, |ack| async move {
+                match handle_service_process_list(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Dispatch a container event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_container_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    let data = Value::Array(event_args.to_vec());
+    match event_name {
+        "inspectContainer" => {
+            match handle_inspect_container(socket, ctx, &data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "probeService" => {
+            match handle_probe_service(socket, ctx, &data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "restartContainer" => {
+            match handle_restart_container(socket, ctx, &data).await {
+                Ok(()) => callback_ok(ack.take(), "Restarted", false),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "serviceProcessList" => {
+            match handle_service_process_list(socket, ctx, &data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn parse_inspect_container_args(data: &Value) -> Result<InspectContainerArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let service_name = args
+        .get(1)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("serviceName must be a string"))?;
+    let redact_env = args
+        .get(2)
+        .and_then(|options| options.get("redactEnv"))
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+
+    Ok(InspectContainerArgs {
+        stack_name: StackName::parse(stack_name)?,
+        service_name: service_name.to_string(),
+        redact_env,
+    })
+}
+
+fn parse_probe_service_args(data: &Value) -> Result<ProbeServiceArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let service_name = args
+        .get(1)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("serviceName must be a string"))?;
+    let options = args.get(2).unwrap_or(&Value::Null);
+    let string_option = |key: &str| -> Result<Option<String>> {
+        match options.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) if !s.is_empty() => Ok(Some(s.clone())),
+            Some(_) => Err(anyhow!("{} must be a string", key)),
+        }
+    };
+    let port = match options.get("port") {
+        None | Some(Value::Null) => None,
+        Some(port) => Some(
+            port.as_u64()
+                .and_then(|p| u16::try_from(p).ok())
+                .filter(|p| *p > 0)
+                .ok_or_else(|| anyhow!("port must be a port number"))?,
+        ),
+    };
+    let options = ProbeOptions {
+        host: string_option("host")?,
+        port,
+        scheme: string_option("scheme")?,
+        path: string_option("path")?,
+    };
+    options.validate()?;
+
+    Ok(ProbeServiceArgs {
+        stack_name: StackName::parse(stack_name)?,
+        service_name: service_name.to_string(),
+        options,
+    })
+}
+
+fn parse_restart_container_args(data: &Value) -> Result<RestartContainerArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let container = args
+        .get(1)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("container must be a string"))?;
+
+    Ok(RestartContainerArgs {
+        stack_name: StackName::parse(stack_name)?,
+        container: container.to_string(),
+    })
+}
+
+fn parse_service_process_list_args(data: &Value) -> Result<ServiceProcessListArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let service_name = args
+        .get(1)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| anyhow!("serviceName must be a string"))?;
+
+    Ok(ServiceProcessListArgs {
+        stack_name: StackName::parse(stack_name)?,
+        service_name: service_name.to_string(),
+    })
+}
+
+async fn handle_inspect_container(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    check_login(socket)?;
+    let args = parse_inspect_container_args(data)?;
+    let context =
+        crate::docker::stack_docker_context(&ctx.config.stacks_dir.join(args.stack_name.as_str()));
+    let docker = ctx.docker.for_context(context.as_deref()).await?;
+    let containers = container_inspect::inspect_service(
+        &docker,
+        &args.stack_name,
+        &args.service_name,
+        args.redact_env,
+    )
+    .await?;
+    Ok(CustomResponse::ok_with_fields(InspectContainerResponse { containers }).into())
+}
+
+async fn handle_probe_service(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    check_login(socket)?;
+    let args = parse_probe_service_args(data)?;
+    let context =
+        crate::docker::stack_docker_context(&ctx.config.stacks_dir.join(args.stack_name.as_str()));
+    let docker = ctx.docker.for_context(context.as_deref()).await?;
+    let results = probe::probe_service(
+        &docker,
+        args.stack_name.as_str(),
+        &args.service_name,
+        &args.options,
+    )
+    .await?;
+    Ok(CustomResponse::ok_with_fields(ProbeServiceResponse { results }).into())
+}
+
+async fn handle_restart_container(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<()> {
+    let user_id = check_user_login(socket)?;
+    let args = parse_restart_container_args(data)?;
+    let context =
+        crate::docker::stack_docker_context(&ctx.config.stacks_dir.join(args.stack_name.as_str()));
+    let docker = ctx.docker.for_context(context.as_deref()).await?;
+    let name = crate::docker::restart_container(&docker, args.stack_name.as_str(), &args.container)
+        .await?;
+    info!(
+        "User {} restarted container {} of stack {}",
+        user_id, name, args.stack_name
+    );
+    Ok(())
+}
+
+async fn handle_service_process_list(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    check_login(socket)?;
+    let args = parse_service_process_list_args(data)?;
+    let context =
+        crate::docker::stack_docker_context(&ctx.config.stacks_dir.join(args.stack_name.as_str()));
+    let docker = ctx.docker.for_context(context.as_deref()).await?;
+    let containers =
+        container_inspect::service_processes(&docker, &args.stack_name, &args.service_name).await?;
+    Ok(CustomResponse::ok_with_fields(ServiceProcessListResponse { containers }).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_inspect_container_args() {
+        let args = parse_inspect_container_args(&json!(["web", "app"])).unwrap();
+        assert_eq!(args.stack_name.as_str(), "web");
+        assert_eq!(args.service_name, "app");
+        assert!(args.redact_env);
+
+        let args =
+            parse_inspect_container_args(&json!(["web", "app", { "redactEnv": false }])).unwrap();
+        assert!(!args.redact_env);
+
+        assert!(parse_inspect_container_args(&json!(["web"])).is_err());
+        assert!(parse_inspect_container_args(&json!(["../etc", "app"])).is_err());
+        assert!(parse_inspect_container_args(&json!("web")).is_err());
+    }
+
+    #[test]
+    fn test_parse_probe_service_args() {
+        let args = parse_probe_service_args(&json!(["web", "app"])).unwrap();
+        assert_eq!(args.stack_name.as_str(), "web");
+        assert_eq!(args.service_name, "app");
+        assert_eq!(args.options, ProbeOptions::default());
+
+        let args = parse_probe_service_args(&json!([
+            "web",
+            "app",
+            { "host": "nas.lan", "port": 8080, "scheme": "http", "path": "/health" }
+        ]))
+        .unwrap();
+        assert_eq!(
+            args.options,
+            ProbeOptions {
+                host: Some("nas.lan".to_string()),
+                port: Some(8080),
+                scheme: Some("http".to_string()),
+                path: Some("/health".to_string()),
+            }
+        );
+
+        assert!(parse_probe_service_args(&json!(["web", "app", { "port": 70000 }])).is_err());
+        assert!(parse_probe_service_args(&json!(["web", "app", { "scheme": "ftp" }])).is_err());
+        assert!(parse_probe_service_args(&json!(["web", "app", { "host": 1 }])).is_err());
+        assert!(parse_probe_service_args(&json!(["web"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_restart_container_args() {
+        let args = parse_restart_container_args(&json!(["web", "web-app-2"])).unwrap();
+        assert_eq!(args.stack_name.as_str(), "web");
+        assert_eq!(args.container, "web-app-2");
+
+        assert!(parse_restart_container_args(&json!(["web", ""])).is_err());
+        assert!(parse_restart_container_args(&json!(["../etc", "abc"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_service_process_list_args() {
+        let args = parse_service_process_list_args(&json!(["web", "app"])).unwrap();
+        assert_eq!(args.stack_name.as_str(), "web");
+        assert_eq!(args.service_name, "app");
+
+        assert!(parse_service_process_list_args(&json!(["web"])).is_err());
+        assert!(parse_service_process_list_args(&json!([1, "app"])).is_err());
+    }
+}
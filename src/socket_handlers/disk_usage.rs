@@ -0,0 +1,191 @@
+// Docker disk usage and daemon info
+//
+//   getDockerDiskUsage                 -> { usage }
+//   getDockerInfo                      -> { info }
+//   pruneDockerDiskUsage  [category]   -> { usage }
+//
+// getDockerInfo reports the engine version, storage driver, cgroup version and
+// container counts, like `docker info`.
+// The prune output streams to the prune-<endpoint> terminal; the ack carries
+// the usage after the prune. See disk_usage for the categories.
+
+use crate::disk_usage::{self, DiskUsage, PruneCategory};
+use crate::docker::DockerInfo;
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, callback_ok_with_fields, check_login, get_endpoint, spawn_handler,
+};
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct DiskUsageResponse {
+    usage: DiskUsage,
+}
+
+#[derive(Serialize)]
+struct DockerInfoResponse {
+    info: DockerInfo,
+}
+
+pub fn setup_disk_usage_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // getDockerDiskUsage
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getDockerDiskUsage",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getDockerDiskUsage", ack, |ack| async move {
+                match handle_get_disk_usage(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // getDockerInfo
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getDockerInfo",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getDockerInfo", ack, |ack| async move {
+                match handle_get_docker_info(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // pruneDockerDiskUsage
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "pruneDockerDiskUsage",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("pruneDockerDiskUsage", ack, |ack| async move {
+                match handle_prune(&socket, &ctx, &data).await {
+                    Ok(usage) => callback_ok_with_fields(
+                        ack.take(),
+                        "Pruned",
+                        true,
+                        DiskUsageResponse { usage },
+                    ),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Dispatch a disk usage event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_disk_usage_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    let data = Value::Array(event_args.to_vec());
+    match event_name {
+        "getDockerDiskUsage" => {
+            match handle_get_disk_usage(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getDockerInfo" => {
+            match handle_get_docker_info(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "pruneDockerDiskUsage" => {
+            match handle_prune(socket, ctx, &data).await {
+                Ok(usage) => {
+                    callback_ok_with_fields(ack.take(), "Pruned", true, DiskUsageResponse { usage })
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn parse_prune_category(data: &Value) -> Result<PruneCategory> {
+    let category = match data {
+        Value::Array(args) => args.first().unwrap_or(&Value::Null),
+        other => other,
+    };
+    serde_json::from_value(category.clone())
+        .map_err(|_| anyhow!("Expected images, containers, volumes or buildCache"))
+}
+
+async fn handle_get_disk_usage(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+    let usage = disk_usage::disk_usage(&ctx.docker).await?;
+    Ok(CustomResponse::ok_with_fields(DiskUsageResponse { usage }).into())
+}
+
+async fn handle_get_docker_info(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+    let info = crate::docker::docker_info(&ctx.docker).await?;
+    Ok(CustomResponse::ok_with_fields(DockerInfoResponse { info }).into())
+}
+
+/// Prune a category, returning the usage afterwards
+async fn handle_prune(socket: &SocketRef, ctx: &ServerContext, data: &Value) -> Result<DiskUsage> {
+    check_login(socket)?;
+    let category = parse_prune_category(data)?;
+    disk_usage::prune(
+        ctx.io.clone(),
+        Some(socket.clone()),
+        &get_endpoint(socket),
+        category,
+        ctx.config.stacks_dir.display().to_string(),
+    )
+    .await?;
+    disk_usage::disk_usage(&ctx.docker).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_prune_category() {
+        assert_eq!(
+            parse_prune_category(&json!(["images"])).unwrap(),
+            PruneCategory::Images
+        );
+        assert_eq!(
+            parse_prune_category(&json!("volumes")).unwrap(),
+            PruneCategory::Volumes
+        );
+        assert!(parse_prune_category(&json!([])).is_err());
+        assert!(parse_prune_category(&json!(["everything"])).is_err());
+    }
+}
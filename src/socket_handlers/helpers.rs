@@ -1,22 +1,54 @@
-use crate::utils::types::BaseRes;
+use crate::utils::types::{BaseRes, CustomResponse, OperationTiming};
 use anyhow::Result;
+use dockru_protocol::events::server as server_event;
 use serde::Serialize;
 use serde_json::{json, Value};
-use socketioxide::extract::SocketRef;
+use socketioxide::extract::{AckSender, SocketRef};
 use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
 use std::sync::{Arc, RwLock};
-use tracing::debug;
+use tracing::{debug, error};
 
 /// Socket state stored per connection
 #[derive(Debug, Clone, Default)]
 pub struct SocketState {
     pub user_id: Option<i64>,
+    pub username: Option<String>,
+    /// Set when the socket logged in with an agent token rather than a password
+    pub agent_token_id: Option<i64>,
     pub endpoint: String,
-    /// IP address of the socket connection.
-    /// Note: Currently always None due to socketioxide not exposing peer address.
-    /// See rust-next.md section 3.5 for implementation plan (signed nonce system).
-    #[allow(dead_code)]
-    pub ip_address: Option<String>,
+    /// User-Agent header of the handshake request
+    pub user_agent: Option<String>,
+    /// Origin header of the handshake request, absent for non-browser clients
+    pub origin: Option<String>,
+    /// Address of the peer the socket connected from (the reverse proxy, if
+    /// there is one)
+    pub ip_address: Option<IpAddr>,
+}
+
+impl SocketState {
+    /// Initial state from the headers of the socket's handshake request
+    ///
+    /// A controller connecting to this instance as an agent sends the name it
+    /// knows this instance by in an `endpoint` header.
+    pub fn from_handshake(headers: &axum::http::HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        Self {
+            endpoint: header("endpoint").unwrap_or_default(),
+            user_agent: header("user-agent"),
+            origin: header("origin"),
+            ..Default::default()
+        }
+    }
 }
 
 /// Global socket state storage
@@ -25,7 +57,7 @@ static SOCKET_STATE: once_cell::sync::Lazy<Arc<RwLock<HashMap<String, SocketStat
     once_cell::sync::Lazy::new(|| Arc::new(RwLock::new(HashMap::new())));
 
 /// Room name for all authenticated sockets
-const AUTHENTICATED_ROOM: &str = "authenticated";
+pub const AUTHENTICATED_ROOM: &str = "authenticated";
 
 /// Set socket state
 pub fn set_socket_state(socket_id: &str, state: SocketState) {
@@ -55,6 +87,22 @@ pub fn get_user_id(socket: &SocketRef) -> Option<i64> {
     get_socket_state(&socket.id.to_string()).and_then(|s| s.user_id)
 }
 
+/// Get the username of the user logged in on a socket
+pub fn get_username(socket_id: &str) -> Option<String> {
+    get_socket_state(socket_id).and_then(|s| s.username)
+}
+
+/// Get the distinct user IDs of all authenticated sockets
+pub fn get_authenticated_user_ids() -> Vec<i64> {
+    let mut ids: Vec<i64> = SOCKET_STATE
+        .read()
+        .map(|map| map.values().filter_map(|s| s.user_id).collect())
+        .unwrap_or_default();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
 /// Set user ID in socket state
 pub fn set_user_id(socket: &SocketRef, user_id: i64) {
     let socket_id = socket.id.to_string();
@@ -63,6 +111,22 @@ pub fn set_user_id(socket: &SocketRef, user_id: i64) {
     set_socket_state(&socket_id, state);
 }
 
+/// Set username in socket state
+pub fn set_username(socket: &SocketRef, username: &str) {
+    let socket_id = socket.id.to_string();
+    let mut state = get_socket_state(&socket_id).unwrap_or_default();
+    state.username = Some(username.to_string());
+    set_socket_state(&socket_id, state);
+}
+
+/// Mark the socket as logged in with an agent token
+pub fn set_agent_token_id(socket: &SocketRef, agent_token_id: i64) {
+    let socket_id = socket.id.to_string();
+    let mut state = get_socket_state(&socket_id).unwrap_or_default();
+    state.agent_token_id = Some(agent_token_id);
+    set_socket_state(&socket_id, state);
+}
+
 /// Get endpoint from socket state
 pub fn get_endpoint(socket: &SocketRef) -> String {
     get_socket_state(&socket.id.to_string())
@@ -70,25 +134,19 @@ pub fn get_endpoint(socket: &SocketRef) -> String {
         .unwrap_or_default()
 }
 
-/// Set endpoint in socket state
-pub fn set_endpoint(socket: &SocketRef, endpoint: String) {
-    let socket_id = socket.id.to_string();
-    let mut state = get_socket_state(&socket_id).unwrap_or_default();
-    state.endpoint = endpoint;
-    set_socket_state(&socket_id, state);
+/// Get the User-Agent the socket connected with
+pub fn get_user_agent(socket_id: &str) -> Option<String> {
+    get_socket_state(socket_id).and_then(|s| s.user_agent)
 }
 
-/// Get IP address from socket state
-/// Infrastructure for future use - see rust-next.md section 3.5
-#[allow(dead_code)]
-pub fn get_ip_address(socket: &SocketRef) -> Option<String> {
+/// Get the peer address from socket state
+pub fn get_ip_address(socket: &SocketRef) -> Option<IpAddr> {
     get_socket_state(&socket.id.to_string()).and_then(|s| s.ip_address)
 }
 
-/// Set IP address in socket state
-/// Infrastructure for future use - see rust-next.md section 3.5
+/// Set the peer address in socket state
 #[allow(dead_code)]
-pub fn set_ip_address(socket: &SocketRef, ip_address: Option<String>) {
+pub fn set_ip_address(socket: &SocketRef, ip_address: Option<IpAddr>) {
     let socket_id = socket.id.to_string();
     let mut state = get_socket_state(&socket_id).unwrap_or_default();
     state.ip_address = ip_address;
@@ -106,6 +164,18 @@ pub fn check_login(socket: &SocketRef) -> Result<i64> {
     get_user_id(socket).ok_or_else(|| anyhow::anyhow!("You are not logged in."))
 }
 
+/// Check if socket is authenticated with user credentials
+///
+/// Agent token sessions are rejected, for actions a paired controller
+/// shouldn't be able to take (changing passwords, managing agents and tokens).
+pub fn check_user_login(socket: &SocketRef) -> Result<i64> {
+    let user_id = check_login(socket)?;
+    if get_socket_state(&socket.id.to_string()).is_some_and(|s| s.agent_token_id.is_some()) {
+        return Err(anyhow::anyhow!("Not allowed when logged in with an agent token."));
+    }
+    Ok(user_id)
+}
+
 /// Create success response with data
 pub fn ok_response<T: Serialize>(data: T) -> BaseRes {
     BaseRes::ok_with_data(data)
@@ -138,7 +208,7 @@ pub fn emit_agent(socket: &SocketRef, event: &str, data: Value) -> Result<()> {
 
     // Wrap in "agent" event: emit("agent", eventName, data)
     socket
-        .emit("agent", &(event, &agent_data))
+        .emit(server_event::AGENT, &(event, &agent_data))
         .map_err(|e| anyhow::anyhow!("Failed to emit agent event: {}", e))?;
     debug!("Emitted agent/{} to socket {}", event, socket.id);
 
@@ -153,13 +223,76 @@ pub async fn broadcast_to_authenticated(
 ) -> Result<()> {
     // Emit to the authenticated room
     io.to(AUTHENTICATED_ROOM)
-        .emit("agent", &(event, &data))
+        .emit(server_event::AGENT, &(event, &data))
         .await
         .map_err(|e| anyhow::anyhow!("Failed to broadcast to authenticated sockets: {}", e))?;
     debug!("Broadcasted agent/{} to authenticated sockets", event);
     Ok(())
 }
 
+/// A handler's ack, shared with [`spawn_handler`] so it can still be answered
+/// if the handler panics
+#[derive(Clone)]
+pub struct AckSlot(Arc<std::sync::Mutex<Option<AckSender>>>);
+
+impl AckSlot {
+    fn new(ack: AckSender) -> Self {
+        Self(Arc::new(std::sync::Mutex::new(Some(ack))))
+    }
+
+    /// Take the ack to answer it; None once it has been taken
+    pub fn take(&self) -> Option<AckSender> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// Answer the ack with `data`
+    pub fn send<T: Serialize + ?Sized>(&self, data: &T) -> Result<()> {
+        let ack = self
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Ack was already answered"))?;
+        ack.send(data)
+            .map_err(|e| anyhow::anyhow!("Failed to send ack: {}", e))
+    }
+}
+
+/// Run a socket handler's work in its own task
+///
+/// If the work panics without having answered the ack, the client gets an
+/// error response instead of waiting forever. The panic itself goes to the log
+/// and the crash report.
+pub fn spawn_handler<F, Fut>(event: &'static str, ack: AckSender, work: F)
+where
+    F: FnOnce(AckSlot) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let slot = AckSlot::new(ack);
+
+    #[cfg(feature = "chaos")]
+    if crate::chaos::take_db_timeout(event) {
+        callback_error(slot.take(), sqlx::Error::PoolTimedOut.into());
+        return;
+    }
+
+    if let Err(e) = crate::relocation::check_event(event) {
+        callback_error(slot.take(), e);
+        return;
+    }
+
+    let task = tokio::spawn(work(slot.clone()));
+    tokio::spawn(async move {
+        if let Err(e) = task.await {
+            if e.is_panic() {
+                let message = crate::crash_report::panic_message(e.into_panic().as_ref());
+                error!("Handler for {} panicked: {}", event, message);
+                callback_error(
+                    slot.take(),
+                    anyhow::anyhow!("Internal error while handling {}", event),
+                );
+            }
+        }
+    });
+}
+
 /// Handle callback with simple ok response
 pub fn callback_ok(callback: Option<socketioxide::extract::AckSender>, msg: &str, msgi18n: bool) {
     if let Some(ack) = callback {
@@ -172,6 +305,33 @@ pub fn callback_ok(callback: Option<socketioxide::extract::AckSender>, msg: &str
     }
 }
 
+/// Handle callback with an ok response carrying the operation's timing
+pub fn callback_ok_timed(
+    callback: Option<socketioxide::extract::AckSender>,
+    msg: &str,
+    msgi18n: bool,
+    timing: OperationTiming,
+) {
+    callback_ok_with_fields(callback, msg, msgi18n, timing);
+}
+
+/// Handle callback with an ok response carrying extra fields
+pub fn callback_ok_with_fields<T: Serialize>(
+    callback: Option<socketioxide::extract::AckSender>,
+    msg: &str,
+    msgi18n: bool,
+    fields: T,
+) {
+    if let Some(ack) = callback {
+        let base = if msgi18n {
+            BaseRes::ok_with_msg_i18n(msg)
+        } else {
+            BaseRes::ok_with_msg(msg)
+        };
+        ack.send(&CustomResponse { base, fields }).ok();
+    }
+}
+
 /// Handle callback with error
 pub fn callback_error(callback: Option<socketioxide::extract::AckSender>, error: anyhow::Error) {
     if let Some(ack) = callback {
@@ -179,3 +339,25 @@ pub fn callback_error(callback: Option<socketioxide::extract::AckSender>, error:
         ack.send(&response).ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, HeaderValue};
+
+    #[test]
+    fn test_socket_state_from_handshake() {
+        let mut headers = HeaderMap::new();
+        headers.insert("endpoint", HeaderValue::from_static("nas.local:5001"));
+        headers.insert("user-agent", HeaderValue::from_static("Mozilla/5.0"));
+        headers.insert("origin", HeaderValue::from_static(""));
+
+        let state = SocketState::from_handshake(&headers);
+        assert_eq!(state.endpoint, "nas.local:5001");
+        assert_eq!(state.user_agent.as_deref(), Some("Mozilla/5.0"));
+        assert_eq!(state.origin, None);
+        assert_eq!(state.user_id, None);
+
+        assert_eq!(SocketState::from_handshake(&HeaderMap::new()).endpoint, "");
+    }
+}
@@ -0,0 +1,153 @@
+// Stack migration from Dockge or Portainer
+//
+//   migrateStacks  [{ source, dryRun, only? }]  -> { results }
+//
+// source is { type: "dockge", path } or { type: "portainer", stacks }, see
+// migration. A dry run acks with what would happen to each stack; otherwise
+// the stacks are created and the stack list is refreshed.
+
+use super::stack_management::broadcast_stack_list;
+use crate::migration::{self, MigrationResult, MigrationSource};
+use crate::server::ServerContext;
+use crate::socket_handlers::{callback_error, check_user_login, get_endpoint, spawn_handler};
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Debug, Deserialize)]
+struct MigrateStacksData {
+    source: Value,
+    #[serde(default, rename = "dryRun")]
+    dry_run: bool,
+    /// Names of the stacks to migrate, all when absent
+    #[serde(default)]
+    only: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct MigrateStacksResponse {
+    results: Vec<MigrationResult>,
+}
+
+pub fn setup_migration_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // migrateStacks
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "migrateStacks",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("migrateStacks", ack, |ack| async move {
+                match handle_migrate_stacks(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Dispatch a migration event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_migration_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    match event_name {
+        "migrateStacks" => {
+            let data = Value::Array(event_args.to_vec());
+            match handle_migrate_stacks(socket, ctx, &data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn parse_migrate_args(data: &Value) -> Result<(MigrationSource, bool, Option<Vec<String>>)> {
+    let arg = match data {
+        Value::Array(args) => args.first().cloned().unwrap_or(Value::Null),
+        other => other.clone(),
+    };
+    let data: MigrateStacksData = serde_json::from_value(arg)
+        .map_err(|e| anyhow!("Invalid migrateStacks arguments: {}", e))?;
+    Ok((
+        migration::parse_source(data.source)?,
+        data.dry_run,
+        data.only,
+    ))
+}
+
+async fn handle_migrate_stacks(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    let user_id = check_user_login(socket)?;
+    let (source, dry_run, only) = parse_migrate_args(data)?;
+
+    let results = migration::migrate(
+        Arc::new(ctx.clone()),
+        get_endpoint(socket),
+        source,
+        dry_run,
+        only,
+    )
+    .await?;
+
+    if !dry_run {
+        let created = results
+            .iter()
+            .filter(|r| r.status == migration::MigrationStatus::Created)
+            .count();
+        info!("User {} migrated {} stacks", user_id, created);
+        if created > 0 {
+            broadcast_stack_list(ctx).await;
+        }
+    }
+
+    Ok(CustomResponse::ok_with_fields(MigrateStacksResponse { results }).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_migrate_args() {
+        let (source, dry_run, only) = parse_migrate_args(&json!([{
+            "source": { "type": "dockge", "path": "/opt/stacks" },
+            "dryRun": true
+        }]))
+        .unwrap();
+        assert!(matches!(source, MigrationSource::Dockge { path } if path == "/opt/stacks"));
+        assert!(dry_run);
+        assert!(only.is_none());
+
+        let (_, dry_run, only) = parse_migrate_args(&json!([{
+            "source": { "type": "portainer", "stacks": [] },
+            "only": ["web"]
+        }]))
+        .unwrap();
+        assert!(!dry_run);
+        assert_eq!(only, Some(vec!["web".to_string()]));
+
+        assert!(parse_migrate_args(&json!([{ "source": { "type": "rancher" } }])).is_err());
+        assert!(parse_migrate_args(&json!([])).is_err());
+    }
+}
@@ -1,17 +1,44 @@
 mod helpers;
 pub use helpers::*;
 
+mod admin;
 mod agent;
 mod auth;
+mod backup;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod containers;
+mod disk_usage;
+mod migration;
+mod networks;
+mod schedule;
+mod secrets;
 mod settings;
+mod stack_clone;
 mod stack_management;
+mod stats;
 mod terminal;
+mod transfer;
 
+pub use admin::setup_admin_handlers;
 pub use agent::setup_agent_handlers;
 pub use auth::setup_auth_handlers;
+pub use backup::setup_backup_handlers;
+#[cfg(feature = "chaos")]
+pub use chaos::setup_chaos_handlers;
+pub use containers::setup_container_handlers;
+pub use disk_usage::setup_disk_usage_handlers;
+pub(crate) use auth::{login_by_trusted_header, user_from_token};
+pub use migration::setup_migration_handlers;
+pub use networks::setup_network_handlers;
+pub use schedule::setup_schedule_handlers;
+pub use secrets::setup_secret_handlers;
 pub use settings::setup_settings_handlers;
-pub use stack_management::setup_stack_handlers;
+pub use stack_clone::setup_stack_clone_handlers;
+pub use stack_management::{setup_stack_handlers, stop_stack_watch};
+pub use stats::setup_stats_handlers;
 pub use terminal::setup_terminal_handlers;
+pub use transfer::setup_transfer_handlers;
 
 use crate::server::ServerContext;
 use socketioxide::extract::SocketRef;
@@ -24,4 +51,43 @@ pub fn setup_all_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
     setup_stack_handlers(socket.clone(), ctx.clone());
     setup_terminal_handlers(socket.clone(), ctx.clone());
     setup_agent_handlers(socket.clone(), ctx.clone());
+    setup_admin_handlers(socket.clone(), ctx.clone());
+    setup_schedule_handlers(socket.clone(), ctx.clone());
+    setup_secret_handlers(socket.clone(), ctx.clone());
+    setup_transfer_handlers(socket.clone(), ctx.clone());
+    setup_stats_handlers(socket.clone(), ctx.clone());
+    setup_network_handlers(socket.clone(), ctx.clone());
+    setup_backup_handlers(socket.clone(), ctx.clone());
+    setup_disk_usage_handlers(socket.clone(), ctx.clone());
+    setup_migration_handlers(socket.clone(), ctx.clone());
+    setup_container_handlers(socket.clone(), ctx.clone());
+    setup_stack_clone_handlers(socket.clone(), ctx.clone());
+    #[cfg(feature = "chaos")]
+    setup_chaos_handlers(socket.clone(), ctx.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use dockru_protocol::events::client;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+    use std::path::Path;
+
+    /// Clients build against the protocol crate's event names, so every
+    /// registered event must be listed there and every listed event registered
+    #[test]
+    fn test_protocol_lists_registered_events() {
+        let registration = Regex::new(r#"socket\.on\(\s*"([A-Za-z]+)""#).unwrap();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/socket_handlers");
+        let mut registered = BTreeSet::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let source = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            for captures in registration.captures_iter(&source) {
+                registered.insert(captures[1].to_string());
+            }
+        }
+
+        let listed: BTreeSet<String> = client::ALL.iter().map(|e| e.to_string()).collect();
+        assert_eq!(registered, listed);
+    }
 }
@@ -0,0 +1,198 @@
+// Docker network management
+//
+//   inspectDockerNetwork [name]          -> { network }
+//   createDockerNetwork  [{ name, ... }] -> { id }
+//   deleteDockerNetwork  [name]
+//
+// getDockerNetworkList, listing network names for the compose editor, is a
+// stack event. See networks for the create options.
+
+use crate::networks::{self, NetworkDetails, NewNetwork};
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, callback_ok, callback_ok_with_fields, check_login, spawn_handler,
+};
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct InspectNetworkResponse {
+    network: NetworkDetails,
+}
+
+#[derive(Serialize)]
+struct CreateNetworkResponse {
+    id: String,
+}
+
+pub fn setup_network_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // inspectDockerNetwork
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "inspectDockerNetwork",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("inspectDockerNetwork", ack, |ack| async move {
+                match handle_inspect_network(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // createDockerNetwork
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "createDockerNetwork",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("createDockerNetwork", ack, |ack| async move {
+                match handle_create_network(&socket, &ctx, &data).await {
+                    Ok(id) => callback_ok_with_fields(
+                        ack.take(),
+                        "Created",
+                        true,
+                        CreateNetworkResponse { id },
+                    ),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // deleteDockerNetwork
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "deleteDockerNetwork",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("deleteDockerNetwork", ack, |ack| async move {
+                match handle_delete_network(&socket, &ctx, &data).await {
+                    Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Dispatch a network event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_network_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    let data = Value::Array(event_args.to_vec());
+    match event_name {
+        "inspectDockerNetwork" => {
+            match handle_inspect_network(socket, ctx, &data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "createDockerNetwork" => {
+            match handle_create_network(socket, ctx, &data).await {
+                Ok(id) => callback_ok_with_fields(
+                    ack.take(),
+                    "Created",
+                    true,
+                    CreateNetworkResponse { id },
+                ),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deleteDockerNetwork" => {
+            match handle_delete_network(socket, ctx, &data).await {
+                Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The first argument, which may also be sent bare
+fn first_arg(data: &Value) -> &Value {
+    match data {
+        Value::Array(args) => args.first().unwrap_or(&Value::Null),
+        other => other,
+    }
+}
+
+fn parse_network_name(data: &Value) -> Result<&str> {
+    first_arg(data)
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected a network name"))
+}
+
+fn parse_new_network(data: &Value) -> Result<NewNetwork> {
+    serde_json::from_value(first_arg(data).clone())
+        .map_err(|e| anyhow!("Invalid network options: {}", e))
+}
+
+async fn handle_inspect_network(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    check_login(socket)?;
+    let network = networks::inspect_network(&ctx.docker, parse_network_name(data)?).await?;
+    Ok(CustomResponse::ok_with_fields(InspectNetworkResponse { network }).into())
+}
+
+/// Create a network, returning its ID
+async fn handle_create_network(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<String> {
+    check_login(socket)?;
+    networks::create_network(&ctx.docker, parse_new_network(data)?).await
+}
+
+async fn handle_delete_network(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<()> {
+    check_login(socket)?;
+    networks::delete_network(&ctx.docker, parse_network_name(data)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_network_args() {
+        assert_eq!(parse_network_name(&json!(["proxy"])).unwrap(), "proxy");
+        assert_eq!(parse_network_name(&json!("proxy")).unwrap(), "proxy");
+        assert!(parse_network_name(&json!([])).is_err());
+        assert!(parse_network_name(&json!([1])).is_err());
+
+        let network =
+            parse_new_network(&json!([{ "name": "proxy", "subnet": "10.5.0.0/24" }])).unwrap();
+        assert_eq!(network.name, "proxy");
+        assert_eq!(network.subnet.as_deref(), Some("10.5.0.0/24"));
+        assert!(!network.internal);
+        assert!(parse_new_network(&json!([{ "name": "proxy", "internal": "yes" }])).is_err());
+    }
+}
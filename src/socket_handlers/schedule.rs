@@ -0,0 +1,314 @@
+use crate::db::models::{NewStackSchedule, StackSchedule};
+use crate::scheduler::{
+    find_conflicts, parse_cron, upcoming_runs, ScheduleAction, ScheduleConflict, ScheduledRun,
+};
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, callback_ok, check_login, get_endpoint, spawn_handler,
+};
+use crate::stack::Stack;
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde::Serialize;
+use serde_json::{json, Value};
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How far ahead previewSchedules looks
+const PREVIEW_DAYS: i64 = 31;
+
+/// Runs returned per stack by previewSchedules
+const PREVIEW_RUNS_PER_STACK: usize = 20;
+
+#[derive(Debug)]
+struct CreateScheduleData {
+    stack_name: String,
+    action: String,
+    cron: String,
+}
+
+/// Setup schedule event handlers
+pub fn setup_schedule_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // createSchedule
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "createSchedule",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("createSchedule", ack, |ack| async move {
+                match parse_create_schedule_args(&data) {
+                    Ok(parsed) => match handle_create_schedule(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // getScheduleList
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getScheduleList",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getScheduleList", ack, |ack| async move {
+                let stack_name = data.as_str().map(|s| s.to_string());
+                match handle_get_schedule_list(&socket, &ctx, stack_name.as_deref()).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // previewSchedules
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "previewSchedules",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("previewSchedules", ack, |ack| async move {
+                let stack_name = data.as_str().map(|s| s.to_string());
+                match handle_preview_schedules(&socket, &ctx, stack_name.as_deref()).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // deleteSchedule
+    let ctx_clone = ctx;
+    socket.on(
+        "deleteSchedule",
+        async move |socket: SocketRef, Data::<i64>(id), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("deleteSchedule", ack, |ack| async move {
+                match handle_delete_schedule(&socket, &ctx, id).await {
+                    Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Parse createSchedule positional args: [stackName, action, cron]
+fn parse_create_schedule_args(data: &Value) -> Result<CreateScheduleData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 3 {
+        return Err(anyhow!(
+            "createSchedule requires 3 arguments: stackName, action, cron"
+        ));
+    }
+    Ok(CreateScheduleData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        action: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("action must be a string"))?
+            .to_string(),
+        cron: args[2]
+            .as_str()
+            .ok_or_else(|| anyhow!("cron must be a string"))?
+            .to_string(),
+    })
+}
+
+/// Dispatch a schedule event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_schedule_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    match event_name {
+        "createSchedule" => {
+            let data = parse_create_schedule_args(&json!(event_args))?;
+            match handle_create_schedule(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getScheduleList" => {
+            let stack_name = event_args.first().and_then(|v| v.as_str());
+            match handle_get_schedule_list(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "previewSchedules" => {
+            let stack_name = event_args.first().and_then(|v| v.as_str());
+            match handle_preview_schedules(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deleteSchedule" => {
+            let id = event_args
+                .first()
+                .and_then(|v| v.as_i64())
+                .ok_or_else(|| anyhow!("deleteSchedule requires a schedule id"))?;
+            match handle_delete_schedule(socket, ctx, id).await {
+                Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+async fn handle_create_schedule(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: CreateScheduleData,
+) -> Result<Value> {
+    check_login(socket)?;
+
+    let action = ScheduleAction::from_str(&data.action)?;
+    parse_cron(&data.cron)?;
+
+    let endpoint = get_endpoint(socket);
+    Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+
+    let schedule = StackSchedule::create(
+        &ctx.db,
+        NewStackSchedule {
+            stack_name: data.stack_name,
+            action: action.as_str().to_string(),
+            cron: data.cron.trim().to_string(),
+        },
+    )
+    .await?;
+
+    // Reported, not enforced: the client shows them as warnings
+    let stack_schedules = StackSchedule::find_all(&ctx.db, Some(&schedule.stack_name)).await?;
+    let conflicts: Vec<ScheduleConflict> = find_conflicts(&stack_schedules, &Local::now())
+        .into_iter()
+        .filter(|c| c.involves(schedule.id))
+        .collect();
+
+    #[derive(Serialize)]
+    struct ScheduleResponse {
+        schedule: StackSchedule,
+        conflicts: Vec<ScheduleConflict>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ScheduleResponse {
+        schedule,
+        conflicts,
+    })
+    .into())
+}
+
+async fn handle_get_schedule_list(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: Option<&str>,
+) -> Result<Value> {
+    check_login(socket)?;
+
+    let schedules = StackSchedule::find_all(&ctx.db, stack_name).await?;
+
+    #[derive(Serialize)]
+    struct ScheduleListResponse {
+        #[serde(rename = "scheduleList")]
+        schedule_list: Vec<StackSchedule>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ScheduleListResponse {
+        schedule_list: schedules,
+    })
+    .into())
+}
+
+async fn handle_preview_schedules(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: Option<&str>,
+) -> Result<Value> {
+    check_login(socket)?;
+
+    let schedules = StackSchedule::find_all(&ctx.db, stack_name).await?;
+    let now = Local::now();
+
+    let mut runs = upcoming_runs(
+        &schedules,
+        &now,
+        &(now + chrono::Duration::days(PREVIEW_DAYS)),
+    );
+    for stack_runs in runs.values_mut() {
+        stack_runs.truncate(PREVIEW_RUNS_PER_STACK);
+    }
+
+    #[derive(Serialize)]
+    struct PreviewSchedulesResponse {
+        /// Stack name -> next runs of all its schedules, in order
+        runs: BTreeMap<String, Vec<ScheduledRun>>,
+        conflicts: Vec<ScheduleConflict>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(PreviewSchedulesResponse {
+        runs,
+        conflicts: find_conflicts(&schedules, &now),
+    })
+    .into())
+}
+
+async fn handle_delete_schedule(socket: &SocketRef, ctx: &ServerContext, id: i64) -> Result<()> {
+    check_login(socket)?;
+
+    if !StackSchedule::delete(&ctx.db, id).await? {
+        return Err(anyhow!("Schedule not found"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_create_schedule_args() {
+        let data = parse_create_schedule_args(&json!(["web", "restart", "0 4 * * *"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.action, "restart");
+        assert_eq!(data.cron, "0 4 * * *");
+
+        assert!(parse_create_schedule_args(&json!(["web", "restart"])).is_err());
+    }
+}
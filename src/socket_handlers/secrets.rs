@@ -0,0 +1,178 @@
+use crate::db::models::{SecretInfo, StoredSecret};
+use crate::server::ServerContext;
+use crate::socket_handlers::{callback_error, callback_ok, check_login, spawn_handler};
+use crate::utils::crypto::encrypt_password;
+use crate::utils::secrets::validate_name;
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use redact::Secret;
+use serde::Serialize;
+use serde_json::{json, Value};
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+use tracing::info;
+
+#[derive(Debug)]
+struct SetSecretData {
+    name: String,
+    /// None deletes the secret
+    value: Option<String>,
+}
+
+/// Setup secret event handlers
+pub fn setup_secret_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // setSecret
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setSecret",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setSecret", ack, |ack| async move {
+                match parse_set_secret_args(&data) {
+                    Ok(parsed) => {
+                        let deleting = parsed.value.is_none();
+                        match handle_set_secret(&socket, &ctx, parsed).await {
+                            Ok(_) if deleting => callback_ok(ack.take(), "Deleted", true),
+                            Ok(_) => callback_ok(ack.take(), "Saved", true),
+                            Err(e) => callback_error(ack.take(), e),
+                        }
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // listSecrets
+    let ctx_clone = ctx;
+    socket.on(
+        "listSecrets",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("listSecrets", ack, |ack| async move {
+                match handle_list_secrets(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Parse setSecret positional args: [name, value]; a null value deletes the secret
+fn parse_set_secret_args(data: &Value) -> Result<SetSecretData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!("setSecret requires 2 arguments: name, value"));
+    }
+    let name = args[0]
+        .as_str()
+        .ok_or_else(|| anyhow!("name must be a string"))?
+        .to_string();
+    validate_name(&name)?;
+    let value = match &args[1] {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        _ => return Err(anyhow!("value must be a string or null")),
+    };
+    Ok(SetSecretData { name, value })
+}
+
+/// Dispatch a secret event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_secret_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    match event_name {
+        "setSecret" => {
+            let data = parse_set_secret_args(&json!(event_args))?;
+            let msg = if data.value.is_none() {
+                "Deleted"
+            } else {
+                "Saved"
+            };
+            match handle_set_secret(socket, ctx, data).await {
+                Ok(_) => callback_ok(ack.take(), msg, true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "listSecrets" => {
+            match handle_list_secrets(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+async fn handle_set_secret(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetSecretData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    match data.value {
+        Some(value) => {
+            let key = ctx.require_encryption_secret()?;
+            let encrypted = encrypt_password(&Secret::new(value), &key)?;
+            StoredSecret::set(&ctx.db, &data.name, &encrypted).await?;
+            info!("Saved secret {}", data.name);
+        }
+        None => {
+            if !StoredSecret::delete(&ctx.db, &data.name).await? {
+                return Err(anyhow!("Secret not found"));
+            }
+            info!("Deleted secret {}", data.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Names of the secrets; values never leave the server
+async fn handle_list_secrets(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+
+    #[derive(Serialize)]
+    struct SecretListResponse {
+        secrets: Vec<SecretInfo>,
+    }
+
+    let secrets = StoredSecret::list(&ctx.db).await?;
+    Ok(CustomResponse::ok_with_fields(SecretListResponse { secrets }).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_set_secret_args() {
+        let data = parse_set_secret_args(&json!(["DB_PASSWORD", "hunter2"])).unwrap();
+        assert_eq!(data.name, "DB_PASSWORD");
+        assert_eq!(data.value.as_deref(), Some("hunter2"));
+
+        let data = parse_set_secret_args(&json!(["DB_PASSWORD", null])).unwrap();
+        assert_eq!(data.value, None);
+
+        assert!(parse_set_secret_args(&json!(["DB_PASSWORD"])).is_err());
+        assert!(parse_set_secret_args(&json!(["db-password", "x"])).is_err());
+        assert!(parse_set_secret_args(&json!(["DB_PASSWORD", 1])).is_err());
+    }
+}
@@ -1,14 +1,26 @@
+use crate::cluster::ClusterEvent;
 use crate::db::models::{Setting, SettingsCache, User};
 use crate::server::ServerContext;
-use crate::socket_handlers::{callback_error, callback_ok, check_login, emit_agent};
+use crate::socket_handlers::{
+    callback_error, callback_ok, check_login, check_user_login, emit_agent, spawn_handler,
+};
+use crate::utils::compose_env::{
+    ComposeEnvPolicy, COMPOSE_ENV_POLICY_SETTING, COMPOSE_ENV_POLICY_SETTING_TYPE,
+};
+use crate::utils::compose_policy::{
+    ComposePolicy, COMPOSE_POLICY_SETTING, COMPOSE_POLICY_SETTING_TYPE,
+};
+use crate::utils::constants::{ACCEPTED_COMPOSE_FILE_NAMES, MIN_STACK_REFRESH_SECS};
+use crate::utils::docker_run::convert_docker_run;
 use crate::utils::types::{BaseRes, CustomResponse};
 use anyhow::{anyhow, Result};
+use dockru_protocol::events::server as server_event;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use socketioxide::extract::{AckSender, Data, SocketRef, TryData};
 use std::sync::Arc;
 use tokio::fs;
-use tracing::debug;
+use tracing::{debug, info};
 
 #[derive(Debug, Deserialize)]
 struct SetSettingsData {
@@ -25,12 +37,12 @@ pub fn setup_settings_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "getSettings",
         async move |socket: SocketRef, ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("getSettings", ack, |ack| async move {
                 match handle_get_settings(&socket, &ctx).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
@@ -44,14 +56,14 @@ pub fn setup_settings_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
                     TryData::<String>(password_result),
                     ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("setSettings", ack, |ack| async move {
                 let current_password = password_result.ok().filter(|s| !s.is_empty());
                 if let Err(e) =
                     handle_set_settings(&socket, &ctx, settings_data, current_password).await
                 {
-                    callback_error(Some(ack), e);
+                    callback_error(ack.take(), e);
                 } else {
-                    callback_ok(Some(ack), "Saved", false);
+                    callback_ok(ack.take(), "Saved", false);
 
                     // Re-send info after settings change
                     if let Err(e) = send_info_after_settings(&socket, &ctx).await {
@@ -67,12 +79,86 @@ pub fn setup_settings_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "composerize",
         async move |socket: SocketRef, Data::<String>(docker_run_command), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("composerize", ack, |ack| async move {
                 match handle_composerize(&socket, &ctx, docker_run_command).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getComposePolicy",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getComposePolicy", ack, |ack| async move {
+                match handle_get_compose_policy(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setComposePolicy",
+        async move |socket: SocketRef, Data::<ComposePolicy>(policy), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setComposePolicy", ack, |ack| async move {
+                match handle_set_compose_policy(&socket, &ctx, policy).await {
+                    Ok(_) => callback_ok(ack.take(), "Saved", true),
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getComposeEnvPolicy",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getComposeEnvPolicy", ack, |ack| async move {
+                match handle_get_compose_env_policy(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setComposeEnvPolicy",
+        async move |socket: SocketRef, Data::<ComposeEnvPolicy>(policy), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setComposeEnvPolicy", ack, |ack| async move {
+                match handle_set_compose_env_policy(&socket, &ctx, policy).await {
+                    Ok(_) => callback_ok(ack.take(), "Saved", true),
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    socket.on(
+        "convertDockerRun",
+        async move |socket: SocketRef, Data::<String>(docker_run_command), ack: AckSender| {
+            spawn_handler("convertDockerRun", ack, |ack| async move {
+                match handle_convert_docker_run(&socket, &docker_run_command) {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
@@ -178,10 +264,118 @@ async fn handle_set_settings(
         }
     }
 
+    if let Some(value) = settings_to_save.get("stackRefreshInterval") {
+        let secs = value
+            .as_u64()
+            .or_else(|| value.as_str().and_then(|s| s.trim().parse().ok()))
+            .ok_or_else(|| anyhow!("Stack refresh interval must be a number of seconds"))?;
+        if secs < MIN_STACK_REFRESH_SECS {
+            return Err(anyhow!(
+                "Stack refresh interval must be at least {} seconds",
+                MIN_STACK_REFRESH_SECS
+            ));
+        }
+    }
+
+    if let Some(value) = settings_to_save.get("defaultComposeFileName") {
+        let name = value.as_str().unwrap_or_default();
+        if !ACCEPTED_COMPOSE_FILE_NAMES.contains(&name) {
+            return Err(anyhow!(
+                "Default compose file name must be one of {}",
+                ACCEPTED_COMPOSE_FILE_NAMES.join(", ")
+            ));
+        }
+    }
+
     for (key, value) in settings_to_save {
         Setting::set(&ctx.db, &cache, &key, &value, Some("general")).await?;
     }
 
+    crate::cluster::publish(ctx, ClusterEvent::Settings).await;
+
+    Ok(())
+}
+
+async fn handle_get_compose_policy(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+
+    let policy = match Setting::get(&ctx.db, &ctx.cache, COMPOSE_POLICY_SETTING).await? {
+        Some(value) => serde_json::from_value(value)?,
+        None => ComposePolicy::default(),
+    };
+
+    #[derive(Serialize)]
+    struct ComposePolicyResponse {
+        policy: ComposePolicy,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ComposePolicyResponse { policy }).into())
+}
+
+/// Replace the policy stacks are checked against on save and deploy
+async fn handle_set_compose_policy(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    policy: ComposePolicy,
+) -> Result<()> {
+    let user_id = check_user_login(socket)?;
+    policy.validate()?;
+
+    Setting::set(
+        &ctx.db,
+        &ctx.cache,
+        COMPOSE_POLICY_SETTING,
+        &serde_json::to_value(&policy)?,
+        Some(COMPOSE_POLICY_SETTING_TYPE),
+    )
+    .await?;
+    info!("User {} updated the compose policy: {:?}", user_id, policy);
+
+    crate::cluster::publish(ctx, ClusterEvent::Settings).await;
+
+    Ok(())
+}
+
+async fn handle_get_compose_env_policy(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+
+    let policy = match Setting::get(&ctx.db, &ctx.cache, COMPOSE_ENV_POLICY_SETTING).await? {
+        Some(value) => serde_json::from_value(value)?,
+        None => ComposeEnvPolicy::default(),
+    };
+
+    #[derive(Serialize)]
+    struct ComposeEnvPolicyResponse {
+        policy: ComposeEnvPolicy,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ComposeEnvPolicyResponse { policy }).into())
+}
+
+/// Replace the policy on which dockru variables compose commands inherit
+async fn handle_set_compose_env_policy(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    policy: ComposeEnvPolicy,
+) -> Result<()> {
+    let user_id = check_user_login(socket)?;
+    policy.validate()?;
+
+    Setting::set(
+        &ctx.db,
+        &ctx.cache,
+        COMPOSE_ENV_POLICY_SETTING,
+        &serde_json::to_value(&policy)?,
+        Some(COMPOSE_ENV_POLICY_SETTING_TYPE),
+    )
+    .await?;
+    info!(
+        "User {} updated the compose env policy: {:?}",
+        user_id, policy
+    );
+
+    crate::cluster::publish(ctx, ClusterEvent::Settings).await;
+
     Ok(())
 }
 
@@ -211,6 +405,14 @@ async fn handle_composerize(
     Ok(CustomResponse::ok_with_fields(ComposerizeResponse { compose_template }).into())
 }
 
+/// Convert a `docker run` command into a service snippet for an existing stack
+fn handle_convert_docker_run(socket: &SocketRef, docker_run_command: &str) -> Result<Value> {
+    check_login(socket)?;
+
+    let converted = convert_docker_run(docker_run_command)?;
+    Ok(CustomResponse::ok_with_fields(converted).into())
+}
+
 /// Send updated info after settings change
 async fn send_info_after_settings(socket: &SocketRef, ctx: &ServerContext) -> Result<()> {
     let cache = SettingsCache::default();
@@ -219,7 +421,7 @@ async fn send_info_after_settings(socket: &SocketRef, ctx: &ServerContext) -> Re
 
     emit_agent(
         socket,
-        "info",
+        server_event::INFO,
         json!({
             "version": env!("CARGO_PKG_VERSION"),
             "latestVersion": null,
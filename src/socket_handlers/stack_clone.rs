@@ -0,0 +1,319 @@
+// Cloning stacks to other endpoints
+//
+//   getRewriteRules                      -> { rules }  (by endpoint)
+//   setRewriteRules  [endpoint, rules?]  (null removes the endpoint's rules)
+//   cloneStack       [stackName, endpoint, { deploy, dryRun }?]
+//                                        -> { composeYAML, composeENV, changes, created }
+//
+// cloneStack copies a stack of this instance to an agent endpoint, applying
+// the endpoint's rewrite rules (see rewrite_rules) to the compose file and
+// .env. A stack of the same name there is updated, so cloning again syncs it.
+// A dry run only returns the rewritten files. The clone goes through this
+// socket's agent connections, so it is not available through the agent proxy.
+
+use crate::agent_manager;
+use crate::cluster::ClusterEvent;
+use crate::db::models::Setting;
+use crate::server::ServerContext;
+use crate::socket_handlers::{
+    callback_error, check_login, check_user_login, get_endpoint, spawn_handler,
+};
+use crate::stack::Stack;
+use crate::utils::rewrite_rules::{
+    RewriteRules, Rewritten, REWRITE_RULES_SETTING, REWRITE_RULES_SETTING_TYPE,
+};
+use crate::utils::stack_name::StackName;
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::info;
+
+type RulesByEndpoint = BTreeMap<String, RewriteRules>;
+
+#[derive(Debug, Default, Deserialize)]
+struct CloneOptions {
+    /// Deploy the stack on the endpoint instead of only saving it
+    #[serde(default)]
+    deploy: bool,
+    #[serde(default, rename = "dryRun")]
+    dry_run: bool,
+}
+
+#[derive(Debug)]
+struct CloneStackArgs {
+    stack_name: StackName,
+    endpoint: String,
+    options: CloneOptions,
+}
+
+#[derive(Serialize)]
+struct RewriteRulesResponse {
+    rules: RulesByEndpoint,
+}
+
+#[derive(Serialize)]
+struct CloneStackResponse {
+    #[serde(flatten)]
+    rewritten: Rewritten,
+    /// The stack did not exist on the endpoint before
+    created: bool,
+}
+
+pub fn setup_stack_clone_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // getRewriteRules
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getRewriteRules",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getRewriteRules", ack, |ack| async move {
+                match handle_get_rewrite_rules(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // setRewriteRules
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setRewriteRules",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setRewriteRules", ack, |ack| async move {
+                match handle_set_rewrite_rules(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // cloneStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "cloneStack",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("cloneStack", ack, |ack| async move {
+                match handle_clone_stack(&socket, &ctx, &data).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+async fn load_rules(ctx: &ServerContext) -> Result<RulesByEndpoint> {
+    match Setting::get(&ctx.db, &ctx.cache, REWRITE_RULES_SETTING).await? {
+        Some(value) => serde_json::from_value(value).context("Stored rewrite rules are invalid"),
+        None => Ok(RulesByEndpoint::new()),
+    }
+}
+
+fn parse_set_rewrite_rules_args(data: &Value) -> Result<(String, Option<RewriteRules>)> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let endpoint = args
+        .first()
+        .and_then(Value::as_str)
+        .filter(|e| !e.is_empty())
+        .ok_or_else(|| anyhow!("endpoint must be a non-empty string"))?;
+    let rules = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(rules) => {
+            let rules: RewriteRules = serde_json::from_value(rules.clone())
+                .map_err(|e| anyhow!("Invalid rewrite rules: {}", e))?;
+            rules.validate()?;
+            Some(rules)
+        }
+    };
+    Ok((endpoint.to_string(), rules))
+}
+
+fn parse_clone_stack_args(data: &Value) -> Result<CloneStackArgs> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let endpoint = args
+        .get(1)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("endpoint must be a string"))?;
+    let options = match args.get(2) {
+        None | Some(Value::Null) => CloneOptions::default(),
+        Some(options) => serde_json::from_value(options.clone())
+            .map_err(|e| anyhow!("Invalid clone options: {}", e))?,
+    };
+    Ok(CloneStackArgs {
+        stack_name: StackName::parse(stack_name)?,
+        endpoint: endpoint.to_string(),
+        options,
+    })
+}
+
+async fn handle_get_rewrite_rules(socket: &SocketRef, ctx: &ServerContext) -> Result<Value> {
+    check_login(socket)?;
+    let rules = load_rules(ctx).await?;
+    Ok(CustomResponse::ok_with_fields(RewriteRulesResponse { rules }).into())
+}
+
+async fn handle_set_rewrite_rules(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    let user_id = check_user_login(socket)?;
+    let (endpoint, rules) = parse_set_rewrite_rules_args(data)?;
+
+    let mut all = load_rules(ctx).await?;
+    match rules {
+        Some(rules) if !rules.is_empty() => {
+            all.insert(endpoint.clone(), rules);
+        }
+        _ => {
+            all.remove(&endpoint);
+        }
+    }
+    Setting::set(
+        &ctx.db,
+        &ctx.cache,
+        REWRITE_RULES_SETTING,
+        &serde_json::to_value(&all)?,
+        Some(REWRITE_RULES_SETTING_TYPE),
+    )
+    .await?;
+    info!("User {} updated the rewrite rules of {}", user_id, endpoint);
+
+    crate::cluster::publish(ctx, ClusterEvent::Settings).await;
+
+    Ok(CustomResponse::ok_with_fields(RewriteRulesResponse { rules: all }).into())
+}
+
+async fn handle_clone_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<Value> {
+    let user_id = check_user_login(socket)?;
+    let args = parse_clone_stack_args(data)?;
+    if args.endpoint.is_empty() || args.endpoint == get_endpoint(socket) {
+        bail!("Choose another endpoint to clone the stack to");
+    }
+
+    let mut stack = Stack::get_stack(
+        Arc::new(ctx.clone()),
+        args.stack_name.as_str(),
+        get_endpoint(socket),
+    )
+    .await?;
+    let compose_yaml = stack.compose_yaml().await?;
+    let compose_env = stack.compose_env().await?;
+
+    let rules = load_rules(ctx)
+        .await?
+        .remove(&args.endpoint)
+        .unwrap_or_default();
+    let rewritten = rules.apply(&compose_yaml, &compose_env)?;
+    if args.options.dry_run {
+        return Ok(CustomResponse::ok_with_fields(CloneStackResponse {
+            rewritten,
+            created: false,
+        })
+        .into());
+    }
+
+    let manager = agent_manager::get_agent_manager(&socket.id.to_string())
+        .await
+        .ok_or_else(|| anyhow!("Agent manager not found"))?;
+    let event = if args.options.deploy {
+        "deployStack"
+    } else {
+        "saveStack"
+    };
+    let send = |is_add: bool| {
+        manager.emit_to_endpoint_with_ack(
+            &args.endpoint,
+            event,
+            json!([
+                args.stack_name.as_str(),
+                rewritten.compose_yaml,
+                rewritten.compose_env,
+                is_add
+            ]),
+        )
+    };
+
+    // Update the stack if the endpoint has it, create it otherwise
+    let mut created = false;
+    let mut response = send(false).await?;
+    if response["msg"].as_str() == Some("Stack not found") {
+        created = true;
+        response = send(true).await?;
+    }
+    if response["ok"].as_bool() != Some(true) {
+        bail!(
+            "{}: {}",
+            args.endpoint,
+            response["msg"].as_str().unwrap_or("Cloning failed")
+        );
+    }
+
+    info!(
+        "User {} cloned stack {} to {}",
+        user_id, args.stack_name, args.endpoint
+    );
+    Ok(CustomResponse::ok_with_fields(CloneStackResponse { rewritten, created }).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_clone_stack_args() {
+        let args = parse_clone_stack_args(&json!(["web", "nas2:5001"])).unwrap();
+        assert_eq!(args.stack_name.as_str(), "web");
+        assert_eq!(args.endpoint, "nas2:5001");
+        assert!(!args.options.deploy && !args.options.dry_run);
+
+        let args =
+            parse_clone_stack_args(&json!(["web", "nas2:5001", { "deploy": true }])).unwrap();
+        assert!(args.options.deploy);
+
+        assert!(parse_clone_stack_args(&json!(["web"])).is_err());
+        assert!(parse_clone_stack_args(&json!(["../web", "nas2:5001"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_rewrite_rules_args() {
+        let (endpoint, rules) =
+            parse_set_rewrite_rules_args(&json!(["nas2:5001", { "portOffset": 100 }])).unwrap();
+        assert_eq!(endpoint, "nas2:5001");
+        assert_eq!(rules.unwrap().port_offset, 100);
+
+        let (_, rules) = parse_set_rewrite_rules_args(&json!(["nas2:5001", null])).unwrap();
+        assert!(rules.is_none());
+
+        assert!(parse_set_rewrite_rules_args(&json!(["", {}])).is_err());
+        assert!(
+            parse_set_rewrite_rules_args(&json!(["nas2:5001", { "portOffset": "x" }])).is_err()
+        );
+    }
+}
@@ -1,37 +1,304 @@
+use crate::cluster::ClusterEvent;
+use crate::db::models::stack_order::sort_by_hints;
+use crate::db::models::{
+    GroupSummary, ShareLink, StackAutostart, StackDependency, StackEvent, StackGroup, StackHistory,
+    StackOrder, StackSchedule, StackUpdateWindow, StackWebhook, StatusPeriod,
+};
+use crate::docker::{DeployOptions, DockerContext};
+use crate::exposure::ExposedService;
 use crate::server::ServerContext;
 use crate::socket_handlers::{
-    broadcast_to_authenticated, callback_error, callback_ok, check_login, get_endpoint,
+    broadcast_to_authenticated, callback_error, callback_ok, callback_ok_timed,
+    callback_ok_with_fields, check_login, emit_agent, get_endpoint, get_username, spawn_handler,
+};
+use crate::utils::constants::{
+    DEFAULT_SHARE_LINK_MINUTES, DEFAULT_STACK_EVENT_PAGE_SIZE, DEFAULT_STACK_LIST_PAGE_SIZE,
+    MAX_BATCH_STACKS, MAX_GROUP_NAME_LENGTH, MAX_SHARE_LINK_MINUTES, MAX_STACK_EVENT_PAGE_SIZE,
+    MAX_STACK_LIST_PAGE_SIZE, MAX_STACK_ORDER_LENGTH, MAX_STACK_REFRESH_SECS,
+    MIN_WATCH_STACK_REFRESH_SECS, REQUEST_STACK_LIST_DEBOUNCE_MS,
 };
-use crate::stack::{ServiceStatus, Stack, StackJson};
-use crate::utils::types::CustomResponse;
+use crate::stack::{
+    BatchAction, BatchStackResult, ServiceStatus, Stack, StackAction, StackJson, StackSimpleJson,
+};
+use crate::uptime::UptimeSummary;
+use crate::utils::restart_policy::RestartPolicy;
+use crate::utils::stack_name::StackName;
+use crate::utils::types::{CustomResponse, OperationTiming};
+use crate::utils::update_window::UpdateWindow;
 use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use dockru_protocol::events::server as server_event;
+use once_cell::sync::Lazy;
+use redact::Secret;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use socketioxide::extract::{AckSender, Data, SocketRef};
 use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{debug, warn};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Per-socket task refreshing the service status of the stack being viewed
+static STACK_WATCHERS: Lazy<Mutex<HashMap<String, JoinHandle<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// When requestStackList last broadcast, held while broadcasting
+static LAST_REQUESTED_STACK_LIST: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Debug)]
+struct WatchStackData {
+    stack_name: String,
+    interval_secs: u64,
+}
+
+/// Ack fields of saveStack and deployStack
+#[derive(Debug, Serialize)]
+struct SavedStack {
+    #[serde(flatten)]
+    timing: Option<OperationTiming>,
+    /// What the compose policy warns about in the saved stack
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+struct RollbackStackData {
+    stack_name: String,
+    history_id: i64,
+}
+
+/// A page of a stack's event timeline
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StackEventsQuery {
+    /// Zero-based page number
+    page: i64,
+    #[serde(rename = "pageSize")]
+    page_size: Option<i64>,
+}
+
+impl StackEventsQuery {
+    fn page_size(&self) -> i64 {
+        self.page_size
+            .unwrap_or(DEFAULT_STACK_EVENT_PAGE_SIZE)
+            .clamp(1, MAX_STACK_EVENT_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug)]
+struct SetStackComposeFilesData {
+    stack_name: String,
+    files: Vec<String>,
+}
+
+#[derive(Debug)]
+struct SetStackDockerContextData {
+    stack_name: String,
+    /// None deploys the stack to the instance's daemon again
+    context: Option<String>,
+}
+
+#[derive(Debug)]
+struct SetStackRestartPolicyData {
+    stack_name: String,
+    policy: RestartPolicy,
+    /// Also update the running containers with `docker update`
+    apply: bool,
+}
+
+/// Ack fields of setStackRestartPolicy
+#[derive(Debug, Serialize)]
+struct RestartPolicySet {
+    /// Containers updated with `docker update`
+    updated: usize,
+}
+
+#[derive(Debug)]
+struct SetStackGroupData {
+    stack_name: String,
+    /// None takes the stack out of its group
+    group: Option<String>,
+}
+
+#[derive(Debug)]
+struct CreateShareLinkData {
+    stack_name: String,
+    minutes: i64,
+}
+
+#[derive(Debug)]
+struct SetStackPinnedData {
+    stack_name: String,
+    pinned: bool,
+}
+
+#[derive(Debug)]
+struct DiffStackData {
+    stack_name: StackName,
+    compose_yaml: String,
+    compose_env: String,
+    /// Also diff the rendered `docker compose config`
+    include_config: bool,
+}
+
+#[derive(Debug)]
+struct SetStackAutostartData {
+    stack_name: String,
+    enabled: bool,
+}
+
+#[derive(Debug)]
+struct SetStackUpdateWindowData {
+    stack_name: String,
+    /// None stops applying updates automatically
+    window: Option<UpdateWindow>,
+}
+
+#[derive(Debug)]
+struct SetInjectedEnvData {
+    stack_name: String,
+    /// Variable name -> value, or null to remove
+    changes: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug)]
+struct SetStackDependenciesData {
+    stack_name: String,
+    depends_on: Vec<String>,
+}
+
+#[derive(Debug)]
+struct PreviewStackConfigData {
+    stack_name: StackName,
+    /// Unsaved compose YAML and .env; the saved files are used when absent
+    content: Option<(String, String)>,
+}
+
+#[derive(Debug)]
+struct ImportStackData {
+    upload_id: String,
+    name: String,
+    deploy: bool,
+    /// Needed for encrypted archives
+    passphrase: Option<Secret<String>>,
+}
 
 #[derive(Debug, Deserialize)]
 struct DeployStackData {
-    name: String,
+    name: StackName,
     #[serde(rename = "composeYAML")]
     compose_yaml: String,
     #[serde(rename = "composeENV")]
     compose_env: String,
     #[serde(rename = "isAdd")]
     is_add: bool,
+    #[serde(default)]
+    options: DeployOptions,
+    /// Compose file name for a new stack, instead of the default
+    #[serde(rename = "composeFileName", default)]
+    compose_file_name: Option<String>,
+}
+
+#[derive(Debug)]
+struct BatchStackActionData {
+    stack_names: Vec<String>,
+    action: StackAction,
+}
+
+#[derive(Debug)]
+struct StartStackData {
+    stack_name: String,
+    options: DeployOptions,
+}
+
+/// Filters and page for queryStackList; all filters are optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct StackListQuery {
+    /// Case-insensitive substring of the stack name
+    search: Option<String>,
+    tag: Option<String>,
+    group: Option<String>,
+    status: Option<i32>,
+    endpoint: Option<String>,
+    /// Zero-based page number
+    page: usize,
+    #[serde(rename = "pageSize")]
+    page_size: Option<usize>,
+}
+
+impl StackListQuery {
+    fn matches(&self, stack: &StackSimpleJson) -> bool {
+        if let Some(search) = self.search.as_deref().filter(|s| !s.is_empty()) {
+            if !stack.name.contains(&search.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !stack.tags.contains(tag) {
+                return false;
+            }
+        }
+        if self.group.is_some() && self.group != stack.group {
+            return false;
+        }
+        if self.status.is_some_and(|status| status != stack.status) {
+            return false;
+        }
+        if self.endpoint.as_ref().is_some_and(|e| *e != stack.endpoint) {
+            return false;
+        }
+        true
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+            .unwrap_or(DEFAULT_STACK_LIST_PAGE_SIZE)
+            .clamp(1, MAX_STACK_LIST_PAGE_SIZE)
+    }
+
+    /// The page of matching stacks, in the user's order, and how many matched
+    fn apply(
+        &self,
+        stacks: Vec<StackSimpleJson>,
+        order: &[StackOrder],
+    ) -> (Vec<StackSimpleJson>, usize) {
+        let mut matching: Vec<StackSimpleJson> =
+            stacks.into_iter().filter(|s| self.matches(s)).collect();
+        sort_by_hints(&mut matching, order, |s| &s.name);
+
+        let total = matching.len();
+        let page_size = self.page_size();
+        let page = matching
+            .into_iter()
+            .skip(self.page.saturating_mul(page_size))
+            .take(page_size)
+            .collect();
+        (page, total)
+    }
+}
+
+/// A stack name with an optional boolean flag (noCache, rebuild)
+#[derive(Debug)]
+struct StackFlagData {
+    stack_name: String,
+    flag: bool,
 }
 
 #[derive(Debug, Deserialize)]
 struct SaveStackData {
-    name: String,
+    name: StackName,
     #[serde(rename = "composeYAML")]
     compose_yaml: String,
     #[serde(rename = "composeENV")]
     compose_env: String,
     #[serde(rename = "isAdd")]
     is_add: bool,
+    /// Compose file name for a new stack, instead of the default
+    #[serde(rename = "composeFileName", default)]
+    compose_file_name: Option<String>,
 }
 
 /// Setup stack management event handlers
@@ -42,16 +309,16 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "deployStack",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("deployStack", ack, |ack| async move {
                 match parse_deploy_stack_args(&data) {
                     Ok(parsed) => match handle_deploy_stack(&socket, &ctx, parsed).await {
-                        Ok(_) => {
-                            callback_ok(Some(ack), "Deployed", true);
+                        Ok(deployed) => {
+                            callback_ok_with_fields(ack.take(), "Deployed", true, deployed);
                             broadcast_stack_list(&ctx).await;
                         }
-                        Err(e) => callback_error(Some(ack), e),
+                        Err(e) => callback_error(ack.take(), e),
                     },
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -63,16 +330,16 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "saveStack",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("saveStack", ack, |ack| async move {
                 match parse_save_stack_args(&data) {
                     Ok(parsed) => match handle_save_stack(&socket, &ctx, parsed).await {
-                        Ok(_) => {
-                            callback_ok(Some(ack), "Saved", true);
+                        Ok(saved) => {
+                            callback_ok_with_fields(ack.take(), "Saved", true, saved);
                             broadcast_stack_list(&ctx).await;
                         }
-                        Err(e) => callback_error(Some(ack), e),
+                        Err(e) => callback_error(ack.take(), e),
                     },
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -84,13 +351,13 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "deleteStack",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("deleteStack", ack, |ack| async move {
                 match handle_delete_stack(&socket, &ctx, &stack_name).await {
                     Ok(_) => {
-                        callback_ok(Some(ack), "Deleted", true);
+                        callback_ok(ack.take(), "Deleted", true);
                         broadcast_stack_list(&ctx).await;
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -102,12 +369,12 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "getStack",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("getStack", ack, |ack| async move {
                 match handle_get_stack(&socket, &ctx, &stack_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
@@ -119,28 +386,80 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "requestStackList",
         async move |socket: SocketRef, ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("requestStackList", ack, |ack| async move {
                 if check_login(&socket).is_ok() {
-                    broadcast_stack_list(&ctx).await;
-                    callback_ok(Some(ack), "Updated", true);
+                    request_stack_list(&ctx).await;
+                    callback_ok(ack.take(), "Updated", true);
+                }
+            });
+        },
+    );
+
+    // queryStackList
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "queryStackList",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("queryStackList", ack, |ack| async move {
+                match parse_stack_list_query(&data) {
+                    Ok(query) => match handle_query_stack_list(&socket, &ctx, query).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // watchStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "watchStack",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("watchStack", ack, |ack| async move {
+                match parse_watch_stack_args(&data) {
+                    Ok(parsed) => match handle_watch_stack(&socket, &ctx, parsed).await {
+                        Ok(_) => callback_ok(ack.take(), "Watching", false),
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
     );
 
+    // unwatchStack
+    socket.on(
+        "unwatchStack",
+        async move |socket: SocketRef, ack: AckSender| {
+            spawn_handler("unwatchStack", ack, |ack| async move {
+                stop_stack_watch(&socket.id.to_string()).await;
+                callback_ok(ack.take(), "Stopped", false);
+            });
+        },
+    );
+
     // startStack
     let ctx_clone = ctx.clone();
     socket.on(
         "startStack",
-        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
-                match handle_start_stack(&socket, &ctx, &stack_name).await {
-                    Ok(_) => {
-                        callback_ok(Some(ack), "Started", true);
-                        broadcast_stack_list(&ctx).await;
-                    }
-                    Err(e) => callback_error(Some(ack), e),
+            spawn_handler("startStack", ack, |ack| async move {
+                match parse_start_stack_args(&data) {
+                    Ok(parsed) => match handle_start_stack(&socket, &ctx, parsed).await {
+                        Ok(timing) => {
+                            callback_ok_timed(ack.take(), "Started", true, timing);
+                            broadcast_stack_list(&ctx).await;
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -152,13 +471,13 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "stopStack",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("stopStack", ack, |ack| async move {
                 match handle_stop_stack(&socket, &ctx, &stack_name).await {
-                    Ok(_) => {
-                        callback_ok(Some(ack), "Stopped", true);
+                    Ok(timing) => {
+                        callback_ok_timed(ack.take(), "Stopped", true, timing);
                         broadcast_stack_list(&ctx).await;
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -170,13 +489,13 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "restartStack",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("restartStack", ack, |ack| async move {
                 match handle_restart_stack(&socket, &ctx, &stack_name).await {
-                    Ok(_) => {
-                        callback_ok(Some(ack), "Restarted", true);
+                    Ok(timing) => {
+                        callback_ok_timed(ack.take(), "Restarted", true, timing);
                         broadcast_stack_list(&ctx).await;
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -186,15 +505,36 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
     let ctx_clone = ctx.clone();
     socket.on(
         "updateStack",
-        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
-                match handle_update_stack(&socket, &ctx, &stack_name).await {
-                    Ok(_) => {
-                        callback_ok(Some(ack), "Updated", true);
-                        broadcast_stack_list(&ctx).await;
-                    }
-                    Err(e) => callback_error(Some(ack), e),
+            spawn_handler("updateStack", ack, |ack| async move {
+                match parse_stack_flag_args(&data, "updateStack") {
+                    Ok(parsed) => match handle_update_stack(&socket, &ctx, parsed).await {
+                        Ok(timing) => {
+                            callback_ok_timed(ack.take(), "Updated", true, timing);
+                            broadcast_stack_list(&ctx).await;
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // buildStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "buildStack",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("buildStack", ack, |ack| async move {
+                match parse_stack_flag_args(&data, "buildStack") {
+                    Ok(parsed) => match handle_build_stack(&socket, &ctx, parsed).await {
+                        Ok(timing) => callback_ok_timed(ack.take(), "Built", true, timing),
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -206,13 +546,13 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "downStack",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("downStack", ack, |ack| async move {
                 match handle_down_stack(&socket, &ctx, &stack_name).await {
-                    Ok(_) => {
-                        callback_ok(Some(ack), "Downed", true);
+                    Ok(timing) => {
+                        callback_ok_timed(ack.take(), "Downed", true, timing);
                         broadcast_stack_list(&ctx).await;
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -224,15 +564,15 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "restartService",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("restartService", ack, |ack| async move {
                 match parse_service_args(&data) {
                     Ok((stack_name, service_name)) => {
                         match handle_restart_service(&socket, &ctx, &stack_name, &service_name).await {
-                            Ok(_) => callback_ok(Some(ack), "Restarted", true),
-                            Err(e) => callback_error(Some(ack), e),
+                            Ok(_) => callback_ok(ack.take(), "Restarted", true),
+                            Err(e) => callback_error(ack.take(), e),
                         }
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -244,15 +584,15 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "startService",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("startService", ack, |ack| async move {
                 match parse_service_args(&data) {
                     Ok((stack_name, service_name)) => {
                         match handle_start_service(&socket, &ctx, &stack_name, &service_name).await {
-                            Ok(_) => callback_ok(Some(ack), "Started", true),
-                            Err(e) => callback_error(Some(ack), e),
+                            Ok(_) => callback_ok(ack.take(), "Started", true),
+                            Err(e) => callback_error(ack.take(), e),
                         }
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -264,15 +604,15 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "stopService",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("stopService", ack, |ack| async move {
                 match parse_service_args(&data) {
                     Ok((stack_name, service_name)) => {
                         match handle_stop_service(&socket, &ctx, &stack_name, &service_name).await {
-                            Ok(_) => callback_ok(Some(ack), "Stopped", true),
-                            Err(e) => callback_error(Some(ack), e),
+                            Ok(_) => callback_ok(ack.take(), "Stopped", true),
+                            Err(e) => callback_error(ack.take(), e),
                         }
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -284,15 +624,15 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "pullService",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("pullService", ack, |ack| async move {
                 match parse_service_args(&data) {
                     Ok((stack_name, service_name)) => {
                         match handle_pull_service(&socket, &ctx, &stack_name, &service_name).await {
-                            Ok(_) => callback_ok(Some(ack), "Pulled", true),
-                            Err(e) => callback_error(Some(ack), e),
+                            Ok(_) => callback_ok(ack.take(), "Pulled", true),
+                            Err(e) => callback_error(ack.take(), e),
                         }
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -304,479 +644,2751 @@ pub fn setup_stack_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "serviceStatusList",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("serviceStatusList", ack, |ack| async move {
                 match handle_service_status_list(&socket, &ctx, &stack_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
     );
 
-    // getDockerNetworkList
+    // regenerateStackWebhook
     let ctx_clone = ctx.clone();
     socket.on(
-        "getDockerNetworkList",
-        async move |socket: SocketRef, ack: AckSender| {
+        "regenerateStackWebhook",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
-                match handle_get_docker_network_list(&socket, &ctx).await {
+            spawn_handler("regenerateStackWebhook", ack, |ack| async move {
+                match handle_regenerate_stack_webhook(&socket, &ctx, &stack_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
     );
-}
-
-/// Parse deployStack positional args: [name, composeYAML, composeENV, isAdd]
-fn parse_deploy_stack_args(data: &Value) -> Result<DeployStackData> {
-    let args = data
-        .as_array()
-        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
-    if args.len() < 4 {
-        return Err(anyhow!(
-            "deployStack requires 4 arguments: name, composeYAML, composeENV, isAdd"
-        ));
-    }
-    Ok(DeployStackData {
-        name: args[0]
-            .as_str()
-            .ok_or_else(|| anyhow!("name must be a string"))?
-            .to_string(),
-        compose_yaml: args[1]
-            .as_str()
-            .ok_or_else(|| anyhow!("composeYAML must be a string"))?
-            .to_string(),
-        compose_env: args[2]
-            .as_str()
-            .ok_or_else(|| anyhow!("composeENV must be a string"))?
-            .to_string(),
-        is_add: args[3]
-            .as_bool()
-            .ok_or_else(|| anyhow!("isAdd must be a boolean"))?,
-    })
-}
 
-/// Parse saveStack positional args: [name, composeYAML, composeENV, isAdd]
-fn parse_save_stack_args(data: &Value) -> Result<SaveStackData> {
-    let args = data
-        .as_array()
-        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
-    if args.len() < 4 {
-        return Err(anyhow!(
-            "saveStack requires 4 arguments: name, composeYAML, composeENV, isAdd"
-        ));
-    }
-    Ok(SaveStackData {
-        name: args[0]
-            .as_str()
-            .ok_or_else(|| anyhow!("name must be a string"))?
-            .to_string(),
-        compose_yaml: args[1]
-            .as_str()
-            .ok_or_else(|| anyhow!("composeYAML must be a string"))?
-            .to_string(),
-        compose_env: args[2]
-            .as_str()
-            .ok_or_else(|| anyhow!("composeENV must be a string"))?
-            .to_string(),
-        is_add: args[3]
-            .as_bool()
-            .ok_or_else(|| anyhow!("isAdd must be a boolean"))?,
-    })
-}
+    // deleteStackWebhook
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "deleteStackWebhook",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("deleteStackWebhook", ack, |ack| async move {
+                match handle_delete_stack_webhook(&socket, &ctx, &stack_name).await {
+                    Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
 
-/// Dispatch a stack event from the agent proxy (local endpoint).
-/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
-pub(crate) async fn dispatch_stack_event(
-    socket: &SocketRef,
-    ctx: &ServerContext,
-    event_name: &str,
-    event_args: &[Value],
-    ack: &mut Option<AckSender>,
-) -> Result<bool> {
-    match event_name {
-        "deployStack" => {
-            let data = parse_deploy_stack_args(&json!(event_args))?;
-            match handle_deploy_stack(socket, ctx, data).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Deployed", true);
-                    broadcast_stack_list(ctx).await;
-                }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "saveStack" => {
-            warn!(
-                "dispatch_stack_event saveStack: event_args={:?}",
-                event_args
-            );
-            let data = parse_save_stack_args(&json!(event_args))?;
-            warn!(
-                "dispatch_stack_event saveStack parsed: name={}, is_add={}",
-                data.name, data.is_add
-            );
-            match handle_save_stack(socket, ctx, data).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Saved", true);
-                    broadcast_stack_list(ctx).await;
+    // createShareLink
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "createShareLink",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("createShareLink", ack, |ack| async move {
+                match parse_create_share_link_args(&data) {
+                    Ok(parsed) => match handle_create_share_link(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "deleteStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("deleteStack requires a stack name"))?;
-            match handle_delete_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Deleted", true);
-                    broadcast_stack_list(ctx).await;
-                }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "getStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("getStack requires a stack name"))?;
-            match handle_get_stack(socket, ctx, stack_name).await {
-                Ok(response) => {
-                    if let Some(ack) = ack.take() {
+            });
+        },
+    );
+
+    // getShareLinks
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getShareLinks",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getShareLinks", ack, |ack| async move {
+                match handle_get_share_links(&socket, &ctx, &stack_name).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // deleteShareLink
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "deleteShareLink",
+        async move |socket: SocketRef, Data::<i64>(id), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("deleteShareLink", ack, |ack| async move {
+                match handle_delete_share_link(&socket, &ctx, id).await {
+                    Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // getStackHistory
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getStackHistory",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getStackHistory", ack, |ack| async move {
+                match handle_get_stack_history(&socket, &ctx, &stack_name).await {
+                    Ok(response) => {
                         ack.send(&response).ok();
                     }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // getStackEvents
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getStackEvents",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getStackEvents", ack, |ack| async move {
+                match parse_stack_events_args(&data) {
+                    Ok((stack_name, query)) => {
+                        match handle_get_stack_events(&socket, &ctx, &stack_name, query).await {
+                            Ok(response) => {
+                                ack.send(&response).ok();
+                            }
+                            Err(e) => callback_error(ack.take(), e),
+                        }
+                    }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "requestStackList" => {
-            if check_login(socket).is_ok() {
-                broadcast_stack_list(ctx).await;
-                callback_ok(ack.take(), "Updated", true);
-            }
-            Ok(true)
-        }
-        "startStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("startStack requires a stack name"))?;
-            match handle_start_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Started", true);
-                    broadcast_stack_list(ctx).await;
+            });
+        },
+    );
+
+    // importStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "importStack",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("importStack", ack, |ack| async move {
+                match parse_import_stack_args(&data) {
+                    Ok(parsed) => match handle_import_stack(&socket, &ctx, parsed).await {
+                        Ok(_) => {
+                            callback_ok(ack.take(), "Imported", true);
+                            broadcast_stack_list(&ctx).await;
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "stopStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("stopStack requires a stack name"))?;
-            match handle_stop_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Stopped", true);
-                    broadcast_stack_list(ctx).await;
+            });
+        },
+    );
+
+    // exportStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "exportStack",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("exportStack", ack, |ack| async move {
+                match parse_export_stack_args(&data) {
+                    Ok((stack_name, passphrase)) => {
+                        match handle_export_stack(&socket, &ctx, &stack_name, passphrase).await {
+                            Ok(response) => {
+                                ack.send(&response).ok();
+                            }
+                            Err(e) => callback_error(ack.take(), e),
+                        }
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // previewStackConfig
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "previewStackConfig",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("previewStackConfig", ack, |ack| async move {
+                match parse_preview_stack_config_args(&data) {
+                    Ok(parsed) => match handle_preview_stack_config(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "restartStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("restartStack requires a stack name"))?;
-            match handle_restart_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Restarted", true);
-                    broadcast_stack_list(ctx).await;
+            });
+        },
+    );
+
+    // getStackNormalized
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getStackNormalized",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getStackNormalized", ack, |ack| async move {
+                match handle_get_stack_normalized(&socket, &ctx, &stack_name).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // diffStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "diffStack",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("diffStack", ack, |ack| async move {
+                match parse_diff_stack_args(&data) {
+                    Ok(parsed) => match handle_diff_stack(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "updateStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("updateStack requires a stack name"))?;
-            match handle_update_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Updated", true);
-                    broadcast_stack_list(ctx).await;
+            });
+        },
+    );
+
+    // setStackComposeFiles
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackComposeFiles",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackComposeFiles", ack, |ack| async move {
+                match parse_set_stack_compose_files_args(&data) {
+                    Ok(parsed) => match handle_set_stack_compose_files(&socket, &ctx, parsed).await
+                    {
+                        Ok(_) => {
+                            callback_ok(ack.take(), "Saved", true);
+                            broadcast_stack_list(&ctx).await;
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "downStack" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("downStack requires a stack name"))?;
-            match handle_down_stack(socket, ctx, stack_name).await {
-                Ok(_) => {
-                    callback_ok(ack.take(), "Downed", true);
-                    broadcast_stack_list(ctx).await;
+            });
+        },
+    );
+
+    // setStackRestartPolicy
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackRestartPolicy",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackRestartPolicy", ack, |ack| async move {
+                match parse_set_stack_restart_policy_args(&data) {
+                    Ok(parsed) => {
+                        match handle_set_stack_restart_policy(&socket, &ctx, parsed).await {
+                            Ok(set) => callback_ok_with_fields(ack.take(), "Saved", true, set),
+                            Err(e) => callback_error(ack.take(), e),
+                        }
+                    }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "serviceStatusList" => {
-            let stack_name = event_args
-                .first()
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow!("serviceStatusList requires a stack name"))?;
-            match handle_service_status_list(socket, ctx, stack_name).await {
-                Ok(response) => {
-                    if let Some(ack) = ack.take() {
-                        ack.send(&response).ok();
+            });
+        },
+    );
+
+    // setStackDockerContext
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackDockerContext",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackDockerContext", ack, |ack| async move {
+                match parse_set_stack_docker_context_args(&data) {
+                    Ok(parsed) => {
+                        match handle_set_stack_docker_context(&socket, &ctx, parsed).await {
+                            Ok(_) => {
+                                callback_ok(ack.take(), "Saved", true);
+                                broadcast_stack_list(&ctx).await;
+                            }
+                            Err(e) => callback_error(ack.take(), e),
+                        }
                     }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "getDockerNetworkList" => {
-            match handle_get_docker_network_list(socket, ctx).await {
-                Ok(response) => {
-                    if let Some(ack) = ack.take() {
+            });
+        },
+    );
+
+    // getDockerContexts
+    socket.on(
+        "getDockerContexts",
+        async move |socket: SocketRef, ack: AckSender| {
+            spawn_handler("getDockerContexts", ack, |ack| async move {
+                match handle_get_docker_contexts(&socket).await {
+                    Ok(response) => {
                         ack.send(&response).ok();
                     }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "restartService" => {
-            let args = json!(event_args);
-            match parse_service_args(&args) {
-                Ok((stack_name, service_name)) => {
-                    match handle_restart_service(socket, ctx, &stack_name, &service_name).await {
-                        Ok(_) => callback_ok(ack.take(), "Restarted", true),
-                        Err(e) => callback_error(ack.take(), e),
+            });
+        },
+    );
+
+    // setStackDependencies
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackDependencies",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackDependencies", ack, |ack| async move {
+                match parse_set_stack_dependencies_args(&data) {
+                    Ok(parsed) => {
+                        match handle_set_stack_dependencies(&socket, &ctx, parsed).await {
+                            Ok(_) => callback_ok(ack.take(), "Saved", true),
+                            Err(e) => callback_error(ack.take(), e),
+                        }
                     }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "startService" => {
-            let args = json!(event_args);
-            match parse_service_args(&args) {
-                Ok((stack_name, service_name)) => {
-                    match handle_start_service(socket, ctx, &stack_name, &service_name).await {
-                        Ok(_) => callback_ok(ack.take(), "Started", true),
+            });
+        },
+    );
+
+    // getInjectedEnv
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getInjectedEnv",
+        async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getInjectedEnv", ack, |ack| async move {
+                match handle_get_injected_env(&socket, &ctx, &stack_name).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // setInjectedEnv
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setInjectedEnv",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setInjectedEnv", ack, |ack| async move {
+                match parse_set_injected_env_args(&data) {
+                    Ok(parsed) => match handle_set_injected_env(&socket, &ctx, parsed).await {
+                        Ok(_) => callback_ok(ack.take(), "Saved", true),
                         Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // deployAll / startAll
+    for (event, action) in [
+        ("deployAll", BatchAction::Deploy),
+        ("startAll", BatchAction::Start),
+    ] {
+        let ctx_clone = ctx.clone();
+        socket.on(event, async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler(event, ack, |ack| async move {
+                let result = handle_run_all(&socket, &ctx, action).await;
+                match result {
+                    Ok(response) => {
+                        ack.send(&response).ok();
                     }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "stopService" => {
-            let args = json!(event_args);
-            match parse_service_args(&args) {
-                Ok((stack_name, service_name)) => {
-                    match handle_stop_service(socket, ctx, &stack_name, &service_name).await {
-                        Ok(_) => callback_ok(ack.take(), "Stopped", true),
+                broadcast_stack_list(&ctx).await;
+            });
+        });
+    }
+
+    // batchStackAction
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "batchStackAction",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("batchStackAction", ack, |ack| async move {
+                match parse_batch_stack_action_args(&data) {
+                    Ok(data) => {
+                        match handle_batch_stack_action(&socket, &ctx, data).await {
+                            Ok(response) => {
+                                ack.send(&response).ok();
+                            }
+                            Err(e) => callback_error(ack.take(), e),
+                        }
+                        broadcast_stack_list(&ctx).await;
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // restoreStackSnapshot
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "restoreStackSnapshot",
+        async move |socket: SocketRef, Data::<i64>(history_id), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("restoreStackSnapshot", ack, |ack| async move {
+                match handle_restore_stack_snapshot(&socket, &ctx, history_id).await {
+                    Ok(_) => {
+                        callback_ok(ack.take(), "Restored", true);
+                        broadcast_stack_list(&ctx).await;
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // rollbackStack
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "rollbackStack",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("rollbackStack", ack, |ack| async move {
+                match parse_rollback_stack_args(&data) {
+                    Ok(parsed) => match handle_rollback_stack(&socket, &ctx, parsed).await {
+                        Ok(_) => {
+                            callback_ok(ack.take(), "Rolled back", true);
+                            broadcast_stack_list(&ctx).await;
+                        }
                         Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // getDockerNetworkList
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getDockerNetworkList",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getDockerNetworkList", ack, |ack| async move {
+                match handle_get_docker_network_list(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // getExposureReport
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getExposureReport",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getExposureReport", ack, |ack| async move {
+                match handle_get_exposure_report(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
                     }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // setStackGroup
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackGroup",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackGroup", ack, |ack| async move {
+                match parse_set_stack_group_args(&data) {
+                    Ok(parsed) => match handle_set_stack_group(&socket, &ctx, parsed).await {
+                        Ok(_) => {
+                            callback_ok(ack.take(), "Saved", true);
+                            broadcast_stack_list(&ctx).await;
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        "pullService" => {
-            let args = json!(event_args);
-            match parse_service_args(&args) {
-                Ok((stack_name, service_name)) => {
-                    match handle_pull_service(socket, ctx, &stack_name, &service_name).await {
-                        Ok(_) => callback_ok(ack.take(), "Pulled", true),
+            });
+        },
+    );
+
+    // setStackAutostart
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackAutostart",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackAutostart", ack, |ack| async move {
+                match parse_set_stack_autostart_args(&data) {
+                    Ok(parsed) => match handle_set_stack_autostart(&socket, &ctx, parsed).await {
+                        Ok(_) => {
+                            callback_ok(ack.take(), "Saved", true);
+                            broadcast_stack_list(&ctx).await;
+                        }
                         Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // setStackUpdateWindow
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackUpdateWindow",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackUpdateWindow", ack, |ack| async move {
+                match parse_set_stack_update_window_args(&data) {
+                    Ok(parsed) => {
+                        match handle_set_stack_update_window(&socket, &ctx, parsed).await {
+                            Ok(_) => {
+                                callback_ok(ack.take(), "Saved", true);
+                                broadcast_stack_list(&ctx).await;
+                            }
+                            Err(e) => callback_error(ack.take(), e),
+                        }
                     }
+                    Err(e) => callback_error(ack.take(), e),
                 }
-                Err(e) => callback_error(ack.take(), e),
-            }
-            Ok(true)
-        }
-        _ => Ok(false),
+            });
+        },
+    );
+
+    // getStackOrder
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getStackOrder",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getStackOrder", ack, |ack| async move {
+                match handle_get_stack_order(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // setStackPinned
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackPinned",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackPinned", ack, |ack| async move {
+                match parse_set_stack_pinned_args(&data) {
+                    Ok(parsed) => match handle_set_stack_pinned(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // setStackOrder
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "setStackOrder",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("setStackOrder", ack, |ack| async move {
+                match parse_set_stack_order_args(&data) {
+                    Ok(names) => match handle_set_stack_order(&socket, &ctx, names).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // listGroups
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "listGroups",
+        async move |socket: SocketRef, ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("listGroups", ack, |ack| async move {
+                match handle_list_groups(&socket, &ctx).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+}
+
+/// Parse deployStack positional args:
+/// [name, composeYAML, composeENV, isAdd, options?, composeFileName?]
+fn parse_deploy_stack_args(data: &Value) -> Result<DeployStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 4 {
+        return Err(anyhow!(
+            "deployStack requires 4 arguments: name, composeYAML, composeENV, isAdd"
+        ));
+    }
+    Ok(DeployStackData {
+        name: StackName::parse(
+            args[0]
+                .as_str()
+                .ok_or_else(|| anyhow!("name must be a string"))?,
+        )?,
+        compose_yaml: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeYAML must be a string"))?
+            .to_string(),
+        compose_env: args[2]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeENV must be a string"))?
+            .to_string(),
+        is_add: args[3]
+            .as_bool()
+            .ok_or_else(|| anyhow!("isAdd must be a boolean"))?,
+        options: parse_deploy_options(args.get(4))?,
+        compose_file_name: parse_compose_file_name(args.get(5))?,
+    })
+}
+
+/// Parse startStack args: a stack name, or [stackName, options?]
+fn parse_start_stack_args(data: &Value) -> Result<StartStackData> {
+    let (name, options) = match data {
+        Value::Array(args) => (args.first(), args.get(1)),
+        name => (Some(name), None),
+    };
+    Ok(StartStackData {
+        stack_name: name
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("startStack requires a stack name"))?
+            .to_string(),
+        options: parse_deploy_options(options)?,
+    })
+}
+
+/// Parse args of events taking a stack name and an optional flag:
+/// a stack name, or [stackName, flag?]
+fn parse_stack_flag_args(data: &Value, event: &str) -> Result<StackFlagData> {
+    let (name, flag) = match data {
+        Value::Array(args) => (args.first(), args.get(1)),
+        name => (Some(name), None),
+    };
+    Ok(StackFlagData {
+        stack_name: name
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("{} requires a stack name", event))?
+            .to_string(),
+        flag: match flag {
+            None | Some(Value::Null) => false,
+            Some(flag) => flag
+                .as_bool()
+                .ok_or_else(|| anyhow!("{} flag must be a boolean", event))?,
+        },
+    })
+}
+
+/// Parse queryStackList args: a query object, or [query?]
+fn parse_stack_list_query(data: &Value) -> Result<StackListQuery> {
+    let query = match data {
+        Value::Array(args) => args.first().cloned().unwrap_or(Value::Null),
+        query => query.clone(),
+    };
+    if query.is_null() {
+        return Ok(StackListQuery::default());
+    }
+    serde_json::from_value(query).map_err(|e| anyhow!("Invalid stack list query: {}", e))
+}
+
+/// Deploy options object ({forceRecreate, pull, noBuild}), defaults when absent
+fn parse_deploy_options(value: Option<&Value>) -> Result<DeployOptions> {
+    match value {
+        None | Some(Value::Null) => Ok(DeployOptions::default()),
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| anyhow!("Invalid deploy options: {}", e)),
     }
 }
 
-async fn handle_deploy_stack(
+/// Parse saveStack positional args: [name, composeYAML, composeENV, isAdd, composeFileName?]
+fn parse_save_stack_args(data: &Value) -> Result<SaveStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 4 {
+        return Err(anyhow!(
+            "saveStack requires 4 arguments: name, composeYAML, composeENV, isAdd"
+        ));
+    }
+    Ok(SaveStackData {
+        name: StackName::parse(
+            args[0]
+                .as_str()
+                .ok_or_else(|| anyhow!("name must be a string"))?,
+        )?,
+        compose_yaml: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeYAML must be a string"))?
+            .to_string(),
+        compose_env: args[2]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeENV must be a string"))?
+            .to_string(),
+        is_add: args[3]
+            .as_bool()
+            .ok_or_else(|| anyhow!("isAdd must be a boolean"))?,
+        compose_file_name: parse_compose_file_name(args.get(4))?,
+    })
+}
+
+/// Optional compose file name override for new stacks
+fn parse_compose_file_name(value: Option<&Value>) -> Result<Option<String>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|name| Some(name.to_string()))
+            .ok_or_else(|| anyhow!("composeFileName must be a string")),
+    }
+}
+
+/// Parse setStackRestartPolicy positional args: [stackName, policy, { apply }?]
+fn parse_set_stack_restart_policy_args(data: &Value) -> Result<SetStackRestartPolicyData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackRestartPolicy requires 2 arguments: stackName, policy"
+        ));
+    }
+    Ok(SetStackRestartPolicyData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        policy: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("policy must be a string"))?
+            .parse()?,
+        apply: args
+            .get(2)
+            .and_then(|options| options.get("apply"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+/// Parse setStackDockerContext positional args: [stackName, context]
+fn parse_set_stack_docker_context_args(data: &Value) -> Result<SetStackDockerContextData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackDockerContext requires 2 arguments: stackName, context"
+        ));
+    }
+    Ok(SetStackDockerContextData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        context: match &args[1] {
+            Value::Null => None,
+            Value::String(context) if context.is_empty() => None,
+            Value::String(context) => Some(context.clone()),
+            _ => return Err(anyhow!("context must be a string or null")),
+        },
+    })
+}
+
+/// Parse rollbackStack positional args: [stackName, historyId]
+fn parse_rollback_stack_args(data: &Value) -> Result<RollbackStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "rollbackStack requires 2 arguments: stackName, historyId"
+        ));
+    }
+    Ok(RollbackStackData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        history_id: args[1]
+            .as_i64()
+            .ok_or_else(|| anyhow!("historyId must be a number"))?,
+    })
+}
+
+/// Parse batchStackAction positional args: [stackNames, action]
+///
+/// Names are checked and duplicates dropped, keeping the first occurrence.
+fn parse_batch_stack_action_args(data: &Value) -> Result<BatchStackActionData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "batchStackAction requires 2 arguments: stackNames, action"
+        ));
+    }
+
+    let names: Vec<String> = serde_json::from_value(args[0].clone())
+        .map_err(|_| anyhow!("stackNames must be a list of stack names"))?;
+    let mut stack_names: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        let name = StackName::parse(&name)?.to_string();
+        if !stack_names.contains(&name) {
+            stack_names.push(name);
+        }
+    }
+    if stack_names.is_empty() {
+        return Err(anyhow!("No stacks given"));
+    }
+    if stack_names.len() > MAX_BATCH_STACKS {
+        return Err(anyhow!(
+            "At most {} stacks can be run at once",
+            MAX_BATCH_STACKS
+        ));
+    }
+
+    let action = serde_json::from_value(args[1].clone())
+        .map_err(|_| anyhow!("action must be one of start, stop, update, down"))?;
+    Ok(BatchStackActionData {
+        stack_names,
+        action,
+    })
+}
+
+/// Parse getStackEvents positional args: [stackName, {page, pageSize}?]
+fn parse_stack_events_args(data: &Value) -> Result<(String, StackEventsQuery)> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("getStackEvents requires a stack name"))?
+        .to_string();
+    let query = match args.get(1) {
+        None | Some(Value::Null) => StackEventsQuery::default(),
+        Some(query) => serde_json::from_value(query.clone())
+            .map_err(|e| anyhow!("Invalid stack events query: {}", e))?,
+    };
+    if query.page < 0 {
+        return Err(anyhow!("page must not be negative"));
+    }
+    Ok((stack_name, query))
+}
+
+/// Parse previewStackConfig positional args: [stackName, composeYAML?, composeENV?]
+fn parse_preview_stack_config_args(data: &Value) -> Result<PreviewStackConfigData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = StackName::parse(
+        args.first()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("previewStackConfig requires a stack name"))?,
+    )?;
+
+    let content = match args.get(1).filter(|v| !v.is_null()) {
+        Some(yaml) => {
+            let yaml = yaml
+                .as_str()
+                .ok_or_else(|| anyhow!("composeYAML must be a string"))?;
+            let env = match args.get(2).filter(|v| !v.is_null()) {
+                Some(env) => env
+                    .as_str()
+                    .ok_or_else(|| anyhow!("composeENV must be a string"))?,
+                None => "",
+            };
+            Some((yaml.to_string(), env.to_string()))
+        }
+        None => None,
+    };
+
+    Ok(PreviewStackConfigData {
+        stack_name,
+        content,
+    })
+}
+
+/// Parse setStackComposeFiles positional args: [stackName, files]
+fn parse_set_stack_compose_files_args(data: &Value) -> Result<SetStackComposeFilesData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackComposeFiles requires 2 arguments: stackName, files"
+        ));
+    }
+    Ok(SetStackComposeFilesData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        files: args[1]
+            .as_array()
+            .ok_or_else(|| anyhow!("files must be an array"))?
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("files must be an array of strings"))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+/// Parse diffStack positional args: [stackName, composeYAML, composeENV, includeConfig?]
+fn parse_diff_stack_args(data: &Value) -> Result<DiffStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 3 {
+        return Err(anyhow!(
+            "diffStack requires 3 arguments: stackName, composeYAML, composeENV"
+        ));
+    }
+    Ok(DiffStackData {
+        stack_name: StackName::parse(
+            args[0]
+                .as_str()
+                .ok_or_else(|| anyhow!("stackName must be a string"))?,
+        )?,
+        compose_yaml: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeYAML must be a string"))?
+            .to_string(),
+        compose_env: args[2]
+            .as_str()
+            .ok_or_else(|| anyhow!("composeENV must be a string"))?
+            .to_string(),
+        include_config: args.get(3).and_then(|v| v.as_bool()).unwrap_or(false),
+    })
+}
+
+/// Parse setStackGroup args: [stackName, group]; a null or blank group
+/// takes the stack out of its group
+fn parse_set_stack_group_args(data: &Value) -> Result<SetStackGroupData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("setStackGroup requires a stack name"))?
+        .to_string();
+    let group = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(group) => {
+            let group = group
+                .as_str()
+                .ok_or_else(|| anyhow!("group must be a string or null"))?
+                .trim();
+            if group.chars().count() > MAX_GROUP_NAME_LENGTH {
+                return Err(anyhow!(
+                    "Group name must be at most {} characters",
+                    MAX_GROUP_NAME_LENGTH
+                ));
+            }
+            Some(group.to_string()).filter(|g| !g.is_empty())
+        }
+    };
+    Ok(SetStackGroupData { stack_name, group })
+}
+
+/// Parse setStackAutostart args: [stackName, enabled]
+fn parse_set_stack_autostart_args(data: &Value) -> Result<SetStackAutostartData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackAutostart requires 2 arguments: stackName, enabled"
+        ));
+    }
+    Ok(SetStackAutostartData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        enabled: args[1]
+            .as_bool()
+            .ok_or_else(|| anyhow!("enabled must be a boolean"))?,
+    })
+}
+
+/// Parse createShareLink args: [stackName, expiresInMinutes?]
+fn parse_create_share_link_args(data: &Value) -> Result<CreateShareLinkData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("createShareLink requires a stack name"))?;
+    let minutes = match args.get(1) {
+        None | Some(Value::Null) => DEFAULT_SHARE_LINK_MINUTES,
+        Some(minutes) => minutes
+            .as_i64()
+            .filter(|m| (1..=MAX_SHARE_LINK_MINUTES).contains(m))
+            .ok_or_else(|| {
+                anyhow!(
+                    "expiresInMinutes must be between 1 and {}",
+                    MAX_SHARE_LINK_MINUTES
+                )
+            })?,
+    };
+    Ok(CreateShareLinkData {
+        stack_name: StackName::parse(stack_name)?.to_string(),
+        minutes,
+    })
+}
+
+/// Parse setStackPinned args: [stackName, pinned]
+fn parse_set_stack_pinned_args(data: &Value) -> Result<SetStackPinnedData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackPinned requires 2 arguments: stackName, pinned"
+        ));
+    }
+    Ok(SetStackPinnedData {
+        stack_name: StackName::parse(
+            args[0]
+                .as_str()
+                .ok_or_else(|| anyhow!("stackName must be a string"))?,
+        )?
+        .to_string(),
+        pinned: args[1]
+            .as_bool()
+            .ok_or_else(|| anyhow!("pinned must be a boolean"))?,
+    })
+}
+
+/// Parse setStackOrder args: [stackNames], first stack first
+fn parse_set_stack_order_args(data: &Value) -> Result<Vec<String>> {
+    let names: Vec<String> = data
+        .as_array()
+        .and_then(|args| args.first())
+        .and_then(|names| serde_json::from_value(names.clone()).ok())
+        .ok_or_else(|| anyhow!("setStackOrder requires a list of stack names"))?;
+
+    let mut stack_names: Vec<String> = Vec::with_capacity(names.len());
+    for name in names {
+        let name = StackName::parse(&name)?.to_string();
+        if !stack_names.contains(&name) {
+            stack_names.push(name);
+        }
+    }
+    if stack_names.len() > MAX_STACK_ORDER_LENGTH {
+        return Err(anyhow!(
+            "At most {} stacks can be ordered",
+            MAX_STACK_ORDER_LENGTH
+        ));
+    }
+    Ok(stack_names)
+}
+
+/// Parse setStackUpdateWindow args: [stackName, window]; a null or blank
+/// window stops applying updates automatically
+fn parse_set_stack_update_window_args(data: &Value) -> Result<SetStackUpdateWindowData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("setStackUpdateWindow requires a stack name"))?
+        .to_string();
+    let window = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(window) => {
+            let window = window
+                .as_str()
+                .ok_or_else(|| anyhow!("window must be a string or null"))?
+                .trim();
+            if window.is_empty() {
+                None
+            } else {
+                Some(window.parse()?)
+            }
+        }
+    };
+    Ok(SetStackUpdateWindowData { stack_name, window })
+}
+
+fn parse_set_injected_env_args(data: &Value) -> Result<SetInjectedEnvData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setInjectedEnv requires 2 arguments: stackName, variables"
+        ));
+    }
+    Ok(SetInjectedEnvData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        changes: args[1]
+            .as_object()
+            .ok_or_else(|| anyhow!("variables must be an object"))?
+            .clone(),
+    })
+}
+
+/// Parse setStackDependencies positional args: [stackName, dependsOn]
+fn parse_set_stack_dependencies_args(data: &Value) -> Result<SetStackDependenciesData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "setStackDependencies requires 2 arguments: stackName, dependsOn"
+        ));
+    }
+    Ok(SetStackDependenciesData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        depends_on: args[1]
+            .as_array()
+            .ok_or_else(|| anyhow!("dependsOn must be an array"))?
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("dependsOn must be an array of strings"))
+            })
+            .collect::<Result<_>>()?,
+    })
+}
+
+/// Parse watchStack positional args: [stackName, intervalSecs]
+fn parse_watch_stack_args(data: &Value) -> Result<WatchStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "watchStack requires 2 arguments: stackName, intervalSecs"
+        ));
+    }
+    Ok(WatchStackData {
+        stack_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("stackName must be a string"))?
+            .to_string(),
+        interval_secs: args[1]
+            .as_u64()
+            .ok_or_else(|| anyhow!("intervalSecs must be a positive integer"))?
+            .clamp(MIN_WATCH_STACK_REFRESH_SECS, MAX_STACK_REFRESH_SECS),
+    })
+}
+
+/// Parse importStack positional args: [uploadId, name, deploy?, passphrase?]
+fn parse_import_stack_args(data: &Value) -> Result<ImportStackData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "importStack requires 2 arguments: uploadId, name"
+        ));
+    }
+    Ok(ImportStackData {
+        upload_id: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("uploadId must be a string"))?
+            .to_string(),
+        name: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("name must be a string"))?
+            .to_string(),
+        deploy: args.get(2).and_then(|v| v.as_bool()).unwrap_or(false),
+        passphrase: passphrase_arg(args.get(3))?,
+    })
+}
+
+/// Parse exportStack args: stackName or [stackName, passphrase?]
+fn parse_export_stack_args(data: &Value) -> Result<(String, Option<Secret<String>>)> {
+    let (stack_name, passphrase) = match data {
+        Value::Array(args) => (args.first(), args.get(1)),
+        name => (Some(name), None),
+    };
+    let stack_name = stack_name
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("exportStack requires a stack name"))?
+        .to_string();
+    Ok((stack_name, passphrase_arg(passphrase)?))
+}
+
+/// An optional archive passphrase, None when missing, null or empty
+fn passphrase_arg(value: Option<&Value>) -> Result<Option<Secret<String>>> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) if s.is_empty() => Ok(None),
+        Some(Value::String(s)) => Ok(Some(Secret::new(s.clone()))),
+        Some(_) => Err(anyhow!("passphrase must be a string")),
+    }
+}
+
+/// Dispatch a stack event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_stack_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    match event_name {
+        "deployStack" => {
+            let data = parse_deploy_stack_args(&json!(event_args))?;
+            match handle_deploy_stack(socket, ctx, data).await {
+                Ok(deployed) => {
+                    callback_ok_with_fields(ack.take(), "Deployed", true, deployed);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "saveStack" => {
+            warn!(
+                "dispatch_stack_event saveStack: event_args={:?}",
+                event_args
+            );
+            let data = parse_save_stack_args(&json!(event_args))?;
+            warn!(
+                "dispatch_stack_event saveStack parsed: name={}, is_add={}",
+                data.name, data.is_add
+            );
+            match handle_save_stack(socket, ctx, data).await {
+                Ok(saved) => {
+                    callback_ok_with_fields(ack.take(), "Saved", true, saved);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deleteStack" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("deleteStack requires a stack name"))?;
+            match handle_delete_stack(socket, ctx, stack_name).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Deleted", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getStack" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("getStack requires a stack name"))?;
+            match handle_get_stack(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "queryStackList" => {
+            let query = parse_stack_list_query(&json!(event_args))?;
+            match handle_query_stack_list(socket, ctx, query).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "requestStackList" => {
+            if check_login(socket).is_ok() {
+                request_stack_list(ctx).await;
+                callback_ok(ack.take(), "Updated", true);
+            }
+            Ok(true)
+        }
+        "watchStack" => {
+            let data = parse_watch_stack_args(&json!(event_args))?;
+            match handle_watch_stack(socket, ctx, data).await {
+                Ok(_) => callback_ok(ack.take(), "Watching", false),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "unwatchStack" => {
+            stop_stack_watch(&socket.id.to_string()).await;
+            callback_ok(ack.take(), "Stopped", false);
+            Ok(true)
+        }
+        "startStack" => {
+            let data = parse_start_stack_args(&json!(event_args))?;
+            match handle_start_stack(socket, ctx, data).await {
+                Ok(timing) => {
+                    callback_ok_timed(ack.take(), "Started", true, timing);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "stopStack" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("stopStack requires a stack name"))?;
+            match handle_stop_stack(socket, ctx, stack_name).await {
+                Ok(timing) => {
+                    callback_ok_timed(ack.take(), "Stopped", true, timing);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "restartStack" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("restartStack requires a stack name"))?;
+            match handle_restart_stack(socket, ctx, stack_name).await {
+                Ok(timing) => {
+                    callback_ok_timed(ack.take(), "Restarted", true, timing);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "updateStack" => {
+            let data = parse_stack_flag_args(&json!(event_args), "updateStack")?;
+            match handle_update_stack(socket, ctx, data).await {
+                Ok(timing) => {
+                    callback_ok_timed(ack.take(), "Updated", true, timing);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "buildStack" => {
+            let data = parse_stack_flag_args(&json!(event_args), "buildStack")?;
+            match handle_build_stack(socket, ctx, data).await {
+                Ok(timing) => callback_ok_timed(ack.take(), "Built", true, timing),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "downStack" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("downStack requires a stack name"))?;
+            match handle_down_stack(socket, ctx, stack_name).await {
+                Ok(timing) => {
+                    callback_ok_timed(ack.take(), "Downed", true, timing);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "serviceStatusList" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("serviceStatusList requires a stack name"))?;
+            match handle_service_status_list(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "regenerateStackWebhook" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("regenerateStackWebhook requires a stack name"))?;
+            match handle_regenerate_stack_webhook(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deleteStackWebhook" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("deleteStackWebhook requires a stack name"))?;
+            match handle_delete_stack_webhook(socket, ctx, stack_name).await {
+                Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "createShareLink" => {
+            let data = parse_create_share_link_args(&json!(event_args))?;
+            match handle_create_share_link(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getShareLinks" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("getShareLinks requires a stack name"))?;
+            match handle_get_share_links(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deleteShareLink" => {
+            let id = event_args
+                .first()
+                .and_then(|v| v.as_i64())
+                .ok_or_else(|| anyhow!("deleteShareLink requires a link id"))?;
+            match handle_delete_share_link(socket, ctx, id).await {
+                Ok(_) => callback_ok(ack.take(), "Deleted", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getStackHistory" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("getStackHistory requires a stack name"))?;
+            match handle_get_stack_history(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getStackEvents" => {
+            let (stack_name, query) = parse_stack_events_args(&json!(event_args))?;
+            match handle_get_stack_events(socket, ctx, &stack_name, query).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "importStack" => {
+            let data = parse_import_stack_args(&json!(event_args))?;
+            match handle_import_stack(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Imported", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "exportStack" => {
+            let (stack_name, passphrase) = parse_export_stack_args(&json!(event_args))?;
+            match handle_export_stack(socket, ctx, &stack_name, passphrase).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "previewStackConfig" => {
+            let data = parse_preview_stack_config_args(&json!(event_args))?;
+            match handle_preview_stack_config(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getStackNormalized" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("getStackNormalized requires a stack name"))?;
+            match handle_get_stack_normalized(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "diffStack" => {
+            let data = parse_diff_stack_args(&json!(event_args))?;
+            match handle_diff_stack(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackComposeFiles" => {
+            let data = parse_set_stack_compose_files_args(&json!(event_args))?;
+            match handle_set_stack_compose_files(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Saved", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackRestartPolicy" => {
+            let data = parse_set_stack_restart_policy_args(&json!(event_args))?;
+            match handle_set_stack_restart_policy(socket, ctx, data).await {
+                Ok(set) => callback_ok_with_fields(ack.take(), "Saved", true, set),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackDockerContext" => {
+            let data = parse_set_stack_docker_context_args(&json!(event_args))?;
+            match handle_set_stack_docker_context(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Saved", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getDockerContexts" => {
+            match handle_get_docker_contexts(socket).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackDependencies" => {
+            let data = parse_set_stack_dependencies_args(&json!(event_args))?;
+            match handle_set_stack_dependencies(socket, ctx, data).await {
+                Ok(_) => callback_ok(ack.take(), "Saved", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getInjectedEnv" => {
+            let stack_name = event_args
+                .first()
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("getInjectedEnv requires a stack name"))?;
+            match handle_get_injected_env(socket, ctx, stack_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setInjectedEnv" => {
+            let data = parse_set_injected_env_args(&json!(event_args))?;
+            match handle_set_injected_env(socket, ctx, data).await {
+                Ok(_) => callback_ok(ack.take(), "Saved", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "deployAll" | "startAll" => {
+            let action = if event_name == "deployAll" {
+                BatchAction::Deploy
+            } else {
+                BatchAction::Start
+            };
+            match handle_run_all(socket, ctx, action).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            broadcast_stack_list(ctx).await;
+            Ok(true)
+        }
+        "batchStackAction" => {
+            let data = parse_batch_stack_action_args(&json!(event_args))?;
+            match handle_batch_stack_action(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            broadcast_stack_list(ctx).await;
+            Ok(true)
+        }
+        "restoreStackSnapshot" => {
+            let history_id = event_args
+                .first()
+                .and_then(|v| v.as_i64())
+                .ok_or_else(|| anyhow!("restoreStackSnapshot requires a history id"))?;
+            match handle_restore_stack_snapshot(socket, ctx, history_id).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Restored", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "rollbackStack" => {
+            let data = parse_rollback_stack_args(&json!(event_args))?;
+            match handle_rollback_stack(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Rolled back", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getDockerNetworkList" => {
+            match handle_get_docker_network_list(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getExposureReport" => {
+            match handle_get_exposure_report(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackGroup" => {
+            let data = parse_set_stack_group_args(&json!(event_args))?;
+            match handle_set_stack_group(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Saved", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackAutostart" => {
+            let data = parse_set_stack_autostart_args(&json!(event_args))?;
+            match handle_set_stack_autostart(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Saved", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackUpdateWindow" => {
+            let data = parse_set_stack_update_window_args(&json!(event_args))?;
+            match handle_set_stack_update_window(socket, ctx, data).await {
+                Ok(_) => {
+                    callback_ok(ack.take(), "Saved", true);
+                    broadcast_stack_list(ctx).await;
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getStackOrder" => {
+            match handle_get_stack_order(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackPinned" => {
+            let data = parse_set_stack_pinned_args(&json!(event_args))?;
+            match handle_set_stack_pinned(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "setStackOrder" => {
+            let names = parse_set_stack_order_args(&json!(event_args))?;
+            match handle_set_stack_order(socket, ctx, names).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "listGroups" => {
+            match handle_list_groups(socket, ctx).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "restartService" => {
+            let args = json!(event_args);
+            match parse_service_args(&args) {
+                Ok((stack_name, service_name)) => {
+                    match handle_restart_service(socket, ctx, &stack_name, &service_name).await {
+                        Ok(_) => callback_ok(ack.take(), "Restarted", true),
+                        Err(e) => callback_error(ack.take(), e),
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "startService" => {
+            let args = json!(event_args);
+            match parse_service_args(&args) {
+                Ok((stack_name, service_name)) => {
+                    match handle_start_service(socket, ctx, &stack_name, &service_name).await {
+                        Ok(_) => callback_ok(ack.take(), "Started", true),
+                        Err(e) => callback_error(ack.take(), e),
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "stopService" => {
+            let args = json!(event_args);
+            match parse_service_args(&args) {
+                Ok((stack_name, service_name)) => {
+                    match handle_stop_service(socket, ctx, &stack_name, &service_name).await {
+                        Ok(_) => callback_ok(ack.take(), "Stopped", true),
+                        Err(e) => callback_error(ack.take(), e),
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "pullService" => {
+            let args = json!(event_args);
+            match parse_service_args(&args) {
+                Ok((stack_name, service_name)) => {
+                    match handle_pull_service(socket, ctx, &stack_name, &service_name).await {
+                        Ok(_) => callback_ok(ack.take(), "Pulled", true),
+                        Err(e) => callback_error(ack.take(), e),
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+async fn handle_deploy_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: DeployStackData,
+) -> Result<SavedStack> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let mut stack = Stack::new_with_content(
+        ctx.clone().into(),
+        data.name.clone(),
+        endpoint,
+        data.compose_yaml,
+        data.compose_env,
+    );
+    if let Some(name) = &data.compose_file_name {
+        stack.set_compose_file_name(name)?;
+    }
+
+    // Validate YAML is parseable
+    stack.compose_yaml().await?;
+    let warnings = stack.save(data.is_add).await?;
+    let (result, timing) =
+        OperationTiming::measure(stack.deploy(&data.options, Some(socket.clone()))).await;
+    stack
+        .record_event("deploy", &event_actor(socket), &result)
+        .await;
+    result?;
+
+    // Join combined terminal to see logs
+    stack.join_combined_terminal(socket.clone()).await?;
+
+    Ok(SavedStack {
+        timing: Some(timing),
+        warnings,
+    })
+}
+
+async fn handle_save_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SaveStackData,
+) -> Result<SavedStack> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let mut stack = Stack::new_with_content(
+        ctx.clone().into(),
+        data.name,
+        endpoint,
+        data.compose_yaml,
+        data.compose_env,
+    );
+    if let Some(name) = &data.compose_file_name {
+        stack.set_compose_file_name(name)?;
+    }
+
+    // Validate YAML is parseable
+    stack.compose_yaml().await?;
+    let warnings = stack.save(data.is_add).await?;
+
+    Ok(SavedStack {
+        timing: None,
+        warnings,
+    })
+}
+
+async fn handle_delete_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
+    let result = stack.delete(Some(socket.clone())).await;
+    stack
+        .record_event("delete", &event_actor(socket), &result)
+        .await;
+    result?;
+
+    // A recreated stack with the same name must not inherit the old token or schedules
+    StackWebhook::delete(&ctx.db, stack_name).await?;
+    StackSchedule::delete_by_stack(&ctx.db, stack_name).await?;
+    StackDependency::delete_by_stack(&ctx.db, stack_name).await?;
+    StackGroup::delete_by_stack(&ctx.db, stack_name).await?;
+    StackAutostart::delete_by_stack(&ctx.db, stack_name).await?;
+    StackUpdateWindow::delete_by_stack(&ctx.db, stack_name).await?;
+    StatusPeriod::delete_by_stack(&ctx.db, stack_name).await?;
+    StackOrder::delete_by_stack(&ctx.db, stack_name).await?;
+    ShareLink::delete_by_stack(&ctx.db, stack_name).await?;
+
+    Ok(())
+}
+
+async fn handle_get_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let mut stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint.clone()).await?;
+
+    // Join combined terminal if managed by dockru
+    if stack.is_managed_by_dockru().await {
+        stack.join_combined_terminal(socket.clone()).await.ok();
+    }
+
+    let stack_json = stack.to_json().await?;
+    let has_webhook = StackWebhook::find_by_stack(&ctx.db, stack_name)
+        .await?
+        .is_some();
+    let depends_on = StackDependency::find_by_stack(&ctx.db, stack_name).await?;
+    let uptime = crate::uptime::stack_uptime(&ctx.db, stack_name).await?;
+
+    #[derive(Serialize)]
+    struct StackResponse {
+        stack: StackJson,
+        #[serde(rename = "hasWebhook")]
+        has_webhook: bool,
+        #[serde(rename = "dependsOn")]
+        depends_on: Vec<String>,
+        uptime: UptimeSummary,
+    }
+
+    Ok(CustomResponse::ok_with_fields(StackResponse {
+        stack: stack_json,
+        has_webhook,
+        depends_on,
+        uptime,
+    })
+    .into())
+}
+
+async fn handle_get_stack_history(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let history = StackHistory::find_by_stack(&ctx.db, stack_name).await?;
+
+    #[derive(Serialize)]
+    struct StackHistoryResponse {
+        history: Vec<StackHistory>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(StackHistoryResponse { history }).into())
+}
+
+/// A page of the stack's lifecycle events, newest first
+async fn handle_get_stack_events(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+    query: StackEventsQuery,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let page_size = query.page_size();
+    let events = StackEvent::find_by_stack(
+        &ctx.db,
+        stack_name,
+        page_size,
+        query.page.saturating_mul(page_size),
+    )
+    .await?;
+    let total = StackEvent::count_by_stack(&ctx.db, stack_name).await?;
+
+    #[derive(Serialize)]
+    struct StackEventsResponse {
+        events: Vec<StackEvent>,
+        total: i64,
+        page: i64,
+        #[serde(rename = "pageSize")]
+        page_size: i64,
+    }
+
+    Ok(CustomResponse::ok_with_fields(StackEventsResponse {
+        events,
+        total,
+        page: query.page,
+        page_size,
+    })
+    .into())
+}
+
+/// Who triggered an action from a socket, for the stack event timeline
+fn event_actor(socket: &SocketRef) -> String {
+    get_username(&socket.id.to_string()).unwrap_or_else(|| "unknown".to_string())
+}
+
+async fn handle_rollback_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: RollbackStackData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let history = StackHistory::find_by_id(&ctx.db, data.history_id)
+        .await?
+        .filter(|h| h.stack_name == data.stack_name)
+        .ok_or_else(|| anyhow!("Snapshot not found"))?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint.clone()).await?;
+
+    // Keep the current revision so the rollback itself can be undone
+    stack.snapshot("rollback").await?;
+    Stack::restore_snapshot(ctx.clone().into(), &history).await?;
+
+    // Reload so the restored compose file name is picked up
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    let result = stack
+        .deploy(&DeployOptions::default(), Some(socket.clone()))
+        .await;
+    stack
+        .record_event("rollback", &event_actor(socket), &result)
+        .await;
+    result?;
+    stack.join_combined_terminal(socket.clone()).await?;
+
+    Ok(())
+}
+
+async fn handle_import_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: ImportStackData,
+) -> Result<()> {
+    let user_id = check_login(socket)?;
+
+    let archive = crate::archive::take_upload(&data.upload_id, user_id)
+        .ok_or_else(|| anyhow!("Upload not found or expired"))?;
+    let passphrase = data.passphrase;
+    let entries = tokio::task::spawn_blocking(move || {
+        let archive = crate::archive::decrypt_archive(archive, passphrase.as_ref())?;
+        crate::archive::read_stack_archive(&archive)
+    })
+    .await??;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::import(ctx.clone().into(), &data.name, endpoint, entries).await?;
+
+    if data.deploy {
+        let result = stack
+            .deploy(&DeployOptions::default(), Some(socket.clone()))
+            .await;
+        stack
+            .record_event("deploy", &event_actor(socket), &result)
+            .await;
+        result?;
+        stack.join_combined_terminal(socket.clone()).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle_export_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+    passphrase: Option<Secret<String>>,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
+    let encrypted = passphrase.is_some();
+    let archive = stack.export(passphrase).await?;
+
+    #[derive(Serialize)]
+    struct ExportStackResponse {
+        filename: String,
+        /// Base64-encoded .tar.gz, encrypted when a passphrase was given
+        archive: String,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ExportStackResponse {
+        filename: crate::archive::archive_filename(stack_name, encrypted),
+        archive: BASE64.encode(archive),
+    })
+    .into())
+}
+
+async fn handle_preview_stack_config(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: PreviewStackConfigData,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let mut stack = match data.content {
+        Some((compose_yaml, compose_env)) => Stack::new_with_content(
+            ctx.clone().into(),
+            data.stack_name,
+            endpoint,
+            compose_yaml,
+            compose_env,
+        ),
+        None => Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?,
+    };
+
+    let preview = stack.preview_config().await?;
+
+    Ok(CustomResponse::ok_with_fields(preview).into())
+}
+
+async fn handle_get_stack_normalized(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let mut stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
+    let normalized = stack.normalized_config().await?;
+
+    Ok(CustomResponse::ok_with_fields(normalized).into())
+}
+
+async fn handle_diff_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: DiffStackData,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    // A stack that isn't saved yet diffs against empty files
+    let mut stack =
+        match Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint.clone()).await {
+            Ok(stack) => stack,
+            Err(_) => Stack::new(ctx.clone().into(), data.stack_name, endpoint),
+        };
+
+    let diff = stack
+        .diff(&data.compose_yaml, &data.compose_env, data.include_config)
+        .await?;
+
+    Ok(CustomResponse::ok_with_fields(diff).into())
+}
+
+async fn handle_set_stack_compose_files(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackComposeFilesData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    stack.set_compose_files(&data.files).await
+}
+
+async fn handle_set_stack_restart_policy(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackRestartPolicyData,
+) -> Result<RestartPolicySet> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    let updated = stack.set_restart_policy(data.policy, data.apply).await?;
+    Ok(RestartPolicySet { updated })
+}
+
+async fn handle_set_stack_docker_context(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackDockerContextData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    stack.set_docker_context(data.context.as_deref()).await
+}
+
+async fn handle_get_docker_contexts(socket: &SocketRef) -> Result<Value> {
+    check_login(socket)?;
+
+    #[derive(Serialize)]
+    struct DockerContextsResponse {
+        contexts: Vec<DockerContext>,
+    }
+
+    let contexts = crate::docker::list_docker_contexts().await?;
+    Ok(CustomResponse::ok_with_fields(DockerContextsResponse { contexts }).into())
+}
+
+async fn handle_set_stack_group(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackGroupData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    StackGroup::set(&ctx.db, &stack.name, data.group.as_deref()).await
+}
+
+async fn handle_set_stack_autostart(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackAutostartData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    if data.enabled && !stack.is_managed_by_dockru().await {
+        return Err(anyhow!(
+            "Only stacks managed by dockru can be started at boot"
+        ));
+    }
+    StackAutostart::set(&ctx.db, &stack.name, data.enabled).await
+}
+
+async fn handle_set_stack_update_window(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackUpdateWindowData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    if data.window.is_some() && !stack.is_managed_by_dockru().await {
+        return Err(anyhow!(
+            "Only stacks managed by dockru can be updated automatically"
+        ));
+    }
+    StackUpdateWindow::set(&ctx.db, &stack.name, data.window.as_ref()).await
+}
+
+/// The calling user's pinned and reordered stacks, as `{stackOrder}`
+async fn stack_order_response(ctx: &ServerContext, user_id: i64) -> Result<serde_json::Value> {
+    #[derive(Serialize)]
+    struct StackOrderResponse {
+        #[serde(rename = "stackOrder")]
+        stack_order: Vec<StackOrder>,
+    }
+
+    let stack_order = StackOrder::find_by_user(&ctx.db, user_id).await?;
+    Ok(CustomResponse::ok_with_fields(StackOrderResponse { stack_order }).into())
+}
+
+async fn handle_get_stack_order(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value> {
+    let user_id = check_login(socket)?;
+    stack_order_response(ctx, user_id).await
+}
+
+async fn handle_set_stack_pinned(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackPinnedData,
+) -> Result<serde_json::Value> {
+    let user_id = check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    StackOrder::set_pinned(&ctx.db, user_id, &stack.name, data.pinned).await?;
+    stack_order_response(ctx, user_id).await
+}
+
+/// Names aren't checked against the stack list; a name that matches no stack
+/// is simply never sorted
+async fn handle_set_stack_order(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_names: Vec<String>,
+) -> Result<serde_json::Value> {
+    let user_id = check_login(socket)?;
+    StackOrder::set_order(&ctx.db, user_id, &stack_names).await?;
+    stack_order_response(ctx, user_id).await
+}
+
+async fn handle_list_groups(socket: &SocketRef, ctx: &ServerContext) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    #[derive(Serialize)]
+    struct ListGroupsResponse {
+        groups: Vec<GroupSummary>,
+    }
+
+    let groups = StackGroup::list(&ctx.db).await?;
+    Ok(CustomResponse::ok_with_fields(ListGroupsResponse { groups }).into())
+}
+
+/// Names of a stack's injected variables; values never leave the server
+async fn handle_get_injected_env(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    stack_name: &str,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
+    let names = stack.injected_env_names().await?;
+
+    #[derive(Serialize)]
+    struct InjectedEnvResponse {
+        names: Vec<String>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(InjectedEnvResponse { names }).into())
+}
+
+async fn handle_set_injected_env(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetInjectedEnvData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    stack.set_injected_env(&data.changes).await
+}
+
+async fn handle_set_stack_dependencies(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: SetStackDependenciesData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint.clone()).await?;
+    for dependency in &data.depends_on {
+        if *dependency == data.stack_name {
+            return Err(anyhow!("A stack can't depend on itself"));
+        }
+        Stack::get_stack(ctx.clone().into(), dependency, endpoint.clone())
+            .await
+            .map_err(|_| anyhow!("Stack {} not found", dependency))?;
+    }
+
+    // Refuse a change that would make deployAll impossible
+    let mut dependencies = StackDependency::find_all(&ctx.db).await?;
+    dependencies.insert(data.stack_name.clone(), data.depends_on.clone());
+    let stacks: Vec<String> = dependencies
+        .iter()
+        .flat_map(|(stack, deps)| std::iter::once(stack).chain(deps))
+        .cloned()
+        .collect();
+    crate::utils::stack_order::dependency_order(&stacks, &dependencies)?;
+
+    StackDependency::set(&ctx.db, &data.stack_name, &data.depends_on).await
+}
+
+async fn handle_run_all(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    action: BatchAction,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let (result, timing) = OperationTiming::measure(Stack::run_all(
+        ctx.clone().into(),
+        endpoint,
+        action,
+        &event_actor(socket),
+        Some(socket.clone()),
+    ))
+    .await;
+    let stacks = result?;
+
+    #[derive(Serialize)]
+    struct RunAllResponse {
+        /// Stacks in the order they ran
+        stacks: Vec<String>,
+        #[serde(flatten)]
+        timing: OperationTiming,
+    }
+
+    Ok(CustomResponse::ok_with_fields(RunAllResponse { stacks, timing }).into())
+}
+
+/// Run an action on several stacks at once, with a summary of how each went
+async fn handle_batch_stack_action(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: BatchStackActionData,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let (results, timing) = OperationTiming::measure(Stack::run_batch(
+        ctx.clone().into(),
+        endpoint,
+        data.stack_names,
+        data.action,
+        event_actor(socket),
+        Some(socket.clone()),
+    ))
+    .await;
+    let failed = results.iter().filter(|r| !r.ok).count();
+
+    #[derive(Serialize)]
+    struct BatchStackActionResponse {
+        action: StackAction,
+        results: Vec<BatchStackResult>,
+        succeeded: usize,
+        failed: usize,
+        #[serde(flatten)]
+        timing: OperationTiming,
+    }
+
+    Ok(CustomResponse::ok_with_fields(BatchStackActionResponse {
+        action: data.action,
+        succeeded: results.len() - failed,
+        failed,
+        results,
+        timing,
+    })
+    .into())
+}
+
+async fn handle_restore_stack_snapshot(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    history_id: i64,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let history = StackHistory::find_by_id(&ctx.db, history_id)
+        .await?
+        .ok_or_else(|| anyhow!("Snapshot not found"))?;
+
+    Stack::restore_snapshot(ctx.clone().into(), &history).await?;
+
+    Ok(())
+}
+
+async fn handle_regenerate_stack_webhook(
     socket: &SocketRef,
     ctx: &ServerContext,
-    data: DeployStackData,
-) -> Result<()> {
+    stack_name: &str,
+) -> Result<serde_json::Value> {
     check_login(socket)?;
 
+    // Make sure the stack exists before handing out a token for it
     let endpoint = get_endpoint(socket);
-    let mut stack = Stack::new_with_content(
-        ctx.clone().into(),
-        data.name.clone(),
-        endpoint,
-        data.compose_yaml,
-        data.compose_env,
-    );
+    Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
 
-    // Validate YAML is parseable
-    stack.compose_yaml().await?;
-    stack.save(data.is_add).await?;
-    stack.deploy(Some(socket.clone())).await?;
+    let token = StackWebhook::regenerate(&ctx.db, stack_name).await?;
 
-    // Join combined terminal to see logs
-    stack.join_combined_terminal(socket.clone()).await?;
+    #[derive(Serialize)]
+    struct WebhookResponse {
+        token: String,
+        path: String,
+    }
 
-    Ok(())
+    Ok(CustomResponse::ok_with_fields(WebhookResponse {
+        token,
+        path: crate::webhook::deploy_path(stack_name),
+    })
+    .into())
 }
 
-async fn handle_save_stack(
+/// Create a link to the stack's logs that works without logging in
+async fn handle_create_share_link(
     socket: &SocketRef,
     ctx: &ServerContext,
-    data: SaveStackData,
-) -> Result<()> {
+    data: CreateShareLinkData,
+) -> Result<serde_json::Value> {
     check_login(socket)?;
 
-    let endpoint = get_endpoint(socket);
-    let mut stack = Stack::new_with_content(
-        ctx.clone().into(),
-        data.name,
-        endpoint,
-        data.compose_yaml,
-        data.compose_env,
+    // Logs are only shared for local stacks, which the REST route reads
+    Stack::get_stack(ctx.clone().into(), &data.stack_name, String::new()).await?;
+
+    let now = chrono::Utc::now().timestamp();
+    let (share_link, token) = ShareLink::create(
+        &ctx.db,
+        &data.stack_name,
+        &event_actor(socket),
+        now + data.minutes * 60,
+        now,
+    )
+    .await?;
+    info!(
+        "Share link to the logs of {} created by {}, expires in {} minutes",
+        data.stack_name, share_link.created_by, data.minutes
     );
 
-    // Validate YAML is parseable
-    stack.compose_yaml().await?;
-    stack.save(data.is_add).await?;
+    #[derive(Serialize)]
+    struct ShareLinkResponse {
+        #[serde(rename = "shareLink")]
+        share_link: ShareLink,
+        token: String,
+        path: String,
+    }
 
-    Ok(())
+    Ok(CustomResponse::ok_with_fields(ShareLinkResponse {
+        share_link,
+        path: format!("/api/share/{}/logs", token),
+        token,
+    })
+    .into())
 }
 
-async fn handle_delete_stack(
+async fn handle_get_share_links(
     socket: &SocketRef,
     ctx: &ServerContext,
     stack_name: &str,
-) -> Result<()> {
+) -> Result<serde_json::Value> {
     check_login(socket)?;
 
-    let endpoint = get_endpoint(socket);
-    let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.delete(Some(socket.clone())).await?;
+    #[derive(Serialize)]
+    struct ShareLinksResponse {
+        #[serde(rename = "shareLinks")]
+        share_links: Vec<ShareLink>,
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let share_links = ShareLink::find_by_stack(&ctx.db, stack_name, now).await?;
+    Ok(CustomResponse::ok_with_fields(ShareLinksResponse { share_links }).into())
+}
+
+/// Revoke a share link; logs already being followed through it stop when it
+/// would have expired
+async fn handle_delete_share_link(socket: &SocketRef, ctx: &ServerContext, id: i64) -> Result<()> {
+    check_login(socket)?;
+
+    if !ShareLink::delete(&ctx.db, id).await? {
+        return Err(anyhow!("Share link not found"));
+    }
 
     Ok(())
 }
 
-async fn handle_get_stack(
+async fn handle_delete_stack_webhook(
     socket: &SocketRef,
     ctx: &ServerContext,
     stack_name: &str,
-) -> Result<serde_json::Value> {
+) -> Result<()> {
     check_login(socket)?;
 
-    let endpoint = get_endpoint(socket);
-    let mut stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint.clone()).await?;
-
-    // Join combined terminal if managed by dockru
-    if stack.is_managed_by_dockru().await {
-        stack.join_combined_terminal(socket.clone()).await.ok();
-    }
-
-    let stack_json = stack.to_json().await?;
-
-    #[derive(Serialize)]
-    struct StackResponse {
-        stack: StackJson,
+    if !StackWebhook::delete(&ctx.db, stack_name).await? {
+        return Err(anyhow!("Stack has no webhook"));
     }
 
-    Ok(CustomResponse::ok_with_fields(StackResponse { stack: stack_json }).into())
+    Ok(())
 }
 
 async fn handle_start_stack(
     socket: &SocketRef,
     ctx: &ServerContext,
-    stack_name: &str,
-) -> Result<()> {
+    data: StartStackData,
+) -> Result<OperationTiming> {
     check_login(socket)?;
 
     let endpoint = get_endpoint(socket);
-    let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.start(Some(socket.clone())).await?;
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    let (result, timing) =
+        OperationTiming::measure(stack.start(&data.options, Some(socket.clone()))).await;
+    stack
+        .record_event("start", &event_actor(socket), &result)
+        .await;
+    result?;
     stack.join_combined_terminal(socket.clone()).await?;
 
-    Ok(())
+    Ok(timing)
 }
 
 async fn handle_stop_stack(
     socket: &SocketRef,
     ctx: &ServerContext,
     stack_name: &str,
-) -> Result<()> {
+) -> Result<OperationTiming> {
     check_login(socket)?;
 
     let endpoint = get_endpoint(socket);
     let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.stop(Some(socket.clone())).await?;
+    let (result, timing) = OperationTiming::measure(stack.stop(Some(socket.clone()))).await;
+    stack
+        .record_event("stop", &event_actor(socket), &result)
+        .await;
+    result?;
 
-    Ok(())
+    Ok(timing)
 }
 
 async fn handle_restart_stack(
     socket: &SocketRef,
     ctx: &ServerContext,
     stack_name: &str,
-) -> Result<()> {
+) -> Result<OperationTiming> {
     check_login(socket)?;
 
     let endpoint = get_endpoint(socket);
     let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.restart(Some(socket.clone())).await?;
+    let (result, timing) = OperationTiming::measure(stack.restart(Some(socket.clone()))).await;
+    stack
+        .record_event("restart", &event_actor(socket), &result)
+        .await;
+    result?;
 
-    Ok(())
+    Ok(timing)
 }
 
+/// Update a stack; the flag rebuilds images instead of pulling them
 async fn handle_update_stack(
     socket: &SocketRef,
     ctx: &ServerContext,
-    stack_name: &str,
-) -> Result<()> {
+    data: StackFlagData,
+) -> Result<OperationTiming> {
     check_login(socket)?;
 
     let endpoint = get_endpoint(socket);
-    let mut stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.update(Some(socket.clone())).await?;
+    let mut stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    let (result, timing) =
+        OperationTiming::measure(stack.update(data.flag, Some(socket.clone()))).await;
+    stack
+        .record_event("update", &event_actor(socket), &result)
+        .await;
+    result?;
 
-    Ok(())
+    Ok(timing)
+}
+
+/// Build a stack's images; the flag builds without the layer cache
+async fn handle_build_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: StackFlagData,
+) -> Result<OperationTiming> {
+    check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    let (result, timing) =
+        OperationTiming::measure(stack.build(data.flag, Some(socket.clone()))).await;
+    result?;
+
+    Ok(timing)
 }
 
 async fn handle_down_stack(
     socket: &SocketRef,
     ctx: &ServerContext,
     stack_name: &str,
-) -> Result<()> {
+) -> Result<OperationTiming> {
     check_login(socket)?;
 
     let endpoint = get_endpoint(socket);
     let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
-    stack.down(Some(socket.clone())).await?;
+    let (result, timing) = OperationTiming::measure(stack.down(Some(socket.clone()))).await;
+    stack
+        .record_event("down", &event_actor(socket), &result)
+        .await;
+    result?;
 
-    Ok(())
+    Ok(timing)
 }
 
 async fn handle_service_status_list(
@@ -826,6 +3438,23 @@ async fn handle_get_docker_network_list(
     .into())
 }
 
+/// The services publishing ports on this host, for hardening it
+async fn handle_get_exposure_report(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let services = crate::exposure::exposure_report(&ctx.docker).await?;
+
+    #[derive(Serialize)]
+    struct ExposureReportResponse {
+        services: Vec<ExposedService>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(ExposureReportResponse { services }).into())
+}
+
 fn parse_service_args(data: &Value) -> Result<(String, String)> {
     let args = data
         .as_array()
@@ -897,11 +3526,125 @@ async fn handle_pull_service(
     Ok(())
 }
 
+/// Broadcast the stack list for requestStackList
+///
+/// Several tabs tend to ask at once, e.g. after a reconnect. Requests within
+/// the debounce window of the last broadcast are answered by it, and
+/// requests made while a broadcast is running wait for it.
+async fn request_stack_list(ctx: &ServerContext) {
+    let mut last = LAST_REQUESTED_STACK_LIST.lock().await;
+    let window = Duration::from_millis(REQUEST_STACK_LIST_DEBOUNCE_MS);
+    if last.is_some_and(|at| at.elapsed() < window) {
+        debug!("requestStackList debounced");
+        return;
+    }
+
+    broadcast_stack_list(ctx).await;
+    *last = Some(Instant::now());
+}
+
+/// Refresh the service status of one stack for a socket until told otherwise
+///
+/// Lets the client viewing a stack see changes faster than the stack list
+/// broadcast. A socket watches one stack at a time; watching another stack,
+/// unwatchStack and disconnecting stop it.
+async fn handle_watch_stack(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: WatchStackData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let socket_id = socket.id.to_string();
+    let socket = socket.clone();
+    let ctx: Arc<ServerContext> = Arc::new(ctx.clone());
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(data.interval_secs));
+        loop {
+            interval.tick().await;
+            if !socket.connected() {
+                break;
+            }
+
+            let endpoint = get_endpoint(&socket);
+            let status = match Stack::get_stack(ctx.clone(), &data.stack_name, endpoint).await {
+                Ok(stack) => stack.get_service_status_list().await,
+                Err(e) => Err(e),
+            };
+            match status {
+                Ok(service_status_list) => {
+                    let data = json!({
+                        "ok": true,
+                        "stackName": data.stack_name,
+                        "serviceStatusList": service_status_list,
+                    });
+                    emit_agent(&socket, server_event::SERVICE_STATUS_LIST, data).ok();
+                }
+                Err(e) => debug!("Watch of {} failed: {}", data.stack_name, e),
+            }
+        }
+    });
+
+    if let Some(previous) = STACK_WATCHERS.lock().await.insert(socket_id, task) {
+        previous.abort();
+    }
+
+    Ok(())
+}
+
+/// Stop a socket's stack watch, if any
+pub async fn stop_stack_watch(socket_id: &str) {
+    if let Some(task) = STACK_WATCHERS.lock().await.remove(socket_id) {
+        task.abort();
+    }
+}
+
 /// Broadcast stack list to all authenticated sockets
-async fn broadcast_stack_list(ctx: &ServerContext) {
+/// One page of the stack list, for clients that don't want all of it
+async fn handle_query_stack_list(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    query: StackListQuery,
+) -> Result<serde_json::Value> {
+    let user_id = check_login(socket)?;
+
+    let endpoint = get_endpoint(socket);
+    let stack_list = Stack::get_stack_list(ctx.clone().into(), endpoint, false).await?;
+    let mut stacks = Vec::with_capacity(stack_list.len());
+    for stack in stack_list.values() {
+        stacks.push(stack.to_simple_json().await);
+    }
+    let stack_order = StackOrder::find_by_user(&ctx.db, user_id).await?;
+    let (stacks, total) = query.apply(stacks, &stack_order);
+
+    #[derive(Serialize)]
+    struct QueryStackListResponse {
+        #[serde(rename = "stackList")]
+        stack_list: Vec<StackSimpleJson>,
+        #[serde(rename = "stackOrder")]
+        stack_order: Vec<StackOrder>,
+        total: usize,
+        page: usize,
+        #[serde(rename = "pageSize")]
+        page_size: usize,
+    }
+
+    Ok(CustomResponse::ok_with_fields(QueryStackListResponse {
+        stack_list: stacks,
+        stack_order,
+        total,
+        page: query.page,
+        page_size: query.page_size(),
+    })
+    .into())
+}
+
+pub(crate) async fn broadcast_stack_list(ctx: &ServerContext) {
     use crate::stack::Stack;
     use std::collections::HashMap;
 
+    crate::cluster::publish(ctx, ClusterEvent::StackList).await;
+
     let ctx_arc = Arc::new(ctx.clone());
     match Stack::get_stack_list(ctx_arc, String::new(), false).await {
         Ok(stack_list) => {
@@ -923,7 +3666,7 @@ async fn broadcast_stack_list(ctx: &ServerContext) {
                 CustomResponse::ok_with_fields(StackListResponse { stack_list: map }).into();
 
             // Broadcast to authenticated sockets only
-            if let Err(e) = broadcast_to_authenticated(&ctx.io, "stackList", response).await {
+            if let Err(e) = broadcast_to_authenticated(&ctx.io, server_event::STACK_LIST, response).await {
                 debug!("Failed to broadcast stack list: {}", e);
             }
         }
@@ -936,6 +3679,424 @@ async fn broadcast_stack_list(ctx: &ServerContext) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::docker::PullPolicy;
+
+    #[test]
+    fn test_parse_deploy_stack_options() {
+        let data = parse_deploy_stack_args(&json!(["web", "services: {}", "", true])).unwrap();
+        assert_eq!(data.options, DeployOptions::default());
+
+        let data = parse_deploy_stack_args(&json!([
+            "web",
+            "services: {}",
+            "",
+            false,
+            { "forceRecreate": true, "pull": "always" }
+        ]))
+        .unwrap();
+        assert!(data.options.force_recreate);
+        assert_eq!(data.options.pull, Some(PullPolicy::Always));
+        assert!(!data.options.no_build);
+
+        let bad = json!(["web", "services: {}", "", false, { "pull": "sometimes" }]);
+        assert!(parse_deploy_stack_args(&bad).is_err());
+    }
+
+    #[test]
+    fn test_parse_stack_flag_args() {
+        let data = parse_stack_flag_args(&json!("web"), "buildStack").unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert!(!data.flag);
+
+        let data = parse_stack_flag_args(&json!(["web", true]), "buildStack").unwrap();
+        assert!(data.flag);
+        let data = parse_stack_flag_args(&json!(["web", null]), "updateStack").unwrap();
+        assert!(!data.flag);
+
+        assert!(parse_stack_flag_args(&json!(["web", "yes"]), "buildStack").is_err());
+        assert!(parse_stack_flag_args(&json!([]), "updateStack").is_err());
+    }
+
+    #[test]
+    fn test_parse_compose_file_name_override() {
+        let data = parse_save_stack_args(&json!([
+            "web",
+            "services: {}",
+            "",
+            true,
+            "docker-compose.yml"
+        ]))
+        .unwrap();
+        assert_eq!(
+            data.compose_file_name.as_deref(),
+            Some("docker-compose.yml")
+        );
+
+        let data = parse_deploy_stack_args(&json!(["web", "services: {}", "", true])).unwrap();
+        assert!(data.compose_file_name.is_none());
+        let data = parse_deploy_stack_args(&json!([
+            "web",
+            "services: {}",
+            "",
+            true,
+            null,
+            "compose.yml"
+        ]))
+        .unwrap();
+        assert_eq!(data.compose_file_name.as_deref(), Some("compose.yml"));
+
+        assert!(parse_save_stack_args(&json!(["web", "", "", true, 1])).is_err());
+    }
+
+    fn simple_stack(name: &str, status: i32) -> StackSimpleJson {
+        StackSimpleJson {
+            name: name.to_string(),
+            status,
+            tags: Vec::new(),
+            group: None,
+            autostart: false,
+            update_window: None,
+            services: None,
+            health: None,
+            ui_hints: None,
+            is_managed_by_dockru: true,
+            compose_file_name: "compose.yaml".to_string(),
+            docker_context: None,
+            endpoint: String::new(),
+            directory_times: None,
+            compose_file_times: None,
+            image_updates: HashMap::new(),
+            update_available: false,
+            pending_update: false,
+        }
+    }
+
+    #[test]
+    fn test_stack_list_query() {
+        let stacks = || {
+            vec![
+                simple_stack("web", 3),
+                simple_stack("db", 4),
+                simple_stack("webhooks", 4),
+                simple_stack("cache", 3),
+            ]
+        };
+        let names = |stacks: Vec<StackSimpleJson>| -> Vec<String> {
+            stacks.into_iter().map(|s| s.name).collect()
+        };
+
+        let (page, total) = StackListQuery::default().apply(stacks(), &[]);
+        assert_eq!(total, 4);
+        assert_eq!(names(page), ["cache", "db", "web", "webhooks"]);
+
+        let query = parse_stack_list_query(&json!({ "search": "WEB", "status": 4 })).unwrap();
+        let (page, total) = query.apply(stacks(), &[]);
+        assert_eq!(total, 1);
+        assert_eq!(names(page), ["webhooks"]);
+
+        let query = parse_stack_list_query(&json!([{ "page": 1, "pageSize": 3 }])).unwrap();
+        let (page, total) = query.apply(stacks(), &[]);
+        assert_eq!(total, 4);
+        assert_eq!(names(page), ["webhooks"]);
+
+        let mut grouped = stacks();
+        grouped[1].group = Some("backend".to_string());
+        let query = parse_stack_list_query(&json!({ "group": "backend" })).unwrap();
+        assert_eq!(names(query.apply(grouped, &[]).0), ["db"]);
+
+        let query = parse_stack_list_query(&json!({ "endpoint": "remote:5001" })).unwrap();
+        assert_eq!(query.apply(stacks(), &[]).1, 0);
+
+        // Pinned stacks first, then the user's order
+        let order = [
+            StackOrder {
+                stack_name: "web".to_string(),
+                pinned: false,
+                position: Some(0),
+            },
+            StackOrder {
+                stack_name: "webhooks".to_string(),
+                pinned: true,
+                position: None,
+            },
+        ];
+        let (page, _) = StackListQuery::default().apply(stacks(), &order);
+        assert_eq!(names(page), ["webhooks", "web", "cache", "db"]);
+
+        assert!(parse_stack_list_query(&json!({ "page": -1 })).is_err());
+        assert!(parse_stack_list_query(&json!(null)).is_ok());
+    }
+
+    #[test]
+    fn test_parse_create_share_link_args() {
+        let data = parse_create_share_link_args(&json!(["web"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.minutes, DEFAULT_SHARE_LINK_MINUTES);
+        assert_eq!(
+            parse_create_share_link_args(&json!(["web", 15]))
+                .unwrap()
+                .minutes,
+            15
+        );
+        assert!(parse_create_share_link_args(&json!(["web", 0])).is_err());
+        assert!(parse_create_share_link_args(&json!(["web", MAX_SHARE_LINK_MINUTES + 1])).is_err());
+        assert!(parse_create_share_link_args(&json!(["../web"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_stack_order_args() {
+        let data = parse_set_stack_pinned_args(&json!(["web", true])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert!(data.pinned);
+        assert!(parse_set_stack_pinned_args(&json!(["web"])).is_err());
+        assert!(parse_set_stack_pinned_args(&json!(["../web", true])).is_err());
+
+        let names = parse_set_stack_order_args(&json!([["db", "web", "db"]])).unwrap();
+        assert_eq!(names, ["db", "web"]);
+        assert!(parse_set_stack_order_args(&json!([[]])).unwrap().is_empty());
+        assert!(parse_set_stack_order_args(&json!(["web"])).is_err());
+        assert!(parse_set_stack_order_args(&json!([["Web!"]])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_group_args() {
+        let data = parse_set_stack_group_args(&json!(["web", " frontend "])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.group.as_deref(), Some("frontend"));
+
+        for group in [json!(null), json!("  ")] {
+            let data = parse_set_stack_group_args(&json!(["web", group])).unwrap();
+            assert_eq!(data.group, None);
+        }
+        assert!(parse_set_stack_group_args(&json!(["web", 1])).is_err());
+        assert!(parse_set_stack_group_args(&json!(["web", "x".repeat(65)])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_update_window_args() {
+        let data = parse_set_stack_update_window_args(&json!(["web", "03:00-05:00"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.window.unwrap().to_string(), "03:00-05:00");
+
+        for window in [json!(null), json!(" ")] {
+            let data = parse_set_stack_update_window_args(&json!(["web", window])).unwrap();
+            assert_eq!(data.window, None);
+        }
+        assert!(parse_set_stack_update_window_args(&json!(["web", "03:00"])).is_err());
+        assert!(parse_set_stack_update_window_args(&json!(["web", 3])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_injected_env_args() {
+        let data =
+            parse_set_injected_env_args(&json!(["web", { "TOKEN": "x", "OLD": null }])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.changes.len(), 2);
+
+        assert!(parse_set_injected_env_args(&json!(["web"])).is_err());
+        assert!(parse_set_injected_env_args(&json!(["web", ["TOKEN"]])).is_err());
+    }
+
+    #[test]
+    fn test_parse_start_stack_args() {
+        let data = parse_start_stack_args(&json!("web")).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.options, DeployOptions::default());
+
+        let data = parse_start_stack_args(&json!(["web", { "noBuild": true }])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert!(data.options.no_build);
+
+        assert!(parse_start_stack_args(&json!([])).is_err());
+        assert!(parse_start_stack_args(&json!(42)).is_err());
+    }
+
+    #[test]
+    fn test_parse_rollback_stack_args() {
+        let data = parse_rollback_stack_args(&json!(["web", 12])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.history_id, 12);
+
+        assert!(parse_rollback_stack_args(&json!(["web", "12"])).is_err());
+        assert!(parse_rollback_stack_args(&json!(["web"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_batch_stack_action_args() {
+        let data = parse_batch_stack_action_args(&json!([["web", "db", "web"], "update"])).unwrap();
+        assert_eq!(data.stack_names, ["web", "db"]);
+        assert_eq!(data.action, StackAction::Update);
+
+        assert!(parse_batch_stack_action_args(&json!([[], "stop"])).is_err());
+        assert!(parse_batch_stack_action_args(&json!([["web"], "restart"])).is_err());
+        assert!(parse_batch_stack_action_args(&json!([["../etc"], "stop"])).is_err());
+        assert!(parse_batch_stack_action_args(&json!(["web", "stop"])).is_err());
+        assert!(parse_batch_stack_action_args(&json!([["web"]])).is_err());
+
+        let too_many: Vec<String> = (0..=MAX_BATCH_STACKS).map(|i| format!("s{}", i)).collect();
+        assert!(parse_batch_stack_action_args(&json!([too_many, "stop"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_stack_events_args() {
+        let (stack_name, query) = parse_stack_events_args(&json!(["web"])).unwrap();
+        assert_eq!(stack_name, "web");
+        assert_eq!(query.page, 0);
+        assert_eq!(query.page_size(), DEFAULT_STACK_EVENT_PAGE_SIZE);
+
+        let (_, query) =
+            parse_stack_events_args(&json!(["web", { "page": 2, "pageSize": 1000 }])).unwrap();
+        assert_eq!(query.page, 2);
+        assert_eq!(query.page_size(), MAX_STACK_EVENT_PAGE_SIZE);
+
+        assert!(parse_stack_events_args(&json!([])).is_err());
+        assert!(parse_stack_events_args(&json!(["web", { "page": -1 }])).is_err());
+        assert!(parse_stack_events_args(&json!(["web", { "page": "x" }])).is_err());
+    }
+
+    #[test]
+    fn test_parse_diff_stack_args() {
+        let data = parse_diff_stack_args(&json!(["web", "services: {}\n", "A=1"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.compose_env, "A=1");
+        assert!(!data.include_config);
+
+        let data = parse_diff_stack_args(&json!(["web", "", "", true])).unwrap();
+        assert!(data.include_config);
+
+        assert!(parse_diff_stack_args(&json!(["web", "services: {}\n"])).is_err());
+        assert!(parse_diff_stack_args(&json!(["../web", "", ""])).is_err());
+    }
+
+    #[test]
+    fn test_parse_preview_stack_config_args() {
+        let data = parse_preview_stack_config_args(&json!(["web"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert!(data.content.is_none());
+
+        let data =
+            parse_preview_stack_config_args(&json!(["web", "services: {}\n", "A=1"])).unwrap();
+        assert_eq!(
+            data.content,
+            Some(("services: {}\n".to_string(), "A=1".to_string()))
+        );
+
+        let data = parse_preview_stack_config_args(&json!(["web", "services: {}\n"])).unwrap();
+        assert_eq!(data.content.unwrap().1, "");
+
+        assert!(parse_preview_stack_config_args(&json!([])).is_err());
+        assert!(parse_preview_stack_config_args(&json!(["web", 1])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_restart_policy_args() {
+        let data =
+            parse_set_stack_restart_policy_args(&json!(["web", "unless-stopped"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.policy, RestartPolicy::UnlessStopped);
+        assert!(!data.apply);
+
+        let data =
+            parse_set_stack_restart_policy_args(&json!(["web", "no", { "apply": true }])).unwrap();
+        assert_eq!(data.policy, RestartPolicy::No);
+        assert!(data.apply);
+
+        assert!(parse_set_stack_restart_policy_args(&json!(["web", "sometimes"])).is_err());
+        assert!(parse_set_stack_restart_policy_args(&json!(["web"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_docker_context_args() {
+        let data = parse_set_stack_docker_context_args(&json!(["web", "nas"])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.context.as_deref(), Some("nas"));
+
+        let data = parse_set_stack_docker_context_args(&json!(["web", null])).unwrap();
+        assert!(data.context.is_none());
+        let data = parse_set_stack_docker_context_args(&json!(["web", ""])).unwrap();
+        assert!(data.context.is_none());
+
+        assert!(parse_set_stack_docker_context_args(&json!(["web"])).is_err());
+        assert!(parse_set_stack_docker_context_args(&json!(["web", 1])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_compose_files_args() {
+        let data = parse_set_stack_compose_files_args(&json!([
+            "web",
+            ["compose.yaml", "compose.prod.yaml"]
+        ]))
+        .unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.files, vec!["compose.yaml", "compose.prod.yaml"]);
+
+        let data = parse_set_stack_compose_files_args(&json!(["web", []])).unwrap();
+        assert!(data.files.is_empty());
+
+        assert!(parse_set_stack_compose_files_args(&json!(["web"])).is_err());
+        assert!(parse_set_stack_compose_files_args(&json!(["web", [1]])).is_err());
+    }
+
+    #[test]
+    fn test_parse_set_stack_dependencies_args() {
+        let data = parse_set_stack_dependencies_args(&json!(["app", ["proxy", "db"]])).unwrap();
+        assert_eq!(data.stack_name, "app");
+        assert_eq!(data.depends_on, vec!["proxy", "db"]);
+
+        assert!(parse_set_stack_dependencies_args(&json!(["app"])).is_err());
+        assert!(parse_set_stack_dependencies_args(&json!(["app", "proxy"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_watch_stack_args() {
+        let data = parse_watch_stack_args(&json!(["web", 3])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert_eq!(data.interval_secs, 3);
+
+        // Clamped to the fastest allowed refresh
+        let data = parse_watch_stack_args(&json!(["web", 0])).unwrap();
+        assert_eq!(data.interval_secs, MIN_WATCH_STACK_REFRESH_SECS);
+
+        assert!(parse_watch_stack_args(&json!(["web"])).is_err());
+        assert!(parse_watch_stack_args(&json!(["web", -1])).is_err());
+    }
+
+    #[test]
+    fn test_parse_import_stack_args() {
+        let data = parse_import_stack_args(&json!(["abc123", "web", true])).unwrap();
+        assert_eq!(data.upload_id, "abc123");
+        assert_eq!(data.name, "web");
+        assert!(data.deploy);
+
+        let data = parse_import_stack_args(&json!(["abc123", "web"])).unwrap();
+        assert!(!data.deploy);
+
+        assert!(parse_import_stack_args(&json!(["abc123"])).is_err());
+
+        let data =
+            parse_import_stack_args(&json!(["abc123", "web", false, "correct horse"])).unwrap();
+        assert_eq!(
+            data.passphrase.unwrap().expose_secret(),
+            "correct horse"
+        );
+        let data = parse_import_stack_args(&json!(["abc123", "web", false, ""])).unwrap();
+        assert!(data.passphrase.is_none());
+        assert!(parse_import_stack_args(&json!(["abc123", "web", false, 1])).is_err());
+    }
+
+    #[test]
+    fn test_parse_export_stack_args() {
+        let (name, passphrase) = parse_export_stack_args(&json!("web")).unwrap();
+        assert_eq!(name, "web");
+        assert!(passphrase.is_none());
+
+        let (name, passphrase) =
+            parse_export_stack_args(&json!(["web", "correct horse"])).unwrap();
+        assert_eq!(name, "web");
+        assert_eq!(passphrase.unwrap().expose_secret(), "correct horse");
+
+        assert!(parse_export_stack_args(&json!([])).is_err());
+    }
 
     #[test]
     fn test_deploy_stack_data_deserialize() {
@@ -0,0 +1,148 @@
+// Container stats subscriptions
+//
+//   streamContainerStats [stackName, serviceName?]  -> containerStats events
+//   stopContainerStats   [stackName?, serviceName?] -> without args stops all
+//
+// See container_stats for what the stream sends.
+
+use crate::container_stats::{self, StreamKey};
+use crate::server::ServerContext;
+use crate::socket_handlers::{callback_error, callback_ok, check_login, spawn_handler};
+use crate::utils::stack_name::StackName;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::sync::Arc;
+
+pub fn setup_stats_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    // streamContainerStats
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "streamContainerStats",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("streamContainerStats", ack, |ack| async move {
+                match handle_stream_container_stats(&socket, &ctx, &data) {
+                    Ok(_) => callback_ok(ack.take(), "Streaming", false),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // stopContainerStats
+    socket.on(
+        "stopContainerStats",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            spawn_handler("stopContainerStats", ack, |ack| async move {
+                match handle_stop_container_stats(&socket, &data) {
+                    Ok(_) => callback_ok(ack.take(), "Stopped", false),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+}
+
+/// Dispatch a stats event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_stats_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    let data = Value::Array(event_args.to_vec());
+    match event_name {
+        "streamContainerStats" => {
+            match handle_stream_container_stats(socket, ctx, &data) {
+                Ok(_) => callback_ok(ack.take(), "Streaming", false),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "stopContainerStats" => {
+            match handle_stop_container_stats(socket, &data) {
+                Ok(_) => callback_ok(ack.take(), "Stopped", false),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Parse [stackName, serviceName?]; None when no stack is given
+fn parse_stream_key(data: &Value) -> Result<Option<StreamKey>> {
+    let args = match data {
+        Value::Array(args) => args.as_slice(),
+        Value::Null => &[],
+        _ => return Err(anyhow!("Expected array of arguments")),
+    };
+    let Some(stack) = args.first() else {
+        return Ok(None);
+    };
+    let stack = stack
+        .as_str()
+        .ok_or_else(|| anyhow!("stackName must be a string"))?;
+    let service = match args.get(1) {
+        None | Some(Value::Null) => None,
+        Some(service) => Some(
+            service
+                .as_str()
+                .ok_or_else(|| anyhow!("serviceName must be a string"))?
+                .to_string(),
+        ),
+    };
+    Ok(Some(StreamKey {
+        stack: StackName::parse(stack)?.into(),
+        service,
+    }))
+}
+
+fn handle_stream_container_stats(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: &Value,
+) -> Result<()> {
+    check_login(socket)?;
+    let key = parse_stream_key(data)?
+        .ok_or_else(|| anyhow!("streamContainerStats requires a stack name"))?;
+    container_stats::subscribe(&ctx.docker, socket, key);
+    Ok(())
+}
+
+fn handle_stop_container_stats(socket: &SocketRef, data: &Value) -> Result<()> {
+    check_login(socket)?;
+    let socket_id = socket.id.to_string();
+    match parse_stream_key(data)? {
+        Some(key) => container_stats::unsubscribe(&socket_id, &key),
+        None => container_stats::unsubscribe_all(&socket_id),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_stream_key() {
+        let key = parse_stream_key(&json!(["web", "nginx"])).unwrap().unwrap();
+        assert_eq!(key.stack, "web");
+        assert_eq!(key.service.as_deref(), Some("nginx"));
+
+        let key = parse_stream_key(&json!(["web"])).unwrap().unwrap();
+        assert_eq!(key.service, None);
+        let key = parse_stream_key(&json!(["web", null])).unwrap().unwrap();
+        assert_eq!(key.service, None);
+
+        assert!(parse_stream_key(&json!([])).unwrap().is_none());
+        assert!(parse_stream_key(&Value::Null).unwrap().is_none());
+        assert!(parse_stream_key(&json!(["../etc"])).is_err());
+        assert!(parse_stream_key(&json!([1])).is_err());
+        assert!(parse_stream_key(&json!(["web", 1])).is_err());
+    }
+}
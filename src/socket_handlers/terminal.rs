@@ -1,14 +1,30 @@
+use crate::db::models::User;
 use crate::server::ServerContext;
-use crate::socket_handlers::{callback_error, check_login, get_endpoint};
+use crate::socket_handlers::{
+    callback_error, callback_ok, check_login, emit_agent, get_endpoint, get_username,
+    spawn_handler,
+};
+use crate::docker::ExecOptions;
 use crate::stack::Stack;
-use crate::terminal::{Terminal, TerminalType};
+use crate::rate_limiter::TerminalResizeRateLimiter;
+use crate::terminal::{Terminal, TerminalClient, TerminalType};
+use crate::terminal_audit;
+use crate::utils::constants::{
+    MAX_TERMINAL_COLS, MAX_TERMINAL_ROWS, MIN_TERMINAL_COLS, MIN_TERMINAL_ROWS,
+};
+use crate::utils::log_timestamps::LogOptions;
 use crate::utils::types::{BaseRes, CustomResponse};
 use anyhow::{anyhow, Result};
+use dockru_protocol::events::server as server_event;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use socketioxide::extract::{AckSender, Data, SocketRef};
 use std::sync::Arc;
-use tracing::{debug, info};
+use tracing::debug;
+
+/// Shared limiter so resize floods are dropped before touching the PTY
+static RESIZE_RATE_LIMITER: once_cell::sync::Lazy<TerminalResizeRateLimiter> =
+    once_cell::sync::Lazy::new(TerminalResizeRateLimiter::new);
 
 #[derive(Debug, Deserialize)]
 struct TerminalInputData {
@@ -24,6 +40,9 @@ struct InteractiveTerminalData {
     #[serde(rename = "serviceName")]
     service_name: String,
     shell: String,
+    /// User and working directory to exec as
+    #[serde(default)]
+    options: ExecOptions,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,6 +51,26 @@ struct ContainerLogsData {
     stack_name: String,
     #[serde(rename = "serviceName")]
     service_name: String,
+    /// Raw log options, resolved against the user's timezone in the handler
+    options: Option<Value>,
+}
+
+#[derive(Debug)]
+struct LeaveContainerLogsData {
+    stack_name: String,
+    service_name: String,
+}
+
+#[derive(Debug)]
+struct CombinedLogsData {
+    stack_name: String,
+    options: Option<Value>,
+}
+
+#[derive(Debug)]
+struct KickTerminalClientData {
+    terminal_name: String,
+    socket_id: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -50,14 +89,14 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "terminalInput",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("terminalInput", ack, |ack| async move {
                 match parse_terminal_input_args(&data) {
                     Ok(parsed) => {
                         if let Err(e) = handle_terminal_input(&socket, &ctx, parsed).await {
-                            callback_error(Some(ack), e);
+                            callback_error(ack.take(), e);
                         }
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -69,12 +108,12 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "mainTerminal",
         async move |socket: SocketRef, Data::<String>(terminal_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("mainTerminal", ack, |ack| async move {
                 match handle_main_terminal(&socket, &ctx, terminal_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
@@ -86,12 +125,12 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "checkMainTerminal",
         async move |socket: SocketRef, ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("checkMainTerminal", ack, |ack| async move {
                 match handle_check_main_terminal(&socket, &ctx).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
@@ -103,15 +142,15 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "interactiveTerminal",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("interactiveTerminal", ack, |ack| async move {
                 match parse_interactive_terminal_args(&data) {
                     Ok(parsed) => match handle_interactive_terminal(&socket, &ctx, parsed).await {
                         Ok(response) => {
                             ack.send(&response).ok();
                         }
-                        Err(e) => callback_error(Some(ack), e),
+                        Err(e) => callback_error(ack.take(), e),
                     },
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -123,15 +162,35 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "containerLogsTerminal",
         async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("containerLogsTerminal", ack, |ack| async move {
                 match parse_container_logs_args(&data) {
                     Ok(parsed) => match handle_container_logs_terminal(&socket, &ctx, parsed).await {
                         Ok(response) => {
                             ack.send(&response).ok();
                         }
-                        Err(e) => callback_error(Some(ack), e),
+                        Err(e) => callback_error(ack.take(), e),
                     },
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
+    // combinedLogsTerminal
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "combinedLogsTerminal",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("combinedLogsTerminal", ack, |ack| async move {
+                match parse_combined_logs_args(&data) {
+                    Ok(parsed) => match handle_combined_logs_terminal(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
                 }
             });
         },
@@ -143,34 +202,90 @@ pub fn setup_terminal_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
         "terminalJoin",
         async move |socket: SocketRef, Data::<String>(terminal_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("terminalJoin", ack, |ack| async move {
                 match handle_terminal_join(&socket, &ctx, terminal_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
     );
 
+    // getTerminalClients
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "getTerminalClients",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("getTerminalClients", ack, |ack| async move {
+                let terminal_name = data.as_str().map(|s| s.to_string());
+                match handle_get_terminal_clients(&socket, &ctx, terminal_name.as_deref()).await {
+                    Ok(response) => {
+                        ack.send(&response).ok();
+                    }
+                    Err(e) => callback_error(ack.take(), e),
+                };
+            });
+        },
+    );
+
+    // kickTerminalClient
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "kickTerminalClient",
+        async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("kickTerminalClient", ack, |ack| async move {
+                match parse_kick_terminal_client_args(&data) {
+                    Ok(parsed) => match handle_kick_terminal_client(&socket, &ctx, parsed).await {
+                        Ok(_) => callback_ok(ack.take(), "Disconnected", true),
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
     // leaveCombinedTerminal
     let ctx_clone = ctx.clone();
     socket.on(
         "leaveCombinedTerminal",
         async move |socket: SocketRef, Data::<String>(stack_name), ack: AckSender| {
             let ctx = ctx_clone.clone();
-            tokio::spawn(async move {
+            spawn_handler("leaveCombinedTerminal", ack, |ack| async move {
                 match handle_leave_combined_terminal(&socket, &ctx, &stack_name).await {
                     Ok(response) => {
                         ack.send(&response).ok();
                     }
-                    Err(e) => callback_error(Some(ack), e),
+                    Err(e) => callback_error(ack.take(), e),
                 };
             });
         },
     );
 
+    // leaveContainerLogs
+    let ctx_clone = ctx.clone();
+    socket.on(
+        "leaveContainerLogs",
+        async move |socket: SocketRef, Data::<serde_json::Value>(data), ack: AckSender| {
+            let ctx = ctx_clone.clone();
+            spawn_handler("leaveContainerLogs", ack, |ack| async move {
+                match parse_leave_container_logs_args(&data) {
+                    Ok(parsed) => match handle_leave_container_logs(&socket, &ctx, parsed).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    },
+                    Err(e) => callback_error(ack.take(), e),
+                }
+            });
+        },
+    );
+
     // terminalResize
     let ctx_clone = ctx.clone();
     socket.on(
@@ -213,7 +328,7 @@ fn parse_terminal_input_args(data: &Value) -> Result<TerminalInputData> {
     })
 }
 
-/// Parse interactiveTerminal positional args: [stackName, serviceName, shell]
+/// Parse interactiveTerminal positional args: [stackName, serviceName, shell, options?]
 fn parse_interactive_terminal_args(data: &Value) -> Result<InteractiveTerminalData> {
     let args = data
         .as_array()
@@ -236,10 +351,15 @@ fn parse_interactive_terminal_args(data: &Value) -> Result<InteractiveTerminalDa
             .as_str()
             .ok_or_else(|| anyhow!("shell must be a string"))?
             .to_string(),
+        options: match args.get(3) {
+            None | Some(Value::Null) => ExecOptions::default(),
+            Some(options) => serde_json::from_value(options.clone())
+                .map_err(|e| anyhow!("Invalid exec options: {}", e))?,
+        },
     })
 }
 
-/// Parse containerLogsTerminal positional args: [stackName, serviceName]
+/// Parse containerLogsTerminal positional args: [stackName, serviceName, options?]
 fn parse_container_logs_args(data: &Value) -> Result<ContainerLogsData> {
     let args = data
         .as_array()
@@ -258,6 +378,39 @@ fn parse_container_logs_args(data: &Value) -> Result<ContainerLogsData> {
             .as_str()
             .ok_or_else(|| anyhow!("serviceName must be a string"))?
             .to_string(),
+        options: args.get(2).cloned(),
+    })
+}
+
+/// Parse leaveContainerLogs positional args: [stackName, serviceName]
+fn parse_leave_container_logs_args(data: &Value) -> Result<LeaveContainerLogsData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let arg = |i: usize, name: &str| {
+        args.get(i)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{} must be a string", name))
+    };
+    Ok(LeaveContainerLogsData {
+        stack_name: arg(0, "stackName")?,
+        service_name: arg(1, "serviceName")?,
+    })
+}
+
+/// Parse combinedLogsTerminal positional args: [stackName, options?]
+fn parse_combined_logs_args(data: &Value) -> Result<CombinedLogsData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    let stack_name = args
+        .first()
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("combinedLogsTerminal requires a stack name"))?;
+    Ok(CombinedLogsData {
+        stack_name: stack_name.to_string(),
+        options: args.get(1).cloned(),
     })
 }
 
@@ -276,12 +429,47 @@ fn parse_terminal_resize_args(data: &Value) -> Result<TerminalResizeData> {
             .as_str()
             .ok_or_else(|| anyhow!("terminalName must be a string"))?
             .to_string(),
-        rows: args[1]
-            .as_u64()
-            .ok_or_else(|| anyhow!("rows must be a number"))? as u16,
-        cols: args[2]
-            .as_u64()
-            .ok_or_else(|| anyhow!("cols must be a number"))? as u16,
+        rows: clamp_dimension(
+            args[1]
+                .as_u64()
+                .ok_or_else(|| anyhow!("rows must be a number"))?,
+            MIN_TERMINAL_ROWS,
+            MAX_TERMINAL_ROWS,
+        ),
+        cols: clamp_dimension(
+            args[2]
+                .as_u64()
+                .ok_or_else(|| anyhow!("cols must be a number"))?,
+            MIN_TERMINAL_COLS,
+            MAX_TERMINAL_COLS,
+        ),
+    })
+}
+
+/// Clamp a client-supplied terminal dimension into [min, max]
+fn clamp_dimension(value: u64, min: u16, max: u16) -> u16 {
+    value.clamp(min as u64, max as u64) as u16
+}
+
+/// Parse kickTerminalClient positional args: [terminalName, socketId]
+fn parse_kick_terminal_client_args(data: &Value) -> Result<KickTerminalClientData> {
+    let args = data
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected array of arguments"))?;
+    if args.len() < 2 {
+        return Err(anyhow!(
+            "kickTerminalClient requires 2 arguments: terminalName, socketId"
+        ));
+    }
+    Ok(KickTerminalClientData {
+        terminal_name: args[0]
+            .as_str()
+            .ok_or_else(|| anyhow!("terminalName must be a string"))?
+            .to_string(),
+        socket_id: args[1]
+            .as_str()
+            .ok_or_else(|| anyhow!("socketId must be a string"))?
+            .to_string(),
     })
 }
 
@@ -353,6 +541,38 @@ pub(crate) async fn dispatch_terminal_event(
             }
             Ok(true)
         }
+        "combinedLogsTerminal" => {
+            let data = parse_combined_logs_args(&json!(event_args))?;
+            match handle_combined_logs_terminal(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "getTerminalClients" => {
+            let terminal_name = event_args.first().and_then(|v| v.as_str());
+            match handle_get_terminal_clients(socket, ctx, terminal_name).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
+        "kickTerminalClient" => {
+            let data = parse_kick_terminal_client_args(&json!(event_args))?;
+            match handle_kick_terminal_client(socket, ctx, data).await {
+                Ok(_) => callback_ok(ack.take(), "Disconnected", true),
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
         "terminalJoin" => {
             let terminal_name = event_args
                 .first()
@@ -384,6 +604,18 @@ pub(crate) async fn dispatch_terminal_event(
             }
             Ok(true)
         }
+        "leaveContainerLogs" => {
+            let data = parse_leave_container_logs_args(&json!(event_args))?;
+            match handle_leave_container_logs(socket, ctx, data).await {
+                Ok(response) => {
+                    if let Some(ack) = ack.take() {
+                        ack.send(&response).ok();
+                    }
+                }
+                Err(e) => callback_error(ack.take(), e),
+            }
+            Ok(true)
+        }
         "terminalResize" => {
             let data = parse_terminal_resize_args(&json!(event_args))?;
             if let Err(e) = handle_terminal_resize(socket, ctx, data).await {
@@ -397,7 +629,7 @@ pub(crate) async fn dispatch_terminal_event(
 
 async fn handle_terminal_input(
     socket: &SocketRef,
-    _ctx: &ServerContext,
+    ctx: &ServerContext,
     data: TerminalInputData,
 ) -> Result<()> {
     check_login(socket)?;
@@ -410,6 +642,11 @@ async fn handle_terminal_input(
     if terminal.terminal_type() == TerminalType::Interactive
         || terminal.terminal_type() == TerminalType::Main
     {
+        // Input that can't be recorded isn't written either
+        if terminal_audit::is_enabled(ctx).await {
+            let actor = get_username(&socket.id.to_string()).unwrap_or_else(|| "unknown".into());
+            terminal_audit::record_input(ctx, &terminal, &actor, &data.cmd).await?;
+        }
         terminal.write(&data.cmd).await?;
     } else {
         return Err(anyhow!("Terminal is not interactive"));
@@ -501,11 +738,11 @@ async fn handle_interactive_terminal(
     };
 
     // Join container terminal (index 0 for first connection)
-    stack
-        .join_container_terminal(socket.clone(), &data.service_name, &shell, 0)
+    let terminal_name = stack
+        .join_container_terminal(socket.clone(), &data.service_name, &shell, &data.options, 0)
         .await?;
 
-    Ok(BaseRes::ok().into())
+    Ok(CustomResponse::ok_with_fields(TerminalNameResponse { terminal_name }).into())
 }
 
 async fn handle_container_logs_terminal(
@@ -513,21 +750,62 @@ async fn handle_container_logs_terminal(
     ctx: &ServerContext,
     data: ContainerLogsData,
 ) -> Result<serde_json::Value> {
-    check_login(socket)?;
+    let user_id = check_login(socket)?;
 
     debug!(
         "Container logs terminal - Stack: {}, Service: {}",
         data.stack_name, data.service_name
     );
 
+    let log_options = resolve_log_options(ctx, user_id, data.options.as_ref()).await?;
     let endpoint = get_endpoint(socket);
     let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
 
-    stack
-        .join_container_logs(socket.clone(), &data.service_name)
+    let terminal_name = stack
+        .join_container_logs(socket.clone(), &data.service_name, &log_options)
         .await?;
 
-    Ok(BaseRes::ok().into())
+    Ok(CustomResponse::ok_with_fields(TerminalNameResponse { terminal_name }).into())
+}
+
+async fn handle_combined_logs_terminal(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: CombinedLogsData,
+) -> Result<serde_json::Value> {
+    let user_id = check_login(socket)?;
+
+    let log_options = resolve_log_options(ctx, user_id, data.options.as_ref()).await?;
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+
+    let terminal_name = stack
+        .join_combined_terminal_with_options(socket.clone(), &log_options)
+        .await?;
+
+    Ok(CustomResponse::ok_with_fields(TerminalNameResponse { terminal_name }).into())
+}
+
+#[derive(Serialize)]
+struct TerminalNameResponse {
+    #[serde(rename = "terminalName")]
+    terminal_name: String,
+}
+
+/// Parse log options, using the user's saved timezone for `"timezone": "auto"`
+async fn resolve_log_options(
+    ctx: &ServerContext,
+    user_id: i64,
+    options: Option<&Value>,
+) -> Result<LogOptions> {
+    let user_timezone = match options {
+        Some(value) if value["timezone"] == "auto" => User::find_by_id(&ctx.db, user_id)
+            .await?
+            .and_then(|user| user.timezone),
+        _ => None,
+    };
+
+    LogOptions::from_value(options, user_timezone.as_deref())
 }
 
 async fn handle_terminal_join(
@@ -554,6 +832,92 @@ async fn handle_terminal_join(
     Ok(CustomResponse::ok_with_fields(TerminalJoinResponse { buffer }).into())
 }
 
+async fn handle_get_terminal_clients(
+    socket: &SocketRef,
+    _ctx: &ServerContext,
+    terminal_name: Option<&str>,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    let terminals = match terminal_name {
+        Some(name) => Terminal::get_terminal(name).await.into_iter().collect(),
+        None => Terminal::get_all_terminals().await,
+    };
+
+    #[derive(Serialize)]
+    struct TerminalClients {
+        #[serde(rename = "terminalName")]
+        terminal_name: String,
+        #[serde(rename = "type")]
+        terminal_type: &'static str,
+        clients: Vec<TerminalClient>,
+        #[serde(rename = "bufferBytes")]
+        buffer_bytes: usize,
+    }
+
+    let mut list = Vec::with_capacity(terminals.len());
+    for terminal in terminals {
+        list.push(TerminalClients {
+            terminal_name: terminal.name().to_string(),
+            terminal_type: terminal.terminal_type().as_str(),
+            clients: terminal.clients().await,
+            buffer_bytes: terminal.buffer_size().await,
+        });
+    }
+    list.sort_by(|a, b| a.terminal_name.cmp(&b.terminal_name));
+
+    #[derive(Serialize)]
+    struct TerminalClientsResponse {
+        terminals: Vec<TerminalClients>,
+    }
+
+    Ok(CustomResponse::ok_with_fields(TerminalClientsResponse { terminals: list }).into())
+}
+
+async fn handle_kick_terminal_client(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: KickTerminalClientData,
+) -> Result<()> {
+    check_login(socket)?;
+
+    let terminal = Terminal::get_terminal(&data.terminal_name)
+        .await
+        .ok_or_else(|| anyhow!("Terminal not found"))?;
+    if !terminal
+        .clients()
+        .await
+        .iter()
+        .any(|c| c.socket_id == data.socket_id)
+    {
+        return Err(anyhow!("Client is not attached to this terminal"));
+    }
+
+    let sid = data
+        .socket_id
+        .parse()
+        .map_err(|_| anyhow!("Invalid socket id"))?;
+    let target = ctx
+        .io
+        .get_socket(sid)
+        .ok_or_else(|| anyhow!("Client is no longer connected"))?;
+
+    terminal.leave(target.clone()).await?;
+    emit_agent(
+        &target,
+        server_event::TERMINAL_KICKED,
+        json!({ "terminalName": data.terminal_name }),
+    )
+    .ok();
+
+    debug!(
+        "Socket {} kicked {} from terminal {}",
+        socket.id, data.socket_id, data.terminal_name
+    );
+
+    Ok(())
+}
+
 async fn handle_leave_combined_terminal(
     socket: &SocketRef,
     ctx: &ServerContext,
@@ -570,6 +934,27 @@ async fn handle_leave_combined_terminal(
     Ok(BaseRes::ok().into())
 }
 
+async fn handle_leave_container_logs(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    data: LeaveContainerLogsData,
+) -> Result<serde_json::Value> {
+    check_login(socket)?;
+
+    debug!(
+        "Leave container logs - Stack: {}, Service: {}",
+        data.stack_name, data.service_name
+    );
+
+    let endpoint = get_endpoint(socket);
+    let stack = Stack::get_stack(ctx.clone().into(), &data.stack_name, endpoint).await?;
+    stack
+        .leave_container_logs(socket.clone(), &data.service_name)
+        .await?;
+
+    Ok(BaseRes::ok().into())
+}
+
 async fn handle_terminal_resize(
     socket: &SocketRef,
     _ctx: &ServerContext,
@@ -577,14 +962,31 @@ async fn handle_terminal_resize(
 ) -> Result<()> {
     check_login(socket)?;
 
-    info!(
+    if !RESIZE_RATE_LIMITER.check(&socket.id.to_string()) {
+        debug!("Dropping terminalResize from {} (rate limited)", socket.id);
+        return Ok(());
+    }
+
+    // Only sockets attached to the terminal may resize it
+    if !socket
+        .rooms()
+        .iter()
+        .any(|room| room.as_ref() == data.terminal_name)
+    {
+        return Err(anyhow!(
+            "Socket {} has not joined terminal {}",
+            socket.id,
+            data.terminal_name
+        ));
+    }
+
+    debug!(
         "Terminal resize: {} ({}x{})",
         data.terminal_name, data.rows, data.cols
     );
 
     if let Some(terminal) = Terminal::get_terminal(&data.terminal_name).await {
-        terminal.set_rows(data.rows).await?;
-        terminal.set_cols(data.cols).await?;
+        terminal.resize(data.rows, data.cols).await?;
     } else {
         return Err(anyhow!("Terminal {} not found", data.terminal_name));
     }
@@ -625,6 +1027,16 @@ fn get_shell_args(shell: &str) -> Vec<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_kick_terminal_client_args() {
+        let data = parse_kick_terminal_client_args(&json!(["console", "abc123"])).unwrap();
+        assert_eq!(data.terminal_name, "console");
+        assert_eq!(data.socket_id, "abc123");
+
+        assert!(parse_kick_terminal_client_args(&json!(["console"])).is_err());
+        assert!(parse_kick_terminal_client_args(&json!(["console", 1])).is_err());
+    }
+
     #[test]
     fn test_terminal_input_deserialize() {
         let json = r#"{"terminalName": "test-term", "cmd": "ls -la\n"}"#;
@@ -644,6 +1056,24 @@ mod tests {
         assert_eq!(data.stack_name, "my-stack");
         assert_eq!(data.service_name, "web");
         assert_eq!(data.shell, "/bin/bash");
+        assert_eq!(data.options, ExecOptions::default());
+    }
+
+    #[test]
+    fn test_parse_interactive_terminal_args() {
+        let data = parse_interactive_terminal_args(&json!([
+            "my-stack",
+            "web",
+            "sh",
+            { "user": "root", "workdir": "/app" }
+        ]))
+        .unwrap();
+        assert_eq!(data.options.user.as_deref(), Some("root"));
+        assert_eq!(data.options.workdir.as_deref(), Some("/app"));
+
+        let data = parse_interactive_terminal_args(&json!(["my-stack", "web", "sh"])).unwrap();
+        assert_eq!(data.options, ExecOptions::default());
+        assert!(parse_interactive_terminal_args(&json!(["my-stack", "web", "sh", 1])).is_err());
     }
 
     #[test]
@@ -654,4 +1084,34 @@ mod tests {
         assert_eq!(data.rows, 50);
         assert_eq!(data.cols, 120);
     }
+
+    #[test]
+    fn test_parse_logs_args_options() {
+        let data = parse_container_logs_args(&json!(["web", "db"])).unwrap();
+        assert!(data.options.is_none());
+
+        let data =
+            parse_container_logs_args(&json!(["web", "db", {"timestamps": true}])).unwrap();
+        assert_eq!(data.options, Some(json!({"timestamps": true})));
+
+        let data = parse_combined_logs_args(&json!(["web", {"timezone": "auto"}])).unwrap();
+        assert_eq!(data.stack_name, "web");
+        assert!(data.options.is_some());
+        assert!(parse_combined_logs_args(&json!([])).is_err());
+
+        let data = parse_leave_container_logs_args(&json!(["web", "db"])).unwrap();
+        assert_eq!((data.stack_name.as_str(), data.service_name.as_str()), ("web", "db"));
+        assert!(parse_leave_container_logs_args(&json!(["web"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_terminal_resize_args_clamps() {
+        let data = parse_terminal_resize_args(&json!(["console", 0, 70000])).unwrap();
+        assert_eq!(data.rows, MIN_TERMINAL_ROWS);
+        assert_eq!(data.cols, MAX_TERMINAL_COLS);
+
+        let data = parse_terminal_resize_args(&json!(["console", 40, 120])).unwrap();
+        assert_eq!(data.rows, 40);
+        assert_eq!(data.cols, 120);
+    }
 }
@@ -0,0 +1,562 @@
+// Chunked file transfers
+//
+// Socket.IO messages are limited to 100 kB, so larger payloads move in base64
+// chunks of TRANSFER_CHUNK_BYTES. A transfer has an id and a SHA3-256 checksum
+// of the whole payload, and every chunk carries the checksum of its bytes.
+//
+// Downloads (server to client):
+//   beginDownload  [kind, target, passphrase?] -> transferId, filename, size, chunkSize, chunks, checksum
+//   downloadChunk  [transferId, index]  -> index, data, checksum
+//   endTransfer    [transferId]         -> frees the payload
+//
+// Uploads (client to server):
+//   beginUpload    [kind, size, checksum]                 -> transferId, chunkSize, chunks
+//   uploadChunk    [transferId, index, data, checksum]
+//   transferStatus [transferId]                           -> missing chunk indices
+//   endTransfer    [transferId]                           -> checks the payload and hands it on
+//
+// Chunks can be sent or fetched in any order and more than once, so an
+// interrupted transfer resumes where it stopped (transferStatus tells an
+// uploader what is missing) as long as it isn't idle for TRANSFER_TTL.
+// Transfers are kept in memory; the kind says where a payload comes from or
+// goes to, e.g. a stackImport upload ends as an uploadId for importStack.
+// Through the agent proxy the same events move files to and from agents.
+
+use crate::server::ServerContext;
+use crate::socket_handlers::{callback_error, check_login, get_endpoint, spawn_handler};
+use crate::stack::Stack;
+use crate::utils::constants::{MAX_STACK_ARCHIVE_BYTES, TRANSFER_CHUNK_BYTES};
+use crate::utils::types::CustomResponse;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use redact::Secret;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha3::{Digest, Sha3_256};
+use socketioxide::extract::{AckSender, Data, SocketRef};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Transfers idle for this long are dropped
+const TRANSFER_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of transfers in progress at once
+const MAX_TRANSFERS: usize = 16;
+
+static TRANSFERS: Lazy<Mutex<TransferStore>> = Lazy::new(|| Mutex::new(TransferStore::default()));
+
+/// What a transfer carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransferKind {
+    /// Download of a stack archive, as exportStack
+    StackExport,
+    /// Upload of a stack archive for importStack
+    StackImport,
+}
+
+impl TransferKind {
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "stackExport" => Ok(TransferKind::StackExport),
+            "stackImport" => Ok(TransferKind::StackImport),
+            _ => Err(anyhow!("Unknown transfer kind \"{}\"", s)),
+        }
+    }
+
+    /// Largest payload accepted for an upload of this kind
+    fn max_upload_bytes(self) -> Option<usize> {
+        match self {
+            TransferKind::StackExport => None,
+            TransferKind::StackImport => Some(MAX_STACK_ARCHIVE_BYTES),
+        }
+    }
+}
+
+enum TransferData {
+    Download(Vec<u8>),
+    /// Chunks received so far, by index
+    Upload(Vec<Option<Vec<u8>>>),
+}
+
+struct Transfer {
+    kind: TransferKind,
+    size: usize,
+    checksum: String,
+    data: TransferData,
+    touched: Instant,
+}
+
+/// Reply to beginDownload
+#[derive(Debug, Serialize)]
+struct DownloadInfo {
+    #[serde(rename = "transferId")]
+    transfer_id: String,
+    filename: String,
+    size: usize,
+    #[serde(rename = "chunkSize")]
+    chunk_size: usize,
+    chunks: usize,
+    checksum: String,
+}
+
+/// Reply to beginUpload
+#[derive(Debug, Serialize)]
+struct UploadInfo {
+    #[serde(rename = "transferId")]
+    transfer_id: String,
+    #[serde(rename = "chunkSize")]
+    chunk_size: usize,
+    chunks: usize,
+}
+
+/// A chunk of a download
+#[derive(Debug, Serialize)]
+struct Chunk {
+    index: usize,
+    /// Base64-encoded bytes
+    data: String,
+    checksum: String,
+}
+
+/// Hex SHA3-256 of the bytes
+fn checksum(data: &[u8]) -> String {
+    hex::encode(Sha3_256::digest(data))
+}
+
+fn chunk_count(size: usize) -> usize {
+    size.div_ceil(TRANSFER_CHUNK_BYTES)
+}
+
+#[derive(Default)]
+struct TransferStore {
+    transfers: HashMap<String, Transfer>,
+}
+
+impl TransferStore {
+    fn insert(&mut self, transfer: Transfer) -> Result<String> {
+        self.transfers
+            .retain(|_, t| t.touched.elapsed() < TRANSFER_TTL);
+        if self.transfers.len() >= MAX_TRANSFERS {
+            return Err(anyhow!("Too many transfers in progress, try again later"));
+        }
+
+        let id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+        self.transfers.insert(id.clone(), transfer);
+        Ok(id)
+    }
+
+    fn get(&mut self, id: &str) -> Result<&mut Transfer> {
+        let transfer = self
+            .transfers
+            .get_mut(id)
+            .filter(|t| t.touched.elapsed() < TRANSFER_TTL)
+            .ok_or_else(|| anyhow!("Transfer not found or expired"))?;
+        transfer.touched = Instant::now();
+        Ok(transfer)
+    }
+
+    fn begin_download(
+        &mut self,
+        kind: TransferKind,
+        filename: String,
+        payload: Vec<u8>,
+    ) -> Result<DownloadInfo> {
+        let size = payload.len();
+        let checksum = checksum(&payload);
+        let transfer_id = self.insert(Transfer {
+            kind,
+            size,
+            checksum: checksum.clone(),
+            data: TransferData::Download(payload),
+            touched: Instant::now(),
+        })?;
+
+        Ok(DownloadInfo {
+            transfer_id,
+            filename,
+            size,
+            chunk_size: TRANSFER_CHUNK_BYTES,
+            chunks: chunk_count(size),
+            checksum,
+        })
+    }
+
+    fn download_chunk(&mut self, id: &str, index: usize) -> Result<Chunk> {
+        let TransferData::Download(payload) = &self.get(id)?.data else {
+            return Err(anyhow!("Transfer is an upload"));
+        };
+        let start = index
+            .checked_mul(TRANSFER_CHUNK_BYTES)
+            .filter(|start| *start < payload.len())
+            .ok_or_else(|| anyhow!("Chunk {} is out of range", index))?;
+        let bytes = &payload[start..(start + TRANSFER_CHUNK_BYTES).min(payload.len())];
+
+        Ok(Chunk {
+            index,
+            data: BASE64.encode(bytes),
+            checksum: checksum(bytes),
+        })
+    }
+
+    fn begin_upload(
+        &mut self,
+        kind: TransferKind,
+        size: usize,
+        checksum: String,
+    ) -> Result<UploadInfo> {
+        let Some(max) = kind.max_upload_bytes() else {
+            return Err(anyhow!("Transfers of this kind can't be uploaded"));
+        };
+        if size == 0 || size > max {
+            return Err(anyhow!("Upload size must be between 1 and {} bytes", max));
+        }
+
+        let chunks = chunk_count(size);
+        let transfer_id = self.insert(Transfer {
+            kind,
+            size,
+            checksum: checksum.to_lowercase(),
+            data: TransferData::Upload(vec![None; chunks]),
+            touched: Instant::now(),
+        })?;
+
+        Ok(UploadInfo {
+            transfer_id,
+            chunk_size: TRANSFER_CHUNK_BYTES,
+            chunks,
+        })
+    }
+
+    fn upload_chunk(&mut self, id: &str, index: usize, data: &str, sum: &str) -> Result<()> {
+        let transfer = self.get(id)?;
+        let size = transfer.size;
+        let TransferData::Upload(chunks) = &mut transfer.data else {
+            return Err(anyhow!("Transfer is a download"));
+        };
+        if index >= chunks.len() {
+            return Err(anyhow!("Chunk {} is out of range", index));
+        }
+
+        let bytes = BASE64
+            .decode(data)
+            .map_err(|_| anyhow!("Chunk {} is not valid base64", index))?;
+        let expected_len = if index + 1 == chunks.len() {
+            size - index * TRANSFER_CHUNK_BYTES
+        } else {
+            TRANSFER_CHUNK_BYTES
+        };
+        if bytes.len() != expected_len {
+            return Err(anyhow!(
+                "Chunk {} has {} bytes, expected {}",
+                index,
+                bytes.len(),
+                expected_len
+            ));
+        }
+        if checksum(&bytes) != sum.to_lowercase() {
+            return Err(anyhow!("Checksum mismatch in chunk {}", index));
+        }
+
+        chunks[index] = Some(bytes);
+        Ok(())
+    }
+
+    /// Indices of the chunks an upload is still missing
+    fn missing(&mut self, id: &str) -> Result<Vec<usize>> {
+        match &self.get(id)?.data {
+            TransferData::Upload(chunks) => Ok(chunks
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| chunk.is_none())
+                .map(|(i, _)| i)
+                .collect()),
+            TransferData::Download(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// End a transfer; an upload's payload is returned once complete and verified
+    ///
+    /// An upload that is incomplete or fails its checksum stays open so the
+    /// missing or bad chunks can be sent again.
+    fn end(&mut self, id: &str) -> Result<Option<(TransferKind, Vec<u8>)>> {
+        let transfer = self.get(id)?;
+        if let TransferData::Upload(chunks) = &transfer.data {
+            let missing = chunks.iter().filter(|chunk| chunk.is_none()).count();
+            if missing > 0 {
+                return Err(anyhow!("Upload is missing {} chunk(s)", missing));
+            }
+            let payload: Vec<u8> = chunks.iter().flatten().flatten().copied().collect();
+            if checksum(&payload) != transfer.checksum {
+                return Err(anyhow!("Checksum mismatch, upload the file again"));
+            }
+            let kind = transfer.kind;
+            self.transfers.remove(id);
+            return Ok(Some((kind, payload)));
+        }
+
+        self.transfers.remove(id);
+        Ok(None)
+    }
+}
+
+/// Setup chunked transfer event handlers
+pub fn setup_transfer_handlers(socket: SocketRef, ctx: Arc<ServerContext>) {
+    for event in [
+        "beginDownload",
+        "downloadChunk",
+        "beginUpload",
+        "uploadChunk",
+        "transferStatus",
+        "endTransfer",
+    ] {
+        let ctx_clone = ctx.clone();
+        socket.on(
+            event,
+            async move |socket: SocketRef, Data::<Value>(data), ack: AckSender| {
+                let ctx = ctx_clone.clone();
+                spawn_handler(event, ack, |ack| async move {
+                    let args = data.as_array().cloned().unwrap_or_else(|| vec![data]);
+                    match handle_transfer_event(&socket, &ctx, event, &args).await {
+                        Ok(response) => {
+                            ack.send(&response).ok();
+                        }
+                        Err(e) => callback_error(ack.take(), e),
+                    }
+                });
+            },
+        );
+    }
+}
+
+/// Dispatch a transfer event from the agent proxy (local endpoint).
+/// Returns Ok(true) if the event was handled, Ok(false) if not recognized.
+pub(crate) async fn dispatch_transfer_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    event_args: &[Value],
+    ack: &mut Option<AckSender>,
+) -> Result<bool> {
+    if !matches!(
+        event_name,
+        "beginDownload"
+            | "downloadChunk"
+            | "beginUpload"
+            | "uploadChunk"
+            | "transferStatus"
+            | "endTransfer"
+    ) {
+        return Ok(false);
+    }
+
+    match handle_transfer_event(socket, ctx, event_name, event_args).await {
+        Ok(response) => {
+            if let Some(ack) = ack.take() {
+                ack.send(&response).ok();
+            }
+        }
+        Err(e) => callback_error(ack.take(), e),
+    }
+    Ok(true)
+}
+
+fn str_arg<'a>(args: &'a [Value], index: usize, name: &str) -> Result<&'a str> {
+    args.get(index)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("{} must be a string", name))
+}
+
+fn usize_arg(args: &[Value], index: usize, name: &str) -> Result<usize> {
+    args.get(index)
+        .and_then(Value::as_u64)
+        .map(|n| n as usize)
+        .ok_or_else(|| anyhow!("{} must be a non-negative integer", name))
+}
+
+async fn handle_transfer_event(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    event_name: &str,
+    args: &[Value],
+) -> Result<Value> {
+    let user_id = check_login(socket)?;
+
+    let response = match event_name {
+        "beginDownload" => {
+            let kind = TransferKind::parse(str_arg(args, 0, "kind")?)?;
+            let (filename, payload) = download_payload(socket, ctx, kind, args).await?;
+            let info = TRANSFERS
+                .lock()
+                .unwrap()
+                .begin_download(kind, filename, payload)?;
+            CustomResponse::ok_with_fields(info).into()
+        }
+        "downloadChunk" => {
+            let id = str_arg(args, 0, "transferId")?;
+            let index = usize_arg(args, 1, "index")?;
+            let chunk = TRANSFERS.lock().unwrap().download_chunk(id, index)?;
+            CustomResponse::ok_with_fields(chunk).into()
+        }
+        "beginUpload" => {
+            let kind = TransferKind::parse(str_arg(args, 0, "kind")?)?;
+            let size = usize_arg(args, 1, "size")?;
+            let sum = str_arg(args, 2, "checksum")?.to_string();
+            let info = TRANSFERS.lock().unwrap().begin_upload(kind, size, sum)?;
+            CustomResponse::ok_with_fields(info).into()
+        }
+        "uploadChunk" => {
+            let id = str_arg(args, 0, "transferId")?;
+            let index = usize_arg(args, 1, "index")?;
+            let data = str_arg(args, 2, "data")?;
+            let sum = str_arg(args, 3, "checksum")?;
+            TRANSFERS
+                .lock()
+                .unwrap()
+                .upload_chunk(id, index, data, sum)?;
+            json!({ "ok": true, "index": index })
+        }
+        "transferStatus" => {
+            let id = str_arg(args, 0, "transferId")?;
+            let missing = TRANSFERS.lock().unwrap().missing(id)?;
+            json!({ "ok": true, "missing": missing })
+        }
+        "endTransfer" => {
+            let id = str_arg(args, 0, "transferId")?;
+            let ended = TRANSFERS.lock().unwrap().end(id)?;
+            match ended {
+                Some((kind, payload)) => complete_upload(kind, payload, user_id)?,
+                None => json!({ "ok": true }),
+            }
+        }
+        _ => return Err(anyhow!("Unknown transfer event: {}", event_name)),
+    };
+    Ok(response)
+}
+
+/// Produce the payload of a download: (filename, bytes)
+async fn download_payload(
+    socket: &SocketRef,
+    ctx: &ServerContext,
+    kind: TransferKind,
+    args: &[Value],
+) -> Result<(String, Vec<u8>)> {
+    match kind {
+        TransferKind::StackExport => {
+            let stack_name = str_arg(args, 1, "stackName")?;
+            let endpoint = get_endpoint(socket);
+            let passphrase = match args.get(2) {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(str_arg(args, 2, "passphrase")?)
+                    .filter(|p| !p.is_empty())
+                    .map(|p| Secret::new(p.to_string())),
+            };
+            let encrypted = passphrase.is_some();
+            let stack = Stack::get_stack(ctx.clone().into(), stack_name, endpoint).await?;
+            let archive = stack.export(passphrase).await?;
+            info!("Exporting stack {} in chunks", stack_name);
+            Ok((
+                crate::archive::archive_filename(stack_name, encrypted),
+                archive,
+            ))
+        }
+        TransferKind::StackImport => Err(anyhow!("Transfers of this kind can't be downloaded")),
+    }
+}
+
+/// Hand a user's finished upload on to what it was uploaded for
+fn complete_upload(kind: TransferKind, payload: Vec<u8>, user_id: i64) -> Result<Value> {
+    match kind {
+        TransferKind::StackImport => {
+            let upload_id = crate::archive::store_upload(user_id, payload)?;
+            Ok(json!({ "ok": true, "uploadId": upload_id }))
+        }
+        TransferKind::StackExport => Err(anyhow!("Transfers of this kind can't be uploaded")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_download_chunks() {
+        let mut store = TransferStore::default();
+        let payload: Vec<u8> = (0..TRANSFER_CHUNK_BYTES * 2 + 10)
+            .map(|i| i as u8)
+            .collect();
+        let info = store
+            .begin_download(
+                TransferKind::StackExport,
+                "web.tar.gz".into(),
+                payload.clone(),
+            )
+            .unwrap();
+        assert_eq!(info.chunks, 3);
+        assert_eq!(info.checksum, checksum(&payload));
+
+        let mut received = Vec::new();
+        for index in [0, 1, 2, 1] {
+            let chunk = store.download_chunk(&info.transfer_id, index).unwrap();
+            let bytes = BASE64.decode(&chunk.data).unwrap();
+            assert_eq!(checksum(&bytes), chunk.checksum);
+            if received.len() == index * TRANSFER_CHUNK_BYTES {
+                received.extend(bytes);
+            }
+        }
+        assert_eq!(received, payload);
+        assert!(store.download_chunk(&info.transfer_id, 3).is_err());
+
+        assert!(store.end(&info.transfer_id).unwrap().is_none());
+        assert!(store.download_chunk(&info.transfer_id, 0).is_err());
+    }
+
+    #[test]
+    fn test_upload_resumes_and_verifies() {
+        let mut store = TransferStore::default();
+        let payload: Vec<u8> = (0..TRANSFER_CHUNK_BYTES + 5)
+            .map(|i| (i * 7) as u8)
+            .collect();
+        let parts: Vec<&[u8]> = payload.chunks(TRANSFER_CHUNK_BYTES).collect();
+        let send = |store: &mut TransferStore, id: &str, index: usize| {
+            let part = parts[index];
+            store.upload_chunk(id, index, &BASE64.encode(part), &checksum(part))
+        };
+
+        let info = store
+            .begin_upload(TransferKind::StackImport, payload.len(), checksum(&payload))
+            .unwrap();
+        assert_eq!(info.chunks, 2);
+        let id = info.transfer_id;
+
+        send(&mut store, &id, 1).unwrap();
+        assert_eq!(store.missing(&id).unwrap(), [0]);
+        assert!(store.end(&id).is_err());
+
+        // A corrupted chunk is rejected and can be sent again
+        assert!(store
+            .upload_chunk(&id, 0, &BASE64.encode(parts[0]), &checksum(b"x"))
+            .is_err());
+        send(&mut store, &id, 0).unwrap();
+        assert!(store.missing(&id).unwrap().is_empty());
+
+        let (kind, received) = store.end(&id).unwrap().unwrap();
+        assert_eq!(kind, TransferKind::StackImport);
+        assert_eq!(received, payload);
+
+        assert!(store
+            .begin_upload(TransferKind::StackExport, 10, checksum(b""))
+            .is_err());
+        assert!(store
+            .begin_upload(
+                TransferKind::StackImport,
+                MAX_STACK_ARCHIVE_BYTES + 1,
+                String::new()
+            )
+            .is_err());
+        assert!(TransferKind::parse("backup").is_err());
+    }
+}
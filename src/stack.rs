@@ -5,26 +5,123 @@
 // - Docker CLI operations via PTY (deploy, stop, restart, etc.)
 // - Stack list scanning and status management
 // - YAML/ENV file handling with comment preservation
-// - Service status parsing from docker compose ps
+// - Service status from the Docker API (compose labels of the containers)
 
+use crate::db::models::{
+    NewStackEvent, NewStackHistory, Setting, StackAutostart, StackDependency, StackEvent,
+    StackGroup, StackHistory, StackUpdateWindow, StoredSecret,
+};
+use crate::docker::{
+    ComposeConfig, ComposeEnv, ComposeLogs, ConfigFormat, DeployOptions, DockerHandle, ExecOptions,
+};
 use crate::server::ServerContext;
+use crate::terminal::{Terminal, TerminalType};
+use crate::utils::bulk_edit::{replace_in_file, FileEdit};
+use crate::utils::compose_env::{ComposeEnvPolicy, COMPOSE_ENV_POLICY_SETTING};
+use crate::utils::compose_include::{
+    absolutize_includes, load_includes, merge_includes, IncludedFile,
+};
+use crate::utils::compose_policy::{
+    check_compose_policy, enforce, ComposePolicy, PolicyViolation, COMPOSE_POLICY_SETTING,
+};
+use crate::utils::compose_spec::check_compose_spec;
 use crate::utils::constants::{
-    ACCEPTED_COMPOSE_FILE_NAMES, CREATED_FILE, UNKNOWN,
+    ACCEPTED_COMPOSE_FILE_NAMES, ACCEPTED_README_FILE_NAMES, BATCH_STACK_CONCURRENCY,
+    COMPOSE_FILE_LIST_NAME, CREATED_FILE, DEFAULT_COMPOSE_FILE_NAME, DOCKER_CONTEXT_FILE_NAME,
+    GENERATED_ENV_DIR, README_MAX_BYTES, UNKNOWN,
 };
-use anyhow::{Context, Result};
+use crate::utils::deploy_hooks::{parse_deploy_hooks, DeployHooks, HookStage};
+use crate::utils::env_schema::{fill_env, parse_env_schema};
+use crate::utils::gpu::{gpu_services, gpu_warning};
+use crate::utils::crypto::decrypt_password;
+use crate::utils::injected_env;
+use crate::utils::log_timestamps::LogOptions;
+use crate::utils::restart_policy::{self, RestartPolicy};
+use crate::utils::secrets::{self, GeneratedEnvFile};
+use crate::utils::stack_name::StackName;
+use crate::utils::stack_order::dependency_order;
+use crate::utils::terminal::get_batch_terminal_name;
+use crate::utils::text_diff::unified_diff;
+use crate::utils::ui_hints::{cached_ui_hints, parse_ui_hints, UiHints};
+use crate::utils::update_window::UpdateWindow;
+use crate::utils::yaml_utils::{active_profiles, resolve_services, ComposeService};
+use anyhow::{anyhow, Context, Result};
+use redact::Secret;
 use serde::{Deserialize, Serialize};
 use socketioxide::extract::SocketRef;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs;
-use tracing::warn;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
 use yaml_rust2::YamlLoader;
 
+pub use dockru_protocol::stack::{
+    ComposeFile, FileTimes, ServiceCounts, ServiceStatus, StackJson, StackSimpleJson,
+};
+
+/// Operation deployAll/startAll runs on every stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchAction {
+    Deploy,
+    Start,
+}
+
+/// Operation batchStackAction runs on each of the chosen stacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackAction {
+    Start,
+    Stop,
+    Update,
+    Down,
+}
+
+impl StackAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StackAction::Start => "start",
+            StackAction::Stop => "stop",
+            StackAction::Update => "update",
+            StackAction::Down => "down",
+        }
+    }
+}
+
+/// Outcome of one stack of a batchStackAction
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchStackResult {
+    #[serde(rename = "stackName")]
+    pub stack_name: String,
+    pub ok: bool,
+    /// Exit code of the compose command, None if it didn't get to run
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+}
+
+impl BatchAction {
+    /// The action as recorded in the stack event timeline
+    fn event_name(&self) -> &'static str {
+        match self {
+            BatchAction::Deploy => "deploy",
+            BatchAction::Start => "start",
+        }
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            BatchAction::Deploy => "Deploying",
+            BatchAction::Start => "Starting",
+        }
+    }
+}
+
 /// Represents a Docker Compose stack
 pub struct Stack {
     /// Stack name (directory name)
-    pub name: String,
+    pub name: StackName,
     /// Stack status
     status: i32,
     /// Endpoint identifier for terminal naming
@@ -37,49 +134,44 @@ pub struct Stack {
     compose_env: Option<String>,
     /// Detected compose file name
     compose_file_name: String,
+    /// Whether compose_file_name was chosen for a new stack by the client
+    compose_file_name_chosen: bool,
     /// Config file path from docker (for external stacks)
     config_file_path: Option<String>,
+    /// Stack directory timestamps, gathered while scanning
+    dir_times: Option<FileTimes>,
+    /// Compose file timestamps, gathered while detecting the compose file
+    compose_file_times: Option<FileTimes>,
+    /// Group (folder) the stack is filed under
+    group: Option<String>,
+    /// Whether the stack is started when dockru boots
+    autostart: bool,
+    /// When found image updates are applied automatically, if ever
+    update_window: Option<UpdateWindow>,
+    /// Container states the status was computed from, gathered while listing
+    service_counts: Option<ServiceCounts>,
+    /// How the UI should present the stack, from the compose file's `x-dockru`
+    ui_hints: UiHints,
 }
 
-/// Simple JSON representation for stack lists
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StackSimpleJson {
-    pub name: String,
-    pub status: i32,
-    pub tags: Vec<String>,
-    #[serde(rename = "isManagedByDockru")]
-    pub is_managed_by_dockru: bool,
-    #[serde(rename = "composeFileName")]
-    pub compose_file_name: String,
-    pub endpoint: String,
-}
-
-/// Full JSON representation with compose files
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StackJson {
-    pub name: String,
-    pub status: i32,
-    pub tags: Vec<String>,
-    #[serde(rename = "isManagedByDockru")]
-    pub is_managed_by_dockru: bool,
-    #[serde(rename = "composeFileName")]
-    pub compose_file_name: String,
-    pub endpoint: String,
-    #[serde(rename = "composeYAML")]
-    pub compose_yaml: String,
-    #[serde(rename = "composeENV")]
-    pub compose_env: String,
-    #[serde(rename = "primaryHostname")]
-    pub primary_hostname: String,
+/// Unified diffs from a stack's files on disk to content about to be saved
+///
+/// Each diff is empty when nothing changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct StackDiff {
+    pub yaml: String,
+    pub env: String,
+    /// Diff of the rendered `docker compose config`, if requested
+    pub config: Option<String>,
+    pub changed: bool,
 }
 
-/// Service status information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ServiceStatus {
-    pub state: String,
-    pub ports: Vec<String>,
-    pub health: Option<String>,
-    pub image: Option<String>,
+/// The compose model of a stack as `docker compose config --format json` prints it
+#[derive(Debug, Clone, Serialize)]
+pub struct NormalizedConfig {
+    pub config: serde_json::Value,
+    /// Warnings printed by compose, e.g. variables that are not set
+    pub warnings: Vec<String>,
 }
 
 impl Stack {
@@ -89,7 +181,7 @@ impl Stack {
     /// * `ctx` - Server context
     /// * `name` - Stack name
     /// * `endpoint` - Endpoint identifier
-    pub fn new(ctx: Arc<ServerContext>, name: String, endpoint: String) -> Self {
+    pub fn new(ctx: Arc<ServerContext>, name: StackName, endpoint: String) -> Self {
         Self {
             name,
             status: UNKNOWN,
@@ -97,15 +189,23 @@ impl Stack {
             ctx,
             compose_yaml: None,
             compose_env: None,
-            compose_file_name: "compose.yaml".to_string(),
+            compose_file_name: DEFAULT_COMPOSE_FILE_NAME.to_string(),
+            compose_file_name_chosen: false,
             config_file_path: None,
+            dir_times: None,
+            compose_file_times: None,
+            group: None,
+            autostart: false,
+            update_window: None,
+            service_counts: None,
+            ui_hints: UiHints::default(),
         }
     }
 
     /// Create a new stack with provided YAML and ENV content
     pub fn new_with_content(
         ctx: Arc<ServerContext>,
-        name: String,
+        name: StackName,
         endpoint: String,
         compose_yaml: String,
         compose_env: String,
@@ -117,16 +217,56 @@ impl Stack {
             ctx,
             compose_yaml: Some(compose_yaml),
             compose_env: Some(compose_env),
-            compose_file_name: "compose.yaml".to_string(),
+            compose_file_name: DEFAULT_COMPOSE_FILE_NAME.to_string(),
+            compose_file_name_chosen: false,
             config_file_path: None,
+            dir_times: None,
+            compose_file_times: None,
+            group: None,
+            autostart: false,
+            update_window: None,
+            service_counts: None,
+            ui_hints: UiHints::default(),
         }
     }
 
+    /// Use this compose file name when the stack is created, instead of the
+    /// defaultComposeFileName setting
+    ///
+    /// Must be one of the names docker compose picks up by itself.
+    pub fn set_compose_file_name(&mut self, name: &str) -> Result<()> {
+        if !ACCEPTED_COMPOSE_FILE_NAMES.contains(&name) {
+            anyhow::bail!(
+                "Compose file name must be one of {}",
+                ACCEPTED_COMPOSE_FILE_NAMES.join(", ")
+            );
+        }
+        self.compose_file_name = name.to_string();
+        self.compose_file_name_chosen = true;
+        Ok(())
+    }
+
+    /// Compose file name for new stacks, from the defaultComposeFileName setting
+    pub async fn default_compose_file_name(ctx: &ServerContext) -> String {
+        Setting::get(&ctx.db, &ctx.cache, "defaultComposeFileName")
+            .await
+            .ok()
+            .flatten()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .filter(|name| ACCEPTED_COMPOSE_FILE_NAMES.contains(&name.as_str()))
+            .unwrap_or_else(|| DEFAULT_COMPOSE_FILE_NAME.to_string())
+    }
+
     /// Get the stack's directory path
     pub fn path(&self) -> PathBuf {
         self.ctx.config.stacks_dir.join(&self.name)
     }
 
+    /// Status code, as of when the stack was loaded
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
     /// Check if this stack is managed by Dockru (has a directory in stacks_dir)
     pub async fn is_managed_by_dockru(&self) -> bool {
         let path = self.path();
@@ -168,40 +308,323 @@ impl Stack {
         }
     }
 
+    /// Read the stack's README, if the directory has one
+    ///
+    /// Only the first README_MAX_BYTES are read; a multi-byte character cut off
+    /// at the limit is dropped.
+    pub async fn readme(&self) -> Option<String> {
+        use tokio::io::AsyncReadExt;
+
+        let stack_path = self.path();
+
+        for filename in ACCEPTED_README_FILE_NAMES {
+            let Ok(file) = fs::File::open(stack_path.join(filename)).await else {
+                continue;
+            };
+
+            let mut buf = Vec::new();
+            if file
+                .take(README_MAX_BYTES as u64)
+                .read_to_end(&mut buf)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+
+            return Some(match String::from_utf8(buf) {
+                Ok(content) => content,
+                Err(e) => {
+                    let valid = e.utf8_error().valid_up_to();
+                    let mut buf = e.into_bytes();
+                    buf.truncate(valid);
+                    String::from_utf8(buf).unwrap_or_default()
+                }
+            });
+        }
+
+        None
+    }
+
+    /// Export the stack directory as a `.tar.gz` archive, encrypted with the
+    /// passphrase if one is given
+    pub async fn export(&self, passphrase: Option<Secret<String>>) -> Result<Vec<u8>> {
+        if !self.is_managed_by_dockru().await {
+            return Err(anyhow::anyhow!("Only stacks managed by Dockru can be exported"));
+        }
+
+        let path = self.path();
+        let name = self.name.clone();
+        tokio::task::spawn_blocking(move || {
+            let archive = crate::archive::pack_stack_dir(&path, &name)?;
+            match passphrase {
+                Some(passphrase) => crate::archive::encrypt_archive(&archive, &passphrase),
+                None => Ok(archive),
+            }
+        })
+        .await
+        .context("Export task failed")?
+    }
+
+    /// Create a new stack from the entries of an imported archive
+    ///
+    /// The archive must contain a compose file at its root. The stack is validated
+    /// before anything is written, and the directory is removed again if writing fails.
+    pub async fn import(
+        ctx: Arc<ServerContext>,
+        name: &str,
+        endpoint: String,
+        entries: Vec<crate::archive::ArchiveEntry>,
+    ) -> Result<Self> {
+        let read_text = |file_name: &str| -> Result<Option<String>> {
+            entries
+                .iter()
+                .find(|e| e.path == Path::new(file_name))
+                .and_then(|e| e.data.clone())
+                .map(|data| {
+                    String::from_utf8(data)
+                        .map_err(|_| anyhow::anyhow!("{} is not valid UTF-8", file_name))
+                })
+                .transpose()
+        };
+
+        let mut compose = None;
+        for file_name in ACCEPTED_COMPOSE_FILE_NAMES {
+            if let Some(yaml) = read_text(file_name)? {
+                compose = Some((file_name.to_string(), yaml));
+                break;
+            }
+        }
+        let (compose_file_name, compose_yaml) =
+            compose.ok_or_else(|| anyhow::anyhow!("Archive does not contain a compose file"))?;
+        let compose_env = read_text(".env")?.unwrap_or_default();
+
+        let mut stack =
+            Self::new_with_content(ctx, StackName::parse(name)?, endpoint, compose_yaml, compose_env);
+        stack.compose_file_name = compose_file_name;
+        stack.validate().await?;
+        let compose_yaml = stack.compose_yaml().await?;
+        enforce(&stack.policy_violations(&compose_yaml).await?)?;
+        let filled_env = stack.fill_env_from_schema().await?;
+
+        let dir = stack.path();
+        if fs::metadata(&dir).await.is_ok() {
+            anyhow::bail!("Stack name already exists");
+        }
+
+        let write_dir = dir.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            crate::archive::write_entries(&write_dir, &entries)
+        })
+        .await
+        .context("Import task failed")?;
+
+        let result = match (result, filled_env) {
+            (Ok(()), Some(env)) => fs::write(dir.join(".env"), env)
+                .await
+                .context("Failed to write .env file"),
+            (result, _) => result,
+        };
+        if let Err(e) = result {
+            fs::remove_dir_all(&dir).await.ok();
+            return Err(e);
+        }
+
+        info!("Imported stack {} into {}", stack.name, dir.display());
+        Ok(stack)
+    }
+
     /// Detect which compose file exists in the stack directory
     pub async fn detect_compose_file(&mut self) -> Result<()> {
         let stack_path = self.path();
 
         for filename in ACCEPTED_COMPOSE_FILE_NAMES {
             let compose_path = stack_path.join(filename);
-            if fs::metadata(&compose_path).await.is_ok() {
+            if let Ok(metadata) = fs::metadata(&compose_path).await {
                 self.compose_file_name = filename.to_string();
+                self.compose_file_times = Some(FileTimes::from_metadata(&metadata));
                 return Ok(());
             }
         }
 
         // Default to compose.yaml if nothing found
-        self.compose_file_name = "compose.yaml".to_string();
+        self.compose_file_name = DEFAULT_COMPOSE_FILE_NAME.to_string();
         Ok(())
     }
 
-    /// Validate the stack before saving
-    pub async fn validate(&mut self) -> Result<()> {
-        // Check name, allows [a-z][0-9] _ - only (must be non-empty)
-        if self.name.is_empty() {
-            anyhow::bail!("Stack name must not be empty");
-        }
-        if !self
-            .name
-            .chars()
-            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    /// Read the compose file's UI hints, cached until the file changes
+    fn load_ui_hints(&mut self) {
+        let modified = self.compose_file_times.and_then(|times| times.modified);
+        self.ui_hints = cached_ui_hints(&self.path().join(&self.compose_file_name), modified);
+    }
+
+    /// The compose files passed to docker compose, with their content
+    pub async fn compose_files(&self) -> Vec<ComposeFile> {
+        let dir = self.path();
+        let mut files = Vec::new();
+
+        for name in crate::docker::resolve_compose_files(&dir) {
+            let content = fs::read_to_string(dir.join(&name)).await.ok();
+            files.push(ComposeFile { name, content });
+        }
+
+        files
+    }
+
+    /// Set the compose files used for this stack, in merge order
+    ///
+    /// Every file must exist in the stack directory. An empty list goes back to
+    /// the main compose file plus its override file.
+    pub async fn set_compose_files(&self, files: &[String]) -> Result<()> {
+        if !self.is_managed_by_dockru().await {
+            anyhow::bail!("Only stacks managed by Dockru can use custom compose files");
+        }
+
+        let dir = self.path();
+        let list_path = dir.join(COMPOSE_FILE_LIST_NAME);
+
+        if files.is_empty() {
+            if fs::metadata(&list_path).await.is_ok() {
+                fs::remove_file(&list_path)
+                    .await
+                    .context("Failed to remove compose file list")?;
+            }
+            return Ok(());
+        }
+
+        for (i, name) in files.iter().enumerate() {
+            crate::docker::validate_compose_file_name(name)?;
+            if files[..i].contains(name) {
+                anyhow::bail!("Compose file \"{}\" is listed twice", name);
+            }
+            if !fs::metadata(dir.join(name))
+                .await
+                .map(|m| m.is_file())
+                .unwrap_or(false)
+            {
+                anyhow::bail!("Compose file \"{}\" does not exist", name);
+            }
+        }
+
+        let mut list = files.join("\n");
+        list.push('\n');
+        fs::write(&list_path, list)
+            .await
+            .context("Failed to write compose file list")?;
+
+        info!("Stack {} now uses compose files {:?}", self.name, files);
+        Ok(())
+    }
+
+    /// The docker context the stack is deployed to, None for the instance's daemon
+    pub fn docker_context(&self) -> Option<String> {
+        crate::docker::stack_docker_context(&self.path())
+    }
+
+    /// The Docker handle of the daemon the stack is deployed to
+    pub async fn docker(&self) -> Result<DockerHandle> {
+        self.ctx
+            .docker
+            .for_context(self.docker_context().as_deref())
+            .await
+    }
+
+    /// Deploy the stack to a docker context, or back to the instance's daemon
+    ///
+    /// The stack keeps running where it is; it has to be stopped first and
+    /// deployed again for the change to move it.
+    pub async fn set_docker_context(&self, context: Option<&str>) -> Result<()> {
+        if !self.is_managed_by_dockru().await {
+            anyhow::bail!("Only stacks managed by Dockru can use a docker context");
+        }
+
+        let context_path = self.path().join(DOCKER_CONTEXT_FILE_NAME);
+        let Some(context) = context.filter(|c| !c.is_empty()) else {
+            if fs::metadata(&context_path).await.is_ok() {
+                fs::remove_file(&context_path)
+                    .await
+                    .context("Failed to remove docker context file")?;
+            }
+            info!("Stack {} now uses the instance's docker daemon", self.name);
+            return Ok(());
+        };
+
+        crate::docker::validate_docker_context_name(context)?;
+        if !crate::docker::list_docker_contexts()
+            .await?
+            .iter()
+            .any(|c| c.name == context)
         {
-            anyhow::bail!("Stack name can only contain [a-z][0-9] _ - only");
+            anyhow::bail!("Docker context \"{}\" does not exist", context);
         }
+        // Fail now rather than on the next status refresh
+        self.ctx.docker.for_context(Some(context)).await?;
+
+        fs::write(&context_path, format!("{}\n", context))
+            .await
+            .context("Failed to write docker context file")?;
+        info!("Stack {} now uses docker context {}", self.name, context);
+        Ok(())
+    }
+
+    /// The files the compose file includes, read from the stack directory
+    pub async fn included_files(&mut self) -> Result<Vec<IncludedFile>> {
+        let compose_yaml = self.compose_yaml().await?;
+        load_includes(&self.path(), &self.compose_file_name, &compose_yaml)
+    }
+
+    /// The services compose would run with the stack's .env
+    ///
+    /// Services of included files are merged in, `extends:` is resolved and
+    /// services whose profiles COMPOSE_PROFILES doesn't activate are left out.
+    pub async fn services(&mut self) -> Result<Vec<ComposeService>> {
+        let compose_yaml = self.compose_yaml().await?;
+        let includes = self.included_files().await?;
+        let doc = merge_includes(&compose_yaml, &includes)?;
+        let profiles = active_profiles(&self.compose_env().await?);
+
+        let stack_dir = self.path();
+        resolve_services(&doc, &profiles, &|path| {
+            std::fs::read_to_string(stack_dir.join(path)).ok()
+        })
+    }
+
+    /// Add the env schema's defaults and generated secrets to a new stack's .env
+    ///
+    /// Returns the new .env content if anything was added.
+    async fn fill_env_from_schema(&mut self) -> Result<Option<String>> {
+        let schema = parse_env_schema(&self.compose_yaml().await?)?;
+        let env = self.compose_env().await?;
+
+        let filled = fill_env(&schema, &env);
+        if filled == env {
+            return Ok(None);
+        }
+
+        info!("Filled .env of new stack {} from its env schema", self.name);
+        self.compose_env = Some(filled.clone());
+        Ok(Some(filled))
+    }
+
+    /// Validate the stack before saving
+    pub async fn validate(&mut self) -> Result<()> {
+        // The name was checked when it was parsed into a StackName
 
         // Check YAML format
         let yaml = self.compose_yaml().await?;
         YamlLoader::load_from_str(&yaml).context("Invalid YAML format")?;
+        let issues = check_compose_spec(&yaml);
+        if !issues.is_empty() {
+            let messages: Vec<String> = issues.iter().map(ToString::to_string).collect();
+            anyhow::bail!(messages.join("\n"));
+        }
+        parse_env_schema(&yaml)?;
+        parse_deploy_hooks(&yaml)?;
+        parse_ui_hints(&yaml)?;
+
+        // Included files must exist and merge without conflicts
+        let includes = self.included_files().await?;
+        merge_includes(&yaml, &includes)?;
 
         // Check .env format
         let env = self.compose_env().await?;
@@ -216,15 +639,80 @@ impl Stack {
         Ok(())
     }
 
+    /// Render the compose file with `docker compose config`
+    ///
+    /// Uses the YAML and .env held by this Stack, which may be unsaved edits, so
+    /// variable substitution and merging can be checked before deploying.
+    pub async fn preview_config(&mut self) -> Result<ComposeConfig> {
+        self.render_config(ConfigFormat::Yaml).await
+    }
+
+    /// The fully resolved compose model of the stack, as JSON
+    ///
+    /// This is what `docker compose config --format json` prints: variables
+    /// interpolated, includes and extends merged, short syntax expanded to the
+    /// long form. Tools can consume it instead of parsing the YAML themselves.
+    pub async fn normalized_config(&mut self) -> Result<NormalizedConfig> {
+        let rendered = self.render_config(ConfigFormat::Json).await?;
+        let config = serde_json::from_str(&rendered.config)
+            .context("docker compose config printed invalid JSON")?;
+        Ok(NormalizedConfig {
+            config,
+            warnings: rendered.warnings,
+        })
+    }
+
+    async fn render_config(&mut self, format: ConfigFormat) -> Result<ComposeConfig> {
+        self.validate().await?;
+
+        let compose_yaml = self.compose_yaml().await?;
+        if compose_yaml.trim().is_empty() {
+            anyhow::bail!("Stack has no compose file");
+        }
+        let compose_env = self.compose_env().await?;
+
+        // The YAML is rendered from a temporary copy; includes stay relative to the stack
+        let project_dir = self.path();
+        let compose_yaml = absolutize_includes(&compose_yaml, &project_dir)?;
+        let project_dir = self.is_managed_by_dockru().await.then_some(project_dir);
+
+        crate::docker::compose_config(
+            &self.ctx.config.stacks_dir,
+            &self.name,
+            project_dir.as_deref(),
+            &self.compose_file_name,
+            &compose_yaml,
+            &compose_env,
+            &self.process_env().await?,
+            format,
+        )
+        .await
+    }
+
     /// Convert to simple JSON representation
     pub async fn to_simple_json(&self) -> StackSimpleJson {
+        let image_updates = crate::image_updates::get_stack_updates(&self.name).await;
+        let update_available = image_updates.values().any(|v| *v);
+
         StackSimpleJson {
-            name: self.name.clone(),
+            name: self.name.to_string(),
             status: self.status,
             tags: Vec::new(),
+            group: self.group.clone(),
+            autostart: self.autostart,
+            update_window: self.update_window.map(|w| w.to_string()),
+            services: self.service_counts,
+            health: self.service_counts.and_then(|counts| counts.health()),
+            ui_hints: Some(self.ui_hints.clone()).filter(|hints| !hints.is_empty()),
             is_managed_by_dockru: self.is_managed_by_dockru().await,
             compose_file_name: self.compose_file_name.clone(),
+            docker_context: self.docker_context(),
             endpoint: self.endpoint.clone(),
+            directory_times: self.dir_times,
+            compose_file_times: self.compose_file_times,
+            image_updates,
+            update_available,
+            pending_update: update_available && self.update_window.is_some(),
         }
     }
 
@@ -234,6 +722,24 @@ impl Stack {
         let compose_yaml = self.compose_yaml().await?;
         let compose_env = self.compose_env().await?;
 
+        // A broken schema shouldn't keep the stack from loading; saving reports it
+        let env_schema = parse_env_schema(&compose_yaml).unwrap_or_else(|e| {
+            warn!("Ignoring env schema of stack {}: {}", self.name, e);
+            Vec::new()
+        });
+        let deploy_hooks = parse_deploy_hooks(&compose_yaml).unwrap_or_else(|e| {
+            warn!("Ignoring deploy hooks of stack {}: {}", self.name, e);
+            DeployHooks::default()
+        });
+        let included_files = self.included_files().await.unwrap_or_else(|e| {
+            warn!("Ignoring includes of stack {}: {}", self.name, e);
+            Vec::new()
+        });
+        let services = self.services().await.unwrap_or_else(|e| {
+            warn!("Ignoring services of stack {}: {:#}", self.name, e);
+            Vec::new()
+        });
+
         // Determine primary hostname
         let primary_hostname = if self.endpoint.is_empty() {
             "localhost".to_string()
@@ -247,15 +753,26 @@ impl Stack {
         };
 
         Ok(StackJson {
-            name: self.name.clone(),
+            name: self.name.to_string(),
             status: self.status,
             tags: Vec::new(),
+            group: self.group.clone(),
+            autostart: self.autostart,
+            update_window: self.update_window.map(|w| w.to_string()),
+            ui_hints: Some(self.ui_hints.clone()).filter(|hints| !hints.is_empty()),
             is_managed_by_dockru: self.is_managed_by_dockru().await,
             compose_file_name: self.compose_file_name.clone(),
+            docker_context: self.docker_context(),
             endpoint: self.endpoint.clone(),
             compose_yaml,
             compose_env,
             primary_hostname,
+            readme: self.readme().await,
+            compose_files: self.compose_files().await,
+            included_files,
+            env_schema,
+            deploy_hooks,
+            services,
         })
     }
 }
@@ -269,8 +786,12 @@ impl Stack {
     ///
     /// # Arguments
     /// * `is_add` - If true, create new directory; if false, update existing
-    pub async fn save(&mut self, is_add: bool) -> Result<()> {
+    ///
+    /// Returns the compose policy's warnings about the stack.
+    pub async fn save(&mut self, is_add: bool) -> Result<Vec<String>> {
         self.validate().await?;
+        let yaml = self.compose_yaml().await?;
+        let warnings = enforce(&self.policy_violations(&yaml).await?)?;
 
         let dir = self.path();
         warn!(
@@ -288,8 +809,19 @@ impl Stack {
             fs::create_dir_all(&dir)
                 .await
                 .context("Failed to create stack directory")?;
+            if !self.compose_file_name_chosen {
+                self.compose_file_name = Self::default_compose_file_name(&self.ctx).await;
+            }
+            self.fill_env_from_schema().await?;
         } else if fs::metadata(&dir).await.is_err() {
             anyhow::bail!("Stack not found");
+        } else {
+            // Write to the compose file the stack already has
+            self.detect_compose_file().await?;
+            if self.differs_from_disk().await? {
+                // Keep the revision being overwritten so the edit can be rolled back
+                self.snapshot("save").await?;
+            }
         }
 
         // Write compose file
@@ -309,51 +841,318 @@ impl Stack {
                 .context("Failed to write .env file")?;
         }
 
+        Ok(warnings)
+    }
+
+    /// Record a lifecycle action and its outcome in the stack's event timeline
+    ///
+    /// `actor` is the user who triggered it, or what did for background runs.
+    /// Failing to record is logged rather than failing the action itself.
+    pub async fn record_event(&self, action: &str, actor: &str, result: &Result<i32>) {
+        let (exit_code, error) = match result {
+            Ok(code) => (Some(i64::from(*code)), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        let event = NewStackEvent {
+            stack_name: self.name.to_string(),
+            action: action.to_string(),
+            actor: actor.to_string(),
+            exit_code,
+            error,
+        };
+        if let Err(e) = StackEvent::create(&self.ctx.db, event).await {
+            warn!("Failed to record {} of {}: {:#}", action, self.name, e);
+        }
+    }
+
+    /// Store the compose file and .env currently on disk in the stack history
+    ///
+    /// Taken before destructive operations so they can be undone. Returns None
+    /// for stacks without a compose file in stacks_dir (unmanaged stacks).
+    pub async fn snapshot(&self, operation: &str) -> Result<Option<StackHistory>> {
+        let dir = self.path();
+        let Ok(compose_yaml) = fs::read_to_string(dir.join(&self.compose_file_name)).await else {
+            return Ok(None);
+        };
+        let compose_env = fs::read_to_string(dir.join(".env"))
+            .await
+            .unwrap_or_default();
+
+        let history = StackHistory::create(
+            &self.ctx.db,
+            NewStackHistory {
+                stack_name: self.name.to_string(),
+                operation: operation.to_string(),
+                compose_file_name: self.compose_file_name.clone(),
+                compose_yaml,
+                compose_env,
+            },
+        )
+        .await
+        .with_context(|| format!("Failed to snapshot stack before {}", operation))?;
+
+        Ok(Some(history))
+    }
+
+    /// Diff the files on disk against the given YAML and .env
+    ///
+    /// With `include_config`, the rendered `docker compose config` of both is
+    /// diffed too, which also shows changes that come from interpolating the
+    /// .env. A stack that doesn't exist yet diffs against empty files.
+    pub async fn diff(
+        &mut self,
+        compose_yaml: &str,
+        compose_env: &str,
+        include_config: bool,
+    ) -> Result<StackDiff> {
+        let disk_yaml = self.compose_yaml().await?;
+        let disk_env = self.compose_env().await?;
+        let yaml = unified_diff(&disk_yaml, compose_yaml, &self.compose_file_name);
+        let env = unified_diff(&disk_env, compose_env, ".env");
+
+        let config = if include_config {
+            let disk_config = if disk_yaml.trim().is_empty() {
+                String::new()
+            } else {
+                self.preview_config().await?.config
+            };
+            let mut next = Stack::new_with_content(
+                self.ctx.clone(),
+                self.name.clone(),
+                self.endpoint.clone(),
+                compose_yaml.to_string(),
+                compose_env.to_string(),
+            );
+            next.compose_file_name = self.compose_file_name.clone();
+            let next_config = next.preview_config().await?.config;
+            Some(unified_diff(&disk_config, &next_config, &self.compose_file_name))
+        } else {
+            None
+        };
+
+        let changed = !yaml.is_empty()
+            || !env.is_empty()
+            || config.as_ref().is_some_and(|c| !c.is_empty());
+        Ok(StackDiff {
+            yaml,
+            env,
+            config,
+            changed,
+        })
+    }
+
+    /// Whether the compose file or .env in memory differ from what is on disk
+    async fn differs_from_disk(&mut self) -> Result<bool> {
+        let dir = self.path();
+        let disk_yaml = fs::read_to_string(dir.join(&self.compose_file_name))
+            .await
+            .unwrap_or_default();
+        let disk_env = fs::read_to_string(dir.join(".env"))
+            .await
+            .unwrap_or_default();
+
+        Ok(self.compose_yaml().await? != disk_yaml || self.compose_env().await? != disk_env)
+    }
+
+    /// Write a history snapshot back to the stack directory
+    ///
+    /// Recreates the directory if the stack was deleted.
+    pub async fn restore_snapshot(ctx: Arc<ServerContext>, history: &StackHistory) -> Result<()> {
+        let name = StackName::parse(&history.stack_name)?;
+        let dir = ctx.config.stacks_dir.join(&name);
+        fs::create_dir_all(&dir)
+            .await
+            .context("Failed to create stack directory")?;
+
+        // Remove other compose files so the restored one is the one detected
+        for filename in ACCEPTED_COMPOSE_FILE_NAMES {
+            if *filename != history.compose_file_name {
+                fs::remove_file(dir.join(filename)).await.ok();
+            }
+        }
+
+        fs::write(dir.join(&history.compose_file_name), &history.compose_yaml)
+            .await
+            .context("Failed to write compose file")?;
+
+        let env_path = dir.join(".env");
+        if !history.compose_env.is_empty() || fs::metadata(&env_path).await.is_ok() {
+            fs::write(&env_path, &history.compose_env)
+                .await
+                .context("Failed to write .env file")?;
+        }
+
         Ok(())
     }
 
+    /// The deploy hooks of the compose file on disk
+    async fn deploy_hooks(&self) -> Result<DeployHooks> {
+        let path = self.path().join(&self.compose_file_name);
+        match fs::read_to_string(&path).await {
+            Ok(yaml) => parse_deploy_hooks(&yaml),
+            // Unmanaged stacks have no compose file here, and no hooks
+            Err(_) => Ok(DeployHooks::default()),
+        }
+    }
+
+    /// Run the deploy hooks of one stage
+    async fn run_hooks(
+        &self,
+        hooks: &DeployHooks,
+        stage: HookStage,
+        socket: Option<SocketRef>,
+    ) -> Result<()> {
+        crate::docker::run_hooks(
+            self.ctx.io.clone(),
+            &self.name,
+            &self.path(),
+            &self.endpoint,
+            stage,
+            hooks.commands(stage),
+            socket,
+        )
+        .await
+    }
+
     /// Deploy the stack (docker compose up -d --remove-orphans)
     ///
+    /// Runs the compose file's pre-deploy hooks first and its post-deploy hooks
+    /// afterwards; a failing pre-deploy hook aborts the deploy. If services
+    /// request a GPU the host can't provide, a failing deploy says so.
+    ///
     /// # Arguments
+    /// * `options` - Force-recreate, pull policy and build flags for `up`
     /// * `socket` - Optional socket for terminal output
-    pub async fn deploy(&self, socket: Option<SocketRef>) -> Result<i32> {
-        crate::docker::deploy(
+    pub async fn deploy(&self, options: &DeployOptions, socket: Option<SocketRef>) -> Result<i32> {
+        self.enforce_policy_on_disk().await?;
+
+        let gpu_warnings = self.gpu_warnings().await;
+        for warning in &gpu_warnings {
+            warn!("Deploying {}: {}", self.name, warning);
+        }
+
+        let hooks = self.deploy_hooks().await?;
+        self.run_hooks(&hooks, HookStage::PreDeploy, socket.clone()).await?;
+
+        let (env, _generated) = self.up_env().await?;
+        let result = crate::docker::deploy(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
-            socket,
+            options,
+            &env,
+            socket.clone(),
         )
-        .await
+        .await;
+        // Compose's own error doesn't tell that the GPU is missing
+        let exit_code = match result {
+            Err(e) if !gpu_warnings.is_empty() => {
+                return Err(anyhow!("{}\n{}", e, gpu_warnings.join("\n")));
+            }
+            result => result?,
+        };
+
+        self.run_hooks(&hooks, HookStage::PostDeploy, socket).await?;
+        Ok(exit_code)
+    }
+
+    /// Warnings about services that request a GPU when the stack's Docker host
+    /// has no GPU runtime
+    ///
+    /// Checks the compose file on disk and the files it includes. Nothing is
+    /// reported when Docker can't be asked.
+    pub async fn gpu_warnings(&self) -> Vec<String> {
+        let stack_dir = self.path();
+        let Ok(yaml) = fs::read_to_string(stack_dir.join(&self.compose_file_name)).await else {
+            return Vec::new();
+        };
+        let mut services = gpu_services(&yaml);
+        if let Ok(files) = load_includes(&stack_dir, &self.compose_file_name, &yaml) {
+            for content in files.iter().filter_map(|file| file.content.as_deref()) {
+                services.extend(gpu_services(content));
+            }
+        }
+        if services.is_empty() {
+            return Vec::new();
+        }
+
+        let runtimes = match self.docker().await {
+            Ok(docker) => crate::docker::gpu_runtimes(&docker).await,
+            Err(e) => Err(e),
+        };
+        match runtimes {
+            Ok(runtimes) if runtimes.is_empty() => services
+                .iter()
+                .map(|service| gpu_warning(service))
+                .collect(),
+            Ok(_) => Vec::new(),
+            Err(e) => {
+                debug!("Failed to check the GPU runtimes for {}: {}", self.name, e);
+                Vec::new()
+            }
+        }
     }
 
     /// Start the stack (same as deploy)
-    pub async fn start(&self, socket: Option<SocketRef>) -> Result<i32> {
-        self.deploy(socket).await
+    pub async fn start(&self, options: &DeployOptions, socket: Option<SocketRef>) -> Result<i32> {
+        self.deploy(options, socket).await
+    }
+
+    /// Build the stack's images (docker compose build)
+    pub async fn build(&self, no_cache: bool, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
+        crate::docker::build(
+            self.ctx.io.clone(),
+            &self.name,
+            &self.path(),
+            &self.ctx.config.stacks_dir,
+            &self.endpoint,
+            no_cache,
+            &env,
+            socket,
+        )
+        .await
     }
 
     /// Stop the stack (docker compose stop)
     pub async fn stop(&self, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::stop(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
+            &env,
             socket,
         )
         .await
     }
 
+    /// Start `docker compose logs` on the stack, with its output piped
+    pub async fn logs(&self, options: &ComposeLogs) -> Result<tokio::process::Child> {
+        let env = self.process_env().await?;
+        crate::docker::compose_logs(
+            &self.name,
+            &self.path(),
+            &self.ctx.config.stacks_dir,
+            options,
+            &env,
+        )
+    }
+
     /// Restart the stack (docker compose restart)
     pub async fn restart(&self, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::restart(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
+            &env,
             socket,
         )
         .await
@@ -361,46 +1160,309 @@ impl Stack {
 
     /// Down the stack (docker compose down)
     pub async fn down(&self, socket: Option<SocketRef>) -> Result<i32> {
+        self.snapshot("down").await?;
+        let env = self.process_env().await?;
         crate::docker::down(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
+            &env,
             socket,
         )
         .await
     }
 
     /// Update the stack (docker compose pull, then up -d if running)
-    pub async fn update(&mut self, socket: Option<SocketRef>) -> Result<i32> {
-        crate::docker::update(
+    ///
+    /// With `rebuild`, images are built from their build contexts instead of
+    /// pulled. Deploy hooks run around the update like they do for deploy.
+    /// When the pruneImagesAfterUpdate setting is on, images the update left
+    /// dangling are removed afterwards.
+    pub async fn update(&mut self, rebuild: bool, socket: Option<SocketRef>) -> Result<i32> {
+        self.enforce_policy_on_disk().await?;
+        self.snapshot("update").await?;
+        let hooks = self.deploy_hooks().await?;
+        self.run_hooks(&hooks, HookStage::PreDeploy, socket.clone()).await?;
+
+        let docker = self.docker().await?;
+        let old_image_ids = if self.prune_images_after_update().await {
+            crate::docker::project_image_ids(&docker, &self.name)
+                .await
+                .ok()
+        } else {
+            None
+        };
+
+        let (env, generated) = self.up_env().await?;
+        let exit_code = crate::docker::update(
             self.ctx.io.clone(),
-            &self.ctx.docker,
+            &docker,
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
-            socket,
+            rebuild,
+            &env,
+            socket.clone(),
         )
-        .await
+        .await?;
+        drop(generated);
+
+        self.run_hooks(&hooks, HookStage::PostDeploy, socket).await?;
+
+        if let Some(old_image_ids) = old_image_ids {
+            match crate::docker::prune_superseded_images(
+                &docker,
+                &self.name,
+                &old_image_ids,
+            )
+            .await
+            {
+                Ok(removed) if !removed.is_empty() => {
+                    info!("Removed {} old image(s) of {}", removed.len(), self.name)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to prune old images of {}: {}", self.name, e),
+            }
+        }
+
+        Ok(exit_code)
+    }
+
+    /// The admin's compose policy, which allows everything when not set
+    async fn compose_policy(&self) -> Result<ComposePolicy> {
+        match Setting::get(&self.ctx.db, &self.ctx.cache, COMPOSE_POLICY_SETTING).await? {
+            Some(value) => serde_json::from_value(value).context("Invalid compose policy"),
+            None => Ok(ComposePolicy::default()),
+        }
+    }
+
+    /// Fail if the compose file on disk breaks the compose policy
+    ///
+    /// Catches compose files edited outside dockru or before the policy changed.
+    async fn enforce_policy_on_disk(&self) -> Result<()> {
+        let path = self.path().join(&self.compose_file_name);
+        if let Ok(yaml) = fs::read_to_string(&path).await {
+            enforce(&self.policy_violations(&yaml).await?)?;
+        }
+        Ok(())
+    }
+
+    /// Check a compose file of this stack and the files it includes against
+    /// the compose policy
+    async fn policy_violations(&self, compose_yaml: &str) -> Result<Vec<PolicyViolation>> {
+        let policy = self.compose_policy().await?;
+        if policy == ComposePolicy::default() {
+            return Ok(Vec::new());
+        }
+
+        let stack_dir = self.path();
+        let mut violations = check_compose_policy(compose_yaml, &policy, &stack_dir, &stack_dir);
+        for file in load_includes(&stack_dir, &self.compose_file_name, compose_yaml)? {
+            let Some(content) = &file.content else {
+                continue;
+            };
+            let base_dir = stack_dir.join(&file.name);
+            let base_dir = base_dir.parent().unwrap_or(&stack_dir);
+            for mut violation in check_compose_policy(content, &policy, &stack_dir, base_dir) {
+                violation.file = Some(file.name.clone());
+                violations.push(violation);
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Whether the pruneImagesAfterUpdate setting is on (off by default)
+    async fn prune_images_after_update(&self) -> bool {
+        Setting::get(&self.ctx.db, &self.ctx.cache, "pruneImagesAfterUpdate")
+            .await
+            .ok()
+            .flatten()
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Replace `find` with `replace` in the compose file and .env (see
+    /// utils::bulk_edit)
+    ///
+    /// Only reports the edits unless `apply` is set. Applying snapshots the
+    /// files first, and fails if the edited compose file doesn't parse or
+    /// breaks the compose policy.
+    pub async fn bulk_replace(
+        &self,
+        find: &str,
+        replace: &str,
+        apply: bool,
+    ) -> Result<Vec<FileEdit>> {
+        let dir = self.path();
+        let mut edits = Vec::new();
+        for file in [self.compose_file_name.as_str(), ".env"] {
+            let Ok(content) = fs::read_to_string(dir.join(file)).await else {
+                continue;
+            };
+            edits.extend(replace_in_file(file, &content, find, replace));
+        }
+        if !apply || edits.is_empty() {
+            return Ok(edits);
+        }
+
+        if let Some(compose) = edits.iter().find(|e| e.file == self.compose_file_name) {
+            YamlLoader::load_from_str(&compose.content)
+                .map_err(|e| anyhow!("The edited compose file isn't valid YAML: {}", e))?;
+            enforce(&self.policy_violations(&compose.content).await?)?;
+        }
+        self.snapshot("bulkEdit").await?;
+        for edit in &edits {
+            fs::write(dir.join(&edit.file), &edit.content)
+                .await
+                .with_context(|| format!("Failed to write {}", edit.file))?;
+        }
+        info!("Replaced {:?} in {} files of {}", find, edits.len(), self.name);
+        Ok(edits)
+    }
+
+    /// Set the restart policy of every service in the compose file
+    ///
+    /// The previous file is kept in the stack history. With `apply`, the
+    /// stack's containers get the policy right away with `docker update`
+    /// instead of on their next deploy. Returns how many containers were
+    /// updated.
+    pub async fn set_restart_policy(&self, policy: RestartPolicy, apply: bool) -> Result<usize> {
+        if !self.is_managed_by_dockru().await {
+            anyhow::bail!("Only stacks managed by Dockru can be edited");
+        }
+
+        let path = self.path().join(&self.compose_file_name);
+        let yaml = fs::read_to_string(&path)
+            .await
+            .context("Failed to read compose file")?;
+        let edited = restart_policy::set_restart_policy(&yaml, policy)?;
+        if edited != yaml {
+            self.snapshot("restartPolicy").await?;
+            fs::write(&path, edited)
+                .await
+                .context("Failed to write compose file")?;
+            info!("Set the restart policy of {} to {}", self.name, policy);
+        }
+
+        if !apply {
+            return Ok(0);
+        }
+        let docker = self.docker().await?;
+        crate::docker::update_restart_policy(&docker, &self.name, policy).await
     }
 
     /// Delete the stack (down + remove directory)
     pub async fn delete(&self, socket: Option<SocketRef>) -> Result<i32> {
-        crate::docker::delete(
+        self.snapshot("delete").await?;
+        let env = self.process_env().await?;
+        let exit_code = crate::docker::delete(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
+            &env,
             socket,
         )
+        .await?;
+
+        let key = injected_env::setting_key(&self.name);
+        Setting::delete(&self.ctx.db, &self.ctx.cache, &key).await?;
+        Ok(exit_code)
+    }
+
+    /// Stored variables passed to compose through its environment, decrypted
+    async fn injected_env(&self) -> Result<Vec<(String, String)>> {
+        let key = injected_env::setting_key(&self.name);
+        let stored = Setting::get(&self.ctx.db, &self.ctx.cache, &key).await?;
+        if stored.is_none() {
+            return Ok(Vec::new());
+        }
+        injected_env::decrypt(stored.as_ref(), &self.ctx.require_encryption_secret()?)
+    }
+
+    /// Names of the variables injected at deploy (values are never sent back)
+    pub async fn injected_env_names(&self) -> Result<Vec<String>> {
+        let key = injected_env::setting_key(&self.name);
+        let stored = Setting::get(&self.ctx.db, &self.ctx.cache, &key).await?;
+        Ok(injected_env::names(stored.as_ref()))
+    }
+
+    /// Set (string) or remove (null) variables injected at deploy
+    pub async fn set_injected_env(
+        &self,
+        changes: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let key = injected_env::setting_key(&self.name);
+        let stored = Setting::get(&self.ctx.db, &self.ctx.cache, &key).await?;
+        let secret = self.ctx.require_encryption_secret()?;
+        let vars = injected_env::apply_changes(stored, changes, &secret)?;
+        Setting::set(
+            &self.ctx.db,
+            &self.ctx.cache,
+            &key,
+            &serde_json::Value::Object(vars),
+            Some(injected_env::INJECTED_ENV_SETTING_TYPE),
+        )
         .await
     }
 
+    /// The admin's compose env policy, which passes everything when not set
+    async fn compose_env_policy(&self) -> Result<ComposeEnvPolicy> {
+        match Setting::get(&self.ctx.db, &self.ctx.cache, COMPOSE_ENV_POLICY_SETTING).await? {
+            Some(value) => serde_json::from_value(value).context("Invalid compose env policy"),
+            None => Ok(ComposeEnvPolicy::default()),
+        }
+    }
+
+    /// Process environment of the stack's compose commands: the injected
+    /// variables, without the dockru variables the env policy holds back
+    async fn process_env(&self) -> Result<ComposeEnv> {
+        Ok(ComposeEnv {
+            vars: self.injected_env().await?,
+            removed: self.compose_env_policy().await?.removed(),
+            env_file: None,
+        })
+    }
+
+    /// Environment for `up` and `pull`: the process environment, and the .env
+    /// with its secret references filled in if it has any
+    ///
+    /// The generated env file is deleted when the returned guard is dropped.
+    async fn up_env(&self) -> Result<(ComposeEnv, Option<GeneratedEnvFile>)> {
+        let mut compose_env = self.process_env().await?;
+
+        let env = fs::read_to_string(self.path().join(".env"))
+            .await
+            .unwrap_or_default();
+        if !secrets::has_references(&env) {
+            return Ok((compose_env, None));
+        }
+
+        let names = secrets::references(&env)?;
+        let key = self.ctx.require_encryption_secret()?;
+        let mut values = HashMap::new();
+        for (name, encrypted) in StoredSecret::find_values(&self.ctx.db, &names).await? {
+            let value = decrypt_password(&encrypted, &key)
+                .with_context(|| format!("Failed to decrypt secret {}", name))?;
+            values.insert(name, value.expose_secret().clone());
+        }
+        let generated = GeneratedEnvFile::write(
+            &self.ctx.config.data_dir.join(GENERATED_ENV_DIR),
+            &self.name,
+            &secrets::interpolate(&env, &values)?,
+        )?;
+
+        compose_env.env_file = Some(generated.path().to_path_buf());
+        Ok((compose_env, Some(generated)))
+    }
+
     /// Restart a single service in the stack (docker compose restart <service>)
     pub async fn restart_service(&self, service_name: &str, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::restart_service(
             self.ctx.io.clone(),
             &self.name,
@@ -408,6 +1470,7 @@ impl Stack {
             &self.ctx.config.stacks_dir,
             &self.endpoint,
             service_name,
+            &env,
             socket,
         )
         .await
@@ -415,6 +1478,7 @@ impl Stack {
 
     /// Start a single service in the stack (docker compose start <service>)
     pub async fn start_service(&self, service_name: &str, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::start_service(
             self.ctx.io.clone(),
             &self.name,
@@ -422,6 +1486,7 @@ impl Stack {
             &self.ctx.config.stacks_dir,
             &self.endpoint,
             service_name,
+            &env,
             socket,
         )
         .await
@@ -429,6 +1494,7 @@ impl Stack {
 
     /// Stop a single service in the stack (docker compose stop <service>)
     pub async fn stop_service(&self, service_name: &str, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::stop_service(
             self.ctx.io.clone(),
             &self.name,
@@ -436,6 +1502,7 @@ impl Stack {
             &self.ctx.config.stacks_dir,
             &self.endpoint,
             service_name,
+            &env,
             socket,
         )
         .await
@@ -443,6 +1510,7 @@ impl Stack {
 
     /// Pull a new image for a single service (docker compose pull <service>)
     pub async fn pull_service(&self, service_name: &str, socket: Option<SocketRef>) -> Result<i32> {
+        let env = self.process_env().await?;
         crate::docker::pull_service(
             self.ctx.io.clone(),
             &self.name,
@@ -450,6 +1518,7 @@ impl Stack {
             &self.ctx.config.stacks_dir,
             &self.endpoint,
             service_name,
+            &env,
             socket,
         )
         .await
@@ -457,21 +1526,39 @@ impl Stack {
 
     /// Get service status list for this stack
     pub async fn get_service_status_list(&self) -> Result<HashMap<String, ServiceStatus>> {
-        let containers = crate::docker::list_containers_by_project(&self.ctx.docker, &self.name)
+        let docker = self.docker().await?;
+        let containers = crate::docker::list_containers_by_project(&docker, &self.name)
             .await
             .context("Failed to get service status")?;
 
-        Ok(crate::docker::map_to_service_status(containers))
+        let mut status_list = crate::docker::map_to_service_status(containers);
+        crate::docker::enrich_service_status(&docker, &mut status_list).await;
+
+        Ok(status_list)
     }
 
     /// Join the combined terminal (docker compose logs -f --tail 100)
     pub async fn join_combined_terminal(&self, socket: SocketRef) -> Result<()> {
+        self.join_combined_terminal_with_options(socket, &LogOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    /// Join the combined terminal with log options, returning the terminal name
+    pub async fn join_combined_terminal_with_options(
+        &self,
+        socket: SocketRef,
+        log_options: &LogOptions,
+    ) -> Result<String> {
+        let env = self.process_env().await?;
         crate::docker::join_logs_terminal(
             self.ctx.io.clone(),
             &self.name,
             &self.path(),
             &self.ctx.config.stacks_dir,
             &self.endpoint,
+            log_options,
+            &env,
             socket,
         )
         .await
@@ -488,14 +1575,19 @@ impl Stack {
     /// * `socket` - Socket to join for terminal I/O
     /// * `service_name` - Service name from compose file
     /// * `shell` - Shell to execute (e.g., "/bin/bash", "sh", "ash")
+    /// * `exec_options` - User, working directory and TERM to exec with
     /// * `index` - Terminal instance index (for multiple connections to same service)
+    ///
+    /// Returns the terminal name
     pub async fn join_container_terminal(
         &self,
         socket: SocketRef,
         service_name: &str,
         shell: &str,
+        exec_options: &ExecOptions,
         index: usize,
-    ) -> Result<()> {
+    ) -> Result<String> {
+        let env = self.process_env().await?;
         crate::docker::join_exec_terminal(
             self.ctx.io.clone(),
             &self.name,
@@ -504,18 +1596,23 @@ impl Stack {
             &self.endpoint,
             service_name,
             shell,
+            exec_options,
+            &env,
             index,
             socket,
         )
         .await
     }
 
-    /// Join a container's logs terminal (docker compose logs -f --tail 100 <service>)
+    /// Join a container's logs terminal (docker compose logs -f --tail 100 <service>),
+    /// returning the terminal name
     pub async fn join_container_logs(
         &self,
         socket: SocketRef,
         service_name: &str,
-    ) -> Result<()> {
+        log_options: &LogOptions,
+    ) -> Result<String> {
+        let env = self.process_env().await?;
         crate::docker::join_container_logs_terminal(
             self.ctx.io.clone(),
             &self.name,
@@ -523,6 +1620,19 @@ impl Stack {
             &self.ctx.config.stacks_dir,
             &self.endpoint,
             service_name,
+            log_options,
+            &env,
+            socket,
+        )
+        .await
+    }
+
+    /// Leave a container's logs terminal
+    pub async fn leave_container_logs(&self, socket: SocketRef, service_name: &str) -> Result<()> {
+        crate::docker::leave_container_logs_terminal(
+            &self.name,
+            &self.endpoint,
+            service_name,
             socket,
         )
         .await
@@ -548,15 +1658,26 @@ impl Stack {
 
     /// Get a single stack by name
     pub async fn get_stack(ctx: Arc<ServerContext>, name: &str, endpoint: String) -> Result<Stack> {
-        let stack_path = ctx.config.stacks_dir.join(name);
+        // Reject names that aren't a single directory before building any path
+        let name = StackName::parse(name)?;
+        let stack_path = ctx.config.stacks_dir.join(&name);
 
         // Check if directory exists in stacks_dir (managed stack)
         if let Ok(metadata) = fs::metadata(&stack_path).await {
             if metadata.is_dir() {
-                let mut stack = Stack::new(ctx, name.to_string(), endpoint);
+                let mut stack = Stack::new(ctx, name, endpoint);
+                stack.dir_times = Some(FileTimes::from_metadata(&metadata));
                 stack.detect_compose_file().await?;
+                stack.load_ui_hints();
                 stack.status = UNKNOWN;
                 stack.config_file_path = Some(stack_path.display().to_string());
+                // A group set in dockru takes precedence over the compose file's
+                stack.group = StackGroup::find_by_stack(&stack.ctx.db, &stack.name)
+                    .await?
+                    .or_else(|| stack.ui_hints.group.clone());
+                stack.autostart = StackAutostart::is_enabled(&stack.ctx.db, &stack.name).await?;
+                stack.update_window =
+                    StackUpdateWindow::find_by_stack(&stack.ctx.db, &stack.name).await?;
                 return Ok(stack);
             }
         }
@@ -565,7 +1686,7 @@ impl Stack {
         let stack_list = Self::get_stack_list(ctx.clone(), endpoint.clone(), true).await?;
         if let Some(stack) = stack_list
             .into_iter()
-            .find(|(n, _)| n == name)
+            .find(|(n, _)| n == name.as_str())
             .map(|(_, s)| s)
         {
             return Ok(stack);
@@ -607,6 +1728,10 @@ impl Stack {
                 Ok(name) => name,
                 Err(_) => continue,
             };
+            // Directories that can't be stack names aren't stacks
+            let Ok(name) = StackName::parse(&filename) else {
+                continue;
+            };
 
             // Check if it's a directory
             let metadata = match fs::metadata(&path).await {
@@ -623,12 +1748,32 @@ impl Stack {
                 continue;
             }
 
-            let mut stack = Stack::new(ctx.clone(), filename.clone(), endpoint.clone());
+            let mut stack = Stack::new(ctx.clone(), name, endpoint.clone());
+            stack.dir_times = Some(FileTimes::from_metadata(&metadata));
             stack.detect_compose_file().await?;
+            stack.load_ui_hints();
             stack.status = CREATED_FILE;
             stack_list.insert(filename, stack);
         }
 
+        let mut groups = StackGroup::find_all(&ctx.db).await?;
+        let autostart = StackAutostart::find_all(&ctx.db).await?;
+        let mut update_windows = StackUpdateWindow::find_all(&ctx.db).await?;
+        for (name, stack) in stack_list.iter_mut() {
+            // A group set in dockru takes precedence over the compose file's
+            stack.group = groups.remove(name).or_else(|| stack.ui_hints.group.clone());
+            stack.autostart = autostart.contains(name);
+            stack.update_window = update_windows.remove(name);
+        }
+
+        // Without the daemon no status is known, and compose ls would fail
+        if !ctx.docker.is_healthy() {
+            for stack in stack_list.values_mut() {
+                stack.status = UNKNOWN;
+            }
+            return Ok(stack_list);
+        }
+
         // Get status from docker compose ls
         let compose_projects = crate::docker::list_compose_projects().await?;
 
@@ -644,18 +1789,254 @@ impl Stack {
                 stack.config_file_path = Some(config_files);
             } else {
                 // Add unmanaged stack
-                let mut stack = Stack::new(ctx.clone(), project_name.clone(), endpoint.clone());
+                let Ok(name) = StackName::parse(&project_name) else {
+                    debug!("Ignoring compose project with invalid name {}", project_name);
+                    continue;
+                };
+                let mut stack = Stack::new(ctx.clone(), name, endpoint.clone());
                 stack.status = status;
                 stack.config_file_path = Some(config_files);
+                stack.group = groups.remove(&project_name);
+                stack.autostart = autostart.contains(&project_name);
                 stack_list.insert(project_name, stack);
             }
         }
 
+        // compose ls rolls "running(2), exited(1)" up to exited; the container
+        // states give the real picture. They're kept current from Docker events
+        // when those are followed.
+        let counts = match crate::docker_events::cached_service_counts() {
+            Some(counts) => Ok(counts),
+            None => crate::docker::project_service_counts(&ctx.docker).await,
+        };
+        match counts {
+            Ok(mut counts) => {
+                for (name, stack) in stack_list.iter_mut() {
+                    if let Some(counts) = counts.remove(name) {
+                        stack.status = counts.status();
+                        stack.service_counts = Some(counts);
+                    }
+                }
+            }
+            Err(e) => warn!(
+                "Failed to get container states, using compose ls status: {:#}",
+                e
+            ),
+        }
+
+        // Stacks deployed to a docker context run on another daemon
+        let mut by_context: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, stack) in stack_list.iter() {
+            if let Some(context) = stack.docker_context() {
+                by_context.entry(context).or_default().push(name.clone());
+            }
+        }
+        for (context, names) in by_context {
+            let counts = match ctx.docker.for_context(Some(&context)).await {
+                Ok(docker) => crate::docker::project_service_counts(&docker).await,
+                Err(e) => Err(e),
+            };
+            let mut counts = match counts {
+                Ok(counts) => counts,
+                Err(e) => {
+                    warn!("Failed to get container states of context {}: {:#}", context, e);
+                    continue;
+                }
+            };
+            for name in names {
+                if let Some(stack) = stack_list.get_mut(&name) {
+                    let counts = counts.remove(&name);
+                    stack.status = counts.map(|c| c.status()).unwrap_or(CREATED_FILE);
+                    stack.service_counts = counts;
+                }
+            }
+        }
+
         Ok(stack_list)
     }
+
+    /// Deploy or start every managed stack, in dependency order
+    ///
+    /// Progress is written to the batch terminal and each stack's output to
+    /// its own compose terminal. Stops at the first stack that fails, since the
+    /// stacks after it may depend on it. Returns the stacks in the order run.
+    /// Each stack's run is recorded in its event timeline as done by `actor`.
+    pub async fn run_all(
+        ctx: Arc<ServerContext>,
+        endpoint: String,
+        action: BatchAction,
+        actor: &str,
+        socket: Option<SocketRef>,
+    ) -> Result<Vec<String>> {
+        let terminal_name = get_batch_terminal_name(&endpoint);
+        if Terminal::get_terminal(&terminal_name).await.is_some() {
+            anyhow::bail!("Another operation is already running, please try again later.");
+        }
+
+        let mut stack_list = Self::get_stack_list(ctx.clone(), endpoint, false).await?;
+        let mut managed = Vec::new();
+        for (name, stack) in &stack_list {
+            if stack.is_managed_by_dockru().await {
+                managed.push(name.clone());
+            }
+        }
+        let dependencies = StackDependency::find_all(&ctx.db).await?;
+        let order = dependency_order(&managed, &dependencies)?;
+
+        let terminal = Terminal::new(
+            ctx.io.clone(),
+            terminal_name,
+            TerminalType::Base,
+            String::new(),
+            Vec::new(),
+            String::new(),
+        );
+        if let Some(socket) = &socket {
+            terminal.join(socket.clone()).await?;
+        }
+
+        for (i, name) in order.iter().enumerate() {
+            terminal
+                .write_output(&format!(
+                    "\x1b[1m[{}/{}] {} {}\x1b[0m\r\n",
+                    i + 1,
+                    order.len(),
+                    action.verb(),
+                    name
+                ))
+                .await;
+
+            let Some(stack) = stack_list.remove(name) else {
+                continue;
+            };
+            let result = match action {
+                BatchAction::Deploy => stack.deploy(&DeployOptions::default(), socket.clone()).await,
+                BatchAction::Start => stack.start(&DeployOptions::default(), socket.clone()).await,
+            };
+            stack
+                .record_event(action.event_name(), actor, &result)
+                .await;
+
+            if let Err(e) = result {
+                terminal
+                    .write_output(&format!("\x1b[31m{} failed: {}\x1b[0m\r\n", name, e))
+                    .await;
+                terminal.finish(1).await;
+                return Err(anyhow!("{} failed: {}", name, e));
+            }
+        }
+
+        terminal.write_output("Done\r\n").await;
+        terminal.finish(0).await;
+        Ok(order)
+    }
+
+    /// Run one action on each of the named stacks, BATCH_STACK_CONCURRENCY at a time
+    ///
+    /// Each stack's output goes to its own compose terminal, which `socket`
+    /// joins. A failing stack doesn't stop the others; each run is recorded in
+    /// the stack's event timeline as done by `actor`. Returns the results in
+    /// the order the stacks were given.
+    pub async fn run_batch(
+        ctx: Arc<ServerContext>,
+        endpoint: String,
+        stack_names: Vec<String>,
+        action: StackAction,
+        actor: String,
+        socket: Option<SocketRef>,
+    ) -> Vec<BatchStackResult> {
+        let permits = Arc::new(Semaphore::new(BATCH_STACK_CONCURRENCY));
+
+        let handles: Vec<_> = stack_names
+            .iter()
+            .map(|name| {
+                let ctx = ctx.clone();
+                let endpoint = endpoint.clone();
+                let name = name.clone();
+                let actor = actor.clone();
+                let socket = socket.clone();
+                let permits = permits.clone();
+                tokio::spawn(async move {
+                    let _permit = permits.acquire_owned().await?;
+                    let mut stack = Stack::get_stack(ctx, &name, endpoint).await?;
+                    let result = match action {
+                        StackAction::Start => stack.start(&DeployOptions::default(), socket).await,
+                        StackAction::Stop => stack.stop(socket).await,
+                        StackAction::Update => stack.update(false, socket).await,
+                        StackAction::Down => stack.down(socket).await,
+                    };
+                    stack.record_event(action.as_str(), &actor, &result).await;
+                    result
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(handles.len());
+        for (stack_name, handle) in stack_names.into_iter().zip(handles) {
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => Err(anyhow!("{} panicked: {}", action.as_str(), e)),
+            };
+            results.push(match result {
+                Ok(exit_code) => BatchStackResult {
+                    stack_name,
+                    ok: true,
+                    exit_code: Some(exit_code),
+                    error: None,
+                },
+                Err(e) => BatchStackResult {
+                    stack_name,
+                    ok: false,
+                    exit_code: None,
+                    error: Some(format!("{:#}", e)),
+                },
+            });
+        }
+        results
+    }
 }
 
 // TODO: Implement Docker operations (deploy, stop, restart, etc.)
 // TODO: Implement static methods (get_stack_list, get_status_list, etc.)
 // TODO: Implement service status parsing
 // TODO: Implement terminal operations (join_combined_terminal, etc.)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_context, test_db, StacksDir};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_update_enforces_compose_policy() {
+        let db = test_db().await;
+        let stacks = StacksDir::new();
+        stacks.stack(
+            "privileged",
+            "services:\n  app:\n    image: nginx\n    privileged: true\n",
+        );
+        stacks.stack(
+            "host-network",
+            "services:\n  app:\n    image: nginx\n    network_mode: host\n",
+        );
+        let stacks_dir = stacks.path().display().to_string();
+        let ctx = test_context(&db, &["--stacks-dir", &stacks_dir]).await;
+        Setting::set(
+            db.pool(),
+            &ctx.cache,
+            COMPOSE_POLICY_SETTING,
+            &json!({ "privileged": "deny", "hostNetwork": "deny" }),
+            None,
+        )
+        .await
+        .unwrap();
+
+        for name in ["privileged", "host-network"] {
+            let mut stack = Stack::get_stack(ctx.clone(), name, String::new())
+                .await
+                .unwrap();
+            let e = stack.update(false, None).await.unwrap_err();
+            assert!(e.to_string().contains("compose policy"), "{}: {}", name, e);
+        }
+    }
+}
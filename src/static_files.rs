@@ -1,20 +1,81 @@
+use anyhow::{Context, Result};
 use axum::{
     body::Body,
     extract::Request,
     http::{
-        header::{ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE},
-        StatusCode, Uri,
+        header::{ACCEPT, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+        HeaderMap, HeaderValue, StatusCode, Uri,
     },
-    response::{IntoResponse, Response},
+    response::{Html, IntoResponse, Response},
+    Json,
 };
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tower::ServiceExt;
 use tower_http::services::ServeDir;
-use tracing::{debug, trace};
+use tracing::{debug, info, trace, warn};
 
-/// Custom static file service that serves pre-compressed files (.br, .gz)
-/// when the client supports them, matching express-static-gzip behavior
+/// Files smaller than this aren't worth compressing
+const MIN_COMPRESS_BYTES: u64 = 1024;
+
+/// Extensions of files that compress well
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &["html", "css", "js", "mjs", "json", "svg", "txt", "map"];
+
+/// A content encoding static files can be pre-compressed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// In order of preference when the client accepts several equally
+    const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    /// Content-Encoding token
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension of the pre-compressed file, without the dot
+    fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+                Ok(out)
+            }
+            Encoding::Zstd => Ok(zstd::encode_all(data, 19)?),
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// Custom static file service that serves pre-compressed files (.br, .zst,
+/// .gz) when the client supports them, matching express-static-gzip behavior
 pub struct PreCompressedStaticFiles {
     serve_dir: ServeDir,
     base_path: PathBuf,
@@ -41,39 +102,53 @@ impl PreCompressedStaticFiles {
         }
     }
 
-    /// Detect supported encodings from Accept-Encoding header
-    fn parse_accept_encoding(accept_encoding: &str) -> (bool, bool) {
-        let lower = accept_encoding.to_lowercase();
-        let supports_br = lower.contains("br");
-        let supports_gzip = lower.contains("gzip");
-        (supports_br, supports_gzip)
+    /// Encodings the client accepts, most preferred first
+    ///
+    /// Follows the q-values in Accept-Encoding, with `q=0` meaning not
+    /// acceptable; ties go to the better compression.
+    fn parse_accept_encoding(accept_encoding: &str) -> Vec<Encoding> {
+        let mut listed: Vec<(String, f32)> = Vec::new();
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap_or("").trim().to_lowercase();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            listed.push((coding, q));
+        }
+        let q_of = |coding: &str| listed.iter().find(|(c, _)| c == coding).map(|(_, q)| *q);
+
+        // An encoding listed by name overrides the `*` wildcard
+        let mut accepted: Vec<(Encoding, f32)> = Encoding::ALL
+            .into_iter()
+            .filter_map(|e| q_of(e.as_str()).or_else(|| q_of("*")).map(|q| (e, q)))
+            .collect();
+
+        accepted.retain(|(_, q)| *q > 0.0);
+        // Stable sort keeps the preference order of Encoding::ALL among ties
+        accepted.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        accepted.into_iter().map(|(e, _)| e).collect()
     }
 
     /// Try to serve a pre-compressed version of the file
     async fn try_compressed(
         &self,
         path: &str,
-        supports_br: bool,
-        supports_gzip: bool,
-    ) -> Option<(PathBuf, &'static str)> {
+        encodings: &[Encoding],
+    ) -> Option<(PathBuf, Encoding)> {
         // Remove leading slash
         let path = path.trim_start_matches('/');
 
-        // Try brotli first (best compression)
-        if supports_br {
-            let br_path = format!("{}.br", path);
-            if let Some(full_path) = self.check_file(Path::new(&br_path)).await {
-                trace!("Serving brotli compressed: {}", br_path);
-                return Some((full_path, "br"));
-            }
-        }
-
-        // Try gzip second
-        if supports_gzip {
-            let gz_path = format!("{}.gz", path);
-            if let Some(full_path) = self.check_file(Path::new(&gz_path)).await {
-                trace!("Serving gzip compressed: {}", gz_path);
-                return Some((full_path, "gzip"));
+        for &encoding in encodings {
+            let compressed_path = format!("{}.{}", path, encoding.extension());
+            if let Some(full_path) = self.check_file(Path::new(&compressed_path)).await {
+                trace!(
+                    "Serving {} compressed: {}",
+                    encoding.as_str(),
+                    compressed_path
+                );
+                return Some((full_path, encoding));
             }
         }
 
@@ -84,8 +159,8 @@ impl PreCompressedStaticFiles {
     fn get_mime_type(path: &Path) -> &'static str {
         let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
 
-        // Strip .br or .gz if present to get actual file extension
-        let actual_path = if extension == "br" || extension == "gz" {
+        // Strip .br, .zst or .gz if present to get actual file extension
+        let actual_path = if Encoding::ALL.iter().any(|e| e.extension() == extension) {
             path.file_stem()
                 .and_then(|s| s.to_str())
                 .and_then(|s| Path::new(s).extension())
@@ -111,7 +186,7 @@ impl PreCompressedStaticFiles {
     }
 
     /// Serve a file with appropriate headers
-    async fn serve_file(path: PathBuf, encoding: Option<&str>, is_immutable: bool) -> Response {
+    async fn serve_file(path: PathBuf, encoding: Option<Encoding>, is_immutable: bool) -> Response {
         match fs::read(&path).await {
             Ok(contents) => {
                 let mime_type = Self::get_mime_type(&path);
@@ -121,8 +196,9 @@ impl PreCompressedStaticFiles {
 
                 // Set Content-Encoding if compressed
                 if let Some(enc) = encoding {
-                    response = response.header(CONTENT_ENCODING, enc);
+                    response = response.header(CONTENT_ENCODING, enc.as_str());
                 }
+                response = response.header(VARY, "Accept-Encoding");
 
                 // Set cache headers
                 // Assets in /assets/ folder are immutable (they have content hashes)
@@ -156,15 +232,11 @@ impl PreCompressedStaticFiles {
             .and_then(|v| v.to_str().ok())
             .unwrap_or("");
 
-        let (supports_br, supports_gzip) = Self::parse_accept_encoding(accept_encoding);
+        let encodings = Self::parse_accept_encoding(accept_encoding);
 
         // Try to serve pre-compressed version
-        if supports_br || supports_gzip {
-            if let Some((compressed_path, encoding)) =
-                self.try_compressed(path, supports_br, supports_gzip).await
-            {
-                return Self::serve_file(compressed_path, Some(encoding), is_immutable).await;
-            }
+        if let Some((compressed_path, encoding)) = self.try_compressed(path, &encodings).await {
+            return Self::serve_file(compressed_path, Some(encoding), is_immutable).await;
         }
 
         // Fall back to regular file serving via ServeDir
@@ -180,6 +252,11 @@ impl PreCompressedStaticFiles {
                 response
                     .headers_mut()
                     .insert(CACHE_CONTROL, cache_value.parse().unwrap());
+                // Caches must not hand this to a client that could have had
+                // a compressed variant, or the other way around
+                response
+                    .headers_mut()
+                    .insert(VARY, HeaderValue::from_static("Accept-Encoding"));
                 response.into_response()
             }
             Err(err) => {
@@ -190,23 +267,205 @@ impl PreCompressedStaticFiles {
     }
 }
 
+/// Response for a path no route or static file matched
+///
+/// Browser navigations get index.html so the SPA router can handle the path.
+/// Anything else, e.g. a missing script or an unknown `/api/` path, gets a
+/// 404 instead of a page the caller can't use.
+pub fn not_found(path: &str, headers: &HeaderMap, index_html: Option<String>) -> Response {
+    if path == "/api" || path.starts_with("/api/") {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "ok": false, "msg": "Not found" })),
+        )
+            .into_response();
+    }
+
+    match index_html {
+        Some(html) if is_navigation(headers) => Html(html).into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Whether a request is a browser loading a page
+fn is_navigation(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Write missing or outdated compressed variants of the assets in `dir`
+///
+/// Returns how many files were written. Runs at startup so a frontend build
+/// without pre-compressed files is still served compressed; a read-only
+/// directory is skipped with a warning.
+pub fn precompress_dir(dir: &Path) -> usize {
+    let mut written = 0;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let entries = match std::fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read {:?}: {}", current, e);
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if is_compressible(&path, metadata.len()) {
+                match precompress_file(&path, &metadata) {
+                    Ok(count) => written += count,
+                    Err(e) => {
+                        warn!("Failed to pre-compress {:?}: {}", path, e);
+                        return written;
+                    }
+                }
+            }
+        }
+    }
+
+    if written > 0 {
+        info!("Pre-compressed {} static files", written);
+    }
+    written
+}
+
+fn is_compressible(path: &Path, len: u64) -> bool {
+    len >= MIN_COMPRESS_BYTES
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| COMPRESSIBLE_EXTENSIONS.contains(&e))
+}
+
+/// Compress one file with every encoding that has no up-to-date variant yet
+fn precompress_file(path: &Path, metadata: &std::fs::Metadata) -> Result<usize> {
+    let modified = metadata.modified().ok();
+    let mut data = None;
+    let mut written = 0;
+
+    for encoding in Encoding::ALL {
+        let mut target = path.as_os_str().to_owned();
+        target.push(".");
+        target.push(encoding.extension());
+        let target = PathBuf::from(target);
+
+        let up_to_date = match (std::fs::metadata(&target), modified) {
+            (Ok(existing), Some(modified)) => existing.modified().is_ok_and(|m| m >= modified),
+            (Ok(_), None) => true,
+            (Err(_), _) => false,
+        };
+        if up_to_date {
+            continue;
+        }
+
+        if data.is_none() {
+            data = Some(std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?);
+        }
+        let data = data.as_deref().unwrap_or_default();
+        let compressed = encoding.compress(data)?;
+        if compressed.len() >= data.len() {
+            continue;
+        }
+
+        // Write to a temporary name first so a request never sees half a file
+        let mut tmp = target.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        std::fs::write(&tmp, &compressed).with_context(|| format!("Failed to write {:?}", tmp))?;
+        std::fs::rename(&tmp, &target).with_context(|| format!("Failed to write {:?}", target))?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_parse_accept_encoding() {
-        let (br, gzip) = PreCompressedStaticFiles::parse_accept_encoding("gzip, deflate, br");
-        assert!(br);
-        assert!(gzip);
+        let encodings = PreCompressedStaticFiles::parse_accept_encoding("gzip, deflate, br");
+        assert_eq!(encodings, vec![Encoding::Brotli, Encoding::Gzip]);
+
+        let encodings = PreCompressedStaticFiles::parse_accept_encoding("gzip, deflate");
+        assert_eq!(encodings, vec![Encoding::Gzip]);
 
-        let (br, gzip) = PreCompressedStaticFiles::parse_accept_encoding("gzip, deflate");
-        assert!(!br);
-        assert!(gzip);
+        let encodings = PreCompressedStaticFiles::parse_accept_encoding("identity");
+        assert!(encodings.is_empty());
 
-        let (br, gzip) = PreCompressedStaticFiles::parse_accept_encoding("identity");
-        assert!(!br);
-        assert!(!gzip);
+        let encodings = PreCompressedStaticFiles::parse_accept_encoding("gzip, deflate, br, zstd");
+        assert_eq!(
+            encodings,
+            vec![Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+        );
+    }
+
+    #[test]
+    fn test_parse_accept_encoding_q_values() {
+        let encodings =
+            PreCompressedStaticFiles::parse_accept_encoding("br;q=0.5, zstd, gzip;q=0.8");
+        assert_eq!(
+            encodings,
+            vec![Encoding::Zstd, Encoding::Gzip, Encoding::Brotli]
+        );
+
+        let encodings = PreCompressedStaticFiles::parse_accept_encoding("*, br;q=0");
+        assert_eq!(encodings, vec![Encoding::Zstd, Encoding::Gzip]);
+    }
+
+    #[test]
+    fn test_not_found() {
+        let index = Some("<html></html>".to_string());
+        let mut navigation = HeaderMap::new();
+        navigation.insert(ACCEPT, HeaderValue::from_static("text/html,*/*;q=0.8"));
+        let mut script = HeaderMap::new();
+        script.insert(ACCEPT, HeaderValue::from_static("*/*"));
+
+        let response = not_found("/compose/web", &navigation, index.clone());
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = not_found("/assets/missing.js", &script, index.clone());
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = not_found("/api/nope", &navigation, index.clone());
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            HeaderValue::from_static("application/json")
+        );
+
+        let response = not_found("/compose/web", &navigation, None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_precompress_dir() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let assets = temp.path().join("assets");
+        std::fs::create_dir(&assets).unwrap();
+        std::fs::write(assets.join("app.js"), "console.log('hello');\n".repeat(200)).unwrap();
+        std::fs::write(temp.path().join("tiny.css"), "a{}").unwrap();
+        std::fs::write(temp.path().join("logo.png"), vec![0u8; 4096]).unwrap();
+
+        assert_eq!(precompress_dir(temp.path()), 3);
+        for ext in ["br", "zst", "gz"] {
+            assert!(assets.join(format!("app.js.{}", ext)).exists());
+        }
+        assert!(!temp.path().join("tiny.css.gz").exists());
+        assert!(!temp.path().join("logo.png.gz").exists());
+
+        // Up-to-date variants are left alone
+        assert_eq!(precompress_dir(temp.path()), 0);
     }
 
     #[test]
@@ -223,6 +482,10 @@ mod tests {
             PreCompressedStaticFiles::get_mime_type(Path::new("app.js.gz")),
             "text/javascript; charset=utf-8"
         );
+        assert_eq!(
+            PreCompressedStaticFiles::get_mime_type(Path::new("app.js.zst")),
+            "text/javascript; charset=utf-8"
+        );
         assert_eq!(
             PreCompressedStaticFiles::get_mime_type(Path::new("style.css")),
             "text/css; charset=utf-8"
@@ -7,21 +7,31 @@
 //
 // Key features:
 // - PTY spawning with configurable rows/cols
-// - Output buffering (circular buffer, last 100 chunks)
+// - Output buffering (circular buffer, last 100 chunks within a byte budget)
 // - Socket room-based broadcasting (terminalWrite, terminalExit events)
 // - Auto-kick disconnected clients (60s interval)
 // - Optional keep-alive (close if no clients for 60s)
 // - Static registry: RwLock<HashMap<String, Arc<Terminal>>>
+// - Joined clients per terminal (socket id -> username), so admins can see and
+//   kick who is attached to a session
 // - exec() — one-shot command execution returning exit code
 
-use crate::utils::constants::{PROGRESS_TERMINAL_ROWS, TERMINAL_COLS, TERMINAL_ROWS};
+use crate::utils::constants::{
+    DEFAULT_TERMINAL_BUFFER_BYTES, DEFAULT_TERMINAL_LANG, DEFAULT_TERMINAL_TERM,
+    PROGRESS_TERMINAL_ROWS, TERMINAL_BUFFER_CHUNKS, TERMINAL_COLS, TERMINAL_ROWS,
+};
+use crate::utils::docker::CommandTrace;
 use crate::utils::limit_queue::LimitQueue;
-use anyhow::{Context, Result};
-use once_cell::sync::Lazy;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use dockru_protocol::events::server as server_event;
+use once_cell::sync::{Lazy, OnceCell};
 use portable_pty::{CommandBuilder, PtyPair, PtySize};
+use serde::Serialize;
 use socketioxide::extract::SocketRef;
 use std::collections::HashMap;
 use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, RwLock};
@@ -39,6 +49,27 @@ pub enum TerminalType {
     Main,
 }
 
+impl TerminalType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TerminalType::Base => "base",
+            TerminalType::Interactive => "interactive",
+            TerminalType::Main => "main",
+        }
+    }
+}
+
+/// A socket attached to a terminal
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalClient {
+    pub socket_id: String,
+    /// None if the socket is not logged in (e.g. an agent connection)
+    pub username: Option<String>,
+    pub user_agent: Option<String>,
+    pub joined_at: DateTime<Utc>,
+}
+
 /// Represents a pseudo-terminal with PTY support
 pub struct Terminal {
     /// Terminal type (Base, Interactive, Main)
@@ -57,7 +88,7 @@ struct TerminalInner {
     pty_pair: Option<PtyPair>,
     /// PTY writer (kept alive to prevent stdin EOF)
     pty_writer: Option<Box<dyn std::io::Write + Send>>,
-    /// Output buffer (last 100 chunks)
+    /// Output buffer (last 100 chunks, at most BUFFER_BYTE_LIMIT bytes)
     buffer: LimitQueue<String>,
     /// Number of rows
     rows: u16,
@@ -67,12 +98,117 @@ struct TerminalInner {
     enable_keep_alive: bool,
     /// Exit callback
     on_exit_callback: Option<Box<dyn FnOnce(i32) + Send>>,
+    /// Output filter applied before buffering/broadcasting
+    output_filter: Option<OutputFilter>,
+    /// Changes to the environment the spawned command inherits
+    env: CommandEnv,
+    /// Sockets that joined, by socket id
+    clients: HashMap<String, TerminalClient>,
     /// Reader task handle
     reader_task: Option<JoinHandle<()>>,
     /// Cleanup tasks handle (kick clients + keep alive)
     cleanup_task: Option<JoinHandle<()>>,
 }
 
+/// Transforms PTY output before it is buffered and broadcast. May hold data back
+/// by returning an empty string.
+pub type OutputFilter = Box<dyn FnMut(&str) -> String + Send>;
+
+/// Byte budget for each terminal's output buffer, set from the config at startup
+static BUFFER_BYTE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_TERMINAL_BUFFER_BYTES);
+
+/// Set the output buffer budget of terminals created from now on
+pub fn set_buffer_byte_limit(bytes: usize) {
+    BUFFER_BYTE_LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+/// Changes to the environment a terminal's command inherits from dockru
+///
+/// Removals apply to the inherited variables only, so they never drop TERM,
+/// the docker connection variables or `vars`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandEnv {
+    /// Variables set for the command
+    pub vars: Vec<(String, String)>,
+    /// Inherited variables the command doesn't get
+    pub removed: Vec<String>,
+}
+
+/// TERM and locale of terminals, set from the config at startup
+static TERMINAL_ENV: OnceCell<TerminalEnv> = OnceCell::new();
+
+/// Terminal type and locale given to terminal processes and container shells
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalEnv {
+    pub term: String,
+    /// Empty to leave LANG unset
+    pub lang: String,
+}
+
+impl Default for TerminalEnv {
+    fn default() -> Self {
+        Self {
+            term: DEFAULT_TERMINAL_TERM.to_string(),
+            lang: DEFAULT_TERMINAL_LANG.to_string(),
+        }
+    }
+}
+
+impl TerminalEnv {
+    pub fn validate(&self) -> Result<()> {
+        validate_term(&self.term)?;
+        let valid_lang = self
+            .lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '@'));
+        if !valid_lang {
+            return Err(anyhow!("Invalid terminal locale {:?}", self.lang));
+        }
+        Ok(())
+    }
+
+    /// TERM, COLORTERM and LANG, with `term` in place of the configured TERM
+    ///
+    /// COLORTERM=truecolor tells programs that xterm.js renders 24-bit colors.
+    pub fn vars(&self, term: Option<&str>) -> Vec<(String, String)> {
+        let term = term.unwrap_or(&self.term);
+        let mut vars = vec![("TERM".to_string(), term.to_string())];
+        if term != "dumb" {
+            vars.push(("COLORTERM".to_string(), "truecolor".to_string()));
+        }
+        if !self.lang.is_empty() {
+            vars.push(("LANG".to_string(), self.lang.clone()));
+        }
+        vars
+    }
+}
+
+/// Check a TERM value, e.g. "xterm-256color"
+pub fn validate_term(term: &str) -> Result<()> {
+    let valid = !term.is_empty()
+        && term.len() <= 64
+        && term
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '+'));
+    if !valid {
+        return Err(anyhow!("Invalid terminal type {:?}", term));
+    }
+    Ok(())
+}
+
+/// Set the TERM and locale of terminals started from now on
+pub fn set_terminal_env(env: TerminalEnv) {
+    if TERMINAL_ENV.set(env).is_err() {
+        debug!("Terminal environment was already set");
+    }
+}
+
+/// The TERM and locale of terminals, the defaults until configured
+pub fn terminal_env() -> &'static TerminalEnv {
+    static DEFAULT: Lazy<TerminalEnv> = Lazy::new(TerminalEnv::default);
+    TERMINAL_ENV.get().unwrap_or(&DEFAULT)
+}
+
 /// Static registry of all active terminals
 static TERMINAL_REGISTRY: Lazy<RwLock<HashMap<String, Arc<Terminal>>>> =
     Lazy::new(|| RwLock::new(HashMap::new()));
@@ -102,11 +238,15 @@ impl Terminal {
             inner: Arc::new(Mutex::new(TerminalInner {
                 pty_pair: None,
                 pty_writer: None,
-                buffer: LimitQueue::new(100),
+                buffer: LimitQueue::new(TERMINAL_BUFFER_CHUNKS)
+                    .with_byte_limit(BUFFER_BYTE_LIMIT.load(Ordering::Relaxed), String::len),
                 rows: TERMINAL_ROWS,
                 cols: TERMINAL_COLS,
                 enable_keep_alive: false,
                 on_exit_callback: None,
+                output_filter: None,
+                env: CommandEnv::default(),
+                clients: HashMap::new(),
                 reader_task: None,
                 cleanup_task: None,
             })),
@@ -169,7 +309,6 @@ impl Terminal {
     }
 
     /// Get terminal name
-    #[allow(dead_code)]
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -216,12 +355,44 @@ impl Terminal {
         Ok(())
     }
 
+    /// Set rows and columns together with a single PTY resize
+    pub async fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        inner.rows = rows;
+        inner.cols = cols;
+        debug!("Terminal {} size: {}x{}", self.name, rows, cols);
+        if let Some(ref pty_pair) = inner.pty_pair {
+            pty_pair
+                .master
+                .resize(PtySize {
+                    rows,
+                    cols,
+                    pixel_width: 0,
+                    pixel_height: 0,
+                })
+                .context("Failed to resize PTY")?;
+        }
+        Ok(())
+    }
+
     /// Enable keep-alive (terminal closes if no clients for 60s)
     pub async fn enable_keep_alive(&self, enable: bool) {
         let mut inner = self.inner.lock().await;
         inner.enable_keep_alive = enable;
     }
 
+    /// Set a filter for output (only applies to output read after this call)
+    pub async fn set_output_filter(&self, filter: OutputFilter) {
+        let mut inner = self.inner.lock().await;
+        inner.output_filter = Some(filter);
+    }
+
+    /// Set the command's environment changes (applies on start)
+    pub async fn set_env(&self, env: CommandEnv) {
+        let mut inner = self.inner.lock().await;
+        inner.env = env;
+    }
+
     /// Start the terminal (spawn PTY and begin output monitoring)
     pub async fn start(
         self: &Arc<Self>,
@@ -239,6 +410,7 @@ impl Terminal {
         let rows = inner.rows;
         let cols = inner.cols;
         let enable_keep_alive = inner.enable_keep_alive;
+        let env = inner.env.clone();
 
         drop(inner); // Release lock before spawning tasks
 
@@ -257,7 +429,18 @@ impl Terminal {
         let mut cmd = CommandBuilder::new(&file);
         cmd.args(&args);
         cmd.cwd(&cwd);
-        cmd.env("TERM", "xterm-256color");
+        for key in &env.removed {
+            cmd.env_remove(key);
+        }
+        for (key, value) in terminal_env().vars(None) {
+            cmd.env(key, value);
+        }
+        for (key, value) in crate::docker_host::cli_env() {
+            cmd.env(key, value);
+        }
+        for (key, value) in env.vars {
+            cmd.env(key, value);
+        }
 
         let mut child = pty_pair
             .slave
@@ -363,21 +546,39 @@ impl Terminal {
 
     /// Broadcast output to all connected clients
     async fn broadcast_output(&self, data: &str) {
-        // Add to buffer
-        {
+        // Filter, then add to buffer
+        let data = {
             let mut inner = self.inner.lock().await;
-            inner.buffer.push(data.to_string());
-        }
+            let data = match inner.output_filter.as_mut() {
+                Some(filter) => filter(data),
+                None => data.to_string(),
+            };
+            if data.is_empty() {
+                return;
+            }
+            inner.buffer.push(data.clone());
+            data
+        };
 
         // Broadcast to all sockets in the terminal's room
         let room_name = self.name.clone();
         let _ = self
             .io
             .to(room_name)
-            .emit("agent", &("terminalWrite", &self.name, data))
+            .emit(server_event::AGENT, &(server_event::TERMINAL_WRITE, &self.name, &data))
             .await;
     }
 
+    /// Write output that doesn't come from a process, e.g. progress messages
+    pub async fn write_output(&self, data: &str) {
+        self.broadcast_output(data).await;
+    }
+
+    /// End a terminal that never started a process, as if it exited
+    pub async fn finish(&self, exit_code: i32) {
+        self.handle_exit(exit_code).await;
+    }
+
     /// Spawn cleanup task for kicking disconnected clients and keep-alive
     fn spawn_cleanup_task(&self, enable_keep_alive: bool) -> JoinHandle<()> {
         let name = self.name.clone();
@@ -435,7 +636,7 @@ impl Terminal {
         let _ = self
             .io
             .to(room_name)
-            .emit("terminalExit", &(&self.name, exit_code))
+            .emit(server_event::TERMINAL_EXIT, &(&self.name, exit_code))
             .await;
 
         // Call exit callback
@@ -462,6 +663,7 @@ impl Terminal {
         // Remove from registry
         let mut registry = TERMINAL_REGISTRY.write().await;
         registry.remove(&self.name);
+        crate::terminal_audit::forget_terminal(&self.name);
 
         debug!("Terminal {} removed from registry", self.name);
     }
@@ -479,6 +681,20 @@ impl Terminal {
     pub async fn join(&self, socket: SocketRef) -> Result<()> {
         let room_name = self.name.clone();
         socket.join(room_name);
+
+        let socket_id = socket.id.to_string();
+        let mut inner = self.inner.lock().await;
+        inner
+            .clients
+            .entry(socket_id.clone())
+            .or_insert_with(|| TerminalClient {
+                username: crate::socket_handlers::get_username(&socket_id),
+                user_agent: crate::socket_handlers::get_user_agent(&socket_id),
+                socket_id,
+                joined_at: Utc::now(),
+            });
+        drop(inner);
+
         debug!("Socket {} joined terminal {}", socket.id, self.name);
         Ok(())
     }
@@ -487,6 +703,11 @@ impl Terminal {
     pub async fn leave(&self, socket: SocketRef) -> Result<()> {
         let room_name = self.name.clone();
         socket.leave(room_name.clone());
+        self.inner
+            .lock()
+            .await
+            .clients
+            .remove(&socket.id.to_string());
         debug!("Socket {} left terminal {}", socket.id, self.name);
 
         // Schedule terminal closure if room became empty
@@ -495,6 +716,27 @@ impl Terminal {
         Ok(())
     }
 
+    /// Sockets currently attached to this terminal, oldest first
+    ///
+    /// Entries for sockets that left the room without leave() (e.g. on
+    /// disconnect) are dropped here.
+    pub async fn clients(&self) -> Vec<TerminalClient> {
+        let in_room: Vec<String> = self
+            .io
+            .within(self.name.clone())
+            .sockets()
+            .iter()
+            .map(|s| s.id.to_string())
+            .collect();
+
+        let mut inner = self.inner.lock().await;
+        inner.clients.retain(|id, _| in_room.contains(id));
+
+        let mut clients: Vec<TerminalClient> = inner.clients.values().cloned().collect();
+        clients.sort_by_key(|c| c.joined_at);
+        clients
+    }
+
     /// Get terminal output buffer
     pub async fn get_buffer(&self) -> String {
         let inner = self.inner.lock().await;
@@ -505,6 +747,11 @@ impl Terminal {
         }
     }
 
+    /// Size of the output buffer in bytes
+    pub async fn buffer_size(&self) -> usize {
+        self.inner.lock().await.buffer.byte_size()
+    }
+
     /// Close the terminal (send Ctrl+C)
     #[allow(dead_code)]
     pub async fn close(&self) -> Result<()> {
@@ -561,6 +808,12 @@ impl Terminal {
         registry.get(name).cloned()
     }
 
+    /// Get every terminal in the registry
+    pub async fn get_all_terminals() -> Vec<Arc<Terminal>> {
+        let registry = TERMINAL_REGISTRY.read().await;
+        registry.values().cloned().collect()
+    }
+
     /// Get or create a terminal
     pub async fn get_or_create_terminal(
         io: socketioxide::SocketIo,
@@ -600,6 +853,34 @@ impl Terminal {
         file: String,
         args: Vec<String>,
         cwd: String,
+    ) -> Result<i32> {
+        Self::exec_with_env(
+            io,
+            socket,
+            terminal_name,
+            file,
+            args,
+            cwd,
+            CommandEnv::default(),
+            None,
+        )
+        .await
+    }
+
+    /// Like [`Terminal::exec`], with changes to the command's environment and
+    /// an optional filter for its output
+    ///
+    /// The variables are not logged or written to the terminal output.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn exec_with_env(
+        io: socketioxide::SocketIo,
+        socket: Option<SocketRef>,
+        terminal_name: String,
+        file: String,
+        args: Vec<String>,
+        cwd: String,
+        env: CommandEnv,
+        output_filter: Option<OutputFilter>,
     ) -> Result<i32> {
         // Check if terminal already exists
         {
@@ -621,12 +902,21 @@ impl Terminal {
 
         // Set progress terminal size
         terminal.set_rows(PROGRESS_TERMINAL_ROWS).await?;
+        terminal.set_env(env).await;
+        if let Some(filter) = output_filter {
+            terminal.set_output_filter(filter).await;
+        }
 
         // Join socket if provided
         if let Some(socket) = socket {
             terminal.join(socket).await?;
         }
 
+        let trace = CommandTrace::start(&file, &args, &cwd);
+        terminal
+            .write_output(&format!("[{}] $ {}\r\n", trace.id(), trace.command()))
+            .await;
+
         // Create channel for exit code
         let (tx, rx) = tokio::sync::oneshot::channel();
 
@@ -638,10 +928,14 @@ impl Terminal {
             .await;
 
         // Start terminal
-        terminal.start(file, args, cwd).await?;
+        if let Err(e) = terminal.start(file, args, cwd).await {
+            trace.fail(&e);
+            return Err(e);
+        }
 
         // Wait for exit
         let exit_code = rx.await.unwrap_or(1);
+        trace.finish(exit_code);
 
         Ok(exit_code)
     }
@@ -652,6 +946,25 @@ impl Terminal {
         let registry = TERMINAL_REGISTRY.read().await;
         registry.len()
     }
+
+    /// Total size of all terminals' output buffers in bytes
+    pub async fn get_total_buffer_size() -> usize {
+        let mut total = 0;
+        for terminal in Self::get_all_terminals().await {
+            total += terminal.buffer_size().await;
+        }
+        total
+    }
+
+    /// Get count of active terminals grouped by terminal type
+    pub async fn get_terminal_count_by_type() -> HashMap<&'static str, usize> {
+        let registry = TERMINAL_REGISTRY.read().await;
+        let mut counts = HashMap::new();
+        for terminal in registry.values() {
+            *counts.entry(terminal.terminal_type.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
 }
 
 /// Schedule terminal closure if its room is empty
@@ -731,6 +1044,26 @@ mod tests {
         io
     }
 
+    #[test]
+    fn test_terminal_env_vars() {
+        let env = TerminalEnv::default();
+        let vars = env.vars(None);
+        assert!(vars.contains(&("TERM".to_string(), "xterm-256color".to_string())));
+        assert!(vars.contains(&("COLORTERM".to_string(), "truecolor".to_string())));
+        assert!(vars.contains(&("LANG".to_string(), "C.UTF-8".to_string())));
+
+        let env = TerminalEnv {
+            term: "xterm".to_string(),
+            lang: String::new(),
+        };
+        let vars = env.vars(Some("dumb"));
+        assert_eq!(vars, vec![("TERM".to_string(), "dumb".to_string())]);
+
+        assert!(validate_term("screen.xterm-256color").is_ok());
+        assert!(validate_term("").is_err());
+        assert!(validate_term("xterm; rm -rf /").is_err());
+    }
+
     #[tokio::test]
     async fn test_terminal_creation() {
         let io = create_test_io();
@@ -747,6 +1080,13 @@ mod tests {
         assert_eq!(terminal.terminal_type(), TerminalType::Base);
     }
 
+    #[test]
+    fn test_terminal_type_as_str() {
+        assert_eq!(TerminalType::Base.as_str(), "base");
+        assert_eq!(TerminalType::Interactive.as_str(), "interactive");
+        assert_eq!(TerminalType::Main.as_str(), "main");
+    }
+
     #[tokio::test]
     async fn test_terminal_registry() {
         let io = create_test_io();
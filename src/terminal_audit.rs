@@ -0,0 +1,275 @@
+// Terminal input auditing
+//
+// Some compliance environments only allow shell access when what is typed
+// into it is kept. With the terminalInputAudit setting on, input written to
+// Interactive and Main terminals is recorded into the audit log, one entry per
+// line entered (Enter, or a newline in pasted text).
+//
+// Keystrokes arrive a few bytes at a time, so they are collected per terminal
+// and user until a line is complete. Backspace and Ctrl+U edit the line,
+// Ctrl+C drops it, and escape sequences (arrow keys and the like) are left
+// out. Edits the shell makes itself, like tab completion or history recall,
+// aren't seen.
+//
+// A line entered right after the terminal printed what looks like a password
+// prompt ("Password:", "Enter passphrase for key:", ...) is recorded as
+// [redacted], as the shell doesn't echo it.
+
+use crate::db::models::{AuditEntry, NewAuditEntry};
+use crate::server::ServerContext;
+use crate::terminal::Terminal;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// General setting turning auditing on
+pub const TERMINAL_INPUT_AUDIT_SETTING: &str = "terminalInputAudit";
+
+/// Audit action of recorded lines
+const AUDIT_ACTION: &str = "terminalInput";
+
+/// Recorded in place of input typed at a password prompt
+const REDACTED: &str = "[redacted]";
+
+/// Longest line kept, the rest of a longer one is dropped
+const MAX_LINE_CHARS: usize = 4096;
+
+/// How much of the end of the terminal output is searched for a prompt
+const PROMPT_TAIL_CHARS: usize = 512;
+
+/// Output ending in a prompt for a secret
+static PASSWORD_PROMPT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(password|passphrase|passcode|pin|secret|token)\b[^\n]*:\s*$").unwrap()
+});
+
+/// Escape sequences, e.g. colors in the terminal output
+static ANSI_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\x1b(\[[0-9;?]*[ -/]*[@-~]|\][^\x07]*\x07|[@-Z\\-_])").unwrap());
+
+/// Partial lines by (terminal name, username)
+static PENDING: Lazy<Mutex<HashMap<(String, String), LineBuffer>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Whether auditing is on, off when the setting can't be read
+pub async fn is_enabled(ctx: &ServerContext) -> bool {
+    crate::db::models::Setting::get(&ctx.db, &ctx.cache, TERMINAL_INPUT_AUDIT_SETTING)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Record the lines completed by input written to a terminal
+pub async fn record_input(
+    ctx: &ServerContext,
+    terminal: &Terminal,
+    actor: &str,
+    input: &str,
+) -> Result<()> {
+    let lines = {
+        let key = (terminal.name().to_string(), actor.to_string());
+        let mut pending = PENDING.lock().unwrap();
+        let buffer = pending.entry(key.clone()).or_default();
+        let lines = buffer.feed(input);
+        if buffer.is_empty() {
+            pending.remove(&key);
+        }
+        lines
+    };
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    // The prompt is still the last output when the first line is entered;
+    // lines pasted after it are answered by whatever the prompt runs
+    let at_prompt = at_password_prompt(&terminal.get_buffer().await);
+    for (i, line) in lines.into_iter().enumerate() {
+        let detail = if i == 0 && at_prompt {
+            REDACTED.to_string()
+        } else {
+            line
+        };
+        AuditEntry::create(
+            &ctx.db,
+            NewAuditEntry {
+                actor: actor.to_string(),
+                action: AUDIT_ACTION.to_string(),
+                target: terminal.name().to_string(),
+                detail,
+            },
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Forget the partial lines typed into a terminal that has closed
+pub fn forget_terminal(terminal_name: &str) {
+    PENDING
+        .lock()
+        .unwrap()
+        .retain(|(name, _), _| name != terminal_name);
+}
+
+/// Whether the terminal output ends in a prompt for a secret
+fn at_password_prompt(output: &str) -> bool {
+    let start = output
+        .char_indices()
+        .rev()
+        .nth(PROMPT_TAIL_CHARS)
+        .map_or(0, |(i, _)| i);
+    let tail = ANSI_RE.replace_all(&output[start..], "");
+    let last_line = tail
+        .trim_end_matches(['\r', '\n'])
+        .rsplit(['\r', '\n'])
+        .next()
+        .unwrap_or("");
+    PASSWORD_PROMPT_RE.is_match(last_line)
+}
+
+/// Line editing state of the input typed into a terminal
+#[derive(Debug, Default)]
+struct LineBuffer {
+    line: String,
+    /// Inside an escape sequence
+    escape: Escape,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    #[default]
+    None,
+    /// After ESC
+    Start,
+    /// In a CSI (ESC [) or SS3 (ESC O) sequence
+    Sequence,
+}
+
+impl LineBuffer {
+    /// Apply input, returning the non-empty lines it completes
+    fn feed(&mut self, input: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        for c in input.chars() {
+            match self.escape {
+                Escape::Start => {
+                    self.escape = if c == '[' || c == 'O' {
+                        Escape::Sequence
+                    } else {
+                        Escape::None
+                    };
+                    continue;
+                }
+                Escape::Sequence => {
+                    if ('@'..='~').contains(&c) {
+                        self.escape = Escape::None;
+                    }
+                    continue;
+                }
+                Escape::None => {}
+            }
+
+            match c {
+                '\r' | '\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    if !line.trim().is_empty() {
+                        lines.push(line);
+                    }
+                }
+                '\x1b' => self.escape = Escape::Start,
+                // Backspace / DEL
+                '\x08' | '\x7f' => {
+                    self.line.pop();
+                }
+                // Ctrl+C, Ctrl+U
+                '\x03' | '\x15' => self.line.clear(),
+                '\t' => self.push('\t'),
+                c if c.is_control() => {}
+                c => self.push(c),
+            }
+        }
+        lines
+    }
+
+    fn push(&mut self, c: char) {
+        if self.line.chars().count() < MAX_LINE_CHARS {
+            self.line.push(c);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.line.is_empty() && self.escape == Escape::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer_collects_keystrokes() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.feed("l").is_empty());
+        assert!(buffer.feed("s -la").is_empty());
+        assert_eq!(buffer.feed("\r"), vec!["ls -la"]);
+        assert!(buffer.is_empty());
+
+        // Empty lines aren't recorded
+        assert!(buffer.feed("\r  \r").is_empty());
+
+        // Pasted text
+        assert_eq!(
+            buffer.feed("cd /app\necho hi\r\nexit"),
+            vec!["cd /app", "echo hi"]
+        );
+        assert_eq!(buffer.feed("\r"), vec!["exit"]);
+    }
+
+    #[test]
+    fn test_line_buffer_editing() {
+        let mut buffer = LineBuffer::default();
+        assert_eq!(buffer.feed("rmx\x7f -rf\x08f /tmp\r"), vec!["rm -rf /tmp"]);
+
+        // Ctrl+C and Ctrl+U drop the line
+        assert!(buffer.feed("reboot\x03").is_empty());
+        assert!(buffer.feed("shutdown\x15").is_empty());
+        assert_eq!(buffer.feed("id\r"), vec!["id"]);
+
+        // Arrow keys, split across writes
+        assert!(buffer.feed("ps\x1b[").is_empty());
+        assert!(!buffer.is_empty());
+        assert_eq!(buffer.feed("D\x1bOA aux\r"), vec!["ps aux"]);
+    }
+
+    #[test]
+    fn test_at_password_prompt() {
+        assert!(at_password_prompt(
+            "$ sudo ls\r\n[sudo] password for root: "
+        ));
+        assert!(at_password_prompt(
+            "$ ssh-add\nEnter passphrase for /root/.ssh/id_ed25519: "
+        ));
+        assert!(at_password_prompt("\x1b[1mPassword:\x1b[0m "));
+        assert!(at_password_prompt("Enter PIN: "));
+
+        assert!(!at_password_prompt(""));
+        assert!(!at_password_prompt("root@web:/app# "));
+        assert!(!at_password_prompt("Password: \r\nroot@web:/app# "));
+        assert!(!at_password_prompt("$ cat passwords.txt\nhunter2\n$ "));
+    }
+
+    #[test]
+    fn test_forget_terminal() {
+        let key = ("test-forget".to_string(), "admin".to_string());
+        PENDING
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .feed("partial");
+        forget_terminal("test-forget");
+        assert!(!PENDING.lock().unwrap().contains_key(&key));
+    }
+}
@@ -0,0 +1,238 @@
+// Test support
+//
+// Fixtures shared by the unit tests: an in-memory database with the migrations
+// applied, builders for the rows tests commonly need, a throwaway stacks
+// directory, and a server context around them. Only compiled for tests.
+
+use crate::check_version::VersionChecker;
+use crate::config::Config;
+use crate::db::models::agent::{Agent, NewAgent};
+use crate::db::models::{NewUser, Setting, SettingsCache, User};
+use crate::db::Database;
+use crate::docker::DockerHandle;
+use crate::docker_host::{DockerConnector, DockerHost};
+use crate::server::ServerContext;
+use crate::utils::constants::DEFAULT_COMPOSE_FILE_NAME;
+use redact::Secret;
+use serde_json::Value as JsonValue;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Password given to users created by [`user`]
+pub const TEST_PASSWORD: &str = "password";
+
+/// A fresh in-memory database with the migrations applied
+pub async fn test_db() -> Database {
+    let db = Database::new_in_memory().await.unwrap();
+    db.migrate().await.unwrap();
+    db
+}
+
+/// The encryption secret agents are created with
+pub fn test_encryption_secret() -> Secret<String> {
+    Secret::new("test_encryption_secret".to_string())
+}
+
+/// Builder for a user row
+pub struct UserFixture {
+    new_user: NewUser,
+}
+
+/// An active user with TEST_PASSWORD
+pub fn user(username: &str) -> UserFixture {
+    UserFixture {
+        new_user: NewUser {
+            username: username.to_string(),
+            password: Some(TEST_PASSWORD.to_string()),
+            active: true,
+            timezone: None,
+        },
+    }
+}
+
+impl UserFixture {
+    pub fn password(mut self, password: &str) -> Self {
+        self.new_user.password = Some(password.to_string());
+        self
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.new_user.active = false;
+        self
+    }
+
+    pub fn timezone(mut self, timezone: &str) -> Self {
+        self.new_user.timezone = Some(timezone.to_string());
+        self
+    }
+
+    pub async fn create(self, db: &Database) -> User {
+        User::create(db.pool(), self.new_user).await.unwrap()
+    }
+}
+
+/// Builder for an agent row
+pub struct AgentFixture {
+    new_agent: NewAgent,
+}
+
+/// An active agent logging in as admin/pass
+pub fn agent(url: &str) -> AgentFixture {
+    AgentFixture {
+        new_agent: NewAgent {
+            url: url.to_string(),
+            username: "admin".to_string(),
+            password: Secret::new("pass".to_string()),
+            active: true,
+            token: None,
+        },
+    }
+}
+
+impl AgentFixture {
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.new_agent.username = username.to_string();
+        self.new_agent.password = Secret::new(password.to_string());
+        self
+    }
+
+    /// Log in with an agent token instead of a password
+    pub fn token(mut self, token: &str) -> Self {
+        self.new_agent.username = String::new();
+        self.new_agent.password = Secret::new(String::new());
+        self.new_agent.token = Some(Secret::new(token.to_string()));
+        self
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.new_agent.active = false;
+        self
+    }
+
+    /// Create the agent, encrypted with test_encryption_secret()
+    pub async fn create(self, db: &Database) -> Agent {
+        Agent::create(db.pool(), self.new_agent, &test_encryption_secret())
+            .await
+            .unwrap()
+    }
+}
+
+/// Store the settings and return the cache they were written through
+pub async fn settings(db: &Database, values: &[(&str, JsonValue)]) -> SettingsCache {
+    let cache = SettingsCache::new();
+    for (key, value) in values {
+        Setting::set(db.pool(), &cache, key, value, None)
+            .await
+            .unwrap();
+    }
+    cache
+}
+
+/// A server context on the database, configured with the command line `args`
+///
+/// Its Docker daemon doesn't exist: Docker calls fail right away.
+pub async fn test_context(db: &Database, args: &[&str]) -> Arc<ServerContext> {
+    let config = <Config as clap::Parser>::try_parse_from(
+        std::iter::once("dockru").chain(args.iter().copied()),
+    )
+    .unwrap();
+    let connector = DockerConnector::new(
+        // Nothing listens on port 1, so connections are refused
+        DockerHost::Tcp {
+            url: "tcp://127.0.0.1:1".to_string(),
+            tls: None,
+        },
+        &config.data_dir,
+    );
+    let docker = DockerHandle::connect(connector).await.unwrap();
+    let (_, io) = socketioxide::SocketIo::new_layer();
+    Arc::new(ServerContext::new(
+        Arc::new(config),
+        io,
+        db.pool().clone(),
+        SettingsCache::new(),
+        VersionChecker::new("1.5.0".to_string()),
+        docker,
+    ))
+}
+
+/// A temporary stacks directory, removed when dropped
+pub struct StacksDir {
+    dir: TempDir,
+}
+
+impl StacksDir {
+    pub fn new() -> Self {
+        Self {
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Add a stack directory with a compose.yaml; returns the stack directory
+    pub fn stack(&self, name: &str, compose_yaml: &str) -> PathBuf {
+        self.file(
+            &format!("{}/{}", name, DEFAULT_COMPOSE_FILE_NAME),
+            compose_yaml,
+        );
+        self.path().join(name)
+    }
+
+    /// Write a file relative to the stacks directory, creating its parents
+    pub fn file(&self, relative: &str, content: &str) -> PathBuf {
+        let path = self.path().join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fixtures() {
+        let db = test_db().await;
+        let admin = user("admin").timezone("UTC").create(&db).await;
+        assert!(admin.verify_password(TEST_PASSWORD).unwrap());
+        assert_eq!(admin.timezone.as_deref(), Some("UTC"));
+        assert!(!user("guest").inactive().create(&db).await.active);
+
+        let remote = agent("https://remote:5001").token("t").create(&db).await;
+        assert_eq!(remote.endpoint, "remote:5001");
+        assert!(
+            !agent("https://old:5001")
+                .inactive()
+                .create(&db)
+                .await
+                .active
+        );
+
+        let cache = settings(&db, &[("primaryHostname", JsonValue::from("example.com"))]).await;
+        assert_eq!(
+            Setting::get(db.pool(), &cache, "primaryHostname")
+                .await
+                .unwrap(),
+            Some(JsonValue::from("example.com"))
+        );
+
+        // Every database is private
+        assert_eq!(User::count(test_db().await.pool()).await.unwrap(), 0);
+
+        let stacks = StacksDir::new();
+        let web = stacks.stack("web", "services: {}\n");
+        assert!(web.join(DEFAULT_COMPOSE_FILE_NAME).is_file());
+        assert!(stacks.file("global.env", "A=1\n").is_file());
+
+        let ctx = test_context(&db, &["--cluster-node-id", "n1"]).await;
+        assert!(ctx.cluster.is_some());
+        assert!(!ctx.docker.check_health().await);
+    }
+}
@@ -0,0 +1,174 @@
+// Stack uptime
+//
+// Every UPTIME_SAMPLE_SECS the status of every stack is sampled into the
+// stack_status_history table as periods of unchanged status. Uptime over a
+// window is the share of the sampled time in the window the stack was
+// running; time that wasn't sampled, because dockru itself was down or the
+// stack didn't exist yet, is left out rather than counted as downtime. A
+// stack is up only when all its containers run, so partially running,
+// unhealthy and crash-looping stacks count as down.
+//
+// Samples are written in batches by a StatusWriter rather than one by one. In
+// a cluster only the leader samples.
+
+use crate::db::models::{StatusPeriod, StatusSample};
+use crate::db::status_writer::StatusWriter;
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use crate::utils::constants::{RUNNING, UPTIME_SAMPLE_SECS};
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Longest window uptime is reported for; older history is pruned
+const RETENTION_SECS: i64 = 30 * DAY_SECS;
+
+/// Samples further apart than this don't extend a period
+const MAX_SAMPLE_GAP_SECS: i64 = 3 * UPTIME_SAMPLE_SECS as i64;
+
+/// Rolling uptime percentages, None for windows without any samples
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UptimeSummary {
+    #[serde(rename = "24h")]
+    pub day: Option<f64>,
+    #[serde(rename = "7d")]
+    pub week: Option<f64>,
+    #[serde(rename = "30d")]
+    pub month: Option<f64>,
+}
+
+/// Sample stack statuses in the background
+pub fn start(ctx: Arc<ServerContext>) {
+    let writer = Arc::new(StatusWriter::new(ctx.db.clone(), MAX_SAMPLE_GAP_SECS));
+    writer.start();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(UPTIME_SAMPLE_SECS));
+        loop {
+            interval.tick().await;
+            if let Err(e) = sample(&ctx, &writer).await {
+                warn!("Failed to sample stack statuses: {:#}", e);
+            }
+        }
+    });
+}
+
+/// Buffer the current status of every stack and prune expired history
+///
+/// In a cluster only the leader samples; the history is shared, and samples
+/// from several nodes would interleave into overlapping periods.
+async fn sample(ctx: &Arc<ServerContext>, writer: &StatusWriter) -> Result<()> {
+    if !crate::cluster::is_leader(ctx) {
+        return Ok(());
+    }
+    let stack_list = Stack::get_stack_list(ctx.clone(), String::new(), false).await?;
+    let now = Utc::now().timestamp();
+    for (name, stack) in stack_list {
+        writer.push(StatusSample {
+            status: stack.status(),
+            stack_name: name,
+            at: now,
+        });
+    }
+
+    let pruned = StatusPeriod::prune(&ctx.db, now - RETENTION_SECS).await?;
+    if pruned > 0 {
+        debug!("Pruned {} expired stack status periods", pruned);
+    }
+    Ok(())
+}
+
+/// A stack's uptime over the last 24 hours, 7 days and 30 days
+pub async fn stack_uptime(pool: &SqlitePool, stack_name: &str) -> Result<UptimeSummary> {
+    let now = Utc::now().timestamp();
+    let periods = StatusPeriod::find_since(pool, stack_name, now - RETENTION_SECS).await?;
+    Ok(UptimeSummary {
+        day: uptime_percent(&periods, now - DAY_SECS, now),
+        week: uptime_percent(&periods, now - 7 * DAY_SECS, now),
+        month: uptime_percent(&periods, now - RETENTION_SECS, now),
+    })
+}
+
+/// Percentage of the sampled time between `from` and `to` the stack was running
+fn uptime_percent(periods: &[StatusPeriod], from: i64, to: i64) -> Option<f64> {
+    let mut sampled = 0;
+    let mut up = 0;
+    for period in periods {
+        let overlap = period.until.min(to) - period.since.max(from);
+        if overlap <= 0 {
+            continue;
+        }
+        sampled += overlap;
+        if period.status == RUNNING {
+            up += overlap;
+        }
+    }
+    (sampled > 0).then(|| (up as f64 * 10000.0 / sampled as f64).round() / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_context, test_db, StacksDir};
+    use crate::utils::constants::EXITED;
+
+    fn period(status: i32, since: i64, until: i64) -> StatusPeriod {
+        StatusPeriod {
+            id: 0,
+            stack_name: "web".to_string(),
+            status,
+            since,
+            until,
+        }
+    }
+
+    #[test]
+    fn test_uptime_percent() {
+        let periods = [
+            period(RUNNING, 0, 300),
+            period(EXITED, 300, 400),
+            // Not sampled from 400 to 1000
+            period(RUNNING, 1000, 1100),
+        ];
+        assert_eq!(uptime_percent(&periods, 0, 1100), Some(80.0));
+        // Only the part of a period inside the window counts
+        assert_eq!(uptime_percent(&periods, 200, 1100), Some(66.67));
+        assert_eq!(uptime_percent(&periods, 1000, 2000), Some(100.0));
+        assert_eq!(uptime_percent(&periods, 400, 1000), None);
+        assert_eq!(uptime_percent(&[], 0, 1000), None);
+    }
+
+    #[tokio::test]
+    async fn test_sample_only_on_leader() {
+        let db = test_db().await;
+        let stacks = StacksDir::new();
+        stacks.stack("web", "services: {}\n");
+        let stacks_dir = stacks.path().display().to_string();
+        let expired = Utc::now().timestamp() - RETENTION_SECS - DAY_SECS;
+        let old = StatusSample {
+            stack_name: "web".to_string(),
+            status: RUNNING,
+            at: expired,
+        };
+        StatusPeriod::record_batch(db.pool(), &[old], MAX_SAMPLE_GAP_SECS)
+            .await
+            .unwrap();
+
+        let follower = test_context(
+            &db,
+            &["--stacks-dir", &stacks_dir, "--cluster-node-id", "n2"],
+        )
+        .await;
+        let writer = StatusWriter::new(db.pool().clone(), MAX_SAMPLE_GAP_SECS);
+        sample(&follower, &writer).await.unwrap();
+        assert_eq!(writer.flush().await.unwrap(), 0);
+        // Nor is the history pruned
+        let periods = StatusPeriod::find_since(db.pool(), "web", 0).await.unwrap();
+        assert_eq!(periods.len(), 1);
+    }
+}
@@ -0,0 +1,66 @@
+// Find and replace across stacks
+//
+// A value such as an old registry host name or SMTP server tends to be
+// spread over many stacks. bulkEnvEdit replaces it in the .env and compose
+// file of every managed stack in two steps: without `apply` it only reports
+// which files would change, with a diff of each, and with `apply` it edits
+// the stacks the user confirmed. The files of an edited stack are
+// snapshotted to its history first, so each edit can be rolled back.
+//
+// Matching is plain text and case sensitive.
+
+use crate::utils::text_diff::unified_diff;
+use serde::Serialize;
+
+/// A file bulkEnvEdit changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileEdit {
+    pub file: String,
+    /// Occurrences replaced
+    pub matches: usize,
+    pub diff: String,
+    /// New content, written when the edit is applied
+    #[serde(skip)]
+    pub content: String,
+}
+
+/// The edit replacing `find` with `replace` in a file, None if it doesn't
+/// contain `find`
+pub fn replace_in_file(file: &str, content: &str, find: &str, replace: &str) -> Option<FileEdit> {
+    let matches = content.matches(find).count();
+    if find.is_empty() || matches == 0 {
+        return None;
+    }
+    let new_content = content.replace(find, replace);
+    Some(FileEdit {
+        file: file.to_string(),
+        matches,
+        diff: unified_diff(content, &new_content, file),
+        content: new_content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_in_file() {
+        let env = "REGISTRY=registry.old.lan\nMIRROR=registry.old.lan:5000\nTZ=UTC\n";
+        let edit = replace_in_file(".env", env, "registry.old.lan", "registry.lan").unwrap();
+        assert_eq!(edit.file, ".env");
+        assert_eq!(edit.matches, 2);
+        assert_eq!(
+            edit.content,
+            "REGISTRY=registry.lan\nMIRROR=registry.lan:5000\nTZ=UTC\n"
+        );
+        assert!(edit.diff.contains("-REGISTRY=registry.old.lan"));
+        assert!(edit.diff.contains("+REGISTRY=registry.lan"));
+
+        assert_eq!(
+            replace_in_file(".env", env, "smtp.old.lan", "smtp.lan"),
+            None
+        );
+        assert_eq!(replace_in_file(".env", env, "", "x"), None);
+    }
+}
@@ -0,0 +1,120 @@
+// Environment passthrough to compose commands
+//
+// `docker compose` inherits the dockru process environment, which may hold
+// credentials or settings that compose picks up for `${VAR}` interpolation.
+// An admin can limit what it gets with the composeEnvPolicy setting:
+//
+// - allow: variables passed through, all of them when empty
+// - deny: variables never passed, even when allowed
+//
+// Entries are variable names or prefixes ending in `*`, e.g. `AWS_*`. PATH and
+// HOME are always passed, compose needs them to find its plugins and the
+// docker config. The docker connection variables (see docker_host) and a
+// stack's injected variables (see injected_env) are set on top and aren't
+// subject to the policy.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Setting holding the policy
+pub const COMPOSE_ENV_POLICY_SETTING: &str = "composeEnvPolicy";
+
+/// Setting type of the policy, keeping it out of the general settings
+pub const COMPOSE_ENV_POLICY_SETTING_TYPE: &str = "policy";
+
+/// Variables compose gets whatever the policy says
+const ALWAYS_PASSED: [&str; 2] = ["PATH", "HOME"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComposeEnvPolicy {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl ComposeEnvPolicy {
+    /// Reject entries that aren't a variable name with an optional trailing `*`
+    pub fn validate(&self) -> Result<()> {
+        for entry in self.allow.iter().chain(&self.deny) {
+            let name = entry.strip_suffix('*').unwrap_or(entry);
+            let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && (!name.is_empty() || entry == "*");
+            if !valid {
+                bail!("Invalid environment variable pattern \"{}\"", entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether compose inherits the variable `name`
+    pub fn passes(&self, name: &str) -> bool {
+        if ALWAYS_PASSED.contains(&name) {
+            return true;
+        }
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|p| matches(p, name));
+        allowed && !self.deny.iter().any(|p| matches(p, name))
+    }
+
+    /// Names of the dockru process's variables compose doesn't inherit
+    pub fn removed(&self) -> Vec<String> {
+        if self.allow.is_empty() && self.deny.is_empty() {
+            return Vec::new();
+        }
+        std::env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .filter(|name| !self.passes(name))
+            .collect()
+    }
+}
+
+/// Match a name against a variable name or a `PREFIX*` pattern
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> ComposeEnvPolicy {
+        ComposeEnvPolicy {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_passes() {
+        let default = ComposeEnvPolicy::default();
+        assert!(default.passes("AWS_SECRET_ACCESS_KEY"));
+        assert!(default.removed().is_empty());
+
+        let deny = policy(&[], &["AWS_*", "DOCKRU_JWT"]);
+        assert!(!deny.passes("AWS_SECRET_ACCESS_KEY"));
+        assert!(!deny.passes("DOCKRU_JWT"));
+        assert!(deny.passes("DOCKRU_JWT_2"));
+        assert!(deny.passes("TZ"));
+
+        let allow = policy(&["TZ", "LC_*"], &["LC_ALL"]);
+        assert!(allow.passes("TZ"));
+        assert!(allow.passes("LC_TIME"));
+        assert!(!allow.passes("LC_ALL"));
+        assert!(!allow.passes("AWS_SECRET_ACCESS_KEY"));
+        assert!(allow.passes("PATH"));
+
+        let everything = policy(&[], &["*"]);
+        assert!(!everything.passes("TZ"));
+        assert!(everything.passes("HOME"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(policy(&["TZ", "LC_*"], &["*"]).validate().is_ok());
+        assert!(policy(&["A*B"], &[]).validate().is_err());
+        assert!(policy(&[], &[""]).validate().is_err());
+        assert!(policy(&[], &["FOO BAR"]).validate().is_err());
+    }
+}
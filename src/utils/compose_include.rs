@@ -0,0 +1,345 @@
+// Compose `include:` support
+//
+// A compose file can pull in other compose files, each merged into the project
+// as if its resources were declared in the including file:
+//
+//   include:
+//     - ../shared/proxy.yaml
+//     - path: db/compose.yaml
+//       env_file: db/.env
+//
+// Paths are relative to the directory of the file that includes them, and
+// included files can include further files. Remote includes (git or OCI
+// references) are listed but not read.
+//
+// Validation merges the top-level resources of every included file into the
+// main file the way compose does, where a resource declared twice is an error,
+// so a stack that compose would reject can't be saved.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use yaml_rust2::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
+
+pub use dockru_protocol::compose::IncludedFile;
+
+/// How deep includes may nest before they are assumed to loop
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Top-level sections whose entries are merged from included files
+const MERGED_SECTIONS: &[&str] = &["services", "networks", "volumes", "configs", "secrets"];
+
+/// The paths a compose file includes, as written
+pub fn include_paths(compose_yaml: &str) -> Result<Vec<String>> {
+    let docs = YamlLoader::load_from_str(compose_yaml).context("Invalid YAML format")?;
+    let Some(include) = docs.first().map(|doc| &doc["include"]) else {
+        return Ok(Vec::new());
+    };
+
+    let entries = match include {
+        Yaml::BadValue | Yaml::Null => return Ok(Vec::new()),
+        Yaml::Array(entries) => entries,
+        _ => return Err(anyhow!("include must be a list")),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        match entry {
+            Yaml::String(path) => paths.push(path.clone()),
+            Yaml::Hash(_) => match &entry["path"] {
+                Yaml::String(path) => paths.push(path.clone()),
+                Yaml::Array(list) => {
+                    for path in list {
+                        let path = path
+                            .as_str()
+                            .ok_or_else(|| anyhow!("include path must be a string"))?;
+                        paths.push(path.to_string());
+                    }
+                }
+                _ => return Err(anyhow!("include entry must have a path")),
+            },
+            _ => return Err(anyhow!("include entry must be a path or a mapping")),
+        }
+    }
+    Ok(paths)
+}
+
+/// Whether an include refers to a git repository or OCI artifact
+pub fn is_remote(path: &str) -> bool {
+    path.contains("://") || path.starts_with("git@")
+}
+
+/// Read every file the compose file includes, directly or through other includes
+///
+/// Each file is listed once, in the order it is first included; the main
+/// compose file `main_file` is never listed.
+pub fn load_includes(
+    stack_dir: &Path,
+    main_file: &str,
+    compose_yaml: &str,
+) -> Result<Vec<IncludedFile>> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::from([main_file.to_string()]);
+    collect_includes(stack_dir, "", compose_yaml, 0, &mut seen, &mut files)?;
+    Ok(files)
+}
+
+fn collect_includes(
+    stack_dir: &Path,
+    base: &str,
+    compose_yaml: &str,
+    depth: usize,
+    seen: &mut HashSet<String>,
+    files: &mut Vec<IncludedFile>,
+) -> Result<()> {
+    if depth >= MAX_INCLUDE_DEPTH {
+        return Err(anyhow!(
+            "Includes nest more than {} levels deep",
+            MAX_INCLUDE_DEPTH
+        ));
+    }
+
+    for path in include_paths(compose_yaml)? {
+        if is_remote(&path) {
+            if seen.insert(path.clone()) {
+                files.push(IncludedFile {
+                    name: path,
+                    remote: true,
+                    content: None,
+                });
+            }
+            continue;
+        }
+
+        let name = join_relative(base, &path);
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let content = std::fs::read_to_string(stack_dir.join(&name)).ok();
+        files.push(IncludedFile {
+            name: name.clone(),
+            remote: false,
+            content: content.clone(),
+        });
+
+        if let Some(content) = content {
+            let parent = Path::new(&name)
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            collect_includes(stack_dir, &parent, &content, depth + 1, seen, files)
+                .with_context(|| format!("In included file {}", name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Join an include path onto the directory of the including file, lexically
+///
+/// Both are relative to the stack directory; `..` is kept where it leaves it.
+pub(crate) fn join_relative(base: &str, path: &str) -> String {
+    let joined = Path::new(base).join(path);
+    let mut parts: Vec<Component> = Vec::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(parts.last(), Some(Component::Normal(_))) => {
+                parts.pop();
+            }
+            other => parts.push(other),
+        }
+    }
+    parts.iter().collect::<PathBuf>().display().to_string()
+}
+
+/// Merge the top-level resources of the included files into the compose file
+///
+/// Fails if an included file is missing or invalid, or declares a resource
+/// that is already declared, which compose refuses to deploy.
+pub fn merge_includes(compose_yaml: &str, includes: &[IncludedFile]) -> Result<Yaml> {
+    let mut doc = YamlLoader::load_from_str(compose_yaml)
+        .context("Invalid YAML format")?
+        .into_iter()
+        .next()
+        .unwrap_or(Yaml::Hash(Hash::new()));
+
+    for file in includes.iter().filter(|file| !file.remote) {
+        let content = file
+            .content
+            .as_deref()
+            .ok_or_else(|| anyhow!("Included file {} does not exist", file.name))?;
+        let included = YamlLoader::load_from_str(content)
+            .with_context(|| format!("Invalid YAML in included file {}", file.name))?
+            .into_iter()
+            .next()
+            .unwrap_or(Yaml::Null);
+
+        for section in MERGED_SECTIONS {
+            let Yaml::Hash(resources) = &included[*section] else {
+                continue;
+            };
+            let Yaml::Hash(root) = &mut doc else {
+                return Err(anyhow!("Compose file must be a mapping"));
+            };
+            let merged = root
+                .entry(Yaml::String(section.to_string()))
+                .or_insert_with(|| Yaml::Hash(Hash::new()));
+            let Yaml::Hash(merged) = merged else {
+                return Err(anyhow!("{} must be a mapping", section));
+            };
+            for (key, value) in resources {
+                if merged.contains_key(key) {
+                    return Err(anyhow!(
+                        "{} {} from included file {} is already declared",
+                        section,
+                        key.as_str().unwrap_or("?"),
+                        file.name
+                    ));
+                }
+                merged.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    Ok(doc)
+}
+
+/// Rewrite the local include paths of a compose file to absolute paths
+///
+/// Used when the file is rendered from somewhere other than `stack_dir`, e.g.
+/// a temporary copy, so the includes still resolve. Unchanged if the file has
+/// no includes.
+pub fn absolutize_includes(compose_yaml: &str, stack_dir: &Path) -> Result<String> {
+    if include_paths(compose_yaml)?.is_empty() {
+        return Ok(compose_yaml.to_string());
+    }
+
+    let mut doc = YamlLoader::load_from_str(compose_yaml)
+        .context("Invalid YAML format")?
+        .remove(0);
+    let absolute = |path: &Yaml| match path {
+        Yaml::String(path) if !is_remote(path) => {
+            Yaml::String(stack_dir.join(path).display().to_string())
+        }
+        other => other.clone(),
+    };
+
+    if let Yaml::Hash(root) = &mut doc {
+        if let Some(Yaml::Array(entries)) = root.get_mut(&Yaml::String("include".to_string())) {
+            for entry in entries.iter_mut() {
+                match entry {
+                    Yaml::Hash(options) => {
+                        for key in ["path", "env_file", "project_directory"] {
+                            let key = Yaml::String(key.to_string());
+                            if let Some(value) = options.get_mut(&key) {
+                                *value = match &*value {
+                                    Yaml::Array(list) => {
+                                        Yaml::Array(list.iter().map(absolute).collect())
+                                    }
+                                    other => absolute(other),
+                                };
+                            }
+                        }
+                    }
+                    other => *other = absolute(&*other),
+                }
+            }
+        }
+    }
+
+    let mut output = String::new();
+    YamlEmitter::new(&mut output)
+        .dump(&doc)
+        .context("Failed to emit YAML")?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_include_paths() {
+        let yaml = "include:\n  - ../shared/proxy.yaml\n  - path: db/compose.yaml\n    env_file: db/.env\n  - path: [a.yaml, b.yaml]\n  - oci://registry/app:1\nservices: {}\n";
+        assert_eq!(
+            include_paths(yaml).unwrap(),
+            [
+                "../shared/proxy.yaml",
+                "db/compose.yaml",
+                "a.yaml",
+                "b.yaml",
+                "oci://registry/app:1"
+            ]
+        );
+        assert!(include_paths("services: {}\n").unwrap().is_empty());
+        assert!(include_paths("include: a.yaml\n").is_err());
+        assert!(include_paths("include:\n  - env_file: .env\n").is_err());
+
+        assert!(is_remote("https://github.com/org/repo.git"));
+        assert!(is_remote("git@github.com:org/repo.git"));
+        assert!(!is_remote("../shared/proxy.yaml"));
+
+        assert_eq!(join_relative("", "./db/compose.yaml"), "db/compose.yaml");
+        assert_eq!(join_relative("db", "../shared/x.yaml"), "shared/x.yaml");
+        assert_eq!(join_relative("", "../shared/x.yaml"), "../shared/x.yaml");
+    }
+
+    #[test]
+    fn test_load_and_merge_includes() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("db")).unwrap();
+        std::fs::write(
+            dir.path().join("db/compose.yaml"),
+            "include:\n  - ../cache.yaml\n  - ../compose.yaml\nservices:\n  db:\n    image: postgres\nvolumes:\n  data: {}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("cache.yaml"),
+            "services:\n  cache:\n    image: redis\n",
+        )
+        .unwrap();
+        let main = "include:\n  - db/compose.yaml\nservices:\n  web:\n    image: nginx\n";
+        std::fs::write(dir.path().join("compose.yaml"), main).unwrap();
+
+        let includes = load_includes(dir.path(), "compose.yaml", main).unwrap();
+        let names: Vec<&str> = includes.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["db/compose.yaml", "cache.yaml"]);
+
+        let merged = merge_includes(main, &includes).unwrap();
+        let services: Vec<&str> = merged["services"]
+            .as_hash()
+            .unwrap()
+            .keys()
+            .filter_map(|k| k.as_str())
+            .collect();
+        assert_eq!(services, ["web", "db", "cache"]);
+        assert!(merged["volumes"]["data"].as_hash().is_some());
+
+        let conflicting = "services:\n  db:\n    image: mysql\n";
+        assert!(merge_includes(conflicting, &includes).is_err());
+
+        let missing =
+            load_includes(dir.path(), "compose.yaml", "include:\n  - nope.yaml\n").unwrap();
+        assert_eq!(missing[0].content, None);
+        assert!(merge_includes("services: {}\n", &missing).is_err());
+    }
+
+    #[test]
+    fn test_absolutize_includes() {
+        let yaml = "include:\n  - db.yaml\n  - path: [a.yaml]\n    env_file: a.env\n  - oci://registry/app:1\n";
+        let rewritten = absolutize_includes(yaml, Path::new("/opt/stacks/web")).unwrap();
+        assert_eq!(
+            include_paths(&rewritten).unwrap(),
+            [
+                "/opt/stacks/web/db.yaml",
+                "/opt/stacks/web/a.yaml",
+                "oci://registry/app:1"
+            ]
+        );
+        assert!(rewritten.contains("/opt/stacks/web/a.env"));
+
+        let plain = "services:\n  web:\n    image: nginx # pinned\n";
+        assert_eq!(absolutize_includes(plain, Path::new("/x")).unwrap(), plain);
+    }
+}
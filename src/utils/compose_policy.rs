@@ -0,0 +1,394 @@
+// Compose policies
+//
+// On hosts shared by a team, an admin can restrict what stacks may do. Each
+// rule of the policy is off, warns or denies:
+//
+// - privileged: services running with `privileged: true`
+// - hostNetwork: services with `network_mode: host`
+// - resourceLimits: services without a memory and a CPU limit, set with
+//   `mem_limit`/`cpus` or `deploy.resources.limits`
+// - bindMounts: bind mounts of paths outside the stack directory and the
+//   allowedBindPaths
+//
+// The policy is stored in the composePolicy setting and checked when a stack
+// is saved, where denied violations fail the save and warnings are returned
+// with it, and again before a stack is deployed. Values containing `$` are
+// left alone, since they are only known after variable substitution.
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Setting holding the policy
+pub const COMPOSE_POLICY_SETTING: &str = "composePolicy";
+
+/// Setting type of the policy, keeping it out of the general settings
+pub const COMPOSE_POLICY_SETTING_TYPE: &str = "policy";
+
+/// What a rule does when a stack breaks it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Enforcement {
+    #[default]
+    Off,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComposePolicy {
+    pub privileged: Enforcement,
+    #[serde(rename = "hostNetwork")]
+    pub host_network: Enforcement,
+    #[serde(rename = "resourceLimits")]
+    pub resource_limits: Enforcement,
+    #[serde(rename = "bindMounts")]
+    pub bind_mounts: Enforcement,
+    /// Absolute host paths bind mounts may use besides the stack directory
+    #[serde(rename = "allowedBindPaths")]
+    pub allowed_bind_paths: Vec<String>,
+}
+
+impl ComposePolicy {
+    /// Reject allowed paths that aren't absolute or step out with `..`
+    pub fn validate(&self) -> Result<()> {
+        for path in &self.allowed_bind_paths {
+            let p = Path::new(path);
+            if !p.is_absolute() || p.components().any(|c| c == Component::ParentDir) {
+                bail!("Allowed bind path \"{}\" must be an absolute path", path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A rule a compose file breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub enforcement: Enforcement,
+    /// Included file the violation is in, None for the main compose file
+    pub file: Option<String>,
+    /// Dotted path of the offending key, e.g. `services.web.privileged`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
+        }
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Check a compose file against the policy
+///
+/// Relative bind mounts are resolved against `base_dir`, the directory of the
+/// file; `stack_dir` may always be bind mounted. Returns nothing for YAML that
+/// doesn't parse, which is reported separately.
+pub fn check_compose_policy(
+    compose_yaml: &str,
+    policy: &ComposePolicy,
+    stack_dir: &Path,
+    base_dir: &Path,
+) -> Vec<PolicyViolation> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Vec::new();
+    };
+    let Some(services) = docs.first().and_then(|doc| doc["services"].as_hash()) else {
+        return Vec::new();
+    };
+
+    let mut violations = Vec::new();
+    let mut report = |enforcement: Enforcement, path: String, message: String| {
+        if enforcement != Enforcement::Off {
+            violations.push(PolicyViolation {
+                enforcement,
+                file: None,
+                path,
+                message,
+            });
+        }
+    };
+
+    for (name, service) in services {
+        let (Some(name), Some(_)) = (name.as_str(), service.as_hash()) else {
+            continue;
+        };
+        let prefix = format!("services.{}", name);
+
+        if service["privileged"].as_bool() == Some(true) {
+            report(
+                policy.privileged,
+                format!("{}.privileged", prefix),
+                "Privileged containers are not allowed".to_string(),
+            );
+        }
+
+        if service["network_mode"].as_str() == Some("host") {
+            report(
+                policy.host_network,
+                format!("{}.network_mode", prefix),
+                "The host network is not allowed".to_string(),
+            );
+        }
+
+        let limits = &service["deploy"]["resources"]["limits"];
+        if service["mem_limit"].is_badvalue() && limits["memory"].is_badvalue() {
+            report(
+                policy.resource_limits,
+                prefix.clone(),
+                "A memory limit is required (mem_limit or deploy.resources.limits.memory)"
+                    .to_string(),
+            );
+        }
+        if service["cpus"].is_badvalue() && limits["cpus"].is_badvalue() {
+            report(
+                policy.resource_limits,
+                prefix.clone(),
+                "A CPU limit is required (cpus or deploy.resources.limits.cpus)".to_string(),
+            );
+        }
+
+        if let Some(volumes) = service["volumes"].as_vec() {
+            for (i, volume) in volumes.iter().enumerate() {
+                let Some(source) = bind_source(volume) else {
+                    continue;
+                };
+                if !bind_allowed(source, policy, stack_dir, base_dir) {
+                    report(
+                        policy.bind_mounts,
+                        format!("{}.volumes[{}]", prefix, i),
+                        format!("Bind mounting {} is not allowed", source),
+                    );
+                }
+            }
+        }
+    }
+
+    violations
+}
+
+/// Fail with the denied violations, or return the warnings
+pub fn enforce(violations: &[PolicyViolation]) -> Result<Vec<String>> {
+    let denied: Vec<String> = violations
+        .iter()
+        .filter(|v| v.enforcement == Enforcement::Deny)
+        .map(ToString::to_string)
+        .collect();
+    if !denied.is_empty() {
+        return Err(anyhow!(
+            "The compose policy doesn't allow this stack:\n{}",
+            denied.join("\n")
+        ));
+    }
+    Ok(violations
+        .iter()
+        .filter(|v| v.enforcement == Enforcement::Warn)
+        .map(ToString::to_string)
+        .collect())
+}
+
+/// The host path of a bind mount entry, None for volumes and unknown values
+fn bind_source(volume: &Yaml) -> Option<&str> {
+    let source = match volume {
+        Yaml::String(short) => {
+            let (source, _) = short.split_once(':')?;
+            if !source.starts_with(['/', '.', '~']) {
+                // A named volume
+                return None;
+            }
+            source
+        }
+        Yaml::Hash(_) => {
+            if volume["type"].as_str() != Some("bind") {
+                return None;
+            }
+            volume["source"].as_str()?
+        }
+        _ => return None,
+    };
+    (!source.contains('$')).then_some(source)
+}
+
+fn bind_allowed(source: &str, policy: &ComposePolicy, stack_dir: &Path, base_dir: &Path) -> bool {
+    // The home directory isn't known here
+    if source.starts_with('~') {
+        return false;
+    }
+    let path = normalize(&base_dir.join(source));
+    path.starts_with(normalize(stack_dir))
+        || policy
+            .allowed_bind_paths
+            .iter()
+            .any(|allowed| path.starts_with(normalize(Path::new(allowed))))
+}
+
+/// Resolve `.` and `..` lexically
+fn normalize(path: &Path) -> PathBuf {
+    let mut parts: Vec<Component> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(parts.last(), Some(Component::Normal(_))) {
+                    parts.pop();
+                }
+            }
+            other => parts.push(other),
+        }
+    }
+    parts.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACK_DIR: &str = "/opt/stacks/web";
+
+    fn check(yaml: &str, policy: &ComposePolicy) -> Vec<String> {
+        let dir = Path::new(STACK_DIR);
+        check_compose_policy(yaml, policy, dir, dir)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    fn deny_all() -> ComposePolicy {
+        ComposePolicy {
+            privileged: Enforcement::Deny,
+            host_network: Enforcement::Deny,
+            resource_limits: Enforcement::Deny,
+            bind_mounts: Enforcement::Deny,
+            allowed_bind_paths: vec!["/srv/media".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_off_policy_allows_everything() {
+        let yaml = "services:\n  web:\n    privileged: true\n    network_mode: host\n";
+        assert!(check(yaml, &ComposePolicy::default()).is_empty());
+    }
+
+    #[test]
+    fn test_privileged_and_host_network() {
+        let yaml = r#"
+services:
+  web:
+    privileged: true
+    network_mode: host
+    mem_limit: 512m
+    cpus: 0.5
+  db:
+    privileged: false
+    network_mode: bridge
+    deploy:
+      resources:
+        limits:
+          memory: 1g
+          cpus: "1"
+"#;
+        assert_eq!(
+            check(yaml, &deny_all()),
+            vec![
+                "services.web.privileged: Privileged containers are not allowed",
+                "services.web.network_mode: The host network is not allowed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let yaml = "services:\n  web:\n    image: nginx\n    mem_limit: 512m\n";
+        assert_eq!(
+            check(yaml, &deny_all()),
+            vec!["services.web: A CPU limit is required (cpus or deploy.resources.limits.cpus)"]
+        );
+    }
+
+    #[test]
+    fn test_bind_mounts() {
+        let yaml = r#"
+services:
+  web:
+    mem_limit: 512m
+    cpus: 1
+    volumes:
+      - ./data:/data
+      - /srv/media/movies:/movies:ro
+      - data:/var/lib/data
+      - /etc:/host-etc
+      - ../other:/other
+      - ~/secrets:/secrets
+      - ${CONFIG_DIR}:/config
+      - type: bind
+        source: /var/run/docker.sock
+        target: /var/run/docker.sock
+      - type: volume
+        source: cache
+        target: /cache
+"#;
+        assert_eq!(
+            check(yaml, &deny_all()),
+            vec![
+                "services.web.volumes[3]: Bind mounting /etc is not allowed",
+                "services.web.volumes[4]: Bind mounting ../other is not allowed",
+                "services.web.volumes[5]: Bind mounting ~/secrets is not allowed",
+                "services.web.volumes[7]: Bind mounting /var/run/docker.sock is not allowed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_enforce() {
+        let violation = |enforcement| PolicyViolation {
+            enforcement,
+            file: Some("db/compose.yaml".to_string()),
+            path: "services.db.privileged".to_string(),
+            message: "Privileged containers are not allowed".to_string(),
+        };
+
+        let warnings = enforce(&[violation(Enforcement::Warn)]).unwrap();
+        assert_eq!(
+            warnings,
+            vec!["db/compose.yaml: services.db.privileged: Privileged containers are not allowed"]
+        );
+
+        let err = enforce(&[violation(Enforcement::Warn), violation(Enforcement::Deny)])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("db/compose.yaml: services.db.privileged"));
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        assert!(deny_all().validate().is_ok());
+        let policy = ComposePolicy {
+            allowed_bind_paths: vec!["srv".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+        let policy = ComposePolicy {
+            allowed_bind_paths: vec!["/srv/../etc".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_policy_deserialize_defaults() {
+        let policy: ComposePolicy =
+            serde_json::from_value(serde_json::json!({ "privileged": "deny" })).unwrap();
+        assert_eq!(policy.privileged, Enforcement::Deny);
+        assert_eq!(policy.bind_mounts, Enforcement::Off);
+        assert!(serde_json::from_value::<ComposePolicy>(
+            serde_json::json!({ "privileged": "maybe" })
+        )
+        .is_err());
+    }
+}
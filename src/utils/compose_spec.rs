@@ -0,0 +1,514 @@
+// Structural checks against the Compose specification
+//
+// Saving a stack used to only check that the compose file is YAML, so typos
+// like `service:` or `restart: sometimes` were only reported by docker compose
+// at deploy time. These checks catch the common mistakes when the file is
+// saved, each reported with the line and column it is on so the editor can
+// point at it:
+//
+// - unknown top-level keys (extensions starting with `x-` are allowed)
+// - services that aren't mappings
+// - port mappings that aren't valid short or long syntax
+// - invalid restart policies, for `restart` and `deploy.restart_policy`
+//
+// Values containing `$` are left alone, since they are only known after
+// variable substitution.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Top-level keys the Compose specification defines
+const TOP_LEVEL_KEYS: &[&str] = &[
+    "version", "name", "include", "services", "networks", "volumes", "configs", "secrets", "models",
+];
+
+/// Values of a service's `restart`, besides `on-failure:<max retries>`
+const RESTART_POLICIES: &[&str] = &["no", "always", "on-failure", "unless-stopped"];
+
+/// Values of `deploy.restart_policy.condition`
+const RESTART_CONDITIONS: &[&str] = &["none", "on-failure", "any"];
+
+const PORT_PROTOCOLS: &[&str] = &["tcp", "udp", "sctp"];
+
+const PORT_MODES: &[&str] = &["host", "ingress"];
+
+/// A place where a compose file doesn't follow the specification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeIssue {
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column
+    pub column: usize,
+    /// Dotted path of the offending key, e.g. `services.web.ports[0]`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ComposeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Line {}, column {}: {}: {}",
+            self.line, self.column, self.path, self.message
+        )
+    }
+}
+
+/// Check a compose file against the specification
+///
+/// Returns the issues in document order; none for YAML that doesn't parse,
+/// which is reported separately.
+pub fn check_compose_spec(compose_yaml: &str) -> Vec<ComposeIssue> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Vec::new();
+    };
+    let mut checker = Checker {
+        positions: positions(compose_yaml),
+        issues: Vec::new(),
+    };
+    if let Some(doc) = docs.first() {
+        checker.check_document(doc);
+    }
+
+    checker
+        .issues
+        .sort_by_key(|issue| (issue.line, issue.column));
+    checker.issues
+}
+
+struct Checker {
+    positions: HashMap<String, (usize, usize)>,
+    issues: Vec<ComposeIssue>,
+}
+
+impl Checker {
+    fn report(&mut self, path: &str, message: String) {
+        // Point at the nearest ancestor that has a position
+        let mut anchor = path;
+        let (line, column) = loop {
+            if let Some(position) = self.positions.get(anchor) {
+                break *position;
+            }
+            match anchor.rfind(['.', '[']) {
+                Some(end) => anchor = &anchor[..end],
+                None => break (1, 1),
+            }
+        };
+        self.issues.push(ComposeIssue {
+            line,
+            column,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn check_document(&mut self, doc: &Yaml) {
+        let root = match doc {
+            Yaml::Hash(root) => root,
+            Yaml::Null => return,
+            _ => {
+                self.report("", "The compose file must be a mapping".to_string());
+                return;
+            }
+        };
+
+        for (key, value) in root {
+            let Some(key) = key.as_str() else {
+                self.report("", "Top-level keys must be strings".to_string());
+                continue;
+            };
+            if !key.starts_with("x-") && !TOP_LEVEL_KEYS.contains(&key) {
+                self.report(key, format!("Unknown top-level key \"{}\"", key));
+            } else if key == "services" {
+                self.check_services(value);
+            }
+        }
+    }
+
+    fn check_services(&mut self, services: &Yaml) {
+        let services = match services {
+            Yaml::Hash(services) => services,
+            Yaml::Null => return,
+            _ => {
+                self.report("services", "services must be a mapping".to_string());
+                return;
+            }
+        };
+
+        for (name, service) in services {
+            let name = name.as_str().unwrap_or("?");
+            let path = format!("services.{}", name);
+            match service {
+                Yaml::Hash(_) => {
+                    self.check_ports(&path, &service["ports"]);
+                    self.check_restart(&path, service);
+                }
+                _ => self.report(&path, format!("Service \"{}\" must be a mapping", name)),
+            }
+        }
+    }
+
+    fn check_ports(&mut self, service_path: &str, ports: &Yaml) {
+        let path = format!("{}.ports", service_path);
+        let ports = match ports {
+            Yaml::Array(ports) => ports,
+            Yaml::BadValue | Yaml::Null => return,
+            _ => {
+                self.report(&path, "ports must be a list".to_string());
+                return;
+            }
+        };
+
+        for (index, port) in ports.iter().enumerate() {
+            let path = format!("{}[{}]", path, index);
+            if let Err(message) = check_port(port) {
+                self.report(&path, message);
+            }
+        }
+    }
+
+    fn check_restart(&mut self, service_path: &str, service: &Yaml) {
+        if let Some(restart) = scalar(&service["restart"]) {
+            if !is_substituted(&restart) && !is_restart_policy(&restart) {
+                self.report(
+                    &format!("{}.restart", service_path),
+                    format!(
+                        "Invalid restart policy \"{}\", expected one of no, always, \
+                         on-failure[:max-retries], unless-stopped",
+                        restart
+                    ),
+                );
+            }
+        }
+
+        if let Some(condition) = scalar(&service["deploy"]["restart_policy"]["condition"]) {
+            if !is_substituted(&condition) && !RESTART_CONDITIONS.contains(&condition.as_str()) {
+                self.report(
+                    &format!("{}.deploy.restart_policy.condition", service_path),
+                    format!(
+                        "Invalid restart condition \"{}\", expected one of {}",
+                        condition,
+                        RESTART_CONDITIONS.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// A scalar value as text, None if absent or not a scalar
+fn scalar(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn is_substituted(value: &str) -> bool {
+    value.contains('$')
+}
+
+fn is_restart_policy(restart: &str) -> bool {
+    if RESTART_POLICIES.contains(&restart) {
+        return true;
+    }
+    restart
+        .strip_prefix("on-failure:")
+        .is_some_and(|retries| retries.parse::<u32>().is_ok())
+}
+
+/// Check one entry of a service's ports, in short or long syntax
+fn check_port(port: &Yaml) -> Result<(), String> {
+    match port {
+        Yaml::Integer(port) => match u16::try_from(*port) {
+            Ok(1..) => Ok(()),
+            _ => Err(format!("Port {} is out of range", port)),
+        },
+        Yaml::String(spec) => check_port_spec(spec),
+        Yaml::Hash(_) => check_long_port(port),
+        _ => Err("Port must be a string, a number or a mapping".to_string()),
+    }
+}
+
+/// Check short syntax: `[[HOST_IP:][HOST_PORT[-RANGE]]:]CONTAINER_PORT[-RANGE][/PROTOCOL]`
+fn check_port_spec(spec: &str) -> Result<(), String> {
+    if is_substituted(spec) {
+        return Ok(());
+    }
+    let invalid = || {
+        format!(
+            "Invalid port \"{}\", expected [[ip:]host-port:]container-port[/protocol]",
+            spec
+        )
+    };
+
+    let (ports, protocol) = match spec.split_once('/') {
+        Some((ports, protocol)) => (ports, Some(protocol)),
+        None => (spec, None),
+    };
+    if let Some(protocol) = protocol {
+        if !PORT_PROTOCOLS.contains(&protocol) {
+            return Err(format!(
+                "Invalid protocol \"{}\" in port \"{}\", expected one of {}",
+                protocol,
+                spec,
+                PORT_PROTOCOLS.join(", ")
+            ));
+        }
+    }
+
+    // An IPv6 host address may be bracketed, or written as is before the ports
+    let (host_ip, ports) = match ports.strip_prefix('[') {
+        Some(rest) => {
+            let (ip, rest) = rest.split_once("]:").ok_or_else(invalid)?;
+            (Some(ip), rest)
+        }
+        None => (None, ports),
+    };
+    let parts: Vec<&str> = ports.split(':').collect();
+    let (host_ip, host_port, container_port) = match (host_ip, parts.as_slice()) {
+        (None, [container]) => (None, None, *container),
+        (None, [host, container]) => (None, Some(*host), *container),
+        (Some(ip), [host, container]) => (Some(ip.to_string()), Some(*host), *container),
+        (None, [ip @ .., host, container]) if !ip.is_empty() => {
+            (Some(ip.join(":")), Some(*host), *container)
+        }
+        _ => return Err(invalid()),
+    };
+
+    if let Some(ip) = host_ip {
+        if ip.parse::<IpAddr>().is_err() {
+            return Err(format!("Invalid host IP \"{}\" in port \"{}\"", ip, spec));
+        }
+    }
+    // An empty host port publishes on a random port
+    if let Some(host_port) = host_port.filter(|p| !p.is_empty()) {
+        if !is_port_range(host_port) {
+            return Err(invalid());
+        }
+    }
+    if !is_port_range(container_port) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// A port number or an ascending `start-end` range
+fn is_port_range(range: &str) -> bool {
+    let port = |p: &str| p.parse::<u16>().ok().filter(|p| *p > 0);
+    match range.split_once('-') {
+        Some((start, end)) => matches!((port(start), port(end)), (Some(s), Some(e)) if s <= e),
+        None => port(range).is_some(),
+    }
+}
+
+/// Check long syntax: a mapping with at least `target`
+fn check_long_port(port: &Yaml) -> Result<(), String> {
+    match scalar(&port["target"]) {
+        None => return Err("Port mapping requires a target".to_string()),
+        Some(target) if !is_substituted(&target) && !is_port_range(&target) => {
+            return Err(format!("Invalid target port \"{}\"", target));
+        }
+        Some(_) => {}
+    }
+    if let Some(published) = scalar(&port["published"]) {
+        if !is_substituted(&published) && !is_port_range(&published) {
+            return Err(format!("Invalid published port \"{}\"", published));
+        }
+    }
+    if let Some(protocol) = scalar(&port["protocol"]) {
+        if !is_substituted(&protocol) && !PORT_PROTOCOLS.contains(&protocol.as_str()) {
+            return Err(format!(
+                "Invalid protocol \"{}\", expected one of {}",
+                protocol,
+                PORT_PROTOCOLS.join(", ")
+            ));
+        }
+    }
+    if let Some(mode) = scalar(&port["mode"]) {
+        if !is_substituted(&mode) && !PORT_MODES.contains(&mode.as_str()) {
+            return Err(format!(
+                "Invalid port mode \"{}\", expected one of {}",
+                mode,
+                PORT_MODES.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Where each key and list item of the first document starts, by path
+fn positions(compose_yaml: &str) -> HashMap<String, (usize, usize)> {
+    let mut recorder = PositionRecorder::default();
+    // Unparseable YAML is reported elsewhere; whatever was recorded is kept
+    let _ = Parser::new_from_str(compose_yaml).load(&mut recorder, false);
+    recorder.positions
+}
+
+enum Frame {
+    Mapping {
+        path: String,
+        /// Key of the value being read, None while reading a key
+        key: Option<String>,
+    },
+    Sequence {
+        path: String,
+        index: usize,
+    },
+}
+
+#[derive(Default)]
+struct PositionRecorder {
+    stack: Vec<Frame>,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl PositionRecorder {
+    /// Path of the node starting now; None if it is a mapping key, which is
+    /// recorded instead
+    fn node_path(&mut self, key: Option<&str>, mark: Marker) -> Option<String> {
+        let position = (mark.line(), mark.col() + 1);
+        match self.stack.last_mut() {
+            None => Some(String::new()),
+            Some(Frame::Sequence { path, index }) => {
+                let item = format!("{}[{}]", path, index);
+                *index += 1;
+                self.positions.entry(item.clone()).or_insert(position);
+                Some(item)
+            }
+            Some(Frame::Mapping { path, key: current }) => match current.take() {
+                Some(current) => Some(join(path, &current)),
+                None => {
+                    let key = key.unwrap_or("?").to_string();
+                    self.positions.entry(join(path, &key)).or_insert(position);
+                    *current = Some(key);
+                    None
+                }
+            },
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+impl MarkedEventReceiver for PositionRecorder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                self.node_path(Some(&value), mark);
+            }
+            Event::Alias(_) => {
+                self.node_path(None, mark);
+            }
+            Event::MappingStart(..) => {
+                let path = self.node_path(None, mark).unwrap_or_default();
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.node_path(None, mark).unwrap_or_default();
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_compose_spec() {
+        let yaml = r#"version: "3.8"
+service:
+  web:
+    image: nginx
+services:
+  web:
+    image: nginx
+    restart: sometimes
+    ports:
+      - "8080:80"
+      - "127.0.0.1:8443:443/tcp"
+      - "[::1]:53:53/udp"
+      - "::1:6000:6001"
+      - "9000-9010:9000-9010"
+      - "${PORT}:80"
+      - 3000
+      - "80:http"
+      - "70000:80"
+      - target: 80
+        published: "8081"
+        mode: host
+      - published: 8082
+  worker:
+    image: busybox
+    restart: on-failure:3
+    deploy:
+      restart_policy:
+        condition: never
+  broken: nginx
+x-dockru:
+  group: web
+"#;
+        let issues = check_compose_spec(yaml);
+        let found: Vec<(usize, &str)> = issues
+            .iter()
+            .map(|issue| (issue.line, issue.path.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, "service"),
+                (8, "services.web.restart"),
+                (17, "services.web.ports[7]"),
+                (18, "services.web.ports[8]"),
+                (22, "services.web.ports[10]"),
+                (28, "services.worker.deploy.restart_policy.condition"),
+                (29, "services.broken"),
+            ]
+        );
+        assert_eq!(issues[0].column, 1);
+        assert_eq!(issues[2].column, 9);
+        assert!(issues[0]
+            .to_string()
+            .starts_with("Line 2, column 1: service: Unknown top-level key"));
+
+        assert!(check_compose_spec("services:\n  web:\n    image: nginx\n").is_empty());
+        assert!(check_compose_spec("").is_empty());
+        assert!(check_compose_spec("services: [").is_empty());
+        assert_eq!(check_compose_spec("- web\n")[0].line, 1);
+    }
+
+    #[test]
+    fn test_check_port_spec() {
+        for spec in ["80", "8080:80", "127.0.0.1::80", "1.2.3.4:80-81:80-81/sctp"] {
+            assert!(check_port_spec(spec).is_ok(), "{}", spec);
+        }
+        for spec in [
+            "",
+            "80/icmp",
+            "0:80",
+            "81-80:80",
+            "host:80:80",
+            "[::1:80:80",
+        ] {
+            assert!(check_port_spec(spec).is_err(), "{}", spec);
+        }
+    }
+}
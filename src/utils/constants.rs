@@ -1,11 +1,7 @@
 // Constants and status codes
 
 // Stack Status
-pub const UNKNOWN: i32 = 0;
-pub const CREATED_FILE: i32 = 1;
-pub const CREATED_STACK: i32 = 2;
-pub const RUNNING: i32 = 3;
-pub const EXITED: i32 = 4;
+pub use dockru_protocol::status::*;
 
 // Terminal dimensions
 pub const TERMINAL_COLS: u16 = 105;
@@ -14,6 +10,115 @@ pub const PROGRESS_TERMINAL_ROWS: u16 = 8;
 pub const COMBINED_TERMINAL_COLS: u16 = 58;
 pub const COMBINED_TERMINAL_ROWS: u16 = 20;
 
+// Bounds for client-requested terminal sizes
+pub const MIN_TERMINAL_ROWS: u16 = 2;
+pub const MAX_TERMINAL_ROWS: u16 = 500;
+pub const MIN_TERMINAL_COLS: u16 = 10;
+pub const MAX_TERMINAL_COLS: u16 = 1000;
+
+// Terminal output history: chunks kept, and bytes kept unless configured
+pub const TERMINAL_BUFFER_CHUNKS: usize = 100;
+pub const DEFAULT_TERMINAL_BUFFER_BYTES: usize = 256 * 1024;
+
+// TERM and LANG of terminal processes and container shells, unless configured
+pub const DEFAULT_TERMINAL_TERM: &str = "xterm-256color";
+pub const DEFAULT_TERMINAL_LANG: &str = "C.UTF-8";
+
+// Stack list broadcast interval in seconds (stackRefreshInterval setting)
+pub const DEFAULT_STACK_REFRESH_SECS: u64 = 10;
+pub const MIN_STACK_REFRESH_SECS: u64 = 5;
+pub const MAX_STACK_REFRESH_SECS: u64 = 60 * 60;
+
+// Fastest refresh a client can ask for on the stack it is viewing
+pub const MIN_WATCH_STACK_REFRESH_SECS: u64 = 2;
+
+// requestStackList calls within this window share one broadcast
+pub const REQUEST_STACK_LIST_DEBOUNCE_MS: u64 = 1000;
+
+// Container events within this window are applied as one status update
+pub const DOCKER_EVENT_DEBOUNCE_MS: u64 = 250;
+
+// Seconds between full stack list broadcasts while Docker events are followed
+pub const STACK_RESYNC_SECS: u64 = 5 * 60;
+
+// Seconds to wait before subscribing to Docker events again after the stream ends
+pub const DOCKER_EVENTS_RETRY_SECS: u64 = 5;
+
+// Seconds between Docker daemon health checks
+pub const DOCKER_WATCHDOG_SECS: u64 = 30;
+
+// Seconds between reconnect attempts while the Docker daemon is unreachable
+pub const DOCKER_WATCHDOG_RETRY_SECS: u64 = 5;
+
+// Seconds between containerStats emits; Docker samples stats every second
+pub const CONTAINER_STATS_EMIT_SECS: u64 = 2;
+
+// Seconds to wait before looking for a stats stream's containers again
+pub const CONTAINER_STATS_RETRY_SECS: u64 = 5;
+
+// Milliseconds between pullProgress emits while compose pulls
+pub const PULL_PROGRESS_EMIT_MS: u64 = 250;
+
+// Seconds probeService waits for a connection or HTTP response
+pub const PROBE_TIMEOUT_SECS: u64 = 5;
+
+// queryStackList page size, when not given and at most
+pub const DEFAULT_STACK_LIST_PAGE_SIZE: usize = 50;
+pub const MAX_STACK_LIST_PAGE_SIZE: usize = 500;
+
+// getStackEvents page size, when not given and at most
+pub const DEFAULT_STACK_EVENT_PAGE_SIZE: i64 = 50;
+pub const MAX_STACK_EVENT_PAGE_SIZE: i64 = 200;
+
+// getAuditLog page size, when not given and at most
+pub const DEFAULT_AUDIT_LOG_PAGE_SIZE: i64 = 100;
+pub const MAX_AUDIT_LOG_PAGE_SIZE: i64 = 500;
+
+// How often stack statuses are sampled for uptime, in seconds
+pub const UPTIME_SAMPLE_SECS: u64 = 60;
+
+// Seconds between batched writes of buffered status samples
+pub const STATUS_WRITE_BATCH_SECS: u64 = 5;
+
+// Status samples buffered for writing at most; the oldest are dropped beyond
+pub const STATUS_WRITE_BUFFER: usize = 10_000;
+
+// Stacks a batchStackAction runs at the same time
+pub const BATCH_STACK_CONCURRENCY: usize = 4;
+
+// Most stacks a single batchStackAction may name
+pub const MAX_BATCH_STACKS: usize = 100;
+
+// Most stacks a user's setStackOrder may list
+pub const MAX_STACK_ORDER_LENGTH: usize = 1000;
+
+// How long a share link to a stack's logs is valid, when not given and at most (minutes)
+pub const DEFAULT_SHARE_LINK_MINUTES: i64 = 60;
+pub const MAX_SHARE_LINK_MINUTES: i64 = 7 * 24 * 60;
+
+// Log lines a share link shows before following new output
+pub const SHARE_LINK_LOG_TAIL: u64 = 500;
+
+// Seconds between checks that a followed share link hasn't been revoked
+pub const SHARE_LINK_RECHECK_SECS: u64 = 5;
+
+// How long a proxied agent event waits for the remote endpoint's response.
+// Long enough for a deploy that pulls large images.
+pub const AGENT_PROXY_ACK_TIMEOUT_SECS: u64 = 15 * 60;
+
+// Directory under the data dir for env files with secrets filled in
+pub const GENERATED_ENV_DIR: &str = "generated-env";
+
+// Panic reports in the data dir, rotated once they reach the size
+pub const CRASH_REPORT_FILE_NAME: &str = "crash-report.log";
+pub const CRASH_REPORT_MAX_BYTES: u64 = 1024 * 1024;
+
+// Where relocateStacksDir moved the stacks dir, in the data dir
+pub const RELOCATION_RECORD_FILE_NAME: &str = "stacks-dir.json";
+
+// Longest stack group name, in characters
+pub const MAX_GROUP_NAME_LENGTH: usize = 64;
+
 // Error types
 #[allow(dead_code)]
 pub const ERROR_TYPE_VALIDATION: i32 = 1;
@@ -21,6 +126,9 @@ pub const ERROR_TYPE_VALIDATION: i32 = 1;
 // Special endpoint marker
 pub const ALL_ENDPOINTS: &str = "##ALL_DOCKRU_ENDPOINTS##";
 
+// Compose file name of new stacks, unless the defaultComposeFileName setting says otherwise
+pub const DEFAULT_COMPOSE_FILE_NAME: &str = "compose.yaml";
+
 // Accepted compose file names (in order of preference)
 pub const ACCEPTED_COMPOSE_FILE_NAMES: &[&str] = &[
     "compose.yaml",
@@ -29,6 +137,47 @@ pub const ACCEPTED_COMPOSE_FILE_NAMES: &[&str] = &[
     "compose.yml",
 ];
 
+// Override files used alongside the main compose file (in order of preference)
+pub const ACCEPTED_COMPOSE_OVERRIDE_FILE_NAMES: &[&str] = &[
+    "compose.override.yaml",
+    "docker-compose.override.yaml",
+    "docker-compose.override.yml",
+    "compose.override.yml",
+];
+
+// File in a stack directory holding a user-specified compose file list, one per line
+pub const COMPOSE_FILE_LIST_NAME: &str = ".dockru-compose-files";
+
+// File in a stack directory holding the docker context the stack is deployed to
+pub const DOCKER_CONTEXT_FILE_NAME: &str = ".dockru-docker-context";
+
+// README files shown in the stack detail view (in order of preference)
+pub const ACCEPTED_README_FILE_NAMES: &[&str] = &["README.md", "readme.md", "Readme.md"];
+
+// Maximum README size sent to clients, larger files are truncated
+pub const README_MAX_BYTES: usize = 64 * 1024;
+
+// Maximum size of a compressed stack export/import archive
+pub const MAX_STACK_ARCHIVE_BYTES: usize = 50 * 1024 * 1024;
+
+// Maximum total size of the files in an imported stack archive
+pub const MAX_STACK_ARCHIVE_UNPACKED_BYTES: usize = 200 * 1024 * 1024;
+
+// Maximum size of a file uploaded into a container
+pub const MAX_CONTAINER_FILE_BYTES: usize = 50 * 1024 * 1024;
+
+// Maximum size of an uploaded image tarball for docker load
+pub const MAX_IMAGE_ARCHIVE_BYTES: u64 = 16 * 1024 * 1024 * 1024;
+
+// Maximum size of a compressed instance backup, which is built in memory
+pub const MAX_INSTANCE_BACKUP_BYTES: usize = 1024 * 1024 * 1024;
+
+// Maximum size of a compose or .env file read when migrating stacks from another tool
+pub const MAX_MIGRATION_FILE_BYTES: u64 = 1024 * 1024;
+
+// Bytes per chunk of a chunked transfer; base64 keeps a chunk under Socket.IO's 100 kB limit
+pub const TRANSFER_CHUNK_BYTES: usize = 48 * 1024;
+
 /// Convert status code to status name
 #[allow(dead_code)]
 pub fn status_name(status: i32) -> &'static str {
@@ -37,6 +186,9 @@ pub fn status_name(status: i32) -> &'static str {
         CREATED_STACK => "created_stack",
         RUNNING => "running",
         EXITED => "exited",
+        PARTIAL => "partial",
+        RESTARTING => "restarting",
+        UNHEALTHY => "unhealthy",
         _ => "unknown",
     }
 }
@@ -49,6 +201,9 @@ pub fn status_name_short(status: i32) -> &'static str {
         CREATED_STACK => "inactive",
         RUNNING => "active",
         EXITED => "exited",
+        PARTIAL => "partial",
+        RESTARTING => "restarting",
+        UNHEALTHY => "unhealthy",
         _ => "?",
     }
 }
@@ -61,6 +216,9 @@ pub fn status_color(status: i32) -> &'static str {
         CREATED_STACK => "dark",
         RUNNING => "primary",
         EXITED => "danger",
+        PARTIAL => "warning",
+        RESTARTING => "warning",
+        UNHEALTHY => "danger",
         _ => "secondary",
     }
 }
@@ -75,6 +233,8 @@ mod tests {
         assert_eq!(status_name(EXITED), "exited");
         assert_eq!(status_name(CREATED_FILE), "draft");
         assert_eq!(status_name(UNKNOWN), "unknown");
+        assert_eq!(status_name(PARTIAL), "partial");
+        assert_eq!(status_name(UNHEALTHY), "unhealthy");
     }
 
     #[test]
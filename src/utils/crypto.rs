@@ -1,6 +1,6 @@
 // Cryptographic utilities
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
 use anyhow::{Context, Result};
@@ -21,7 +21,6 @@ const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwx
 ///
 /// # Returns
 /// A random string of the specified length
-#[allow(dead_code)]
 pub fn gen_secret(length: usize) -> String {
     let mut rng = rand::thread_rng();
     (0..length)
@@ -166,6 +165,113 @@ pub fn is_password_encrypted(value: &str) -> bool {
     value.starts_with(ENCRYPTED_PREFIX)
 }
 
+/// Magic bytes starting a passphrase-encrypted payload
+const PASSPHRASE_MAGIC: &[u8; 8] = b"DOCKRUE1";
+
+/// PBKDF2 rounds for keys derived from passphrases
+const PASSPHRASE_ITERATIONS: u32 = 210_000;
+
+/// Most PBKDF2 rounds accepted from a payload header
+const MAX_PASSPHRASE_ITERATIONS: u32 = 10_000_000;
+
+const PASSPHRASE_SALT_LEN: usize = 16;
+
+/// Magic, rounds (u32, big endian), salt and nonce
+const PASSPHRASE_HEADER_LEN: usize = PASSPHRASE_MAGIC.len() + 4 + PASSPHRASE_SALT_LEN + 12;
+
+/// Shortest passphrase accepted for encryption
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Derive a 256-bit AES key from a passphrase with PBKDF2-HMAC-SHA3-256.
+fn derive_passphrase_key(passphrase: &Secret<String>, salt: &[u8], iterations: u32) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<Sha3_256, 32>(
+        passphrase.expose_secret().as_bytes(),
+        salt,
+        iterations,
+    )
+}
+
+/// Encrypt data with a key derived from a passphrase, using AES-256-GCM.
+///
+/// Format: `DOCKRUE1 | rounds | salt | nonce | ciphertext + tag`. The header is
+/// authenticated along with the data, so decryption fails if any of it was
+/// changed.
+pub fn encrypt_with_passphrase(data: &[u8], passphrase: &Secret<String>) -> Result<Vec<u8>> {
+    encrypt_with_passphrase_rounds(data, passphrase, PASSPHRASE_ITERATIONS)
+}
+
+/// [`encrypt_with_passphrase`] with a given number of PBKDF2 rounds
+pub(crate) fn encrypt_with_passphrase_rounds(
+    data: &[u8],
+    passphrase: &Secret<String>,
+    iterations: u32,
+) -> Result<Vec<u8>> {
+    if passphrase.expose_secret().chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(anyhow::anyhow!(
+            "The passphrase must be at least {} characters long",
+            MIN_PASSPHRASE_LEN
+        ));
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; PASSPHRASE_SALT_LEN];
+    rng.fill(&mut salt);
+    let mut nonce_bytes = [0u8; 12];
+    rng.fill(&mut nonce_bytes);
+
+    let mut output = Vec::with_capacity(PASSPHRASE_HEADER_LEN + data.len() + 16);
+    output.extend_from_slice(PASSPHRASE_MAGIC);
+    output.extend_from_slice(&iterations.to_be_bytes());
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce_bytes);
+
+    let key = derive_passphrase_key(passphrase, &salt, iterations);
+    let cipher = Aes256Gcm::new_from_slice(&key).context("Failed to create AES-GCM cipher")?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce_bytes),
+            Payload {
+                msg: data,
+                aad: &output,
+            },
+        )
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+/// Decrypt data encrypted by [`encrypt_with_passphrase`], verifying it wasn't altered.
+pub fn decrypt_with_passphrase(data: &[u8], passphrase: &Secret<String>) -> Result<Vec<u8>> {
+    if !is_passphrase_encrypted(data) || data.len() < PASSPHRASE_HEADER_LEN {
+        return Err(anyhow::anyhow!("Data is not passphrase-encrypted"));
+    }
+    let (header, ciphertext) = data.split_at(PASSPHRASE_HEADER_LEN);
+    let (iterations, rest) = header[PASSPHRASE_MAGIC.len()..].split_at(4);
+    let (salt, nonce_bytes) = rest.split_at(PASSPHRASE_SALT_LEN);
+
+    let iterations = u32::from_be_bytes(iterations.try_into().expect("4 bytes"));
+    if iterations == 0 || iterations > MAX_PASSPHRASE_ITERATIONS {
+        return Err(anyhow::anyhow!("Encrypted data has an invalid header"));
+    }
+
+    let key = derive_passphrase_key(passphrase, salt, iterations);
+    let cipher = Aes256Gcm::new_from_slice(&key).context("Failed to create AES-GCM cipher")?;
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce_bytes),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase, or the encrypted data is corrupted"))
+}
+
+/// Check if data starts like a passphrase-encrypted payload.
+pub fn is_passphrase_encrypted(data: &[u8]) -> bool {
+    data.starts_with(PASSPHRASE_MAGIC)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -304,4 +410,82 @@ mod tests {
         let decrypted = decrypt_password(&encrypted, &secret).unwrap();
         assert_eq!(decrypted.expose_secret(), "pässwörd_日本語_🔒");
     }
+
+    #[test]
+    fn test_passphrase_encryption_roundtrip() {
+        let passphrase = Secret::new("correct horse battery".to_string());
+        let data = b"stack archive bytes".to_vec();
+
+        let encrypted = encrypt_with_passphrase_rounds(&data, &passphrase, 1000).unwrap();
+        assert!(is_passphrase_encrypted(&encrypted));
+        assert!(!is_passphrase_encrypted(&data));
+        assert_ne!(&encrypted[PASSPHRASE_HEADER_LEN..], data.as_slice());
+
+        assert_eq!(
+            decrypt_with_passphrase(&encrypted, &passphrase).unwrap(),
+            data
+        );
+
+        // Fresh salt and nonce every time
+        let again = encrypt_with_passphrase_rounds(&data, &passphrase, 1000).unwrap();
+        assert_ne!(encrypted, again);
+    }
+
+    #[test]
+    fn test_passphrase_decryption_verifies_integrity() {
+        let passphrase = Secret::new("correct horse battery".to_string());
+        let encrypted = encrypt_with_passphrase_rounds(b"data", &passphrase, 1000).unwrap();
+
+        let wrong = Secret::new("incorrect horse battery".to_string());
+        let err = decrypt_with_passphrase(&encrypted, &wrong).unwrap_err();
+        assert!(err.to_string().contains("Wrong passphrase"));
+
+        // Flipping a bit of the ciphertext or of the header is detected
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt_with_passphrase(&tampered, &passphrase).is_err());
+        let mut tampered = encrypted.clone();
+        tampered[PASSPHRASE_MAGIC.len() + 4] ^= 1;
+        assert!(decrypt_with_passphrase(&tampered, &passphrase).is_err());
+
+        assert!(decrypt_with_passphrase(&encrypted[..20], &passphrase).is_err());
+        assert!(decrypt_with_passphrase(b"plain", &passphrase).is_err());
+    }
+
+    #[test]
+    fn test_derive_passphrase_key_known_answers() {
+        // Computed with Python's hashlib.pbkdf2_hmac("sha3_256", ...); existing
+        // exports can only be decrypted while these hold
+        let vectors = [
+            (
+                "password",
+                "salt",
+                1,
+                "94613f3ee2ea730e0b06754f3fc816d4f87c9be9cbd8556b5d59b52330e333a8",
+            ),
+            (
+                "password",
+                "salt",
+                4096,
+                "778b6e237a0f49621549ff70d218d2080756b9fb38d71b5d7ef447fa2254af61",
+            ),
+            (
+                "passwordPASSWORDpassword",
+                "saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                "7aef8f1ad8c7f12205334f624d4af9e2863121618f7a0b3209bef3934801c39f",
+            ),
+        ];
+        for (passphrase, salt, iterations, expected) in vectors {
+            let passphrase = Secret::new(passphrase.to_string());
+            let key = derive_passphrase_key(&passphrase, salt.as_bytes(), iterations);
+            assert_eq!(hex::encode(key), expected);
+        }
+    }
+
+    #[test]
+    fn test_passphrase_too_short() {
+        let short = Secret::new("hunter2".to_string());
+        assert!(encrypt_with_passphrase(b"data", &short).is_err());
+    }
 }
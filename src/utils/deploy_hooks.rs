@@ -0,0 +1,127 @@
+// Deploy hooks for compose files
+//
+// A compose file can define shell commands to run around a deploy or update
+// with an `x-dockru.hooks` extension:
+//
+//   x-dockru:
+//     hooks:
+//       pre-deploy:
+//         - ./scripts/backup-db.sh
+//       post-deploy: curl -fsS https://hc-ping.com/some-uuid
+//
+// Each command runs with `sh -c` in the stack directory, one after another,
+// with its output in the stack's terminal. A failing pre-deploy hook aborts the
+// deploy before `docker compose up`; a failing post-deploy hook fails the
+// operation after the stack is up.
+
+use anyhow::{anyhow, Result};
+use yaml_rust2::{Yaml, YamlLoader};
+
+pub use dockru_protocol::compose::{DeployHooks, HookStage};
+
+/// Read the `x-dockru.hooks` extension from a compose file
+///
+/// Returns no hooks when the compose file has none. Hooks that are present
+/// but malformed are an error.
+pub fn parse_deploy_hooks(compose_yaml: &str) -> Result<DeployHooks> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Ok(DeployHooks::default());
+    };
+    let Some(doc) = docs.first() else {
+        return Ok(DeployHooks::default());
+    };
+
+    let hooks = &doc["x-dockru"]["hooks"];
+    match hooks {
+        Yaml::BadValue | Yaml::Null => return Ok(DeployHooks::default()),
+        Yaml::Hash(stages) => {
+            for key in stages.keys() {
+                match key.as_str() {
+                    Some("pre-deploy") | Some("post-deploy") => {}
+                    _ => {
+                        return Err(anyhow!(
+                            "Unknown hook {:?}, expected pre-deploy or post-deploy",
+                            key
+                        ))
+                    }
+                }
+            }
+        }
+        _ => return Err(anyhow!("x-dockru.hooks must be a mapping")),
+    }
+
+    Ok(DeployHooks {
+        pre_deploy: parse_commands(&hooks[HookStage::PreDeploy.as_str()], HookStage::PreDeploy)?,
+        post_deploy: parse_commands(
+            &hooks[HookStage::PostDeploy.as_str()],
+            HookStage::PostDeploy,
+        )?,
+    })
+}
+
+/// A single command or a list of commands
+fn parse_commands(value: &Yaml, stage: HookStage) -> Result<Vec<String>> {
+    let commands = match value {
+        Yaml::BadValue | Yaml::Null => Vec::new(),
+        Yaml::String(command) => vec![command.clone()],
+        Yaml::Array(items) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("{} hooks must be strings", stage.as_str()))
+            })
+            .collect::<Result<_>>()?,
+        _ => {
+            return Err(anyhow!(
+                "{} must be a command or a list of commands",
+                stage.as_str()
+            ))
+        }
+    };
+
+    if commands.iter().any(|c| c.trim().is_empty()) {
+        return Err(anyhow!("{} hooks must not be empty", stage.as_str()));
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deploy_hooks() {
+        let yaml = r#"
+services:
+  web:
+    image: nginx
+x-dockru:
+  hooks:
+    pre-deploy:
+      - ./backup.sh
+      - echo ready
+    post-deploy: curl -fsS https://example.com/ping
+"#;
+        let hooks = parse_deploy_hooks(yaml).unwrap();
+        assert_eq!(hooks.pre_deploy, vec!["./backup.sh", "echo ready"]);
+        assert_eq!(
+            hooks.commands(HookStage::PostDeploy),
+            ["curl -fsS https://example.com/ping"]
+        );
+
+        assert_eq!(
+            parse_deploy_hooks("services: {}\n").unwrap(),
+            DeployHooks::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_deploy_hooks_invalid() {
+        assert!(parse_deploy_hooks("x-dockru:\n  hooks: [a]\n").is_err());
+        assert!(parse_deploy_hooks("x-dockru:\n  hooks:\n    pre-start: a\n").is_err());
+        assert!(parse_deploy_hooks("x-dockru:\n  hooks:\n    pre-deploy: [1]\n").is_err());
+        assert!(parse_deploy_hooks("x-dockru:\n  hooks:\n    pre-deploy: ''\n").is_err());
+    }
+}
@@ -1,5 +1,85 @@
 // Docker-related utilities
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// A docker CLI invocation, logged when it starts and when it finishes
+///
+/// Each invocation gets a short correlation ID that prefixes its log lines and
+/// is printed at the top of its terminal, so a failed operation in the UI can
+/// be matched to the server log.
+#[derive(Debug)]
+pub struct CommandTrace {
+    id: String,
+    command: String,
+    started: Instant,
+}
+
+impl CommandTrace {
+    /// Log the start of a command
+    pub fn start(file: &str, args: &[String], cwd: &str) -> Self {
+        let trace = CommandTrace {
+            id: hex::encode(rand::thread_rng().gen::<[u8; 4]>()),
+            command: format_command(file, args),
+            started: Instant::now(),
+        };
+        info!("[{}] Running {} (cwd: {})", trace.id, trace.command, cwd);
+        trace
+    }
+
+    /// Correlation ID of this invocation
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The command line, as it would be typed in a shell
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Log the exit code and how long the command took
+    pub fn finish(self, exit_code: i32) {
+        let elapsed = self.started.elapsed().as_millis();
+        if exit_code == 0 {
+            info!("[{}] Exited with 0 after {}ms", self.id, elapsed);
+        } else {
+            warn!(
+                "[{}] Exited with {} after {}ms: {}",
+                self.id, exit_code, elapsed, self.command
+            );
+        }
+    }
+
+    /// Log a command that could not be run at all
+    pub fn fail(self, error: &anyhow::Error) {
+        warn!(
+            "[{}] Failed after {}ms: {}: {:#}",
+            self.id,
+            self.started.elapsed().as_millis(),
+            self.command,
+            error
+        );
+    }
+}
+
+/// Join a command and its arguments, quoting arguments a shell would split
+pub fn format_command(file: &str, args: &[String]) -> String {
+    let mut command = file.to_string();
+    for arg in args {
+        command.push(' ');
+        if arg.is_empty()
+            || arg
+                .chars()
+                .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '$' | '\\'))
+        {
+            command.push_str(&format!("'{}'", arg.replace('\'', r"'\''")));
+        } else {
+            command.push_str(arg);
+        }
+    }
+    command
+}
 
 /// Parsed Docker port information
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -104,6 +184,23 @@ pub fn parse_docker_port(input: &str, hostname: &str) -> DockerPort {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_command() {
+        let args: Vec<String> = ["compose", "up", "-d", "--env-file", "../global env"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            format_command("docker", &args),
+            "docker compose up -d --env-file '../global env'"
+        );
+        assert_eq!(
+            format_command("sh", &["-c".to_string(), "echo 'hi'".to_string()]),
+            r"sh -c 'echo '\''hi'\'''"
+        );
+        assert_eq!(format_command("docker", &[String::new()]), "docker ''");
+    }
+
     #[test]
     fn test_parse_docker_port_simple() {
         let result = parse_docker_port("3000", "localhost");
@@ -0,0 +1,101 @@
+// Conversion of a `docker run` command into a compose service
+//
+// composerize does the flag mapping; this picks the service out of the compose
+// file it generates so the snippet can be pasted under an existing stack's
+// `services:`, and lists the named volumes and external networks the service
+// uses, which the stack has to declare at the top level.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use yaml_rust2::{yaml::Hash, Yaml, YamlLoader};
+
+use super::yaml_utils::yaml_to_string;
+
+/// A compose service converted from a `docker run` command
+#[derive(Debug, Clone, Serialize)]
+pub struct ConvertedService {
+    #[serde(rename = "serviceName")]
+    pub service_name: String,
+    /// `<serviceName>:` and the service's definition
+    #[serde(rename = "serviceYAML")]
+    pub service_yaml: String,
+    /// Named volumes to declare under the top-level `volumes:`
+    pub volumes: Vec<String>,
+    /// External networks to declare under the top-level `networks:`
+    pub networks: Vec<String>,
+}
+
+/// Convert a `docker run ...` command line into a compose service
+///
+/// The service is named after `--name` if given, otherwise after the image.
+pub fn convert_docker_run(command: &str) -> Result<ConvertedService> {
+    let compose = composerize_np::composerize(command.trim(), "", "latest", 2)
+        .map_err(|e| anyhow!("Failed to convert docker run command: {}", e))?;
+    let doc = YamlLoader::load_from_str(&compose)
+        .context("Failed to parse converted compose file")?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Converted compose file is empty"))?;
+
+    let (image_name, service) = doc["services"]
+        .as_hash()
+        .and_then(|services| services.iter().next())
+        .ok_or_else(|| anyhow!("Converted compose file has no service"))?;
+    let service_name = service["container_name"]
+        .as_str()
+        .or_else(|| image_name.as_str())
+        .ok_or_else(|| anyhow!("Converted service has no name"))?
+        .to_string();
+
+    let mut snippet = Hash::new();
+    snippet.insert(Yaml::String(service_name.clone()), service.clone());
+    let service_yaml = yaml_to_string(&Yaml::Hash(snippet))?;
+    let service_yaml = service_yaml
+        .strip_prefix("---\n")
+        .unwrap_or(&service_yaml)
+        .to_string();
+
+    Ok(ConvertedService {
+        service_name,
+        service_yaml,
+        volumes: top_level_names(&doc["volumes"]),
+        networks: top_level_names(&doc["networks"]),
+    })
+}
+
+fn top_level_names(section: &Yaml) -> Vec<String> {
+    section
+        .as_hash()
+        .map(|hash| {
+            hash.keys()
+                .filter_map(|key| key.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_docker_run() {
+        let converted = convert_docker_run(
+            "docker run -d --name web -p 8080:80 -v data:/usr/share/nginx/html \
+             -e TZ=UTC --restart unless-stopped nginx:1.27",
+        )
+        .unwrap();
+        assert_eq!(converted.service_name, "web");
+        assert!(converted.service_yaml.starts_with("web:\n"));
+        assert!(converted.service_yaml.contains("image: \"nginx:1.27\""));
+        assert!(converted.service_yaml.contains("8080:80"));
+        assert!(converted.service_yaml.contains("restart: unless-stopped"));
+        assert_eq!(converted.volumes, ["data"]);
+        assert!(converted.networks.is_empty());
+
+        let converted = convert_docker_run("docker run redis").unwrap();
+        assert_eq!(converted.service_name, "redis");
+
+        assert!(convert_docker_run("").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+// Environment variable schema for compose files
+//
+// A compose file can describe the variables its .env is expected to define with
+// an `x-dockru.env-schema` extension, which docker compose itself ignores:
+//
+//   x-dockru:
+//     env-schema:
+//       DB_PASSWORD:
+//         type: secret
+//         description: Password for the database user
+//         required: true
+//       LOG_LEVEL:
+//         type: string
+//         enum: [debug, info, warn]
+//         default: info
+//
+// The schema is returned with the stack so the UI can render a form for .env
+// editing. Variables keep the order they are declared in. When a stack is
+// created, variables missing from its .env are filled in from their defaults,
+// and secret variables without a default get a random value.
+
+use crate::utils::crypto::gen_secret;
+use anyhow::{anyhow, Result};
+use yaml_rust2::{Yaml, YamlLoader};
+
+pub use dockru_protocol::compose::{EnvVarSchema, EnvVarType};
+
+/// Length of values generated for secret variables
+const GENERATED_SECRET_LENGTH: usize = 32;
+
+fn parse_var_type(s: &str) -> Result<EnvVarType> {
+    match s {
+        "string" => Ok(EnvVarType::String),
+        "number" => Ok(EnvVarType::Number),
+        "boolean" => Ok(EnvVarType::Boolean),
+        "secret" => Ok(EnvVarType::Secret),
+        _ => Err(anyhow!(
+            "Unknown type \"{}\", expected string, number, boolean or secret",
+            s
+        )),
+    }
+}
+
+/// Read the `x-dockru.env-schema` extension from a compose file
+///
+/// Returns an empty list when the compose file has no schema. A schema that
+/// is present but malformed is an error.
+pub fn parse_env_schema(compose_yaml: &str) -> Result<Vec<EnvVarSchema>> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Ok(Vec::new());
+    };
+    let Some(doc) = docs.first() else {
+        return Ok(Vec::new());
+    };
+
+    let schema = &doc["x-dockru"]["env-schema"];
+    let vars = match schema {
+        Yaml::BadValue | Yaml::Null => return Ok(Vec::new()),
+        Yaml::Hash(vars) => vars,
+        _ => return Err(anyhow!("x-dockru.env-schema must be a mapping")),
+    };
+
+    vars.iter()
+        .map(|(name, spec)| {
+            let name = name
+                .as_str()
+                .ok_or_else(|| anyhow!("env-schema variable names must be strings"))?;
+            parse_var(name, spec).map_err(|e| anyhow!("env-schema {}: {}", name, e))
+        })
+        .collect()
+}
+
+fn parse_var(name: &str, spec: &Yaml) -> Result<EnvVarSchema> {
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        || name.is_empty()
+        || name.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return Err(anyhow!("Invalid variable name"));
+    }
+
+    let mut var = EnvVarSchema {
+        name: name.to_string(),
+        var_type: EnvVarType::default(),
+        description: None,
+        required: false,
+        allowed: Vec::new(),
+        default: None,
+    };
+
+    match spec {
+        // `NAME:` with nothing else, a plain string variable
+        Yaml::Null => return Ok(var),
+        Yaml::Hash(_) => {}
+        _ => return Err(anyhow!("Expected a mapping")),
+    }
+
+    if let Some(var_type) = optional(&spec["type"], "type")? {
+        var.var_type = parse_var_type(&var_type)?;
+    }
+    var.description = optional(&spec["description"], "description")?;
+    var.required = match &spec["required"] {
+        Yaml::BadValue | Yaml::Null => false,
+        Yaml::Boolean(b) => *b,
+        _ => return Err(anyhow!("required must be a boolean")),
+    };
+    var.allowed = match &spec["enum"] {
+        Yaml::BadValue | Yaml::Null => Vec::new(),
+        Yaml::Array(values) => values
+            .iter()
+            .map(|v| scalar(v).ok_or_else(|| anyhow!("enum values must be scalars")))
+            .collect::<Result<_>>()?,
+        _ => return Err(anyhow!("enum must be a list")),
+    };
+    var.default = optional(&spec["default"], "default")?;
+
+    if let Some(default) = &var.default {
+        if !var.allowed.is_empty() && !var.allowed.contains(default) {
+            return Err(anyhow!("default \"{}\" is not one of enum", default));
+        }
+    }
+
+    Ok(var)
+}
+
+/// Append the schema's variables that `env` doesn't define yet
+///
+/// Variables get their default, secrets without a default a random value.
+/// Others are left out, since an empty value would override compose's own
+/// `${VAR:-default}` fallbacks. Returns `env` unchanged if nothing is missing.
+pub fn fill_env(schema: &[EnvVarSchema], env: &str) -> String {
+    let defined: Vec<&str> = env.lines().filter_map(env_line_name).collect();
+
+    let mut added = String::new();
+    for var in schema {
+        if defined.contains(&var.name.as_str()) {
+            continue;
+        }
+        let value = match (&var.default, var.var_type) {
+            (Some(default), _) => default.clone(),
+            (None, EnvVarType::Secret) => gen_secret(GENERATED_SECRET_LENGTH),
+            (None, _) => continue,
+        };
+
+        if let Some(description) = &var.description {
+            for line in description.lines() {
+                added.push_str(&format!("# {}\n", line));
+            }
+        }
+        added.push_str(&format!("{}={}\n", var.name, quote_env_value(&value)));
+    }
+
+    if added.is_empty() {
+        return env.to_string();
+    }
+
+    let mut filled = env.to_string();
+    if !filled.is_empty() && !filled.ends_with('\n') {
+        filled.push('\n');
+    }
+    filled.push_str(&added);
+    filled
+}
+
+/// The variable name of a `NAME=value` or `export NAME=value` line
+fn env_line_name(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    line.split_once('=').map(|(name, _)| name.trim())
+}
+
+/// Quote values that compose would otherwise split or interpolate
+pub(crate) fn quote_env_value(value: &str) -> String {
+    if value
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '#' | '$' | '"' | '\'' | '\\'))
+    {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    } else {
+        value.to_string()
+    }
+}
+
+/// An optional scalar field, as a string
+fn optional(value: &Yaml, field: &str) -> Result<Option<String>> {
+    match value {
+        Yaml::BadValue | Yaml::Null => Ok(None),
+        _ => scalar(value)
+            .map(Some)
+            .ok_or_else(|| anyhow!("{} must be a scalar", field)),
+    }
+}
+
+/// Numbers and booleans are written to .env as their YAML source text
+fn scalar(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) | Yaml::Real(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_schema() {
+        let yaml = r#"
+services:
+  db:
+    image: postgres
+x-dockru:
+  env-schema:
+    DB_PASSWORD:
+      type: secret
+      description: Password for the database user
+      required: true
+    LOG_LEVEL:
+      enum: [debug, info, warn]
+      default: info
+    PORT:
+      type: number
+      default: 8080
+    PLAIN:
+"#;
+        let schema = parse_env_schema(yaml).unwrap();
+        let names: Vec<&str> = schema.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["DB_PASSWORD", "LOG_LEVEL", "PORT", "PLAIN"]);
+
+        assert_eq!(schema[0].var_type, EnvVarType::Secret);
+        assert!(schema[0].required);
+        assert_eq!(schema[1].allowed, vec!["debug", "info", "warn"]);
+        assert_eq!(schema[1].default.as_deref(), Some("info"));
+        assert_eq!(schema[2].default.as_deref(), Some("8080"));
+        assert_eq!(schema[3].var_type, EnvVarType::String);
+        assert!(!schema[3].required);
+
+        let json = serde_json::to_value(&schema[1]).unwrap();
+        assert_eq!(json["type"], "string");
+        assert_eq!(json["enum"][0], "debug");
+    }
+
+    #[test]
+    fn test_parse_env_schema_missing_or_invalid() {
+        assert!(parse_env_schema("services: {}\n").unwrap().is_empty());
+        assert!(parse_env_schema("not: [valid").unwrap().is_empty());
+
+        let bad_type = "x-dockru:\n  env-schema:\n    A:\n      type: date\n";
+        assert!(parse_env_schema(bad_type).is_err());
+
+        let bad_default = "x-dockru:\n  env-schema:\n    A:\n      enum: [x]\n      default: y\n";
+        assert!(parse_env_schema(bad_default).is_err());
+
+        let bad_name = "x-dockru:\n  env-schema:\n    1A:\n";
+        assert!(parse_env_schema(bad_name).is_err());
+
+        assert!(parse_env_schema("x-dockru:\n  env-schema: [A]\n").is_err());
+    }
+
+    #[test]
+    fn test_fill_env() {
+        let yaml = r#"
+x-dockru:
+  env-schema:
+    DB_PASSWORD:
+      type: secret
+    LOG_LEVEL:
+      description: How chatty the app is
+      default: info
+    GREETING:
+      default: hello world
+    HOSTNAME:
+      required: true
+"#;
+        let schema = parse_env_schema(yaml).unwrap();
+
+        let env = fill_env(&schema, "LOG_LEVEL=debug");
+        let lines: Vec<&str> = env.lines().collect();
+        assert_eq!(lines[0], "LOG_LEVEL=debug");
+        assert!(lines[1].starts_with("DB_PASSWORD="));
+        assert_eq!(
+            lines[1].len(),
+            "DB_PASSWORD=".len() + GENERATED_SECRET_LENGTH
+        );
+        assert_eq!(lines[2], "GREETING='hello world'");
+        assert_eq!(lines.len(), 3);
+
+        let env = fill_env(&schema, "");
+        assert!(env.contains("# How chatty the app is\nLOG_LEVEL=info\n"));
+
+        // Nothing missing, nothing changes
+        let full = "export DB_PASSWORD=x\nLOG_LEVEL=warn\nGREETING=hi";
+        assert_eq!(fill_env(&schema, full), full);
+    }
+}
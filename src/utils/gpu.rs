@@ -0,0 +1,94 @@
+// GPU requests of compose files
+//
+// A service gets a GPU with `gpus:` or a device reservation under
+// `deploy.resources.reservations.devices`. On a host without the NVIDIA or
+// ROCm container runtime, `docker compose up` then fails with errors like
+// `could not select device driver "nvidia"`, which don't say much. Before a
+// deploy the stack checks the host's runtimes (`docker info`) and warns about
+// the services that request a GPU when there is none.
+//
+// The NVIDIA toolkit can also work through its prestart hook without a
+// registered runtime, so this only ever warns.
+
+use yaml_rust2::YamlLoader;
+
+/// Substrings of the names of runtimes that provide GPUs, e.g. `nvidia`,
+/// `nvidia-cdi` or `amd`
+const GPU_RUNTIMES: [&str; 3] = ["nvidia", "amd", "rocm"];
+
+/// Names of the services of a compose file that request a GPU
+///
+/// Returns nothing for YAML that doesn't parse, which is reported separately.
+pub fn gpu_services(compose_yaml: &str) -> Vec<String> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Vec::new();
+    };
+    let Some(services) = docs.first().and_then(|doc| doc["services"].as_hash()) else {
+        return Vec::new();
+    };
+
+    services
+        .iter()
+        .filter(|(_, service)| {
+            let devices = &service["deploy"]["resources"]["reservations"]["devices"];
+            !service["gpus"].is_badvalue() || devices.as_vec().is_some_and(|d| !d.is_empty())
+        })
+        .filter_map(|(name, _)| name.as_str().map(str::to_string))
+        .collect()
+}
+
+/// Whether a container runtime provides GPUs
+pub fn is_gpu_runtime(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    GPU_RUNTIMES.iter().any(|runtime| name.contains(runtime))
+}
+
+/// Deploy warning about a service requesting a GPU on a host without GPU runtimes
+pub fn gpu_warning(service: &str) -> String {
+    format!(
+        "services.{}: Requests a GPU, but the Docker host has no NVIDIA or ROCm runtime",
+        service
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_services() {
+        let yaml = r#"
+services:
+  ollama:
+    image: ollama/ollama
+    deploy:
+      resources:
+        reservations:
+          devices:
+            - driver: nvidia
+              count: all
+              capabilities: [gpu]
+  jellyfin:
+    image: jellyfin/jellyfin
+    gpus: all
+  web:
+    image: nginx
+    deploy:
+      resources:
+        reservations:
+          memory: 128m
+"#;
+        assert_eq!(gpu_services(yaml), vec!["ollama", "jellyfin"]);
+        assert!(gpu_services("services: [").is_empty());
+        assert!(gpu_services("version: '3'").is_empty());
+    }
+
+    #[test]
+    fn test_is_gpu_runtime() {
+        assert!(is_gpu_runtime("nvidia"));
+        assert!(is_gpu_runtime("nvidia-cdi"));
+        assert!(is_gpu_runtime("amd"));
+        assert!(!is_gpu_runtime("runc"));
+        assert!(!is_gpu_runtime("io.containerd.runc.v2"));
+    }
+}
@@ -0,0 +1,123 @@
+// Deploy-time environment injection
+//
+// Variables a user doesn't want in plaintext under the stacks directory can be
+// stored per stack in the settings table, encrypted with the same key as agent
+// passwords. They're decrypted for every compose command of the stack and
+// passed through its process environment, where compose picks them up for
+// `${VAR}` interpolation; nothing is written to `.env`.
+
+use anyhow::{anyhow, Result};
+use redact::Secret;
+use serde_json::{Map, Value};
+
+use super::crypto::{decrypt_password, encrypt_password};
+
+/// Settings type of the stored variables
+pub const INJECTED_ENV_SETTING_TYPE: &str = "injectedEnv";
+
+/// Settings key holding a stack's variables
+pub fn setting_key(stack_name: &str) -> String {
+    format!("{}:{}", INJECTED_ENV_SETTING_TYPE, stack_name)
+}
+
+/// Check a variable name: a letter or `_`, then letters, digits and `_`
+pub fn validate_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!("Invalid environment variable name: {:?}", name));
+    }
+    Ok(())
+}
+
+/// Apply changes to a stored (encrypted) set of variables
+///
+/// A string value sets the variable, null removes it.
+pub fn apply_changes(
+    stored: Option<Value>,
+    changes: &Map<String, Value>,
+    secret: &Secret<String>,
+) -> Result<Map<String, Value>> {
+    let mut vars = match stored {
+        Some(Value::Object(vars)) => vars,
+        _ => Map::new(),
+    };
+    for (name, value) in changes {
+        validate_name(name)?;
+        match value {
+            Value::Null => {
+                vars.remove(name);
+            }
+            Value::String(value) => {
+                let encrypted = encrypt_password(&Secret::new(value.clone()), secret)?;
+                vars.insert(name.clone(), Value::String(encrypted));
+            }
+            _ => return Err(anyhow!("Value of {} must be a string or null", name)),
+        }
+    }
+    Ok(vars)
+}
+
+/// Names of the stored variables, sorted
+pub fn names(stored: Option<&Value>) -> Vec<String> {
+    let mut names: Vec<String> = match stored {
+        Some(Value::Object(vars)) => vars.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+/// Decrypt stored variables into (name, value) pairs for a process environment
+pub fn decrypt(stored: Option<&Value>, secret: &Secret<String>) -> Result<Vec<(String, String)>> {
+    let Some(Value::Object(vars)) = stored else {
+        return Ok(Vec::new());
+    };
+    vars.iter()
+        .map(|(name, value)| {
+            let encrypted = value
+                .as_str()
+                .ok_or_else(|| anyhow!("Stored value of {} is not a string", name))?;
+            let value = decrypt_password(encrypted, secret)
+                .map_err(|e| anyhow!("Failed to decrypt {}: {}", name, e))?;
+            Ok((name.clone(), value.expose_secret().clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_injected_env_round_trip() {
+        let secret = Secret::new("test-secret".to_string());
+        let changes = json!({ "DB_PASSWORD": "hunter2", "API_KEY": "abc" });
+        let stored = apply_changes(None, changes.as_object().unwrap(), &secret).unwrap();
+        let stored = Value::Object(stored);
+
+        assert!(!stored.to_string().contains("hunter2"));
+        assert_eq!(names(Some(&stored)), ["API_KEY", "DB_PASSWORD"]);
+
+        let changes = json!({ "API_KEY": null });
+        let stored = apply_changes(Some(stored), changes.as_object().unwrap(), &secret).unwrap();
+        let env = decrypt(Some(&Value::Object(stored)), &secret).unwrap();
+        assert_eq!(env, [("DB_PASSWORD".to_string(), "hunter2".to_string())]);
+    }
+
+    #[test]
+    fn test_injected_env_rejects_bad_input() {
+        let secret = Secret::new("test-secret".to_string());
+        for changes in [
+            json!({ "1ABC": "x" }),
+            json!({ "A-B": "x" }),
+            json!({ "A": 1 }),
+        ] {
+            assert!(apply_changes(None, changes.as_object().unwrap(), &secret).is_err());
+        }
+    }
+}
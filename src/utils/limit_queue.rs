@@ -1,14 +1,23 @@
 // Fixed-size queue that removes oldest items when limit is exceeded
 use std::collections::VecDeque;
 
+/// Measures the size of a queue item in bytes
+type SizeOf<T> = fn(&T) -> usize;
+
 /// A queue that automatically removes the oldest element when the limit is exceeded
 ///
 /// This is useful for maintaining a fixed-size buffer, such as terminal output history.
+/// Besides the item count, the queue can be limited by the total size of its
+/// items in bytes (see `with_byte_limit`).
 #[derive(Debug, Clone)]
 pub struct LimitQueue<T> {
     queue: VecDeque<T>,
     limit: usize,
     on_exceed: Option<fn(&T)>,
+    /// Byte budget and how to measure an item
+    byte_limit: Option<(usize, SizeOf<T>)>,
+    /// Total size of the items, when a byte limit is set
+    bytes: usize,
 }
 
 impl<T> LimitQueue<T> {
@@ -21,9 +30,25 @@ impl<T> LimitQueue<T> {
             queue: VecDeque::with_capacity(limit),
             limit,
             on_exceed: None,
+            byte_limit: None,
+            bytes: 0,
         }
     }
 
+    /// Also remove the oldest items while the queue holds more than `bytes`
+    ///
+    /// The newest item is always kept, even if it alone is over the budget.
+    ///
+    /// # Arguments
+    /// * `bytes` - Maximum total size of the items
+    /// * `size_of` - Size of an item in bytes
+    pub fn with_byte_limit(mut self, bytes: usize, size_of: SizeOf<T>) -> Self {
+        self.bytes = self.queue.iter().map(size_of).sum();
+        self.byte_limit = Some((bytes, size_of));
+        self.trim();
+        self
+    }
+
     /// Set a callback to be called when an item is removed due to exceeding the limit
     ///
     /// # Arguments
@@ -41,14 +66,32 @@ impl<T> LimitQueue<T> {
     /// # Arguments
     /// * `item` - The item to push
     pub fn push(&mut self, item: T) {
+        if let Some((_, size_of)) = self.byte_limit {
+            self.bytes += size_of(&item);
+        }
         self.queue.push_back(item);
+        self.trim();
+    }
 
-        if self.queue.len() > self.limit {
-            if let Some(removed) = self.queue.pop_front() {
-                if let Some(callback) = self.on_exceed {
-                    callback(&removed);
-                }
+    /// Remove the oldest items until the queue is within its limits
+    fn trim(&mut self) {
+        while self.queue.len() > self.limit || self.over_byte_limit() {
+            let Some(removed) = self.queue.pop_front() else {
+                break;
+            };
+            if let Some((_, size_of)) = self.byte_limit {
+                self.bytes -= size_of(&removed);
             }
+            if let Some(callback) = self.on_exceed {
+                callback(&removed);
+            }
+        }
+    }
+
+    fn over_byte_limit(&self) -> bool {
+        match self.byte_limit {
+            Some((limit, _)) => self.bytes > limit && self.queue.len() > 1,
+            None => false,
         }
     }
 
@@ -69,6 +112,8 @@ impl<T> LimitQueue<T> {
     }
 
     /// Get a mutable iterator over the items in the queue
+    ///
+    /// Changing the size of items this way isn't reflected in `byte_size`.
     #[allow(dead_code)]
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
         self.queue.iter_mut()
@@ -84,10 +129,21 @@ impl<T> LimitQueue<T> {
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.queue.clear();
+        self.bytes = 0;
+    }
+
+    /// Remove all items from the queue, oldest first
+    pub fn drain(&mut self) -> Vec<T> {
+        self.bytes = 0;
+        self.queue.drain(..).collect()
+    }
+
+    /// Total size of the items in bytes, 0 without a byte limit
+    pub fn byte_size(&self) -> usize {
+        self.bytes
     }
 
     /// Get the limit of the queue
-    #[allow(dead_code)]
     pub fn limit(&self) -> usize {
         self.limit
     }
@@ -183,6 +239,42 @@ mod tests {
         assert!(queue.is_empty());
     }
 
+    #[test]
+    fn test_limit_queue_drain() {
+        let mut queue = LimitQueue::new(2).with_byte_limit(10, String::len);
+
+        queue.push("a".to_string());
+        queue.push("b".to_string());
+        queue.push("c".to_string());
+
+        assert_eq!(queue.drain(), ["b", "c"]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.byte_size(), 0);
+    }
+
+    #[test]
+    fn test_limit_queue_byte_limit() {
+        let mut queue = LimitQueue::new(100).with_byte_limit(10, String::len);
+
+        queue.push("abcd".to_string());
+        queue.push("efgh".to_string());
+        assert_eq!(queue.byte_size(), 8);
+
+        // Over the budget, the oldest chunk goes even though the count is fine
+        queue.push("ijk".to_string());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0], "efgh");
+        assert_eq!(queue.byte_size(), 7);
+
+        // A chunk larger than the budget is kept on its own
+        queue.push("x".repeat(20));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.byte_size(), 20);
+
+        queue.clear();
+        assert_eq!(queue.byte_size(), 0);
+    }
+
     #[test]
     fn test_limit_queue_index() {
         let mut queue = LimitQueue::new(3);
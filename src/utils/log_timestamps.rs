@@ -0,0 +1,182 @@
+// Log timestamp options for log terminals
+//
+// `docker compose logs --timestamps` prefixes each line with an RFC3339 UTC
+// timestamp after the service prefix, e.g.
+//   web-1  | 2024-06-01T04:00:00.123456789Z listening on :80
+// When a timezone is requested, the timestamp is rewritten in that timezone
+// before the output reaches clients, so logs from several services (or hosts)
+// line up with the user's clock.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// Partial lines longer than this are flushed without rewriting
+const MAX_PENDING_BYTES: usize = 16 * 1024;
+
+/// Matches the service prefix (with optional ANSI colors) and a UTC timestamp
+static TIMESTAMP_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^((?:[^|\n]*\|\s*)?(?:\x1b\[[0-9;]*m)*)(\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?Z)",
+    )
+    .unwrap()
+});
+
+/// Options for log terminals, sent as an optional object argument
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Pass `--timestamps` to docker compose logs
+    pub timestamps: bool,
+    /// Rewrite timestamps into this IANA timezone (implies timestamps)
+    pub timezone: Option<Tz>,
+}
+
+impl LogOptions {
+    /// Parse `{ timestamps?: bool, timezone?: string }`
+    ///
+    /// A missing or null value gives the defaults. `user_timezone` is used when
+    /// `timezone` is `"auto"`.
+    pub fn from_value(value: Option<&Value>, user_timezone: Option<&str>) -> Result<Self> {
+        let Some(value) = value.filter(|v| !v.is_null()) else {
+            return Ok(Self::default());
+        };
+        if !value.is_object() {
+            return Err(anyhow!("Log options must be an object"));
+        }
+
+        let timezone = match value["timezone"].as_str() {
+            None | Some("") => None,
+            Some("auto") => user_timezone.and_then(|tz| tz.parse::<Tz>().ok()),
+            Some(tz) => Some(
+                tz.parse::<Tz>()
+                    .map_err(|_| anyhow!("Unknown timezone \"{}\"", tz))?,
+            ),
+        };
+
+        Ok(Self {
+            timestamps: value["timestamps"].as_bool().unwrap_or(false) || timezone.is_some(),
+            timezone,
+        })
+    }
+
+    /// Suffix appended to the terminal name, so each variant gets its own terminal
+    pub fn terminal_suffix(&self) -> String {
+        match (&self.timezone, self.timestamps) {
+            (Some(tz), _) => format!("-ts-{}", tz.name()),
+            (None, true) => "-ts".to_string(),
+            (None, false) => String::new(),
+        }
+    }
+}
+
+/// Rewrites UTC log timestamps into a timezone, buffering partial lines
+pub struct TimestampRewriter {
+    timezone: Tz,
+    pending: String,
+}
+
+impl TimestampRewriter {
+    pub fn new(timezone: Tz) -> Self {
+        Self {
+            timezone,
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a chunk of output, returning everything up to the last complete line
+    pub fn process(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+
+        let Some(end) = self.pending.rfind('\n') else {
+            if self.pending.len() > MAX_PENDING_BYTES {
+                return std::mem::take(&mut self.pending);
+            }
+            return String::new();
+        };
+
+        let rest = self.pending.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.pending, rest);
+
+        complete
+            .split_inclusive('\n')
+            .map(|line| self.rewrite_line(line))
+            .collect()
+    }
+
+    fn rewrite_line(&self, line: &str) -> String {
+        let Some(caps) = TIMESTAMP_RE.captures(line) else {
+            return line.to_string();
+        };
+        let Ok(utc) = caps[2].parse::<DateTime<Utc>>() else {
+            return line.to_string();
+        };
+
+        let local = utc
+            .with_timezone(&self.timezone)
+            .to_rfc3339_opts(SecondsFormat::Millis, false);
+        let whole = caps.get(0).unwrap();
+
+        format!("{}{}{}", &caps[1], local, &line[whole.end()..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_log_options_from_value() {
+        assert_eq!(
+            LogOptions::from_value(None, None).unwrap(),
+            LogOptions::default()
+        );
+
+        let opts = LogOptions::from_value(Some(&json!({"timestamps": true})), None).unwrap();
+        assert!(opts.timestamps);
+        assert_eq!(opts.terminal_suffix(), "-ts");
+
+        let opts =
+            LogOptions::from_value(Some(&json!({"timezone": "auto"})), Some("Europe/Berlin"))
+                .unwrap();
+        assert!(opts.timestamps);
+        assert_eq!(opts.terminal_suffix(), "-ts-Europe/Berlin");
+
+        assert!(LogOptions::from_value(Some(&json!({"timezone": "Mars/Base"})), None).is_err());
+        assert!(LogOptions::from_value(Some(&json!("yes")), None).is_err());
+    }
+
+    #[test]
+    fn test_rewriter_converts_timestamps() {
+        let mut rewriter = TimestampRewriter::new(chrono_tz::Asia::Tokyo);
+
+        let out = rewriter.process("web-1  | 2024-06-01T04:00:00.123456789Z hello\n");
+        assert_eq!(out, "web-1  | 2024-06-01T13:00:00.123+09:00 hello\n");
+
+        // Colored service prefix, as written through a PTY
+        let out = rewriter.process("\x1b[36mdb-1  | \x1b[0m2024-06-01T04:00:00Z ready\r\n");
+        assert_eq!(
+            out,
+            "\x1b[36mdb-1  | \x1b[0m2024-06-01T13:00:00.000+09:00 ready\r\n"
+        );
+
+        // Lines without a timestamp pass through
+        assert_eq!(
+            rewriter.process("no timestamp here\n"),
+            "no timestamp here\n"
+        );
+    }
+
+    #[test]
+    fn test_rewriter_buffers_partial_lines() {
+        let mut rewriter = TimestampRewriter::new(chrono_tz::UTC);
+
+        assert_eq!(rewriter.process("web-1  | 2024-06-01T04:0"), "");
+        let out = rewriter.process("0:00Z hi\nweb-1  | 2024");
+        assert_eq!(out, "web-1  | 2024-06-01T04:00:00.000+00:00 hi\n");
+        assert_eq!(rewriter.pending, "web-1  | 2024");
+    }
+}
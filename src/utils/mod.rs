@@ -1,10 +1,30 @@
 // Common utilities for Dockru
+pub mod bulk_edit;
+pub mod compose_env;
+pub mod compose_include;
+pub mod compose_policy;
+pub mod compose_spec;
 pub mod constants;
 pub mod crypto;
+pub mod deploy_hooks;
 pub mod docker;
+pub mod docker_run;
+pub mod env_schema;
+pub mod gpu;
+pub mod injected_env;
 pub mod limit_queue;
+pub mod log_timestamps;
+pub mod pull_progress;
+pub mod restart_policy;
+pub mod rewrite_rules;
+pub mod secrets;
+pub mod stack_name;
+pub mod stack_order;
 pub mod terminal;
+pub mod text_diff;
 pub mod types;
+pub mod ui_hints;
+pub mod update_window;
 pub mod yaml_utils;
 
 // Re-export commonly used items
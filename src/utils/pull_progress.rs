@@ -0,0 +1,284 @@
+// Structured progress of `docker compose pull`
+//
+// compose draws its pull progress for a terminal, redrawing it in place:
+//
+//    ⠿ web Pulling                                          3.1s
+//      ⠿ 2d429b9e73a6 Downloading [==>      ]  6.29MB/29.13MB   1.2s
+//      ✔ 8a1e25ce7c4f Download complete                     0.4s
+//    ✔ db Pulled                                            1.1s
+//
+// PullProgressParser reads that output as it passes through the compose
+// terminal and keeps the latest state of each service and its layers, which
+// is sent to the sockets watching the terminal as pullProgress events.
+//
+// A layer counts as half done once it is downloaded; extracting it is the
+// other half. A service's percentage is the mean of its layers'.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// Partial lines longer than this are dropped
+const MAX_PENDING_BYTES: usize = 16 * 1024;
+
+/// Statuses compose shows for a service
+const SERVICE_STATUSES: [&str; 6] = [
+    "Pulling",
+    "Pulled",
+    "Skipped",
+    "Waiting",
+    "Error",
+    "Interrupted",
+];
+
+/// Service statuses after which nothing changes anymore
+const DONE_STATUSES: [&str; 4] = ["Pulled", "Skipped", "Error", "Interrupted"];
+
+static ANSI_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\x1b(\[[0-9;?]*[ -/]*[@-~]|\][^\x07]*\x07|[@-Z\\-_])").unwrap());
+
+static LAYER_ID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[0-9a-f]{12}$").unwrap());
+
+/// `6.29MB/29.13MB`
+static SIZES_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"([\d.]+)\s?([kMGT]?B)\s*/\s*([\d.]+)\s?([kMGT]?B)").unwrap());
+
+/// Trailing elapsed time, e.g. `3.1s`
+static ELAPSED_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+[\d.]+m?s$").unwrap());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerProgress {
+    pub id: String,
+    pub status: String,
+    /// Bytes downloaded or extracted so far
+    pub current: Option<u64>,
+    pub total: Option<u64>,
+    pub percent: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceProgress {
+    pub service: String,
+    pub status: String,
+    pub layers: Vec<LayerProgress>,
+    pub percent: u8,
+}
+
+impl ServiceProgress {
+    fn is_done(&self) -> bool {
+        DONE_STATUSES.iter().any(|s| self.status.starts_with(s))
+    }
+}
+
+/// The pull progress of a stack, services in the order compose lists them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PullProgress {
+    pub services: Vec<ServiceProgress>,
+}
+
+#[derive(Debug, Default)]
+pub struct PullProgressParser {
+    pending: String,
+    progress: PullProgress,
+    /// Index of the service whose layers are being listed
+    current: Option<usize>,
+}
+
+impl PullProgressParser {
+    /// Read a chunk of compose output, returning whether the progress changed
+    pub fn process(&mut self, data: &str) -> bool {
+        self.pending.push_str(data);
+        let Some(end) = self.pending.rfind('\n') else {
+            if self.pending.len() > MAX_PENDING_BYTES {
+                self.pending.clear();
+            }
+            return false;
+        };
+        let complete: String = self.pending.drain(..=end).collect();
+
+        let mut changed = false;
+        for line in complete.split(['\n', '\r']) {
+            changed |= self.process_line(&ANSI_RE.replace_all(line, ""));
+        }
+        changed
+    }
+
+    pub fn progress(&self) -> &PullProgress {
+        &self.progress
+    }
+
+    /// Whether every service compose listed is done pulling
+    pub fn is_done(&self) -> bool {
+        !self.progress.services.is_empty() && self.progress.services.iter().all(|s| s.is_done())
+    }
+
+    fn process_line(&mut self, line: &str) -> bool {
+        let line = ELAPSED_RE.replace(line.trim(), "");
+        // Skip the spinner or check mark
+        let line = line.trim_start_matches(|c: char| !c.is_ascii_alphanumeric());
+        let Some((name, status)) = line.split_once(' ') else {
+            return false;
+        };
+        let status = status.trim();
+
+        if LAYER_ID_RE.is_match(name) {
+            let Some(service) = self.current.and_then(|i| self.progress.services.get_mut(i)) else {
+                return false;
+            };
+            let layer = parse_layer(name, status);
+            let changed = match service.layers.iter_mut().find(|l| l.id == name) {
+                Some(existing) if *existing == layer => false,
+                Some(existing) => {
+                    *existing = layer;
+                    true
+                }
+                None => {
+                    service.layers.push(layer);
+                    true
+                }
+            };
+            if changed {
+                service.percent = service_percent(service);
+            }
+            return changed;
+        }
+
+        if !SERVICE_STATUSES.iter().any(|s| status.starts_with(s)) {
+            return false;
+        }
+        let index = match self
+            .progress
+            .services
+            .iter()
+            .position(|s| s.service == name)
+        {
+            Some(index) => index,
+            None => {
+                self.progress.services.push(ServiceProgress {
+                    service: name.to_string(),
+                    status: String::new(),
+                    layers: Vec::new(),
+                    percent: 0,
+                });
+                self.progress.services.len() - 1
+            }
+        };
+        self.current = Some(index);
+        let service = &mut self.progress.services[index];
+        if service.status == status {
+            return false;
+        }
+        service.status = status.to_string();
+        service.percent = service_percent(service);
+        true
+    }
+}
+
+fn parse_layer(id: &str, status: &str) -> LayerProgress {
+    let sizes = SIZES_RE
+        .captures(status)
+        .and_then(|c| Some((parse_size(&c[1], &c[2])?, parse_size(&c[3], &c[4])?)));
+    let status = status.split(" [").next().unwrap_or(status).trim();
+    let fraction = match sizes {
+        Some((current, total)) if total > 0 => current.min(total) as f64 / total as f64,
+        _ => 0.0,
+    };
+    let percent = match status {
+        "Downloading" => fraction * 50.0,
+        "Verifying Checksum" | "Download complete" => 50.0,
+        "Extracting" => 50.0 + fraction * 50.0,
+        "Pull complete" | "Already exists" => 100.0,
+        _ => 0.0,
+    };
+    LayerProgress {
+        id: id.to_string(),
+        status: status.to_string(),
+        current: sizes.map(|(current, _)| current),
+        total: sizes.map(|(_, total)| total),
+        percent: percent as u8,
+    }
+}
+
+fn service_percent(service: &ServiceProgress) -> u8 {
+    if service.is_done() {
+        return 100;
+    }
+    if service.layers.is_empty() {
+        return 0;
+    }
+    let sum: u32 = service.layers.iter().map(|l| u32::from(l.percent)).sum();
+    (sum / service.layers.len() as u32) as u8
+}
+
+/// Bytes of a size as docker prints it, e.g. `6.29` `MB`
+fn parse_size(number: &str, unit: &str) -> Option<u64> {
+    let number: f64 = number.parse().ok()?;
+    let factor = match unit {
+        "B" => 1.0,
+        "kB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+    Some((number * factor) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pull_output() {
+        let mut parser = PullProgressParser::default();
+        assert!(!parser.process("[+] Pulling 0/2\r\n ⠿ web Pulling   "));
+        assert!(parser.process("  0.1s\r\n"));
+        assert!(parser.process(
+            "   \x1b[33m⠿\x1b[0m 2d429b9e73a6 Downloading [==>      ]  6.291MB/29.13MB   1.2s\r\n\
+             \x20  ✔ 8a1e25ce7c4f Download complete   0.4s\r\n\
+             \x20⠿ db Waiting   0.1s\r\n"
+        ));
+
+        let progress = parser.progress();
+        assert_eq!(progress.services.len(), 2);
+        let web = &progress.services[0];
+        assert_eq!(web.service, "web");
+        assert_eq!(web.status, "Pulling");
+        assert_eq!(web.layers[0].status, "Downloading");
+        assert_eq!(web.layers[0].current, Some(6_291_000));
+        assert_eq!(web.layers[0].total, Some(29_130_000));
+        assert_eq!(web.layers[0].percent, 10);
+        assert_eq!(web.layers[1].percent, 50);
+        assert_eq!(web.percent, 30);
+        assert!(!parser.is_done());
+
+        // A redraw without changes
+        assert!(
+            !parser.process(" ⠿ web Pulling   0.2s\n   ✔ 8a1e25ce7c4f Download complete   0.4s\n")
+        );
+
+        assert!(
+            parser.process(" ✔ web Pulled   3.1s\n ✔ db Skipped - No image to be pulled   0.0s\n")
+        );
+        assert_eq!(parser.progress().services[0].percent, 100);
+        assert!(parser.is_done());
+    }
+
+    #[test]
+    fn test_ignores_other_output() {
+        let mut parser = PullProgressParser::default();
+        assert!(!parser.process("Error response from daemon: pull access denied\n"));
+        // Layers without a service before them
+        assert!(!parser.process("2d429b9e73a6 Pull complete\n"));
+        assert!(parser.progress().services.is_empty());
+        assert!(!parser.is_done());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("6.291", "MB"), Some(6_291_000));
+        assert_eq!(parse_size("512", "B"), Some(512));
+        assert_eq!(parse_size("1.5", "kB"), Some(1500));
+        assert_eq!(parse_size("1", "KiB"), None);
+    }
+}
@@ -0,0 +1,296 @@
+// Stack-wide restart policies
+//
+// setStackRestartPolicy sets `restart:` on every service of a stack's compose
+// file, e.g. `unless-stopped` when a stack is promoted to production. The file
+// is edited line by line, so its comments and formatting are kept: an
+// existing `restart:` line gets the new value, other services get one right
+// below their name. Only block-style services can be edited; the result is
+// parsed again to make sure every service ended up with the policy.
+//
+// Services of included files aren't touched.
+
+use anyhow::{anyhow, bail, Result};
+use bollard::models::RestartPolicyNameEnum;
+use std::fmt;
+use std::str::FromStr;
+use yaml_rust2::YamlLoader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    No,
+    Always,
+    UnlessStopped,
+}
+
+impl RestartPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RestartPolicy::No => "no",
+            RestartPolicy::Always => "always",
+            RestartPolicy::UnlessStopped => "unless-stopped",
+        }
+    }
+
+    /// The policy as the Docker API names it, for `docker update`
+    pub fn docker_name(self) -> RestartPolicyNameEnum {
+        match self {
+            RestartPolicy::No => RestartPolicyNameEnum::NO,
+            RestartPolicy::Always => RestartPolicyNameEnum::ALWAYS,
+            RestartPolicy::UnlessStopped => RestartPolicyNameEnum::UNLESS_STOPPED,
+        }
+    }
+
+    /// The value written to the compose file; `no` is quoted so YAML 1.1
+    /// parsers don't read it as false
+    fn yaml_value(self) -> &'static str {
+        match self {
+            RestartPolicy::No => "\"no\"",
+            other => other.as_str(),
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "no" => Ok(RestartPolicy::No),
+            "always" => Ok(RestartPolicy::Always),
+            "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+            _ => Err(anyhow!(
+                "Invalid restart policy \"{}\", expected one of no, always, unless-stopped",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Set the restart policy of every service of a compose file
+pub fn set_restart_policy(compose_yaml: &str, policy: RestartPolicy) -> Result<String> {
+    YamlLoader::load_from_str(compose_yaml)
+        .map_err(|e| anyhow!("The compose file isn't valid YAML: {}", e))?;
+
+    let lines: Vec<&str> = compose_yaml.lines().collect();
+    let services_line = lines
+        .iter()
+        .position(|line| indent(line) == 0 && key_of(line) == Some("services"))
+        .ok_or_else(|| anyhow!("The compose file has no services"))?;
+    if !value_of(lines[services_line]).is_empty() {
+        bail!("services must be a block mapping to set the restart policy");
+    }
+    let services_end = block_end(&lines, services_line + 1, 0);
+    let service_indent = (services_line + 1..services_end)
+        .find(|&i| is_content(lines[i]))
+        .map(|i| indent(lines[i]))
+        .ok_or_else(|| anyhow!("The compose file has no services"))?;
+
+    // Line to replace, or line to insert after, with the new line
+    let mut replaced: Vec<(usize, String)> = Vec::new();
+    let mut inserted: Vec<(usize, String)> = Vec::new();
+    let mut i = services_line + 1;
+    while i < services_end {
+        let line = lines[i];
+        if !is_content(line) || indent(line) != service_indent {
+            i += 1;
+            continue;
+        }
+        let name = key_of(line).ok_or_else(|| anyhow!("Unexpected line {}: {}", i + 1, line))?;
+        let value = value_of(line);
+        if !value.is_empty() && !value.starts_with('&') {
+            bail!(
+                "Service {} must be a block mapping to set the restart policy",
+                name
+            );
+        }
+
+        let end = block_end(&lines, i + 1, service_indent);
+        let property_indent = (i + 1..end)
+            .find(|&j| is_content(lines[j]))
+            .map(|j| indent(lines[j]))
+            .unwrap_or(service_indent + service_indent.max(2));
+        let restart = (i + 1..end).find(|&j| {
+            is_content(lines[j])
+                && indent(lines[j]) == property_indent
+                && key_of(lines[j]) == Some("restart")
+        });
+        let new_line = format!(
+            "{}restart: {}",
+            " ".repeat(property_indent),
+            policy.yaml_value()
+        );
+        match restart {
+            Some(j) => {
+                let comment = lines[j].find(" #").map_or("", |k| &lines[j][k..]);
+                replaced.push((j, format!("{}{}", new_line, comment)));
+            }
+            None => inserted.push((i, new_line)),
+        }
+        i = end;
+    }
+
+    let mut result = String::with_capacity(compose_yaml.len() + inserted.len() * 24);
+    for (i, line) in lines.iter().enumerate() {
+        match replaced.iter().find(|(j, _)| *j == i) {
+            Some((_, new_line)) => result.push_str(new_line),
+            None => result.push_str(line),
+        }
+        result.push('\n');
+        if let Some((_, new_line)) = inserted.iter().find(|(j, _)| *j == i) {
+            result.push_str(new_line);
+            result.push('\n');
+        }
+    }
+    if !compose_yaml.ends_with('\n') {
+        result.pop();
+    }
+
+    check_restart_policy(&result, policy)?;
+    Ok(result)
+}
+
+/// Make sure every service of the edited file has the policy
+fn check_restart_policy(compose_yaml: &str, policy: RestartPolicy) -> Result<()> {
+    let docs = YamlLoader::load_from_str(compose_yaml)
+        .map_err(|e| anyhow!("Failed to set the restart policy: {}", e))?;
+    let services = docs
+        .first()
+        .and_then(|doc| doc["services"].as_hash())
+        .ok_or_else(|| anyhow!("Failed to set the restart policy"))?;
+    for (name, service) in services {
+        if service["restart"].as_str() != Some(policy.as_str()) {
+            bail!(
+                "Failed to set the restart policy of service {}",
+                name.as_str().unwrap_or("?")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Whether a line holds YAML rather than whitespace or a comment
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// The key of a `key: value` line, without quotes
+fn key_of(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let (key, _) = trimmed.split_once(':')?;
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// The value of a `key: value` line, without a trailing comment
+fn value_of(line: &str) -> &str {
+    let Some((_, value)) = line.split_once(':') else {
+        return "";
+    };
+    let value = value.trim();
+    if value.starts_with('#') {
+        return "";
+    }
+    match value.find(" #") {
+        Some(i) => value[..i].trim_end(),
+        None => value,
+    }
+}
+
+/// The first line from `start` that ends a block indented deeper than
+/// `parent_indent`
+fn block_end(lines: &[&str], start: usize, parent_indent: usize) -> usize {
+    (start..lines.len())
+        .find(|&i| is_content(lines[i]) && indent(lines[i]) <= parent_indent)
+        .unwrap_or(lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_restart_policy() {
+        let yaml = r#"# Media stack
+services:
+  web:
+    image: nginx # pinned below
+    restart: always # for now
+    ports:
+      - "80:80"
+
+  db: &db
+    image: postgres:16
+    environment:
+      restart: not-this-one
+
+  worker:
+volumes:
+  data:
+"#;
+        let expected = r#"# Media stack
+services:
+  web:
+    image: nginx # pinned below
+    restart: unless-stopped # for now
+    ports:
+      - "80:80"
+
+  db: &db
+    restart: unless-stopped
+    image: postgres:16
+    environment:
+      restart: not-this-one
+
+  worker:
+    restart: unless-stopped
+volumes:
+  data:
+"#;
+        assert_eq!(
+            set_restart_policy(yaml, RestartPolicy::UnlessStopped).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_set_restart_policy_no() {
+        let yaml = "services:\n    web:\n        image: nginx";
+        assert_eq!(
+            set_restart_policy(yaml, RestartPolicy::No).unwrap(),
+            "services:\n    web:\n        restart: \"no\"\n        image: nginx"
+        );
+    }
+
+    #[test]
+    fn test_set_restart_policy_unsupported() {
+        assert!(
+            set_restart_policy("services: {web: {image: nginx}}", RestartPolicy::Always).is_err()
+        );
+        assert!(
+            set_restart_policy("services:\n  web: {image: nginx}\n", RestartPolicy::Always)
+                .is_err()
+        );
+        assert!(set_restart_policy("volumes:\n  data:\n", RestartPolicy::Always).is_err());
+        assert!(set_restart_policy("services: [", RestartPolicy::Always).is_err());
+    }
+
+    #[test]
+    fn test_parse_restart_policy() {
+        assert_eq!("no".parse::<RestartPolicy>().unwrap(), RestartPolicy::No);
+        assert_eq!(
+            "unless-stopped".parse::<RestartPolicy>().unwrap(),
+            RestartPolicy::UnlessStopped
+        );
+        assert!("on-failure:3".parse::<RestartPolicy>().is_err());
+    }
+}
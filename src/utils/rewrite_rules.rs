@@ -0,0 +1,476 @@
+// Rewrite rules for cloning stacks to other endpoints
+//
+// Each endpoint can have rules that adapt a stack to its host when the stack is
+// cloned there, so one stack can be rolled out to several machines:
+//
+//   hostnames       substitutions such as nas1.lan -> nas2.lan in the compose
+//                   file and .env, matched as whole host names
+//   portOffset      added to every published host port
+//   volumePrefixes  bind mount sources under `from` are moved under `to`
+//
+// Port and volume rules edit the parsed compose file, so a file they change is
+// written back without its comments. Host ports given as a variable of the
+// .env (${HTTP_PORT}) are offset in the .env instead.
+
+use crate::utils::yaml_utils::{parse_yaml, yaml_to_string};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use yaml_rust2::Yaml;
+
+/// Setting holding the rules of every endpoint, by endpoint
+pub const REWRITE_RULES_SETTING: &str = "endpointRewriteRules";
+
+/// Type of the rewrite rules setting
+pub const REWRITE_RULES_SETTING_TYPE: &str = "rewriteRules";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Substitution {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteRules {
+    #[serde(default)]
+    pub hostnames: Vec<Substitution>,
+    #[serde(default, rename = "portOffset")]
+    pub port_offset: i32,
+    #[serde(default, rename = "volumePrefixes")]
+    pub volume_prefixes: Vec<Substitution>,
+}
+
+/// A stack's files after the rules were applied
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rewritten {
+    #[serde(rename = "composeYAML")]
+    pub compose_yaml: String,
+    #[serde(rename = "composeENV")]
+    pub compose_env: String,
+    /// What the rules changed, for showing before the clone is made
+    pub changes: Vec<String>,
+}
+
+impl RewriteRules {
+    pub fn validate(&self) -> Result<()> {
+        for rule in &self.hostnames {
+            if rule.from.trim().is_empty() || rule.to.trim().is_empty() {
+                bail!("Host name substitutions need a name to replace and a replacement");
+            }
+        }
+        if self.port_offset.unsigned_abs() >= 65535 {
+            bail!("Port offset must be between -65534 and 65534");
+        }
+        for rule in &self.volume_prefixes {
+            for prefix in [&rule.from, &rule.to] {
+                if !(prefix.starts_with('/') || prefix.starts_with('.') || prefix.starts_with('~'))
+                {
+                    bail!("Volume prefix {} must be a host path", prefix);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hostnames.is_empty() && self.port_offset == 0 && self.volume_prefixes.is_empty()
+    }
+
+    /// Apply the rules to a stack's compose file and .env
+    pub fn apply(&self, compose_yaml: &str, compose_env: &str) -> Result<Rewritten> {
+        let mut compose_yaml = compose_yaml.to_string();
+        let mut compose_env = compose_env.to_string();
+        let mut changes = Vec::new();
+
+        for rule in &self.hostnames {
+            let (yaml, in_yaml) = replace_host(&compose_yaml, &rule.from, &rule.to);
+            let (env, in_env) = replace_host(&compose_env, &rule.from, &rule.to);
+            if in_yaml + in_env > 0 {
+                changes.push(format!(
+                    "{} -> {} ({} in the compose file, {} in .env)",
+                    rule.from, rule.to, in_yaml, in_env
+                ));
+            }
+            compose_yaml = yaml;
+            compose_env = env;
+        }
+
+        if self.port_offset != 0 || !self.volume_prefixes.is_empty() {
+            let mut docs = parse_yaml(&compose_yaml)?;
+            let mut edited = false;
+            if let Some(Yaml::Hash(root)) = docs.first_mut() {
+                if let Some(Yaml::Hash(services)) =
+                    root.get_mut(&Yaml::String("services".to_string()))
+                {
+                    let mut env_ports = HashSet::new();
+                    for (name, service) in services.iter_mut() {
+                        let name = name.as_str().unwrap_or_default().to_string();
+                        let Yaml::Hash(service) = service else {
+                            continue;
+                        };
+                        if self.port_offset != 0 {
+                            if let Some(Yaml::Array(ports)) =
+                                service.get_mut(&Yaml::String("ports".to_string()))
+                            {
+                                for port in ports.iter_mut() {
+                                    edited |= self.offset_port(
+                                        &name,
+                                        port,
+                                        &mut compose_env,
+                                        &mut env_ports,
+                                        &mut changes,
+                                    )?;
+                                }
+                            }
+                        }
+                        if let Some(Yaml::Array(volumes)) =
+                            service.get_mut(&Yaml::String("volumes".to_string()))
+                        {
+                            for volume in volumes.iter_mut() {
+                                edited |= self.move_volume(&name, volume, &mut changes);
+                            }
+                        }
+                    }
+                }
+            }
+            if edited {
+                compose_yaml = yaml_to_string(&docs[0])?;
+            }
+        }
+
+        Ok(Rewritten {
+            compose_yaml,
+            compose_env,
+            changes,
+        })
+    }
+
+    /// Offset the host port of a `ports` entry, returns whether the entry changed
+    fn offset_port(
+        &self,
+        service: &str,
+        port: &mut Yaml,
+        env: &mut String,
+        env_ports: &mut HashSet<String>,
+        changes: &mut Vec<String>,
+    ) -> Result<bool> {
+        let published = match port {
+            Yaml::String(short) => {
+                let Some((prefix, host, rest)) = split_short_port(short) else {
+                    return Ok(false);
+                };
+                if let Some(var) = variable_name(host) {
+                    self.offset_env_port(service, var, env, env_ports, changes)?;
+                    return Ok(false);
+                }
+                let offset = offset_ports(host, self.port_offset)?;
+                let new = format!("{}{}{}", prefix, offset, rest);
+                changes.push(format!("{}: port {} -> {}", service, short, new));
+                *short = new;
+                return Ok(true);
+            }
+            Yaml::Hash(long) => long.get_mut(&Yaml::String("published".to_string())),
+            _ => None,
+        };
+
+        match published {
+            Some(Yaml::Integer(published)) => {
+                let new = offset_port_number(*published, self.port_offset)?;
+                changes.push(format!("{}: port {} -> {}", service, published, new));
+                *published = new;
+                Ok(true)
+            }
+            Some(Yaml::String(published)) => {
+                if let Some(var) = variable_name(published) {
+                    self.offset_env_port(service, var, env, env_ports, changes)?;
+                    return Ok(false);
+                }
+                let new = offset_ports(published, self.port_offset)?;
+                changes.push(format!("{}: port {} -> {}", service, published, new));
+                *published = new;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Offset a port set through a variable of the .env, once per variable
+    fn offset_env_port(
+        &self,
+        service: &str,
+        var: &str,
+        env: &mut String,
+        env_ports: &mut HashSet<String>,
+        changes: &mut Vec<String>,
+    ) -> Result<()> {
+        if !env_ports.insert(var.to_string()) {
+            return Ok(());
+        }
+        let mut found = false;
+        let mut lines: Vec<String> = Vec::new();
+        for line in env.lines() {
+            match line.split_once('=') {
+                Some((key, value)) if key.trim().trim_start_matches("export ").trim() == var => {
+                    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                    let new = offset_ports(value, self.port_offset)?;
+                    changes.push(format!("{}: {}={} -> {} (.env)", service, var, value, new));
+                    lines.push(format!("{}={}", key, new));
+                    found = true;
+                }
+                _ => lines.push(line.to_string()),
+            }
+        }
+        if !found {
+            changes.push(format!(
+                "{}: port {} is not set in .env, not offset",
+                service, var
+            ));
+            return Ok(());
+        }
+        let trailing_newline = env.ends_with('\n');
+        *env = lines.join("\n");
+        if trailing_newline {
+            env.push('\n');
+        }
+        Ok(())
+    }
+
+    /// Move the source of a bind mount, returns whether the entry changed
+    fn move_volume(&self, service: &str, volume: &mut Yaml, changes: &mut Vec<String>) -> bool {
+        let source = match volume {
+            Yaml::String(short) => {
+                let (source, rest) = match short.split_once(':') {
+                    Some((source, rest)) => (source, format!(":{}", rest)),
+                    None => return false,
+                };
+                let Some(new) = self.moved_source(source) else {
+                    return false;
+                };
+                changes.push(format!("{}: volume {} -> {}", service, source, new));
+                *short = format!("{}{}", new, rest);
+                return true;
+            }
+            Yaml::Hash(long) => long.get_mut(&Yaml::String("source".to_string())),
+            _ => None,
+        };
+        match source {
+            Some(Yaml::String(source)) => {
+                let Some(new) = self.moved_source(source) else {
+                    return false;
+                };
+                changes.push(format!("{}: volume {} -> {}", service, source, new));
+                *source = new;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The source path under the first matching volume prefix
+    fn moved_source(&self, source: &str) -> Option<String> {
+        self.volume_prefixes.iter().find_map(|rule| {
+            let from = rule.from.trim_end_matches('/');
+            let to = rule.to.trim_end_matches('/');
+            match source.strip_prefix(from) {
+                Some("") => Some(to.to_string()),
+                Some(rest) if rest.starts_with('/') => Some(format!("{}{}", to, rest)),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Whether a character can be part of a host name label
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Replace whole occurrences of a host name, returns the text and the count
+fn replace_host(text: &str, from: &str, to: &str) -> (String, usize) {
+    let mut output = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut rest = text;
+    while let Some(index) = rest.find(from) {
+        let before = rest[..index]
+            .chars()
+            .last()
+            .or_else(|| output.chars().last());
+        let after = rest[index + from.len()..].chars().next();
+        output.push_str(&rest[..index]);
+        if before.is_some_and(is_host_char) || after.is_some_and(is_host_char) {
+            output.push_str(from);
+        } else {
+            output.push_str(to);
+            count += 1;
+        }
+        rest = &rest[index + from.len()..];
+    }
+    output.push_str(rest);
+    (output, count)
+}
+
+/// Split a short `ports` entry into what precedes the host port, the host port
+/// and what follows it, None when it publishes no host port
+fn split_short_port(short: &str) -> Option<(&str, &str, &str)> {
+    // Skip an IPv6 address in brackets
+    let start = if short.starts_with('[') {
+        short.find(']')? + 1
+    } else {
+        0
+    };
+    let parts: Vec<usize> = short[start..]
+        .match_indices(':')
+        .map(|(i, _)| start + i)
+        .collect();
+    let (host_start, host_end) = match parts.as_slice() {
+        [] => return None,
+        [container] => (0, *container),
+        [ip, container] => (ip + 1, *container),
+        _ => return None,
+    };
+    let host = &short[host_start..host_end];
+    if host.is_empty() {
+        return None;
+    }
+    Some((&short[..host_start], host, &short[host_end..]))
+}
+
+/// The variable of a `${NAME}` or `$NAME` reference making up the whole value
+fn variable_name(value: &str) -> Option<&str> {
+    let name = value
+        .strip_prefix("${")
+        .and_then(|v| v.strip_suffix('}'))
+        .or_else(|| value.strip_prefix('$'))?;
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .then_some(name)
+}
+
+fn offset_port_number(port: i64, offset: i32) -> Result<i64> {
+    let new = port + i64::from(offset);
+    if !(1..=65535).contains(&new) {
+        bail!("Port {} offset by {} is out of range", port, offset);
+    }
+    Ok(new)
+}
+
+/// Offset a port or port range such as 8080-8090
+fn offset_ports(ports: &str, offset: i32) -> Result<String> {
+    let offset_one = |port: &str| -> Result<String> {
+        let number: i64 = port
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Cannot offset port {}", ports))?;
+        Ok(offset_port_number(number, offset)?.to_string())
+    };
+    match ports.split_once('-') {
+        Some((first, last)) => Ok(format!("{}-{}", offset_one(first)?, offset_one(last)?)),
+        None => offset_one(ports),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: serde_json::Value) -> RewriteRules {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_replace_host() {
+        assert_eq!(
+            replace_host(
+                "DB=nas1.lan\nURL=http://api.nas1.lan:80",
+                "nas1.lan",
+                "nas2.lan"
+            ),
+            ("DB=nas2.lan\nURL=http://api.nas2.lan:80".to_string(), 2)
+        );
+        assert_eq!(
+            replace_host("nas10 mynas1 nas1-b nas1", "nas1", "nas2"),
+            ("nas10 mynas1 nas1-b nas2".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_split_short_port() {
+        assert_eq!(split_short_port("8080:80"), Some(("", "8080", ":80")));
+        assert_eq!(
+            split_short_port("127.0.0.1:8080-8081:80-81/udp"),
+            Some(("127.0.0.1:", "8080-8081", ":80-81/udp"))
+        );
+        assert_eq!(
+            split_short_port("[::1]:8080:80"),
+            Some(("[::1]:", "8080", ":80"))
+        );
+        assert_eq!(split_short_port("80"), None);
+        assert_eq!(split_short_port("127.0.0.1::80"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let rules = rules(serde_json::json!({
+            "hostnames": [{ "from": "nas1.lan", "to": "nas2.lan" }],
+            "portOffset": 100,
+            "volumePrefixes": [{ "from": "/mnt/nas1", "to": "/mnt/nas2/" }]
+        }));
+        rules.validate().unwrap();
+
+        let yaml = "services:\n  web:\n    image: nginx\n    environment:\n      UPSTREAM: nas1.lan\n    ports:\n      - \"8080:80\"\n      - \"${HTTPS_PORT}:443\"\n      - 9000\n      - target: 53\n        published: 53\n    volumes:\n      - /mnt/nas1/www:/usr/share/nginx/html:ro\n      - /mnt/nas10:/data\n      - type: bind\n        source: /mnt/nas1\n        target: /backup\n      - cache:/cache\n";
+        let env = "# ports\nexport HTTPS_PORT=\"8443\"\nOTHER=1\n";
+        let rewritten = rules.apply(yaml, env).unwrap();
+
+        let doc = &parse_yaml(&rewritten.compose_yaml).unwrap()[0];
+        let web = &doc["services"]["web"];
+        assert_eq!(web["environment"]["UPSTREAM"].as_str(), Some("nas2.lan"));
+        assert_eq!(web["ports"][0].as_str(), Some("8180:80"));
+        assert_eq!(web["ports"][1].as_str(), Some("${HTTPS_PORT}:443"));
+        assert_eq!(web["ports"][2].as_i64(), Some(9000));
+        assert_eq!(web["ports"][3]["published"].as_i64(), Some(153));
+        assert_eq!(
+            web["volumes"][0].as_str(),
+            Some("/mnt/nas2/www:/usr/share/nginx/html:ro")
+        );
+        assert_eq!(web["volumes"][1].as_str(), Some("/mnt/nas10:/data"));
+        assert_eq!(web["volumes"][2]["source"].as_str(), Some("/mnt/nas2"));
+        assert_eq!(web["volumes"][3].as_str(), Some("cache:/cache"));
+        assert_eq!(
+            rewritten.compose_env,
+            "# ports\nexport HTTPS_PORT=8543\nOTHER=1\n"
+        );
+        assert_eq!(rewritten.changes.len(), 6);
+    }
+
+    #[test]
+    fn test_apply_keeps_untouched_files() {
+        let yaml = "# comment\nservices:\n  web:\n    image: nginx\n";
+        let rewritten = rules(serde_json::json!({ "portOffset": 1 }))
+            .apply(yaml, "")
+            .unwrap();
+        assert_eq!(rewritten.compose_yaml, yaml);
+        assert!(rewritten.changes.is_empty());
+
+        let out_of_range = "services:\n  web:\n    ports:\n      - \"65535:80\"\n";
+        assert!(rules(serde_json::json!({ "portOffset": 1 }))
+            .apply(out_of_range, "")
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(RewriteRules::default().validate().is_ok());
+        assert!(
+            rules(serde_json::json!({ "hostnames": [{ "from": "", "to": "b" }] }))
+                .validate()
+                .is_err()
+        );
+        assert!(rules(serde_json::json!({ "portOffset": 70000 }))
+            .validate()
+            .is_err());
+        assert!(
+            rules(serde_json::json!({ "volumePrefixes": [{ "from": "data", "to": "/x" }] }))
+                .validate()
+                .is_err()
+        );
+    }
+}
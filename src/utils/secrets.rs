@@ -0,0 +1,176 @@
+// Secret references in .env files
+//
+// A stack's `.env` can refer to a stored secret with `${secret:NAME}` instead
+// of holding the value:
+//
+//   DB_PASSWORD=${secret:DB_PASSWORD}
+//
+// Before containers are created, the references are replaced with the
+// decrypted values in a generated env file outside the stacks directory, which
+// compose reads instead of `.env` and which is removed when the command ends.
+// Other compose commands don't need the values and get an empty env file, so
+// compose never sees the references.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const REFERENCE_START: &str = "${secret:";
+
+/// Env file compose reads instead of a `.env` that has secret references
+pub const EMPTY_ENV_FILE: &str = "/dev/null";
+
+/// Check a secret name: a letter or `_`, then letters, digits and `_`
+pub fn validate_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!("Invalid secret name: {:?}", name));
+    }
+    Ok(())
+}
+
+/// Whether the text contains a secret reference
+pub fn has_references(text: &str) -> bool {
+    text.contains(REFERENCE_START)
+}
+
+/// Whether the env file at this path contains a secret reference
+pub fn env_file_has_references(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .map(|env| has_references(&env))
+        .unwrap_or(false)
+}
+
+/// Replace each reference using `resolve`, which gets the secret's name
+fn replace_references(
+    text: &str,
+    mut resolve: impl FnMut(&str) -> Result<String>,
+) -> Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(REFERENCE_START) {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + REFERENCE_START.len()..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated secret reference"))?;
+        let name = &after[..end];
+        validate_name(name)?;
+        output.push_str(&resolve(name)?);
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Names of the secrets referenced in the text, each once, in order
+pub fn references(text: &str) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    replace_references(text, |name| {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+        Ok(String::new())
+    })?;
+    Ok(names)
+}
+
+/// Replace every reference with its value; referring to a missing secret is an error
+pub fn interpolate(text: &str, values: &HashMap<String, String>) -> Result<String> {
+    replace_references(text, |name| {
+        values
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Secret {} is not set", name))
+    })
+}
+
+/// An env file with the secrets filled in, deleted when dropped
+pub struct GeneratedEnvFile {
+    path: PathBuf,
+}
+
+impl GeneratedEnvFile {
+    /// Write the content to a new file in `dir`, readable only by this user
+    pub fn write(dir: &Path, stack_name: &str, content: &str) -> Result<Self> {
+        use std::io::Write;
+
+        std::fs::create_dir_all(dir).context("Failed to create the generated env directory")?;
+        let path = dir.join(format!(
+            "{}-{}.env",
+            stack_name,
+            hex::encode(rand::random::<[u8; 8]>())
+        ));
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&path)
+            .context("Failed to create the generated env file")?;
+        // From here on the file is removed again if anything fails
+        let generated = Self { path };
+        file.write_all(content.as_bytes())
+            .context("Failed to write the generated env file")?;
+        Ok(generated)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for GeneratedEnvFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!(
+                "Failed to remove generated env file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_interpolate_references() {
+        let env = "DB_USER=app\nDB_PASSWORD=${secret:DB_PASSWORD}\nURL=pg://app:${secret:DB_PASSWORD}@db\nHOME=${HOME}\n";
+        assert!(has_references(env));
+        assert_eq!(references(env).unwrap(), ["DB_PASSWORD"]);
+
+        let values = HashMap::from([("DB_PASSWORD".to_string(), "hunter2".to_string())]);
+        assert_eq!(
+            interpolate(env, &values).unwrap(),
+            "DB_USER=app\nDB_PASSWORD=hunter2\nURL=pg://app:hunter2@db\nHOME=${HOME}\n"
+        );
+
+        assert!(interpolate("A=${secret:OTHER}", &values).is_err());
+        assert!(references("A=${secret:DB_PASSWORD").is_err());
+        assert!(references("A=${secret:bad-name}").is_err());
+        assert!(!has_references("A=${HOME}"));
+    }
+
+    #[test]
+    fn test_generated_env_file_is_removed() {
+        let dir = TempDir::new().unwrap();
+        let generated = GeneratedEnvFile::write(dir.path(), "web", "A=1\n").unwrap();
+        let path = generated.path().to_path_buf();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "A=1\n");
+
+        drop(generated);
+        assert!(!path.exists());
+    }
+}
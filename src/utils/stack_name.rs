@@ -0,0 +1,129 @@
+// Validated stack names
+//
+// Stack names arrive from clients and remote agents and end up joined into
+// paths under the stacks directory. Every name is checked once, when it is
+// parsed into a StackName, so code holding one can use it in a path without
+// checking again.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Names Windows won't create files or directories with
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// A stack (compose project) name: non-empty, [a-z0-9_-] only
+///
+/// This excludes path separators, `.` and `..`, so a StackName is always a
+/// single directory below the stacks directory.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StackName(String);
+
+impl StackName {
+    /// Check a name, with an error message suitable for clients
+    pub fn parse(name: &str) -> Result<Self> {
+        if name.is_empty() {
+            return Err(anyhow!("Stack name must not be empty"));
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+        {
+            return Err(anyhow!("Stack name can only contain [a-z][0-9] _ - only"));
+        }
+        if RESERVED_NAMES.contains(&name) {
+            return Err(anyhow!("\"{}\" is a reserved name", name));
+        }
+        Ok(StackName(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for StackName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for StackName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for StackName {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.0)
+    }
+}
+
+impl fmt::Display for StackName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for StackName {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        StackName::parse(&name)
+    }
+}
+
+impl From<StackName> for String {
+    fn from(name: StackName) -> String {
+        name.0
+    }
+}
+
+impl PartialEq<str> for StackName {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for StackName {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stack_name() {
+        assert_eq!(StackName::parse("web").unwrap(), "web");
+        assert_eq!(StackName::parse("my_app-2").unwrap().as_str(), "my_app-2");
+
+        for name in [
+            "", ".", "..", "../etc", "a/b", "a\\b", "Web", "web.yml", "nul",
+        ] {
+            assert!(
+                StackName::parse(name).is_err(),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_stack_name_serde() {
+        let name: StackName = serde_json::from_str("\"web\"").unwrap();
+        assert_eq!(serde_json::to_string(&name).unwrap(), "\"web\"");
+        assert!(serde_json::from_str::<StackName>("\"../web\"").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+// Ordering stacks by their dependencies
+//
+// A stack can depend on other stacks (e.g. `app` on `proxy`), meaning they
+// have to be up first. deployAll and startAll run stacks one at a time in an
+// order where every stack comes after the stacks it depends on.
+
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeSet, HashMap};
+
+/// Order `stacks` so every stack comes after the stacks it depends on
+///
+/// Dependencies on stacks that aren't in `stacks` are ignored. Stacks that
+/// don't depend on each other keep alphabetical order. A dependency cycle is
+/// an error naming the stacks in it.
+pub fn dependency_order(
+    stacks: &[String],
+    dependencies: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>> {
+    let included: BTreeSet<&str> = stacks.iter().map(String::as_str).collect();
+
+    // Number of unmet dependencies per stack
+    let mut pending: HashMap<&str, usize> = included
+        .iter()
+        .map(|&stack| {
+            let count = dependencies
+                .get(stack)
+                .map(|deps| {
+                    deps.iter()
+                        .filter(|d| d.as_str() != stack && included.contains(d.as_str()))
+                        .collect::<BTreeSet<_>>()
+                        .len()
+                })
+                .unwrap_or(0);
+            (stack, count)
+        })
+        .collect();
+
+    let mut ready: BTreeSet<&str> = pending
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(stack, _)| *stack)
+        .collect();
+    let mut order = Vec::with_capacity(included.len());
+
+    while let Some(stack) = ready.pop_first() {
+        pending.remove(stack);
+        order.push(stack.to_string());
+
+        for (dependent, count) in pending.iter_mut() {
+            let depends_on_stack = dependencies
+                .get(*dependent)
+                .is_some_and(|deps| deps.iter().any(|d| d == stack));
+            if depends_on_stack {
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        let mut cycle: Vec<&str> = pending.into_keys().collect();
+        cycle.sort();
+        return Err(anyhow!(
+            "Stack dependencies form a cycle: {}",
+            cycle.join(", ")
+        ));
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deps(pairs: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        pairs
+            .iter()
+            .map(|(stack, deps)| {
+                (
+                    stack.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn names(stacks: &[&str]) -> Vec<String> {
+        stacks.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let dependencies = deps(&[("app", &["db", "proxy"]), ("db", &["proxy"])]);
+        let order = dependency_order(&names(&["app", "blog", "db", "proxy"]), &dependencies);
+        assert_eq!(order.unwrap(), vec!["blog", "proxy", "db", "app"]);
+
+        // Dependencies outside the list don't hold anything up
+        let order = dependency_order(&names(&["app", "db"]), &dependencies);
+        assert_eq!(order.unwrap(), vec!["db", "app"]);
+    }
+
+    #[test]
+    fn test_dependency_order_cycle() {
+        let dependencies = deps(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        let err = dependency_order(&names(&["a", "b", "c", "d"]), &dependencies).unwrap_err();
+        assert!(err.to_string().contains("a, b, c"));
+    }
+}
@@ -24,6 +24,28 @@ pub fn get_combined_terminal_name(endpoint: &str, stack: &str) -> String {
     format!("combined-{}-{}", endpoint, stack)
 }
 
+/// Get the name for the terminal of a deployAll/startAll run
+///
+/// # Arguments
+/// * `endpoint` - The endpoint identifier
+///
+/// # Returns
+/// Terminal name in format "batch-{endpoint}"
+pub fn get_batch_terminal_name(endpoint: &str) -> String {
+    format!("batch-{}", endpoint)
+}
+
+/// Get the name for the terminal of a pruneDockerDiskUsage run
+///
+/// # Arguments
+/// * `endpoint` - The endpoint identifier
+///
+/// # Returns
+/// Terminal name in format "prune-{endpoint}"
+pub fn get_prune_terminal_name(endpoint: &str) -> String {
+    format!("prune-{}", endpoint)
+}
+
 /// Get the name for a container terminal
 ///
 /// # Arguments
@@ -99,6 +121,11 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_batch_terminal_name() {
+        assert_eq!(get_batch_terminal_name("localhost"), "batch-localhost");
+    }
+
     #[test]
     fn test_get_container_terminal_name() {
         assert_eq!(
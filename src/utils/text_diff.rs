@@ -0,0 +1,35 @@
+// Unified diffs of stack files
+
+use similar::TextDiff;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Unified diff from `old` to `new` under the given file name, empty if equal
+pub fn unified_diff(old: &str, new: &str, file_name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&format!("a/{}", file_name), &format!("b/{}", file_name))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "services:\n  web:\n    image: nginx:1.25\n";
+        let new = "services:\n  web:\n    image: nginx:1.27\n";
+        assert_eq!(
+            unified_diff(old, new, "compose.yaml"),
+            "--- a/compose.yaml\n+++ b/compose.yaml\n@@ -1,3 +1,3 @@\n services:\n   web:\n-    image: nginx:1.25\n+    image: nginx:1.27\n"
+        );
+        assert_eq!(unified_diff(old, old, "compose.yaml"), "");
+        assert!(unified_diff("", "A=1\n", ".env").contains("+A=1"));
+    }
+}
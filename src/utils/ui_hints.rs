@@ -0,0 +1,151 @@
+// UI hints for compose files
+//
+// A compose file can tell the UI how to present its stack with keys in the
+// `x-dockru` extension, which docker compose itself ignores:
+//
+//   x-dockru:
+//     display-name: Home Assistant
+//     accent-color: "#41bdf5"
+//     group: Home automation
+//
+// The display name is shown instead of the directory name, which is useful
+// when stacks are generated with names like `ha-7f3e`. The group files the
+// stack under a folder unless a group was set for it in dockru. Hints are
+// read for the stack list on every refresh, so parsed hints are cached until
+// the compose file changes.
+
+use crate::utils::constants::MAX_GROUP_NAME_LENGTH;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use yaml_rust2::{Yaml, YamlLoader};
+
+pub use dockru_protocol::compose::UiHints;
+
+/// Longest display name accepted
+const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+
+/// Compose file path -> (modified time, hints)
+type HintsCache = HashMap<PathBuf, (Option<i64>, UiHints)>;
+
+static CACHE: Lazy<Mutex<HintsCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Read the UI hints from the `x-dockru` extension of a compose file
+///
+/// Returns no hints when the compose file has none. Hints that are present but
+/// malformed are an error.
+pub fn parse_ui_hints(compose_yaml: &str) -> Result<UiHints> {
+    let Ok(docs) = YamlLoader::load_from_str(compose_yaml) else {
+        return Ok(UiHints::default());
+    };
+    let Some(extension) = docs.first().map(|doc| &doc["x-dockru"]) else {
+        return Ok(UiHints::default());
+    };
+
+    let display_name = string_hint(extension, "display-name")?;
+    if let Some(name) = &display_name {
+        if name.chars().count() > MAX_DISPLAY_NAME_LENGTH || name.chars().any(char::is_control) {
+            return Err(anyhow!(
+                "x-dockru.display-name must be at most {} characters on one line",
+                MAX_DISPLAY_NAME_LENGTH
+            ));
+        }
+    }
+
+    let accent_color = string_hint(extension, "accent-color")?;
+    if let Some(color) = &accent_color {
+        if !is_hex_color(color) {
+            return Err(anyhow!(
+                "x-dockru.accent-color must be a color like \"#41bdf5\", got {:?}",
+                color
+            ));
+        }
+    }
+
+    let group = string_hint(extension, "group")?;
+    if group
+        .as_ref()
+        .is_some_and(|g| g.chars().count() > MAX_GROUP_NAME_LENGTH)
+    {
+        return Err(anyhow!(
+            "x-dockru.group must be at most {} characters",
+            MAX_GROUP_NAME_LENGTH
+        ));
+    }
+
+    Ok(UiHints {
+        display_name,
+        accent_color,
+        group,
+    })
+}
+
+/// A trimmed string hint, None when absent or blank
+fn string_hint(extension: &Yaml, key: &str) -> Result<Option<String>> {
+    match &extension[key] {
+        Yaml::BadValue | Yaml::Null => Ok(None),
+        Yaml::String(value) => Ok(Some(value.trim().to_string()).filter(|v| !v.is_empty())),
+        _ => Err(anyhow!("x-dockru.{} must be a string", key)),
+    }
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The hints of the compose file at `path`, parsed again only when it changed
+///
+/// Broken hints are ignored here; saving the stack reports them.
+pub fn cached_ui_hints(path: &Path, modified: Option<i64>) -> UiHints {
+    if let Some((cached_modified, hints)) = CACHE.lock().unwrap().get(path) {
+        if *cached_modified == modified && modified.is_some() {
+            return hints.clone();
+        }
+    }
+
+    let hints = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|yaml| parse_ui_hints(&yaml).ok())
+        .unwrap_or_default();
+    CACHE
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), (modified, hints.clone()));
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ui_hints() {
+        let yaml = r##"
+services:
+  web:
+    image: nginx
+x-dockru:
+  display-name: "  Home Assistant "
+  accent-color: "#41BDF5"
+  group: Home automation
+"##;
+        let hints = parse_ui_hints(yaml).unwrap();
+        assert_eq!(hints.display_name.as_deref(), Some("Home Assistant"));
+        assert_eq!(hints.accent_color.as_deref(), Some("#41BDF5"));
+        assert_eq!(hints.group.as_deref(), Some("Home automation"));
+
+        assert!(parse_ui_hints("services: {}\n").unwrap().is_empty());
+        assert!(parse_ui_hints("x-dockru:\n  display-name: \"\"\n")
+            .unwrap()
+            .is_empty());
+        assert!(parse_ui_hints("x-dockru:\n  accent-color: \"#fff\"\n").is_ok());
+        assert!(parse_ui_hints("x-dockru:\n  accent-color: red\n").is_err());
+        assert!(parse_ui_hints("x-dockru:\n  accent-color: \"#12345\"\n").is_err());
+        assert!(parse_ui_hints("x-dockru:\n  display-name: [a]\n").is_err());
+        assert!(parse_ui_hints(&format!("x-dockru:\n  group: {}\n", "g".repeat(65))).is_err());
+    }
+}
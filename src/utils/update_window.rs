@@ -0,0 +1,110 @@
+// Update windows
+//
+// A stack with an update window has the image updates found by the update
+// check applied automatically, but only while the local time of day is inside
+// the window. Windows are written "HH:MM-HH:MM" and may wrap past midnight
+// ("23:00-02:00"); the end is exclusive.
+
+use anyhow::{anyhow, Result};
+use chrono::Timelike;
+use std::fmt;
+use std::str::FromStr;
+
+/// Time-of-day range, in minutes since midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateWindow {
+    start: u16,
+    end: u16,
+}
+
+impl UpdateWindow {
+    /// Whether the minute of the day is inside the window
+    pub fn contains_minute(&self, minute: u16) -> bool {
+        if self.start < self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+
+    /// Whether the time of day is inside the window
+    pub fn contains<T: Timelike>(&self, time: &T) -> bool {
+        // The hour is below 24 and the minute below 60, so this can't overflow
+        self.contains_minute((time.hour() * 60 + time.minute()) as u16)
+    }
+}
+
+fn parse_time(time: &str) -> Result<u16> {
+    let invalid = || anyhow!("Invalid time {:?}, expected HH:MM", time);
+    let (hour, minute) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let hour: u16 = hour.parse().map_err(|_| invalid())?;
+    let minute: u16 = minute.parse().map_err(|_| invalid())?;
+    if hour >= 24 || minute >= 60 {
+        return Err(invalid());
+    }
+    Ok(hour * 60 + minute)
+}
+
+impl FromStr for UpdateWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow!("Invalid update window {:?}, expected HH:MM-HH:MM", s))?;
+        let window = Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        };
+        if window.start == window.end {
+            return Err(anyhow!("Update window {:?} is empty", s));
+        }
+        Ok(window)
+    }
+}
+
+impl fmt::Display for UpdateWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |minutes: u16| format!("{:02}:{:02}", minutes / 60, minutes % 60);
+        write!(f, "{}-{}", time(self.start), time(self.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_update_window() {
+        let window: UpdateWindow = "3:00-05:30".parse().unwrap();
+        assert_eq!(window.to_string(), "03:00-05:30");
+        assert!(window.contains_minute(3 * 60));
+        assert!(window.contains_minute(5 * 60 + 29));
+        assert!(!window.contains_minute(5 * 60 + 30));
+        assert!(!window.contains_minute(2 * 60 + 59));
+
+        let time = chrono::NaiveTime::from_hms_opt(4, 15, 0).unwrap();
+        assert!(window.contains(&time));
+
+        for invalid in [
+            "",
+            "03:00",
+            "03:00-03:00",
+            "24:00-01:00",
+            "03:60-04:00",
+            "a-b",
+        ] {
+            assert!(invalid.parse::<UpdateWindow>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_update_window_wraps_midnight() {
+        let window: UpdateWindow = "23:00-02:00".parse().unwrap();
+        assert!(window.contains_minute(23 * 60 + 30));
+        assert!(window.contains_minute(0));
+        assert!(window.contains_minute(60 + 59));
+        assert!(!window.contains_minute(2 * 60));
+        assert!(!window.contains_minute(12 * 60));
+    }
+}
@@ -1,10 +1,17 @@
 // YAML utilities with comment preservation
 #![allow(dead_code)]
 
-use anyhow::{Context, Result};
+use crate::utils::compose_include::join_relative;
+use anyhow::{anyhow, bail, Context, Result};
 use std::collections::HashMap;
+use std::path::Path;
 use yaml_rust2::{yaml::Hash, Yaml, YamlEmitter, YamlLoader};
 
+pub use dockru_protocol::compose::ComposeService;
+
+/// How deep `extends:` may chain before it is assumed to loop
+const MAX_EXTENDS_DEPTH: usize = 10;
+
 /// Substitute environment variables in a string
 ///
 /// Replaces ${VAR} and $VAR patterns with values from the provided map
@@ -100,6 +107,188 @@ pub fn yaml_to_string(doc: &Yaml) -> Result<String> {
     Ok(output)
 }
 
+/// The profiles a .env activates with COMPOSE_PROFILES
+///
+/// `*` activates every profile. The last definition wins, as in compose.
+pub fn active_profiles(env: &str) -> Vec<String> {
+    let value = env
+        .lines()
+        .rev()
+        .find_map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            line.strip_prefix("COMPOSE_PROFILES=")
+        })
+        .unwrap_or_default()
+        .trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .unwrap_or(value);
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The services compose would run: `extends:` resolved, and services whose
+/// profiles are all inactive left out
+///
+/// `load_file` reads the files `extends.file` refers to, by path relative to
+/// the stack directory; a file it can't read is an error.
+pub fn resolve_services(
+    doc: &Yaml,
+    active_profiles: &[String],
+    load_file: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<ComposeService>> {
+    let Some(services) = doc["services"].as_hash() else {
+        return Ok(Vec::new());
+    };
+    let all_profiles = active_profiles.iter().any(|p| p == "*");
+
+    let mut resolved = Vec::new();
+    for name in services.keys() {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let definition = resolve_extends(services, "", name, load_file, &mut Vec::new())
+            .with_context(|| format!("Failed to resolve service {}", name))?;
+
+        let profiles = string_list(&definition["profiles"]);
+        let enabled = profiles.is_empty()
+            || all_profiles
+            || profiles.iter().any(|p| active_profiles.contains(p));
+        if !enabled {
+            continue;
+        }
+
+        let depends_on = match &definition["depends_on"] {
+            Yaml::Hash(hash) => hash
+                .keys()
+                .filter_map(|k| k.as_str().map(str::to_string))
+                .collect(),
+            other => string_list(other),
+        };
+        resolved.push(ComposeService {
+            name: name.to_string(),
+            image: definition["image"].as_str().map(str::to_string),
+            build: !matches!(definition["build"], Yaml::BadValue | Yaml::Null),
+            profiles,
+            depends_on,
+        });
+    }
+    Ok(resolved)
+}
+
+/// A service's definition merged onto the service it extends, recursively
+///
+/// `file` is the file `services` come from, "" for the compose file itself.
+/// `chain` holds the services being resolved, to catch loops.
+fn resolve_extends(
+    services: &Hash,
+    file: &str,
+    name: &str,
+    load_file: &dyn Fn(&str) -> Option<String>,
+    chain: &mut Vec<String>,
+) -> Result<Yaml> {
+    let key = format!("{}#{}", file, name);
+    if chain.contains(&key) {
+        bail!("extends loops back to service {}", name);
+    }
+    if chain.len() >= MAX_EXTENDS_DEPTH {
+        bail!(
+            "extends is nested more than {} levels deep",
+            MAX_EXTENDS_DEPTH
+        );
+    }
+    let definition = services
+        .get(&Yaml::String(name.to_string()))
+        .ok_or_else(|| match file {
+            "" => anyhow!("Service {} doesn't exist", name),
+            _ => anyhow!("Service {} doesn't exist in {}", name, file),
+        })?;
+
+    let (base_file, base_name) = match &definition["extends"] {
+        Yaml::BadValue | Yaml::Null => return Ok(definition.clone()),
+        Yaml::String(base) => (None, base.clone()),
+        extends => {
+            let base = extends["service"]
+                .as_str()
+                .ok_or_else(|| anyhow!("extends of {} needs a service", name))?;
+            (extends["file"].as_str(), base.to_string())
+        }
+    };
+
+    chain.push(key);
+    let base = match base_file {
+        None => resolve_extends(services, file, &base_name, load_file, chain)?,
+        Some(base_file) => {
+            let dir = Path::new(file)
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+            let path = join_relative(&dir, base_file);
+            let content = load_file(&path).ok_or_else(|| anyhow!("Failed to read {}", path))?;
+            let docs = parse_yaml(&content).with_context(|| format!("Invalid YAML in {}", path))?;
+            let base_services = docs
+                .first()
+                .and_then(|doc| doc["services"].as_hash())
+                .ok_or_else(|| anyhow!("{} has no services", path))?;
+            resolve_extends(base_services, &path, &base_name, load_file, chain)?
+        }
+    };
+    chain.pop();
+
+    let mut overrides = definition.clone();
+    if let Yaml::Hash(hash) = &mut overrides {
+        hash.remove(&Yaml::String("extends".to_string()));
+    }
+    Ok(merge_service(base, overrides))
+}
+
+/// Merge a service definition onto the one it extends
+///
+/// Mappings merge key by key, sequences are appended to (without repeating
+/// entries) and anything else is replaced.
+fn merge_service(base: Yaml, overrides: Yaml) -> Yaml {
+    match (base, overrides) {
+        (Yaml::Hash(mut base), Yaml::Hash(overrides)) => {
+            for (key, value) in overrides {
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge_service(existing, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Yaml::Hash(base)
+        }
+        (Yaml::Array(mut base), Yaml::Array(overrides)) => {
+            for value in overrides {
+                if !base.contains(&value) {
+                    base.push(value);
+                }
+            }
+            Yaml::Array(base)
+        }
+        (_, overrides) => overrides,
+    }
+}
+
+/// A string or a sequence of strings, as a list
+fn string_list(value: &Yaml) -> Vec<String> {
+    match value {
+        Yaml::String(s) => vec![s.clone()],
+        Yaml::Array(items) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,4 +380,135 @@ number: 42
         assert!(output.contains("key"));
         assert!(output.contains("value"));
     }
+    fn services(yaml: &str, profiles: &[&str]) -> Result<Vec<ComposeService>> {
+        let docs = parse_yaml(yaml).unwrap();
+        let profiles: Vec<String> = profiles.iter().map(|p| p.to_string()).collect();
+        let files: HashMap<&str, &str> = HashMap::from([
+            (
+                "common/base.yaml",
+                "services:\n  base:\n    extends:\n      file: ../shared.yaml\n      service: root\n    environment:\n      TIER: base\n",
+            ),
+            (
+                "shared.yaml",
+                "services:\n  root:\n    image: alpine:3\n    depends_on: [db]\n",
+            ),
+        ]);
+        resolve_services(&docs[0], &profiles, &|path| {
+            files.get(path).map(|c| c.to_string())
+        })
+    }
+
+    #[test]
+    fn test_active_profiles() {
+        assert_eq!(
+            active_profiles("A=1\nCOMPOSE_PROFILES=debug, tools\n"),
+            vec!["debug", "tools"]
+        );
+        assert_eq!(
+            active_profiles("COMPOSE_PROFILES=a\nexport COMPOSE_PROFILES=\"b\"\n"),
+            vec!["b"]
+        );
+        assert!(active_profiles("# COMPOSE_PROFILES=a\n").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_services_profiles() {
+        let yaml = r#"
+services:
+  web:
+    image: nginx
+    depends_on:
+      db:
+        condition: service_healthy
+  db:
+    image: postgres
+  adminer:
+    image: adminer
+    profiles: [debug]
+  backup:
+    build: ./backup
+    profiles: [tools, cron]
+"#;
+        let names = |profiles: &[&str]| -> Vec<String> {
+            services(yaml, profiles)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect()
+        };
+        assert_eq!(names(&[]), vec!["web", "db"]);
+        assert_eq!(names(&["cron"]), vec!["web", "db", "backup"]);
+        assert_eq!(names(&["*"]), vec!["web", "db", "adminer", "backup"]);
+
+        let all = services(yaml, &["*"]).unwrap();
+        assert_eq!(all[0].depends_on, vec!["db"]);
+        assert!(all[3].build);
+        assert_eq!(all[3].image, None);
+    }
+
+    #[test]
+    fn test_resolve_nested_extends() {
+        let yaml = r#"
+services:
+  base:
+    image: myapp:1
+    profiles: [workers]
+    environment:
+      LOG: info
+    volumes: [./data:/data]
+  worker:
+    extends: base
+    environment:
+      ROLE: worker
+    volumes: [./cache:/cache]
+  urgent-worker:
+    extends:
+      service: worker
+    image: myapp:2
+  remote:
+    extends:
+      file: common/base.yaml
+      service: base
+"#;
+        let docs = parse_yaml(yaml).unwrap();
+        let services_hash = docs[0]["services"].as_hash().unwrap();
+        let urgent = resolve_extends(
+            services_hash,
+            "",
+            "urgent-worker",
+            &|_| None,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(urgent["image"].as_str(), Some("myapp:2"));
+        assert_eq!(urgent["environment"]["LOG"].as_str(), Some("info"));
+        assert_eq!(urgent["environment"]["ROLE"].as_str(), Some("worker"));
+        assert_eq!(urgent["volumes"].as_vec().unwrap().len(), 2);
+        assert!(urgent["extends"].is_badvalue());
+
+        // Profiles are inherited too
+        let names: Vec<String> = services(yaml, &[])
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["remote"]);
+
+        // Extends across files, relative to the extending file
+        let remote = services(yaml, &["workers"]).unwrap().pop().unwrap();
+        assert_eq!(remote.name, "remote");
+        assert_eq!(remote.image.as_deref(), Some("alpine:3"));
+        assert_eq!(remote.depends_on, vec!["db"]);
+    }
+
+    #[test]
+    fn test_resolve_extends_errors() {
+        let looping = "services:\n  a:\n    extends: b\n  b:\n    extends: a\n";
+        assert!(services(looping, &[]).is_err());
+        let missing = "services:\n  a:\n    extends: nope\n";
+        assert!(services(missing, &[]).is_err());
+        let missing_file =
+            "services:\n  a:\n    extends:\n      file: other.yaml\n      service: a\n";
+        assert!(services(missing_file, &[]).is_err());
+    }
 }
@@ -0,0 +1,132 @@
+//! Deploy webhooks for CI/CD
+//!
+//! `POST /api/webhook/deploy/:stack` redeploys a stack without a socket.io session.
+//! Each stack has its own token, generated from the UI and stored hashed. The token
+//! is accepted as `Authorization: Bearer <token>` or a `?token=` query parameter.
+//!
+//! The optional `?action=` parameter selects what to run:
+//! - `update` (default) - pull images, then redeploy if the stack is running
+//! - `deploy` - `docker compose up -d`
+
+use crate::db::models::StackWebhook;
+use crate::docker::DeployOptions;
+use crate::server::ServerContext;
+use crate::stack::Stack;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+#[derive(Debug, Deserialize)]
+struct WebhookQuery {
+    token: Option<String>,
+    action: Option<String>,
+}
+
+/// Build the webhook routes
+pub fn routes(ctx: Arc<ServerContext>) -> Router {
+    Router::new()
+        .route("/api/webhook/deploy/:stack", post(deploy_webhook))
+        .with_state(ctx)
+}
+
+/// Path to the deploy webhook for a stack, relative to the server root
+pub fn deploy_path(stack_name: &str) -> String {
+    format!("/api/webhook/deploy/{}", stack_name)
+}
+
+async fn deploy_webhook(
+    State(ctx): State<Arc<ServerContext>>,
+    Path(stack_name): Path<String>,
+    Query(query): Query<WebhookQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(token) = bearer_token(&headers).or(query.token) else {
+        return error(StatusCode::UNAUTHORIZED, "Missing webhook token");
+    };
+
+    match StackWebhook::verify(&ctx.db, &stack_name, &token).await {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!("Rejected deploy webhook for stack {}", stack_name);
+            return error(StatusCode::UNAUTHORIZED, "Invalid webhook token");
+        }
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+
+    let action = query.action.unwrap_or_else(|| "update".to_string());
+    if action != "update" && action != "deploy" {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "action must be either \"update\" or \"deploy\"",
+        );
+    }
+
+    let mut stack = match Stack::get_stack(ctx.clone(), &stack_name, String::new()).await {
+        Ok(stack) => stack,
+        Err(e) => return error(StatusCode::NOT_FOUND, &e.to_string()),
+    };
+
+    info!(
+        "Deploy webhook triggered for stack {} ({})",
+        stack_name, action
+    );
+
+    let result = if action == "deploy" {
+        stack.deploy(&DeployOptions::default(), None).await
+    } else {
+        stack.update(false, None).await
+    };
+    stack.record_event(&action, "webhook", &result).await;
+
+    // Refresh the stack list for connected clients
+    ctx.broadcast_notify.notify_one();
+
+    match result {
+        Ok(0) => Json(json!({ "ok": true, "action": action, "exitCode": 0 })).into_response(),
+        Ok(exit_code) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "ok": false, "action": action, "exitCode": exit_code })),
+        )
+            .into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+/// Extract a bearer token from the Authorization header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|t| t.trim().to_string())
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    (status, Json(json!({ "ok": false, "msg": msg }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(AUTHORIZATION, "Bearer abc123".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("abc123".to_string()));
+
+        headers.insert(AUTHORIZATION, "Basic abc123".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+    }
+}